@@ -48,7 +48,7 @@ format_scene() {
     # Extract node content and reasoning
     local content=$(jq -r ".nodes[\"$node_id\"].content" "$JSON_FILE")
     local reasoning=$(jq -r ".nodes[\"$node_id\"].reasoning" "$JSON_FILE")
-    local successor=$(jq -r ".nodes[\"$node_id\"].successor" "$JSON_FILE")
+    local successor=$(jq -r ".nodes[\"$node_id\"] | .successors[0] // .successor" "$JSON_FILE")
     
     # Write scene to file
     cat >> "$OUTPUT_FILE" << EOF
@@ -66,7 +66,8 @@ $reasoning
 
 EOF
     
-    # Return successor ID
+    # Return the first successor ID, which continues the main storyline
+    # (stories saved by older versions have a single successor instead)
     echo "$successor"
 }
 
@@ -3,12 +3,33 @@
 //! This module provides functionality for managing various artifacts used in the story
 //! generation process, such as premises, character arcs, and world-building details.
 //! It handles the persistence and retrieval of these artifacts from the file system.
+//!
+//! Artifacts can reference the artifacts they build on, such as a character
+//! arc referencing the world-building document of the character's homeland,
+//! by listing their IDs in the `references` metadata. The references are
+//! checked for dangling IDs and cycles, and prompts present the artifacts in
+//! dependency order, every artifact after the ones it references.
+//!
+//! Artifacts can also carry tags, and `ArtifactManager::search` finds
+//! artifacts by the words in their content, metadata, and tags, which keeps
+//! a project with dozens of lore documents navigable.
+//!
+//! Deleting or renaming an artifact through the manager removes or renames
+//! its file and fixes up the references other artifacts make to it.
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 use std::path::Path;
+use log::{debug, info};
 use crate::StoryChainError;
 
+/// Characters of context shown on each side of a search match
+const SNIPPET_CONTEXT_CHARS: usize = 40;
+
+/// Artifact metadata key listing the IDs of the artifacts it references,
+/// separated by commas
+pub const REFERENCES_KEY: &str = "references";
+
 /// Manages the storage and retrieval of story-related artifacts
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ArtifactManager {
@@ -62,11 +83,10 @@ impl ArtifactManager {
     /// # Arguments
     /// * `artifact` - The artifact to save
     pub fn save_artifact(&self, artifact: &Artifact) -> Result<(), StoryChainError> {
-        let path = Path::new(&self.artifact_dir)
-            .join(format!("{}.json", artifact.id));
+        let path = self.artifact_path(&artifact.id);
         
         let content = serde_json::to_string_pretty(artifact)?;
-        std::fs::write(path, content)?;
+        crate::files::write_atomic(path, content)?;
         
         Ok(())
     }
@@ -106,6 +126,7 @@ impl ArtifactManager {
             content,
             artifact_type,
             metadata: HashMap::new(),
+            tags: Vec::new(),
         };
         
         self.artifacts.insert(artifact.id.clone(), artifact.clone());
@@ -114,6 +135,106 @@ impl ArtifactManager {
         Ok(())
     }
 
+    /// Deletes an artifact and its file, and removes it from the references
+    /// of the artifacts that reference it
+    ///
+    /// # Arguments
+    /// * `id` - The ID of the artifact to delete
+    ///
+    /// # Returns
+    /// The deleted artifact
+    pub fn delete_artifact(&mut self, id: &str) -> Result<Artifact, StoryChainError> {
+        let artifact = self
+            .artifacts
+            .remove(id)
+            .ok_or_else(|| StoryChainError::ConfigError(format!("No artifact found with ID {}", id)))?;
+        let path = self.artifact_path(id);
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+        let updated = self.replace_references(id, None)?;
+        info!("Deleted artifact {}, updating the references of {} others", id, updated.len());
+        Ok(artifact)
+    }
+
+    /// Renames an artifact, moving its file, and updates the references of
+    /// the artifacts that reference it
+    ///
+    /// # Arguments
+    /// * `old_id` - The current ID of the artifact
+    /// * `new_id` - The ID to give it, which no other artifact may have
+    pub fn rename_artifact(&mut self, old_id: &str, new_id: &str) -> Result<(), StoryChainError> {
+        let new_id = new_id.trim();
+        if new_id.is_empty() || new_id.contains(['/', '\\', ',']) {
+            return Err(StoryChainError::ConfigError(format!("Invalid artifact ID {:?}", new_id)));
+        }
+        if !self.artifacts.contains_key(old_id) {
+            return Err(StoryChainError::ConfigError(format!("No artifact found with ID {}", old_id)));
+        }
+        if old_id == new_id {
+            return Ok(());
+        }
+        if self.artifacts.contains_key(new_id) {
+            return Err(StoryChainError::ConfigError(format!("An artifact with ID {} already exists", new_id)));
+        }
+
+        // Write the renamed artifact before removing the old file, so that it is never lost
+        let mut artifact = self.artifacts.remove(old_id).expect("artifact exists");
+        artifact.id = new_id.to_string();
+        self.update_artifact(artifact)?;
+        let old_path = self.artifact_path(old_id);
+        if old_path.exists() {
+            std::fs::remove_file(old_path)?;
+        }
+        let updated = self.replace_references(old_id, Some(new_id))?;
+        info!("Renamed artifact {} to {}, updating the references of {} others", old_id, new_id, updated.len());
+        Ok(())
+    }
+
+    /// Returns the path of an artifact's file
+    fn artifact_path(&self, id: &str) -> std::path::PathBuf {
+        Path::new(&self.artifact_dir).join(format!("{}.json", id))
+    }
+
+    /// Replaces or removes an ID in the references of every artifact and
+    /// saves the artifacts that changed
+    ///
+    /// # Arguments
+    /// * `id` - The referenced ID
+    /// * `replacement` - The ID to reference instead, or `None` to drop the reference
+    ///
+    /// # Returns
+    /// The IDs of the artifacts that changed
+    fn replace_references(&mut self, id: &str, replacement: Option<&str>) -> Result<Vec<String>, StoryChainError> {
+        let mut changed = Vec::new();
+        for artifact in self.artifacts.values_mut() {
+            let references = artifact.references();
+            if !references.contains(&id) {
+                continue;
+            }
+            let mut updated: Vec<&str> = Vec::new();
+            for reference in references {
+                let reference = if reference == id { replacement } else { Some(reference) };
+                if let Some(reference) = reference.filter(|r| !updated.contains(r)) {
+                    updated.push(reference);
+                }
+            }
+            let updated = updated.join(", ");
+            if updated.is_empty() {
+                artifact.metadata.remove(REFERENCES_KEY);
+            } else {
+                artifact.metadata.insert(REFERENCES_KEY.to_string(), updated);
+            }
+            debug!("Updated the references of {}", artifact.id);
+            changed.push(artifact.id.clone());
+        }
+        changed.sort();
+        for artifact_id in &changed {
+            self.save_artifact(&self.artifacts[artifact_id])?;
+        }
+        Ok(changed)
+    }
+
     /// Retrieves all artifacts of a specific type
     /// 
     /// # Arguments
@@ -124,6 +245,223 @@ impl ArtifactManager {
             .filter(|a| &a.artifact_type == artifact_type)
             .collect()
     }
+
+    /// Retrieves all loaded artifacts, sorted by ID
+    pub fn get_all_artifacts(&self) -> Vec<&Artifact> {
+        let mut artifacts: Vec<&Artifact> = self.artifacts.values().collect();
+        artifacts.sort_by(|a, b| a.id.cmp(&b.id));
+        artifacts
+    }
+
+    /// Checks that every artifact reference names a loaded artifact and that
+    /// no artifact depends on itself through its references
+    ///
+    /// # Returns
+    /// A `ConfigError` listing every dangling reference and cycle found
+    pub fn validate(&self) -> Result<(), StoryChainError> {
+        let mut problems = Vec::new();
+        for artifact in self.get_all_artifacts() {
+            for reference in artifact.references() {
+                if !self.artifacts.contains_key(reference) {
+                    problems.push(format!("{} references {}, which does not exist", artifact.id, reference));
+                }
+            }
+        }
+        problems.extend(self.cycles().iter().map(|cycle| format!("{} reference each other in a cycle", cycle.join(" -> "))));
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(StoryChainError::ConfigError(format!("Invalid artifacts: {}", problems.join("; "))))
+        }
+    }
+
+    /// Finds the reference cycles among the artifacts
+    ///
+    /// # Returns
+    /// Each cycle as the IDs along it, starting and ending with the same ID
+    fn cycles(&self) -> Vec<Vec<String>> {
+        /// Visits an artifact depth-first, recording a cycle whenever a
+        /// reference leads back to an artifact on the current path
+        fn visit<'a>(
+            manager: &'a ArtifactManager,
+            id: &'a str,
+            path: &mut Vec<&'a str>,
+            done: &mut BTreeSet<&'a str>,
+            cycles: &mut Vec<Vec<String>>,
+        ) {
+            if let Some(start) = path.iter().position(|on_path| *on_path == id) {
+                let mut cycle: Vec<String> = path[start..].iter().map(|id| id.to_string()).collect();
+                cycle.push(id.to_string());
+                cycles.push(cycle);
+                return;
+            }
+            if !done.insert(id) {
+                return;
+            }
+            path.push(id);
+            for reference in manager.artifacts[id].references() {
+                if manager.artifacts.contains_key(reference) {
+                    visit(manager, reference, path, done, cycles);
+                }
+            }
+            path.pop();
+        }
+
+        let mut done = BTreeSet::new();
+        let mut cycles = Vec::new();
+        for artifact in self.get_all_artifacts() {
+            visit(self, &artifact.id, &mut Vec::new(), &mut done, &mut cycles);
+        }
+        cycles
+    }
+
+    /// Orders the artifacts so that each comes after the artifacts it
+    /// references, for assembling prompts that build up from the foundations
+    ///
+    /// Artifacts that do not depend on each other keep their ID order, and
+    /// references to artifacts that are not loaded are ignored.
+    ///
+    /// # Returns
+    /// The artifacts in dependency order, or a `ConfigError` naming a cycle
+    pub fn topological_order(&self) -> Result<Vec<&Artifact>, StoryChainError> {
+        if let Some(cycle) = self.cycles().first() {
+            return Err(StoryChainError::ConfigError(format!(
+                "Artifacts {} reference each other in a cycle",
+                cycle.join(" -> ")
+            )));
+        }
+
+        // Kahn's algorithm, taking the ready artifacts in ID order
+        let mut waiting_on: HashMap<&str, usize> = HashMap::new();
+        let mut dependents: HashMap<&str, Vec<&str>> = HashMap::new();
+        for artifact in self.artifacts.values() {
+            let references: BTreeSet<&str> =
+                artifact.references().into_iter().filter(|id| self.artifacts.contains_key(*id)).collect();
+            waiting_on.insert(&artifact.id, references.len());
+            for reference in references {
+                dependents.entry(reference).or_default().push(&artifact.id);
+            }
+        }
+        let mut ready: BTreeSet<&str> = waiting_on.iter().filter(|(_, count)| **count == 0).map(|(id, _)| *id).collect();
+        let mut order = Vec::with_capacity(self.artifacts.len());
+        while let Some(id) = ready.pop_first() {
+            order.push(&self.artifacts[id]);
+            for dependent in dependents.get(id).into_iter().flatten() {
+                let count = waiting_on.get_mut(dependent).expect("every artifact is counted");
+                *count -= 1;
+                if *count == 0 {
+                    ready.insert(dependent);
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    /// Retrieves the artifacts with a tag, ignoring case, sorted by ID
+    ///
+    /// # Arguments
+    /// * `tag` - The tag to look for
+    pub fn get_artifacts_by_tag(&self, tag: &str) -> Vec<&Artifact> {
+        self.get_all_artifacts().into_iter().filter(|artifact| artifact.has_tag(tag)).collect()
+    }
+
+    /// Searches the artifacts' IDs, content, metadata, and tags, ignoring case
+    ///
+    /// Every word of the query must appear somewhere in an artifact for it
+    /// to match; a `tag:<name>` word instead requires the artifact to have
+    /// that tag.
+    ///
+    /// # Arguments
+    /// * `query` - The words to search for
+    ///
+    /// # Returns
+    /// The matching artifacts, those where the words occur most often first,
+    /// each with a snippet around the first occurrence
+    pub fn search(&self, query: &str) -> Vec<SearchResult<'_>> {
+        let mut tags = Vec::new();
+        let mut terms = Vec::new();
+        for word in query.split_whitespace() {
+            match word.split_once(':') {
+                Some((prefix, tag)) if prefix.eq_ignore_ascii_case("tag") && !tag.is_empty() => tags.push(tag),
+                _ => terms.push(word.to_lowercase()),
+            }
+        }
+        if tags.is_empty() && terms.is_empty() {
+            return Vec::new();
+        }
+
+        let mut results: Vec<SearchResult> = self
+            .get_all_artifacts()
+            .into_iter()
+            .filter(|artifact| tags.iter().all(|tag| artifact.has_tag(tag)))
+            .filter_map(|artifact| {
+                // Search the content first, so that snippets come from it where possible
+                let mut fields = vec![("content".to_string(), artifact.content.clone()), ("id".to_string(), artifact.id.clone())];
+                let mut keys: Vec<&String> = artifact.metadata.keys().collect();
+                keys.sort();
+                fields.extend(keys.into_iter().map(|key| (key.clone(), format!("{} {}", key, artifact.metadata[key]))));
+                if !artifact.tags.is_empty() {
+                    fields.push(("tags".to_string(), artifact.tags.join(" ")));
+                }
+                let fields: Vec<(String, String)> =
+                    fields.into_iter().map(|(name, text)| (name, text.to_lowercase())).collect();
+
+                let mut occurrences = 0;
+                for term in &terms {
+                    let count: usize = fields.iter().map(|(_, text)| text.matches(term.as_str()).count()).sum();
+                    if count == 0 {
+                        return None;
+                    }
+                    occurrences += count;
+                }
+                let (field, snippet) = match terms.first() {
+                    Some(term) => fields
+                        .iter()
+                        .find_map(|(name, text)| text.find(term.as_str()).map(|at| (name.clone(), snippet(text, at, term.len()))))
+                        .unwrap_or_default(),
+                    None => ("tags".to_string(), artifact.tags.join(", ")),
+                };
+                Some(SearchResult { artifact, field, snippet, occurrences })
+            })
+            .collect();
+        results.sort_by(|a, b| b.occurrences.cmp(&a.occurrences).then_with(|| a.artifact.id.cmp(&b.artifact.id)));
+        results
+    }
+}
+
+/// An artifact found by `ArtifactManager::search`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult<'a> {
+    /// The artifact found
+    pub artifact: &'a Artifact,
+
+    /// Where the first word of the query was found: `content`, `id`,
+    /// `tags`, or a metadata key
+    pub field: String,
+
+    /// The text around the first occurrence, on one line and in lower case
+    pub snippet: String,
+
+    /// How often the words of the query occur in the artifact
+    pub occurrences: usize,
+}
+
+/// Returns the text around a match, on one line, marking cut ends with `...`
+///
+/// # Arguments
+/// * `text` - The text searched
+/// * `at` - Byte offset of the match
+/// * `len` - Byte length of the match
+fn snippet(text: &str, at: usize, len: usize) -> String {
+    let start = text[..at].char_indices().rev().nth(SNIPPET_CONTEXT_CHARS - 1).map_or(0, |(index, _)| index);
+    let end = text[at + len..].char_indices().nth(SNIPPET_CONTEXT_CHARS).map_or(text.len(), |(index, _)| at + len + index);
+    format!(
+        "{}{}{}",
+        if start > 0 { "..." } else { "" },
+        text[start..end].split_whitespace().collect::<Vec<_>>().join(" "),
+        if end < text.len() { "..." } else { "" }
+    )
 }
 
 /// Represents a single story-related artifact
@@ -140,10 +478,53 @@ pub struct Artifact {
     
     /// Additional metadata associated with this artifact
     pub metadata: HashMap<String, String>,
+
+    /// Labels for finding the artifact, such as `lore` or `northern-kingdom`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl Artifact {
+    /// Returns true if the artifact has the tag, ignoring case
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|own| own.eq_ignore_ascii_case(tag.trim()))
+    }
+
+    /// Adds a tag unless the artifact already has it
+    ///
+    /// # Returns
+    /// True if the tag was added
+    pub fn add_tag(&mut self, tag: &str) -> bool {
+        let tag = tag.trim();
+        if tag.is_empty() || self.has_tag(tag) {
+            return false;
+        }
+        self.tags.push(tag.to_string());
+        true
+    }
+
+    /// Removes a tag, ignoring case
+    ///
+    /// # Returns
+    /// True if the artifact had the tag
+    pub fn remove_tag(&mut self, tag: &str) -> bool {
+        let before = self.tags.len();
+        self.tags.retain(|own| !own.eq_ignore_ascii_case(tag.trim()));
+        self.tags.len() < before
+    }
+
+    /// Returns the IDs of the artifacts this one references, from its
+    /// `references` metadata
+    pub fn references(&self) -> Vec<&str> {
+        self.metadata
+            .get(REFERENCES_KEY)
+            .map(|references| references.split(',').map(str::trim).filter(|id| !id.is_empty()).collect())
+            .unwrap_or_default()
+    }
 }
 
 /// Enumerates the different types of artifacts that can be managed
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum ArtifactType {
     /// The foundational premise of the story
     Premise,
@@ -156,7 +537,34 @@ pub enum ArtifactType {
     
     /// World-building details and background
     WorldBuilding,
+
+    /// Condensed summary of a generated story
+    Synopsis,
+
+    /// Continuity information carried over from a previous story in a series
+    Continuity,
+
+    /// Invented terms, places, and concepts with their definitions
+    Glossary,
+
+    /// Image-generation prompt for a cover or interior illustration
+    IllustrationBrief,
+
+    /// A viewpoint character whose perspective scenes can be told from
+    PovCharacter,
+
+    /// A sample scene in the desired voice, included in prompts as a few-shot example
+    Exemplar,
+
+    /// A character's traits, goals, relationships, and voice, included in
+    /// continuation prompts when the character is in the previous scene
+    CharacterSheet,
+
+    /// The narrative voice, tense, banned words, and example passages every
+    /// scene should follow, included in every scene prompt
+    StyleGuide,
     
-    /// Custom artifact type with specified name
+    /// Custom artifact type with specified name, given a part in generation
+    /// by the `ArtifactProcessor` registered for it
     Custom(String),
 } 
\ No newline at end of file
@@ -0,0 +1,477 @@
+//! Audiobook Export
+//!
+//! This module reads a story aloud. Each scene of the main storyline is sent
+//! to a `TtsProvider` and saved as a WAV file of its own, the chapter titles
+//! are spoken before their first scenes, and all of it is joined with short
+//! pauses into a single audiobook with chapter markers. Two providers are
+//! included: Piper, run locally, and any HTTP server with an
+//! OpenAI-compatible `/v1/audio/speech` endpoint.
+//!
+//! Available with the `audio` feature.
+
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use serde_json::json;
+use tokio::io::AsyncWriteExt;
+use log::{debug, error, info};
+use crate::files::write_atomic;
+use crate::{StoryChain, StoryChainError, StoryNode};
+
+/// Name of the joined audiobook in the output directory
+pub const AUDIOBOOK_FILE: &str = "audiobook.wav";
+
+/// Name of the chapter markers in the output directory, in ffmpeg's metadata format
+pub const CHAPTERS_FILE: &str = "chapters.txt";
+
+/// Silence between scenes and around spoken chapter titles
+const SCENE_PAUSE: Duration = Duration::from_millis(1200);
+
+/// Characters an OpenAI-compatible speech endpoint accepts in one request
+const HTTP_MAX_INPUT_CHARS: usize = 4096;
+
+/// Trait defining the interface for text-to-speech engines
+#[async_trait::async_trait]
+pub trait TtsProvider: Send + Sync {
+    /// Reads a text aloud
+    ///
+    /// # Arguments
+    /// * `text` - The text to speak
+    ///
+    /// # Returns
+    /// The speech as the bytes of a PCM WAV file, or an error
+    async fn synthesize(&self, text: &str) -> Result<Vec<u8>, StoryChainError>;
+
+    /// Returns the name of the voice, recorded in the chapter markers
+    fn voice(&self) -> &str;
+
+    /// Returns the most characters the engine accepts in one request, if it
+    /// has a limit; longer scenes are read in parts at paragraph breaks
+    fn max_input_chars(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Implementation of TtsProvider running the Piper command-line synthesizer
+pub struct PiperTtsProvider {
+    /// Path or name of the `piper` executable
+    binary: String,
+
+    /// Path of the `.onnx` voice model
+    model: String,
+
+    /// Speaker of a multi-speaker voice model
+    speaker: Option<u32>,
+}
+
+impl PiperTtsProvider {
+    /// Creates a new PiperTtsProvider running `piper` from the `PATH`
+    ///
+    /// # Arguments
+    /// * `model` - Path of the `.onnx` voice model
+    pub fn new(model: String) -> Self {
+        Self {
+            binary: "piper".to_string(),
+            model,
+            speaker: None,
+        }
+    }
+
+    /// Sets the path of the `piper` executable
+    pub fn with_binary(mut self, binary: String) -> Self {
+        self.binary = binary;
+        self
+    }
+
+    /// Sets the speaker of a multi-speaker voice model
+    pub fn with_speaker(mut self, speaker: u32) -> Self {
+        self.speaker = Some(speaker);
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl TtsProvider for PiperTtsProvider {
+    async fn synthesize(&self, text: &str) -> Result<Vec<u8>, StoryChainError> {
+        // Piper writes the WAV to a file; each request gets its own
+        static REQUESTS: AtomicUsize = AtomicUsize::new(0);
+        let output = std::env::temp_dir().join(format!(
+            "storychain-piper-{}-{}.wav",
+            std::process::id(),
+            REQUESTS.fetch_add(1, Ordering::Relaxed)
+        ));
+        debug!("Running {} with voice {}", self.binary, self.model);
+
+        let mut command = tokio::process::Command::new(&self.binary);
+        command.arg("--model").arg(&self.model).arg("--output_file").arg(&output);
+        if let Some(speaker) = self.speaker {
+            command.arg("--speaker").arg(speaker.to_string());
+        }
+        let mut child = command
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .map_err(|e| StoryChainError::AudioError(format!("Could not run {}: {}", self.binary, e)))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes()).await?;
+        }
+        let result = child.wait_with_output().await?;
+        if !result.status.success() {
+            let _ = std::fs::remove_file(&output);
+            let stderr = String::from_utf8_lossy(&result.stderr);
+            error!("Piper failed with {}: {}", result.status, stderr);
+            return Err(StoryChainError::AudioError(format!("Piper failed with {}: {}", result.status, stderr.trim())));
+        }
+        let audio = std::fs::read(&output);
+        let _ = std::fs::remove_file(&output);
+        Ok(audio?)
+    }
+
+    fn voice(&self) -> &str {
+        Path::new(&self.model).file_stem().and_then(|stem| stem.to_str()).unwrap_or(&self.model)
+    }
+}
+
+/// Implementation of TtsProvider for servers with an OpenAI-compatible speech endpoint
+pub struct HttpTtsProvider {
+    /// HTTP client used for requests
+    client: reqwest::Client,
+
+    /// URL of the speech endpoint, e.g. `http://localhost:8880/v1/audio/speech`
+    url: String,
+
+    /// Name of the speech model
+    model: String,
+
+    /// Name of the voice
+    voice: String,
+
+    /// API key sent as a bearer token
+    api_key: Option<String>,
+}
+
+impl HttpTtsProvider {
+    /// Creates a new HttpTtsProvider
+    ///
+    /// # Arguments
+    /// * `url` - URL of the speech endpoint, e.g. `https://api.openai.com/v1/audio/speech`
+    /// * `model` - Name of the speech model
+    /// * `voice` - Name of the voice
+    pub fn new(url: &str, model: String, voice: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.to_string(),
+            model,
+            voice,
+            api_key: None,
+        }
+    }
+
+    /// Sets an API key sent as a bearer token
+    pub fn with_api_key(mut self, api_key: String) -> Self {
+        self.api_key = Some(api_key);
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl TtsProvider for HttpTtsProvider {
+    async fn synthesize(&self, text: &str) -> Result<Vec<u8>, StoryChainError> {
+        debug!("Requesting speech from {} with voice {}", self.url, self.voice);
+        let mut request = self.client.post(&self.url).json(&json!({
+            "model": self.model,
+            "voice": self.voice,
+            "input": text,
+            "response_format": "wav",
+        }));
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request.send().await.map_err(|e| {
+            error!("Speech request failed: {}", e);
+            StoryChainError::AudioError(format!("Speech request failed: {}", e))
+        })?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            error!("Speech request failed with {}: {}", status, text);
+            return Err(StoryChainError::AudioError(format!("Speech request failed with {}: {}", status, text)));
+        }
+        let audio = response
+            .bytes()
+            .await
+            .map_err(|e| StoryChainError::AudioError(format!("Invalid speech response: {}", e)))?;
+        Ok(audio.to_vec())
+    }
+
+    fn voice(&self) -> &str {
+        &self.voice
+    }
+
+    fn max_input_chars(&self) -> Option<usize> {
+        Some(HTTP_MAX_INPUT_CHARS)
+    }
+}
+
+/// PCM audio read from a WAV file
+#[derive(Debug, Clone, PartialEq)]
+struct Wav {
+    /// Body of the `fmt ` chunk, which must match for clips to be joined
+    format: Vec<u8>,
+
+    /// Samples of the `data` chunk
+    data: Vec<u8>,
+}
+
+impl Wav {
+    /// Reads the format and samples of a WAV file, skipping its other chunks
+    fn parse(bytes: &[u8]) -> Result<Self, StoryChainError> {
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+            return Err(StoryChainError::AudioError("Speech is not a WAV file".to_string()));
+        }
+        let mut format = None;
+        let mut offset = 12;
+        while offset + 8 <= bytes.len() {
+            let id = &bytes[offset..offset + 4];
+            let size = u32::from_le_bytes(bytes[offset + 4..offset + 8].try_into().unwrap()) as usize;
+            // Streamed files leave the data size unset, so it runs to the end
+            let body = &bytes[offset + 8..(offset + 8).saturating_add(size).min(bytes.len())];
+            match id {
+                b"fmt " if body.len() >= 16 => format = Some(body.to_vec()),
+                b"data" => {
+                    let format = format.ok_or_else(|| {
+                        StoryChainError::AudioError("WAV file has no format before its samples".to_string())
+                    })?;
+                    if u16::from_le_bytes([format[0], format[1]]) != 1 {
+                        return Err(StoryChainError::AudioError("Speech is not PCM audio".to_string()));
+                    }
+                    return Ok(Self { format, data: body.to_vec() });
+                }
+                _ => {}
+            }
+            offset += 8 + size + size % 2;
+        }
+        Err(StoryChainError::AudioError("WAV file has no samples".to_string()))
+    }
+
+    /// Returns the bytes of audio per second
+    fn byte_rate(&self) -> u32 {
+        u32::from_le_bytes(self.format[8..12].try_into().unwrap())
+    }
+
+    /// Returns the bytes of one sample across all channels
+    fn block_align(&self) -> usize {
+        u16::from_le_bytes([self.format[12], self.format[13]]).max(1) as usize
+    }
+
+    /// Returns how long the audio plays
+    fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.data.len() as f64 / self.byte_rate().max(1) as f64)
+    }
+
+    /// Appends another clip, which must have the same format
+    fn append(&mut self, other: &Wav) -> Result<(), StoryChainError> {
+        if other.format != self.format {
+            return Err(StoryChainError::AudioError(
+                "Speech clips differ in sample rate, channels, or sample size and cannot be joined".to_string(),
+            ));
+        }
+        self.data.extend_from_slice(&other.data);
+        Ok(())
+    }
+
+    /// Appends silence
+    fn append_silence(&mut self, duration: Duration) {
+        let samples = (duration.as_secs_f64() * self.byte_rate() as f64) as usize / self.block_align();
+        // Eight-bit samples are unsigned, so their silence is the midpoint
+        let bits_per_sample = u16::from_le_bytes([self.format[14], self.format[15]]);
+        let level = if bits_per_sample == 8 { 0x80 } else { 0 };
+        self.data.resize(self.data.len() + samples * self.block_align(), level);
+    }
+
+    /// Returns the clip as the bytes of a WAV file
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.format.len() + self.data.len() + 28);
+        bytes.extend_from_slice(b"RIFF");
+        bytes.extend_from_slice(&((20 + self.format.len() + self.data.len()) as u32).to_le_bytes());
+        bytes.extend_from_slice(b"WAVEfmt ");
+        bytes.extend_from_slice(&(self.format.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.format);
+        bytes.extend_from_slice(b"data");
+        bytes.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&self.data);
+        if self.data.len() % 2 == 1 {
+            bytes.push(0);
+        }
+        bytes
+    }
+}
+
+/// Splits a text into parts of at most `max_chars` characters, at paragraph
+/// breaks where possible and otherwise between words
+fn split_for_speech(text: &str, max_chars: usize) -> Vec<String> {
+    // Paragraphs, with the ones too long to read at once split between words
+    let mut pieces = Vec::new();
+    for paragraph in text.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+        let mut piece = String::new();
+        for word in paragraph.split_whitespace() {
+            if !piece.is_empty() && piece.chars().count() + 1 + word.chars().count() > max_chars {
+                pieces.push(std::mem::take(&mut piece));
+            }
+            if !piece.is_empty() {
+                piece.push(' ');
+            }
+            piece.push_str(word);
+        }
+        pieces.push(piece);
+    }
+
+    let mut parts: Vec<String> = Vec::new();
+    for piece in pieces {
+        match parts.last_mut() {
+            Some(part) if part.chars().count() + 2 + piece.chars().count() <= max_chars => {
+                part.push_str("\n\n");
+                part.push_str(&piece);
+            }
+            _ => parts.push(piece),
+        }
+    }
+    parts
+}
+
+/// Appends a clip to the audiobook after a pause, or starts the audiobook with it
+fn join(audiobook: &mut Option<Wav>, clip: &Wav) -> Result<(), StoryChainError> {
+    match audiobook {
+        Some(audiobook) => {
+            audiobook.append_silence(SCENE_PAUSE);
+            audiobook.append(clip)
+        }
+        None => {
+            *audiobook = Some(clip.clone());
+            Ok(())
+        }
+    }
+}
+
+/// A chapter of the audiobook and where it plays
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChapterMarker {
+    /// Title of the chapter, or `Scene <n>` when the story has no chapters
+    pub title: String,
+
+    /// Where the chapter starts in the audiobook
+    pub start: Duration,
+
+    /// Where the chapter ends in the audiobook
+    pub end: Duration,
+}
+
+/// Writes chapter markers in ffmpeg's metadata format
+///
+/// # Arguments
+/// * `title` - Title of the audiobook
+/// * `voice` - Name of the voice that read it
+/// * `markers` - The chapters of the audiobook
+pub fn chapters_metadata(title: &str, voice: &str, markers: &[ChapterMarker]) -> String {
+    let escape = |text: &str| {
+        text.chars()
+            .flat_map(|c| match c {
+                '=' | ';' | '#' | '\\' | '\n' => vec!['\\', c],
+                c => vec![c],
+            })
+            .collect::<String>()
+    };
+    let mut metadata = format!(";FFMETADATA1\ntitle={}\nartist={}\n", escape(title), escape(voice));
+    for marker in markers {
+        metadata.push_str(&format!(
+            "\n[CHAPTER]\nTIMEBASE=1/1000\nSTART={}\nEND={}\ntitle={}\n",
+            marker.start.as_millis(),
+            marker.end.as_millis(),
+            escape(&marker.title)
+        ));
+    }
+    metadata
+}
+
+impl StoryChain {
+    /// Reads a scene aloud, in parts when it is longer than the provider accepts
+    async fn speak(&self, tts: &dyn TtsProvider, text: &str) -> Result<Wav, StoryChainError> {
+        let parts = match tts.max_input_chars() {
+            Some(max_chars) => split_for_speech(text, max_chars),
+            None => vec![text.trim().to_string()],
+        };
+        let mut speech: Option<Wav> = None;
+        for part in parts {
+            let clip = Wav::parse(&tts.synthesize(&part).await?)?;
+            match &mut speech {
+                Some(speech) => speech.append(&clip)?,
+                None => speech = Some(clip),
+            }
+        }
+        speech.ok_or_else(|| StoryChainError::AudioError("Nothing to read aloud".to_string()))
+    }
+
+    /// Exports the main storyline as an audiobook
+    ///
+    /// Every scene is read aloud and saved as `<n>_<node id>.wav` in the
+    /// directory. The scenes are then joined, with each chapter's title read
+    /// before its first scene and a pause between scenes, into
+    /// `audiobook.wav`, and the chapter markers are written to `chapters.txt`
+    /// in ffmpeg's metadata format, ready to be muxed into an `.m4b`. Without
+    /// chapters, every scene gets a marker of its own.
+    ///
+    /// # Arguments
+    /// * `tts` - The speech engine to read with
+    /// * `directory` - The directory to write the audiobook to; it is created if missing
+    ///
+    /// # Returns
+    /// The chapter markers of the audiobook
+    pub async fn export_to_audio(&self, tts: &dyn TtsProvider, directory: &str) -> Result<Vec<ChapterMarker>, StoryChainError> {
+        info!("Exporting story as an audiobook to {}", directory);
+        let directory = Path::new(directory);
+        std::fs::create_dir_all(directory)?;
+
+        // Chapters, or every scene in reading order when the story has none
+        let sections: Vec<(String, bool, Vec<&StoryNode>)> = if self.chapters.is_empty() {
+            self.nodes_in_reading_order()
+                .into_iter()
+                .enumerate()
+                .map(|(index, node)| (format!("Scene {}", index + 1), false, vec![node]))
+                .collect()
+        } else {
+            self.chapters
+                .iter()
+                .map(|chapter| (chapter.title.clone(), true, chapter.node_ids.iter().filter_map(|id| self.nodes.get(id)).collect()))
+                .collect()
+        };
+        let scene_count: usize = sections.iter().map(|(_, _, nodes)| nodes.len()).sum();
+
+        let mut audiobook: Option<Wav> = None;
+        let mut markers = Vec::new();
+        let mut scene_number = 0;
+        for (title, spoken, nodes) in sections {
+            let start = audiobook.as_ref().map_or(Duration::ZERO, Wav::duration);
+            if spoken && !title.trim().is_empty() {
+                let heading = self.speak(tts, &title).await?;
+                join(&mut audiobook, &heading)?;
+            }
+            for node in nodes {
+                scene_number += 1;
+                info!("Reading scene {} of {} aloud", scene_number, scene_count);
+                let scene = self.speak(tts, &node.content).await?;
+                write_atomic(directory.join(format!("{:03}_{}.wav", scene_number, node.id)), scene.to_bytes())?;
+                join(&mut audiobook, &scene)?;
+            }
+            let end = audiobook.as_ref().map_or(Duration::ZERO, Wav::duration);
+            markers.push(ChapterMarker { title, start, end });
+        }
+
+        let audiobook = audiobook.ok_or_else(|| StoryChainError::AudioError("The story has no scenes to read".to_string()))?;
+        write_atomic(directory.join(AUDIOBOOK_FILE), audiobook.to_bytes())?;
+        let title = self.metadata.get("title").map(String::as_str).unwrap_or("Generated Story");
+        write_atomic(directory.join(CHAPTERS_FILE), chapters_metadata(title, tts.voice(), &markers))?;
+        info!("Exported {} scenes, {:.0} seconds of audio", scene_count, audiobook.duration().as_secs_f64());
+        Ok(markers)
+    }
+}
@@ -0,0 +1,251 @@
+//! Branching
+//!
+//! A node may have several alternative continuations, which turns the chain
+//! into a tree. The first successor of every node forms the main storyline,
+//! which generation continues and exports present as the story; the other
+//! successors start alternative branches. Branches can be merged back into a
+//! single scene with several predecessors, which makes the chain a directed
+//! acyclic graph. This module walks the graph, steers new alternatives away
+//! from the existing ones, merges branches, and renders the alternative
+//! branches for the markdown export.
+
+use std::collections::HashMap;
+use log::{debug, info};
+use tera::Context;
+use crate::provenance::NodeProvenance;
+use crate::{AIProvider, StoryChain, StoryChainError, StoryNode};
+
+/// Characters of each existing alternative quoted when steering a new one away from it
+const ALTERNATIVE_EXCERPT_CHARS: usize = 300;
+
+/// Name of the merged scene prompt template, recorded in the provenance of merged scenes
+pub const MERGE: &str = "merge";
+
+/// Built-in template of the merged scene prompt
+///
+/// Variables: `scene_before`, the last scene before the split (empty when
+/// the branches share none); `branches`, the scenes of each branch after
+/// the split, as a list of lists.
+pub const MERGE_TEMPLATE: &str = r#"The story below split into {{ branches | length }} alternative branches after the same scene. Write the next scene, in which the branches converge into a single storyline. Reconcile their events so that the scene follows naturally from every branch, keeping what matters from each and resolving contradictions.
+
+{% if scene_before %}Scene Before the Split:
+{{ scene_before }}
+
+{% endif %}{% for branch in branches %}Branch {{ loop.index }}:
+{% for scene in branch %}{{ scene }}
+
+{% endfor %}{% endfor %}IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your reasoning about how the branches come together.
+</think>
+Write the merged scene here."#;
+
+impl StoryChain {
+    /// Returns the nodes from the root to the given node, following the
+    /// predecessor each node was generated from
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the last node of the path
+    pub fn path_to(&self, node_id: &str) -> Vec<&StoryNode> {
+        let mut path = Vec::new();
+        let mut current = self.nodes.get(node_id);
+        while let Some(node) = current {
+            // Guard against malformed chains that link back onto themselves
+            if path.iter().any(|n: &&StoryNode| n.id == node.id) {
+                break;
+            }
+            path.push(node);
+            current = node.predecessor().and_then(|id| self.nodes.get(id));
+        }
+        path.reverse();
+        path
+    }
+
+    /// Returns the IDs of the nodes without successors, in depth-first order
+    /// with the main storyline first
+    pub fn leaf_ids(&self) -> Vec<String> {
+        self.storylines().iter().filter_map(|line| line.last().map(|n| n.id.clone())).collect()
+    }
+
+    /// Returns every path from the root to a leaf, in depth-first order with
+    /// the main storyline first
+    pub fn storylines(&self) -> Vec<Vec<&StoryNode>> {
+        let mut lines = Vec::new();
+        let Some(root) = self.nodes.get(&self.root_node_id) else {
+            return lines;
+        };
+        let mut stack = vec![vec![root]];
+        while let Some(path) = stack.pop() {
+            let last = path[path.len() - 1];
+            let successors: Vec<&StoryNode> = last
+                .successor_ids()
+                .iter()
+                .filter_map(|id| self.nodes.get(*id))
+                .filter(|n| !path.iter().any(|p| p.id == n.id))
+                .collect();
+            if successors.is_empty() {
+                lines.push(path);
+                continue;
+            }
+            // Push in reverse so the first successor is explored first
+            for successor in successors.into_iter().rev() {
+                let mut next = path.clone();
+                next.push(successor);
+                stack.push(next);
+            }
+        }
+        lines
+    }
+
+    /// Returns guidance asking for a continuation unlike the ones a node already has
+    ///
+    /// # Arguments
+    /// * `current_node_id` - ID of the node the next scene will follow
+    pub fn branch_guidance(&self, current_node_id: &str) -> Vec<String> {
+        let Some(node) = self.nodes.get(current_node_id) else {
+            return Vec::new();
+        };
+        let existing: Vec<String> = node
+            .successor_ids()
+            .iter()
+            .filter_map(|id| self.nodes.get(*id))
+            .map(|n| {
+                let excerpt: String = n.content.chars().take(ALTERNATIVE_EXCERPT_CHARS).collect();
+                format!("\"{}...\"", excerpt.split_whitespace().collect::<Vec<_>>().join(" "))
+            })
+            .collect();
+        if existing.is_empty() {
+            return Vec::new();
+        }
+        debug!("Steering away from {} existing continuations of {}", existing.len(), current_node_id);
+        vec![format!(
+            "This is alternative continuation {} of this scene. Take the story in a clearly different \
+            direction from the existing ones, which begin: {}",
+            existing.len() + 1,
+            existing.join("; ")
+        )]
+    }
+
+    /// Renders the branches that leave the main storyline as markdown, one
+    /// section per branch starting at the scene it diverges from
+    pub fn alternative_branches_markdown(&self) -> String {
+        let lines = self.storylines();
+        if lines.len() < 2 {
+            return String::new();
+        }
+        let main: Vec<&str> = lines[0].iter().map(|n| n.id.as_str()).collect();
+        let mut content = String::from("## Alternative Branches\n\n");
+        let mut rendered: Vec<&str> = main.clone();
+
+        for (index, line) in lines.iter().enumerate().skip(1) {
+            // Each branch is shown from where it leaves the storylines already shown
+            let fork = line.iter().take_while(|n| rendered.contains(&n.id.as_str())).count();
+            let Some(parent) = fork.checked_sub(1).map(|i| &line[i]) else {
+                continue;
+            };
+            let parent_label = match main.iter().position(|id| *id == parent.id) {
+                Some(position) => format!("Scene {}", position + 1),
+                None => parent.id.clone(),
+            };
+            content.push_str(&format!("### Branch {} (after {})\n\n", index, parent_label));
+            for node in &line[fork..] {
+                // Merged branches stop where they rejoin a storyline already shown
+                if rendered.contains(&node.id.as_str()) {
+                    let label = match main.iter().position(|id| *id == node.id) {
+                        Some(position) => format!("Scene {}", position + 1),
+                        None => node.id.clone(),
+                    };
+                    content.push_str(&format!("*Rejoins at {}.*\n\n", label));
+                    break;
+                }
+                content.push_str(&format!("#### {}\n\n{}\n\n", node.id, node.content));
+                rendered.push(&node.id);
+            }
+            content.push_str("---\n\n");
+        }
+        content
+    }
+
+    /// Merges divergent branches back into a single storyline
+    ///
+    /// The AI writes one continuation that reconciles the events of every
+    /// branch. The new node follows all of the given nodes, and its
+    /// `merged_from` metadata lists them.
+    ///
+    /// # Arguments
+    /// * `node_ids` - IDs of the last scenes of the branches to merge; the
+    ///   first one is the predecessor the merged scene continues
+    /// * `ai_provider` - The AI provider to use for generation
+    ///
+    /// # Returns
+    /// The ID of the merged node
+    pub async fn merge_nodes(&mut self, node_ids: &[&str], ai_provider: &dyn AIProvider) -> Result<String, StoryChainError> {
+        if node_ids.len() < 2 {
+            return Err(StoryChainError::InvalidChainOperation("Merging needs at least two nodes".to_string()));
+        }
+        for (index, id) in node_ids.iter().enumerate() {
+            if !self.nodes.contains_key(*id) {
+                return Err(StoryChainError::InvalidChainOperation(format!("Cannot merge unknown node: {}", id)));
+            }
+            if node_ids[..index].contains(id) {
+                return Err(StoryChainError::InvalidChainOperation(format!("Node {} is listed more than once", id)));
+            }
+        }
+
+        // Split the paths into the shared history and the scenes of each branch
+        let paths: Vec<Vec<&StoryNode>> = node_ids.iter().map(|id| self.path_to(id)).collect();
+        let shared = (0..paths.iter().map(Vec::len).min().unwrap_or(0))
+            .take_while(|&i| paths.iter().all(|p| p[i].id == paths[0][i].id))
+            .count();
+        if paths.iter().any(|p| p.len() == shared) {
+            return Err(StoryChainError::InvalidChainOperation(
+                "Cannot merge a node with one of its own ancestors".to_string(),
+            ));
+        }
+
+        let branches: Vec<Vec<&str>> =
+            paths.iter().map(|path| path[shared..].iter().map(|node| node.content.trim()).collect()).collect();
+        let mut context = Context::new();
+        context.insert("scene_before", shared.checked_sub(1).map(|i| paths[0][i].content.trim()).unwrap_or_default());
+        context.insert("branches", &branches);
+        let prompt = self.render_prompt(MERGE, context)?;
+        let provenance = NodeProvenance::new(
+            ai_provider,
+            &self.prompts,
+            MERGE,
+            self.settings.system_prompt.as_deref(),
+            &self.settings.generation,
+        );
+        let generation_start = std::time::Instant::now();
+        let usage_before = ai_provider.usage();
+        let (reasoning, content) = ai_provider
+            .generate_with_config(self.settings.system_prompt.as_deref(), &prompt, &self.settings.generation)
+            .await?;
+
+        let new_id = self.next_node_id();
+        let mut metadata = HashMap::new();
+        metadata.insert("merged_from".to_string(), node_ids.join(", "));
+        self.nodes.insert(
+            new_id.clone(),
+            StoryNode {
+                id: new_id.clone(),
+                content,
+                reasoning,
+                predecessors: node_ids.iter().map(|id| id.to_string()).collect(),
+                successors: Vec::new(),
+                metadata,
+                feedback: Vec::new(),
+                scene_card: None,
+                embedding: None,
+                provenance: Some(provenance.with_duration(generation_start.elapsed()).with_usage(usage_before, ai_provider.usage())),
+            },
+        );
+        for id in node_ids {
+            if let Some(node) = self.nodes.get_mut(*id) {
+                node.successors.push(new_id.clone());
+            }
+        }
+        info!("Merged {} into {}", node_ids.join(", "), new_id);
+        Ok(new_id)
+    }
+}
@@ -0,0 +1,147 @@
+//! Cancellation
+//!
+//! This module lets callers stop generation cleanly, for example when the
+//! command line is interrupted or a client of the server cancels a run. A
+//! `CancellationToken` given to a chain, a `StoryRunner`, or a
+//! `CancellableProvider` is checked before every scene and raced against the
+//! requests in flight; once it is cancelled, generation stops with
+//! `StoryChainError::Cancelled` and the request in flight is dropped. The
+//! scenes generated before then are kept with `save_cancelled_run`.
+
+use log::warn;
+use std::future::Future;
+use tokio::sync::mpsc::UnboundedSender;
+use crate::storage::ChainStore;
+use crate::trace::PERSIST_SPAN;
+use crate::{AIProvider, GenerationConfig, StoryChain, StoryChainError, TokenUsage};
+
+pub use tokio_util::sync::CancellationToken;
+
+/// Runs some work until it finishes or the token is cancelled
+///
+/// # Arguments
+/// * `token` - The token stopping the work, if any
+/// * `work` - The work
+///
+/// # Returns
+/// The result of the work, or `StoryChainError::Cancelled`
+pub async fn until_cancelled<T>(
+    token: Option<&CancellationToken>,
+    work: impl Future<Output = Result<T, StoryChainError>>,
+) -> Result<T, StoryChainError> {
+    let Some(token) = token else {
+        return work.await;
+    };
+    tokio::select! {
+        biased;
+        _ = token.cancelled() => Err(StoryChainError::Cancelled),
+        result = work => result,
+    }
+}
+
+/// Wraps a provider so that its requests stop with
+/// `StoryChainError::Cancelled` once a token is cancelled
+pub struct CancellableProvider<P: AIProvider> {
+    /// The provider answering the requests
+    inner: P,
+
+    /// The token stopping the requests
+    token: CancellationToken,
+}
+
+impl<P: AIProvider> CancellableProvider<P> {
+    /// Creates a new CancellableProvider
+    ///
+    /// # Arguments
+    /// * `inner` - The provider answering the requests
+    /// * `token` - The token stopping the requests
+    pub fn new(inner: P, token: CancellationToken) -> Self {
+        Self { inner, token }
+    }
+}
+
+#[async_trait::async_trait]
+impl<P: AIProvider> AIProvider for CancellableProvider<P> {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        until_cancelled(Some(&self.token), self.inner.generate(prompt)).await
+    }
+
+    async fn generate_with_system(&self, system_prompt: &str, prompt: &str) -> Result<(String, String), StoryChainError> {
+        until_cancelled(Some(&self.token), self.inner.generate_with_system(system_prompt, prompt)).await
+    }
+
+    async fn generate_with_config(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+    ) -> Result<(String, String), StoryChainError> {
+        until_cancelled(Some(&self.token), self.inner.generate_with_config(system_prompt, prompt, config)).await
+    }
+
+    async fn generate_stream(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+        tokens: UnboundedSender<String>,
+    ) -> Result<(String, String), StoryChainError> {
+        until_cancelled(Some(&self.token), self.inner.generate_stream(system_prompt, prompt, config, tokens)).await
+    }
+
+    fn provider_name(&self) -> &str {
+        self.inner.provider_name()
+    }
+
+    fn model_name(&self) -> Option<&str> {
+        self.inner.model_name()
+    }
+
+    fn usage(&self) -> Option<TokenUsage> {
+        self.inner.usage()
+    }
+}
+
+impl StoryChain {
+    /// Returns `StoryChainError::Cancelled` once the chain's cancellation
+    /// token is cancelled
+    pub fn check_cancelled(&self) -> Result<(), StoryChainError> {
+        match &self.cancellation {
+            Some(token) if token.is_cancelled() => Err(StoryChainError::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    /// Keeps the scenes of a run that was cancelled instead of losing them
+    ///
+    /// A run that stopped with `StoryChainError::Cancelled` is saved to the
+    /// store and exported to markdown as it stands, so that it can be read
+    /// and continued later; a run that failed otherwise returns its error.
+    ///
+    /// # Arguments
+    /// * `result` - How the run ended
+    /// * `store` - The store the chain is saved to
+    /// * `name` - Name the chain is stored under
+    /// * `markdown_path` - Path of the markdown export
+    ///
+    /// # Returns
+    /// Whether the run was cancelled and its partial story saved
+    pub fn save_cancelled_run(
+        &self,
+        result: Result<(), StoryChainError>,
+        store: &dyn ChainStore,
+        name: &str,
+        markdown_path: &str,
+    ) -> Result<bool, StoryChainError> {
+        match result {
+            Ok(()) => Ok(false),
+            Err(StoryChainError::Cancelled) => {
+                warn!("Interrupted; exporting the {} scenes generated so far", self.nodes.len());
+                tracing::info_span!(PERSIST_SPAN).in_scope(|| store.save_chain(name, self))?;
+                self.export_to_markdown(markdown_path)?;
+                Ok(true)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
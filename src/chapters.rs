@@ -0,0 +1,229 @@
+//! Chapter Grouping
+//!
+//! This module groups the nodes of a story chain into chapters, and chapters
+//! into acts. Chapters are stored on the chain alongside the nodes and carry
+//! their own metadata, such as the "previously on" summaries used for
+//! serialized publishing; the act of a chapter is stored on the chapter.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use log::{info, debug};
+use tera::Context;
+use crate::passes::SCENE_EXCERPT_CHARS;
+use crate::{AIProvider, StoryChain, StoryChainError};
+
+/// Name of the chapter summary prompt template
+pub const CHAPTER_SUMMARY: &str = "chapter_summary";
+
+/// Built-in template of the chapter summary prompt
+///
+/// Variables: `chapter`, the chapter title; `scenes`, excerpts of the
+/// chapter's scenes.
+pub const CHAPTER_SUMMARY_TEMPLATE: &str = r#"You are writing the "previously on" recap for a serialized story. Summarize the events of {{ chapter }} below in three to five sentences, in the past tense, focusing on what a returning reader needs to remember.
+
+{{ chapter }}:
+{% for scene in scenes %}{{ scene }}
+
+{% endfor %}IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your reasoning about which events the reader must remember.
+</think>
+Write the recap here, with no heading or extra formatting."#;
+
+/// A group of consecutive scenes presented together in exports
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Chapter {
+    /// Display title of the chapter
+    pub title: String,
+
+    /// IDs of the nodes in this chapter, in reading order
+    pub node_ids: Vec<String>,
+
+    /// Title of the act the chapter belongs to, if the story has acts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<String>,
+
+    /// Additional metadata associated with this chapter
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+}
+
+impl Chapter {
+    /// Creates a new chapter containing the given nodes
+    pub fn new(title: String, node_ids: Vec<String>) -> Self {
+        Self {
+            title,
+            node_ids,
+            act: None,
+            metadata: HashMap::new(),
+        }
+    }
+}
+
+/// Returns the roman numeral for a number, as used in act titles
+fn roman_numeral(mut number: usize) -> String {
+    const NUMERALS: [(usize, &str); 13] = [
+        (1000, "M"), (900, "CM"), (500, "D"), (400, "CD"), (100, "C"), (90, "XC"), (50, "L"),
+        (40, "XL"), (10, "X"), (9, "IX"), (5, "V"), (4, "IV"), (1, "I"),
+    ];
+    let mut numeral = String::new();
+    for (value, symbol) in NUMERALS {
+        while number >= value {
+            numeral.push_str(symbol);
+            number -= value;
+        }
+    }
+    numeral
+}
+
+impl StoryChain {
+    /// Replaces the chain's chapters with fixed-size groups of scenes in reading order
+    ///
+    /// # Arguments
+    /// * `scenes_per_chapter` - Number of scenes in each chapter (the last chapter may be shorter)
+    pub fn group_into_chapters(&mut self, scenes_per_chapter: usize) {
+        let ids: Vec<String> = self.nodes_in_reading_order().iter().map(|n| n.id.clone()).collect();
+        self.chapters = ids
+            .chunks(scenes_per_chapter.max(1))
+            .enumerate()
+            .map(|(index, chunk)| Chapter::new(format!("Chapter {}", index + 1), chunk.to_vec()))
+            .collect();
+        debug!("Grouped {} scenes into {} chapters", ids.len(), self.chapters.len());
+    }
+
+    /// Divides the chapters into acts of nearly equal size, titled "Act I",
+    /// "Act II", and so on; earlier acts get the extra chapters
+    ///
+    /// # Arguments
+    /// * `act_count` - Number of acts, at most one per chapter
+    pub fn group_into_acts(&mut self, act_count: usize) {
+        let chapter_count = self.chapters.len();
+        let act_count = act_count.clamp(1, chapter_count.max(1));
+        let mut start = 0;
+        for act in 0..act_count {
+            let size = chapter_count / act_count + usize::from(act < chapter_count % act_count);
+            for chapter in &mut self.chapters[start..start + size] {
+                chapter.act = Some(format!("Act {}", roman_numeral(act + 1)));
+            }
+            start += size;
+        }
+        debug!("Grouped {} chapters into {} acts", chapter_count, act_count);
+    }
+
+    /// Moves a node into the chapter with the given title, creating the
+    /// chapter at the end of the story when there is none
+    ///
+    /// The chapter's nodes are kept in reading order, and chapters left
+    /// empty by the move are removed.
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node to move
+    /// * `chapter_title` - Title of the chapter to move it into
+    pub fn assign_to_chapter(&mut self, node_id: &str, chapter_title: &str) -> Result<(), StoryChainError> {
+        if !self.nodes.contains_key(node_id) {
+            return Err(StoryChainError::InvalidChainOperation(format!("Cannot assign unknown node: {}", node_id)));
+        }
+        for chapter in &mut self.chapters {
+            chapter.node_ids.retain(|id| id != node_id);
+        }
+        let index = match self.chapters.iter().position(|c| c.title == chapter_title) {
+            Some(index) => index,
+            None => {
+                let act = self.chapters.last().and_then(|c| c.act.clone());
+                self.chapters.push(Chapter { act, ..Chapter::new(chapter_title.to_string(), Vec::new()) });
+                self.chapters.len() - 1
+            }
+        };
+
+        let order: HashMap<String, usize> =
+            self.nodes_in_reading_order().iter().enumerate().map(|(position, node)| (node.id.clone(), position)).collect();
+        let chapter = &mut self.chapters[index];
+        chapter.node_ids.push(node_id.to_string());
+        chapter.node_ids.sort_by_key(|id| order.get(id).copied().unwrap_or(usize::MAX));
+        self.chapters.retain(|c| !c.node_ids.is_empty());
+        debug!("Assigned {} to chapter {}", node_id, chapter_title);
+        Ok(())
+    }
+
+    /// Returns the chapter starting at the given node, with its index and
+    /// the title of the act it opens, if it is the first chapter of an act
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node to look up
+    pub fn chapter_starting_at(&self, node_id: &str) -> Option<(usize, &Chapter, Option<&str>)> {
+        let index = self.chapters.iter().position(|c| c.node_ids.first().map(String::as_str) == Some(node_id))?;
+        let chapter = &self.chapters[index];
+        let previous_act = index.checked_sub(1).and_then(|i| self.chapters[i].act.as_deref());
+        let act = chapter.act.as_deref().filter(|act| Some(*act) != previous_act);
+        Some((index, chapter, act))
+    }
+
+    /// Returns the "previously on" recap shown at the head of a chapter:
+    /// the summary of the chapter before it, if it has one
+    ///
+    /// # Arguments
+    /// * `chapter_index` - Index of the chapter the recap opens
+    pub fn chapter_recap(&self, chapter_index: usize) -> Option<&str> {
+        let previous = &self.chapters[chapter_index.checked_sub(1)?];
+        previous.metadata.get("summary").map(|summary| summary.trim()).filter(|summary| !summary.is_empty())
+    }
+
+    /// Returns the heading levels of acts, chapters and scenes in exports
+    ///
+    /// The title is the only level-one heading and each level nests inside
+    /// the one above it, so chapters sit below acts only when the story has
+    /// acts, and scenes below chapters only when it has chapters.
+    ///
+    /// # Returns
+    /// The `(act, chapter, scene)` heading levels
+    pub fn heading_levels(&self) -> (usize, usize, usize) {
+        let chapter = 2 + usize::from(self.chapters.iter().any(|c| c.act.is_some()));
+        let scene = chapter + usize::from(!self.chapters.is_empty());
+        (2, chapter, scene)
+    }
+
+    /// Returns the chapter containing the given node, if any
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node to look up
+    pub fn chapter_of(&self, node_id: &str) -> Option<&Chapter> {
+        self.chapters.iter().find(|c| c.node_ids.iter().any(|id| id == node_id))
+    }
+
+    /// Generates a short summary of each chapter and stores it in the chapter
+    /// metadata under the `summary` key
+    ///
+    /// The summary of a chapter is written so that it can serve as the
+    /// "previously on" recap at the head of the following chapter.
+    ///
+    /// # Arguments
+    /// * `ai_provider` - The AI provider to use for generation
+    pub async fn generate_chapter_summaries(
+        &mut self,
+        ai_provider: &dyn AIProvider,
+    ) -> Result<(), StoryChainError> {
+        info!("Generating summaries for {} chapters", self.chapters.len());
+
+        for index in 0..self.chapters.len() {
+            let chapter = &self.chapters[index];
+            let scenes: Vec<String> = chapter
+                .node_ids
+                .iter()
+                .filter_map(|node_id| self.nodes.get(node_id))
+                .map(|node| node.content.chars().take(SCENE_EXCERPT_CHARS * 2).collect::<String>().trim().to_string())
+                .collect();
+            let mut context = Context::new();
+            context.insert("chapter", &chapter.title);
+            context.insert("scenes", &scenes);
+            let prompt = self.render_prompt(CHAPTER_SUMMARY, context)?;
+
+            let (_, summary) = ai_provider.generate(&prompt).await?;
+            debug!("Generated summary for {}", self.chapters[index].title);
+            self.chapters[index]
+                .metadata
+                .insert("summary".to_string(), summary.trim().to_string());
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,492 @@
+//! Character Extraction
+//!
+//! This module derives character information from a generated story chain.
+//! Character sheets describe what the model actually established about each
+//! character (appearance, arc, and the scenes they appear in) and are stored
+//! as artifacts so that they can seed sequels or be reviewed for consistency.
+//! The character registry tracks introduced names and flags suspicious
+//! variants in newly generated scenes. Hand-written `CharacterSheet`
+//! artifacts describe each character's traits, goals, relationships, and
+//! voice, and the sheets of the characters in the previous scene are added
+//! to every continuation prompt.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use log::{info, debug, warn};
+use tera::Context;
+use crate::artifacts::{Artifact, ArtifactManager, ArtifactType};
+use crate::passes::{parse_labeled_fields, split_blocks, SCENE_EXCERPT_CHARS};
+use crate::{AIProvider, StoryChain, StoryChainError};
+
+/// Labels used in the character sheet response format
+const SHEET_LABELS: [&str; 4] = ["CHARACTER", "APPEARANCE", "ARC", "KEY SCENES"];
+
+/// Name of the character sheet prompt template
+pub const CHARACTER_SHEETS: &str = "character_sheets";
+
+/// Built-in template of the character sheet prompt
+///
+/// Variables: `story`, excerpts of every scene, each labeled with its node ID.
+pub const CHARACTER_SHEETS_TEMPLATE: &str = r#"You are a story analyst. Read the scenes below, each labeled with its node ID in brackets, and describe every named character as they are actually portrayed in the text.
+
+Story:
+{{ story }}
+IMPORTANT: Format your response EXACTLY as follows, repeating the block for each character:
+<think>
+Your reasoning about which characters matter and how they are portrayed.
+</think>
+CHARACTER: The character's full name
+APPEARANCE: Physical description established in the text
+ARC: How the character changes over the story
+KEY SCENES: Comma-separated node IDs of the scenes where the character matters most"#;
+
+/// Converts a display name into an identifier-safe slug
+///
+/// # Arguments
+/// * `name` - The name to convert
+pub fn slugify(name: &str) -> String {
+    let mut slug = String::new();
+    for c in name.chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.ends_with('_') {
+            slug.push('_');
+        }
+    }
+    slug.trim_matches('_').to_string()
+}
+
+/// Capitalized words that commonly appear mid-sentence without being names
+const COMMON_CAPITALIZED: [&str; 18] = [
+    "Mr", "Mrs", "Ms", "Dr", "God", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday",
+    "Saturday", "Sunday", "English", "Christmas", "Lord", "Lady", "Sir", "Madam",
+];
+
+/// A character name known to the registry
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RegisteredCharacter {
+    /// Canonical name of the character
+    pub name: String,
+
+    /// Alternative names (nicknames, first or last names) that refer to the same character
+    pub aliases: Vec<String>,
+
+    /// ID of the node where the name was introduced, or `None` if it came from the premise
+    pub introduced_in: Option<String>,
+}
+
+/// A problem found when checking a scene against the character registry
+#[derive(Debug, Clone, PartialEq)]
+pub enum NameIssue {
+    /// A name that closely resembles, but does not match, a registered name
+    SuspiciousVariant {
+        /// The name as it appears in the scene
+        found: String,
+        /// The registered name it most likely refers to
+        expected: String,
+    },
+
+    /// A name that has not been introduced before
+    UnintroducedName(String),
+}
+
+impl std::fmt::Display for NameIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NameIssue::SuspiciousVariant { found, expected } => {
+                write!(f, "'{}' looks like a misspelling of '{}'", found, expected)
+            }
+            NameIssue::UnintroducedName(name) => write!(f, "'{}' has not been introduced", name),
+        }
+    }
+}
+
+/// Registry of introduced character names used for consistency checking
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CharacterRegistry {
+    /// Registered characters in order of introduction
+    pub characters: Vec<RegisteredCharacter>,
+
+    /// Replace suspicious variants with the registered name instead of only flagging them
+    #[serde(default)]
+    pub auto_correct: bool,
+}
+
+/// Computes the Levenshtein edit distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for i in 1..=a.len() {
+        let mut current = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            current[j] = (previous[j] + 1).min(current[j - 1] + 1).min(previous[j - 1] + cost);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// Extracts capitalized words that do not start a sentence, which are likely proper names
+pub(crate) fn candidate_names(text: &str) -> Vec<String> {
+    let re = regex::Regex::new(r"\b[A-Z][a-z]+\b").unwrap();
+    let mut names = Vec::new();
+
+    for m in re.find_iter(text) {
+        let preceding = text[..m.start()]
+            .chars()
+            .rev()
+            .find(|c| !c.is_whitespace() && !"\"'“‘—-(".contains(*c));
+        let starts_sentence = matches!(preceding, None | Some('.') | Some('!') | Some('?') | Some(':'))
+            || text[..m.start()].trim_end_matches([' ', '"', '“', '\'']).ends_with('\n');
+        let word = m.as_str().to_string();
+
+        if !starts_sentence && !COMMON_CAPITALIZED.contains(&word.as_str()) && !names.contains(&word) {
+            names.push(word);
+        }
+    }
+
+    names
+}
+
+impl CharacterRegistry {
+    /// Creates a registry seeded with the character names listed in a premise
+    ///
+    /// Names are read from `name:` entries, and each part of a name (including
+    /// quoted nicknames such as `Vincent 'Vince' Delacroix`) becomes an alias.
+    ///
+    /// # Arguments
+    /// * `premise` - The premise text to read names from
+    pub fn from_premise(premise: &str) -> Self {
+        let re = regex::Regex::new(r#"(?m)^\s*-?\s*name:\s*"?([^"\n#]+)"?"#).unwrap();
+        let mut registry = Self::default();
+        for caps in re.captures_iter(premise) {
+            registry.register(caps[1].trim(), None);
+        }
+        registry
+    }
+
+    /// Registers a character name, deriving aliases from its parts
+    ///
+    /// # Arguments
+    /// * `full_name` - The name to register, optionally containing a quoted nickname
+    /// * `node_id` - ID of the node where the name was introduced
+    pub fn register(&mut self, full_name: &str, node_id: Option<&str>) {
+        let nickname_re = regex::Regex::new(r#"['"‘“]([^'"’”]+)['"’”]"#).unwrap();
+        let aliases: Vec<String> = nickname_re
+            .captures_iter(full_name)
+            .map(|c| c[1].to_string())
+            .chain(
+                nickname_re
+                    .replace_all(full_name, " ")
+                    .split_whitespace()
+                    .map(str::to_string),
+            )
+            .filter(|part| part.chars().next().is_some_and(char::is_uppercase))
+            .collect();
+        let name = nickname_re.replace_all(full_name, " ").split_whitespace().collect::<Vec<_>>().join(" ");
+
+        if self.lookup(&name).is_some() {
+            return;
+        }
+        debug!("Registering character name: {}", name);
+        self.characters.push(RegisteredCharacter {
+            name,
+            aliases,
+            introduced_in: node_id.map(str::to_string),
+        });
+    }
+
+    /// Returns the registered character that uses the given name or alias
+    ///
+    /// # Arguments
+    /// * `name` - The name or alias to look up
+    pub fn lookup(&self, name: &str) -> Option<&RegisteredCharacter> {
+        self.characters
+            .iter()
+            .find(|c| c.name == name || c.aliases.iter().any(|a| a == name))
+    }
+
+    /// Returns true if no names have been registered, in which case checks are skipped
+    pub fn is_empty(&self) -> bool {
+        self.characters.is_empty()
+    }
+
+    /// Checks a scene for misspelled variants of registered names and for
+    /// names that have not been introduced yet
+    ///
+    /// # Arguments
+    /// * `text` - The scene content to check
+    pub fn check_scene(&self, text: &str) -> Vec<NameIssue> {
+        let known: Vec<&String> = self
+            .characters
+            .iter()
+            .flat_map(|c| std::iter::once(&c.name).chain(c.aliases.iter()))
+            .collect();
+
+        candidate_names(text)
+            .into_iter()
+            .filter(|word| self.lookup(word).is_none())
+            .map(|word| {
+                let closest = known
+                    .iter()
+                    .filter(|k| !k.contains(' ') && k.chars().next() == word.chars().next())
+                    .map(|k| (edit_distance(&word, k), *k))
+                    .min();
+                let threshold = if word.chars().count() >= 8 { 2 } else { 1 };
+                match closest {
+                    Some((distance, expected)) if distance <= threshold && word.chars().count() >= 4 => {
+                        NameIssue::SuspiciousVariant { found: word, expected: expected.clone() }
+                    }
+                    _ => NameIssue::UnintroducedName(word),
+                }
+            })
+            .collect()
+    }
+
+    /// Checks a newly generated scene, correcting suspicious variants when
+    /// `auto_correct` is enabled and registering newly introduced names
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node the scene belongs to
+    /// * `content` - The scene content
+    ///
+    /// # Returns
+    /// The (possibly corrected) content and the issues that were found
+    pub fn review_scene(&mut self, node_id: &str, content: String) -> (String, Vec<NameIssue>) {
+        if self.is_empty() {
+            return (content, Vec::new());
+        }
+
+        let issues = self.check_scene(&content);
+        let mut content = content;
+        for issue in &issues {
+            match issue {
+                NameIssue::SuspiciousVariant { found, expected } => {
+                    warn!("Scene {}: {}", node_id, issue);
+                    if self.auto_correct {
+                        let re = regex::Regex::new(&format!(r"\b{}\b", regex::escape(found))).unwrap();
+                        content = re.replace_all(&content, expected.as_str()).into_owned();
+                    }
+                }
+                NameIssue::UnintroducedName(name) => {
+                    debug!("Scene {}: {}", node_id, issue);
+                    self.register(name, Some(node_id));
+                }
+            }
+        }
+
+        (content, issues)
+    }
+}
+
+/// A character's traits, goals, relationships, and voice, kept as a
+/// `CharacterSheet` artifact whose content is the sheet in YAML
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct CharacterSheet {
+    /// The character's full name, optionally with a quoted nickname
+    pub name: String,
+
+    /// Other names the character goes by, besides the parts of the name
+    pub aliases: Vec<String>,
+
+    /// Personality and physical traits
+    pub traits: Vec<String>,
+
+    /// What the character wants
+    pub goals: Vec<String>,
+
+    /// How the character relates to others, by the other character's name
+    pub relationships: BTreeMap<String, String>,
+
+    /// How the character speaks
+    pub voice: Option<String>,
+}
+
+impl CharacterSheet {
+    /// Returns the ID of the artifact holding the sheet
+    pub fn artifact_id(&self) -> String {
+        format!("sheet_{}", slugify(&self.name))
+    }
+
+    /// Reads a sheet from a `CharacterSheet` artifact
+    ///
+    /// # Arguments
+    /// * `artifact` - The artifact, whose content is the sheet in YAML or JSON
+    pub fn from_artifact(artifact: &Artifact) -> Result<Self, StoryChainError> {
+        let sheet: CharacterSheet = serde_yaml::from_str(&artifact.content).map_err(|e| {
+            StoryChainError::ConfigError(format!("Invalid character sheet {}: {}", artifact.id, e))
+        })?;
+        if sheet.name.trim().is_empty() {
+            return Err(StoryChainError::ConfigError(format!("Character sheet {} has no name", artifact.id)));
+        }
+        Ok(sheet)
+    }
+
+    /// Stores the sheet as a `CharacterSheet` artifact
+    pub fn to_artifact(&self) -> Result<Artifact, StoryChainError> {
+        let content = serde_yaml::to_string(self)
+            .map_err(|e| StoryChainError::ConfigError(format!("Cannot write character sheet {}: {}", self.name, e)))?;
+        let mut metadata = HashMap::new();
+        metadata.insert("name".to_string(), self.name.clone());
+        Ok(Artifact { id: self.artifact_id(), content, artifact_type: ArtifactType::CharacterSheet, metadata, tags: Vec::new() })
+    }
+
+    /// Returns true if the character is mentioned in the text by their name,
+    /// a part of it, a nickname, or an alias
+    ///
+    /// # Arguments
+    /// * `text` - The text to search
+    pub fn is_mentioned_in(&self, text: &str) -> bool {
+        let mut registry = CharacterRegistry::default();
+        registry.register(&self.name, None);
+        let Some(character) = registry.characters.first() else {
+            return false;
+        };
+        std::iter::once(&character.name)
+            .chain(&character.aliases)
+            .chain(&self.aliases)
+            .filter(|name| !name.trim().is_empty())
+            .any(|name| {
+                regex::Regex::new(&format!(r"\b{}\b", regex::escape(name.trim())))
+                    .is_ok_and(|re| re.is_match(text))
+            })
+    }
+
+    /// Lays the sheet out for a prompt
+    pub fn to_prompt_section(&self) -> String {
+        let mut lines = vec![self.name.clone()];
+        if !self.aliases.is_empty() {
+            lines.push(format!("- Also called: {}", self.aliases.join(", ")));
+        }
+        if !self.traits.is_empty() {
+            lines.push(format!("- Traits: {}", self.traits.join("; ")));
+        }
+        if !self.goals.is_empty() {
+            lines.push(format!("- Goals: {}", self.goals.join("; ")));
+        }
+        for (other, relationship) in &self.relationships {
+            lines.push(format!("- With {}: {}", other, relationship));
+        }
+        if let Some(voice) = &self.voice {
+            lines.push(format!("- Voice: {}", voice));
+        }
+        lines.join("\n")
+    }
+}
+
+impl ArtifactManager {
+    /// Returns the character sheets among the loaded artifacts, sorted by
+    /// artifact ID and skipping, with a warning, any that cannot be read
+    pub fn character_sheets(&self) -> Vec<CharacterSheet> {
+        self.get_all_artifacts()
+            .into_iter()
+            .filter(|artifact| artifact.artifact_type == ArtifactType::CharacterSheet)
+            .filter_map(|artifact| match CharacterSheet::from_artifact(artifact) {
+                Ok(sheet) => Some(sheet),
+                Err(error) => {
+                    warn!("Skipping character sheet: {}", error);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Saves a character sheet as the artifact `sheet_<name>`, replacing
+    /// any earlier sheet for the same character
+    ///
+    /// # Arguments
+    /// * `sheet` - The sheet to save
+    pub fn update_character_sheet(&mut self, sheet: &CharacterSheet) -> Result<(), StoryChainError> {
+        self.update_artifact(sheet.to_artifact()?)
+    }
+}
+
+impl StoryChain {
+    /// Generates character-sheet artifacts from the completed chain
+    ///
+    /// Each sheet records a character's appearance, arc, and the node IDs of
+    /// their key scenes. Sheets are saved through the given artifact manager as
+    /// `CharacterArc` artifacts with the ID `character_<name>`.
+    ///
+    /// # Arguments
+    /// * `ai_provider` - The AI provider to use for extraction
+    /// * `artifact_manager` - The manager that will store the generated sheets
+    ///
+    /// # Returns
+    /// The IDs of the created or updated artifacts
+    pub async fn generate_character_sheets(
+        &self,
+        ai_provider: &dyn AIProvider,
+        artifact_manager: &mut ArtifactManager,
+    ) -> Result<Vec<String>, StoryChainError> {
+        info!("Generating character sheets from story chain");
+
+        let mut context = Context::new();
+        context.insert("story", &self.condensed_text(SCENE_EXCERPT_CHARS));
+        let prompt = self.render_prompt(CHARACTER_SHEETS, context)?;
+
+        let (_, content) = ai_provider.generate(&prompt).await?;
+
+        let mut artifact_ids = Vec::new();
+        for block in split_blocks(&content, "CHARACTER") {
+            let fields = parse_labeled_fields(&block, &SHEET_LABELS);
+            let Some(name) = fields.get("CHARACTER") else {
+                continue;
+            };
+
+            // Only keep scene references that exist in this chain
+            let key_scenes: Vec<String> = fields
+                .get("KEY SCENES")
+                .map(|scenes| {
+                    scenes
+                        .split([',', '\n'])
+                        .map(|id| id.trim().trim_matches(['[', ']']).to_string())
+                        .filter(|id| {
+                            let known = self.nodes.contains_key(id);
+                            if !known && !id.is_empty() {
+                                warn!("Ignoring unknown scene reference {} for {}", id, name);
+                            }
+                            known
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            let appearance = fields.get("APPEARANCE").cloned().unwrap_or_default();
+            let arc = fields.get("ARC").cloned().unwrap_or_default();
+
+            let mut metadata = HashMap::new();
+            metadata.insert("name".to_string(), name.clone());
+            metadata.insert("appearance".to_string(), appearance.clone());
+            metadata.insert("arc".to_string(), arc.clone());
+            metadata.insert("key_scenes".to_string(), key_scenes.join(","));
+            metadata.insert("source".to_string(), "story".to_string());
+
+            let artifact = Artifact {
+                id: format!("character_{}", slugify(name)),
+                content: format!(
+                    "Name: {}\nAppearance: {}\nArc: {}\nKey scenes: {}",
+                    name,
+                    appearance,
+                    arc,
+                    key_scenes.join(", ")
+                ),
+                artifact_type: ArtifactType::CharacterArc,
+                metadata,
+                tags: Vec::new(),
+            };
+
+            debug!("Saving character sheet: {}", artifact.id);
+            artifact_ids.push(artifact.id.clone());
+            artifact_manager.update_artifact(artifact)?;
+        }
+
+        info!("Generated {} character sheets", artifact_ids.len());
+        Ok(artifact_ids)
+    }
+}
@@ -0,0 +1,400 @@
+//! Chat-Mode Generation
+//!
+//! This module provides an AI provider that keeps a running conversation
+//! with the model instead of sending every prompt on its own. Each scene
+//! prompt and the model's answer are appended to the message history, so
+//! models with large context windows see the whole story so far. When the
+//! history outgrows its budget, the oldest messages are summarized into a
+//! single system message, or dropped if summarization is disabled or fails.
+
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::Mutex;
+use log::{info, debug, warn};
+use tera::Context;
+use crate::parsers::{ResponseParser, ThinkTagParser};
+use crate::prompts::PromptTemplates;
+use crate::{AIProvider, StoryChainError};
+
+/// Default character budget of the message history
+pub const DEFAULT_HISTORY_CHARS: usize = 48_000;
+
+/// Default number of most recent messages never summarized or dropped
+pub const DEFAULT_KEEP_RECENT: usize = 4;
+
+/// Prefix of the system message holding the summary of older messages
+const SUMMARY_PREFIX: &str = "Summary of the story so far:\n";
+
+/// Name of the chat history summary prompt template
+pub const CHAT_SUMMARY: &str = "chat_summary";
+
+/// Built-in template of the prompt older chat messages are summarized with
+///
+/// Variables: `transcript`, the messages to summarize.
+pub const CHAT_SUMMARY_TEMPLATE: &str = r#"Summarize the story told in the conversation below in a few paragraphs. Keep every character, place, unresolved thread, and important fact a writer continuing the story would need.
+
+{{ transcript }}
+
+IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your reasoning about what must be kept.
+</think>
+Write the summary here."#;
+
+/// Role of a message in a chat conversation
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+    /// Instructions that frame the whole conversation
+    System,
+
+    /// A prompt sent to the model
+    User,
+
+    /// A response from the model
+    Assistant,
+}
+
+/// A single message in a chat conversation
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ChatMessage {
+    /// Who wrote the message
+    pub role: ChatRole,
+
+    /// Text of the message
+    pub content: String,
+}
+
+impl ChatMessage {
+    /// Creates a new message
+    pub fn new(role: ChatRole, content: impl Into<String>) -> Self {
+        Self { role, content: content.into() }
+    }
+}
+
+/// A model that answers a list of chat messages
+#[async_trait::async_trait]
+pub trait ChatModel: Send + Sync {
+    /// Sends the conversation to the model and returns its raw reply
+    ///
+    /// # Arguments
+    /// * `messages` - The conversation, oldest message first
+    async fn chat(&self, messages: &[ChatMessage]) -> Result<String, StoryChainError>;
+
+    /// Returns the name of the model, if known
+    fn model_name(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Chat model served by Ollama's `/api/chat` endpoint
+pub struct OllamaChatModel {
+    /// HTTP client used for requests
+    client: reqwest::Client,
+
+    /// Base URL of the Ollama server, e.g. `http://localhost:11434`
+    base_url: String,
+
+    /// Name of the model to chat with
+    model: String,
+
+    /// Sampling seed sent with every request, for reproducible output
+    seed: Option<u64>,
+
+    /// API key sent as a bearer token
+    api_key: Option<String>,
+
+    /// Splits replies into reasoning and content when used as a stateless provider
+    parser: Arc<dyn ResponseParser>,
+}
+
+impl OllamaChatModel {
+    /// Creates a new OllamaChatModel
+    ///
+    /// # Arguments
+    /// * `base_url` - Base URL of the Ollama server
+    /// * `model` - Name of the model to chat with
+    pub fn new(base_url: String, model: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            model,
+            seed: None,
+            api_key: None,
+            parser: Arc::new(ThinkTagParser),
+        }
+    }
+
+    /// Sets the sampling seed sent with every request
+    ///
+    /// # Arguments
+    /// * `seed` - The seed; the same seed and prompt reproduce the same reply
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Sets an API key sent as a bearer token, for servers behind an authenticating proxy
+    pub fn with_api_key(mut self, api_key: String) -> Self {
+        self.api_key = Some(api_key);
+        self
+    }
+
+    /// Sets how replies are split into reasoning and content, `<think>` tags by default
+    pub fn with_parser(mut self, parser: Arc<dyn ResponseParser>) -> Self {
+        self.parser = parser;
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl ChatModel for OllamaChatModel {
+    async fn chat(&self, messages: &[ChatMessage]) -> Result<String, StoryChainError> {
+        let url = format!("{}/api/chat", self.base_url);
+        debug!("Sending {} messages to {}", messages.len(), url);
+
+        let mut request = json!({ "model": self.model, "messages": messages, "stream": false });
+        if let Some(seed) = self.seed {
+            request["options"] = json!({ "seed": seed });
+        }
+
+        let mut builder = self.client.post(&url).json(&request);
+        if let Some(api_key) = &self.api_key {
+            builder = builder.bearer_auth(api_key);
+        }
+        let response = builder
+            .send()
+            .await
+            .map_err(|e| StoryChainError::AIServerError(format!("Chat request failed: {}", e)))?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(StoryChainError::AIServerError(format!("Chat request failed with {}: {}", status, body)));
+        }
+
+        let body: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| StoryChainError::AIServerError(format!("Invalid chat response: {}", e)))?;
+        body["message"]["content"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| StoryChainError::AIServerError("Chat response has no message content".to_string()))
+    }
+
+    fn model_name(&self) -> Option<&str> {
+        Some(&self.model)
+    }
+}
+
+/// Uses a chat model as a stateless provider, sending each prompt on its own
+#[async_trait::async_trait]
+impl AIProvider for OllamaChatModel {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        let raw = self.chat(&[ChatMessage::new(ChatRole::User, prompt)]).await?;
+        self.parser.parse(&raw)
+    }
+
+    /// Sends the system prompt in the system role
+    async fn generate_with_system(&self, system_prompt: &str, prompt: &str) -> Result<(String, String), StoryChainError> {
+        let messages = [ChatMessage::new(ChatRole::System, system_prompt), ChatMessage::new(ChatRole::User, prompt)];
+        let raw = self.chat(&messages).await?;
+        self.parser.parse(&raw)
+    }
+
+    fn provider_name(&self) -> &str {
+        "ollama-chat"
+    }
+
+    fn model_name(&self) -> Option<&str> {
+        Some(&self.model)
+    }
+}
+
+/// AI provider that keeps the conversation history across scenes
+pub struct ChatProvider<M: ChatModel> {
+    /// The model the conversation is held with
+    model: M,
+
+    /// Messages exchanged so far, oldest first
+    history: Mutex<Vec<ChatMessage>>,
+
+    /// System prompt sent at the start of every request
+    system_prompt: Option<String>,
+
+    /// Character budget of the message history
+    max_history_chars: usize,
+
+    /// Number of most recent messages never summarized or dropped
+    keep_recent: usize,
+
+    /// Summarize old messages instead of dropping them
+    summarize: bool,
+
+    /// Splits replies into reasoning and content
+    parser: Arc<dyn ResponseParser>,
+
+    /// Prompt templates holding the summary prompt
+    templates: PromptTemplates,
+}
+
+/// Total number of characters in a list of messages
+fn total_chars(messages: &[ChatMessage]) -> usize {
+    messages.iter().map(|m| m.content.len()).sum()
+}
+
+impl<M: ChatModel> ChatProvider<M> {
+    /// Creates a new ChatProvider with an empty history
+    ///
+    /// # Arguments
+    /// * `model` - The model to hold the conversation with
+    pub fn new(model: M) -> Self {
+        Self {
+            model,
+            history: Mutex::new(Vec::new()),
+            system_prompt: None,
+            max_history_chars: DEFAULT_HISTORY_CHARS,
+            keep_recent: DEFAULT_KEEP_RECENT,
+            summarize: true,
+            parser: Arc::new(ThinkTagParser),
+            templates: PromptTemplates::default(),
+        }
+    }
+
+    /// Sets the system prompt sent at the start of every request
+    pub fn with_system_prompt(mut self, system_prompt: String) -> Self {
+        self.system_prompt = Some(system_prompt);
+        self
+    }
+
+    /// Sets the character budget of the message history
+    pub fn with_max_history_chars(mut self, max_history_chars: usize) -> Self {
+        self.max_history_chars = max_history_chars;
+        self
+    }
+
+    /// Sets the number of most recent messages never summarized or dropped
+    pub fn with_keep_recent(mut self, keep_recent: usize) -> Self {
+        self.keep_recent = keep_recent;
+        self
+    }
+
+    /// Sets whether old messages are summarized (true) or dropped (false)
+    pub fn with_summarization(mut self, summarize: bool) -> Self {
+        self.summarize = summarize;
+        self
+    }
+
+    /// Sets how replies are split into reasoning and content, `<think>` tags by default
+    pub fn with_parser(mut self, parser: Arc<dyn ResponseParser>) -> Self {
+        self.parser = parser;
+        self
+    }
+
+    /// Sets the prompt templates the summary prompt is rendered from
+    pub fn with_templates(mut self, templates: PromptTemplates) -> Self {
+        self.templates = templates;
+        self
+    }
+
+    /// Returns a copy of the current message history
+    pub async fn history(&self) -> Vec<ChatMessage> {
+        self.history.lock().await.clone()
+    }
+
+    /// Clears the message history
+    pub async fn reset(&self) {
+        self.history.lock().await.clear();
+    }
+
+    /// Asks the model for a summary of the given messages
+    async fn summarize_messages(&self, messages: &[ChatMessage]) -> Result<String, StoryChainError> {
+        let transcript: String = messages
+            .iter()
+            .map(|m| match m.role {
+                ChatRole::System => m.content.trim_start_matches(SUMMARY_PREFIX).to_string(),
+                ChatRole::User => format!("Request: {}", m.content),
+                ChatRole::Assistant => format!("Scene: {}", m.content),
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let mut context = Context::new();
+        context.insert("transcript", &transcript);
+        let request = ChatMessage::new(ChatRole::User, self.templates.render(CHAT_SUMMARY, &context)?);
+
+        let raw = self.model.chat(&[request]).await?;
+        Ok(self.parser.parse(&raw).map(|(_, summary)| summary).unwrap_or(raw))
+    }
+
+    /// Shrinks the history so that it and the next prompt fit the budget
+    async fn compact(&self, history: &mut Vec<ChatMessage>, incoming_chars: usize) {
+        if total_chars(history) + incoming_chars <= self.max_history_chars || history.len() <= self.keep_recent {
+            return;
+        }
+
+        let split = history.len() - self.keep_recent;
+        if self.summarize {
+            info!("Summarizing {} older chat messages", split);
+            match self.summarize_messages(&history[..split]).await {
+                Ok(summary) => {
+                    let summary = ChatMessage::new(ChatRole::System, format!("{}{}", SUMMARY_PREFIX, summary.trim()));
+                    history.splice(..split, [summary]);
+                    return;
+                }
+                Err(e) => warn!("Could not summarize chat history, dropping old messages instead: {}", e),
+            }
+        }
+
+        while history.len() > self.keep_recent && total_chars(history) + incoming_chars > self.max_history_chars {
+            history.remove(0);
+        }
+        debug!("Chat history truncated to {} messages", history.len());
+    }
+
+    /// Sends a prompt as the next user turn and records the exchange
+    async fn converse(&self, system_prompt: Option<&str>, prompt: &str) -> Result<(String, String), StoryChainError> {
+        let mut history = self.history.lock().await;
+        self.compact(&mut history, prompt.len()).await;
+
+        let mut messages = Vec::with_capacity(history.len() + 2);
+        if let Some(system_prompt) = system_prompt.filter(|s| !s.trim().is_empty()) {
+            messages.push(ChatMessage::new(ChatRole::System, system_prompt));
+        }
+        messages.extend(history.iter().cloned());
+        messages.push(ChatMessage::new(ChatRole::User, prompt));
+
+        let raw = self.model.chat(&messages).await?;
+        let (reasoning, content) = self.parser.parse(&raw)?;
+
+        history.push(ChatMessage::new(ChatRole::User, prompt));
+        history.push(ChatMessage::new(ChatRole::Assistant, content.clone()));
+        debug!("Chat history now holds {} messages", history.len());
+        Ok((reasoning, content))
+    }
+}
+
+#[async_trait::async_trait]
+impl<M: ChatModel> AIProvider for ChatProvider<M> {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        self.converse(self.system_prompt.as_deref(), prompt).await
+    }
+
+    /// Sends the system prompt in the system role, after the provider's own system prompt if any
+    async fn generate_with_system(&self, system_prompt: &str, prompt: &str) -> Result<(String, String), StoryChainError> {
+        let combined = match &self.system_prompt {
+            Some(own) => format!("{}\n\n{}", own, system_prompt),
+            None => system_prompt.to_string(),
+        };
+        self.converse(Some(&combined), prompt).await
+    }
+
+    fn provider_name(&self) -> &str {
+        "chat"
+    }
+
+    fn model_name(&self) -> Option<&str> {
+        self.model.model_name()
+    }
+}
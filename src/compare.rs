@@ -0,0 +1,131 @@
+//! Model Comparison
+//!
+//! This module generates the same story with several models and renders the
+//! results next to each other, to help decide which model writes better.
+//! Every chain is built from the same premise, prompt templates, and settings,
+//! so the only difference between the runs is the model answering them.
+
+use std::time::Instant;
+use log::info;
+use crate::dialogue::dialogue_ratio;
+use crate::runner::StoryRunner;
+use crate::stats::DEFAULT_WORDS_PER_MINUTE;
+use crate::{AIProvider, GenerationConfig, PromptTemplates, StoryChain, StoryChainError};
+
+/// A story generated by one model
+#[derive(Debug, Clone)]
+pub struct ComparisonRun {
+    /// Name of the model that wrote the story
+    pub model: String,
+
+    /// The generated story
+    pub chain: StoryChain,
+
+    /// Wall-clock generation time in seconds
+    pub seconds: f64,
+}
+
+/// Generates an opening scene and the given number of continuation scenes
+///
+/// # Arguments
+/// * `model` - Name of the model, used to label the run
+/// * `provider` - The provider answering with that model
+/// * `premise` - The story premise
+/// * `epochs` - Number of scenes to generate after the opening scene
+/// * `templates` - Prompt templates shared by every run
+/// * `generation` - Generation parameters shared by every run
+pub async fn generate_comparison_run(
+    model: &str,
+    provider: &dyn AIProvider,
+    premise: &str,
+    epochs: usize,
+    templates: &PromptTemplates,
+    generation: &GenerationConfig,
+) -> Result<ComparisonRun, StoryChainError> {
+    info!("Generating comparison story with {}", model);
+    let start = Instant::now();
+
+    let mut chain = StoryRunner::new(provider)
+        .premise(premise)
+        .templates(templates.clone())
+        .generation(generation.clone())
+        .epochs(epochs)
+        .run()
+        .await?;
+    chain.metadata.insert("model".to_string(), model.to_string());
+
+    Ok(ComparisonRun { model: model.to_string(), chain, seconds: start.elapsed().as_secs_f64() })
+}
+
+/// Makes text safe for a single markdown table cell
+fn table_cell(text: &str) -> String {
+    text.trim().replace('|', "\\|").replace("\n\n", "<br><br>").replace('\n', " ")
+}
+
+/// Renders a side-by-side report with summary statistics and one table row
+/// per scene, with a column for each model
+///
+/// # Arguments
+/// * `runs` - The runs to compare
+pub fn comparison_markdown(runs: &[ComparisonRun]) -> String {
+    let models: Vec<&str> = runs.iter().map(|r| r.model.as_str()).collect();
+    let header = format!("| | {} |\n|---|{}\n", models.join(" | "), "---|".repeat(runs.len()));
+    let row = |label: &str, values: Vec<String>| format!("| {} | {} |\n", label, values.join(" | "));
+
+    let stats: Vec<_> = runs.iter().map(|r| r.chain.stats(DEFAULT_WORDS_PER_MINUTE)).collect();
+    let mut content = String::from("# Model Comparison\n\n## Summary\n\n");
+    content.push_str(&header);
+    content.push_str(&row("Scenes", stats.iter().map(|s| s.scenes.len().to_string()).collect()));
+    content.push_str(&row("Words", stats.iter().map(|s| s.total_words.to_string()).collect()));
+    content.push_str(&row(
+        "Words per scene",
+        stats.iter().map(|s| format!("{:.0}", s.total_words as f64 / s.scenes.len().max(1) as f64)).collect(),
+    ));
+    content.push_str(&row(
+        "Dialogue ratio",
+        runs.iter()
+            .map(|r| {
+                let text: Vec<&str> = r.chain.nodes_in_order().iter().map(|n| n.content.as_str()).collect();
+                format!("{:.2}", dialogue_ratio(&text.join("\n\n")))
+            })
+            .collect(),
+    ));
+    content.push_str(&row(
+        "Average tension",
+        stats.iter()
+            .map(|s| format!("{:.1}", s.scenes.iter().map(|n| n.tension).sum::<f64>() / s.scenes.len().max(1) as f64))
+            .collect(),
+    ));
+    content.push_str(&row("Generation time", runs.iter().map(|r| format!("{:.0}s", r.seconds)).collect()));
+
+    content.push_str("\n## Scenes\n\n");
+    content.push_str(&header);
+    let scenes: Vec<_> = runs.iter().map(|r| r.chain.nodes_in_order()).collect();
+    let scene_count = scenes.iter().map(Vec::len).max().unwrap_or(0);
+    for index in 0..scene_count {
+        let cells = scenes.iter().map(|s| s.get(index).map(|n| table_cell(&n.content)).unwrap_or_default()).collect();
+        content.push_str(&row(&format!("Scene {}", index + 1), cells));
+    }
+    content
+}
+
+/// Renders every model's version of each scene one after another, so the
+/// versions can be read or diffed scene by scene
+///
+/// # Arguments
+/// * `runs` - The runs to interleave
+pub fn interleaved_markdown(runs: &[ComparisonRun]) -> String {
+    let scenes: Vec<_> = runs.iter().map(|r| r.chain.nodes_in_order()).collect();
+    let scene_count = scenes.iter().map(Vec::len).max().unwrap_or(0);
+
+    let mut content = String::from("# Model Comparison: Interleaved Scenes\n\n");
+    for index in 0..scene_count {
+        content.push_str(&format!("## Scene {}\n\n", index + 1));
+        for (run, nodes) in runs.iter().zip(&scenes) {
+            if let Some(node) = nodes.get(index) {
+                content.push_str(&format!("### {}\n\n{}\n\n", run.model, node.content.trim()));
+            }
+        }
+    }
+    content
+}
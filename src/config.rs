@@ -0,0 +1,282 @@
+//! Configuration File
+//!
+//! This module loads `storychain.toml`, which describes the AI provider to
+//! generate with (its type, model, endpoint, and credentials), an optional
+//! critic, default generation parameters, the target scene length, and the
+//! provider's prices. `ProviderFactory` turns a provider description into a
+//! ready `AIProvider`, so the model no longer has to be hard-coded.
+//!
+//! ```toml
+//! [provider]
+//! type = "ollama-http"
+//! model = "deepseek-r1:32b"
+//! endpoint = "https://ollama.example.com"
+//! api_key_env = "OLLAMA_API_KEY"
+//! response_format = "think-tags"
+//! timeout_secs = 600
+//!
+//! [critic]
+//! model = "qwq:32b"
+//!
+//! [generation]
+//! temperature = 0.8
+//! max_tokens = 2048
+//!
+//! [length]
+//! words = 800
+//! tolerance = 0.2
+//!
+//! [pricing]
+//! prompt_per_million = 0.55
+//! completion_per_million = 2.19
+//! ```
+
+use std::path::Path;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use log::{info, warn};
+use crate::length::LengthTarget;
+use crate::parsers::ResponseFormat;
+use crate::prompts::PromptTemplates;
+use crate::usage::Pricing;
+use crate::chat::{ChatProvider, OllamaChatModel, DEFAULT_HISTORY_CHARS};
+use crate::providers::{OllamaHttpProvider, TimeoutProvider, DEFAULT_OLLAMA_HOST, DEFAULT_OLLAMA_PORT};
+use crate::{AIProvider, DeepseekProvider, GenerationConfig, StoryChainError};
+
+/// Configuration file read when none is given
+pub const DEFAULT_CONFIG_FILE: &str = "storychain.toml";
+
+/// Model used when the configuration names none
+pub const DEFAULT_MODEL: &str = "deepseek-r1:32b";
+
+/// File the `ollama` command's responses are logged to by default
+pub const DEFAULT_LOG_FILE: &str = "ai_responses.jsonl";
+
+/// How a provider reaches its model
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ProviderKind {
+    /// Standalone prompts through the `ollama run` command
+    #[default]
+    Ollama,
+
+    /// Standalone prompts through Ollama's `/api/generate` endpoint
+    OllamaHttp,
+
+    /// One conversation across all scenes through Ollama's `/api/chat` endpoint
+    OllamaChat,
+}
+
+/// Description of one AI provider
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ProviderConfig {
+    /// How the provider reaches its model
+    #[serde(rename = "type")]
+    pub kind: ProviderKind,
+
+    /// Name of the model
+    pub model: String,
+
+    /// Base URL of the server; a local Ollama server when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub endpoint: Option<String>,
+
+    /// API key sent as a bearer token, for servers behind an authenticating proxy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+
+    /// Environment variable holding the API key, read when `api_key` is unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key_env: Option<String>,
+
+    /// How long Ollama keeps the model loaded after a request, e.g. `10m`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keep_alive: Option<String>,
+
+    /// Seconds a request may take before it fails with a timeout; unlimited when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout_secs: Option<u64>,
+
+    /// JSON Lines file the `ollama` command's requests and responses are logged to
+    pub log_file: String,
+
+    /// Characters of chat history kept before older messages are summarized
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_history_chars: Option<usize>,
+
+    /// Shape of the model's responses, `think-tags` by default
+    #[serde(skip_serializing_if = "ResponseFormat::is_default")]
+    pub response_format: ResponseFormat,
+}
+
+impl Default for ProviderConfig {
+    fn default() -> Self {
+        Self {
+            kind: ProviderKind::default(),
+            model: DEFAULT_MODEL.to_string(),
+            endpoint: None,
+            api_key: None,
+            api_key_env: None,
+            keep_alive: None,
+            timeout_secs: None,
+            log_file: DEFAULT_LOG_FILE.to_string(),
+            max_history_chars: None,
+            response_format: ResponseFormat::default(),
+        }
+    }
+}
+
+impl ProviderConfig {
+    /// Returns the base URL of the server
+    pub fn endpoint(&self) -> String {
+        self.endpoint
+            .clone()
+            .unwrap_or_else(|| format!("http://{}:{}", DEFAULT_OLLAMA_HOST, DEFAULT_OLLAMA_PORT))
+    }
+
+    /// Returns the API key, taken from the environment when configured so
+    pub fn api_key(&self) -> Option<String> {
+        self.api_key
+            .clone()
+            .or_else(|| self.api_key_env.as_ref().and_then(|name| std::env::var(name).ok()))
+    }
+}
+
+/// Contents of a configuration file
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct StoryChainConfig {
+    /// Provider generating the scenes
+    pub provider: ProviderConfig,
+
+    /// Provider scoring scenes under a quality gate; the default provider when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub critic: Option<ProviderConfig>,
+
+    /// Default sampling parameters, overridden by command-line flags
+    pub generation: GenerationConfig,
+
+    /// Target word count of every scene, overridden by command-line flags
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub length: Option<LengthTarget>,
+
+    /// Prices of a paid provider, from which the cost of a run is estimated
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pricing: Option<Pricing>,
+}
+
+impl StoryChainConfig {
+    /// Parses a configuration from TOML
+    ///
+    /// # Arguments
+    /// * `content` - The TOML text
+    pub fn from_toml(content: &str) -> Result<Self, StoryChainError> {
+        toml::from_str(content).map_err(|e| StoryChainError::ConfigError(e.to_string()))
+    }
+
+    /// Loads a configuration file
+    ///
+    /// # Arguments
+    /// * `path` - Path of the TOML file
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, StoryChainError> {
+        let path = path.as_ref();
+        let config = Self::from_toml(&std::fs::read_to_string(path)?)
+            .map_err(|e| StoryChainError::ConfigError(format!("{}: {}", path.display(), e)))?;
+        info!("Loaded configuration from {}", path.display());
+        Ok(config)
+    }
+
+    /// Loads a configuration file, or returns the default configuration when it does not exist
+    ///
+    /// # Arguments
+    /// * `path` - Path of the TOML file
+    pub fn load_or_default(path: impl AsRef<Path>) -> Result<Self, StoryChainError> {
+        if path.as_ref().exists() {
+            Self::load(path)
+        } else {
+            Ok(Self::default())
+        }
+    }
+}
+
+/// Creates AI providers from their configuration
+pub struct ProviderFactory;
+
+impl ProviderFactory {
+    /// Instantiates the provider a configuration describes
+    ///
+    /// # Arguments
+    /// * `config` - The provider description
+    pub fn from_config(config: &ProviderConfig) -> Result<Box<dyn AIProvider>, StoryChainError> {
+        Self::from_config_with_templates(config, &PromptTemplates::default())
+    }
+
+    /// Instantiates the provider a configuration describes, with the prompt
+    /// templates that providers writing their own prompts, such as chat
+    /// mode's history summary, render them from
+    ///
+    /// # Arguments
+    /// * `config` - The provider description
+    /// * `templates` - The prompt templates
+    pub fn from_config_with_templates(
+        config: &ProviderConfig,
+        templates: &PromptTemplates,
+    ) -> Result<Box<dyn AIProvider>, StoryChainError> {
+        if config.model.trim().is_empty() {
+            return Err(StoryChainError::ConfigError("Provider has no model".to_string()));
+        }
+        let api_key = config.api_key();
+        if config.api_key_env.is_some() && api_key.is_none() {
+            warn!("Environment variable {} is not set", config.api_key_env.as_deref().unwrap_or_default());
+        }
+
+        let provider: Box<dyn AIProvider> = match config.kind {
+            ProviderKind::Ollama => {
+                if config.endpoint.is_some() || api_key.is_some() {
+                    warn!("The ollama command uses its own server settings; endpoint and API key are ignored");
+                }
+                Box::new(
+                    DeepseekProvider::new(config.model.clone(), config.log_file.clone())
+                        .with_parser(config.response_format.parser()),
+                )
+            }
+            ProviderKind::OllamaHttp => {
+                let mut provider = OllamaHttpProvider::new(config.model.clone())
+                    .with_base_url(&config.endpoint())
+                    .with_parser(config.response_format.parser());
+                if let Some(keep_alive) = &config.keep_alive {
+                    provider = provider.with_keep_alive(keep_alive.clone());
+                }
+                if let Some(api_key) = api_key {
+                    provider = provider.with_api_key(api_key);
+                }
+                Box::new(provider)
+            }
+            ProviderKind::OllamaChat => {
+                let max_history_chars = config.max_history_chars.unwrap_or(DEFAULT_HISTORY_CHARS);
+                info!("Using chat mode with a history budget of {} characters", max_history_chars);
+                let mut model = OllamaChatModel::new(config.endpoint(), config.model.clone());
+                if let Some(api_key) = api_key {
+                    model = model.with_api_key(api_key);
+                }
+                Box::new(
+                    ChatProvider::new(model)
+                        .with_max_history_chars(max_history_chars)
+                        .with_parser(config.response_format.parser())
+                        .with_templates(templates.clone()),
+                )
+            }
+        };
+        info!(
+            "Using {:?} provider with model {} and {:?} responses",
+            config.kind, config.model, config.response_format
+        );
+
+        // Abandon requests a hung server never answers
+        if let Some(timeout_secs) = config.timeout_secs {
+            return Ok(Box::new(TimeoutProvider::new(provider, Duration::from_secs(timeout_secs))));
+        }
+        Ok(provider)
+    }
+}
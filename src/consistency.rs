@@ -0,0 +1,185 @@
+//! Consistency Critic
+//!
+//! This module has the model check every new scene against what the story
+//! has already established: the premise, the character sheets of the
+//! characters in the scene, the relevant lore, and the scenes before it.
+//! Contradictions are recorded in the node's `consistency_issues` metadata,
+//! and the scene can optionally be revised to fix them before it is kept.
+
+use serde::{Deserialize, Serialize};
+use log::{info, warn};
+use tera::Context;
+use crate::prompts::PromptTemplates;
+use crate::{AIProvider, StoryChain, StoryChainError};
+
+/// Node metadata key listing the contradictions found in a scene
+pub const CONSISTENCY_ISSUES_KEY: &str = "consistency_issues";
+
+/// Node metadata key set when a scene was revised to fix contradictions
+pub const CONSISTENCY_REVISED_KEY: &str = "consistency_revised";
+
+/// Name of the contradiction check prompt template
+pub const CONTRADICTIONS: &str = "contradictions";
+
+/// Built-in template of the contradiction check prompt
+///
+/// Variables: `premise`, the story premise, empty when there is none;
+/// `facts`, what the story has established before the scene; `scene`, the
+/// new scene.
+pub const CONTRADICTIONS_TEMPLATE: &str = r#"You are a continuity editor. Check the new scene below against what the story has already established and list every contradiction: facts, character traits, relationships, names, objects, injuries, locations, or timeline. Ignore style, and do not list new developments that do not contradict anything.
+
+{% if premise %}Story Premise:
+{{ premise }}
+
+{% endif %}{{ facts }}
+
+New Scene:
+{{ scene }}
+
+IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your comparison of the new scene with the established facts.
+</think>
+CONTRADICTIONS:
+- One contradiction per line, naming what the scene says and what was established
+(or the single line NONE when there are none)"#;
+
+/// Name of the consistency revision prompt template
+pub const CONSISTENCY_REVISION: &str = "consistency_revision";
+
+/// Built-in template of the consistency revision prompt
+///
+/// Variables: `issues`, the contradictions found; `scene`, the scene to revise.
+pub const CONSISTENCY_REVISION_TEMPLATE: &str = r#"Revise the scene below to fix these contradictions with the rest of the story, changing as little else as possible:
+{% for issue in issues %}- {{ issue }}
+{% endfor %}
+Scene:
+{{ scene }}
+
+IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your reasoning about how to resolve each contradiction.
+</think>
+Write the complete revised scene here."#;
+
+/// Number of preceding scenes the critic reads
+const PRIOR_SCENES: usize = 3;
+
+/// Characters of each preceding scene the critic reads
+const PRIOR_SCENE_CHARS: usize = 1500;
+
+/// Settings of the consistency critic
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ConsistencyCheck {
+    /// Revise scenes with contradictions once, and check the revision again
+    pub revise: bool,
+}
+
+/// Reads the contradictions out of a critic response
+///
+/// The response lists one contradiction per `- ` line after
+/// `CONTRADICTIONS:`, or says `NONE`.
+///
+/// # Arguments
+/// * `response` - The critic's response, without its reasoning
+pub fn parse_contradictions(response: &str) -> Vec<String> {
+    let listed = match response.find("CONTRADICTIONS:") {
+        Some(start) => &response[start + "CONTRADICTIONS:".len()..],
+        None => response,
+    };
+    listed
+        .lines()
+        .map(str::trim)
+        .filter_map(|line| line.strip_prefix("- ").or_else(|| line.strip_prefix("* ")))
+        .map(str::trim)
+        .filter(|issue| !issue.is_empty() && !issue.eq_ignore_ascii_case("none"))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Asks the model to rewrite a scene without its contradictions
+///
+/// # Arguments
+/// * `templates` - The prompt templates holding the revision prompt
+/// * `ai_provider` - The AI provider to use for the revision
+/// * `content` - The scene to revise
+/// * `issues` - The contradictions found in it
+pub async fn revise_for_consistency(
+    templates: &PromptTemplates,
+    ai_provider: &dyn AIProvider,
+    content: &str,
+    issues: &[String],
+) -> Result<(String, String), StoryChainError> {
+    info!("Revising scene to fix {} contradictions", issues.len());
+    let mut context = Context::new();
+    context.insert("issues", issues);
+    context.insert("scene", content);
+    let prompt = templates.render(CONSISTENCY_REVISION, &context)?;
+    ai_provider.generate(&prompt).await
+}
+
+impl StoryChain {
+    /// Lays out what the story has established before a scene: the summary,
+    /// recalled scenes, character sheets, lore, and the preceding scenes
+    ///
+    /// # Arguments
+    /// * `context_id` - ID of the node the scene continues
+    /// * `content` - The scene, used to pick character sheets and lore
+    fn established_facts(&self, context_id: &str, content: &str) -> String {
+        let mut sections = Vec::new();
+        if let Some(summary) = self.story_summary(context_id) {
+            sections.push(format!("Story So Far:\n{}", summary));
+        }
+        let recalled = self.recalled_scenes(context_id);
+        if !recalled.is_empty() {
+            sections.push(format!("Earlier Scenes:\n{}", recalled));
+        }
+        let characters = self.prompts.character_section(content);
+        if !characters.is_empty() {
+            sections.push(format!("Character Sheets:\n{}", characters));
+        }
+        let lore = self.prompts.lore.lore_section(content);
+        if !lore.is_empty() {
+            sections.push(format!("Established Lore:\n{}", lore));
+        }
+        let path = self.path_to(context_id);
+        let prior: Vec<String> = path
+            .iter()
+            .skip(path.len().saturating_sub(PRIOR_SCENES))
+            .map(|node| format!("[{}]\n{}", node.id, node.content.trim().chars().take(PRIOR_SCENE_CHARS).collect::<String>()))
+            .collect();
+        sections.push(format!("Preceding Scenes:\n{}", prior.join("\n\n")));
+        sections.join("\n\n")
+    }
+
+    /// Asks the model for the contradictions between a new scene and what
+    /// the story has established before it
+    ///
+    /// # Arguments
+    /// * `context_id` - ID of the node the scene continues
+    /// * `content` - The new scene
+    /// * `critic` - The AI provider acting as critic
+    /// * `premise` - Optional story premise
+    ///
+    /// # Returns
+    /// One description per contradiction; empty when the scene is consistent
+    pub async fn find_contradictions(
+        &self,
+        context_id: &str,
+        content: &str,
+        critic: &dyn AIProvider,
+        premise: Option<&str>,
+    ) -> Result<Vec<String>, StoryChainError> {
+        let mut context = Context::new();
+        context.insert("premise", premise.unwrap_or_default());
+        context.insert("facts", &self.established_facts(context_id, content));
+        context.insert("scene", content);
+        let prompt = self.render_prompt(CONTRADICTIONS, context)?;
+        let (_, response) = critic.generate(&prompt).await?;
+        let issues = parse_contradictions(&response);
+        if !issues.is_empty() {
+            warn!("Found {} contradictions after {}: {}", issues.len(), context_id, issues.join("; "));
+        }
+        Ok(issues)
+    }
+}
@@ -0,0 +1,315 @@
+//! Generation Constraints
+//!
+//! This module parses a small declarative rules format, one rule per line,
+//! such as:
+//!
+//! ```text
+//! # House rules for this story
+//! no character deaths
+//! keep it PG-13
+//! story must stay in one location: the lighthouse
+//! avoid: magic, dragons
+//! The narrator never learns the killer's name
+//! ```
+//!
+//! Every rule is injected into the continuation prompts. Rules the module
+//! recognizes are also checked against each generated scene, and violations
+//! are recorded in the scene's `constraint_violations` metadata. Any other
+//! line is kept as a free-form rule that is only enforced through the prompt.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use crate::{StoryChain, StoryChainError};
+
+/// Strong profanity, not allowed below an R rating
+pub(crate) const STRONG_LANGUAGE: [&str; 6] = ["fuck", "fucking", "shit", "cunt", "motherfucker", "bullshit"];
+
+/// Mild profanity, not allowed in a G-rated story
+pub(crate) const MILD_LANGUAGE: [&str; 6] = ["damn", "hell", "crap", "bastard", "ass", "bitch"];
+
+/// Graphic violence and sexual content, not allowed below an R rating
+pub(crate) const GRAPHIC_CONTENT: [&str; 8] = ["gore", "entrails", "disembowel", "naked", "nude", "sex", "dismember", "mutilated"];
+
+/// Violent vocabulary, not allowed in a G-rated story
+pub(crate) const VIOLENT_CONTENT: [&str; 6] = ["blood", "gun", "knife", "stabbed", "shot", "corpse"];
+
+/// Audience rating a story must stay within
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentRating {
+    /// Suitable for all ages
+    G,
+
+    /// Mild language and peril
+    Pg,
+
+    /// Some strong peril and language, nothing graphic
+    Pg13,
+
+    /// Adult content
+    R,
+}
+
+impl FromStr for ContentRating {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_uppercase().replace([' ', '-'], "").as_str() {
+            "G" => Ok(ContentRating::G),
+            "PG" => Ok(ContentRating::Pg),
+            "PG13" => Ok(ContentRating::Pg13),
+            "R" => Ok(ContentRating::R),
+            other => Err(format!("Unknown content rating: {}", other)),
+        }
+    }
+}
+
+impl ContentRating {
+    /// Returns the rating as conventionally written
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentRating::G => "G",
+            ContentRating::Pg => "PG",
+            ContentRating::Pg13 => "PG-13",
+            ContentRating::R => "R",
+        }
+    }
+}
+
+/// A single generation rule
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Constraint {
+    /// No character may die
+    NoDeaths,
+
+    /// Content must stay within an audience rating
+    Rating(ContentRating),
+
+    /// The story must stay in one location, optionally named
+    SingleLocation(Option<String>),
+
+    /// These words or topics must not appear
+    Avoid(Vec<String>),
+
+    /// Any other rule, enforced only through the prompt
+    Custom(String),
+}
+
+/// A rule broken by a generated scene
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ConstraintViolation {
+    /// The rule as written in prompts
+    pub rule: String,
+
+    /// What in the scene broke the rule
+    pub detail: String,
+}
+
+/// The set of rules applied to a chain
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ConstraintSet {
+    /// The rules, in the order they were written
+    pub constraints: Vec<Constraint>,
+}
+
+/// Returns the listed words that appear in the text as whole words
+fn words_present(text: &str, words: &[&str]) -> Vec<String> {
+    let lower = text.to_lowercase();
+    words
+        .iter()
+        .filter(|word| {
+            Regex::new(&format!(r"\b{}\b", regex::escape(word)))
+                .map(|re| re.is_match(&lower))
+                .unwrap_or(false)
+        })
+        .map(|word| word.to_string())
+        .collect()
+}
+
+impl Constraint {
+    /// Parses one line of the rules format
+    ///
+    /// # Arguments
+    /// * `line` - The rule, without a leading list marker
+    pub fn parse(line: &str) -> Result<Self, StoryChainError> {
+        let lower = line.to_lowercase();
+        let lower = lower.trim_end_matches('.');
+        let value_after_colon = || line.split_once(':').map(|(_, v)| v.trim().trim_end_matches('.').to_string());
+
+        if Regex::new(r"^no (character )?deaths?$|^no one dies$|^nobody dies$").unwrap().is_match(lower) {
+            return Ok(Constraint::NoDeaths);
+        }
+
+        let rating = Regex::new(r"^(?:keep it|rated|rating:?)\s+(g|pg|pg-13|pg13|r)$").unwrap();
+        if let Some(captures) = rating.captures(lower) {
+            return captures[1].parse().map(Constraint::Rating).map_err(StoryChainError::InvalidConstraint);
+        }
+        if lower.starts_with("keep it ") || lower.starts_with("rating") {
+            return Err(StoryChainError::InvalidConstraint(format!("Unknown content rating in rule: {}", line)));
+        }
+
+        if lower.contains("one location") || lower.contains("single location") {
+            return Ok(Constraint::SingleLocation(value_after_colon().filter(|v| !v.is_empty())));
+        }
+
+        if lower.starts_with("avoid:") || lower.starts_with("never mention:") {
+            let terms: Vec<String> = value_after_colon()
+                .unwrap_or_default()
+                .split(',')
+                .map(|t| t.trim().to_lowercase())
+                .filter(|t| !t.is_empty())
+                .collect();
+            if terms.is_empty() {
+                return Err(StoryChainError::InvalidConstraint(format!("No terms given in rule: {}", line)));
+            }
+            return Ok(Constraint::Avoid(terms));
+        }
+
+        Ok(Constraint::Custom(line.trim().to_string()))
+    }
+
+    /// Returns the rule as written in prompts
+    pub fn describe(&self) -> String {
+        match self {
+            Constraint::NoDeaths => "No character may die.".to_string(),
+            Constraint::Rating(rating) => format!(
+                "Keep all language, violence, and sexual content within a {} rating.",
+                rating.as_str()
+            ),
+            Constraint::SingleLocation(Some(location)) => {
+                format!("The story must stay in one location: {}. No scene may move elsewhere.", location)
+            }
+            Constraint::SingleLocation(None) => {
+                "The story must stay in its current location. No scene may move elsewhere.".to_string()
+            }
+            Constraint::Avoid(terms) => format!("Never mention or depict: {}.", terms.join(", ")),
+            Constraint::Custom(rule) => rule.clone(),
+        }
+    }
+
+    /// Checks a generated scene against the rule
+    ///
+    /// Free-form rules are not checked and never report a violation.
+    ///
+    /// # Arguments
+    /// * `content` - The scene content to check
+    pub fn check(&self, content: &str) -> Option<String> {
+        let found = match self {
+            Constraint::NoDeaths => {
+                let deaths = Regex::new(
+                    r"(?i)\b(died|dies|was killed|were killed|killed (him|her|them)|murdered|lay dead|(is|was|were) dead|breathed (his|her|their) last|lifeless body)\b",
+                )
+                .unwrap();
+                deaths.find_iter(content).map(|m| m.as_str().to_lowercase()).collect()
+            }
+            Constraint::Rating(rating) => {
+                let mut found = Vec::new();
+                if *rating < ContentRating::R {
+                    found.extend(words_present(content, &STRONG_LANGUAGE));
+                    found.extend(words_present(content, &GRAPHIC_CONTENT));
+                }
+                if *rating == ContentRating::G {
+                    found.extend(words_present(content, &MILD_LANGUAGE));
+                    found.extend(words_present(content, &VIOLENT_CONTENT));
+                }
+                found
+            }
+            Constraint::SingleLocation(_) => {
+                let travel = Regex::new(
+                    r"(?i)\b(drove|flew|sailed|rode|travell?ed|headed|set off|journeyed) (to|for|toward|towards|across)\b|\b(arrived (at|in))\b",
+                )
+                .unwrap();
+                travel.find_iter(content).map(|m| m.as_str().to_lowercase()).collect()
+            }
+            Constraint::Avoid(terms) => {
+                let terms: Vec<&str> = terms.iter().map(String::as_str).collect();
+                words_present(content, &terms)
+            }
+            Constraint::Custom(_) => Vec::new(),
+        };
+
+        if found.is_empty() {
+            None
+        } else {
+            let mut found = found;
+            found.sort();
+            found.dedup();
+            Some(format!("found \"{}\"", found.join("\", \"")))
+        }
+    }
+}
+
+impl ConstraintSet {
+    /// Parses a rules artifact, one rule per line
+    ///
+    /// Blank lines and lines starting with `#` are ignored, and leading list
+    /// markers (`-`, `*`) are stripped.
+    ///
+    /// # Arguments
+    /// * `text` - The rules text
+    pub fn parse(text: &str) -> Result<Self, StoryChainError> {
+        let constraints = text
+            .lines()
+            .map(|line| line.trim().trim_start_matches(['-', '*']).trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(Constraint::parse)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { constraints })
+    }
+
+    /// Checks a generated scene against every rule
+    ///
+    /// # Arguments
+    /// * `content` - The scene content to check
+    pub fn validate(&self, content: &str) -> Vec<ConstraintViolation> {
+        self.constraints
+            .iter()
+            .filter_map(|constraint| {
+                constraint.check(content).map(|detail| ConstraintViolation { rule: constraint.describe(), detail })
+            })
+            .collect()
+    }
+}
+
+impl StoryChain {
+    /// Builds the constraint guidance listing every rule the scene must obey
+    pub fn constraint_guidance(&self) -> Option<String> {
+        let set = self.settings.constraints.as_ref().filter(|s| !s.constraints.is_empty())?;
+        let rules: Vec<String> = set.constraints.iter().map(Constraint::describe).collect();
+        Some(format!("Rules this story must never break: {}", rules.join(" ")))
+    }
+
+    /// Checks every scene in reading order against the chain's rules
+    ///
+    /// # Returns
+    /// The violations of each scene that broke at least one rule, keyed by node ID
+    pub fn constraint_report(&self) -> Vec<(String, Vec<ConstraintViolation>)> {
+        let Some(set) = &self.settings.constraints else {
+            return Vec::new();
+        };
+        self.nodes_in_reading_order()
+            .into_iter()
+            .map(|node| (node.id.clone(), set.validate(&node.content)))
+            .filter(|(_, violations)| !violations.is_empty())
+            .collect()
+    }
+
+    /// Renders the constraint report as markdown
+    pub fn constraint_report_markdown(&self) -> String {
+        let report = self.constraint_report();
+        let mut content = String::from("## Constraint Violations\n\n");
+        if report.is_empty() {
+            content.push_str("No scene broke a checked rule.\n");
+        }
+        for (node_id, violations) in report {
+            content.push_str(&format!("### {}\n\n", node_id));
+            for violation in violations {
+                content.push_str(&format!("- {} ({})\n", violation.rule, violation.detail));
+            }
+            content.push('\n');
+        }
+        content
+    }
+}
@@ -0,0 +1,136 @@
+//! Dataset Export
+//!
+//! This module writes a chain as JSON Lines for building fine-tuning datasets:
+//! one record per node pairing the prompt that produces the scene with the
+//! completion the model gave. Prompts are reconstructed from the chain with
+//! the current prompt templates, so guidance that depended on the state of the
+//! run at the time (such as reader feedback or tension targets) is left out.
+
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::Write;
+use crate::provenance::NodeProvenance;
+use crate::scene_cards::SceneCard;
+use crate::{StoryChain, StoryChainError, EPOCHS_KEY};
+
+/// One node of a chain as a training record
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct NodeRecord {
+    /// ID of the node
+    pub id: String,
+
+    /// IDs of the nodes this one continues
+    pub predecessors: Vec<String>,
+
+    /// IDs of the nodes that continue this one
+    pub successors: Vec<String>,
+
+    /// Number of scenes between the opening scene and this one, which is
+    /// the epoch the scene was generated in
+    pub depth: usize,
+
+    /// Whether the node is on the main storyline
+    pub main_storyline: bool,
+
+    /// System prompt the scene was generated with, if any
+    pub system: Option<String>,
+
+    /// Reconstructed prompt that produces the scene
+    pub prompt: String,
+
+    /// The AI's reasoning for the scene
+    pub reasoning: String,
+
+    /// The scene content
+    pub content: String,
+
+    /// The reasoning and content in the response format the prompts ask for
+    pub completion: String,
+
+    /// Metadata of the node
+    pub metadata: HashMap<String, String>,
+
+    /// The scene card of the node, if any
+    pub scene_card: Option<SceneCard>,
+
+    /// How the scene was generated, if recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<NodeProvenance>,
+}
+
+impl StoryChain {
+    /// Builds a training record for every node, in storyline order
+    ///
+    /// The opening scene's prompt is rendered from the initial template with
+    /// the chain's format and genre instructions; every other prompt is
+    /// rendered from the continuation template as if continuing the node's
+    /// first predecessor at the node's depth.
+    ///
+    /// # Arguments
+    /// * `premise` - The premise the story was generated from, if known
+    pub fn to_jsonl_records(&self, premise: Option<&str>) -> Result<Vec<NodeRecord>, StoryChainError> {
+        let total_epochs = self
+            .metadata
+            .get(EPOCHS_KEY)
+            .and_then(|e| e.parse().ok())
+            .unwrap_or_else(|| self.completed_epochs());
+        let main_line: Vec<&str> = self.storylines().first().map(|l| l.iter().map(|n| n.id.as_str()).collect()).unwrap_or_default();
+
+        let mut records = Vec::new();
+        for node in self.nodes_in_graph_order() {
+            let depth = self.path_to(&node.id).len().saturating_sub(1);
+            let prompt = match node.predecessor().filter(|id| self.nodes.contains_key(*id)) {
+                Some(context_id) => {
+                    let mut context = self.continuation_context(context_id, premise, depth, total_epochs, &[]);
+                    self.render_continuation_prompt(&mut context)?
+                }
+                None => {
+                    let mut instructions = Vec::new();
+                    instructions.extend(self.settings.format.instructions().map(|i| format!("Format: {}", i)));
+                    instructions.extend(self.settings.genre.as_ref().map(|g| format!("Genre: {}", g.opening_instructions())));
+                    self.prompts.initial_prompt(premise.unwrap_or_default(), &instructions)?
+                }
+            };
+            records.push(NodeRecord {
+                id: node.id.clone(),
+                predecessors: node.predecessors.clone(),
+                successors: node.successors.clone(),
+                depth,
+                main_storyline: main_line.contains(&node.id.as_str()),
+                system: self.settings.system_prompt.clone(),
+                prompt,
+                reasoning: node.reasoning.clone(),
+                content: node.content.clone(),
+                completion: format!("<think>\n{}\n</think>\n{}", node.reasoning.trim(), node.content.trim()),
+                metadata: node.metadata.clone(),
+                scene_card: node.scene_card.clone(),
+                provenance: node.provenance.clone(),
+            });
+        }
+        Ok(records)
+    }
+
+    /// Exports every node as a JSON Lines training record
+    ///
+    /// # Arguments
+    /// * `path` - The path where the JSONL file should be saved
+    /// * `premise` - The premise the story was generated from, if known
+    ///
+    /// # Returns
+    /// The number of records written
+    pub fn export_to_jsonl(&self, path: &str, premise: Option<&str>) -> Result<usize, StoryChainError> {
+        info!("Exporting story nodes to JSONL: {}", path);
+        let records = self.to_jsonl_records(premise)?;
+        crate::files::write_atomic_with(path, |file| {
+            let mut file = std::io::BufWriter::new(file);
+            for record in &records {
+                serde_json::to_writer(&mut file, record)?;
+                file.write_all(b"\n")?;
+            }
+            file.flush()?;
+            Ok(())
+        })?;
+        Ok(records.len())
+    }
+}
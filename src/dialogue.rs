@@ -0,0 +1,158 @@
+//! Dialogue Ratio Targeting
+//!
+//! This module measures how much of a scene is dialogue and steers generation
+//! toward a target dialogue-to-narration ratio, either by nudging the next
+//! prompt or by asking the AI to revise a scene that drifted too far.
+
+use serde::{Deserialize, Serialize};
+use log::{info, debug};
+use tera::Context;
+use crate::prompts::PromptTemplates;
+use crate::{AIProvider, StoryChain, StoryChainError};
+
+/// Name of the dialogue revision prompt template
+pub const DIALOGUE_REVISION: &str = "dialogue_revision";
+
+/// Built-in template of the dialogue revision prompt
+///
+/// Variables: `measured` and `target`, the measured and target percentage of
+/// words in dialogue; `correction`, what the scene should correct; `scene`,
+/// the scene to revise.
+pub const DIALOGUE_REVISION_TEMPLATE: &str = r#"Revise the scene below. About {{ measured }}% of its words are currently dialogue, but the target is {{ target }}%, so it is {{ correction }}. Keep the same events, characters, and outcome.
+
+Scene:
+{{ scene }}
+
+IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your reasoning about how to rebalance dialogue and narration.
+</think>
+Write the complete revised scene here."#;
+
+/// Target share of a scene's words that should be dialogue
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct DialogueTarget {
+    /// Desired fraction of words inside dialogue, from 0.0 to 1.0
+    pub ratio: f64,
+
+    /// Allowed deviation from the target before a scene counts as drifting
+    pub tolerance: f64,
+
+    /// Ask the AI to revise drifting scenes instead of only nudging the next prompt
+    pub revise: bool,
+}
+
+impl Default for DialogueTarget {
+    fn default() -> Self {
+        Self {
+            ratio: 0.3,
+            tolerance: 0.1,
+            revise: false,
+        }
+    }
+}
+
+impl DialogueTarget {
+    /// Returns how far the measured ratio falls outside the tolerance band,
+    /// negative when there is too little dialogue and positive when too much
+    ///
+    /// # Arguments
+    /// * `measured` - The measured dialogue ratio of a scene
+    pub fn drift(&self, measured: f64) -> Option<f64> {
+        let delta = measured - self.ratio;
+        (delta.abs() > self.tolerance).then_some(delta)
+    }
+}
+
+/// Extracts the spoken lines of a passage (text inside straight or curly double quotes)
+///
+/// # Arguments
+/// * `text` - The passage to scan
+pub fn extract_dialogue(text: &str) -> Vec<String> {
+    let re = regex::Regex::new(r#""([^"]+)"|“([^”]+)”"#).unwrap();
+    re.captures_iter(text)
+        .filter_map(|caps| caps.get(1).or_else(|| caps.get(2)))
+        .map(|m| m.as_str().trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect()
+}
+
+/// Computes the fraction of a passage's words that are dialogue
+///
+/// # Arguments
+/// * `text` - The passage to measure
+pub fn dialogue_ratio(text: &str) -> f64 {
+    let total = text.split_whitespace().count();
+    if total == 0 {
+        return 0.0;
+    }
+    let spoken: usize = extract_dialogue(text).iter().map(|l| l.split_whitespace().count()).sum();
+    spoken as f64 / total as f64
+}
+
+/// Describes a drift in the direction the next scene should correct
+fn correction(delta: f64) -> &'static str {
+    if delta < 0.0 {
+        "too expository; convey more of the scene through spoken dialogue between characters"
+    } else {
+        "too dialogue-heavy; ground the conversation with more action, description, and interiority"
+    }
+}
+
+/// Asks the AI to revise a scene toward the target dialogue ratio
+///
+/// # Arguments
+/// * `templates` - The prompt templates holding the revision prompt
+/// * `ai_provider` - The AI provider to use for the revision
+/// * `content` - The scene content to revise
+/// * `measured` - The scene's measured dialogue ratio
+/// * `target` - The dialogue target to revise toward
+///
+/// # Returns
+/// A tuple of (reasoning, revised content)
+pub async fn revise_for_dialogue(
+    templates: &PromptTemplates,
+    ai_provider: &dyn AIProvider,
+    content: &str,
+    measured: f64,
+    target: &DialogueTarget,
+) -> Result<(String, String), StoryChainError> {
+    info!(
+        "Revising scene for dialogue ratio ({:.0}% measured, {:.0}% target)",
+        measured * 100.0,
+        target.ratio * 100.0
+    );
+    let mut context = Context::new();
+    context.insert("measured", &format!("{:.0}", measured * 100.0));
+    context.insert("target", &format!("{:.0}", target.ratio * 100.0));
+    context.insert("correction", correction(measured - target.ratio));
+    context.insert("scene", content);
+    let prompt = templates.render(DIALOGUE_REVISION, &context)?;
+    ai_provider.generate(&prompt).await
+}
+
+impl StoryChain {
+    /// Builds a guidance note when the scene before the next one drifted from
+    /// the dialogue target
+    ///
+    /// # Arguments
+    /// * `current_node_id` - ID of the node the next scene will follow
+    pub fn dialogue_guidance(&self, current_node_id: &str) -> Option<String> {
+        let target = self.settings.dialogue.as_ref()?;
+        let node = self.nodes.get(current_node_id)?;
+        let measured = node
+            .metadata
+            .get("dialogue_ratio")
+            .and_then(|r| r.parse::<f64>().ok())
+            .unwrap_or_else(|| dialogue_ratio(&node.content));
+        debug!("Dialogue ratio of {}: {:.2}", current_node_id, measured);
+
+        let delta = target.drift(measured)?;
+        Some(format!(
+            "The previous scene was {:.0}% dialogue against a target of {:.0}%, which is {}.",
+            measured * 100.0,
+            target.ratio * 100.0,
+            correction(delta)
+        ))
+    }
+}
@@ -0,0 +1,227 @@
+//! DOCX Export
+//!
+//! This module writes the main storyline as a Word document for editors who
+//! work in Word. Chapters and scenes get the built-in heading styles, so they
+//! show up in Word's navigation pane, and the AI's reasoning for each scene is
+//! attached to its heading as a comment that can be reviewed and resolved
+//! like any other.
+//!
+//! Available with the `docx` feature.
+
+use log::info;
+use std::io::Write;
+use zip::write::SimpleFileOptions;
+use zip::ZipWriter;
+use crate::{MarkdownOptions, StoryChain, StoryChainError};
+
+/// Author shown on the reasoning comments
+const COMMENT_AUTHOR: &str = "StoryChain";
+
+/// Initials shown on the reasoning comments
+const COMMENT_INITIALS: &str = "SC";
+
+/// Content types of the package parts
+const CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+<Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>
+<Default Extension="xml" ContentType="application/xml"/>
+<Override PartName="/word/document.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml"/>
+<Override PartName="/word/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.styles+xml"/>
+<Override PartName="/word/comments.xml" ContentType="application/vnd.openxmlformats-officedocument.wordprocessingml.comments+xml"/>
+<Override PartName="/docProps/core.xml" ContentType="application/vnd.openxmlformats-package.core-properties+xml"/>
+</Types>"#;
+
+/// Relationships of the package to its main document and properties
+const PACKAGE_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="word/document.xml"/>
+<Relationship Id="rId2" Type="http://schemas.openxmlformats.org/package/2006/relationships/metadata/core-properties" Target="docProps/core.xml"/>
+</Relationships>"#;
+
+/// Relationships of the main document to its styles and comments
+const DOCUMENT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+<Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/>
+<Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/comments" Target="comments.xml"/>
+</Relationships>"#;
+
+/// Manuscript styles: Times New Roman body text with first-line indents,
+/// chapters starting on new pages
+const STYLES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<w:styles xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+<w:docDefaults>
+<w:rPrDefault><w:rPr><w:rFonts w:ascii="Times New Roman" w:hAnsi="Times New Roman" w:cs="Times New Roman"/><w:sz w:val="24"/></w:rPr></w:rPrDefault>
+<w:pPrDefault><w:pPr><w:spacing w:after="0" w:line="360" w:lineRule="auto"/></w:pPr></w:pPrDefault>
+</w:docDefaults>
+<w:style w:type="paragraph" w:default="1" w:styleId="Normal"><w:name w:val="Normal"/><w:pPr><w:ind w:firstLine="360"/></w:pPr></w:style>
+<w:style w:type="paragraph" w:styleId="Title"><w:name w:val="Title"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:pPr><w:jc w:val="center"/><w:spacing w:before="2880" w:after="480"/><w:ind w:firstLine="0"/></w:pPr><w:rPr><w:b/><w:sz w:val="48"/></w:rPr></w:style>
+<w:style w:type="paragraph" w:styleId="Heading1"><w:name w:val="heading 1"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:pPr><w:keepNext/><w:pageBreakBefore/><w:spacing w:before="1440" w:after="480"/><w:ind w:firstLine="0"/><w:jc w:val="center"/><w:outlineLvl w:val="0"/></w:pPr><w:rPr><w:b/><w:sz w:val="32"/></w:rPr></w:style>
+<w:style w:type="paragraph" w:styleId="Heading2"><w:name w:val="heading 2"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:pPr><w:keepNext/><w:spacing w:before="480" w:after="240"/><w:ind w:firstLine="0"/><w:outlineLvl w:val="1"/></w:pPr><w:rPr><w:b/><w:sz w:val="26"/></w:rPr></w:style>
+<w:style w:type="paragraph" w:styleId="Recap"><w:name w:val="Recap"/><w:basedOn w:val="Normal"/><w:next w:val="Normal"/><w:pPr><w:spacing w:after="240"/><w:ind w:firstLine="0"/></w:pPr><w:rPr><w:i/></w:rPr></w:style>
+<w:style w:type="paragraph" w:styleId="CommentText"><w:name w:val="annotation text"/><w:basedOn w:val="Normal"/><w:pPr><w:ind w:firstLine="0"/></w:pPr><w:rPr><w:sz w:val="20"/></w:rPr></w:style>
+</w:styles>"#;
+
+/// Escapes text for XML content and attributes, dropping characters XML
+/// does not allow
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c < ' ' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Returns a paragraph of the given style holding a single run of text
+fn paragraph(style: &str, text: &str) -> String {
+    format!(
+        "<w:p><w:pPr><w:pStyle w:val=\"{}\"/></w:pPr><w:r><w:t xml:space=\"preserve\">{}</w:t></w:r></w:p>",
+        style,
+        escape_xml(text)
+    )
+}
+
+/// Splits text into paragraphs at blank lines, joining wrapped lines
+fn text_paragraphs(text: &str) -> Vec<String> {
+    text.split("\n\n")
+        .map(|p| p.split_whitespace().collect::<Vec<_>>().join(" "))
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+impl StoryChain {
+    /// Renders the main storyline as the body of a Word document and the
+    /// comments holding the AI's reasoning
+    ///
+    /// # Arguments
+    /// * `recaps` - Open each chapter with a recap of the previous one
+    ///
+    /// # Returns
+    /// The `document.xml` and `comments.xml` parts
+    fn docx_parts(&self, recaps: bool) -> (String, String) {
+        let mut body = String::new();
+        if let Some(title) = self.metadata.get("title") {
+            body.push_str(&paragraph("Title", title.trim()));
+        }
+
+        let date = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ").to_string();
+        let mut comments = String::new();
+        let mut comment_id = 0;
+        for (index, node) in self.nodes_in_reading_order().into_iter().enumerate() {
+            if let Some((chapter_index, chapter, act)) = self.chapter_starting_at(&node.id) {
+                if let Some(act) = act {
+                    body.push_str(&paragraph("Heading1", act.trim()));
+                }
+                body.push_str(&paragraph("Heading1", chapter.title.trim()));
+                if let Some(recap) = self.chapter_recap(chapter_index).filter(|_| recaps) {
+                    let recap = recap.split_whitespace().collect::<Vec<_>>().join(" ");
+                    body.push_str(&paragraph("Recap", &format!("Previously: {}", recap)));
+                }
+            }
+
+            // The reasoning is attached to the scene heading as a comment
+            let heading = format!("Scene {}", index + 1);
+            let reasoning = text_paragraphs(&node.reasoning);
+            if reasoning.is_empty() {
+                body.push_str(&paragraph("Heading2", &heading));
+            } else {
+                body.push_str(&format!(
+                    "<w:p><w:pPr><w:pStyle w:val=\"Heading2\"/></w:pPr>\
+                    <w:commentRangeStart w:id=\"{id}\"/>\
+                    <w:r><w:t xml:space=\"preserve\">{heading}</w:t></w:r>\
+                    <w:commentRangeEnd w:id=\"{id}\"/>\
+                    <w:r><w:commentReference w:id=\"{id}\"/></w:r></w:p>",
+                    id = comment_id,
+                    heading = escape_xml(&heading)
+                ));
+                comments.push_str(&format!(
+                    "<w:comment w:id=\"{}\" w:author=\"{}\" w:initials=\"{}\" w:date=\"{}\">",
+                    comment_id, COMMENT_AUTHOR, COMMENT_INITIALS, date
+                ));
+                for text in &reasoning {
+                    comments.push_str(&paragraph("CommentText", text));
+                }
+                comments.push_str("</w:comment>");
+                comment_id += 1;
+            }
+
+            for text in text_paragraphs(&node.content) {
+                body.push_str(&paragraph("Normal", &text));
+            }
+        }
+
+        let document = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+            <w:document xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\">\
+            <w:body>{}<w:sectPr><w:pgSz w:w=\"12240\" w:h=\"15840\"/>\
+            <w:pgMar w:top=\"1440\" w:right=\"1440\" w:bottom=\"1440\" w:left=\"1440\" w:header=\"720\" w:footer=\"720\" w:gutter=\"0\"/>\
+            </w:sectPr></w:body></w:document>",
+            body
+        );
+        let comments = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+            <w:comments xmlns:w=\"http://schemas.openxmlformats.org/wordprocessingml/2006/main\">{}</w:comments>",
+            comments
+        );
+        (document, comments)
+    }
+
+    /// Exports the main storyline as a Word document
+    ///
+    /// The title comes first when the chain has one. Chapter titles use the
+    /// Heading 1 style and start on a new page; every scene is headed
+    /// "Scene N" in the Heading 2 style, with the AI's reasoning attached to
+    /// the heading as a comment.
+    ///
+    /// # Arguments
+    /// * `path` - The path where the DOCX file should be saved
+    pub fn export_to_docx(&self, path: &str) -> Result<(), StoryChainError> {
+        self.export_to_docx_with_options(path, &MarkdownOptions::default())
+    }
+
+    /// Exports the main storyline as a Word document, with a recap of the
+    /// previous chapter below each chapter title when `chapter_recaps` is set
+    ///
+    /// # Arguments
+    /// * `path` - The path where the DOCX file should be saved
+    /// * `options` - Controls which optional sections are rendered
+    pub fn export_to_docx_with_options(&self, path: &str, options: &MarkdownOptions) -> Result<(), StoryChainError> {
+        info!("Exporting story to DOCX: {}", path);
+        let (document, comments) = self.docx_parts(options.chapter_recaps);
+        let title = self.metadata.get("title").map(String::as_str).unwrap_or("Generated Story");
+        let core = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+            <cp:coreProperties xmlns:cp=\"http://schemas.openxmlformats.org/package/2006/metadata/core-properties\" \
+            xmlns:dc=\"http://purl.org/dc/elements/1.1/\"><dc:title>{}</dc:title><dc:creator>{}</dc:creator></cp:coreProperties>",
+            escape_xml(title.trim()),
+            COMMENT_AUTHOR
+        );
+
+        crate::files::write_atomic_with(path, |file| {
+            let mut zip = ZipWriter::new(file);
+            let options = SimpleFileOptions::default();
+            for (name, part) in [
+                ("[Content_Types].xml", CONTENT_TYPES),
+                ("_rels/.rels", PACKAGE_RELS),
+                ("docProps/core.xml", core.as_str()),
+                ("word/_rels/document.xml.rels", DOCUMENT_RELS),
+                ("word/document.xml", document.as_str()),
+                ("word/styles.xml", STYLES),
+                ("word/comments.xml", comments.as_str()),
+            ] {
+                zip.start_file(name, options).map_err(std::io::Error::from)?;
+                zip.write_all(part.as_bytes())?;
+            }
+            zip.finish().map_err(std::io::Error::from)?;
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,435 @@
+//! Chain Editing
+//!
+//! This module changes a generated chain without regenerating everything
+//! downstream. A weak scene can be re-rolled in place: the AI writes a
+//! replacement from the same preceding scene, the node keeps its ID and
+//! links, and the replaced version is archived in the node's
+//! `previous_versions` metadata. Editors can also insert hand-written scenes,
+//! remove scenes, and splice in a separately generated chain, with the links
+//! around the edit kept intact.
+
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use log::info;
+use crate::feedback::Feedback;
+use crate::providers::skipping_cache;
+use crate::{AIProvider, StoryChain, StoryChainError, StoryNode};
+
+/// Node metadata key holding the archived versions of a scene, as JSON
+pub const PREVIOUS_VERSIONS_KEY: &str = "previous_versions";
+
+/// Node metadata key marking a scene to be regenerated before the story is continued
+pub const REGENERATE_KEY: &str = "regenerate";
+
+/// Replaces one ID in a list of node links with others, keeping the order
+/// and dropping duplicates
+fn replace_link(links: &mut Vec<String>, from: &str, to: &[String]) {
+    let mut replaced: Vec<String> = Vec::new();
+    for id in links.drain(..) {
+        let ids = if id == from { to.to_vec() } else { vec![id] };
+        for id in ids {
+            if !replaced.contains(&id) {
+                replaced.push(id);
+            }
+        }
+    }
+    *links = replaced;
+}
+
+/// A replaced version of a scene
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ArchivedVersion {
+    /// Content of the scene
+    pub content: String,
+
+    /// The AI's reasoning for the scene
+    pub reasoning: String,
+
+    /// Metadata of the scene, without earlier archived versions
+    pub metadata: HashMap<String, String>,
+
+    /// Reader feedback given on this version
+    #[serde(default)]
+    pub feedback: Vec<Feedback>,
+
+    /// When the version was replaced, as a Unix timestamp
+    pub replaced_at: i64,
+}
+
+impl StoryChain {
+    /// Returns the archived versions of a scene, oldest first
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node
+    pub fn previous_versions(&self, node_id: &str) -> Vec<ArchivedVersion> {
+        self.nodes
+            .get(node_id)
+            .and_then(|node| node.metadata.get(PREVIOUS_VERSIONS_KEY))
+            .and_then(|json| serde_json::from_str(json).ok())
+            .unwrap_or_default()
+    }
+
+    /// Replaces a scene with a newly generated one, keeping its position in
+    /// the chain
+    ///
+    /// The replacement is generated from the node's predecessor with the
+    /// same settings as any new scene. The node keeps its ID, predecessors, and successors; its
+    /// content, reasoning, metadata, and feedback move to its
+    /// `previous_versions` metadata. Scenes downstream are left as they are.
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node to regenerate
+    /// * `ai_provider` - The AI provider to use for generation
+    /// * `premise` - Optional premise to include in generation
+    pub async fn regenerate_node(
+        &mut self,
+        node_id: &str,
+        ai_provider: &dyn AIProvider,
+        premise: Option<&str>,
+    ) -> Result<(), StoryChainError> {
+        let node = self
+            .nodes
+            .get(node_id)
+            .ok_or_else(|| StoryChainError::InvalidChainOperation(format!("Cannot regenerate unknown node: {}", node_id)))?;
+        let predecessor_id = node.predecessor().cloned().ok_or_else(|| {
+            StoryChainError::InvalidChainOperation("The opening scene cannot be regenerated".to_string())
+        })?;
+        if node.metadata.contains_key("subplot") {
+            return Err(StoryChainError::InvalidChainOperation(format!(
+                "Node {} is a subplot scene; only main-plot scenes can be regenerated",
+                node_id
+            )));
+        }
+
+        // Place the replacement at the same point of the story
+        let epoch = self.path_to(node_id).len() - 1;
+        let total_epochs = self.storylines().iter().map(Vec::len).max().unwrap_or(1).saturating_sub(1).max(epoch);
+        let context_id = self.main_plot_context(&predecessor_id);
+
+        // Generate the replacement as a temporary sibling, then move it into the node,
+        // skipping the response cache, which holds the scene being replaced
+        self.forget_setups_of(node_id);
+        let new_ids = skipping_cache(self.generate_scene(&predecessor_id, &context_id, ai_provider, premise, epoch, total_epochs))
+            .await?;
+        let temp_id = new_ids[0].clone();
+        let replacement = self.nodes.remove(&temp_id).expect("generated node exists");
+        if let Some(predecessor) = self.nodes.get_mut(&predecessor_id) {
+            predecessor.successors.retain(|id| *id != temp_id);
+        }
+        self.rename_references(&temp_id, node_id);
+
+        let node = self.nodes.get_mut(node_id).expect("node exists");
+        let mut versions: Vec<ArchivedVersion> = node
+            .metadata
+            .remove(PREVIOUS_VERSIONS_KEY)
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        versions.push(ArchivedVersion {
+            content: std::mem::replace(&mut node.content, replacement.content),
+            reasoning: std::mem::replace(&mut node.reasoning, replacement.reasoning),
+            metadata: std::mem::replace(&mut node.metadata, replacement.metadata),
+            feedback: std::mem::take(&mut node.feedback),
+            replaced_at: chrono::Utc::now().timestamp(),
+        });
+        node.scene_card = replacement.scene_card;
+        node.provenance = replacement.provenance;
+        node.metadata.insert(PREVIOUS_VERSIONS_KEY.to_string(), serde_json::to_string(&versions)?);
+
+        info!("Regenerated {} ({} earlier versions archived)", node_id, versions.len());
+        Ok(())
+    }
+
+    /// Marks or unmarks a scene to be regenerated later
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node to mark
+    /// * `marked` - Whether the node should be regenerated
+    pub fn mark_for_regeneration(&mut self, node_id: &str, marked: bool) -> Result<(), StoryChainError> {
+        if marked && node_id == self.root_node_id {
+            return Err(StoryChainError::InvalidChainOperation("The opening scene cannot be regenerated".to_string()));
+        }
+        let node = self
+            .nodes
+            .get_mut(node_id)
+            .ok_or_else(|| StoryChainError::InvalidChainOperation(format!("Cannot mark unknown node: {}", node_id)))?;
+        if marked {
+            node.metadata.insert(REGENERATE_KEY.to_string(), "true".to_string());
+        } else {
+            node.metadata.remove(REGENERATE_KEY);
+        }
+        Ok(())
+    }
+
+    /// Returns the IDs of the scenes marked for regeneration, in storyline order
+    pub fn marked_for_regeneration(&self) -> Vec<String> {
+        self.nodes_in_graph_order()
+            .into_iter()
+            .filter(|node| node.metadata.contains_key(REGENERATE_KEY))
+            .map(|node| node.id.clone())
+            .collect()
+    }
+
+    /// Regenerates every scene marked for regeneration with `regenerate_node`,
+    /// which clears the mark
+    ///
+    /// # Arguments
+    /// * `ai_provider` - The AI provider to use for generation
+    /// * `premise` - Optional premise to include in generation
+    ///
+    /// # Returns
+    /// The IDs of the regenerated nodes
+    pub async fn regenerate_marked(
+        &mut self,
+        ai_provider: &dyn AIProvider,
+        premise: Option<&str>,
+    ) -> Result<Vec<String>, StoryChainError> {
+        let marked = self.marked_for_regeneration();
+        for node_id in &marked {
+            self.regenerate_node(node_id, ai_provider, premise).await?;
+        }
+        Ok(marked)
+    }
+
+    /// Replaces the content of a scene with an edited version, keeping its
+    /// position in the chain
+    ///
+    /// The replaced content moves to the node's `previous_versions`
+    /// metadata together with the reasoning, metadata, and feedback it had;
+    /// the node keeps its reasoning and metadata, while its feedback, which
+    /// was given on the old text, is cleared.
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node to edit
+    /// * `content` - The edited content
+    pub fn edit_node(&mut self, node_id: &str, content: String) -> Result<(), StoryChainError> {
+        let node = self
+            .nodes
+            .get_mut(node_id)
+            .ok_or_else(|| StoryChainError::InvalidChainOperation(format!("Cannot edit unknown node: {}", node_id)))?;
+        if node.content == content {
+            return Ok(());
+        }
+        let mut versions: Vec<ArchivedVersion> = node
+            .metadata
+            .remove(PREVIOUS_VERSIONS_KEY)
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        versions.push(ArchivedVersion {
+            content: std::mem::replace(&mut node.content, content),
+            reasoning: node.reasoning.clone(),
+            metadata: node.metadata.clone(),
+            feedback: std::mem::take(&mut node.feedback),
+            replaced_at: chrono::Utc::now().timestamp(),
+        });
+        node.metadata.insert(PREVIOUS_VERSIONS_KEY.to_string(), serde_json::to_string(&versions)?);
+        if let Some(provenance) = &mut node.provenance {
+            provenance.revised(crate::provenance::EDIT_REVISION);
+        }
+
+        info!("Edited {} ({} earlier versions archived)", node_id, versions.len());
+        Ok(())
+    }
+
+    /// Inserts a new scene directly after a node
+    ///
+    /// The new node takes over all of the node's successors, so every
+    /// storyline that ran through the node runs through the inserted scene.
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node the new scene follows
+    /// * `content` - Content of the new scene
+    /// * `reasoning` - Reasoning recorded for the new scene
+    ///
+    /// # Returns
+    /// The ID of the inserted node
+    pub fn insert_node_after(&mut self, node_id: &str, content: String, reasoning: String) -> Result<String, StoryChainError> {
+        let new_id = self.next_node_id();
+        let node = self
+            .nodes
+            .get_mut(node_id)
+            .ok_or_else(|| StoryChainError::InvalidChainOperation(format!("Cannot insert after unknown node: {}", node_id)))?;
+        let successors = std::mem::replace(&mut node.successors, vec![new_id.clone()]);
+        for successor_id in &successors {
+            if let Some(successor) = self.nodes.get_mut(successor_id) {
+                replace_link(&mut successor.predecessors, node_id, std::slice::from_ref(&new_id));
+            }
+        }
+
+        self.nodes.insert(
+            new_id.clone(),
+            StoryNode {
+                id: new_id.clone(),
+                content,
+                reasoning,
+                predecessors: vec![node_id.to_string()],
+                successors,
+                metadata: HashMap::new(),
+                feedback: Vec::new(),
+                scene_card: None,
+                embedding: None,
+                provenance: None,
+            },
+        );
+        info!("Inserted {} after {}", new_id, node_id);
+        Ok(new_id)
+    }
+
+    /// Removes a scene from the chain, linking its predecessors directly to
+    /// its successors
+    ///
+    /// Setups, characters, glossary entries, chapters, subplots, strands, and
+    /// the reading order stop referring to the removed node. The opening scene cannot be removed.
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node to remove
+    ///
+    /// # Returns
+    /// The removed node
+    pub fn remove_node(&mut self, node_id: &str) -> Result<StoryNode, StoryChainError> {
+        if node_id == self.root_node_id {
+            return Err(StoryChainError::InvalidChainOperation("The opening scene cannot be removed".to_string()));
+        }
+        let node = self
+            .nodes
+            .remove(node_id)
+            .ok_or_else(|| StoryChainError::InvalidChainOperation(format!("Cannot remove unknown node: {}", node_id)))?;
+
+        for predecessor_id in &node.predecessors {
+            if let Some(predecessor) = self.nodes.get_mut(predecessor_id) {
+                replace_link(&mut predecessor.successors, node_id, &node.successors);
+            }
+        }
+        for successor_id in &node.successors {
+            if let Some(successor) = self.nodes.get_mut(successor_id) {
+                replace_link(&mut successor.predecessors, node_id, &node.predecessors);
+            }
+        }
+
+        self.forget_setups_of(node_id);
+        for character in &mut self.character_registry.characters {
+            if character.introduced_in.as_deref() == Some(node_id) {
+                character.introduced_in = None;
+            }
+        }
+        for entry in &mut self.glossary {
+            if entry.first_node_id.as_deref() == Some(node_id) {
+                entry.first_node_id = None;
+            }
+        }
+        for chapter in &mut self.chapters {
+            chapter.node_ids.retain(|id| id != node_id);
+        }
+        for subplot in &mut self.subplots {
+            subplot.node_ids.retain(|id| id != node_id);
+        }
+        for strand in &mut self.strands {
+            strand.node_ids.retain(|id| id != node_id);
+        }
+        self.reading_order.retain(|id| id != node_id);
+
+        info!("Removed {}", node_id);
+        Ok(node)
+    }
+
+    /// Splices the scenes of another chain in directly after a node
+    ///
+    /// The other chain's opening scene follows the node, and the end of its
+    /// main storyline leads into the node's former successors. Its scenes are
+    /// given new IDs; chain-level data such as setups and chapters is not
+    /// carried over.
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node the spliced scenes follow
+    /// * `chain` - The chain to splice in
+    ///
+    /// # Returns
+    /// The new IDs of the spliced nodes, in storyline order
+    pub fn splice_chain(&mut self, node_id: &str, chain: StoryChain) -> Result<Vec<String>, StoryChainError> {
+        if !self.nodes.contains_key(node_id) {
+            return Err(StoryChainError::InvalidChainOperation(format!("Cannot splice after unknown node: {}", node_id)));
+        }
+
+        // Scenes not reachable from the other chain's opening are left out
+        let mut order: Vec<String> = Vec::new();
+        for line in chain.storylines() {
+            for node in line {
+                if !order.contains(&node.id) {
+                    order.push(node.id.clone());
+                }
+            }
+        }
+        let Some(main_end) = chain.nodes_in_order().last().map(|n| n.id.clone()) else {
+            return Ok(Vec::new());
+        };
+
+        let mut new_ids = HashMap::new();
+        for id in &order {
+            let new_id = self.next_node_id();
+            let mut node = chain.nodes[id].clone();
+            node.id = new_id.clone();
+            self.nodes.insert(new_id.clone(), node);
+            new_ids.insert(id.clone(), new_id);
+        }
+        let map = |links: &[String]| links.iter().filter_map(|id| new_ids.get(id).cloned()).collect::<Vec<_>>();
+        for id in &order {
+            let node = self.nodes.get_mut(&new_ids[id]).expect("spliced node exists");
+            node.predecessors = map(&node.predecessors);
+            node.successors = map(&node.successors);
+        }
+
+        // Link the spliced scenes in between the node and its successors
+        let first = new_ids[&chain.root_node_id].clone();
+        let last = new_ids[&main_end].clone();
+        let successors = std::mem::replace(
+            &mut self.nodes.get_mut(node_id).expect("node exists").successors,
+            vec![first.clone()],
+        );
+        for successor_id in &successors {
+            if let Some(successor) = self.nodes.get_mut(successor_id) {
+                replace_link(&mut successor.predecessors, node_id, std::slice::from_ref(&last));
+            }
+        }
+        self.nodes.get_mut(&first).expect("spliced node exists").predecessors = vec![node_id.to_string()];
+        self.nodes.get_mut(&last).expect("spliced node exists").successors.extend(successors);
+
+        info!("Spliced {} scenes in after {}", order.len(), node_id);
+        Ok(order.iter().map(|id| new_ids[id].clone()).collect())
+    }
+
+    /// Points every chain-level reference to a node ID at another ID
+    ///
+    /// # Arguments
+    /// * `from` - The ID being replaced
+    /// * `to` - The ID to refer to instead
+    fn rename_references(&mut self, from: &str, to: &str) {
+        let rename = |id: &mut String| {
+            if id == from {
+                *id = to.to_string();
+            }
+        };
+        for node in self.nodes.values_mut() {
+            node.predecessors.iter_mut().for_each(rename);
+            node.successors.iter_mut().for_each(rename);
+        }
+        for setup in &mut self.setups {
+            setup.planted_in.iter_mut().for_each(rename);
+            setup.paid_off_in.iter_mut().for_each(rename);
+        }
+        for character in &mut self.character_registry.characters {
+            character.introduced_in.iter_mut().for_each(rename);
+        }
+        for entry in &mut self.glossary {
+            entry.first_node_id.iter_mut().for_each(rename);
+        }
+        for chapter in &mut self.chapters {
+            chapter.node_ids.iter_mut().for_each(rename);
+        }
+        for subplot in &mut self.subplots {
+            subplot.node_ids.iter_mut().for_each(rename);
+        }
+        for strand in &mut self.strands {
+            strand.node_ids.iter_mut().for_each(rename);
+        }
+        self.reading_order.iter_mut().for_each(rename);
+    }
+}
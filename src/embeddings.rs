@@ -0,0 +1,270 @@
+//! Scene Embeddings
+//!
+//! This module lets continuation prompts reach back past the immediate
+//! predecessor in stories longer than the context window. Every scene on the
+//! storyline being continued is embedded with an `EmbeddingProvider`, the
+//! vector is stored on its node, and the earlier scenes most similar to the
+//! previous one are included in the prompt.
+
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use log::{debug, error, info};
+use crate::providers::{DEFAULT_OLLAMA_HOST, DEFAULT_OLLAMA_PORT};
+use crate::{StoryChain, StoryChainError};
+
+/// Embedding model used unless another is configured
+pub const DEFAULT_EMBEDDING_MODEL: &str = "nomic-embed-text";
+
+/// Characters of each recalled scene included in prompts unless configured otherwise
+pub const DEFAULT_RECALL_EXCERPT_CHARS: usize = 1200;
+
+/// Trait defining the interface for models that turn text into vectors
+#[async_trait::async_trait]
+pub trait EmbeddingProvider: Send + Sync {
+    /// Embeds a text
+    ///
+    /// # Arguments
+    /// * `text` - The text to embed
+    ///
+    /// # Returns
+    /// The embedding vector or an error
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, StoryChainError>;
+
+    /// Returns the name of the embedding model, stored with each vector so
+    /// that vectors from different models are never compared
+    fn model(&self) -> &str;
+}
+
+impl std::fmt::Debug for dyn EmbeddingProvider {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EmbeddingProvider({})", self.model())
+    }
+}
+
+/// Implementation of EmbeddingProvider using Ollama's `/api/embed` endpoint
+pub struct OllamaEmbeddingProvider {
+    /// HTTP client used for requests
+    client: reqwest::Client,
+
+    /// Base URL of the Ollama server, e.g. `http://localhost:11434`
+    base_url: String,
+
+    /// Name of the embedding model
+    model: String,
+
+    /// API key sent as a bearer token
+    api_key: Option<String>,
+}
+
+impl OllamaEmbeddingProvider {
+    /// Creates a new OllamaEmbeddingProvider talking to a local Ollama server
+    ///
+    /// # Arguments
+    /// * `model` - Name of the embedding model
+    pub fn new(model: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: format!("http://{}:{}", DEFAULT_OLLAMA_HOST, DEFAULT_OLLAMA_PORT),
+            model,
+            api_key: None,
+        }
+    }
+
+    /// Sets the base URL of the Ollama server, e.g. `https://ollama.example.com`
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Sets an API key sent as a bearer token, for servers behind an authenticating proxy
+    pub fn with_api_key(mut self, api_key: String) -> Self {
+        self.api_key = Some(api_key);
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl EmbeddingProvider for OllamaEmbeddingProvider {
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, StoryChainError> {
+        let url = format!("{}/api/embed", self.base_url);
+        debug!("Requesting an embedding from {} for model: {}", url, self.model);
+
+        let mut request = self.client.post(&url).json(&json!({ "model": self.model, "input": text }));
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request.send().await.map_err(|e| {
+            error!("Ollama embedding request failed: {}", e);
+            StoryChainError::AIServerError(format!("Ollama embedding request failed: {}", e))
+        })?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            error!("Ollama embedding request failed with {}: {}", status, text);
+            return Err(StoryChainError::AIServerError(format!(
+                "Ollama embedding request failed with {}: {}",
+                status, text
+            )));
+        }
+        let body: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| StoryChainError::AIServerError(format!("Invalid Ollama embedding response: {}", e)))?;
+        body["embeddings"][0]
+            .as_array()
+            .map(|values| values.iter().filter_map(|v| v.as_f64()).map(|v| v as f32).collect())
+            .ok_or_else(|| StoryChainError::AIServerError("Ollama embedding response has no vector".to_string()))
+    }
+
+    fn model(&self) -> &str {
+        &self.model
+    }
+}
+
+/// The embedding of a scene, with what it was computed from
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SceneEmbedding {
+    /// Embedding model the vector came from
+    pub model: String,
+
+    /// SHA-256 digest of the content that was embedded, so that edited
+    /// scenes are embedded again
+    pub digest: String,
+
+    /// The embedding vector
+    pub vector: Vec<f32>,
+}
+
+/// How many earlier scenes are recalled into each continuation prompt
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SceneRecall {
+    /// Number of earlier scenes to recall
+    pub scenes: usize,
+
+    /// Characters of each recalled scene to include
+    pub excerpt_chars: usize,
+}
+
+/// Returns the hex SHA-256 digest of a scene's content
+fn content_digest(content: &str) -> String {
+    Sha256::digest(content.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Computes the cosine similarity of two vectors
+///
+/// # Returns
+/// The similarity between -1 and 1, or 0 when the vectors differ in length
+/// or one of them is zero
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+impl StoryChain {
+    /// Sets the model that embeds scenes for recall
+    ///
+    /// # Arguments
+    /// * `embedder` - The embedding provider
+    pub fn with_embedder(mut self, embedder: Arc<dyn EmbeddingProvider>) -> Self {
+        self.embedder = Some(embedder);
+        self
+    }
+
+    /// Embeds every scene on the storyline leading to a node that has no
+    /// up-to-date embedding from the chain's embedding model
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the last node of the storyline
+    ///
+    /// # Returns
+    /// The number of scenes embedded
+    pub async fn embed_storyline(&mut self, node_id: &str) -> Result<usize, StoryChainError> {
+        let Some(embedder) = self.embedder.clone() else {
+            return Ok(0);
+        };
+        let stale: Vec<(String, String, String)> = self
+            .path_to(node_id)
+            .into_iter()
+            .map(|node| (node.id.clone(), node.content.clone(), content_digest(&node.content)))
+            .filter(|(id, _, digest)| {
+                self.nodes[id]
+                    .embedding
+                    .as_ref()
+                    .is_none_or(|e| e.model != embedder.model() || &e.digest != digest)
+            })
+            .collect();
+        for (id, content, digest) in &stale {
+            let vector = embedder.embed(content).await?;
+            if let Some(node) = self.nodes.get_mut(id) {
+                node.embedding = Some(SceneEmbedding { model: embedder.model().to_string(), digest: digest.clone(), vector });
+            }
+        }
+        if !stale.is_empty() {
+            info!("Embedded {} scenes", stale.len());
+        }
+        Ok(stale.len())
+    }
+
+    /// Finds the earlier scenes on a node's storyline most similar to it
+    ///
+    /// Only scenes embedded with the same model as the node are compared,
+    /// and the node itself is left out since it is already in the prompt.
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node whose scene is being continued
+    /// * `count` - Maximum number of scenes to return
+    ///
+    /// # Returns
+    /// The IDs of the most similar scenes, in storyline order
+    pub fn similar_earlier_scenes(&self, node_id: &str, count: usize) -> Vec<String> {
+        let Some(query) = self.nodes.get(node_id).and_then(|node| node.embedding.as_ref()) else {
+            return Vec::new();
+        };
+        let path = self.path_to(node_id);
+        let mut scored: Vec<(f32, usize)> = path
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.id != node_id)
+            .filter_map(|(position, node)| {
+                let embedding = node.embedding.as_ref().filter(|e| e.model == query.model)?;
+                Some((cosine_similarity(&query.vector, &embedding.vector), position))
+            })
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        let mut positions: Vec<usize> = scored.into_iter().take(count).map(|(_, position)| position).collect();
+        positions.sort();
+        positions.into_iter().map(|position| path[position].id.clone()).collect()
+    }
+
+    /// Lays out the recalled scenes for the continuation prompt
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node whose scene is being continued
+    ///
+    /// # Returns
+    /// The recalled scenes, each headed by its node ID, or an empty string
+    /// when recall is disabled or finds nothing
+    pub(crate) fn recalled_scenes(&self, node_id: &str) -> String {
+        let Some(recall) = &self.settings.recall else {
+            return String::new();
+        };
+        self.similar_earlier_scenes(node_id, recall.scenes)
+            .iter()
+            .map(|id| {
+                let excerpt: String = self.nodes[id].content.trim().chars().take(recall.excerpt_chars).collect();
+                format!("[{}]\n{}", id, excerpt)
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
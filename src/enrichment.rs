@@ -0,0 +1,303 @@
+//! Scene Metadata Enrichment
+//!
+//! This module runs a cheap extraction pass over every new scene and records
+//! who appears in it, where and when it takes place, and its mood in the
+//! node's metadata. The pass is either heuristic (registered names, place
+//! prepositions, and word lists) or asks the model. Query helpers such as
+//! `nodes_featuring` then find scenes for analysis and retrieval.
+
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use log::{info, debug};
+use tera::Context;
+use crate::passes::parse_labeled_fields;
+use crate::{AIProvider, StoryChain, StoryChainError, StoryNode};
+
+/// Node metadata key listing the characters in a scene, comma-separated
+pub const CHARACTERS_KEY: &str = "characters";
+
+/// Node metadata key holding where a scene takes place
+pub const LOCATION_KEY: &str = "location";
+
+/// Node metadata key holding when a scene takes place in the story
+pub const TIME_KEY: &str = "time";
+
+/// Node metadata key holding the mood of a scene
+pub const MOOD_KEY: &str = "mood";
+
+/// Name of the scene metadata extraction prompt template
+pub const SCENE_METADATA: &str = "scene_metadata";
+
+/// Built-in template of the scene metadata extraction prompt
+///
+/// Variables: `scene`, the scene to read.
+pub const SCENE_METADATA_TEMPLATE: &str = r#"Read the scene below and list what it establishes.
+
+Scene:
+{{ scene }}
+
+IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your brief reasoning.
+</think>
+CHARACTERS: the names of the characters present or named, comma-separated, or NONE
+LOCATION: where the scene takes place, in a few words
+TIME: when it takes place in the story, such as a time of day or date
+MOOD: the dominant mood, in one or two words"#;
+
+/// Labels of the fields in the model's extraction response
+const EXTRACTION_LABELS: [&str; 4] = ["CHARACTERS", "LOCATION", "TIME", "MOOD"];
+
+/// Words that mark the time of day, grouped by the time they name
+const TIME_WORDS: [(&str, &[&str]); 6] = [
+    ("dawn", &["dawn", "daybreak", "sunrise", "first light"]),
+    ("morning", &["morning", "breakfast"]),
+    ("noon", &["noon", "midday", "lunch"]),
+    ("afternoon", &["afternoon"]),
+    ("evening", &["evening", "dusk", "sunset", "twilight", "dinner"]),
+    ("night", &["night", "midnight", "moonlight", "darkness fell"]),
+];
+
+/// Words that signal a mood, grouped by the mood they signal
+const MOOD_WORDS: [(&str, &[&str]); 5] = [
+    ("tense", &["danger", "fear", "threat", "trembl", "scream", "blood", "gun", "chase", "panic", "desperate"]),
+    ("joyful", &["laugh", "smile", "joy", "delight", "celebrat", "grin", "happy", "cheer"]),
+    ("somber", &["grief", "tears", "wept", "mourn", "funeral", "loss", "sorrow", "regret"]),
+    ("eerie", &["shadow", "whisper", "silence", "strange", "ghost", "cold", "fog", "creak"]),
+    ("calm", &["quiet", "peace", "gentle", "rest", "calm", "warm", "soft", "still"]),
+];
+
+/// How scene metadata is extracted
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EnrichmentMode {
+    /// From registered names, place prepositions, and word lists, with no model call
+    #[default]
+    Heuristic,
+
+    /// By asking the model
+    Ai,
+}
+
+impl FromStr for EnrichmentMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "heuristic" | "regex" => Ok(EnrichmentMode::Heuristic),
+            "ai" | "model" => Ok(EnrichmentMode::Ai),
+            other => Err(format!("Unknown enrichment mode: {}", other)),
+        }
+    }
+}
+
+/// What the extraction pass found in a scene
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct SceneMetadata {
+    /// Characters who appear in or are named in the scene
+    pub characters: Vec<String>,
+
+    /// Where the scene takes place
+    pub location: Option<String>,
+
+    /// When the scene takes place in the story, such as `night`
+    pub time: Option<String>,
+
+    /// The dominant mood of the scene
+    pub mood: Option<String>,
+}
+
+impl SceneMetadata {
+    /// Reads the extracted fields back from a node's metadata
+    ///
+    /// # Arguments
+    /// * `node` - The node to read
+    pub fn from_node(node: &StoryNode) -> Self {
+        let field = |key: &str| node.metadata.get(key).filter(|v| !v.trim().is_empty()).cloned();
+        Self {
+            characters: field(CHARACTERS_KEY).map(|c| split_list(&c)).unwrap_or_default(),
+            location: field(LOCATION_KEY),
+            time: field(TIME_KEY),
+            mood: field(MOOD_KEY),
+        }
+    }
+
+    /// Writes the extracted fields into a node's metadata, leaving fields
+    /// that were not found untouched
+    ///
+    /// # Arguments
+    /// * `node` - The node to update
+    pub fn apply_to(&self, node: &mut StoryNode) {
+        if !self.characters.is_empty() {
+            node.metadata.insert(CHARACTERS_KEY.to_string(), self.characters.join(", "));
+        }
+        for (key, value) in [(LOCATION_KEY, &self.location), (TIME_KEY, &self.time), (MOOD_KEY, &self.mood)] {
+            if let Some(value) = value {
+                node.metadata.insert(key.to_string(), value.clone());
+            }
+        }
+    }
+}
+
+/// Splits a comma-separated list, dropping empty items and `none`
+fn split_list(text: &str) -> Vec<String> {
+    text.split([',', ';', '\n'])
+        .map(|item| item.trim().trim_start_matches("- ").trim())
+        .filter(|item| !item.is_empty() && !item.eq_ignore_ascii_case("none"))
+        .map(str::to_string)
+        .collect()
+}
+
+/// Returns the label of the group whose words occur most often in the text
+fn dominant_group(text: &str, groups: &[(&'static str, &[&str])]) -> Option<String> {
+    let lower = text.to_lowercase();
+    groups
+        .iter()
+        .map(|(label, words)| (*label, words.iter().map(|word| lower.matches(word).count()).sum::<usize>()))
+        .filter(|(_, count)| *count > 0)
+        .max_by_key(|(_, count)| *count)
+        .map(|(label, _)| label.to_string())
+}
+
+/// Returns the first capitalized place named after a preposition, such as
+/// `the Iron Gate` in "they met at the Iron Gate"
+fn find_location(text: &str) -> Option<String> {
+    let re = regex::Regex::new(
+        r"\b(?:in|at|inside|into|outside|across|aboard|beneath|within)\s+((?:the\s+)?[A-Z][\w'’]+(?:\s+(?:of\s+)?[A-Z][\w'’]+)*)",
+    )
+    .unwrap();
+    re.captures(text).map(|caps| caps[1].to_string())
+}
+
+impl StoryChain {
+    /// Extracts scene metadata without calling a model
+    ///
+    /// Characters are the registered names mentioned in the scene or, with
+    /// an empty registry, the capitalized names in it; the location is the
+    /// first capitalized place after a preposition; time and mood come from
+    /// word lists.
+    ///
+    /// # Arguments
+    /// * `content` - The scene to read
+    pub fn extract_scene_metadata(&self, content: &str) -> SceneMetadata {
+        let characters = if self.character_registry.is_empty() {
+            crate::characters::candidate_names(content)
+        } else {
+            self.character_registry
+                .characters
+                .iter()
+                .filter(|character| {
+                    std::iter::once(&character.name).chain(&character.aliases).any(|name| {
+                        regex::Regex::new(&format!(r"\b{}\b", regex::escape(name)))
+                            .is_ok_and(|re| re.is_match(content))
+                    })
+                })
+                .map(|character| character.name.clone())
+                .collect()
+        };
+        let location = find_location(content);
+        let characters = characters
+            .into_iter()
+            .filter(|name| !location.as_ref().is_some_and(|place| place.contains(name.as_str())))
+            .collect();
+        SceneMetadata {
+            characters,
+            location,
+            time: dominant_group(content, &TIME_WORDS),
+            mood: dominant_group(content, &MOOD_WORDS),
+        }
+    }
+
+    /// Runs the extraction pass over a node and stores what it finds in the
+    /// node's metadata
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node to enrich
+    /// * `mode` - Whether to use the heuristics or the model
+    /// * `ai_provider` - The AI provider used in `Ai` mode
+    pub async fn enrich_node(
+        &mut self,
+        node_id: &str,
+        mode: EnrichmentMode,
+        ai_provider: &dyn AIProvider,
+    ) -> Result<SceneMetadata, StoryChainError> {
+        let content = self
+            .nodes
+            .get(node_id)
+            .map(|node| node.content.clone())
+            .ok_or_else(|| StoryChainError::InvalidChainOperation(format!("Node {} not found", node_id)))?;
+
+        let extracted = match mode {
+            EnrichmentMode::Heuristic => self.extract_scene_metadata(&content),
+            EnrichmentMode::Ai => {
+                info!("Extracting scene metadata for {}", node_id);
+                let mut context = Context::new();
+                context.insert("scene", &content);
+                let prompt = self.render_prompt(SCENE_METADATA, context)?;
+                let (_, response) = ai_provider.generate(&prompt).await?;
+                let fields = parse_labeled_fields(&response, &EXTRACTION_LABELS);
+                let field = |label: &str| {
+                    fields.get(label).cloned().filter(|value| !value.eq_ignore_ascii_case("none"))
+                };
+                SceneMetadata {
+                    characters: field("CHARACTERS").map(|c| split_list(&c)).unwrap_or_default(),
+                    location: field("LOCATION"),
+                    time: field("TIME"),
+                    mood: field("MOOD").map(|mood| mood.to_lowercase()),
+                }
+            }
+        };
+
+        debug!("Scene {} metadata: {:?}", node_id, extracted);
+        if let Some(node) = self.nodes.get_mut(node_id) {
+            extracted.apply_to(node);
+        }
+        Ok(extracted)
+    }
+
+    /// Returns the enriched metadata of a node
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node
+    pub fn scene_metadata(&self, node_id: &str) -> Option<SceneMetadata> {
+        self.nodes.get(node_id).map(SceneMetadata::from_node)
+    }
+
+    /// Returns the scenes, in reading order, whose metadata matches a predicate
+    fn nodes_matching(&self, predicate: impl Fn(&SceneMetadata) -> bool) -> Vec<&StoryNode> {
+        self.nodes_in_order()
+            .into_iter()
+            .filter(|node| predicate(&SceneMetadata::from_node(node)))
+            .collect()
+    }
+
+    /// Returns the scenes, in reading order, in which a character appears
+    ///
+    /// A registered character matches by any of their aliases.
+    ///
+    /// # Arguments
+    /// * `name` - Name or alias of the character
+    pub fn nodes_featuring(&self, name: &str) -> Vec<&StoryNode> {
+        let canonical = self.character_registry.lookup(name).map(|c| c.name.as_str()).unwrap_or(name);
+        self.nodes_matching(|metadata| {
+            metadata.characters.iter().any(|c| c.eq_ignore_ascii_case(canonical) || c.eq_ignore_ascii_case(name))
+        })
+    }
+
+    /// Returns the scenes, in reading order, whose location mentions the given place
+    ///
+    /// # Arguments
+    /// * `place` - The place to look for, matched case-insensitively
+    pub fn nodes_at_location(&self, place: &str) -> Vec<&StoryNode> {
+        let place = place.to_lowercase();
+        self.nodes_matching(|metadata| metadata.location.as_ref().is_some_and(|l| l.to_lowercase().contains(&place)))
+    }
+
+    /// Returns the scenes, in reading order, with the given mood
+    ///
+    /// # Arguments
+    /// * `mood` - The mood to look for, matched case-insensitively
+    pub fn nodes_with_mood(&self, mood: &str) -> Vec<&StoryNode> {
+        self.nodes_matching(|metadata| metadata.mood.as_ref().is_some_and(|m| m.eq_ignore_ascii_case(mood)))
+    }
+}
@@ -0,0 +1,336 @@
+//! EPUB Export
+//!
+//! This module writes the main storyline as an EPUB 3 e-book. The title,
+//! logline, and blurb open the book on a title page and become its
+//! description, while the genre tags and keywords become its subjects, so
+//! that e-book readers and stores can catalogue it. Every chapter is its own
+//! document in the book, or every scene when the story has no chapters.
+//! Illustrated scenes carry their image, which is packed into the book.
+//!
+//! Available with the `epub` feature.
+
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+use crate::html::{escape_html, paragraphs_to_html};
+use crate::illustrations::{IMAGE_KEY, IMAGE_PROMPT_KEY};
+use crate::translation::{language_code, LANGUAGE_KEY};
+use crate::{MarkdownOptions, StoryChain, StoryChainError, StoryNode};
+
+/// Creator named in the book's metadata
+const CREATOR: &str = "StoryChain";
+
+/// Points reading systems to the package document
+const CONTAINER: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<container version="1.0" xmlns="urn:oasis:names:tc:opendocument:xmlns:container">
+<rootfiles>
+<rootfile full-path="OEBPS/content.opf" media-type="application/oebps-package+xml"/>
+</rootfiles>
+</container>"#;
+
+/// Styles of the book's documents
+const STYLE: &str = "\
+body { font-family: Georgia, serif; line-height: 1.5; }
+h1, .logline { text-align: center; }
+.logline, .pov { font-style: italic; }
+.act { text-align: center; font-variant: small-caps; }
+p { margin: 0; text-indent: 1.5em; }
+.logline, .tags, .recap { text-indent: 0; margin: 1em 0; }
+figure.illustration { margin: 1.5em 0; text-align: center; }
+figure.illustration img { max-width: 100%; }
+";
+
+/// A document of the book: a chapter, or a scene of a story without chapters
+struct Section<'a> {
+    title: String,
+    act: Option<&'a str>,
+    chapter: Option<&'a str>,
+    recap: Option<&'a str>,
+    nodes: Vec<(usize, &'a StoryNode)>,
+}
+
+/// A scene's illustration as packed into the book
+struct Illustration {
+    href: String,
+    media_type: &'static str,
+    alt: String,
+    data: Vec<u8>,
+}
+
+/// Returns the media type of an image file by its extension, if e-book
+/// readers are required to show it
+fn image_media_type(path: &Path) -> Option<&'static str> {
+    match path.extension()?.to_str()?.to_lowercase().as_str() {
+        "png" => Some("image/png"),
+        "jpg" | "jpeg" => Some("image/jpeg"),
+        "gif" => Some("image/gif"),
+        "webp" => Some("image/webp"),
+        "svg" => Some("image/svg+xml"),
+        _ => None,
+    }
+}
+
+/// Wraps a body in an XHTML document of the book
+fn xhtml_document(language: &str, title: &str, body: &str) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<!DOCTYPE html>\n\
+        <html xmlns=\"http://www.w3.org/1999/xhtml\" xmlns:epub=\"http://www.idpf.org/2007/ops\" lang=\"{0}\" xml:lang=\"{0}\">\n\
+        <head>\n<meta charset=\"utf-8\"/>\n<title>{1}</title>\n<link rel=\"stylesheet\" type=\"text/css\" href=\"style.css\"/>\n</head>\n\
+        <body>\n{2}</body>\n</html>\n",
+        language,
+        escape_html(title),
+        body
+    )
+}
+
+/// Renders text as XHTML paragraphs
+fn paragraphs_to_xhtml(text: &str) -> String {
+    paragraphs_to_html(text).replace("<br>", "<br/>")
+}
+
+/// Splits comma-separated chain metadata into its items
+fn metadata_items<'a>(chain: &'a StoryChain, key: &str) -> Vec<&'a str> {
+    chain.metadata.get(key).map(|v| v.split(',').map(str::trim).filter(|t| !t.is_empty()).collect()).unwrap_or_default()
+}
+
+impl StoryChain {
+    /// Reads the illustrations of the main-storyline scenes, skipping images
+    /// that are missing or in a format e-book readers need not show
+    fn epub_illustrations(&self) -> Result<HashMap<&str, Illustration>, StoryChainError> {
+        let mut illustrations = HashMap::new();
+        for node in self.nodes_in_reading_order() {
+            let Some(image) = node.metadata.get(IMAGE_KEY).map(Path::new) else {
+                continue;
+            };
+            let Some(media_type) = image_media_type(image).filter(|_| image.exists()) else {
+                warn!("Leaving the illustration {} of {} out of the EPUB", image.display(), node.id);
+                continue;
+            };
+            let extension = image.extension().and_then(|e| e.to_str()).unwrap_or_default().to_lowercase();
+            illustrations.insert(
+                node.id.as_str(),
+                Illustration {
+                    href: format!("images/{}.{}", node.id, extension),
+                    media_type,
+                    alt: node.metadata.get(IMAGE_PROMPT_KEY).cloned().unwrap_or_default(),
+                    data: std::fs::read(image)?,
+                },
+            );
+        }
+        Ok(illustrations)
+    }
+
+    /// Splits the main storyline into the documents of the book
+    ///
+    /// # Arguments
+    /// * `recaps` - Open each chapter with a recap of the previous one
+    fn epub_sections(&self, recaps: bool) -> Vec<Section<'_>> {
+        let mut sections: Vec<Section> = Vec::new();
+        for (index, node) in self.nodes_in_reading_order().into_iter().enumerate() {
+            match self.chapter_starting_at(&node.id) {
+                Some((chapter_index, chapter, act)) => sections.push(Section {
+                    title: chapter.title.trim().to_string(),
+                    act: act.map(str::trim),
+                    chapter: Some(chapter.title.trim()),
+                    recap: self.chapter_recap(chapter_index).filter(|_| recaps),
+                    nodes: Vec::new(),
+                }),
+                None if sections.is_empty() || self.chapters.is_empty() => sections.push(Section {
+                    title: format!("Scene {}", index + 1),
+                    act: None,
+                    chapter: None,
+                    recap: None,
+                    nodes: Vec::new(),
+                }),
+                None => {}
+            }
+            sections.last_mut().expect("a section was started").nodes.push((index, node));
+        }
+        sections
+    }
+
+    /// Renders the title page with the logline, blurb, and tags
+    fn epub_title_page(&self, title: &str) -> String {
+        let mut body = format!("<h1>{}</h1>\n", escape_html(title));
+        if let Some(logline) = self.metadata.get("logline") {
+            body.push_str(&format!("<p class=\"logline\">{}</p>\n", escape_html(logline.trim())));
+        }
+        if let Some(blurb) = self.metadata.get("blurb") {
+            body.push_str(&paragraphs_to_xhtml(blurb));
+            body.push('\n');
+        }
+        for (key, label) in [("genre_tags", "Genres"), ("content_warnings", "Content warnings")] {
+            let items = metadata_items(self, key);
+            if !items.is_empty() {
+                body.push_str(&format!("<p class=\"tags\"><strong>{}:</strong> {}</p>\n", label, escape_html(&items.join(", "))));
+            }
+        }
+        body
+    }
+
+    /// Renders a chapter, or a single scene, of the book; headings nest as
+    /// in the HTML export
+    fn epub_section(&self, section: &Section, illustrations: &HashMap<&str, Illustration>) -> String {
+        let (act_level, chapter_level, scene_level) = self.heading_levels();
+        let mut body = String::new();
+        if let Some(act) = section.act {
+            body.push_str(&format!("<h{0} class=\"act\">{1}</h{0}>\n", act_level, escape_html(act)));
+        }
+        if let Some(chapter) = section.chapter {
+            body.push_str(&format!("<h{0}>{1}</h{0}>\n", chapter_level, escape_html(chapter)));
+        }
+        if let Some(recap) = section.recap {
+            body.push_str(&format!("<p class=\"recap\"><em>Previously:</em> {}</p>\n", escape_html(recap)));
+        }
+        for (index, node) in &section.nodes {
+            body.push_str(&format!("<section id=\"{}\">\n", escape_html(&node.id)));
+            body.push_str(&format!("<h{0}>Scene {1}</h{0}>\n", scene_level, index + 1));
+            if let Some(pov) = node.metadata.get("pov") {
+                body.push_str(&format!("<p class=\"pov\">{}</p>\n", escape_html(pov)));
+            }
+            if let Some(illustration) = illustrations.get(node.id.as_str()) {
+                body.push_str(&format!(
+                    "<figure class=\"illustration\"><img src=\"{}\" alt=\"{}\"/></figure>\n",
+                    escape_html(&illustration.href),
+                    escape_html(&illustration.alt)
+                ));
+            }
+            body.push_str(&paragraphs_to_xhtml(&node.content));
+            body.push_str("\n</section>\n");
+        }
+        body
+    }
+
+    /// Exports the main storyline as an EPUB 3 e-book
+    ///
+    /// The book opens with a title page showing the logline, blurb, genres,
+    /// and content warnings. The blurb (or the logline) is the book's
+    /// description, and the genre tags and keywords are its subjects. Every
+    /// chapter is a document of its own, listed in the table of contents, or
+    /// every scene when the story has no chapters. Illustrated scenes show
+    /// their image above the text. The book is in the language the chain was
+    /// translated into, and English otherwise.
+    ///
+    /// # Arguments
+    /// * `path` - The path where the EPUB file should be saved
+    pub fn export_to_epub(&self, path: &str) -> Result<(), StoryChainError> {
+        self.export_to_epub_with_options(path, &MarkdownOptions::default())
+    }
+
+    /// Exports the main storyline as an EPUB 3 e-book, with a recap of the
+    /// previous chapter at each chapter head when `chapter_recaps` is set
+    ///
+    /// # Arguments
+    /// * `path` - The path where the EPUB file should be saved
+    /// * `options` - Controls which optional sections are rendered
+    pub fn export_to_epub_with_options(&self, path: &str, options: &MarkdownOptions) -> Result<(), StoryChainError> {
+        info!("Exporting story to EPUB: {}", path);
+        let title = self.metadata.get("title").map(|t| t.trim()).unwrap_or("Generated Story");
+        let language = self.metadata.get(LANGUAGE_KEY).and_then(|l| language_code(l)).unwrap_or("en");
+        let sections = self.epub_sections(options.chapter_recaps);
+        let illustrations = self.epub_illustrations()?;
+
+        // Documents of the book, in reading order
+        let mut documents = vec![("title".to_string(), xhtml_document(language, title, &self.epub_title_page(title)))];
+        for (index, section) in sections.iter().enumerate() {
+            let document = xhtml_document(language, &section.title, &self.epub_section(section, &illustrations));
+            documents.push((format!("section_{}", index + 1), document));
+        }
+        let toc: String = sections
+            .iter()
+            .enumerate()
+            .map(|(index, s)| format!("<li><a href=\"section_{}.xhtml\">{}</a></li>\n", index + 1, escape_html(&s.title)))
+            .collect();
+        let nav = xhtml_document(
+            language,
+            "Contents",
+            &format!("<nav epub:type=\"toc\" id=\"toc\">\n<h1>Contents</h1>\n<ol>\n{}</ol>\n</nav>\n", toc),
+        );
+
+        // Package document: metadata, every file of the book, and the reading order
+        let opening = self.nodes.get(&self.root_node_id).map(|n| n.content.as_str()).unwrap_or_default();
+        let identifier: String = Sha256::digest(format!("{}\0{}", title, opening).as_bytes())
+            .iter()
+            .take(16)
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let mut metadata = format!(
+            "<dc:identifier id=\"book-id\">urn:storychain:{}</dc:identifier>\n<dc:title>{}</dc:title>\n\
+            <dc:language>{}</dc:language>\n<dc:creator>{}</dc:creator>\n\
+            <meta property=\"dcterms:modified\">{}</meta>\n",
+            identifier,
+            escape_html(title),
+            language,
+            CREATOR,
+            chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ")
+        );
+        if let Some(description) = self.metadata.get("blurb").or_else(|| self.metadata.get("logline")) {
+            metadata.push_str(&format!("<dc:description>{}</dc:description>\n", escape_html(description.trim())));
+        }
+        let mut subjects: Vec<&str> = Vec::new();
+        for subject in metadata_items(self, "genre_tags").into_iter().chain(metadata_items(self, "keywords")) {
+            if !subjects.iter().any(|s| s.eq_ignore_ascii_case(subject)) {
+                subjects.push(subject);
+            }
+        }
+        for subject in subjects {
+            metadata.push_str(&format!("<dc:subject>{}</dc:subject>\n", escape_html(subject)));
+        }
+        let mut manifest = String::from(
+            "<item id=\"nav\" href=\"nav.xhtml\" media-type=\"application/xhtml+xml\" properties=\"nav\"/>\n\
+            <item id=\"style\" href=\"style.css\" media-type=\"text/css\"/>\n",
+        );
+        for (index, illustration) in illustrations.values().enumerate() {
+            manifest.push_str(&format!(
+                "<item id=\"image_{}\" href=\"{}\" media-type=\"{}\"/>\n",
+                index + 1,
+                escape_html(&illustration.href),
+                illustration.media_type
+            ));
+        }
+        let mut spine = String::new();
+        for (id, _) in &documents {
+            manifest.push_str(&format!("<item id=\"{0}\" href=\"{0}.xhtml\" media-type=\"application/xhtml+xml\"/>\n", id));
+            spine.push_str(&format!("<itemref idref=\"{}\"/>\n", id));
+        }
+        let package = format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <package xmlns=\"http://www.idpf.org/2007/opf\" version=\"3.0\" unique-identifier=\"book-id\" xml:lang=\"{}\">\n\
+            <metadata xmlns:dc=\"http://purl.org/dc/elements/1.1/\">\n{}</metadata>\n\
+            <manifest>\n{}</manifest>\n<spine>\n{}</spine>\n</package>\n",
+            language, metadata, manifest, spine
+        );
+
+        crate::files::write_atomic_with(path, |file| {
+            let mut zip = ZipWriter::new(file);
+
+            // The media type comes first and uncompressed, as reading systems expect
+            zip.start_file("mimetype", SimpleFileOptions::default().compression_method(CompressionMethod::Stored))
+                .map_err(std::io::Error::from)?;
+            zip.write_all(b"application/epub+zip")?;
+
+            let options = SimpleFileOptions::default();
+            let parts = [
+                ("META-INF/container.xml".to_string(), CONTAINER.to_string()),
+                ("OEBPS/content.opf".to_string(), package),
+                ("OEBPS/nav.xhtml".to_string(), nav),
+                ("OEBPS/style.css".to_string(), STYLE.to_string()),
+            ];
+            let documents = documents.into_iter().map(|(id, document)| (format!("OEBPS/{}.xhtml", id), document));
+            for (name, part) in parts.into_iter().chain(documents) {
+                zip.start_file(name, options).map_err(std::io::Error::from)?;
+                zip.write_all(part.as_bytes())?;
+            }
+            for illustration in illustrations.values() {
+                zip.start_file(format!("OEBPS/{}", illustration.href), options).map_err(std::io::Error::from)?;
+                zip.write_all(&illustration.data)?;
+            }
+            zip.finish().map_err(std::io::Error::from)?;
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,107 @@
+//! Run Events
+//!
+//! This module describes the progress of a generation run as a sequence of
+//! `RunEvent`s, serialized as JSON objects tagged with their `type`, so that
+//! a front-end following a run, for example over the server's event stream
+//! or WebSocket, can show the model's output as it is generated and each
+//! scene as soon as it is finished:
+//!
+//! ```json
+//! {"type":"token","text":"The fox"}
+//! {"type":"node_completed","node":"node_3","epoch":2,"words":412}
+//! ```
+
+use serde::{Deserialize, Serialize};
+
+/// Something that happened during a generation run
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RunEvent {
+    /// The run started
+    Started {
+        /// ID of the run
+        run: String,
+
+        /// Name of the chain being generated
+        chain: String,
+
+        /// Number of epochs the run generates
+        epochs: usize,
+    },
+
+    /// An epoch started
+    EpochStarted {
+        /// The epoch, counting from 1
+        epoch: usize,
+    },
+
+    /// A piece of the model's output, sent while a response is generated;
+    /// every response ends with a blank line
+    Token {
+        /// The text generated since the previous piece
+        text: String,
+    },
+
+    /// A scene was generated and added to the chain
+    NodeCompleted {
+        /// ID of the new node
+        node: String,
+
+        /// The epoch the scene was generated in; 0 for the opening scene
+        epoch: usize,
+
+        /// Number of words in the scene
+        words: usize,
+    },
+
+    /// An epoch finished and the chain was saved
+    EpochFinished {
+        /// The epoch, counting from 1
+        epoch: usize,
+
+        /// Number of scenes generated in the epoch
+        scenes: usize,
+    },
+
+    /// The run finished
+    Finished {
+        /// Number of scenes in the chain
+        scenes: usize,
+    },
+
+    /// The run stopped with an error
+    Failed {
+        /// Why the run failed
+        error: String,
+    },
+
+    /// The run was cancelled; the chain keeps the epochs saved before
+    Cancelled,
+}
+
+impl RunEvent {
+    /// Returns the name of the event, as in its `type` field
+    pub fn name(&self) -> &'static str {
+        match self {
+            RunEvent::Started { .. } => "started",
+            RunEvent::EpochStarted { .. } => "epoch_started",
+            RunEvent::Token { .. } => "token",
+            RunEvent::NodeCompleted { .. } => "node_completed",
+            RunEvent::EpochFinished { .. } => "epoch_finished",
+            RunEvent::Finished { .. } => "finished",
+            RunEvent::Failed { .. } => "failed",
+            RunEvent::Cancelled => "cancelled",
+        }
+    }
+
+    /// Returns whether the event is worth replaying to a client that starts
+    /// following the run late; tokens are only sent live
+    pub fn is_replayed(&self) -> bool {
+        !matches!(self, RunEvent::Token { .. })
+    }
+
+    /// Returns whether the event ends the run
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, RunEvent::Finished { .. } | RunEvent::Failed { .. } | RunEvent::Cancelled)
+    }
+}
@@ -0,0 +1,136 @@
+//! Export Formats
+//!
+//! This module names the formats a saved chain can be converted to, so that
+//! the `convert` command can write any of them from one story file instead
+//! of regenerating the story with the matching export flag.
+
+use log::info;
+use std::str::FromStr;
+use crate::graph::DEFAULT_COLOR_KEY;
+use crate::{StoryChain, StoryChainError};
+
+/// A format a chain can be exported to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// The chain itself, as JSON
+    Json,
+    /// The readable markdown document
+    Markdown,
+    /// The styled HTML page
+    Html,
+    /// The HTML page where the reader picks the branches
+    InteractiveHtml,
+    /// Plain prose
+    Text,
+    /// Twine's Twee format
+    Twee,
+    /// Inkle's Ink format
+    Ink,
+    /// A Fountain screenplay
+    Fountain,
+    /// The scene graph for Graphviz
+    Dot,
+    /// The scene graph as a Mermaid flowchart
+    Mermaid,
+    /// One training record per node
+    Jsonl,
+    /// The typeset manuscript, with the `pdf` feature
+    Pdf,
+    /// The Word document, with the `docx` feature
+    Docx,
+    /// The e-book, with the `epub` feature
+    Epub,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "json" => Ok(ExportFormat::Json),
+            "markdown" | "md" => Ok(ExportFormat::Markdown),
+            "html" => Ok(ExportFormat::Html),
+            "interactive-html" => Ok(ExportFormat::InteractiveHtml),
+            "text" | "txt" => Ok(ExportFormat::Text),
+            "twee" => Ok(ExportFormat::Twee),
+            "ink" => Ok(ExportFormat::Ink),
+            "fountain" => Ok(ExportFormat::Fountain),
+            "dot" => Ok(ExportFormat::Dot),
+            "mermaid" | "mmd" => Ok(ExportFormat::Mermaid),
+            "jsonl" => Ok(ExportFormat::Jsonl),
+            "pdf" => Ok(ExportFormat::Pdf),
+            "docx" => Ok(ExportFormat::Docx),
+            "epub" => Ok(ExportFormat::Epub),
+            other => Err(format!("Unknown export format: {}", other)),
+        }
+    }
+}
+
+impl ExportFormat {
+    /// Names accepted on the command line, one per format
+    pub const NAMES: [&'static str; 14] = [
+        "json", "markdown", "html", "interactive-html", "text", "twee", "ink", "fountain", "dot", "mermaid", "jsonl", "pdf", "docx",
+        "epub",
+    ];
+
+    /// Returns the suffix the generate command gives files of this format,
+    /// replacing the `.json` of the story file
+    pub fn file_suffix(&self) -> &'static str {
+        match self {
+            ExportFormat::Json => ".json",
+            ExportFormat::Markdown => ".md",
+            ExportFormat::Html => ".html",
+            ExportFormat::InteractiveHtml => "_interactive.html",
+            ExportFormat::Text => ".txt",
+            ExportFormat::Twee => ".twee",
+            ExportFormat::Ink => ".ink",
+            ExportFormat::Fountain => ".fountain",
+            ExportFormat::Dot => ".dot",
+            ExportFormat::Mermaid => ".mmd",
+            ExportFormat::Jsonl => ".jsonl",
+            ExportFormat::Pdf => ".pdf",
+            ExportFormat::Docx => ".docx",
+            ExportFormat::Epub => ".epub",
+        }
+    }
+}
+
+impl StoryChain {
+    /// Exports the chain in the given format
+    ///
+    /// Graphs are colored by the default metadata key and training records
+    /// are built without the premise; use the format's own export method to
+    /// choose otherwise.
+    ///
+    /// # Arguments
+    /// * `format` - The format to write
+    /// * `path` - The path where the file should be saved
+    pub fn export_as(&self, format: ExportFormat, path: &str) -> Result<(), StoryChainError> {
+        info!("Exporting story as {:?} to {}", format, path);
+        match format {
+            ExportFormat::Json => self.export_to_file(path),
+            ExportFormat::Markdown => self.export_to_markdown(path),
+            ExportFormat::Html => self.export_to_html(path),
+            ExportFormat::InteractiveHtml => self.export_to_interactive_html(path),
+            ExportFormat::Text => self.export_to_text(path),
+            ExportFormat::Twee => self.export_to_twee(path),
+            ExportFormat::Ink => self.export_to_ink(path),
+            ExportFormat::Fountain => self.export_to_fountain(path),
+            ExportFormat::Dot => self.export_to_dot(path, DEFAULT_COLOR_KEY),
+            ExportFormat::Mermaid => self.export_to_mermaid(path, DEFAULT_COLOR_KEY),
+            ExportFormat::Jsonl => self.export_to_jsonl(path, None).map(|_| ()),
+            #[cfg(feature = "pdf")]
+            ExportFormat::Pdf => self.export_to_pdf(path),
+            #[cfg(feature = "docx")]
+            ExportFormat::Docx => self.export_to_docx(path),
+            #[cfg(feature = "epub")]
+            ExportFormat::Epub => self.export_to_epub(path),
+            #[allow(unreachable_patterns)]
+            other => Err(StoryChainError::ConfigError(format!(
+                "{:?} export is not available; rebuild with --features {}",
+                other,
+                other.file_suffix().trim_start_matches('.')
+            ))),
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Reader Feedback
+//!
+//! This module lets readers attach ratings and comments to individual nodes
+//! and aggregates that feedback into guidance for subsequent scenes, e.g.
+//! "readers found the pacing slow; tighten the next scene".
+
+use serde::{Deserialize, Serialize};
+use chrono::Local;
+use log::debug;
+use crate::{StoryChain, StoryChainError};
+
+/// Ratings at or below this value mark a node as poorly received
+const LOW_RATING: u8 = 2;
+
+/// Maximum number of reader comments quoted in the generation guidance
+const MAX_QUOTED_COMMENTS: usize = 5;
+
+/// A single reader rating and optional comment attached to a node
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Feedback {
+    /// Rating from 1 (poor) to 5 (excellent)
+    pub rating: u8,
+
+    /// Optional free-form comment
+    pub comment: Option<String>,
+
+    /// When the feedback was recorded
+    pub timestamp: String,
+}
+
+/// Feedback aggregated across a whole chain
+#[derive(Debug, Clone, Default, Serialize, PartialEq)]
+pub struct FeedbackSummary {
+    /// Number of ratings received
+    pub count: usize,
+
+    /// Average rating across all nodes, if any ratings exist
+    pub average_rating: Option<f64>,
+
+    /// IDs of nodes whose average rating is low
+    pub low_rated_nodes: Vec<String>,
+
+    /// Reader comments, most recent node first
+    pub comments: Vec<String>,
+}
+
+impl StoryChain {
+    /// Attaches a reader rating and optional comment to a node
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node being rated
+    /// * `rating` - Rating from 1 to 5
+    /// * `comment` - Optional free-form comment
+    pub fn add_feedback(
+        &mut self,
+        node_id: &str,
+        rating: u8,
+        comment: Option<String>,
+    ) -> Result<(), StoryChainError> {
+        if !(1..=5).contains(&rating) {
+            return Err(StoryChainError::InvalidFeedback(format!(
+                "Rating must be between 1 and 5, got {}",
+                rating
+            )));
+        }
+        let node = self.nodes.get_mut(node_id).ok_or_else(|| {
+            StoryChainError::InvalidFeedback(format!("Node not found: {}", node_id))
+        })?;
+
+        debug!("Recording rating {} for node {}", rating, node_id);
+        node.feedback.push(Feedback {
+            rating,
+            comment: comment.filter(|c| !c.trim().is_empty()),
+            timestamp: Local::now().to_rfc3339(),
+        });
+        Ok(())
+    }
+
+    /// Aggregates the feedback attached to all nodes in reading order
+    pub fn feedback_summary(&self) -> FeedbackSummary {
+        let mut summary = FeedbackSummary::default();
+        let mut total = 0u32;
+
+        for node in self.nodes_in_order().into_iter().rev() {
+            if node.feedback.is_empty() {
+                continue;
+            }
+            let node_total: u32 = node.feedback.iter().map(|f| f.rating as u32).sum();
+            total += node_total;
+            summary.count += node.feedback.len();
+            if node_total as f64 / node.feedback.len() as f64 <= LOW_RATING as f64 {
+                summary.low_rated_nodes.push(node.id.clone());
+            }
+            summary
+                .comments
+                .extend(node.feedback.iter().filter_map(|f| f.comment.clone()));
+        }
+
+        if summary.count > 0 {
+            summary.average_rating = Some(total as f64 / summary.count as f64);
+        }
+        summary
+    }
+
+    /// Builds a guidance note for the next prompt from the aggregated feedback
+    ///
+    /// Returns `None` when no feedback has been recorded.
+    pub fn feedback_guidance(&self) -> Option<String> {
+        let summary = self.feedback_summary();
+        let average = summary.average_rating?;
+
+        let mut note = format!(
+            "Readers rated the story so far {:.1}/5 on average across {} ratings.",
+            average, summary.count
+        );
+        if !summary.low_rated_nodes.is_empty() {
+            note.push_str(&format!(
+                " {} scene(s) were poorly received.",
+                summary.low_rated_nodes.len()
+            ));
+        }
+        if !summary.comments.is_empty() {
+            let quoted: Vec<String> = summary
+                .comments
+                .iter()
+                .take(MAX_QUOTED_COMMENTS)
+                .map(|c| format!("\"{}\"", c))
+                .collect();
+            note.push_str(&format!(" Reader comments: {}.", quoted.join("; ")));
+        }
+        note.push_str(" Address this feedback in the next scene while keeping what readers enjoyed.");
+        Some(note)
+    }
+}
@@ -0,0 +1,186 @@
+//! Safe File Writes
+//!
+//! This module writes files atomically: the contents go to a temporary file
+//! next to the target, which is flushed to disk and then renamed over it, so
+//! a crash or full disk mid-write leaves the previous version intact instead
+//! of a truncated story. It also compresses story files by extension: chains
+//! saved as `.json.gz` are gzipped, and with the `zstd` feature, chains saved
+//! as `.json.zst` use zstd. Compressed files are recognized by their contents
+//! when loaded, whatever their name.
+
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use log::debug;
+use crate::StoryChainError;
+
+/// Extension of gzipped story files
+pub const GZIP_EXTENSION: &str = ".json.gz";
+
+/// Extension of zstd-compressed story files
+pub const ZSTD_EXTENSION: &str = ".json.zst";
+
+/// Magic bytes starting a gzip stream
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Magic bytes starting a zstd frame
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Returns the temporary file a path is written to before being renamed
+fn temporary_path(path: &Path) -> PathBuf {
+    let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+    path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()))
+}
+
+/// Writes a file atomically through a writer
+///
+/// # Arguments
+/// * `path` - The file to write
+/// * `write` - Writes the contents to the temporary file
+pub fn write_atomic_with<F>(path: impl AsRef<Path>, write: F) -> Result<(), StoryChainError>
+where
+    F: FnOnce(&mut std::fs::File) -> Result<(), StoryChainError>,
+{
+    let path = path.as_ref();
+    let temporary = temporary_path(path);
+    let result = std::fs::File::create(&temporary).map_err(StoryChainError::from).and_then(|mut file| {
+        write(&mut file)?;
+        file.sync_all()?;
+        Ok(())
+    });
+    match result.and_then(|_| std::fs::rename(&temporary, path).map_err(StoryChainError::from)) {
+        Ok(()) => {
+            debug!("Wrote {} atomically", path.display());
+            Ok(())
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&temporary);
+            Err(e)
+        }
+    }
+}
+
+/// Writes a file atomically
+///
+/// # Arguments
+/// * `path` - The file to write
+/// * `contents` - The contents of the file
+pub fn write_atomic(path: impl AsRef<Path>, contents: impl AsRef<[u8]>) -> Result<(), StoryChainError> {
+    write_atomic_with(path, |file| Ok(file.write_all(contents.as_ref())?))
+}
+
+/// How a story file is compressed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Plain JSON
+    #[default]
+    None,
+
+    /// gzip, for `.json.gz` files
+    Gzip,
+
+    /// zstd, for `.json.zst` files; needs the `zstd` feature
+    Zstd,
+}
+
+impl Compression {
+    /// Returns the compression a file name asks for
+    ///
+    /// # Arguments
+    /// * `path` - The file to write
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        let name = path.as_ref().to_string_lossy();
+        if name.ends_with(".gz") {
+            Compression::Gzip
+        } else if name.ends_with(".zst") {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Returns the compression of file contents, from their magic bytes
+    ///
+    /// # Arguments
+    /// * `bytes` - The contents of a file
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(&GZIP_MAGIC) {
+            Compression::Gzip
+        } else if bytes.starts_with(&ZSTD_MAGIC) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Compresses contents
+    ///
+    /// # Arguments
+    /// * `bytes` - The contents to compress
+    pub fn compress(self, bytes: &[u8]) -> Result<Vec<u8>, StoryChainError> {
+        match self {
+            Compression::None => Ok(bytes.to_vec()),
+            Compression::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(bytes)?;
+                Ok(encoder.finish()?)
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Ok(zstd::encode_all(bytes, 0)?),
+            #[cfg(not(feature = "zstd"))]
+            Compression::Zstd => Err(zstd_unavailable()),
+        }
+    }
+
+    /// Decompresses contents
+    ///
+    /// # Arguments
+    /// * `bytes` - The compressed contents
+    pub fn decompress(self, bytes: &[u8]) -> Result<Vec<u8>, StoryChainError> {
+        match self {
+            Compression::None => Ok(bytes.to_vec()),
+            Compression::Gzip => {
+                let mut decompressed = Vec::new();
+                flate2::read::GzDecoder::new(bytes).read_to_end(&mut decompressed)?;
+                Ok(decompressed)
+            }
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Ok(zstd::decode_all(bytes)?),
+            #[cfg(not(feature = "zstd"))]
+            Compression::Zstd => Err(zstd_unavailable()),
+        }
+    }
+}
+
+/// Returns the error for zstd files in builds without the `zstd` feature
+#[cfg(not(feature = "zstd"))]
+fn zstd_unavailable() -> StoryChainError {
+    StoryChainError::ConfigError("zstd compression is not available; rebuild with --features zstd".to_string())
+}
+
+/// Reads a file, decompressing it when it is compressed
+///
+/// # Arguments
+/// * `path` - The file to read
+pub fn read_decompressed(path: impl AsRef<Path>) -> Result<Vec<u8>, StoryChainError> {
+    let bytes = std::fs::read(path)?;
+    Compression::detect(&bytes).decompress(&bytes)
+}
+
+/// Writes a file atomically, compressing it as its name asks
+///
+/// # Arguments
+/// * `path` - The file to write
+/// * `contents` - The uncompressed contents
+pub fn write_compressed(path: impl AsRef<Path>, contents: &[u8]) -> Result<(), StoryChainError> {
+    let path = path.as_ref();
+    write_atomic(path, Compression::from_path(path).compress(contents)?)
+}
+
+/// Returns the path of a story file without its compression extension, for
+/// naming the exports written next to it
+///
+/// # Arguments
+/// * `path` - A story file such as `story.json.gz`
+pub fn uncompressed_path(path: &str) -> &str {
+    path.strip_suffix(".gz").or_else(|| path.strip_suffix(".zst")).unwrap_or(path)
+}
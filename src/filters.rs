@@ -0,0 +1,350 @@
+//! Content Filters
+//!
+//! This module screens every generated scene before it is committed to the
+//! chain, for stories written for younger audiences. A `ContentFilter`
+//! reports the passages of a scene it objects to; the chain then either
+//! rejects the scene and asks the model for another, or redacts the
+//! passages. The built-in `KeywordFilter` matches listed words, regular
+//! expressions, and the vocabulary not allowed under an audience rating;
+//! other filters, such as a moderation model, can be added to the chain's
+//! `content_filters`.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use log::{info, debug, warn};
+use crate::constraints::{ContentRating, GRAPHIC_CONTENT, MILD_LANGUAGE, STRONG_LANGUAGE, VIOLENT_CONTENT};
+use crate::{AIProvider, StoryChain, StoryChainError};
+
+/// Number of regenerations used when none is configured
+pub const DEFAULT_FILTER_RETRIES: usize = 2;
+
+/// Text that replaces a redacted passage
+pub const REDACTION: &str = "[redacted]";
+
+/// Node metadata key listing what the filters matched in a scene
+pub const FILTER_MATCHES_KEY: &str = "content_filter_matches";
+
+/// Node metadata key holding how many attempts the filters rejected before the scene was kept
+pub const FILTER_REJECTIONS_KEY: &str = "content_filter_rejections";
+
+/// A passage a filter objects to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterMatch {
+    /// Byte offset where the passage starts
+    pub start: usize,
+
+    /// Byte offset where the passage ends
+    pub end: usize,
+
+    /// Why the passage was matched, such as the listed word
+    pub reason: String,
+}
+
+/// Screens generated scenes before they are committed to the chain
+pub trait ContentFilter: Send + Sync {
+    /// Returns the name of the filter, used in logs
+    fn name(&self) -> &str;
+
+    /// Finds the passages of a scene the filter objects to
+    ///
+    /// # Arguments
+    /// * `content` - The scene to screen
+    ///
+    /// # Returns
+    /// The objectionable passages, empty when the scene is acceptable
+    fn check(&self, content: &str) -> Vec<FilterMatch>;
+}
+
+impl std::fmt::Debug for dyn ContentFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ContentFilter({})", self.name())
+    }
+}
+
+/// What happens to a scene a filter objects to
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterAction {
+    /// Throw the scene away and generate another, redacting the last attempt
+    /// when every retry is rejected
+    #[default]
+    Reject,
+
+    /// Keep the scene with the matched passages replaced by `[redacted]`
+    Redact,
+}
+
+impl FromStr for FilterAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "reject" | "regenerate" => Ok(FilterAction::Reject),
+            "redact" => Ok(FilterAction::Redact),
+            other => Err(format!("Unknown filter action: {}", other)),
+        }
+    }
+}
+
+/// Settings of the built-in keyword filter, saved with the chain
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ContentFilterSettings {
+    /// Words and phrases matched as whole words, ignoring case
+    pub terms: Vec<String>,
+
+    /// Regular expressions matched against the scene
+    pub patterns: Vec<String>,
+
+    /// Audience rating whose disallowed vocabulary is also matched
+    pub rating: Option<ContentRating>,
+
+    /// What happens to a scene the filters object to
+    pub action: FilterAction,
+
+    /// Maximum number of regenerations per scene when rejecting
+    pub max_retries: usize,
+}
+
+impl Default for ContentFilterSettings {
+    fn default() -> Self {
+        Self {
+            terms: Vec::new(),
+            patterns: Vec::new(),
+            rating: None,
+            action: FilterAction::default(),
+            max_retries: DEFAULT_FILTER_RETRIES,
+        }
+    }
+}
+
+impl ContentFilterSettings {
+    /// Reads terms and patterns from a filter list, one per line
+    ///
+    /// Blank lines and lines starting with `#` are skipped, and lines
+    /// written as `/pattern/` are regular expressions.
+    ///
+    /// # Arguments
+    /// * `text` - The filter list
+    pub fn parse_list(&mut self, text: &str) {
+        for line in text.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+            match line.strip_prefix('/').and_then(|rest| rest.strip_suffix('/')) {
+                Some(pattern) => self.patterns.push(pattern.to_string()),
+                None => self.terms.push(line.to_string()),
+            }
+        }
+    }
+}
+
+/// Matches listed words, regular expressions, and the vocabulary not
+/// allowed under an audience rating
+#[derive(Debug, Clone)]
+pub struct KeywordFilter {
+    /// Compiled expressions, each with the reason reported for its matches
+    expressions: Vec<(Regex, String)>,
+}
+
+impl KeywordFilter {
+    /// Builds the filter from its settings
+    ///
+    /// # Arguments
+    /// * `settings` - The terms, patterns, and rating to match
+    ///
+    /// # Returns
+    /// The filter, or a configuration error for an invalid pattern
+    pub fn new(settings: &ContentFilterSettings) -> Result<Self, StoryChainError> {
+        let mut terms: Vec<String> = settings.terms.clone();
+        if let Some(rating) = settings.rating {
+            if rating < ContentRating::R {
+                terms.extend(STRONG_LANGUAGE.iter().chain(&GRAPHIC_CONTENT).map(|w| w.to_string()));
+            }
+            if rating == ContentRating::G {
+                terms.extend(MILD_LANGUAGE.iter().chain(&VIOLENT_CONTENT).map(|w| w.to_string()));
+            }
+        }
+
+        let mut expressions = Vec::new();
+        for term in terms {
+            let re = Regex::new(&format!(r"(?i)\b{}\b", regex::escape(term.trim())))
+                .map_err(|e| StoryChainError::ConfigError(e.to_string()))?;
+            expressions.push((re, term.to_lowercase()));
+        }
+        for pattern in &settings.patterns {
+            let re = Regex::new(pattern)
+                .map_err(|e| StoryChainError::ConfigError(format!("Invalid filter pattern /{}/: {}", pattern, e)))?;
+            expressions.push((re, format!("/{}/", pattern)));
+        }
+        Ok(Self { expressions })
+    }
+}
+
+impl ContentFilter for KeywordFilter {
+    fn name(&self) -> &str {
+        "keyword filter"
+    }
+
+    fn check(&self, content: &str) -> Vec<FilterMatch> {
+        self.expressions
+            .iter()
+            .flat_map(|(re, reason)| {
+                re.find_iter(content).map(|m| FilterMatch { start: m.start(), end: m.end(), reason: reason.clone() })
+            })
+            .collect()
+    }
+}
+
+/// Replaces the matched passages of a scene with `[redacted]`
+///
+/// # Arguments
+/// * `content` - The scene to redact
+/// * `matches` - The passages to replace; overlapping passages are merged
+pub fn redact(content: &str, matches: &[FilterMatch]) -> String {
+    let mut ranges: Vec<(usize, usize)> = matches.iter().map(|m| (m.start, m.end)).collect();
+    ranges.sort();
+    let mut redacted = String::with_capacity(content.len());
+    let mut position = 0;
+    for (start, end) in ranges {
+        if end <= position {
+            continue;
+        }
+        redacted.push_str(&content[position..start.max(position)]);
+        redacted.push_str(REDACTION);
+        position = end;
+    }
+    redacted.push_str(&content[position..]);
+    redacted
+}
+
+/// Returns the distinct reasons of a set of matches, joined for metadata
+fn reasons(matches: &[FilterMatch]) -> String {
+    let mut reasons: Vec<&str> = matches.iter().map(|m| m.reason.as_str()).collect();
+    reasons.sort();
+    reasons.dedup();
+    reasons.join(", ")
+}
+
+/// Outcome of screening a scene
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Screened {
+    /// Reasoning of the kept attempt
+    pub reasoning: String,
+
+    /// Content of the kept attempt, redacted if needed
+    pub content: String,
+
+    /// Number of attempts rejected before this one
+    pub rejections: usize,
+
+    /// What the filters matched in the kept attempt, before redaction
+    pub matched: Option<String>,
+}
+
+impl StoryChain {
+    /// Returns the filters every new scene passes through: the built-in
+    /// keyword filter, when configured, followed by the chain's own
+    pub fn active_filters(&self) -> Result<Vec<std::sync::Arc<dyn ContentFilter>>, StoryChainError> {
+        let mut filters: Vec<std::sync::Arc<dyn ContentFilter>> = Vec::new();
+        if let Some(settings) = &self.settings.content_filter {
+            filters.push(std::sync::Arc::new(KeywordFilter::new(settings)?));
+        }
+        filters.extend(self.content_filters.iter().cloned());
+        Ok(filters)
+    }
+
+    /// Screens a generated scene with the active filters, regenerating it
+    /// from the same prompt or redacting it as the settings ask
+    ///
+    /// # Arguments
+    /// * `ai_provider` - The AI provider that regenerates rejected scenes
+    /// * `prompt` - The prompt the scene was generated from
+    /// * `reasoning` - Reasoning of the generated scene
+    /// * `content` - The generated scene
+    pub(crate) async fn screen_scene(
+        &self,
+        ai_provider: &dyn AIProvider,
+        prompt: &str,
+        reasoning: String,
+        content: String,
+    ) -> Result<Screened, StoryChainError> {
+        let filters = self.active_filters()?;
+        let settings = self.settings.content_filter.clone().unwrap_or_default();
+        let mut screened = Screened { reasoning, content, ..Screened::default() };
+        if filters.is_empty() {
+            return Ok(screened);
+        }
+
+        loop {
+            let mut matches = Vec::new();
+            for filter in &filters {
+                let found = filter.check(&screened.content);
+                if !found.is_empty() {
+                    debug!("The {} matched {} passages", filter.name(), found.len());
+                }
+                matches.extend(found);
+            }
+            if matches.is_empty() {
+                return Ok(screened);
+            }
+
+            let found = reasons(&matches);
+            if settings.action == FilterAction::Reject && screened.rejections < settings.max_retries {
+                info!("Content filters rejected the scene ({}); regenerating", found);
+                self.notify_hooks(|hooks| hooks.node_rejected(self, None, &screened.content, &found));
+                screened.rejections += 1;
+                let retry_prompt = format!(
+                    "{}\n\nYour previous attempt was rejected because it contained unsuitable content ({}). \
+                    Write the scene again without it; the story is for a young audience.",
+                    prompt, found
+                );
+                let generation = self.settings.generation.reseeded(screened.rejections as u64);
+                (screened.reasoning, screened.content) = ai_provider
+                    .generate_with_config(self.settings.system_prompt.as_deref(), &retry_prompt, &generation)
+                    .await?;
+                continue;
+            }
+
+            if settings.action == FilterAction::Reject {
+                warn!("Scene still matched the content filters after {} retries; redacting it", screened.rejections);
+            }
+            screened.content = redact(&screened.content, &matches);
+            screened.matched = Some(found);
+            return Ok(screened);
+        }
+    }
+
+    /// Screens a scene already in the chain, such as the opening scene, and
+    /// replaces it with the regenerated or redacted version
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node to screen
+    /// * `ai_provider` - The AI provider that regenerates rejected scenes
+    /// * `prompt` - The prompt the scene was generated from
+    pub async fn screen_node(
+        &mut self,
+        node_id: &str,
+        ai_provider: &dyn AIProvider,
+        prompt: &str,
+    ) -> Result<(), StoryChainError> {
+        let node = self
+            .nodes
+            .get(node_id)
+            .ok_or_else(|| StoryChainError::InvalidChainOperation(format!("Node {} not found", node_id)))?;
+        let screened = self.screen_scene(ai_provider, prompt, node.reasoning.clone(), node.content.clone()).await?;
+        let node = self.nodes.get_mut(node_id).expect("screened node exists");
+        node.reasoning = screened.reasoning;
+        node.content = screened.content;
+        if screened.rejections > 0 || screened.matched.is_some() {
+            if let Some(provenance) = &mut node.provenance {
+                provenance.revised(crate::provenance::FILTER_REVISION);
+            }
+        }
+        if screened.rejections > 0 {
+            node.metadata.insert(FILTER_REJECTIONS_KEY.to_string(), screened.rejections.to_string());
+        }
+        if let Some(matched) = screened.matched {
+            node.metadata.insert(FILTER_MATCHES_KEY.to_string(), matched);
+        }
+        Ok(())
+    }
+}
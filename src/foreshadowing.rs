@@ -0,0 +1,226 @@
+//! Foreshadowing
+//!
+//! This module weaves hints of planned future beats into the prompts of
+//! earlier scenes. The beats come from a plot outline; each scene generated
+//! within the lead window of a beat is asked to foreshadow it and records the
+//! beat in its `foreshadows` metadata, so the hints can be verified later.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use log::{info, debug, warn};
+use tera::Context;
+use crate::passes::parse_labeled_fields;
+use crate::{AIProvider, StoryChain, StoryChainError};
+
+/// Name of the foreshadowing check prompt template
+pub const FORESHADOWING_CHECK: &str = "foreshadowing_check";
+
+/// Built-in template of the prompt asking whether a scene foreshadows a
+/// planned development
+///
+/// Variables: `development`, the planned beat; `scene`, the earlier scene.
+pub const FORESHADOWING_CHECK_TEMPLATE: &str = r#"A later scene of a story will contain this development: {{ development }}
+
+Does the earlier scene below hint at or foreshadow it, however subtly?
+
+Scene:
+{{ scene }}
+
+IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your reasoning about any hints in the scene.
+</think>
+VERDICT: yes or no
+HINT: The hinting detail, or none"#;
+
+/// Separator between beats in the `foreshadows` node metadata
+const BEAT_SEPARATOR: &str = " | ";
+
+/// A future story development planned for a given scene
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PlannedBeat {
+    /// Scene number (epoch) at which the beat happens
+    pub scene: usize,
+
+    /// What happens in the beat
+    pub description: String,
+}
+
+/// Planned beats and how far ahead of them to start foreshadowing
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ForeshadowingPlan {
+    /// Beats to foreshadow, in scene order
+    pub beats: Vec<PlannedBeat>,
+
+    /// Number of scenes before a beat that carry hints of it
+    pub lead_scenes: usize,
+}
+
+/// Verification result for a single planned beat
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ForeshadowingCheck {
+    /// The beat that was foreshadowed
+    pub beat: PlannedBeat,
+
+    /// IDs of the scenes that were asked to foreshadow the beat
+    pub carrier_ids: Vec<String>,
+
+    /// IDs of the carrier scenes judged to actually hint at the beat
+    pub confirmed_ids: Vec<String>,
+}
+
+impl ForeshadowingPlan {
+    /// Parses planned beats from a plot outline
+    ///
+    /// Every line of the form `Scene 7: The mentor betrays the heroes` (the
+    /// `Scene` prefix is optional, and `Epoch` or `Beat` are also accepted)
+    /// becomes a beat; other lines are ignored.
+    ///
+    /// # Arguments
+    /// * `outline` - The outline text
+    /// * `lead_scenes` - Number of scenes before each beat that carry hints of it
+    pub fn from_outline(outline: &str, lead_scenes: usize) -> Self {
+        let line_pattern = Regex::new(r"(?i)^\W*(?:scene|epoch|beat)?\s*(\d+)\s*[:.)-]\s*(.+)$").unwrap();
+        let mut beats: Vec<PlannedBeat> = outline
+            .lines()
+            .filter_map(|line| {
+                let captures = line_pattern.captures(line.trim())?;
+                Some(PlannedBeat {
+                    scene: captures[1].parse().ok()?,
+                    description: captures[2].trim().to_string(),
+                })
+            })
+            .collect();
+        beats.sort_by_key(|b| b.scene);
+        debug!("Parsed {} planned beats from outline", beats.len());
+
+        Self { beats, lead_scenes }
+    }
+
+    /// Returns the beat planned for the scene at the given position, if any
+    ///
+    /// # Arguments
+    /// * `scene` - Scene number of the scene being generated
+    pub fn planned_beat(&self, scene: usize) -> Option<&PlannedBeat> {
+        self.beats.iter().find(|b| b.scene == scene)
+    }
+
+    /// Returns the beats that a scene at the given position should foreshadow
+    ///
+    /// # Arguments
+    /// * `scene` - Scene number of the scene being generated
+    pub fn beats_to_foreshadow(&self, scene: usize) -> Vec<&PlannedBeat> {
+        self.beats
+            .iter()
+            .filter(|b| b.scene > scene && b.scene - scene <= self.lead_scenes)
+            .collect()
+    }
+}
+
+impl StoryChain {
+    /// Returns the planned beats the scene following the given node should foreshadow
+    ///
+    /// # Arguments
+    /// * `current_node_id` - ID of the node the next scene will follow
+    pub fn upcoming_beats(&self, current_node_id: &str) -> Vec<&PlannedBeat> {
+        let Some(plan) = &self.settings.foreshadowing else {
+            return Vec::new();
+        };
+        let position = self.nodes_in_order().iter().position(|n| n.id == current_node_id);
+        match position {
+            Some(position) => plan.beats_to_foreshadow(position + 1),
+            None => Vec::new(),
+        }
+    }
+
+    /// Builds the foreshadowing guidance for the scene following the given node
+    ///
+    /// # Arguments
+    /// * `current_node_id` - ID of the node the next scene will follow
+    pub fn foreshadowing_guidance(&self, current_node_id: &str) -> Option<String> {
+        let beats = self.upcoming_beats(current_node_id);
+        if beats.is_empty() {
+            return None;
+        }
+        let descriptions: Vec<&str> = beats.iter().map(|b| b.description.as_str()).collect();
+        Some(format!(
+            "Subtly foreshadow what is coming later without revealing or resolving it, through an image, \
+            a line of dialogue, or a small detail: {}.",
+            descriptions.join("; ")
+        ))
+    }
+
+    /// Returns the beat descriptions recorded as foreshadowed by a node
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node to inspect
+    pub fn foreshadowed_by(&self, node_id: &str) -> Vec<&str> {
+        self.nodes
+            .get(node_id)
+            .and_then(|n| n.metadata.get("foreshadows"))
+            .map(|f| f.split(BEAT_SEPARATOR).collect())
+            .unwrap_or_default()
+    }
+
+    /// Asks the AI whether each scene that was meant to foreshadow a beat
+    /// actually hints at it
+    ///
+    /// The verdict for each carrier scene is stored in its
+    /// `foreshadowing_verified` metadata as the confirmed beat descriptions.
+    ///
+    /// # Arguments
+    /// * `ai_provider` - The AI provider to use for verification
+    pub async fn verify_foreshadowing(
+        &mut self,
+        ai_provider: &dyn AIProvider,
+    ) -> Result<Vec<ForeshadowingCheck>, StoryChainError> {
+        let beats = self.settings.foreshadowing.as_ref().map(|p| p.beats.clone()).unwrap_or_default();
+        info!("Verifying foreshadowing for {} planned beats", beats.len());
+
+        let mut checks = Vec::new();
+        for beat in beats {
+            let carrier_ids: Vec<String> = self
+                .nodes_in_order()
+                .iter()
+                .filter(|n| self.foreshadowed_by(&n.id).contains(&beat.description.as_str()))
+                .map(|n| n.id.clone())
+                .collect();
+
+            let mut confirmed_ids = Vec::new();
+            for node_id in &carrier_ids {
+                let mut context = Context::new();
+                context.insert("development", &beat.description);
+                context.insert("scene", &self.nodes[node_id].content);
+                let prompt = self.render_prompt(FORESHADOWING_CHECK, context)?;
+                let (_, content) = ai_provider.generate(&prompt).await?;
+                let fields = parse_labeled_fields(&content, &["VERDICT", "HINT"]);
+                let confirmed = fields.get("VERDICT").is_some_and(|v| v.to_lowercase().starts_with("yes"));
+
+                if confirmed {
+                    confirmed_ids.push(node_id.clone());
+                    if let Some(node) = self.nodes.get_mut(node_id) {
+                        let verified = node.metadata.entry("foreshadowing_verified".to_string()).or_default();
+                        if !verified.is_empty() {
+                            verified.push_str(BEAT_SEPARATOR);
+                        }
+                        verified.push_str(&beat.description);
+                    }
+                } else {
+                    warn!("Scene {} does not foreshadow: {}", node_id, beat.description);
+                }
+            }
+
+            if carrier_ids.is_empty() {
+                warn!("No scene was asked to foreshadow: {}", beat.description);
+            }
+            checks.push(ForeshadowingCheck { beat, carrier_ids, confirmed_ids });
+        }
+
+        Ok(checks)
+    }
+}
+
+/// Joins beat descriptions for storage in node metadata
+pub(crate) fn join_beats(beats: &[&PlannedBeat]) -> String {
+    beats.iter().map(|b| b.description.as_str()).collect::<Vec<_>>().join(BEAT_SEPARATOR)
+}
@@ -0,0 +1,121 @@
+//! Structural Story Formats
+//!
+//! This module defines structural formats that constrain how every scene is
+//! written, such as letters, diary entries, or transcripts. The selected
+//! format adds instructions to each prompt and is checked against each
+//! generated scene.
+
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Month names used to recognize written-out dates
+const MONTHS: [&str; 12] = [
+    "january", "february", "march", "april", "may", "june", "july", "august", "september",
+    "october", "november", "december",
+];
+
+/// Structural format that every scene of a story must follow
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StoryFormat {
+    /// Conventional narrative prose with no structural constraints
+    #[default]
+    Prose,
+
+    /// Every scene is a dated letter from one character to another
+    Epistolary,
+
+    /// Every scene is a dated diary entry by the narrator
+    Diary,
+
+    /// Every scene is a transcript of speaker-labelled lines
+    Transcript,
+}
+
+impl FromStr for StoryFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "prose" => Ok(StoryFormat::Prose),
+            "epistolary" | "letters" => Ok(StoryFormat::Epistolary),
+            "diary" => Ok(StoryFormat::Diary),
+            "transcript" => Ok(StoryFormat::Transcript),
+            other => Err(format!("Unknown story format: {}", other)),
+        }
+    }
+}
+
+/// Returns true if the line looks like it contains a date
+fn contains_date(line: &str) -> bool {
+    let lower = line.to_lowercase();
+    let numeric_date = regex::Regex::new(r"\d{1,4}[/.-]\d{1,2}[/.-]\d{1,4}").unwrap();
+    MONTHS.iter().any(|m| lower.contains(m)) || numeric_date.is_match(line)
+}
+
+impl StoryFormat {
+    /// Returns the instruction added to prompts, or `None` for plain prose
+    pub fn instructions(&self) -> Option<&'static str> {
+        match self {
+            StoryFormat::Prose => None,
+            StoryFormat::Epistolary => Some(
+                "This story is epistolary. Write the scene as a single letter: begin with the date on \
+                its own line, then a salutation such as \"Dear ...,\", and end with a sign-off and the \
+                writer's name. Convey all events through what the letter writer chooses to tell.",
+            ),
+            StoryFormat::Diary => Some(
+                "This story is told as a diary. Write the scene as a single diary entry that begins with \
+                the date on its own line and is written in the first person by the diarist.",
+            ),
+            StoryFormat::Transcript => Some(
+                "This story is told as a transcript. Write the scene entirely as speaker-labelled lines of \
+                the form \"NAME: words\", with any non-spoken action in [square brackets] on its own line.",
+            ),
+        }
+    }
+
+    /// Checks a generated scene against the format's structural rules
+    ///
+    /// # Arguments
+    /// * `content` - The scene content to check
+    ///
+    /// # Returns
+    /// A list of violations; empty if the scene follows the format
+    pub fn validate(&self, content: &str) -> Vec<String> {
+        let lines: Vec<&str> = content.lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+        let mut issues = Vec::new();
+
+        match self {
+            StoryFormat::Prose => {}
+            StoryFormat::Epistolary | StoryFormat::Diary => {
+                if !lines.iter().take(2).any(|l| contains_date(l)) {
+                    issues.push("scene does not open with a date".to_string());
+                }
+                if *self == StoryFormat::Epistolary
+                    && !lines.iter().take(3).any(|l| {
+                        let lower = l.to_lowercase();
+                        lower.starts_with("dear") || lower.starts_with("to ") || lower.starts_with("my dear")
+                    })
+                {
+                    issues.push("letter has no salutation".to_string());
+                }
+            }
+            StoryFormat::Transcript => {
+                let speaker = regex::Regex::new(r"^[\p{Lu}][\p{Lu}\p{Ll} .'-]{0,40}:").unwrap();
+                let conforming = lines
+                    .iter()
+                    .filter(|l| speaker.is_match(l) || (l.starts_with('[') && l.ends_with(']')))
+                    .count();
+                if lines.is_empty() || conforming * 10 < lines.len() * 8 {
+                    issues.push(format!(
+                        "only {} of {} lines are speaker-labelled or bracketed directions",
+                        conforming,
+                        lines.len()
+                    ));
+                }
+            }
+        }
+
+        issues
+    }
+}
@@ -0,0 +1,285 @@
+//! Fountain Screenplay Export
+//!
+//! This module writes the main storyline as a screenplay in the Fountain
+//! markup language, which screenwriting tools such as Highland, Slugline, and
+//! Fade In open directly. Scenes are converted with heuristics: quoted speech
+//! with a recognizable speaker becomes dialogue and everything else becomes
+//! action. An optional pass asks the AI to reformat each scene instead, and
+//! the export prefers those adaptations where they exist.
+
+use log::{info, debug};
+use regex::Regex;
+use tera::Context;
+use crate::characters::CharacterRegistry;
+use crate::passes::strip_code_fence;
+use crate::{AIProvider, StoryChain, StoryChainError};
+
+/// Node metadata key holding the AI's screenplay adaptation of a scene
+pub const SCREENPLAY_KEY: &str = "screenplay";
+
+/// Name of the screenplay adaptation prompt template
+pub const SCREENPLAY: &str = "screenplay";
+
+/// Built-in template of the screenplay adaptation prompt
+///
+/// Variables: `scene`, the scene to adapt.
+pub const SCREENPLAY_TEMPLATE: &str = r#"Adapt the scene below into screenplay format using Fountain markup. Start with a scene heading such as INT. KITCHEN - NIGHT. Write action in the present tense and keep only what can be seen or heard; put each speaker's name in capitals on its own line, followed by their dialogue, with short parentheticals only where the delivery matters. Keep every line of dialogue from the scene.
+
+Scene:
+{{ scene }}
+
+IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your reasoning about the location, the speakers, and what to show.
+</think>
+Write the Fountain screenplay here, with no title page or extra commentary."#;
+
+/// Verbs that attribute a line of dialogue to its speaker
+const SPEECH_VERBS: &str = "said|says|asked|asks|replied|replies|answered|answers|whispered|whispers|\
+    shouted|shouts|muttered|mutters|murmured|murmurs|called|calls|cried|cries|snapped|snaps|added|adds";
+
+/// Capitalized words before a speech verb that are not speakers
+const NOT_SPEAKERS: [&str; 14] = [
+    "He", "She", "They", "It", "I", "We", "You", "The", "Then", "And", "But", "Someone", "Everyone", "Nobody",
+];
+
+/// A paragraph split into the parts of a dialogue block
+#[derive(Debug)]
+struct DialogueBlock {
+    action_before: Vec<String>,
+    speaker: String,
+    parenthetical: Option<String>,
+    lines: Vec<String>,
+    action_after: Vec<String>,
+}
+
+/// Forces a line to be read as action when Fountain would read it as
+/// something else
+fn escape_action(line: &str) -> String {
+    let upper = line.to_uppercase();
+    let is_markup = ['.', '!', '@', '#', '=', '~', '>', '['].iter().any(|c| line.starts_with(*c))
+        || ["INT.", "EXT.", "INT ", "EXT ", "EST.", "I/E"].iter().any(|p| upper.starts_with(p));
+    let all_caps = line.chars().any(char::is_alphabetic) && !line.chars().any(char::is_lowercase);
+    if is_markup || all_caps {
+        format!("!{}", line)
+    } else {
+        line.to_string()
+    }
+}
+
+/// Splits a paragraph into a dialogue block when it holds quoted speech
+/// attributed to a speaker
+///
+/// The speaker is taken from an attribution such as "Mara said" or "asked
+/// Tom", or else from the first registered name in the narration, and is
+/// written under the registered name so that aliases share one cue.
+fn dialogue_block(paragraph: &str, registry: &CharacterRegistry) -> Option<DialogueBlock> {
+    let quotes = Regex::new(r#""([^"]+)"|“([^”]+)”"#).unwrap();
+    let spans: Vec<_> = quotes.captures_iter(paragraph).collect();
+    if spans.is_empty() {
+        return None;
+    }
+    let lines: Vec<String> = spans
+        .iter()
+        .filter_map(|caps| caps.get(1).or_else(|| caps.get(2)))
+        .map(|m| m.as_str().split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect();
+
+    // The narration around the quotes
+    let mut segments = Vec::new();
+    let mut last = 0;
+    for caps in &spans {
+        let whole = caps.get(0).unwrap();
+        segments.push(&paragraph[last..whole.start()]);
+        last = whole.end();
+    }
+    segments.push(&paragraph[last..]);
+
+    let name = r"([A-Z][a-z]+(?: [A-Z][a-z]+)?)";
+    let before_verb = Regex::new(&format!(r"\b{}\s+(?:{})\b", name, SPEECH_VERBS)).unwrap();
+    let after_verb = Regex::new(&format!(r"\b(?:{})\s+{}", SPEECH_VERBS, name)).unwrap();
+    let pronoun = Regex::new(&format!(r"\b(?:[Hh]e|[Ss]he|[Tt]hey|I|[Ww]e)\s+(?:{})\b", SPEECH_VERBS)).unwrap();
+
+    let mut speaker = None;
+    let mut attribution = None;
+    for (index, segment) in segments.iter().enumerate() {
+        let found = before_verb
+            .captures_iter(segment)
+            .chain(after_verb.captures_iter(segment))
+            .find(|caps| !NOT_SPEAKERS.contains(&&caps[1]));
+        if let Some(caps) = found {
+            speaker = Some(caps[1].to_string());
+            attribution = Some((index, caps.get(0).unwrap().as_str().to_string()));
+            break;
+        }
+    }
+    if speaker.is_none() {
+        let narration = segments.concat();
+        speaker = registry
+            .characters
+            .iter()
+            .flat_map(|c| std::iter::once(&c.name).chain(&c.aliases))
+            .find(|n| narration.contains(n.as_str()))
+            .cloned();
+        // "she said" only names the speaker together with a known character
+        if speaker.is_some() {
+            attribution = segments.iter().enumerate().find_map(|(index, segment)| {
+                pronoun.find(segment).map(|m| (index, m.as_str().to_string()))
+            });
+        }
+    }
+    let speaker = speaker?;
+    let speaker = registry.lookup(&speaker).map(|c| c.name.clone()).unwrap_or(speaker);
+
+    let mut block = DialogueBlock {
+        action_before: Vec::new(),
+        speaker: speaker.to_uppercase(),
+        parenthetical: None,
+        lines,
+        action_after: Vec::new(),
+    };
+    for (index, segment) in segments.iter().enumerate() {
+        let is_attribution = attribution.as_ref().is_some_and(|(i, _)| *i == index);
+        let text = match &attribution {
+            Some((i, phrase)) if *i == index => segment.replacen(phrase.as_str(), "", 1),
+            _ => segment.to_string(),
+        };
+        let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+        let text = text
+            .trim_start_matches(|c: char| c.is_whitespace() || matches!(c, ',' | ';' | ':' | '.' | '!' | '?' | '—' | '-'))
+            .trim_end_matches(|c: char| c.is_whitespace() || matches!(c, ',' | ';' | ':' | '—' | '-'));
+        if !text.chars().any(char::is_alphanumeric) {
+            continue;
+        }
+        if is_attribution && text.starts_with(char::is_lowercase) && block.parenthetical.is_none() {
+            block.parenthetical = Some(text.trim_end_matches('.').to_string());
+        } else if index == 0 {
+            block.action_before.push(text.to_string());
+        } else {
+            block.action_after.push(text.to_string());
+        }
+    }
+    Some(block)
+}
+
+/// Converts the prose of a scene into Fountain action and dialogue
+fn scene_to_fountain(content: &str, registry: &CharacterRegistry) -> String {
+    let mut elements = Vec::new();
+    for paragraph in content.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+        match dialogue_block(paragraph, registry) {
+            Some(block) => {
+                elements.extend(block.action_before.iter().map(|a| escape_action(a)));
+                let mut dialogue = block.speaker;
+                if let Some(parenthetical) = block.parenthetical {
+                    dialogue.push_str(&format!("\n({})", parenthetical));
+                }
+                dialogue.push('\n');
+                dialogue.push_str(&block.lines.join(" "));
+                elements.push(dialogue);
+                elements.extend(block.action_after.iter().map(|a| escape_action(a)));
+            }
+            None => {
+                let action = paragraph.split_whitespace().collect::<Vec<_>>().join(" ");
+                elements.push(escape_action(&action));
+            }
+        }
+    }
+    elements.join("\n\n")
+}
+
+/// Forces a scene heading for a location that does not start with INT. or EXT.
+fn scene_heading(location: &str) -> String {
+    let heading = location.split_whitespace().collect::<Vec<_>>().join(" ").to_uppercase();
+    if ["INT.", "EXT.", "INT ", "EXT ", "EST.", "I/E"].iter().any(|p| heading.starts_with(p)) {
+        heading
+    } else {
+        format!(".{}", heading)
+    }
+}
+
+impl StoryChain {
+    /// Asks the AI to reformat each scene of the main storyline as a
+    /// screenplay and stores the result in the scene's `screenplay` metadata
+    ///
+    /// Scenes that already have an adaptation are skipped.
+    ///
+    /// # Arguments
+    /// * `ai_provider` - The AI provider to use for generation
+    ///
+    /// # Returns
+    /// The number of scenes that were adapted
+    pub async fn adapt_to_screenplay(&mut self, ai_provider: &dyn AIProvider) -> Result<usize, StoryChainError> {
+        let pending: Vec<String> = self
+            .nodes_in_reading_order()
+            .into_iter()
+            .filter(|node| !node.metadata.contains_key(SCREENPLAY_KEY))
+            .map(|node| node.id.clone())
+            .collect();
+        info!("Adapting {} scenes to screenplay format", pending.len());
+
+        for node_id in &pending {
+            let mut context = Context::new();
+            context.insert("scene", self.nodes[node_id].content.trim());
+            let prompt = self.render_prompt(SCREENPLAY, context)?;
+
+            let (_, screenplay) = ai_provider.generate(&prompt).await?;
+            debug!("Adapted {} to screenplay format", node_id);
+            self.nodes
+                .get_mut(node_id)
+                .expect("scene exists")
+                .metadata
+                .insert(SCREENPLAY_KEY.to_string(), strip_code_fence(&screenplay).to_string());
+        }
+        Ok(pending.len())
+    }
+
+    /// Renders the main storyline as a Fountain screenplay
+    ///
+    /// A title page comes first when the chain has a title. Chapters become
+    /// sections, nested under act sections when the story has acts. A scene with a `screenplay` adaptation is used as written;
+    /// other scenes get a heading from their `location` metadata, or a
+    /// numbered heading, and their prose is converted with the dialogue
+    /// heuristics, using the character registry to recognize speakers.
+    pub fn to_fountain(&self) -> String {
+        let mut fountain = String::new();
+        if let Some(title) = self.metadata.get("title") {
+            fountain.push_str(&format!("Title: {}\n", title.trim()));
+            if let Some(author) = self.metadata.get("author") {
+                fountain.push_str(&format!("Author: {}\n", author.trim()));
+            }
+            fountain.push_str("\n===\n\n");
+        }
+        for (index, node) in self.nodes_in_reading_order().into_iter().enumerate() {
+            if let Some((_, chapter, act)) = self.chapter_starting_at(&node.id) {
+                if let Some(act) = act {
+                    fountain.push_str(&format!("# {}\n\n", act.trim()));
+                }
+                let level = if chapter.act.is_some() { "##" } else { "#" };
+                fountain.push_str(&format!("{} {}\n\n", level, chapter.title.trim()));
+            }
+            match node.metadata.get(SCREENPLAY_KEY) {
+                Some(screenplay) => fountain.push_str(screenplay.trim()),
+                None => {
+                    let location = node.metadata.get("location").cloned().unwrap_or_else(|| format!("Scene {}", index + 1));
+                    fountain.push_str(&scene_heading(&location));
+                    fountain.push_str("\n\n");
+                    fountain.push_str(&scene_to_fountain(&node.content, &self.character_registry));
+                }
+            }
+            fountain.push_str("\n\n");
+        }
+        fountain.truncate(fountain.trim_end().len());
+        fountain.push('\n');
+        fountain
+    }
+
+    /// Exports the main storyline as a Fountain screenplay
+    ///
+    /// # Arguments
+    /// * `path` - The path where the Fountain file should be saved
+    pub fn export_to_fountain(&self, path: &str) -> Result<(), StoryChainError> {
+        info!("Exporting story to Fountain: {}", path);
+        crate::files::write_atomic(path, self.to_fountain())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,102 @@
+//! Generation Parameters
+//!
+//! This module defines the sampling parameters sent along with a prompt,
+//! such as temperature, token limits, and stop sequences. A chain keeps its
+//! parameters in its settings and passes them with every request; providers
+//! that talk to a backend supporting them translate them into its options,
+//! while the others ignore them. Unset parameters fall back to the provider's
+//! own defaults and then to the model's. With a `seed`, backends that support
+//! it sample deterministically, so the same premise and seed reproduce the
+//! same story.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+/// Sampling parameters for a generation request
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct GenerationConfig {
+    /// Sampling temperature; higher values give more varied prose
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f64>,
+
+    /// Nucleus sampling probability mass
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f64>,
+
+    /// Number of most likely tokens sampled from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<usize>,
+
+    /// Maximum number of tokens to generate
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<usize>,
+
+    /// Penalty for repeating recent tokens; 1.0 disables it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repeat_penalty: Option<f64>,
+
+    /// Sequences that end the generation when produced
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+
+    /// Size of the context window in tokens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub num_ctx: Option<usize>,
+
+    /// Sampling seed; the same seed and prompt reproduce the same response
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+impl GenerationConfig {
+    /// Returns whether no parameter is set
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Returns these parameters with unset ones taken from a base configuration
+    ///
+    /// # Arguments
+    /// * `base` - The parameters to fall back to
+    pub fn merged_over(&self, base: &GenerationConfig) -> GenerationConfig {
+        GenerationConfig {
+            temperature: self.temperature.or(base.temperature),
+            top_p: self.top_p.or(base.top_p),
+            top_k: self.top_k.or(base.top_k),
+            max_tokens: self.max_tokens.or(base.max_tokens),
+            repeat_penalty: self.repeat_penalty.or(base.repeat_penalty),
+            stop: if self.stop.is_empty() { base.stop.clone() } else { self.stop.clone() },
+            num_ctx: self.num_ctx.or(base.num_ctx),
+            seed: self.seed.or(base.seed),
+        }
+    }
+
+    /// Returns these parameters with the seed, if any, advanced by an offset,
+    /// so that requests repeating a prompt still draw different responses
+    ///
+    /// # Arguments
+    /// * `offset` - How far to advance the seed
+    pub fn reseeded(&self, offset: u64) -> GenerationConfig {
+        GenerationConfig { seed: self.seed.map(|seed| seed.wrapping_add(offset)), ..self.clone() }
+    }
+
+    /// Converts the parameters into Ollama's `options` object
+    pub fn to_ollama_options(&self) -> Value {
+        let mut options = Map::new();
+        let mut set = |key: &str, value: Option<Value>| {
+            if let Some(value) = value {
+                options.insert(key.to_string(), value);
+            }
+        };
+        set("temperature", self.temperature.map(|v| json!(v)));
+        set("top_p", self.top_p.map(|v| json!(v)));
+        set("top_k", self.top_k.map(|v| json!(v)));
+        set("num_predict", self.max_tokens.map(|v| json!(v)));
+        set("repeat_penalty", self.repeat_penalty.map(|v| json!(v)));
+        set("stop", (!self.stop.is_empty()).then(|| json!(self.stop)));
+        set("num_ctx", self.num_ctx.map(|v| json!(v)));
+        set("seed", self.seed.map(|v| json!(v)));
+        Value::Object(options)
+    }
+}
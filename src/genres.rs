@@ -0,0 +1,178 @@
+//! Genre Presets
+//!
+//! This module bundles genre conventions into selectable presets. A preset
+//! carries style directives and vocabulary hints that are added to every
+//! prompt, plus structural beats that are suggested as the story reaches the
+//! point where the genre expects them.
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use crate::StoryChain;
+
+/// Half-width, as a fraction of the story, of the window in which a beat is suggested
+const BEAT_WINDOW: f64 = 0.1;
+
+/// A bundle of genre conventions applied to the prompt pipeline
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum GenrePreset {
+    /// Hard-boiled crime with a cynical narrator and moral compromise
+    Noir,
+
+    /// Low-violence puzzle mystery in a close-knit community
+    CozyMystery,
+
+    /// Secondary-world epic with quests, magic, and ancient powers
+    HighFantasy,
+
+    /// Science fiction grounded in plausible physics and engineering
+    HardSf,
+}
+
+impl FromStr for GenrePreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalized: String = s
+            .trim()
+            .trim_matches(['"', '\''])
+            .to_lowercase()
+            .chars()
+            .filter(|c| c.is_alphanumeric())
+            .collect();
+        match normalized.as_str() {
+            "noir" => Ok(GenrePreset::Noir),
+            "cozy" | "cozymystery" | "cosymystery" => Ok(GenrePreset::CozyMystery),
+            "fantasy" | "highfantasy" => Ok(GenrePreset::HighFantasy),
+            "hardsf" | "hardscifi" | "hardsciencefiction" => Ok(GenrePreset::HardSf),
+            _ => Err(format!("Unknown genre preset: {}", s.trim())),
+        }
+    }
+}
+
+impl GenrePreset {
+    /// Reads a preset from a premise artifact
+    ///
+    /// Uses a `genre_preset:` entry when present, and otherwise the `genre:`
+    /// entry if it names a preset exactly.
+    ///
+    /// # Arguments
+    /// * `premise` - The premise text
+    pub fn from_premise(premise: &str) -> Option<Self> {
+        let entry = Regex::new(r"(?m)^\s*(genre_preset|genre)\s*:\s*([^#\n]+)").unwrap();
+        let mut entries: Vec<(String, String)> = entry
+            .captures_iter(premise)
+            .map(|c| (c[1].to_string(), c[2].to_string()))
+            .collect();
+        entries.sort_by_key(|(key, _)| key != "genre_preset");
+        entries.iter().find_map(|(_, value)| value.parse().ok())
+    }
+
+    /// Returns the style directives for the genre
+    pub fn style_directives(&self) -> &'static str {
+        match self {
+            GenrePreset::Noir => {
+                "Write in a hard-boiled noir style: terse sentences, a world-weary voice, rain-slick streets and \
+                smoke-filled rooms, and characters who are all compromised in some way."
+            }
+            GenrePreset::CozyMystery => {
+                "Write in a cozy mystery style: a warm, gently humorous voice, a tight-knit small community, \
+                fair-play clues the reader can follow, and no graphic violence on the page."
+            }
+            GenrePreset::HighFantasy => {
+                "Write in a high fantasy style: an elevated but readable voice, a vividly realized secondary \
+                world with its own history, and magic that has rules and costs."
+            }
+            GenrePreset::HardSf => {
+                "Write in a hard science fiction style: technology and physics must be plausible and \
+                consistent, problems are solved through ingenuity, and wonder comes from real science."
+            }
+        }
+    }
+
+    /// Returns words and images characteristic of the genre
+    pub fn vocabulary_hints(&self) -> &'static [&'static str] {
+        match self {
+            GenrePreset::Noir => &["neon", "gin", "alibi", "dame", "precinct", "shadow", "double-cross"],
+            GenrePreset::CozyMystery => &["bakery", "vicar", "garden fete", "knitting circle", "teacup", "alibi"],
+            GenrePreset::HighFantasy => &["citadel", "oath", "rune", "wyrm", "ancient", "prophecy", "banner"],
+            GenrePreset::HardSf => &["delta-v", "orbit", "reactor", "airlock", "radiation", "telemetry"],
+        }
+    }
+
+    /// Returns the structural beats of the genre with the fraction of the
+    /// story at which each is expected
+    pub fn beats(&self) -> &'static [(f64, &'static str)] {
+        match self {
+            GenrePreset::Noir => &[
+                (0.1, "A client or a corpse draws the protagonist into the case."),
+                (0.35, "The protagonist is warned off, roughed up, or framed."),
+                (0.6, "A betrayal reveals that someone trusted was lying all along."),
+                (0.85, "The confrontation costs the protagonist something they cannot get back."),
+                (1.0, "Justice, if any, is partial and bittersweet."),
+            ],
+            GenrePreset::CozyMystery => &[
+                (0.1, "The body is discovered and the amateur sleuth is drawn in."),
+                (0.3, "Suspects are introduced, each with a motive and a secret."),
+                (0.55, "A second incident or red herring raises the stakes."),
+                (0.8, "A small overlooked clue makes everything click."),
+                (1.0, "The culprit is unmasked and the community is restored."),
+            ],
+            GenrePreset::HighFantasy => &[
+                (0.1, "The call to adventure disrupts the hero's ordinary world."),
+                (0.3, "The hero crosses the threshold into the wider world and gathers companions."),
+                (0.55, "A grievous loss or defeat tests the fellowship."),
+                (0.8, "The hero confronts the dark power at great personal cost."),
+                (1.0, "The hero returns, changed, to a world made new."),
+            ],
+            GenrePreset::HardSf => &[
+                (0.1, "A technical anomaly or discovery sets the story in motion."),
+                (0.35, "A cascading failure exposes the limits of the systems."),
+                (0.6, "The obvious fix fails because of a physical constraint."),
+                (0.85, "An ingenious solution is found under hard deadlines."),
+                (1.0, "The consequences of the discovery are made clear."),
+            ],
+        }
+    }
+
+    /// Returns the beat the genre expects at the given point of the story, if any
+    ///
+    /// # Arguments
+    /// * `progress` - Fraction of the story completed, from 0.0 to 1.0
+    pub fn beat_at(&self, progress: f64) -> Option<&'static str> {
+        self.beats()
+            .iter()
+            .filter(|(at, _)| (progress - at).abs() <= BEAT_WINDOW)
+            .min_by(|a, b| (progress - a.0).abs().total_cmp(&(progress - b.0).abs()))
+            .map(|(_, beat)| *beat)
+    }
+
+    /// Returns the genre instructions for the opening scene
+    pub fn opening_instructions(&self) -> String {
+        format!(
+            "{} Draw on genre vocabulary and imagery such as: {}.",
+            self.style_directives(),
+            self.vocabulary_hints().join(", ")
+        )
+    }
+}
+
+impl StoryChain {
+    /// Builds the genre guidance for the scene generated at the given epoch
+    ///
+    /// # Arguments
+    /// * `current_epoch` - Epoch of the scene being generated
+    /// * `total_epochs` - Total number of epochs planned
+    pub fn genre_guidance(&self, current_epoch: usize, total_epochs: usize) -> Vec<String> {
+        let Some(genre) = self.settings.genre else {
+            return Vec::new();
+        };
+        let mut notes = vec![genre.opening_instructions()];
+        let progress = current_epoch as f64 / total_epochs.max(1) as f64;
+        if let Some(beat) = genre.beat_at(progress) {
+            notes.push(format!("Genre beat due around this point of the story: {}", beat));
+        }
+        notes
+    }
+}
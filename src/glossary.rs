@@ -0,0 +1,150 @@
+//! Glossary and Lore Index
+//!
+//! This module extracts the invented terms, places, and concepts of a story
+//! into a glossary. Each entry records the scene where the term first appears
+//! so that exports can link back to it from the appendix.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use log::{info, debug};
+use tera::Context;
+use crate::artifacts::{Artifact, ArtifactType};
+use crate::passes::{parse_labeled_fields, split_blocks, SCENE_EXCERPT_CHARS};
+use crate::{AIProvider, StoryChain, StoryChainError};
+
+/// Name of the glossary prompt template
+pub const GLOSSARY: &str = "glossary";
+
+/// Built-in template of the glossary prompt
+///
+/// Variables: `story`, excerpts of every scene.
+pub const GLOSSARY_TEMPLATE: &str = r#"You are compiling the glossary for the story below. List every invented or story-specific term a reader might need explained: places, organizations, objects, customs, and concepts. Do not list ordinary words or the characters themselves.
+
+Story:
+{{ story }}
+IMPORTANT: Format your response EXACTLY as follows, repeating the block for each term:
+<think>
+Your reasoning about which terms need explaining.
+</think>
+TERM: The term exactly as written in the story
+CATEGORY: place, organization, object, custom, or concept
+DEFINITION: A one or two sentence in-world definition"#;
+
+/// A single glossary entry
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct GlossaryEntry {
+    /// The term as it appears in the story
+    pub term: String,
+
+    /// Kind of term, e.g. place, concept, organization, or object
+    pub category: String,
+
+    /// Short in-world definition of the term
+    pub definition: String,
+
+    /// ID of the first node in reading order that mentions the term
+    pub first_node_id: Option<String>,
+}
+
+impl StoryChain {
+    /// Returns the ID of the first node in reading order whose content mentions the term
+    ///
+    /// # Arguments
+    /// * `term` - The term to look for (matched case-insensitively)
+    pub fn first_mention(&self, term: &str) -> Option<String> {
+        let needle = term.to_lowercase();
+        self.nodes_in_reading_order()
+            .into_iter()
+            .find(|node| node.content.to_lowercase().contains(&needle))
+            .map(|node| node.id.clone())
+    }
+
+    /// Extracts invented terms, places, and concepts into glossary entries
+    ///
+    /// The entries are returned sorted alphabetically; store them in
+    /// `StoryChain::glossary` to render them as an appendix in exports.
+    ///
+    /// # Arguments
+    /// * `ai_provider` - The AI provider to use for extraction
+    pub async fn generate_glossary(
+        &self,
+        ai_provider: &dyn AIProvider,
+    ) -> Result<Vec<GlossaryEntry>, StoryChainError> {
+        info!("Generating glossary from story chain");
+
+        let mut context = Context::new();
+        context.insert("story", &self.condensed_text(SCENE_EXCERPT_CHARS));
+        let prompt = self.render_prompt(GLOSSARY, context)?;
+
+        let (_, content) = ai_provider.generate(&prompt).await?;
+
+        let mut entries: Vec<GlossaryEntry> = split_blocks(&content, "TERM")
+            .iter()
+            .filter_map(|block| {
+                let fields = parse_labeled_fields(block, &["TERM", "CATEGORY", "DEFINITION"]);
+                let term = fields.get("TERM")?.clone();
+                Some(GlossaryEntry {
+                    first_node_id: self.first_mention(&term),
+                    category: fields.get("CATEGORY").cloned().unwrap_or_default().to_lowercase(),
+                    definition: fields.get("DEFINITION").cloned().unwrap_or_default(),
+                    term,
+                })
+            })
+            .collect();
+
+        entries.sort_by_key(|e| e.term.to_lowercase());
+        entries.dedup_by_key(|e| e.term.to_lowercase());
+        debug!("Extracted {} glossary entries", entries.len());
+        Ok(entries)
+    }
+
+    /// Converts the chain's glossary into an artifact
+    ///
+    /// # Arguments
+    /// * `id` - Unique identifier for the artifact
+    pub fn glossary_artifact(&self, id: String) -> Result<Artifact, StoryChainError> {
+        let content = self
+            .glossary
+            .iter()
+            .map(|e| format!("{} ({}): {}", e.term, e.category, e.definition))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let mut metadata = HashMap::new();
+        metadata.insert("entries".to_string(), serde_json::to_string(&self.glossary)?);
+
+        Ok(Artifact {
+            id,
+            content,
+            artifact_type: ArtifactType::Glossary,
+            metadata,
+            tags: Vec::new(),
+        })
+    }
+
+    /// Renders the glossary as a markdown appendix linking each term to the
+    /// scene where it first appears
+    pub fn glossary_markdown(&self) -> String {
+        let scene_numbers: HashMap<&str, usize> = self
+            .nodes_in_reading_order()
+            .iter()
+            .enumerate()
+            .map(|(index, node)| (node.id.as_str(), index + 1))
+            .collect();
+
+        let mut content = String::from("## Appendix: Glossary\n\n");
+        for entry in &self.glossary {
+            content.push_str(&format!("**{}**", entry.term));
+            if !entry.category.is_empty() {
+                content.push_str(&format!(" *({})*", entry.category));
+            }
+            content.push_str(&format!(" — {}", entry.definition));
+            let scene = entry.first_node_id.as_deref().and_then(|id| scene_numbers.get(id));
+            if let Some(scene) = scene {
+                content.push_str(&format!(" First appears in [Scene {}](#scene-{}).", scene, scene));
+            }
+            content.push_str("\n\n");
+        }
+        content
+    }
+}
@@ -0,0 +1,190 @@
+//! Graph Exports
+//!
+//! This module renders the topology of a chain as a graph, for visualizing
+//! branches and merges. Graphviz DOT files can be rendered with `dot -Tsvg`,
+//! and Mermaid flowcharts can be pasted into markdown documents. Each node is
+//! labeled with its ID and the start of its scene, and nodes can be colored by
+//! a metadata key such as `pov`.
+
+use log::info;
+use std::collections::HashMap;
+use crate::{StoryChain, StoryChainError, StoryNode};
+
+/// Metadata key nodes are colored by when none is given
+pub const DEFAULT_COLOR_KEY: &str = "pov";
+
+/// Maximum number of characters of a scene shown in its node label
+const LABEL_CHARS: usize = 40;
+
+/// Fill colors assigned to metadata values, in order of first appearance
+const PALETTE: [&str; 12] = [
+    "#8dd3c7", "#ffffb3", "#bebada", "#fb8072", "#80b1d3", "#fdb462",
+    "#b3de69", "#fccde5", "#d9d9d9", "#bc80bd", "#ccebc5", "#ffed6f",
+];
+
+/// Returns the start of a scene, shortened to fit a node label
+fn label_excerpt(content: &str) -> String {
+    let content = content.split_whitespace().collect::<Vec<_>>().join(" ");
+    if content.chars().count() > LABEL_CHARS {
+        let excerpt: String = content.chars().take(LABEL_CHARS).collect();
+        format!("{}...", excerpt.trim_end())
+    } else {
+        content
+    }
+}
+
+/// Escapes text for a quoted DOT string
+fn escape_dot(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Escapes text for a quoted Mermaid label
+fn escape_mermaid(text: &str) -> String {
+    text.replace('"', "#quot;")
+}
+
+/// Turns a node ID into an identifier Mermaid accepts
+fn mermaid_id(id: &str) -> String {
+    id.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' }).collect()
+}
+
+/// Assigns a palette color to every value of a metadata key, in order of
+/// first appearance
+fn value_colors(nodes: &[&StoryNode], color_by: &str) -> Vec<(String, &'static str)> {
+    let mut colors: Vec<(String, &'static str)> = Vec::new();
+    for value in nodes.iter().filter_map(|n| n.metadata.get(color_by)) {
+        if !colors.iter().any(|(v, _)| v == value) {
+            colors.push((value.clone(), PALETTE[colors.len() % PALETTE.len()]));
+        }
+    }
+    colors
+}
+
+impl StoryChain {
+    /// Returns every node in storyline order: the main storyline first, then
+    /// the alternative branches, then any nodes not reachable from the root
+    pub(crate) fn nodes_in_graph_order(&self) -> Vec<&StoryNode> {
+        let mut ordered: Vec<&StoryNode> = Vec::new();
+        for line in self.storylines() {
+            for node in line {
+                if !ordered.iter().any(|n| n.id == node.id) {
+                    ordered.push(node);
+                }
+            }
+        }
+        let mut unreachable: Vec<&StoryNode> = self.nodes.values().filter(|n| !ordered.iter().any(|o| o.id == n.id)).collect();
+        unreachable.sort_by(|a, b| a.id.cmp(&b.id));
+        ordered.extend(unreachable);
+        ordered
+    }
+
+    /// Renders the chain as a Graphviz DOT digraph
+    ///
+    /// Main-storyline links are solid and links to alternative branches are
+    /// dashed. Nodes are filled by the value of the given metadata key, with
+    /// a legend listing the values.
+    ///
+    /// # Arguments
+    /// * `color_by` - Metadata key to color nodes by
+    pub fn to_dot(&self, color_by: &str) -> String {
+        let nodes = self.nodes_in_graph_order();
+        let legend = value_colors(&nodes, color_by);
+        let colors: HashMap<&str, &str> = legend.iter().map(|(value, fill)| (value.as_str(), *fill)).collect();
+
+        let mut dot = String::from("digraph story {\n    rankdir=TB;\n");
+        dot.push_str("    node [shape=box, style=\"rounded,filled\", fillcolor=\"#ffffff\", fontname=\"Helvetica\"];\n\n");
+        for node in &nodes {
+            let label = format!("{}\\n{}", escape_dot(&node.id), escape_dot(&label_excerpt(&node.content)));
+            let fill = node.metadata.get(color_by).and_then(|v| colors.get(v.as_str()));
+            match fill {
+                Some(fill) => dot.push_str(&format!("    \"{}\" [label=\"{}\", fillcolor=\"{}\"];\n", escape_dot(&node.id), label, fill)),
+                None => dot.push_str(&format!("    \"{}\" [label=\"{}\"];\n", escape_dot(&node.id), label)),
+            }
+        }
+        dot.push('\n');
+        for node in &nodes {
+            for (index, successor) in node.successors.iter().filter(|id| self.nodes.contains_key(*id)).enumerate() {
+                let style = if index == 0 { "" } else { " [style=dashed]" };
+                dot.push_str(&format!("    \"{}\" -> \"{}\"{};\n", escape_dot(&node.id), escape_dot(successor), style));
+            }
+        }
+
+        // Legend of the colored metadata values
+        if !legend.is_empty() {
+            dot.push_str(&format!("\n    subgraph cluster_legend {{\n        label=\"{}\";\n", escape_dot(color_by)));
+            for (index, (value, fill)) in legend.iter().enumerate() {
+                dot.push_str(&format!(
+                    "        \"legend_{}\" [label=\"{}\", fillcolor=\"{}\"];\n",
+                    index,
+                    escape_dot(value),
+                    fill
+                ));
+            }
+            dot.push_str("    }\n");
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    /// Renders the chain as a Mermaid flowchart
+    ///
+    /// Main-storyline links are solid and links to alternative branches are
+    /// dotted. Nodes are filled by the value of the given metadata key, with
+    /// one class per value named after it in a comment.
+    ///
+    /// # Arguments
+    /// * `color_by` - Metadata key to color nodes by
+    pub fn to_mermaid(&self, color_by: &str) -> String {
+        let nodes = self.nodes_in_graph_order();
+        let colors = value_colors(&nodes, color_by);
+
+        let mut mermaid = String::from("flowchart TD\n");
+        for node in &nodes {
+            mermaid.push_str(&format!(
+                "    {}[\"{}<br>{}\"]\n",
+                mermaid_id(&node.id),
+                escape_mermaid(&node.id),
+                escape_mermaid(&label_excerpt(&node.content))
+            ));
+        }
+        for node in &nodes {
+            for (index, successor) in node.successors.iter().filter(|id| self.nodes.contains_key(*id)).enumerate() {
+                let arrow = if index == 0 { "-->" } else { "-.->" };
+                mermaid.push_str(&format!("    {} {} {}\n", mermaid_id(&node.id), arrow, mermaid_id(successor)));
+            }
+        }
+        for (index, (value, fill)) in colors.iter().enumerate() {
+            let members: Vec<String> = nodes
+                .iter()
+                .filter(|n| n.metadata.get(color_by) == Some(value))
+                .map(|n| mermaid_id(&n.id))
+                .collect();
+            mermaid.push_str(&format!("    %% {}: {}\n", color_by, value.replace('\n', " ")));
+            mermaid.push_str(&format!("    classDef {}_{} fill:{}\n", mermaid_id(color_by), index, fill));
+            mermaid.push_str(&format!("    class {} {}_{}\n", members.join(","), mermaid_id(color_by), index));
+        }
+        mermaid
+    }
+
+    /// Exports the chain topology as a Graphviz DOT file
+    ///
+    /// # Arguments
+    /// * `path` - The path where the DOT file should be saved
+    /// * `color_by` - Metadata key to color nodes by
+    pub fn export_to_dot(&self, path: &str, color_by: &str) -> Result<(), StoryChainError> {
+        info!("Exporting story graph to DOT: {}", path);
+        crate::files::write_atomic(path, self.to_dot(color_by))?;
+        Ok(())
+    }
+
+    /// Exports the chain topology as a Mermaid flowchart file
+    ///
+    /// # Arguments
+    /// * `path` - The path where the Mermaid file should be saved
+    /// * `color_by` - Metadata key to color nodes by
+    pub fn export_to_mermaid(&self, path: &str, color_by: &str) -> Result<(), StoryChainError> {
+        info!("Exporting story graph to Mermaid: {}", path);
+        crate::files::write_atomic(path, self.to_mermaid(color_by))?;
+        Ok(())
+    }
+}
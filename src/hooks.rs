@@ -0,0 +1,81 @@
+//! Pipeline Hooks
+//!
+//! This module lets applications observe the generation pipeline without
+//! forking its loop. A `PipelineHooks` implementation added to a chain's
+//! `hooks` is told when a prompt is built, when a scene is generated or
+//! rejected (by the quality gate, the content filters, or the author),
+//! when a checkpoint is saved, and when a `StoryRunner` finishes, for
+//! custom logging, user interfaces, or integrations. Every method does
+//! nothing by default, so an implementation only overrides what it needs.
+
+use std::path::Path;
+use std::sync::Arc;
+use crate::StoryChain;
+
+/// Observes the generation pipeline
+pub trait PipelineHooks: Send + Sync {
+    /// Called when a prompt has been built, before it is sent to the model
+    ///
+    /// # Arguments
+    /// * `template` - Name of the prompt template, such as `continuation`
+    /// * `prompt` - The prompt
+    fn prompt_built(&self, _template: &str, _prompt: &str) {}
+
+    /// Called when a scene has been generated and added to the chain
+    ///
+    /// # Arguments
+    /// * `chain` - The chain
+    /// * `node_id` - ID of the new node
+    fn node_generated(&self, _chain: &StoryChain, _node_id: &str) {}
+
+    /// Called when a scene is rejected, before it is generated again or removed
+    ///
+    /// # Arguments
+    /// * `chain` - The chain
+    /// * `node_id` - ID of the scene, if it had been added to the chain
+    /// * `content` - The rejected scene
+    /// * `reason` - Why the scene was rejected
+    fn node_rejected(&self, _chain: &StoryChain, _node_id: Option<&str>, _content: &str, _reason: &str) {}
+
+    /// Called when a checkpoint of the chain has been saved
+    ///
+    /// # Arguments
+    /// * `chain` - The chain
+    /// * `epoch` - The epoch the checkpoint was saved after
+    /// * `path` - The checkpoint file
+    fn checkpoint_saved(&self, _chain: &StoryChain, _epoch: usize, _path: &Path) {}
+
+    /// Called when a run has generated all its epochs
+    ///
+    /// # Arguments
+    /// * `chain` - The finished chain
+    fn run_finished(&self, _chain: &StoryChain) {}
+}
+
+impl std::fmt::Debug for dyn PipelineHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "PipelineHooks")
+    }
+}
+
+impl StoryChain {
+    /// Adds hooks observing the chain's generation
+    ///
+    /// # Arguments
+    /// * `hooks` - The hooks to add; hooks the chain already has are not added again
+    pub fn add_hooks(&mut self, hooks: Arc<dyn PipelineHooks>) {
+        if !self.hooks.iter().any(|existing| Arc::ptr_eq(existing, &hooks)) {
+            self.hooks.push(hooks);
+        }
+    }
+
+    /// Calls every hook of the chain
+    ///
+    /// # Arguments
+    /// * `notify` - Called with each hook
+    pub(crate) fn notify_hooks(&self, notify: impl Fn(&dyn PipelineHooks)) {
+        for hooks in &self.hooks {
+            notify(hooks.as_ref());
+        }
+    }
+}
@@ -0,0 +1,370 @@
+//! HTML Exports
+//!
+//! This module renders story chains as standalone HTML files. The static
+//! export is a styled page of every scene with a table of contents and links
+//! between branches; the interactive export presents one scene at a time and
+//! lets the reader choose between the successor branches of each decision node.
+
+use log::info;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::path::Path;
+use crate::illustrations::{IMAGE_KEY, IMAGE_PROMPT_KEY};
+use crate::{MarkdownOptions, StoryChain, StoryChainError, StoryNode};
+
+/// Maximum length of a choice label derived from a successor's opening text
+const CHOICE_LABEL_CHARS: usize = 80;
+
+/// Escapes text for safe inclusion in HTML element content and attributes
+///
+/// # Arguments
+/// * `text` - The text to escape
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Converts plain scene text into HTML paragraphs
+///
+/// # Arguments
+/// * `text` - Scene text with paragraphs separated by blank lines
+pub fn paragraphs_to_html(text: &str) -> String {
+    text.split("\n\n")
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| format!("<p>{}</p>", escape_html(p).replace('\n', "<br>")))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Returns the figure showing a scene's illustration, if it has one
+///
+/// # Arguments
+/// * `node` - The scene
+/// * `page` - Path of the HTML file, which the image's path is made relative to
+fn illustration_html(node: &StoryNode, page: &str) -> String {
+    let Some(image) = node.metadata.get(IMAGE_KEY) else {
+        return String::new();
+    };
+    let directory = Path::new(page).parent().unwrap_or(Path::new(""));
+    let src = Path::new(image).strip_prefix(directory).unwrap_or(Path::new(image));
+    format!(
+        "<figure class=\"illustration\"><img src=\"{}\" alt=\"{}\"></figure>\n",
+        escape_html(&src.to_string_lossy()),
+        escape_html(node.metadata.get(IMAGE_PROMPT_KEY).map(String::as_str).unwrap_or_default())
+    )
+}
+
+/// Returns the text offered to the reader for choosing a successor node
+///
+/// Uses the node's `choice` metadata when present and otherwise the opening
+/// sentence of its content.
+///
+/// # Arguments
+/// * `node` - The successor node the choice leads to
+pub fn choice_label(node: &StoryNode) -> String {
+    if let Some(choice) = node.metadata.get("choice") {
+        return choice.clone();
+    }
+    let first_sentence = node
+        .content
+        .split_inclusive(['.', '!', '?'])
+        .next()
+        .unwrap_or(&node.content)
+        .trim();
+    if first_sentence.chars().count() > CHOICE_LABEL_CHARS {
+        let truncated: String = first_sentence.chars().take(CHOICE_LABEL_CHARS).collect();
+        format!("{}...", truncated.trim_end())
+    } else {
+        first_sentence.to_string()
+    }
+}
+
+/// Styles of the static HTML export
+const STORY_STYLE: &str = "\
+body { max-width: 40em; margin: 3em auto; padding: 0 1em; font-family: Georgia, serif; line-height: 1.6; color: #222; }
+h1, .logline { text-align: center; }
+.logline { font-style: italic; }
+nav.toc { margin: 2em 0; padding: 1em 1.5em; background: #f6f3ee; border-radius: 4px; }
+nav.toc ul { list-style: none; padding-left: 1em; }
+nav.toc .act { font-variant: small-caps; font-weight: bold; margin-top: 1em; }
+nav.toc .chapter { font-weight: bold; margin-top: 0.5em; }
+h2.act { text-align: center; font-variant: small-caps; }
+section.scene { margin: 2.5em 0; padding-top: 1em; border-top: 1px solid #ddd; }
+.pov { font-style: italic; color: #555; }
+.recap { margin: 1em 0; padding: 0.5em 1em; border-left: 3px solid #ccc; color: #555; }
+figure.illustration { margin: 1.5em 0; text-align: center; }
+figure.illustration img { max-width: 100%; border-radius: 4px; }
+details.reasoning { margin: 1em 0; padding: 0.5em 1em; background: #f4f4f4; border-radius: 4px; font-size: 0.9em; color: #444; }
+details.reasoning summary { cursor: pointer; }
+nav.branches { margin: 1em 0; padding: 0.5em 1em; border-left: 3px solid #b08d57; }
+nav.branches ul { margin: 0.3em 0; }
+.rejoin { font-style: italic; }
+a { color: #6b4f1d; }
+";
+
+/// A passage as embedded in the interactive HTML bundle
+#[derive(Serialize)]
+struct Passage {
+    html: String,
+    choices: Vec<(String, String)>,
+}
+
+impl StoryChain {
+    /// Exports the chain as a self-contained interactive "choose your own
+    /// adventure" HTML page
+    ///
+    /// Readers start at the root and, at every node with more than one
+    /// successor, pick which branch to follow. Nodes with a single successor
+    /// offer a "Continue" link, and leaf nodes end the story. Illustrated
+    /// scenes show their image above the text.
+    ///
+    /// # Arguments
+    /// * `path` - The path where the HTML file should be saved
+    pub fn export_to_interactive_html(&self, path: &str) -> Result<(), StoryChainError> {
+        info!("Exporting interactive story to HTML: {}", path);
+
+        let passages: HashMap<&str, Passage> = self
+            .nodes
+            .values()
+            .map(|node| {
+                let successors: Vec<&StoryNode> = node
+                    .successor_ids()
+                    .iter()
+                    .filter_map(|id| self.nodes.get(*id))
+                    .collect();
+                let choices = match successors.as_slice() {
+                    [single] => vec![(single.id.clone(), "Continue".to_string())],
+                    many => many.iter().map(|n| (n.id.clone(), choice_label(n))).collect(),
+                };
+                let html = format!("{}{}", illustration_html(node, path), paragraphs_to_html(&node.content));
+                (node.id.as_str(), Passage { html, choices })
+            })
+            .collect();
+
+        // Keep the embedded JSON from closing the script element early
+        let data = serde_json::to_string(&passages)?.replace("</", "<\\/");
+        let title = escape_html(self.metadata.get("title").map(String::as_str).unwrap_or("Generated Story"));
+
+        let html = format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+<style>
+body {{ max-width: 40em; margin: 3em auto; padding: 0 1em; font-family: Georgia, serif; line-height: 1.6; color: #222; }}
+h1 {{ text-align: center; }}
+figure.illustration {{ margin: 1.5em 0; text-align: center; }}
+figure.illustration img {{ max-width: 100%; border-radius: 4px; }}
+#choices {{ list-style: none; padding: 0; }}
+#choices button {{ width: 100%; margin: 0.4em 0; padding: 0.7em; font: inherit; text-align: left; cursor: pointer; border: 1px solid #888; border-radius: 4px; background: #f6f3ee; }}
+#choices button:hover {{ background: #ebe4d8; }}
+.end {{ text-align: center; font-style: italic; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+<div id="passage"></div>
+<ul id="choices"></ul>
+<p><a href="" id="restart">Start over</a></p>
+<script type="application/json" id="story-data">{data}</script>
+<script>
+const passages = JSON.parse(document.getElementById("story-data").textContent);
+function show(id) {{
+  const passage = passages[id];
+  document.getElementById("passage").innerHTML = passage.html;
+  const list = document.getElementById("choices");
+  list.innerHTML = "";
+  if (passage.choices.length === 0) {{
+    list.innerHTML = '<li class="end">The End</li>';
+  }}
+  for (const [target, label] of passage.choices) {{
+    const button = document.createElement("button");
+    button.textContent = label;
+    button.onclick = () => {{ show(target); window.scrollTo(0, 0); }};
+    const item = document.createElement("li");
+    item.appendChild(button);
+    list.appendChild(item);
+  }}
+}}
+show({root});
+</script>
+</body>
+</html>
+"#,
+            title = title,
+            data = data,
+            root = serde_json::to_string(&self.root_node_id)?,
+        );
+
+        crate::files::write_atomic(path, html)?;
+        Ok(())
+    }
+
+    /// Exports the chain as a standalone, styled HTML page
+    ///
+    /// The page opens with a table of contents and shows every scene of the
+    /// main storyline in reading order, each with its reasoning in a
+    /// collapsible section. Scenes with several successors link to each of
+    /// them, and the alternative branches follow the main storyline.
+    /// Illustrated scenes show their image above the text, linked relative to
+    /// the page.
+    ///
+    /// # Arguments
+    /// * `path` - The path where the HTML file should be saved
+    pub fn export_to_html(&self, path: &str) -> Result<(), StoryChainError> {
+        self.export_to_html_with_options(path, &MarkdownOptions::default())
+    }
+
+    /// Exports the chain as a standalone, styled HTML page, with a recap of
+    /// the previous chapter at each chapter head when `chapter_recaps` is set
+    ///
+    /// # Arguments
+    /// * `path` - The path where the HTML file should be saved
+    /// * `options` - Controls which optional sections are rendered
+    pub fn export_to_html_with_options(&self, path: &str, options: &MarkdownOptions) -> Result<(), StoryChainError> {
+        info!("Exporting story to HTML: {}", path);
+
+        // Label every scene: main-storyline scenes by their position, branch scenes within their branch
+        let main = self.nodes_in_reading_order();
+        let mut labels: HashMap<&str, String> = main
+            .iter()
+            .enumerate()
+            .map(|(index, node)| (node.id.as_str(), format!("Scene {}", index + 1)))
+            .collect();
+        let mut branches: Vec<(String, String, Vec<&StoryNode>, Option<&str>)> = Vec::new();
+        for (index, line) in self.storylines().iter().enumerate().skip(1) {
+            // Each branch is shown from where it leaves the storylines already shown. A custom
+            // reading order can show a scene of the branch early, so the branch may continue after it
+            let mut scenes = 0;
+            let mut position = 0;
+            while position < line.len() {
+                let shown = line[position..].iter().take_while(|n| labels.contains_key(n.id.as_str())).count();
+                let fork = position + shown;
+                let Some(parent) = fork.checked_sub(1).map(|i| line[i].id.as_str()).filter(|_| fork < line.len()) else {
+                    break;
+                };
+                let (anchor, heading) = match scenes {
+                    0 => (format!("branch-{}", index), format!("Branch {} (after {})", index, labels[parent])),
+                    _ => (format!("branch-{}-{}", index, fork), format!("Branch {}, continued (after {})", index, labels[parent])),
+                };
+                let nodes: Vec<&StoryNode> =
+                    line[fork..].iter().take_while(|n| !labels.contains_key(n.id.as_str())).copied().collect();
+                for node in &nodes {
+                    scenes += 1;
+                    labels.insert(&node.id, format!("Branch {}, scene {}", index, scenes));
+                }
+                position = fork + nodes.len();
+                let rejoin = line.get(position).map(|n| n.id.as_str());
+                branches.push((anchor, heading, nodes, rejoin));
+            }
+        }
+        let link = |id: &str| {
+            let label = labels.get(id).cloned().unwrap_or_else(|| id.to_string());
+            format!("<a href=\"#{}\">{}</a>", escape_html(id), escape_html(&label))
+        };
+
+        // Header
+        let title = escape_html(self.metadata.get("title").map(String::as_str).unwrap_or("Generated Story"));
+        let mut body = format!("<h1>{}</h1>\n", title);
+        if let Some(logline) = self.metadata.get("logline") {
+            body.push_str(&format!("<p class=\"logline\">{}</p>\n", escape_html(logline)));
+        }
+        if let Some(blurb) = self.metadata.get("blurb") {
+            body.push_str(&paragraphs_to_html(blurb));
+            body.push('\n');
+        }
+
+        // Table of contents
+        body.push_str("<nav class=\"toc\">\n<h2>Contents</h2>\n<ul>\n");
+        for node in &main {
+            if let Some((_, chapter, act)) = self.chapter_starting_at(&node.id) {
+                if let Some(act) = act {
+                    body.push_str(&format!("<li class=\"act\">{}</li>\n", escape_html(act)));
+                }
+                body.push_str(&format!("<li class=\"chapter\">{}</li>\n", escape_html(&chapter.title)));
+            }
+            body.push_str(&format!("<li>{}</li>\n", link(&node.id)));
+        }
+        for (anchor, heading, _, _) in &branches {
+            body.push_str(&format!("<li><a href=\"#{}\">{}</a></li>\n", anchor, escape_html(heading)));
+        }
+        body.push_str("</ul>\n</nav>\n");
+
+        // Scenes, with links to every successor where the story branches;
+        // headings nest as in the markdown export
+        let (act_level, chapter_level, scene_level) = self.heading_levels();
+        let scene = |node: &StoryNode, level: usize| {
+            let mut html = format!("<section class=\"scene\" id=\"{}\">\n", escape_html(&node.id));
+            if let Some((chapter_index, chapter, act)) = self.chapter_starting_at(&node.id) {
+                if let Some(act) = act {
+                    html.push_str(&format!("<h{0} class=\"act\">{1}</h{0}>\n", act_level, escape_html(act)));
+                }
+                html.push_str(&format!("<h{0}>{1}</h{0}>\n", chapter_level, escape_html(&chapter.title)));
+                if let Some(recap) = self.chapter_recap(chapter_index).filter(|_| options.chapter_recaps) {
+                    html.push_str(&format!("<p class=\"recap\"><em>Previously:</em> {}</p>\n", escape_html(recap)));
+                }
+            }
+            html.push_str(&format!("<h{0}>{1}</h{0}>\n", level, escape_html(&labels[node.id.as_str()])));
+            if let Some(pov) = node.metadata.get("pov") {
+                html.push_str(&format!("<p class=\"pov\">{}</p>\n", escape_html(pov)));
+            }
+            html.push_str(&illustration_html(node, path));
+            html.push_str(&paragraphs_to_html(&node.content));
+            html.push_str("\n<details class=\"reasoning\">\n<summary>AI's Reasoning</summary>\n");
+            html.push_str(&paragraphs_to_html(&node.reasoning));
+            html.push_str("\n</details>\n");
+            let successors: Vec<&StoryNode> = node.successors.iter().filter_map(|id| self.nodes.get(id)).collect();
+            if successors.len() > 1 {
+                html.push_str("<nav class=\"branches\">\n<p>The story branches here:</p>\n<ul>\n");
+                for successor in successors {
+                    html.push_str(&format!(
+                        "<li>{}: {}</li>\n",
+                        link(&successor.id),
+                        escape_html(&choice_label(successor))
+                    ));
+                }
+                html.push_str("</ul>\n</nav>\n");
+            }
+            html.push_str("</section>\n");
+            html
+        };
+        for node in &main {
+            body.push_str(&scene(node, scene_level));
+        }
+        if !branches.is_empty() {
+            body.push_str("<h2>Alternative Branches</h2>\n");
+        }
+        for (anchor, heading, nodes, rejoin) in &branches {
+            body.push_str(&format!("<h3 id=\"{}\">{}</h3>\n", anchor, escape_html(heading)));
+            for node in nodes {
+                body.push_str(&scene(node, 4));
+            }
+            // Merged branches stop where they rejoin a storyline already shown
+            if let Some(rejoin) = rejoin {
+                body.push_str(&format!("<p class=\"rejoin\">Rejoins at {}.</p>\n", link(rejoin)));
+            }
+        }
+
+        let html = format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+            <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+            <title>{}</title>\n<style>\n{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+            title, STORY_STYLE, body
+        );
+        crate::files::write_atomic(path, html)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,344 @@
+//! Illustration Briefs
+//!
+//! This module produces image-generation prompts for a story: one for the
+//! cover and one per chapter. The prompts are stored as artifacts so users can
+//! pipe them into their image tools of choice. With an `ImageProvider`, the
+//! scenes can also be illustrated directly: each scene gets a prompt of its
+//! own, the image is saved next to the story, and its path is stored on the
+//! node for the HTML exports.
+
+use std::collections::HashMap;
+use std::path::Path;
+use base64::Engine;
+use serde_json::json;
+use log::{info, debug, error};
+use tera::Context;
+use crate::artifacts::{Artifact, ArtifactType};
+use crate::passes::{parse_labeled_fields, SCENE_EXCERPT_CHARS};
+use crate::{AIProvider, StoryChain, StoryChainError};
+
+/// Node metadata key holding the path of the scene's illustration
+pub const IMAGE_KEY: &str = "image";
+
+/// Node metadata key holding the prompt the scene's illustration was generated from
+pub const IMAGE_PROMPT_KEY: &str = "image_prompt";
+
+/// Image size requested unless another is configured
+pub const DEFAULT_IMAGE_SIZE: &str = "1024x1024";
+
+/// Trait defining the interface for image-generation models
+#[async_trait::async_trait]
+pub trait ImageProvider: Send + Sync {
+    /// Generates an image
+    ///
+    /// # Arguments
+    /// * `prompt` - Description of the image
+    /// * `negative_prompt` - Things the image must avoid, if any
+    ///
+    /// # Returns
+    /// The bytes of the image file or an error
+    async fn generate_image(&self, prompt: &str, negative_prompt: Option<&str>) -> Result<Vec<u8>, StoryChainError>;
+
+    /// Returns the file extension of the images, without the dot
+    fn extension(&self) -> &str {
+        "png"
+    }
+}
+
+/// Implementation of ImageProvider for servers with an OpenAI-compatible
+/// `/v1/images/generations` endpoint
+pub struct HttpImageProvider {
+    /// HTTP client used for requests
+    client: reqwest::Client,
+
+    /// URL of the image endpoint, e.g. `https://api.openai.com/v1/images/generations`
+    url: String,
+
+    /// Name of the image model
+    model: String,
+
+    /// Size of the images, e.g. `1024x1024`
+    size: String,
+
+    /// API key sent as a bearer token
+    api_key: Option<String>,
+}
+
+impl HttpImageProvider {
+    /// Creates a new HttpImageProvider requesting images of the default size
+    ///
+    /// # Arguments
+    /// * `url` - URL of the image endpoint
+    /// * `model` - Name of the image model
+    pub fn new(url: &str, model: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.to_string(),
+            model,
+            size: DEFAULT_IMAGE_SIZE.to_string(),
+            api_key: None,
+        }
+    }
+
+    /// Sets the size of the images, e.g. `1792x1024`
+    pub fn with_size(mut self, size: String) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Sets an API key sent as a bearer token
+    pub fn with_api_key(mut self, api_key: String) -> Self {
+        self.api_key = Some(api_key);
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl ImageProvider for HttpImageProvider {
+    async fn generate_image(&self, prompt: &str, negative_prompt: Option<&str>) -> Result<Vec<u8>, StoryChainError> {
+        debug!("Requesting an image from {} for model: {}", self.url, self.model);
+        // The endpoint has no negative prompt, so the things to avoid are spelled out
+        let prompt = match negative_prompt {
+            Some(negative_prompt) => format!("{}\n\nAvoid: {}", prompt, negative_prompt),
+            None => prompt.to_string(),
+        };
+        let mut request = self.client.post(&self.url).json(&json!({
+            "model": self.model,
+            "prompt": prompt,
+            "size": self.size,
+            "n": 1,
+            "response_format": "b64_json",
+        }));
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request.send().await.map_err(|e| {
+            error!("Image request failed: {}", e);
+            StoryChainError::AIServerError(format!("Image request failed: {}", e))
+        })?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            error!("Image request failed with {}: {}", status, text);
+            return Err(StoryChainError::AIServerError(format!("Image request failed with {}: {}", status, text)));
+        }
+        let body: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| StoryChainError::AIServerError(format!("Invalid image response: {}", e)))?;
+        let encoded = body["data"][0]["b64_json"]
+            .as_str()
+            .ok_or_else(|| StoryChainError::AIServerError("Image response has no image".to_string()))?;
+        base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .map_err(|e| StoryChainError::AIServerError(format!("Invalid image in response: {}", e)))
+    }
+}
+
+/// Name of the cover brief prompt template
+pub const COVER_BRIEF: &str = "cover_brief";
+
+/// Built-in template of the cover brief prompt
+///
+/// Variables: `title`, the book title; `story`, excerpts of every scene.
+pub const COVER_BRIEF_TEMPLATE: &str = r#"You are an art director commissioning the cover for the book "{{ title }}". Read the scene excerpts below and describe a cover image that captures the story's genre, tone, and central conflict without spoiling the ending. Leave room for the title.
+
+Story:
+{{ story }}
+
+IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your reasoning about the most striking visual for this material.
+</think>
+PROMPT: A single-paragraph image-generation prompt describing subject, composition, lighting, palette, and art style
+NEGATIVE PROMPT: Comma-separated things the image must avoid
+BRIEF: Two or three sentences for a human illustrator explaining what the image must convey"#;
+
+/// Name of the chapter illustration brief prompt template
+pub const INTERIOR_BRIEF: &str = "interior_brief";
+
+/// Built-in template of the chapter illustration brief prompt, also used
+/// per scene when the story has no chapters
+///
+/// Variables: `title`, the book title; `label`, the chapter title or scene
+/// number; `text`, excerpts of its scenes.
+pub const INTERIOR_BRIEF_TEMPLATE: &str = r#"You are an art director commissioning an interior illustration for {{ label }} of the book "{{ title }}". Choose the single most visually striking moment in the text below and describe it.
+
+{{ label }}:
+{{ text }}
+
+IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your reasoning about the most striking visual for this material.
+</think>
+PROMPT: A single-paragraph image-generation prompt describing subject, composition, lighting, palette, and art style
+NEGATIVE PROMPT: Comma-separated things the image must avoid
+BRIEF: Two or three sentences for a human illustrator explaining what the image must convey"#;
+
+/// Name of the scene illustration prompt template
+pub const SCENE_BRIEF: &str = "scene_brief";
+
+/// Built-in template of the prompt an illustrated scene's image prompt is written from
+///
+/// Variables: `title`, the book title; `scene`, the beginning of the scene.
+pub const SCENE_BRIEF_TEMPLATE: &str = r#"You are an art director commissioning an illustration for a scene of the book "{{ title }}". Choose the single most visually striking moment in the scene below and describe it.
+
+Scene:
+{{ scene }}
+
+IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your reasoning about the most striking visual for this material.
+</think>
+PROMPT: A single-paragraph image-generation prompt describing subject, composition, lighting, palette, and art style
+NEGATIVE PROMPT: Comma-separated things the image must avoid
+BRIEF: Two or three sentences for a human illustrator explaining what the image must convey"#;
+
+impl StoryChain {
+    /// Sends a brief request, rendered from one of the brief templates, to
+    /// the provider and wraps the answer in an artifact
+    async fn request_brief(
+        &self,
+        ai_provider: &dyn AIProvider,
+        id: String,
+        template: &str,
+        context: Context,
+        mut metadata: HashMap<String, String>,
+    ) -> Result<Artifact, StoryChainError> {
+        let (_, content) = ai_provider.generate(&self.render_prompt(template, context)?).await?;
+        let fields = parse_labeled_fields(&content, &["PROMPT", "NEGATIVE PROMPT", "BRIEF"]);
+        debug!("Parsed illustration brief {}: {:?}", id, fields);
+
+        let prompt = fields.get("PROMPT").cloned().ok_or_else(|| {
+            StoryChainError::InvalidReasoningFormat(format!("Illustration brief {} did not contain a PROMPT field", id))
+        })?;
+        for (label, key) in [("NEGATIVE PROMPT", "negative_prompt"), ("BRIEF", "brief")] {
+            if let Some(value) = fields.get(label) {
+                metadata.insert(key.to_string(), value.clone());
+            }
+        }
+
+        Ok(Artifact {
+            id,
+            content: prompt,
+            artifact_type: ArtifactType::IllustrationBrief,
+            metadata,
+            tags: Vec::new(),
+        })
+    }
+
+    /// Generates image-generation prompts for the cover and for each chapter
+    ///
+    /// When the chain has no chapters, one illustration is requested per scene.
+    /// Each artifact's content is the image prompt; the negative prompt and the
+    /// illustrator brief are stored in its metadata.
+    ///
+    /// # Arguments
+    /// * `ai_provider` - The AI provider to use for generation
+    pub async fn generate_illustration_briefs(
+        &self,
+        ai_provider: &dyn AIProvider,
+    ) -> Result<Vec<Artifact>, StoryChainError> {
+        info!("Generating cover and illustration briefs");
+        let title = self.metadata.get("title").cloned().unwrap_or_else(|| "Untitled".to_string());
+
+        let mut metadata = HashMap::new();
+        metadata.insert("kind".to_string(), "cover".to_string());
+        let mut context = Context::new();
+        context.insert("title", &title);
+        context.insert("story", &self.condensed_text(SCENE_EXCERPT_CHARS));
+        let mut briefs = vec![self.request_brief(ai_provider, "cover_prompt".to_string(), COVER_BRIEF, context, metadata).await?];
+
+        // Illustrate each chapter, or each scene when no chapters are defined
+        let groups: Vec<(String, Vec<String>)> = if self.chapters.is_empty() {
+            self.nodes_in_reading_order()
+                .iter()
+                .enumerate()
+                .map(|(index, node)| (format!("Scene {}", index + 1), vec![node.id.clone()]))
+                .collect()
+        } else {
+            self.chapters.iter().map(|c| (c.title.clone(), c.node_ids.clone())).collect()
+        };
+
+        for (index, (label, node_ids)) in groups.into_iter().enumerate() {
+            let text: String = node_ids
+                .iter()
+                .filter_map(|id| self.nodes.get(id))
+                .map(|node| node.content.chars().take(SCENE_EXCERPT_CHARS * 2).collect::<String>())
+                .collect::<Vec<_>>()
+                .join("\n\n");
+
+            let mut metadata = HashMap::new();
+            metadata.insert("kind".to_string(), "interior".to_string());
+            metadata.insert("label".to_string(), label.clone());
+            metadata.insert("node_ids".to_string(), node_ids.join(","));
+
+            let mut context = Context::new();
+            context.insert("title", &title);
+            context.insert("label", &label);
+            context.insert("text", &text);
+            briefs.push(
+                self.request_brief(ai_provider, format!("illustration_{}", index + 1), INTERIOR_BRIEF, context, metadata)
+                    .await?,
+            );
+        }
+
+        info!("Generated {} illustration briefs", briefs.len());
+        Ok(briefs)
+    }
+
+    /// Illustrates every scene with an image-generation model
+    ///
+    /// A prompt is written for each scene, the image is saved in the
+    /// directory as `<node id>.<extension>`, and its path and prompt are stored
+    /// in the node's `image` and `image_prompt` metadata, where the HTML
+    /// exports pick them up. Scenes whose image is already on disk are
+    /// skipped, so an interrupted run can be repeated.
+    ///
+    /// # Arguments
+    /// * `ai_provider` - The AI provider writing the prompts
+    /// * `images` - The image-generation model
+    /// * `directory` - The directory to save the images in; it is created if missing
+    ///
+    /// # Returns
+    /// The number of scenes illustrated
+    pub async fn illustrate_scenes(
+        &mut self,
+        ai_provider: &dyn AIProvider,
+        images: &dyn ImageProvider,
+        directory: &str,
+    ) -> Result<usize, StoryChainError> {
+        std::fs::create_dir_all(directory)?;
+        let title = self.metadata.get("title").cloned().unwrap_or_else(|| "Untitled".to_string());
+        let node_ids: Vec<String> = self.nodes_in_reading_order().iter().map(|node| node.id.clone()).collect();
+        let mut illustrated = 0;
+
+        for (index, node_id) in node_ids.iter().enumerate() {
+            let node = &self.nodes[node_id];
+            if node.metadata.get(IMAGE_KEY).is_some_and(|path| Path::new(path).exists()) {
+                debug!("Scene {} is already illustrated", node_id);
+                continue;
+            }
+            info!("Illustrating scene {} of {}", index + 1, node_ids.len());
+            let mut context = Context::new();
+            context.insert("title", &title);
+            context.insert("scene", &node.content.chars().take(SCENE_EXCERPT_CHARS * 2).collect::<String>());
+            let brief = self
+                .request_brief(ai_provider, format!("scene_image_{}", node_id), SCENE_BRIEF, context, HashMap::new())
+                .await?;
+            let image = images
+                .generate_image(&brief.content, brief.metadata.get("negative_prompt").map(String::as_str))
+                .await?;
+            let path = Path::new(directory).join(format!("{}.{}", node_id, images.extension()));
+            crate::files::write_atomic(&path, image)?;
+
+            let node = self.nodes.get_mut(node_id).expect("scene exists");
+            node.metadata.insert(IMAGE_KEY.to_string(), path.to_string_lossy().to_string());
+            node.metadata.insert(IMAGE_PROMPT_KEY.to_string(), brief.content);
+            illustrated += 1;
+        }
+
+        info!("Illustrated {} scenes", illustrated);
+        Ok(illustrated)
+    }
+}
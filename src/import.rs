@@ -0,0 +1,332 @@
+//! Markdown Import
+//!
+//! This module reads the markdown export back into a chain, so that a story
+//! edited by hand in its `.md` file can be re-ingested and generation
+//! continued from the edited text. The title, logline, blurb, and genre lines
+//! of the header, the main storyline with each scene's viewpoint line and
+//! reasoning, its acts and chapters with their recaps, and the alternative
+//! branches are restored; appendices such as the glossary are skipped because
+//! they are derived from the scenes.
+
+use log::{info, debug};
+use regex::Regex;
+use std::collections::{HashMap, HashSet};
+use crate::{Chapter, StoryChain, StoryChainError, StoryNode};
+
+/// Title the markdown export uses for chains without one
+const DEFAULT_TITLE: &str = "Generated Story";
+
+/// A main-storyline scene as read from the export
+struct ImportedScene {
+    content: String,
+    reasoning: String,
+    pov: Option<String>,
+}
+
+/// An alternative branch as read from the export
+struct ImportedBranch {
+    /// "Scene N" or the ID of the branch node the branch leaves from
+    after: String,
+    /// IDs and contents of the branch's scenes
+    scenes: Vec<(String, String)>,
+    /// "Scene N" or the ID of the node the branch rejoins, if it does
+    rejoins: Option<String>,
+}
+
+/// Joins lines and trims the surrounding blank lines
+fn join_trimmed(lines: &[&str]) -> String {
+    lines.join("\n").trim().to_string()
+}
+
+/// Removes the separators and chapter recaps the export places after a section
+fn trim_section_end<'a>(mut lines: &'a [&'a str]) -> &'a [&'a str] {
+    while let Some((last, rest)) = lines.split_last() {
+        let last = last.trim();
+        if last.is_empty() || last == "---" || last.starts_with("> *Previously:*") {
+            lines = rest;
+        } else {
+            break;
+        }
+    }
+    lines
+}
+
+/// Reads the viewpoint line the export writes as an italic line before a scene
+fn pov_line(line: &str) -> Option<String> {
+    let inner = line.trim().strip_prefix('*')?.strip_suffix('*')?;
+    (!inner.is_empty() && !inner.contains('*') && inner.chars().count() <= 40).then(|| inner.to_string())
+}
+
+/// Splits a markdown heading into its level and text
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    let text = line[level..].strip_prefix(' ')?;
+    (level > 0).then(|| (level, text.trim()))
+}
+
+/// Parses the body of a `Scene N` section
+fn parse_scene(lines: &[&str]) -> ImportedScene {
+    let lines = trim_section_end(lines);
+    let (content_lines, reasoning) = match lines.iter().position(|l| l.trim() == "<details>") {
+        Some(start) => {
+            let details = &lines[start + 1..];
+            let body_start = details.iter().position(|l| l.trim_start().starts_with("<summary>")).map_or(0, |i| i + 1);
+            let body_end = details.iter().rposition(|l| l.trim() == "</details>").unwrap_or(details.len());
+            (&lines[..start], join_trimmed(&details[body_start..body_end.max(body_start)]))
+        }
+        None => (lines, String::new()),
+    };
+
+    // A lone italic line before the first paragraph names the viewpoint character
+    let first = content_lines.iter().position(|l| !l.trim().is_empty());
+    let pov = first.and_then(|i| {
+        let is_own_paragraph = content_lines.get(i + 1).is_none_or(|l| l.trim().is_empty());
+        let has_more = content_lines[i + 1..].iter().any(|l| !l.trim().is_empty());
+        pov_line(content_lines[i]).filter(|_| is_own_paragraph && has_more)
+    });
+    let content = match (pov.is_some(), first) {
+        (true, Some(i)) => join_trimmed(&content_lines[i + 1..]),
+        _ => join_trimmed(content_lines),
+    };
+    ImportedScene { content, reasoning, pov }
+}
+
+/// Parses the `## Alternative Branches` section
+fn parse_branches(lines: &[&str]) -> Vec<ImportedBranch> {
+    let branch_heading = Regex::new(r"^### Branch \d+ \(after (.+)\)\s*$").unwrap();
+    let rejoins_line = Regex::new(r"^\*Rejoins at (.+)\.\*\s*$").unwrap();
+
+    let mut branches: Vec<ImportedBranch> = Vec::new();
+    let mut scene: Option<(String, Vec<&str>)> = None;
+    let finish_scene = |scene: &mut Option<(String, Vec<&str>)>, branches: &mut Vec<ImportedBranch>| {
+        if let (Some((id, lines)), Some(branch)) = (scene.take(), branches.last_mut()) {
+            branch.scenes.push((id, join_trimmed(trim_section_end(&lines))));
+        }
+    };
+    for line in lines {
+        if let Some(caps) = branch_heading.captures(line) {
+            finish_scene(&mut scene, &mut branches);
+            branches.push(ImportedBranch { after: caps[1].to_string(), scenes: Vec::new(), rejoins: None });
+        } else if let Some(id) = line.strip_prefix("#### ") {
+            finish_scene(&mut scene, &mut branches);
+            scene = Some((id.trim().to_string(), Vec::new()));
+        } else if let Some(caps) = rejoins_line.captures(line) {
+            finish_scene(&mut scene, &mut branches);
+            if let Some(branch) = branches.last_mut() {
+                branch.rejoins = Some(caps[1].to_string());
+            }
+        } else if let Some((_, lines)) = scene.as_mut() {
+            lines.push(line);
+        }
+    }
+    finish_scene(&mut scene, &mut branches);
+    branches
+}
+
+impl StoryChain {
+    /// Reads a story written by `export_to_markdown` back into a chain
+    ///
+    /// Main-storyline scenes become a chain starting at `root`, keeping their
+    /// viewpoint lines as `pov` metadata and their reasoning when it was
+    /// exported. Alternative branches keep their node IDs, leave from the
+    /// scene they were shown after, and link back to the scene they rejoin.
+    /// Settings and prompt templates are not part of the markdown, so the
+    /// imported chain starts with the defaults.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the markdown file
+    pub fn import_from_markdown(path: &str) -> Result<Self, StoryChainError> {
+        info!("Importing story chain from markdown: {}", path);
+        let markdown = std::fs::read_to_string(path)?;
+        let lines: Vec<&str> = markdown.lines().collect();
+
+        // Split the document into the header and its sections. Acts, chapters,
+        // and scenes nest below the title, while the alternative branches keep
+        // their own subheadings; older exports put an act in a level-one
+        // heading directly before the section it opens
+        let mut sections: Vec<(usize, &str, Vec<&str>)> = Vec::new();
+        let mut header: Vec<&str> = Vec::new();
+        let mut acts: HashMap<usize, &str> = HashMap::new();
+        for (index, line) in lines.iter().enumerate() {
+            let opens_section = || {
+                let next = lines[index + 1..].iter().find(|l| !l.trim().is_empty());
+                next.and_then(|l| heading(l)).is_some_and(|(level, _)| level > 1)
+            };
+            let in_branches = sections.last().is_some_and(|(_, heading, _)| *heading == "Alternative Branches");
+            match heading(line) {
+                Some((level, text)) if level == 2 || (level > 2 && !in_branches) => {
+                    sections.push((level, text, Vec::new()))
+                }
+                Some((1, act)) if opens_section() => {
+                    acts.insert(sections.len(), act);
+                }
+                _ => match sections.last_mut() {
+                    Some((_, _, body)) => body.push(line),
+                    None => header.push(line),
+                },
+            }
+        }
+
+        let scene_heading = Regex::new(r"^Scene \d+$").unwrap();
+        let scenes: Vec<ImportedScene> = sections
+            .iter()
+            .filter(|(_, heading, _)| scene_heading.is_match(heading))
+            .map(|(_, _, body)| parse_scene(body))
+            .collect();
+        let branches: Vec<ImportedBranch> = sections
+            .iter()
+            .filter(|(_, heading, _)| *heading == "Alternative Branches")
+            .flat_map(|(_, _, body)| parse_branches(body))
+            .collect();
+        let Some((opening, rest)) = scenes.split_first() else {
+            return Err(StoryChainError::InvalidChainOperation(format!("{} contains no scenes", path)));
+        };
+
+        let mut chain = StoryChain::new(opening.content.clone(), opening.reasoning.clone());
+        chain.read_markdown_header(&header);
+        let root_id = chain.root_node_id.clone();
+        if let Some(pov) = &opening.pov {
+            chain.nodes.get_mut(&root_id).expect("root exists").metadata.insert("pov".to_string(), pov.clone());
+        }
+
+        // Main-storyline scenes take IDs the branches do not use
+        let reserved: HashSet<&str> = branches.iter().flat_map(|b| b.scenes.iter().map(|(id, _)| id.as_str())).collect();
+        let mut main_ids = vec![root_id];
+        let mut counter = 1;
+        for scene in rest {
+            let id = loop {
+                let id = format!("node_{}", counter);
+                counter += 1;
+                if !reserved.contains(id.as_str()) {
+                    break id;
+                }
+            };
+            let previous = main_ids.last().expect("the main storyline has a root").clone();
+            chain.link_imported(&previous, &id, scene.content.clone(), scene.reasoning.clone())?;
+            if let Some(pov) = &scene.pov {
+                chain.nodes.get_mut(&id).expect("scene exists").metadata.insert("pov".to_string(), pov.clone());
+            }
+            main_ids.push(id);
+        }
+
+        // Branches refer to main scenes by number and to branch scenes by ID
+        let resolve = |label: &str| -> Option<String> {
+            match label.strip_prefix("Scene ").and_then(|n| n.parse::<usize>().ok()) {
+                Some(number) => main_ids.get(number.checked_sub(1)?).cloned(),
+                None => Some(label.to_string()),
+            }
+        };
+        let resolved: Vec<(Option<String>, Option<String>)> =
+            branches.iter().map(|b| (resolve(&b.after), b.rejoins.as_deref().and_then(resolve))).collect();
+        for (branch, (after, rejoins)) in branches.iter().zip(resolved) {
+            let Some(mut previous) = after.filter(|id| chain.nodes.contains_key(id)) else {
+                return Err(StoryChainError::InvalidChainOperation(format!(
+                    "{}: branch after {} leaves from an unknown scene",
+                    path, branch.after
+                )));
+            };
+            for (id, content) in &branch.scenes {
+                chain.link_imported(&previous, id, content.clone(), String::new())?;
+                previous = id.clone();
+            }
+            if let Some(rejoins) = rejoins.filter(|id| chain.nodes.contains_key(id)) {
+                chain.nodes.get_mut(&previous).expect("branch scene exists").successors.push(rejoins.clone());
+                chain.nodes.get_mut(&rejoins).expect("rejoined scene exists").predecessors.push(previous);
+            }
+        }
+
+        // A section heading right before a scene starts a chapter, which
+        // runs until the next chapter starts; a section above the chapter
+        // heading is the act it opens
+        let mut starts: Vec<(usize, Chapter)> = Vec::new();
+        let mut scene_count = 0;
+        for (index, (level, heading, body)) in sections.iter().enumerate() {
+            if scene_heading.is_match(heading) {
+                scene_count += 1;
+                continue;
+            }
+            let starts_chapter = sections.get(index + 1).is_some_and(|(_, next, _)| scene_heading.is_match(next));
+            if *heading == "Alternative Branches" || !starts_chapter {
+                continue;
+            }
+            if let Some(recap) = body.iter().find_map(|l| l.trim().strip_prefix("> *Previously:*")) {
+                if let Some((_, previous)) = starts.last_mut() {
+                    previous.metadata.insert("summary".to_string(), recap.trim().to_string());
+                }
+            }
+            let act_section = index
+                .checked_sub(1)
+                .map(|i| &sections[i])
+                .filter(|(act_level, act, _)| act_level < level && !scene_heading.is_match(act))
+                .map(|(_, act, _)| *act);
+            let act = match acts.get(&index).copied().or(act_section) {
+                Some(act) => Some(act.to_string()),
+                None => starts.last().and_then(|(_, previous)| previous.act.clone()),
+            };
+            starts.push((scene_count, Chapter { act, ..Chapter::new(heading.to_string(), Vec::new()) }));
+        }
+        let ends: Vec<usize> = starts.iter().skip(1).map(|(start, _)| *start).chain([main_ids.len()]).collect();
+        for ((start, mut chapter), end) in starts.into_iter().zip(ends) {
+            chapter.node_ids = main_ids[start.min(end)..end].to_vec();
+            chain.chapters.push(chapter);
+        }
+
+        info!("Imported {} nodes in {} chapters", chain.nodes.len(), chain.chapters.len());
+        Ok(chain)
+    }
+
+    /// Restores the chain metadata shown in the header of the markdown export
+    fn read_markdown_header(&mut self, header: &[&str]) {
+        let mut fields: HashMap<&str, String> = HashMap::new();
+        let mut blurb: Vec<&str> = Vec::new();
+        for line in header.iter().map(|l| l.trim()) {
+            if let Some(title) = line.strip_prefix("# ") {
+                if title.trim() != DEFAULT_TITLE {
+                    fields.insert("title", title.trim().to_string());
+                }
+            } else if let Some(logline) = line.strip_prefix("> ") {
+                fields.insert("logline", logline.trim().to_string());
+            } else if let Some(genres) = line.strip_prefix("**Genres:**") {
+                fields.insert("genre_tags", genres.trim().to_string());
+            } else if let Some(warnings) = line.strip_prefix("**Content warnings:**") {
+                fields.insert("content_warnings", warnings.trim().to_string());
+            } else if line == "---" || (line.starts_with("*Generated on ") && line.ends_with('*')) {
+                continue;
+            } else {
+                blurb.push(line);
+            }
+        }
+        let blurb = join_trimmed(&blurb);
+        if !blurb.is_empty() {
+            fields.insert("blurb", blurb);
+        }
+        for (key, value) in fields {
+            debug!("Restored {} from the markdown header", key);
+            self.metadata.insert(key.to_string(), value);
+        }
+    }
+
+    /// Adds an imported scene after another node
+    fn link_imported(&mut self, previous: &str, id: &str, content: String, reasoning: String) -> Result<(), StoryChainError> {
+        if self.nodes.contains_key(id) {
+            return Err(StoryChainError::InvalidChainOperation(format!("scene {} appears more than once", id)));
+        }
+        self.nodes.get_mut(previous).expect("previous scene exists").successors.push(id.to_string());
+        self.nodes.insert(
+            id.to_string(),
+            StoryNode {
+                id: id.to_string(),
+                content,
+                reasoning,
+                predecessors: vec![previous.to_string()],
+                successors: Vec::new(),
+                metadata: HashMap::new(),
+                feedback: Vec::new(),
+                scene_card: None,
+                embedding: None,
+                provenance: None,
+            },
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,117 @@
+//! Ink Export
+//!
+//! This module exports branching chains as scripts in inkle's Ink language,
+//! which inklecate compiles for the Ink runtimes of game engines such as Unity
+//! and Godot. Every node becomes a knot, a single successor becomes a divert,
+//! and several successors become choices labeled like the links of the other
+//! interactive exports.
+
+use log::info;
+use std::collections::HashMap;
+use crate::html::choice_label;
+use crate::{StoryChain, StoryChainError};
+
+/// Escapes characters that Ink would read as markup inside a line of text
+fn escape_ink(text: &str) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut escaped = String::with_capacity(text.len());
+    for (index, &c) in chars.iter().enumerate() {
+        let next = chars.get(index + 1).copied();
+        let special = match c {
+            '{' | '}' | '[' | ']' | '|' | '#' | '~' | '\\' => true,
+            // Diverts, glue, threads, and comments
+            '-' => next == Some('>') || index == 0,
+            '<' => matches!(next, Some('>') | Some('-')),
+            '/' => matches!(next, Some('/') | Some('*')),
+            // Choices, gathers, and knot headers at the start of a line
+            '*' | '+' | '=' => index == 0,
+            _ => false,
+        };
+        if special {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Turns node IDs into unique knot names Ink accepts
+fn knot_names<'a>(ids: impl IntoIterator<Item = &'a str>) -> HashMap<&'a str, String> {
+    let mut names: HashMap<&str, String> = HashMap::new();
+    for id in ids {
+        let mut name: String = id.chars().map(|c| if c.is_ascii_alphanumeric() || c == '_' { c } else { '_' }).collect();
+        if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+            name.insert_str(0, "scene_");
+        }
+        let base = name.clone();
+        let mut suffix = 2;
+        while names.values().any(|n| *n == name) {
+            name = format!("{}_{}", base, suffix);
+            suffix += 1;
+        }
+        names.insert(id, name);
+    }
+    names
+}
+
+impl StoryChain {
+    /// Renders the chain as an Ink script
+    ///
+    /// The script starts by diverting to the root's knot. Each knot holds
+    /// its scene's paragraphs and a `pov` tag when the node has one, then
+    /// diverts to a single successor, offers one choice per successor where
+    /// the story branches, or ends the story. The title is set as a global
+    /// `title` tag.
+    pub fn to_ink(&self) -> String {
+        let nodes = self.nodes_in_graph_order();
+        let names = knot_names(nodes.iter().map(|n| n.id.as_str()));
+
+        let mut ink = String::new();
+        if let Some(title) = self.metadata.get("title") {
+            ink.push_str(&format!("# title: {}\n\n", escape_ink(title.trim())));
+        }
+        ink.push_str(&format!("-> {}\n", names[self.root_node_id.as_str()]));
+
+        for node in nodes {
+            ink.push_str(&format!("\n=== {} ===\n", names[node.id.as_str()]));
+            if let Some(pov) = node.metadata.get("pov") {
+                ink.push_str(&format!("# pov: {}\n", escape_ink(pov)));
+            }
+            let paragraphs: Vec<String> = node
+                .content
+                .split("\n\n")
+                .map(|p| p.split_whitespace().collect::<Vec<_>>().join(" "))
+                .filter(|p| !p.is_empty())
+                .map(|p| escape_ink(&p))
+                .collect();
+            ink.push_str(&paragraphs.join("\n\n"));
+            ink.push('\n');
+
+            let successors: Vec<_> = node.successors.iter().filter_map(|id| self.nodes.get(id)).collect();
+            match successors.as_slice() {
+                [] => ink.push_str("-> END\n"),
+                [single] => ink.push_str(&format!("-> {}\n", names[single.id.as_str()])),
+                many => {
+                    for successor in many {
+                        ink.push_str(&format!(
+                            "* [{}] -> {}\n",
+                            escape_ink(&choice_label(successor)),
+                            names[successor.id.as_str()]
+                        ));
+                    }
+                }
+            }
+        }
+        ink
+    }
+
+    /// Exports the chain as an Ink script that inklecate can compile
+    ///
+    /// # Arguments
+    /// * `path` - The path where the Ink file should be saved
+    pub fn export_to_ink(&self, path: &str) -> Result<(), StoryChainError> {
+        info!("Exporting story to Ink: {}", path);
+        crate::files::write_atomic(path, self.to_ink())?;
+        Ok(())
+    }
+}
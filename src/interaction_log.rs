@@ -0,0 +1,277 @@
+//! AI Interaction Log
+//!
+//! This module writes every request to the model to a JSON Lines file, one
+//! `InteractionRecord` per line: when it was sent, the provider and model,
+//! the prompts and sampling parameters, the raw response when the provider
+//! sees it, the parsed reasoning and content, how long it took, and the
+//! error when it failed. `DeepseekProvider` writes its own log this way, and
+//! `InteractionLogger` adds one to any other provider. The file is appended
+//! to, so it can be followed while a run is going and read back with
+//! `read_interactions` or any tool that understands JSON Lines.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
+use log::debug;
+use crate::{AIProvider, GenerationConfig, StoryChainError, TokenUsage};
+
+/// One request to the model and its outcome
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct InteractionRecord {
+    /// When the request was sent, in RFC 3339 format
+    pub timestamp: String,
+
+    /// Name of the provider that answered, such as `ollama-http`
+    pub provider: String,
+
+    /// Model the provider generated with, if it reported one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+
+    /// System prompt of the request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+
+    /// Prompt of the request
+    pub prompt: String,
+
+    /// Sampling parameters of the request
+    #[serde(skip_serializing_if = "GenerationConfig::is_empty")]
+    pub config: GenerationConfig,
+
+    /// Response as the model returned it, before parsing
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw_response: Option<String>,
+
+    /// Reasoning parsed from the response
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reasoning: Option<String>,
+
+    /// Content parsed from the response
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+
+    /// How long the request took, in milliseconds
+    pub duration_ms: u64,
+
+    /// Why the request failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl InteractionRecord {
+    /// Records a request that was sent at `started`
+    ///
+    /// # Arguments
+    /// * `ai_provider` - The provider that answered
+    /// * `system_prompt` - The system prompt of the request, if any
+    /// * `prompt` - The prompt of the request
+    /// * `config` - The sampling parameters of the request
+    /// * `started` - When the request was sent
+    pub fn new(
+        ai_provider: &dyn AIProvider,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+        started: Instant,
+    ) -> Self {
+        let duration = started.elapsed();
+        let sent_at = chrono::Utc::now() - chrono::Duration::from_std(duration).unwrap_or_default();
+        Self {
+            timestamp: sent_at.to_rfc3339(),
+            provider: ai_provider.provider_name().to_string(),
+            model: ai_provider.model_name().map(str::to_string),
+            system_prompt: system_prompt.map(str::to_string),
+            prompt: prompt.to_string(),
+            config: config.clone(),
+            duration_ms: duration.as_millis() as u64,
+            ..Self::default()
+        }
+    }
+
+    /// Sets the response as the model returned it
+    ///
+    /// # Arguments
+    /// * `raw_response` - The unparsed response
+    pub fn with_raw_response(mut self, raw_response: &str) -> Self {
+        self.raw_response = Some(raw_response.to_string());
+        self
+    }
+
+    /// Sets the outcome of the request
+    ///
+    /// # Arguments
+    /// * `result` - The parsed (reasoning, content), or the error the request failed with
+    pub fn with_result(mut self, result: &Result<(String, String), StoryChainError>) -> Self {
+        match result {
+            Ok((reasoning, content)) => {
+                self.reasoning = Some(reasoning.clone());
+                self.content = Some(content.clone());
+            }
+            Err(e) => self.error = Some(e.to_string()),
+        }
+        self
+    }
+
+    /// Returns how long the request took
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms)
+    }
+}
+
+/// A JSON Lines file interactions are appended to
+pub struct InteractionLog {
+    /// The log file
+    path: PathBuf,
+
+    /// Keeps concurrent requests from interleaving their lines
+    lock: Mutex<()>,
+}
+
+impl InteractionLog {
+    /// Creates a new InteractionLog; the file is created on the first append
+    ///
+    /// # Arguments
+    /// * `path` - The log file
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into(), lock: Mutex::new(()) }
+    }
+
+    /// Returns the log file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Appends an interaction as one line of JSON
+    ///
+    /// # Arguments
+    /// * `record` - The interaction
+    pub fn append(&self, record: &InteractionRecord) -> Result<(), StoryChainError> {
+        let line = serde_json::to_string(record)?;
+        let _guard = self.lock.lock().unwrap();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(StoryChainError::IOError)?;
+        writeln!(file, "{}", line)?;
+        debug!("Logged interaction with {} to {}", record.provider, self.path.display());
+        Ok(())
+    }
+}
+
+/// Reads the interactions of a log file
+///
+/// # Arguments
+/// * `path` - The log file
+///
+/// # Returns
+/// The interactions in the order they were logged, or an error naming the
+/// first line that is not a valid record
+pub fn read_interactions(path: impl AsRef<Path>) -> Result<Vec<InteractionRecord>, StoryChainError> {
+    std::fs::read_to_string(path.as_ref())?
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line).map_err(|e| {
+                StoryChainError::StorageError(format!(
+                    "{} line {} is not an interaction: {}",
+                    path.as_ref().display(),
+                    index + 1,
+                    e
+                ))
+            })
+        })
+        .collect()
+}
+
+/// Wraps a provider and appends every request it answers, or fails, to an
+/// interaction log
+pub struct InteractionLogger<P: AIProvider> {
+    /// The provider answering the requests
+    inner: P,
+
+    /// The log the requests are written to
+    log: InteractionLog,
+}
+
+impl<P: AIProvider> InteractionLogger<P> {
+    /// Creates a new InteractionLogger
+    ///
+    /// # Arguments
+    /// * `inner` - The provider answering the requests
+    /// * `path` - The JSON Lines file the requests are appended to
+    pub fn new(inner: P, path: impl Into<PathBuf>) -> Self {
+        Self { inner, log: InteractionLog::new(path) }
+    }
+
+    /// Logs a request once it has been answered or has failed
+    fn log(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+        started: Instant,
+        result: Result<(String, String), StoryChainError>,
+    ) -> Result<(String, String), StoryChainError> {
+        let record = InteractionRecord::new(&self.inner, system_prompt, prompt, config, started).with_result(&result);
+        self.log.append(&record)?;
+        result
+    }
+}
+
+#[async_trait::async_trait]
+impl<P: AIProvider> AIProvider for InteractionLogger<P> {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        let started = Instant::now();
+        let result = self.inner.generate(prompt).await;
+        self.log(None, prompt, &GenerationConfig::default(), started, result)
+    }
+
+    async fn generate_with_system(&self, system_prompt: &str, prompt: &str) -> Result<(String, String), StoryChainError> {
+        let started = Instant::now();
+        let result = self.inner.generate_with_system(system_prompt, prompt).await;
+        self.log(Some(system_prompt), prompt, &GenerationConfig::default(), started, result)
+    }
+
+    async fn generate_with_config(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+    ) -> Result<(String, String), StoryChainError> {
+        let started = Instant::now();
+        let result = self.inner.generate_with_config(system_prompt, prompt, config).await;
+        self.log(system_prompt, prompt, config, started, result)
+    }
+
+    async fn generate_stream(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+        tokens: UnboundedSender<String>,
+    ) -> Result<(String, String), StoryChainError> {
+        let started = Instant::now();
+        let result = self.inner.generate_stream(system_prompt, prompt, config, tokens).await;
+        self.log(system_prompt, prompt, config, started, result)
+    }
+
+    fn provider_name(&self) -> &str {
+        self.inner.provider_name()
+    }
+
+    fn model_name(&self) -> Option<&str> {
+        self.inner.model_name()
+    }
+
+    fn usage(&self) -> Option<TokenUsage> {
+        self.inner.usage()
+    }
+}
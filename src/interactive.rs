@@ -0,0 +1,212 @@
+//! Interactive Sessions
+//!
+//! This module holds the loop behind `storychain interactive`: a story is
+//! written one scene at a time along its main storyline, and the author
+//! reviews every scene before the next is generated. A `Reviewer` shows each
+//! scene and returns the author's decision; it may edit the scene or attach
+//! a steering note on the way (see the `review` module). Rejected scenes are
+//! regenerated, and the story is handed to a save callback after every
+//! accepted scene and when the author quits.
+
+use std::time::Instant;
+use log::info;
+use crate::length::LengthTarget;
+use crate::prompts::INITIAL;
+use crate::provenance::NodeProvenance;
+use crate::{AIProvider, GenerationConfig, PromptTemplates, StoryChain, StoryChainError};
+use crate::{EPOCHS_COMPLETED_KEY, EPOCHS_KEY};
+
+/// What the author decided about a generated scene
+#[derive(Debug, Clone, PartialEq)]
+pub enum Review {
+    /// Keep the scene and continue after it
+    Accept,
+
+    /// Discard the scene and generate another, optionally saying why
+    Reject(Option<String>),
+
+    /// Save the story and stop
+    Quit,
+}
+
+/// Shows generated scenes to the author and returns their decisions
+pub trait Reviewer {
+    /// Asks the author what to do with a scene
+    ///
+    /// # Arguments
+    /// * `chain` - The chain holding the scene, which the reviewer may edit or steer
+    /// * `node_id` - ID of the scene to review
+    /// * `label` - Heading of the scene, such as "Scene 2 of 6"
+    fn review(&mut self, chain: &mut StoryChain, node_id: &str, label: &str) -> Result<Review, StoryChainError>;
+
+    /// Called before a scene is generated
+    ///
+    /// # Arguments
+    /// * `label` - Heading of the scene about to be generated
+    fn generating(&mut self, _label: &str) {}
+}
+
+/// Writes a story with the author reviewing every scene
+pub struct InteractiveSession<'a> {
+    /// Provider generating the scenes
+    provider: &'a dyn AIProvider,
+
+    /// Premise of the story, as a prompt section
+    premise: String,
+
+    /// Number of scenes written after the opening
+    epochs: usize,
+
+    /// Prompt templates of the story
+    templates: PromptTemplates,
+
+    /// Sampling parameters of a new story
+    generation: GenerationConfig,
+
+    /// Target word count of every scene of a new story
+    length: Option<LengthTarget>,
+}
+
+impl<'a> InteractiveSession<'a> {
+    /// Creates a new InteractiveSession
+    ///
+    /// # Arguments
+    /// * `provider` - Provider generating the scenes
+    /// * `premise` - Premise of the story, as a prompt section
+    /// * `epochs` - Number of scenes written after the opening
+    pub fn new(provider: &'a dyn AIProvider, premise: impl Into<String>, epochs: usize) -> Self {
+        Self {
+            provider,
+            premise: premise.into(),
+            epochs,
+            templates: PromptTemplates::default(),
+            generation: GenerationConfig::default(),
+            length: None,
+        }
+    }
+
+    /// Sets the prompt templates the story is written with
+    pub fn templates(mut self, templates: PromptTemplates) -> Self {
+        self.templates = templates;
+        self
+    }
+
+    /// Sets the sampling parameters of a new story
+    pub fn generation(mut self, generation: GenerationConfig) -> Self {
+        self.generation = generation;
+        self
+    }
+
+    /// Sets the target word count of every scene of a new story
+    pub fn length(mut self, length: Option<LengthTarget>) -> Self {
+        self.length = length;
+        self
+    }
+
+    /// Runs the session
+    ///
+    /// A new story starts with the author's review of its opening scene; a
+    /// resumed story keeps its own settings and continues from the end of its
+    /// main storyline after the epochs it already completed.
+    ///
+    /// # Arguments
+    /// * `resumed` - A saved story to continue, or `None` to start a new one
+    /// * `reviewer` - Shows every scene to the author
+    /// * `save` - Saves the story, after every accepted scene and when the author quits
+    ///
+    /// # Returns
+    /// Whether the story was completed, rather than the author quitting
+    pub async fn run(
+        self,
+        resumed: Option<StoryChain>,
+        reviewer: &mut dyn Reviewer,
+        mut save: impl FnMut(&StoryChain) -> Result<(), StoryChainError>,
+    ) -> Result<bool, StoryChainError> {
+        let epochs = self.epochs;
+        let label = |epoch: usize| format!("Scene {} of {}", epoch + 1, epochs + 1);
+        let is_new = resumed.is_none();
+        let mut chain = match resumed {
+            Some(chain) => chain,
+            None => {
+                reviewer.generating(&label(0));
+                self.write_opening().await?
+            }
+        };
+        chain.prompts = self.templates;
+        if is_new {
+            chain.settings.generation = self.generation;
+            chain.settings.length = self.length;
+        }
+        chain.branch_ratio = 1;
+        chain.metadata.insert(EPOCHS_KEY.to_string(), epochs.to_string());
+
+        // A new story starts with the author's review of the opening
+        if is_new {
+            let root_id = chain.root_node_id.clone();
+            loop {
+                match reviewer.review(&mut chain, &root_id, &label(0))? {
+                    Review::Accept => break,
+                    Review::Reject(_) => {
+                        info!("Regenerating the rejected opening scene");
+                        reviewer.generating(&label(0));
+                        let generation = chain.draw_generation();
+                        let prompt = chain.prompts.initial_prompt(&self.premise, &[])?;
+                        let (reasoning, content) = self.provider.generate_with_config(None, &prompt, &generation).await?;
+                        let root = chain.nodes.get_mut(&root_id).expect("root exists");
+                        root.content = content;
+                        root.reasoning = reasoning;
+                    }
+                    Review::Quit => {
+                        save(&chain)?;
+                        return Ok(false);
+                    }
+                }
+            }
+            save(&chain)?;
+        }
+
+        // Continue from the end of the main storyline
+        let mut current = chain.storylines()[0].last().expect("a storyline has a root").id.clone();
+        for epoch in chain.completed_epochs() + 1..=epochs {
+            loop {
+                reviewer.generating(&label(epoch));
+                let new_id = chain
+                    .generate_next_nodes(&current, self.provider, Some(&self.premise), epoch, epochs)
+                    .await?
+                    .remove(0);
+                match reviewer.review(&mut chain, &new_id, &label(epoch))? {
+                    Review::Accept => {
+                        current = new_id;
+                        break;
+                    }
+                    Review::Reject(reason) => {
+                        chain.reject_node(&new_id, reason)?;
+                    }
+                    Review::Quit => {
+                        save(&chain)?;
+                        return Ok(false);
+                    }
+                }
+            }
+            chain.metadata.insert(EPOCHS_COMPLETED_KEY.to_string(), epoch.to_string());
+            save(&chain)?;
+        }
+        Ok(true)
+    }
+
+    /// Generates the opening scene of a new story from the premise
+    ///
+    /// # Returns
+    /// A chain holding the opening scene
+    async fn write_opening(&self) -> Result<StoryChain, StoryChainError> {
+        let started = Instant::now();
+        let provenance = NodeProvenance::new(self.provider, &self.templates, INITIAL, None, &self.generation);
+        let prompt = self.templates.initial_prompt(&self.premise, &[])?;
+        let (reasoning, content) = self.provider.generate_with_config(None, &prompt, &self.generation).await?;
+        let mut chain = StoryChain::new(content, reasoning);
+        if let Some(root) = chain.nodes.get_mut(&chain.root_node_id) {
+            root.provenance = Some(provenance.with_duration(started.elapsed()));
+        }
+        Ok(chain)
+    }
+}
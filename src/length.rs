@@ -0,0 +1,142 @@
+//! Scene Length Targeting
+//!
+//! This module keeps scenes near a target word count. The target is put in
+//! every continuation prompt, each generated scene is measured, and a scene
+//! that misses the target by more than the tolerance is sent back to the
+//! model to be expanded or trimmed.
+
+use serde::{Deserialize, Serialize};
+use log::{info, debug};
+use tera::Context;
+use crate::prompts::PromptTemplates;
+use crate::{AIProvider, StoryChain, StoryChainError};
+
+/// Node metadata key holding the word count of a scene
+pub const WORD_COUNT_KEY: &str = "word_count";
+
+/// Node metadata key holding how many times a scene was expanded or trimmed
+pub const LENGTH_ADJUSTMENTS_KEY: &str = "length_adjustments";
+
+/// Name of the length revision prompt template
+pub const LENGTH_REVISION: &str = "length_revision";
+
+/// Built-in template of the length revision prompt
+///
+/// Variables: `target`, the target word count; `words`, the scene's word
+/// count; `scene`, the scene to revise.
+pub const LENGTH_REVISION_TEMPLATE: &str = r#"Revise the scene below to about {{ target }} words. It is currently {{ words }} words. {% if words < target %}Expand it by deepening the existing beats with action, sensory detail, dialogue, and interiority; do not add new plot events{% else %}Trim it by cutting repetition, filler, and over-long description; keep every plot event{% endif %}. Keep the same events, characters, and outcome.
+
+Scene:
+{{ scene }}
+
+IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your reasoning about what to expand or cut.
+</think>
+Write the complete revised scene here."#;
+
+/// Target length of every scene
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct LengthTarget {
+    /// Desired number of words per scene
+    pub words: usize,
+
+    /// Allowed deviation from the target, as a fraction of it
+    pub tolerance: f64,
+
+    /// Maximum number of times a scene is sent back to be expanded or trimmed
+    pub max_adjustments: usize,
+}
+
+impl Default for LengthTarget {
+    fn default() -> Self {
+        Self {
+            words: 800,
+            tolerance: 0.2,
+            max_adjustments: 1,
+        }
+    }
+}
+
+/// Counts the words of a passage
+///
+/// # Arguments
+/// * `text` - The passage to count
+pub fn word_count(text: &str) -> usize {
+    text.split_whitespace().count()
+}
+
+impl LengthTarget {
+    /// Returns the smallest and largest word counts within the tolerance
+    pub fn bounds(&self) -> (usize, usize) {
+        let slack = self.words as f64 * self.tolerance.max(0.0);
+        (
+            (self.words as f64 - slack).round().max(0.0) as usize,
+            (self.words as f64 + slack).round() as usize,
+        )
+    }
+
+    /// Returns how far a word count falls outside the tolerance band,
+    /// negative when the scene is too short and positive when too long
+    ///
+    /// # Arguments
+    /// * `words` - The measured word count of a scene
+    pub fn miss(&self, words: usize) -> Option<i64> {
+        let (min, max) = self.bounds();
+        (words < min || words > max).then_some(words as i64 - self.words as i64)
+    }
+
+    /// Returns the length instruction included in continuation prompts
+    pub fn instruction(&self) -> String {
+        let (min, max) = self.bounds();
+        format!("Write about {} words in this scene (between {} and {}).", self.words, min, max)
+    }
+}
+
+/// Asks the AI to expand or trim a scene toward the target length
+///
+/// # Arguments
+/// * `templates` - The prompt templates holding the revision prompt
+/// * `ai_provider` - The AI provider to use for the revision
+/// * `content` - The scene content to revise
+/// * `target` - The length target to revise toward
+///
+/// # Returns
+/// A tuple of (reasoning, revised content)
+pub async fn adjust_length(
+    templates: &PromptTemplates,
+    ai_provider: &dyn AIProvider,
+    content: &str,
+    target: &LengthTarget,
+) -> Result<(String, String), StoryChainError> {
+    let words = word_count(content);
+    info!("Adjusting scene length ({} words, target {})", words, target.words);
+    let mut context = Context::new();
+    context.insert("target", &target.words);
+    context.insert("words", &words);
+    context.insert("scene", content);
+    let prompt = templates.render(LENGTH_REVISION, &context)?;
+    ai_provider.generate(&prompt).await
+}
+
+impl StoryChain {
+    /// Builds the length guidance for the next scene, noting when the scene
+    /// before it missed the target
+    ///
+    /// # Arguments
+    /// * `current_node_id` - ID of the node the next scene will follow
+    pub fn length_guidance(&self, current_node_id: &str) -> Option<String> {
+        let target = self.settings.length.as_ref()?;
+        let mut note = target.instruction();
+        if let Some(node) = self.nodes.get(current_node_id) {
+            let words = word_count(&node.content);
+            debug!("Word count of {}: {}", current_node_id, words);
+            if let Some(miss) = target.miss(words) {
+                let direction = if miss < 0 { "short" } else { "long" };
+                note.push_str(&format!(" The previous scene ran {} words, too {}.", words, direction));
+            }
+        }
+        Some(note)
+    }
+}
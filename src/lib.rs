@@ -6,15 +6,125 @@
 
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
 use thiserror::Error;
-use log::{info, debug, error};
-use std::process::Command;
-use std::fs::OpenOptions;
-use std::io::Write;
-use chrono::Local;
+use log::{info, debug, error, warn};
+use std::time::Instant;
+use tokio::io::AsyncReadExt;
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::Instrument;
+use futures::stream::{self, StreamExt, TryStreamExt};
 
 pub mod artifacts;
+#[cfg(feature = "audio")]
+pub mod audio;
+pub mod branches;
+pub mod cancellation;
+pub mod chapters;
+pub mod characters;
+pub mod chat;
+pub mod compare;
+pub mod config;
+pub mod consistency;
+pub mod constraints;
+pub mod dataset;
+pub mod dialogue;
+pub mod embeddings;
+#[cfg(feature = "docx")]
+pub mod docx;
+pub mod editing;
+pub mod enrichment;
+#[cfg(feature = "epub")]
+pub mod epub;
+pub mod events;
+pub mod exports;
+pub mod feedback;
+pub mod files;
+pub mod filters;
+pub mod foreshadowing;
+pub mod formats;
+pub mod fountain;
+pub mod generation;
+pub mod genres;
+pub mod glossary;
+pub mod graph;
+pub mod hooks;
+pub mod html;
+pub mod illustrations;
+pub mod import;
+pub mod interaction_log;
+pub mod interactive;
+pub mod ink;
+pub mod length;
+pub mod lore;
+pub mod memory;
+pub mod migrations;
+pub mod pacing;
+pub mod parsers;
+pub mod passes;
+#[cfg(feature = "pdf")]
+pub mod pdf;
+pub mod plugins;
+pub mod pov;
+pub mod premise;
+pub mod progress;
+pub mod project;
+pub mod prompts;
+pub mod provenance;
+pub mod providers;
+pub mod quality;
+pub mod reading_order;
+pub mod review;
+pub mod revision;
+pub mod runner;
+pub mod scene_cards;
+pub mod selection;
+pub mod series;
+#[cfg(feature = "serve")]
+pub mod server;
+pub mod settings;
+pub mod subplots;
+pub mod templates;
+pub mod tension;
+pub mod text;
+pub mod tokenizer;
+pub mod trace;
+pub mod translation;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod twee;
+pub mod usage;
+pub mod wizard;
+pub mod setups;
+pub mod stats;
+pub mod storage;
+pub mod style;
+pub mod strands;
+
+#[cfg(test)]
+mod tests;
 pub use artifacts::{Artifact, ArtifactManager, ArtifactType};
+pub use chapters::Chapter;
+pub use characters::CharacterRegistry;
+pub use feedback::Feedback;
+pub use generation::GenerationConfig;
+pub use glossary::GlossaryEntry;
+pub use prompts::{PromptTemplate, PromptTemplates};
+pub use provenance::NodeProvenance;
+pub use usage::{TokenUsage, UsageSummary};
+pub use providers::OllamaHttpProvider;
+pub use scene_cards::SceneCard;
+pub use settings::ChainSettings;
+pub use setups::Setup;
+pub use strands::Strand;
+pub use style::StyleGuide;
+pub use subplots::Subplot;
+use cancellation::CancellationToken;
+use embeddings::{EmbeddingProvider, SceneEmbedding};
+use filters::ContentFilter;
+use hooks::PipelineHooks;
+use interaction_log::{InteractionLog, InteractionRecord};
+use parsers::{ResponseParser, ThinkTagParser};
 
 /// Represents possible errors that can occur during story generation
 /// and related operations.
@@ -35,6 +145,42 @@ pub enum StoryChainError {
     /// JSON serialization/deserialization error
     #[error("Serialization error: {0}")]
     SerializationError(#[from] serde_json::Error),
+
+    /// Reader feedback that could not be recorded
+    #[error("Invalid feedback: {0}")]
+    InvalidFeedback(String),
+
+    /// A prompt template that could not be parsed or rendered
+    #[error("Template error: {0}")]
+    TemplateError(String),
+
+    /// A generation rule that could not be parsed
+    #[error("Invalid constraint: {0}")]
+    InvalidConstraint(String),
+
+    /// An operation that would leave the chain in an invalid state
+    #[error("Invalid chain operation: {0}")]
+    InvalidChainOperation(String),
+
+    /// A configuration file that could not be parsed or used
+    #[error("Configuration error: {0}")]
+    ConfigError(String),
+
+    /// A store that chains could not be saved to or loaded from
+    #[error("Storage error: {0}")]
+    StorageError(String),
+
+    /// A request the model did not answer in time
+    #[error("Request timed out after {0:?}")]
+    Timeout(std::time::Duration),
+
+    /// Generation stopped by its caller
+    #[error("Generation cancelled")]
+    Cancelled,
+
+    /// Speech that could not be synthesized or joined into an audiobook
+    #[error("Audio error: {0}")]
+    AudioError(String),
 }
 
 /// Represents a single node in the story chain, containing the narrative content
@@ -50,29 +196,212 @@ pub struct StoryNode {
     /// The AI's reasoning for generating this content
     pub reasoning: String,
     
-    /// ID of the previous node in the chain (if any)
-    pub predecessor: Option<String>,
-    
-    /// ID of the next node in the chain (if any)
-    pub successor: Option<String>,
+    /// IDs of the nodes this one continues; the first is the one it was generated from
+    #[serde(alias = "predecessor", deserialize_with = "one_or_many")]
+    pub predecessors: Vec<String>,
+
+    /// IDs of the alternative continuations of this node; the first is the main storyline
+    #[serde(alias = "successor", deserialize_with = "one_or_many")]
+    pub successors: Vec<String>,
     
     /// Additional metadata associated with this node
     pub metadata: HashMap<String, String>,
+
+    /// Reader ratings and comments attached to this node
+    #[serde(default)]
+    pub feedback: Vec<Feedback>,
+
+    /// Structured goal, conflict, outcome, and hook of the scene
+    #[serde(default)]
+    pub scene_card: Option<SceneCard>,
+
+    /// Embedding of the scene, used to recall it into later prompts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<SceneEmbedding>,
+
+    /// How the scene was generated, absent for scenes written or imported by hand
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<NodeProvenance>,
+}
+
+impl StoryNode {
+    /// Returns the IDs of the nodes that directly follow this one
+    pub fn successor_ids(&self) -> Vec<&String> {
+        self.successors.iter().collect()
+    }
+
+    /// Returns the ID of the node this one was generated from, if any
+    pub fn predecessor(&self) -> Option<&String> {
+        self.predecessors.first()
+    }
+}
+
+/// Reads node links saved as a single optional ID, as chains were before
+/// branching, or as a list of IDs
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Links {
+        One(Option<String>),
+        Many(Vec<String>),
+    }
+    Ok(match Links::deserialize(deserializer)? {
+        Links::One(id) => id.into_iter().collect(),
+        Links::Many(ids) => ids,
+    })
+}
+
+/// Chain metadata key holding the number of epochs the run was started with
+pub const EPOCHS_KEY: &str = "epochs";
+
+/// Chain metadata key holding the number of epochs generated so far
+pub const EPOCHS_COMPLETED_KEY: &str = "epochs_completed";
+
+/// Chain metadata key holding the number of scenes drafted with a seed so
+/// far, by which the seed of the next draft is advanced
+pub const SEED_DRAWS_KEY: &str = "seed_draws";
+
+/// Number of alternative continuations generated per node by default
+fn default_branch_ratio() -> usize {
+    1
 }
 
 /// Represents a complete chain of story nodes, forming a narrative.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StoryChain {
+    /// Version of the format the chain was written in
+    #[serde(default = "migrations::unversioned")]
+    pub version: u32,
+
     /// Map of node IDs to their corresponding StoryNode instances
     pub nodes: HashMap<String, StoryNode>,
     
     /// ID of the first node in the chain
     pub root_node_id: String,
+
+    /// Number of alternative continuations generated for each node
+    #[serde(default = "default_branch_ratio")]
+    pub branch_ratio: usize,
+
+    /// Chain-level metadata such as the generated title, blurb, and logline
+    #[serde(default)]
+    pub metadata: HashMap<String, String>,
+
+    /// Chapters grouping the nodes for presentation, in reading order
+    #[serde(default)]
+    pub chapters: Vec<Chapter>,
+
+    /// Registry of introduced character names used to check new scenes
+    #[serde(default)]
+    pub character_registry: CharacterRegistry,
+
+    /// Glossary of invented terms, rendered as an appendix in exports
+    #[serde(default)]
+    pub glossary: Vec<GlossaryEntry>,
+
+    /// Settings that shape how new scenes are generated
+    #[serde(default)]
+    pub settings: ChainSettings,
+
+    /// Node IDs in the order readers see them, when it differs from the causal order
+    #[serde(default)]
+    pub reading_order: Vec<String>,
+
+    /// Planted story elements and where they were paid off
+    #[serde(default)]
+    pub setups: Vec<Setup>,
+
+    /// Secondary plot lines woven into the main chain on a schedule
+    #[serde(default)]
+    pub subplots: Vec<Subplot>,
+
+    /// Parallel storylines interleaved into the chain, sharing its summary
+    #[serde(default)]
+    pub strands: Vec<Strand>,
+
+    /// Tokens and estimated cost spent generating the chain
+    #[serde(default, skip_serializing_if = "UsageSummary::is_empty")]
+    pub usage: UsageSummary,
+
+    /// Templates used to build the generation prompts
+    #[serde(skip)]
+    pub prompts: PromptTemplates,
+
+    /// Model that embeds scenes for recall into continuation prompts
+    #[serde(skip)]
+    pub embedder: Option<Arc<dyn EmbeddingProvider>>,
+
+    /// Filters screening every new scene, in addition to the built-in
+    /// keyword filter configured in the settings
+    #[serde(skip)]
+    pub content_filters: Vec<Arc<dyn ContentFilter>>,
+
+    /// Hooks observing the chain's generation
+    #[serde(skip)]
+    pub hooks: Vec<Arc<dyn PipelineHooks>>,
+
+    /// Token stopping the chain's generation when cancelled
+    #[serde(skip)]
+    pub cancellation: Option<CancellationToken>,
+}
+
+/// A generated scene that has not been added to the chain yet
+pub(crate) struct SceneDraft {
+    /// The scene after the content filters, with its reasoning
+    screened: filters::Screened,
+
+    /// How the scene was generated
+    provenance: NodeProvenance,
+
+    /// Number of times the scene was expanded or trimmed toward the length target
+    length_adjustments: usize,
+
+    /// Contradictions with the established story left in the scene
+    consistency_issues: Vec<String>,
+
+    /// Whether the scene was revised for consistency
+    consistency_revised: bool,
+}
+
+impl SceneDraft {
+    /// Returns the drafted scene
+    pub(crate) fn content(&self) -> &str {
+        &self.screened.content
+    }
+}
+
+/// Options controlling what the markdown export includes
+///
+/// The HTML, EPUB, PDF, and DOCX exports take them too, and follow
+/// `chapter_recaps`.
+#[derive(Debug, Clone)]
+pub struct MarkdownOptions {
+    /// Include the AI's reasoning in a collapsible section after each scene
+    pub include_reasoning: bool,
+
+    /// Render the previous chapter's summary as a recap at each chapter head
+    pub chapter_recaps: bool,
+
+    /// Append a reading-time and pacing report at this reading speed, in words per minute
+    pub stats_words_per_minute: Option<usize>,
+}
+
+impl Default for MarkdownOptions {
+    fn default() -> Self {
+        Self {
+            include_reasoning: true,
+            chapter_recaps: false,
+            stats_words_per_minute: None,
+        }
+    }
 }
 
 /// Trait defining the interface for AI providers that generate story content.
 #[async_trait::async_trait]
-pub trait AIProvider {
+pub trait AIProvider: Send + Sync {
     /// Generates content based on a given prompt
     /// 
     /// # Arguments
@@ -81,6 +410,196 @@ pub trait AIProvider {
     /// # Returns
     /// A tuple of (reasoning, content) strings or an error
     async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError>;
+
+    /// Generates content with a system prompt kept separate from the per-scene prompt
+    ///
+    /// Providers with a chat API send the system prompt in the system role.
+    /// The default implementation prepends it to the prompt.
+    ///
+    /// # Arguments
+    /// * `system_prompt` - Author persona and global style rules
+    /// * `prompt` - The prompt to send to the AI model
+    ///
+    /// # Returns
+    /// A tuple of (reasoning, content) strings or an error
+    async fn generate_with_system(&self, system_prompt: &str, prompt: &str) -> Result<(String, String), StoryChainError> {
+        self.generate(&prepend_system_prompt(system_prompt, prompt)).await
+    }
+
+    /// Generates content with the given sampling parameters
+    ///
+    /// Parameters the provider's backend does not support are ignored. The
+    /// default implementation ignores all of them.
+    ///
+    /// # Arguments
+    /// * `system_prompt` - Optional author persona and global style rules
+    /// * `prompt` - The prompt to send to the AI model
+    /// * `config` - Sampling parameters; unset ones use the provider's defaults
+    ///
+    /// # Returns
+    /// A tuple of (reasoning, content) strings or an error
+    async fn generate_with_config(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+    ) -> Result<(String, String), StoryChainError> {
+        if !config.is_empty() {
+            debug!("Provider ignores generation parameters");
+        }
+        match system_prompt {
+            Some(system_prompt) => self.generate_with_system(system_prompt, prompt).await,
+            None => self.generate(prompt).await,
+        }
+    }
+
+    /// Generates content while sending the raw response to a channel piece by
+    /// piece as the model produces it
+    ///
+    /// The final (reasoning, content) tuple is returned as usual once the
+    /// response is complete. Providers that cannot stream send the whole
+    /// content as a single piece.
+    ///
+    /// # Arguments
+    /// * `system_prompt` - Optional author persona and global style rules
+    /// * `prompt` - The prompt to send to the AI model
+    /// * `config` - Sampling parameters; unset ones use the provider's defaults
+    /// * `tokens` - Channel receiving the pieces of the response
+    ///
+    /// # Returns
+    /// A tuple of (reasoning, content) strings or an error
+    async fn generate_stream(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+        tokens: UnboundedSender<String>,
+    ) -> Result<(String, String), StoryChainError> {
+        let (reasoning, content) = self.generate_with_config(system_prompt, prompt, config).await?;
+        let _ = tokens.send(content.clone());
+        Ok((reasoning, content))
+    }
+
+    /// Returns the name of the provider, recorded in the provenance of the
+    /// scenes it generates
+    ///
+    /// The default implementation returns the provider's type name.
+    fn provider_name(&self) -> &str {
+        let name = std::any::type_name::<Self>();
+        name.rsplit("::").next().unwrap_or(name)
+    }
+
+    /// Returns the model the provider generates with, if it knows it
+    fn model_name(&self) -> Option<&str> {
+        None
+    }
+
+    /// Returns the tokens spent by all requests so far, if the provider
+    /// counts them
+    fn usage(&self) -> Option<TokenUsage> {
+        None
+    }
+}
+
+/// Combines a system prompt and a prompt for providers without a system role
+///
+/// # Arguments
+/// * `system_prompt` - Author persona and global style rules
+/// * `prompt` - The per-scene prompt
+pub fn prepend_system_prompt(system_prompt: &str, prompt: &str) -> String {
+    if system_prompt.trim().is_empty() {
+        prompt.to_string()
+    } else {
+        format!("{}\n\n{}", system_prompt.trim(), prompt)
+    }
+}
+
+/// Boxed providers forward to the provider they hold, so wrappers can take
+/// a provider chosen at runtime
+#[async_trait::async_trait]
+impl<P: AIProvider + ?Sized> AIProvider for Box<P> {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        (**self).generate(prompt).await
+    }
+
+    async fn generate_with_system(&self, system_prompt: &str, prompt: &str) -> Result<(String, String), StoryChainError> {
+        (**self).generate_with_system(system_prompt, prompt).await
+    }
+
+    async fn generate_with_config(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+    ) -> Result<(String, String), StoryChainError> {
+        (**self).generate_with_config(system_prompt, prompt, config).await
+    }
+
+    async fn generate_stream(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+        tokens: UnboundedSender<String>,
+    ) -> Result<(String, String), StoryChainError> {
+        (**self).generate_stream(system_prompt, prompt, config, tokens).await
+    }
+
+    fn provider_name(&self) -> &str {
+        (**self).provider_name()
+    }
+
+    fn model_name(&self) -> Option<&str> {
+        (**self).model_name()
+    }
+
+    fn usage(&self) -> Option<TokenUsage> {
+        (**self).usage()
+    }
+}
+
+/// Shared providers forward to the provider they hold, so one provider can
+/// be wrapped differently by concurrent runs
+#[async_trait::async_trait]
+impl<P: AIProvider + ?Sized> AIProvider for Arc<P> {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        (**self).generate(prompt).await
+    }
+
+    async fn generate_with_system(&self, system_prompt: &str, prompt: &str) -> Result<(String, String), StoryChainError> {
+        (**self).generate_with_system(system_prompt, prompt).await
+    }
+
+    async fn generate_with_config(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+    ) -> Result<(String, String), StoryChainError> {
+        (**self).generate_with_config(system_prompt, prompt, config).await
+    }
+
+    async fn generate_stream(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+        tokens: UnboundedSender<String>,
+    ) -> Result<(String, String), StoryChainError> {
+        (**self).generate_stream(system_prompt, prompt, config, tokens).await
+    }
+
+    fn provider_name(&self) -> &str {
+        (**self).provider_name()
+    }
+
+    fn model_name(&self) -> Option<&str> {
+        (**self).model_name()
+    }
+
+    fn usage(&self) -> Option<TokenUsage> {
+        (**self).usage()
+    }
 }
 
 /// Implementation of AIProvider using the Deepseek language model
@@ -88,50 +607,55 @@ pub struct DeepseekProvider {
     /// The specific Deepseek model to use
     model: String,
     
-    /// Path to the file where AI responses will be logged
-    log_file: String,
+    /// JSON Lines file every request and response is logged to
+    log: InteractionLog,
+
+    /// System prompt prepended to every prompt sent to the model
+    system_prompt: Option<String>,
+
+    /// Splits responses into reasoning and content
+    parser: Arc<dyn ResponseParser>,
 }
 
 impl DeepseekProvider {
     /// Creates a new DeepseekProvider instance
     pub fn new(model: String, log_file: String) -> Self {
-        Self { model, log_file }
+        Self { model, log: InteractionLog::new(log_file), system_prompt: None, parser: Arc::new(ThinkTagParser) }
     }
 
-    /// Logs AI interactions to a file for debugging and analysis
-    /// 
+    /// Sets how responses are split into reasoning and content, `<think>`
+    /// tags by default
+    ///
     /// # Arguments
-    /// * `prompt` - The prompt sent to the AI
-    /// * `response` - The AI's response
-    fn log_response(&self, prompt: &str, response: &str) -> Result<(), StoryChainError> {
-        let mut file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&self.log_file)
-            .map_err(|e| StoryChainError::IOError(e))?;
-
-        let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
-        writeln!(file, "=== AI Response at {} ===", timestamp)?;
-        writeln!(file, "Prompt: {}", prompt)?;
-        writeln!(file, "Response: {}", response)?;
-        writeln!(file, "=== End Response ===\n")?;
-        Ok(())
+    /// * `parser` - The response parser
+    pub fn with_parser(mut self, parser: Arc<dyn ResponseParser>) -> Self {
+        self.parser = parser;
+        self
     }
-}
 
-#[async_trait::async_trait]
-impl AIProvider for DeepseekProvider {
-    /// Generates story content using the Deepseek model via Ollama
-    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
-        info!("Sending request to Ollama for model: {}", self.model);
-        debug!("Prompt: {}", prompt);
+    /// Sets a system prompt (author persona, global style rules) that is
+    /// prepended to every prompt sent to the model
+    ///
+    /// # Arguments
+    /// * `system_prompt` - The system prompt to prepend
+    pub fn with_system_prompt(mut self, system_prompt: String) -> Self {
+        self.system_prompt = Some(system_prompt);
+        self
+    }
 
-        // Execute Ollama command to generate content
-        let output = Command::new("ollama")
+    /// Runs `ollama run` with a prompt and returns its output
+    ///
+    /// # Arguments
+    /// * `prompt` - The prompt, system prompt included
+    async fn run(&self, prompt: &str) -> Result<String, StoryChainError> {
+        // Execute Ollama command to generate content; it is stopped if the request is dropped
+        let output = tokio::process::Command::new("ollama")
             .arg("run")
             .arg(&self.model)
             .arg(prompt)
+            .kill_on_drop(true)
             .output()
+            .await
             .map_err(|e| {
                 error!("Failed to execute Ollama command: {}", e);
                 StoryChainError::AIServerError(format!("Failed to execute Ollama command: {}", e))
@@ -148,76 +672,212 @@ impl AIProvider for DeepseekProvider {
         }
 
         // Parse the output into UTF-8 string
-        let response_text = String::from_utf8(output.stdout)
+        String::from_utf8(output.stdout).map_err(|e| {
+            error!("Failed to parse Ollama output: {}", e);
+            StoryChainError::AIServerError(format!("Failed to parse Ollama output: {}", e))
+        })
+    }
+
+    /// Runs `ollama run` with a prompt, forwarding its output as it is
+    /// written, and returns the whole output
+    ///
+    /// # Arguments
+    /// * `prompt` - The prompt, system prompt included
+    /// * `tokens` - Channel the output is sent to as it arrives
+    async fn run_streaming(&self, prompt: &str, tokens: UnboundedSender<String>) -> Result<String, StoryChainError> {
+        let mut child = tokio::process::Command::new("ollama")
+            .arg("run")
+            .arg(&self.model)
+            .arg(prompt)
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
             .map_err(|e| {
-                error!("Failed to parse Ollama output: {}", e);
-                StoryChainError::AIServerError(format!("Failed to parse Ollama output: {}", e))
+                error!("Failed to execute Ollama command: {}", e);
+                StoryChainError::AIServerError(format!("Failed to execute Ollama command: {}", e))
             })?;
 
-        debug!("Raw AI response: {}", response_text);
-
-        // Log the response for debugging
-        self.log_response(prompt, &response_text)?;
-
-        // Parse the response to extract reasoning and content
-        let re = regex::Regex::new(r"(?s)<think>(.*?)</think>\s*(.*)").unwrap();
-
-        // Extract reasoning and content using regex
-        let (reasoning, content) = match re.captures(&response_text) {
-            Some(caps) => {
-                let raw_reasoning = caps.get(1).unwrap().as_str().trim();
-                let raw_content = caps.get(2).unwrap().as_str().trim();
-                
-                // Filter out Chinese characters and clean up the text
-                let clean_reasoning = raw_reasoning.chars()
-                    .filter(|c| !('\u{4e00}'..='\u{9fff}').contains(c))
-                    .collect::<String>()
-                    .trim()
-                    .to_string();
-                let clean_content = raw_content.chars()
-                    .filter(|c| !('\u{4e00}'..='\u{9fff}').contains(c))
-                    .collect::<String>()
-                    .trim()
-                    .to_string();
-                
-                // Validate that filtering didn't remove all content
-                if clean_reasoning.is_empty() && !raw_reasoning.is_empty() {
-                    error!("Filtering removed all content from reasoning");
-                    return Err(StoryChainError::InvalidReasoningFormat(
-                        "Filtering removed all content from reasoning".to_string()
-                    ));
-                }
-                if clean_content.is_empty() && !raw_content.is_empty() {
-                    error!("Filtering removed all content from story content");
-                    return Err(StoryChainError::InvalidReasoningFormat(
-                        "Filtering removed all content from story content".to_string()
-                    ));
-                }
-                
-                (clean_reasoning, clean_content)
-            },
-            None => {
-                error!("Failed to parse AI response - no <think> tags found");
-                return Err(StoryChainError::AIServerError(
-                    "Failed to parse AI response - no <think> tags found".to_string()
-                ));
+        // Drain stderr alongside stdout so a chatty child can't block on a full pipe
+        let mut stderr = child.stderr.take().expect("stderr is piped");
+        let errors = tokio::spawn(async move {
+            let mut errors = Vec::new();
+            stderr.read_to_end(&mut errors).await.map(|_| errors)
+        });
+
+        // Forward every complete UTF-8 sequence as soon as it arrives
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        let mut raw = Vec::new();
+        let mut sent = 0;
+        let mut buffer = [0u8; 1024];
+        loop {
+            let read = stdout.read(&mut buffer).await?;
+            if read == 0 {
+                break;
             }
-        };
+            raw.extend_from_slice(&buffer[..read]);
+            let valid = match std::str::from_utf8(&raw[sent..]) {
+                Ok(text) => text.len(),
+                Err(e) => e.valid_up_to(),
+            };
+            if valid > 0 {
+                let _ = tokens.send(String::from_utf8_lossy(&raw[sent..sent + valid]).to_string());
+                sent += valid;
+            }
+        }
 
-        // Validate that neither part is empty
-        if reasoning.is_empty() || content.is_empty() {
-            error!("Empty reasoning or content in response");
-            return Err(StoryChainError::InvalidReasoningFormat(
-                "Empty reasoning or content in response".to_string(),
-            ));
+        let status = child.wait().await?;
+        let errors = errors.await.map_err(|e| StoryChainError::AIServerError(e.to_string()))??;
+        if !status.success() {
+            let stderr = String::from_utf8_lossy(&errors);
+            error!("Ollama command failed: {}", stderr);
+            return Err(StoryChainError::AIServerError(format!("Ollama command failed: {}", stderr)));
         }
-        
-        debug!("Filtered reasoning: {}", reasoning);
-        debug!("Filtered content: {}", content);
+
+        String::from_utf8(raw).map_err(|e| {
+            error!("Failed to parse Ollama output: {}", e);
+            StoryChainError::AIServerError(format!("Failed to parse Ollama output: {}", e))
+        })
+    }
+
+    /// Parses the output of `ollama run` and logs the interaction to the
+    /// JSON Lines log for debugging and analysis
+    ///
+    /// # Arguments
+    /// * `prompt` - The prompt sent to the AI
+    /// * `started` - When the prompt was sent
+    /// * `response` - The AI's raw response, or the error the command failed with
+    fn finish_request(
+        &self,
+        prompt: &str,
+        started: Instant,
+        response: Result<String, StoryChainError>,
+    ) -> Result<(String, String), StoryChainError> {
+        let record = InteractionRecord::new(self, None, prompt, &GenerationConfig::default(), started);
+        let (record, result) = match response {
+            Ok(response_text) => {
+                debug!("Raw AI response: {}", response_text);
+                let result = self.parser.parse(&response_text);
+                (record.with_raw_response(&response_text), result)
+            }
+            Err(e) => (record, Err(e)),
+        };
+        self.log.append(&record.with_result(&result))?;
+        result
+    }
+}
+
+#[async_trait::async_trait]
+impl AIProvider for DeepseekProvider {
+    /// Generates story content using the Deepseek model via Ollama
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        info!("Sending request to Ollama for model: {}", self.model);
+        let prompt = &prepend_system_prompt(self.system_prompt.as_deref().unwrap_or_default(), prompt);
+        debug!("Prompt: {}", prompt);
+
+        let started = Instant::now();
+        let response = self.run(prompt).await;
+        let (reasoning, content) = self.finish_request(prompt, started, response)?;
 
         info!("Successfully parsed reasoning and content from response");
         Ok((reasoning, content))
     }
+
+    /// Streams the output of `ollama run` as it is written; `ollama run`
+    /// takes no sampling parameters, so the configuration is ignored
+    async fn generate_stream(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        _config: &GenerationConfig,
+        tokens: UnboundedSender<String>,
+    ) -> Result<(String, String), StoryChainError> {
+        info!("Streaming request to Ollama for model: {}", self.model);
+        let prompt = &prepend_system_prompt(system_prompt.unwrap_or_default(), prompt);
+        let prompt = &prepend_system_prompt(self.system_prompt.as_deref().unwrap_or_default(), prompt);
+        debug!("Prompt: {}", prompt);
+
+        let started = Instant::now();
+        let response = self.run_streaming(prompt, tokens).await;
+        self.finish_request(prompt, started, response)
+    }
+
+    fn provider_name(&self) -> &str {
+        "ollama"
+    }
+
+    fn model_name(&self) -> Option<&str> {
+        Some(&self.model)
+    }
+}
+
+/// Splits a model response into reasoning and content using `<think>` tags
+///
+/// Chinese characters, which reasoning models sometimes mix in, are removed
+/// from both parts.
+///
+/// # Arguments
+/// * `response_text` - The raw response of the model
+///
+/// # Returns
+/// A tuple of (reasoning, content) strings or an error
+pub fn parse_think_response(response_text: &str) -> Result<(String, String), StoryChainError> {
+    let re = regex::Regex::new(r"(?s)<think>(.*?)</think>\s*(.*)").unwrap();
+
+    // Extract reasoning and content using regex
+    let (reasoning, content) = match re.captures(response_text) {
+        Some(caps) => {
+            let raw_reasoning = caps.get(1).unwrap().as_str().trim();
+            let raw_content = caps.get(2).unwrap().as_str().trim();
+            
+            // Filter out Chinese characters and clean up the text
+            let clean_reasoning = raw_reasoning.chars()
+                .filter(|c| !('\u{4e00}'..='\u{9fff}').contains(c))
+                .collect::<String>()
+                .trim()
+                .to_string();
+            let clean_content = raw_content.chars()
+                .filter(|c| !('\u{4e00}'..='\u{9fff}').contains(c))
+                .collect::<String>()
+                .trim()
+                .to_string();
+            
+            // Validate that filtering didn't remove all content
+            if clean_reasoning.is_empty() && !raw_reasoning.is_empty() {
+                error!("Filtering removed all content from reasoning");
+                return Err(StoryChainError::InvalidReasoningFormat(
+                    "Filtering removed all content from reasoning".to_string()
+                ));
+            }
+            if clean_content.is_empty() && !raw_content.is_empty() {
+                error!("Filtering removed all content from story content");
+                return Err(StoryChainError::InvalidReasoningFormat(
+                    "Filtering removed all content from story content".to_string()
+                ));
+            }
+            
+            (clean_reasoning, clean_content)
+        },
+        None => {
+            error!("Failed to parse AI response - no <think> tags found");
+            return Err(StoryChainError::AIServerError(
+                "Failed to parse AI response - no <think> tags found".to_string()
+            ));
+        }
+    };
+
+    // Validate that neither part is empty
+    if reasoning.is_empty() || content.is_empty() {
+        error!("Empty reasoning or content in response");
+        return Err(StoryChainError::InvalidReasoningFormat(
+            "Empty reasoning or content in response".to_string(),
+        ));
+    }
+    
+    debug!("Filtered reasoning: {}", reasoning);
+    debug!("Filtered content: {}", content);
+
+    Ok((reasoning, content))
 }
 
 impl StoryChain {
@@ -228,21 +888,104 @@ impl StoryChain {
             id: "root".to_string(),
             content: root_content,
             reasoning: root_reasoning,
-            predecessor: None,
-            successor: None,
+            predecessors: Vec::new(),
+            successors: Vec::new(),
             metadata: HashMap::new(),
+            feedback: Vec::new(),
+            scene_card: None,
+            embedding: None,
+            provenance: None,
         };
 
         let mut nodes = HashMap::new();
         nodes.insert("root".to_string(), root_node);
 
         Self {
+            version: migrations::SCHEMA_VERSION,
             nodes,
             root_node_id: "root".to_string(),
+            branch_ratio: default_branch_ratio(),
+            metadata: HashMap::new(),
+            chapters: Vec::new(),
+            character_registry: CharacterRegistry::default(),
+            glossary: Vec::new(),
+            settings: ChainSettings::default(),
+            reading_order: Vec::new(),
+            setups: Vec::new(),
+            subplots: Vec::new(),
+            strands: Vec::new(),
+            usage: UsageSummary::default(),
+            prompts: PromptTemplates::default(),
+            embedder: None,
+            content_filters: Vec::new(),
+            hooks: Vec::new(),
+            cancellation: None,
+        }
+    }
+
+    /// Sets the number of alternative continuations generated for each node
+    ///
+    /// # Arguments
+    /// * `branch_ratio` - Alternatives per node; 1 keeps the chain linear
+    pub fn with_branch_ratio(mut self, branch_ratio: usize) -> Self {
+        self.branch_ratio = branch_ratio.max(1);
+        self
+    }
+
+    /// Returns the nodes of the main storyline in causal order, starting at
+    /// the root and following the first successor of each node until the end
+    /// of the chain.
+    pub fn nodes_in_order(&self) -> Vec<&StoryNode> {
+        let mut ordered = Vec::new();
+        let mut current = self.nodes.get(&self.root_node_id);
+
+        while let Some(node) = current {
+            // Guard against malformed chains that link back onto themselves
+            if ordered.iter().any(|n: &&StoryNode| n.id == node.id) {
+                break;
+            }
+            ordered.push(node);
+            current = node.successor_ids().first().and_then(|id| self.nodes.get(*id));
+        }
+
+        ordered
+    }
+
+    /// Collects the guidance notes to include in the prompt for the scene
+    /// following the given node, based on the chain settings
+    ///
+    /// # Arguments
+    /// * `current_node_id` - ID of the node the next scene will follow
+    pub fn guidance_notes(&self, current_node_id: &str) -> Vec<String> {
+        let mut notes = Vec::new();
+        debug!("Collecting guidance notes after node: {}", current_node_id);
+
+        if self.settings.feedback_guidance {
+            notes.extend(self.feedback_guidance());
         }
+        notes.extend(self.pov_guidance(current_node_id));
+        notes.extend(self.settings.format.instructions().map(str::to_string));
+        notes.extend(self.dialogue_guidance(current_node_id));
+        notes.extend(self.length_guidance(current_node_id));
+        notes.extend(self.scene_card_guidance(current_node_id));
+        notes.extend(self.setup_guidance());
+        notes.extend(self.foreshadowing_guidance(current_node_id));
+        notes.extend(self.constraint_guidance());
+        notes.extend(self.branch_guidance(current_node_id));
+        notes.extend(self.review_guidance(current_node_id));
+
+        notes
     }
 
     /// Generates the next node(s) in the story chain
+    ///
+    /// One alternative continuation is generated per `branch_ratio`, each
+    /// steered away from the continuations the node already has. With
+    /// `parallel_candidates` set, the alternatives are instead generated
+    /// concurrently from the same prompt, steered only away from the
+    /// continuations the node had before, and added in order once all are done.
+    /// Generation stops with `StoryChainError::Cancelled` once the chain's
+    /// cancellation token is cancelled.
     /// 
     /// # Arguments
     /// * `current_node_id` - ID of the node to generate from
@@ -258,136 +1001,685 @@ impl StoryChain {
         current_epoch: usize,
         total_epochs: usize,
     ) -> Result<Vec<String>, StoryChainError> {
-        let start_time = std::time::Instant::now();
-        debug!("Generating next node for: {}", current_node_id);
-        
-        // Get the current node or return error if not found
-        let current_node = self.nodes.get(current_node_id)
-            .ok_or_else(|| StoryChainError::AIServerError("Node not found".to_string()))?;
+        // Main-plot scenes continue from the main plot, skipping woven-in subplot scenes
+        let context_id = self.main_plot_context(current_node_id);
+        let candidates = self.branch_ratio.max(1);
+        let mut new_ids = Vec::new();
+        if let Some(limit) = self.settings.parallel_candidates.filter(|_| candidates > 1) {
+            let prompt = self
+                .continuation_prompt(current_node_id, &context_id, ai_provider, premise, current_epoch, total_epochs)
+                .await?;
+            for draft in self.draft_candidates(&context_id, &prompt, ai_provider, premise, candidates, limit).await? {
+                new_ids.extend(self.commit_scene(current_node_id, draft, ai_provider, current_epoch, total_epochs).await?);
+            }
+            return Ok(new_ids);
+        }
+        for _ in 0..candidates {
+            new_ids.extend(
+                self.generate_scene(current_node_id, &context_id, ai_provider, premise, current_epoch, total_epochs)
+                    .await?,
+            );
+        }
+        Ok(new_ids)
+    }
 
-        let mut prompt = String::new();
-        
-        // Include premise in prompt if provided
-        if let Some(premise) = premise {
-            debug!("Including premise in prompt");
-            prompt.push_str(&format!("Story Premise:\n{}\n\n", premise));
+    /// Drafts several scenes from the same prompt concurrently
+    ///
+    /// The requests overlap, so the drafts record no token usage of their
+    /// own; the provider's totals still count them.
+    ///
+    /// # Arguments
+    /// * `context_id` - ID of the node whose scene the new ones continue
+    /// * `prompt` - The continuation prompt
+    /// * `ai_provider` - The AI provider to use for generation
+    /// * `premise` - Optional premise to include in generation
+    /// * `count` - Number of scenes to draft
+    /// * `limit` - Maximum number of scenes drafted at a time
+    ///
+    /// # Returns
+    /// The drafts, in the order they were started
+    pub(crate) async fn draft_candidates(
+        &mut self,
+        context_id: &str,
+        prompt: &str,
+        ai_provider: &dyn AIProvider,
+        premise: Option<&str>,
+        count: usize,
+        limit: usize,
+    ) -> Result<Vec<SceneDraft>, StoryChainError> {
+        info!("Generating {} candidates after {}, {} at a time", count, context_id, limit.max(1));
+
+        // Give every candidate its own seed, since they share the prompt
+        let generations: Vec<GenerationConfig> = (0..count).map(|_| self.draw_generation()).collect();
+        let chain = &*self;
+        let drafts = generations.into_iter().enumerate().map(|(candidate, generation)| {
+            async move { chain.draft_scene(context_id, prompt, ai_provider, premise, &generation).await }
+                .instrument(tracing::info_span!(trace::SCENE_SPAN, node = context_id, candidate = candidate + 1))
+        });
+        let mut drafts: Vec<SceneDraft> = stream::iter(drafts).buffered(limit.max(1)).try_collect().await?;
+        for draft in &mut drafts {
+            draft.provenance.usage = None;
         }
-        
+        Ok(drafts)
+    }
+
+    /// Returns the sampling parameters of the next scene draft
+    ///
+    /// With a seed set, every draft gets its own, counting up from the seed,
+    /// so that regenerating a scene from the same prompt draws a different
+    /// one while the run as a whole stays reproducible. The count is kept in
+    /// the chain's metadata, so a resumed run carries on where it stopped.
+    pub fn draw_generation(&mut self) -> GenerationConfig {
+        if self.settings.generation.seed.is_none() {
+            return self.settings.generation.clone();
+        }
+        let draws = self.metadata.get(SEED_DRAWS_KEY).and_then(|d| d.parse().ok()).unwrap_or(0);
+        self.metadata.insert(SEED_DRAWS_KEY.to_string(), (draws + 1).to_string());
+        self.settings.generation.reseeded(draws)
+    }
+
+    /// Returns an unused ID for a new node
+    ///
+    /// IDs count up from the number of nodes, skipping any still taken after
+    /// nodes were removed.
+    pub(crate) fn next_node_id(&self) -> String {
+        (self.nodes.len()..)
+            .map(|n| format!("node_{}", n))
+            .find(|id| !self.nodes.contains_key(id))
+            .expect("an unused node ID exists")
+    }
+
+    /// Builds the variables of the continuation template for a scene that
+    /// continues the given node
+    ///
+    /// # Arguments
+    /// * `context_id` - ID of the node whose scene the new one continues
+    /// * `premise` - Optional premise to include in generation
+    /// * `current_epoch` - Current epoch number
+    /// * `total_epochs` - Total number of epochs planned
+    /// * `guidance` - Guidance notes for the scene
+    pub(crate) fn continuation_context(
+        &self,
+        context_id: &str,
+        premise: Option<&str>,
+        current_epoch: usize,
+        total_epochs: usize,
+        guidance: &[String],
+    ) -> tera::Context {
+        let current_node = &self.nodes[context_id];
+
         // Add story progression context
         let story_phase = match current_epoch {
             e if e <= total_epochs / 3 => "early_game",
             e if e <= (2 * total_epochs) / 3 => "mid_game",
             _ => "end_game"
         };
-        
         let epochs_remaining = total_epochs.saturating_sub(current_epoch);
-        prompt.push_str(&format!(
-            "Story Progress:\n\
-            - Current epoch: {} of {}\n\
-            - Story phase: {}\n\
-            - Epochs remaining: {}\n\n",
-            current_epoch, total_epochs, story_phase, epochs_remaining
-        ));
-        
-        // Construct the prompt for the next scene
-        prompt.push_str(&format!(
-            "You are continuing a story. Here is the previous scene and its reasoning:\n\n\
-            Previous Scene Reasoning:\n{}\n\n\
-            Previous Scene Content:\n{}\n\n\
-            Now continue the story, maintaining consistency with the previous scene and the overall premise.\n\
-            Consider the current story phase ({}) and remaining epochs ({}) when deciding how to progress the plot.\n\n\
-            IMPORTANT: Format your response EXACTLY as follows:\n\
-            <think>\n\
-            Your reasoning about how this scene continues the story and develops the narrative.\n\
-            </think>\n\
-            Write your scene content here, making sure it flows naturally from the previous scene...",
-            current_node.reasoning,
-            current_node.content,
-            story_phase,
-            epochs_remaining
-        ));
+        let arc_stage = pacing::ArcStage::for_epoch(current_epoch, total_epochs);
+
+        let beat = self
+            .settings
+            .foreshadowing
+            .as_ref()
+            .and_then(|plan| plan.planned_beat(current_epoch))
+            .map(|b| b.description.clone())
+            .unwrap_or_default();
+        let mut context = tera::Context::new();
+        prompts::insert_metadata(&mut context, &self.metadata);
+        context.insert("premise", premise.unwrap_or_default());
+        context.insert("summary", self.story_summary(context_id).unwrap_or_default());
+        context.insert("epoch", &current_epoch);
+        context.insert("total_epochs", &total_epochs);
+        context.insert("epochs_remaining", &epochs_remaining);
+        context.insert("phase", story_phase);
+        context.insert("arc_stage", arc_stage.name());
+        context.insert("pacing", &arc_stage.instruction(epochs_remaining));
+        context.insert("last_scene", &current_node.content);
+        context.insert("last_reasoning", &current_node.reasoning);
+        context.insert("beat", &beat);
+        context.insert("recalled", &self.recalled_scenes(context_id));
+        context.insert("characters", &self.prompts.character_section(&current_node.content));
+        context.insert("lore", &self.prompts.lore.lore_section(&format!("{}\n{}", current_node.content, beat)));
+        context.insert("guidance", guidance);
+        context
+    }
+
+    /// Renders the continuation prompt, trimming the context to fit the
+    /// context window when one is configured
+    ///
+    /// # Arguments
+    /// * `context` - Variables of the continuation template
+    pub(crate) fn render_continuation_prompt(&self, context: &mut tera::Context) -> Result<String, StoryChainError> {
+        match self.settings.max_context_tokens {
+            Some(max_context_tokens) => {
+                // Leave room for the response within the context window
+                let budget = max_context_tokens.saturating_sub(self.settings.generation.max_tokens.unwrap_or_default());
+                tokenizer::render_within_budget(
+                    &self.prompts,
+                    prompts::CONTINUATION,
+                    context,
+                    self.settings.system_prompt.as_deref(),
+                    budget,
+                )
+            }
+            None => self.prompts.render(prompts::CONTINUATION, context),
+        }
+    }
+
+    /// Generates a scene attached after one node while continuing from another
+    ///
+    /// # Arguments
+    /// * `current_node_id` - ID of the node the new scene is attached after
+    /// * `context_id` - ID of the node whose scene the new one continues
+    /// * `ai_provider` - The AI provider to use for generation
+    /// * `premise` - Optional premise to include in generation
+    /// * `current_epoch` - Current epoch number
+    /// * `total_epochs` - Total number of epochs planned
+    #[tracing::instrument(name = "scene", skip_all, fields(node = current_node_id, epoch = current_epoch))]
+    pub(crate) async fn generate_scene(
+        &mut self,
+        current_node_id: &str,
+        context_id: &str,
+        ai_provider: &dyn AIProvider,
+        premise: Option<&str>,
+        current_epoch: usize,
+        total_epochs: usize,
+    ) -> Result<Vec<String>, StoryChainError> {
+        let prompt =
+            self.continuation_prompt(current_node_id, context_id, ai_provider, premise, current_epoch, total_epochs).await?;
+        let generation = self.draw_generation();
+        let draft = self.draft_scene(context_id, &prompt, ai_provider, premise, &generation).await?;
+        self.commit_scene(current_node_id, draft, ai_provider, current_epoch, total_epochs).await
+    }
 
+    /// Builds the prompt for a scene attached after one node while continuing
+    /// from another, bringing the running summary and recalled scenes up to date
+    ///
+    /// # Arguments
+    /// * `current_node_id` - ID of the node the new scene is attached after
+    /// * `context_id` - ID of the node whose scene the new one continues
+    /// * `ai_provider` - The AI provider that maintains the summary
+    /// * `premise` - Optional premise to include in generation
+    /// * `current_epoch` - Current epoch number
+    /// * `total_epochs` - Total number of epochs planned
+    pub(crate) async fn continuation_prompt(
+        &mut self,
+        current_node_id: &str,
+        context_id: &str,
+        ai_provider: &dyn AIProvider,
+        premise: Option<&str>,
+        current_epoch: usize,
+        total_epochs: usize,
+    ) -> Result<String, StoryChainError> {
+        debug!("Generating next node for: {} (continuing {})", current_node_id, context_id);
+        self.check_cancelled()?;
+
+        // Get the current and context nodes or return error if not found
+        if !self.nodes.contains_key(current_node_id) {
+            return Err(StoryChainError::AIServerError("Node not found".to_string()));
+        }
+        if !self.nodes.contains_key(context_id) {
+            return Err(StoryChainError::AIServerError("Node not found".to_string()));
+        }
+
+        // Build the prompt: the running summary, recalled scenes, and guidance
+        let prompt = async {
+            // Fold the scenes written since the last update into the running
+            // summary; strands share the summary of every scene before this one
+            let memory_id =
+                if self.nodes[context_id].metadata.contains_key(strands::STRAND_KEY) { current_node_id } else { context_id };
+            self.maintain_summary(memory_id, ai_provider).await?;
+
+            // Embed the storyline so that earlier scenes can be recalled
+            if self.settings.recall.is_some() {
+                self.embed_storyline(context_id).await?;
+            }
+
+            // Collect guidance derived from the chain settings
+            let mut guidance = self.guidance_notes(current_node_id);
+            // Genre beats and tension targeting depend on how far through the run the scene falls
+            guidance.extend(self.genre_guidance(current_epoch, total_epochs));
+            guidance.extend(self.tension_guidance(current_node_id, current_epoch, total_epochs));
+
+            // Construct the prompt for the next scene from the continuation template
+            let mut context = self.continuation_context(context_id, premise, current_epoch, total_epochs, &guidance);
+            context.insert("summary", self.story_summary(memory_id).unwrap_or_default());
+            self.render_continuation_prompt(&mut context)
+        }
+        .instrument(tracing::info_span!(trace::PROMPT_BUILD_SPAN))
+        .await?;
+        self.notify_hooks(|hooks| hooks.prompt_built(prompts::CONTINUATION, &prompt));
+        Ok(prompt)
+    }
+
+    /// Generates a scene from a continuation prompt and passes it through the
+    /// revision passes and content filters, without adding it to the chain,
+    /// until the chain's cancellation token is cancelled
+    ///
+    /// # Arguments
+    /// * `context_id` - ID of the node whose scene the new one continues
+    /// * `prompt` - The continuation prompt
+    /// * `ai_provider` - The AI provider to use for generation
+    /// * `premise` - Optional premise to include in generation
+    /// * `generation` - Sampling parameters of the request, from `draw_generation`
+    pub(crate) async fn draft_scene(
+        &self,
+        context_id: &str,
+        prompt: &str,
+        ai_provider: &dyn AIProvider,
+        premise: Option<&str>,
+        generation: &GenerationConfig,
+    ) -> Result<SceneDraft, StoryChainError> {
+        cancellation::until_cancelled(
+            self.cancellation.as_ref(),
+            self.write_draft(context_id, prompt, ai_provider, premise, generation),
+        )
+        .await
+    }
+
+    /// Generates a scene from a continuation prompt and passes it through the
+    /// revision passes and content filters
+    ///
+    /// # Arguments
+    /// * `context_id` - ID of the node whose scene the new one continues
+    /// * `prompt` - The continuation prompt
+    /// * `ai_provider` - The AI provider to use for generation
+    /// * `premise` - Optional premise to include in generation
+    /// * `generation` - Sampling parameters of the request
+    async fn write_draft(
+        &self,
+        context_id: &str,
+        prompt: &str,
+        ai_provider: &dyn AIProvider,
+        premise: Option<&str>,
+        generation: &GenerationConfig,
+    ) -> Result<SceneDraft, StoryChainError> {
+        let start_time = std::time::Instant::now();
+        let usage_before = ai_provider.usage();
         debug!("Sending prompt to AI provider");
-        let generation_start = std::time::Instant::now();
-        let (reasoning, content) = ai_provider.generate(&prompt).await?;
-        let generation_time = generation_start.elapsed();
-        info!("AI generation took: {:?}", generation_time);
+        let mut provenance = NodeProvenance::new(
+            ai_provider,
+            &self.prompts,
+            prompts::CONTINUATION,
+            self.settings.system_prompt.as_deref(),
+            generation,
+        );
+        let (mut reasoning, mut content) = ai_provider
+            .generate_with_config(self.settings.system_prompt.as_deref(), prompt, generation)
+            .instrument(tracing::info_span!(trace::AI_GENERATE_SPAN))
+            .await?;
+
+        // Revise scenes that drift too far from the dialogue target
+        if let Some(target) = self.settings.dialogue.as_ref().filter(|t| t.revise) {
+            let measured = dialogue::dialogue_ratio(&content);
+            if target.drift(measured).is_some() {
+                (reasoning, content) = dialogue::revise_for_dialogue(&self.prompts, ai_provider, &content, measured, target)
+                    .instrument(tracing::info_span!(trace::REVISE_SPAN, pass = "dialogue"))
+                    .await?;
+                provenance.revised(provenance::DIALOGUE_REVISION);
+            }
+        }
+
+        // Expand or trim scenes that miss the length target
+        let mut length_adjustments = 0;
+        if let Some(target) = self.settings.length.clone() {
+            while length_adjustments < target.max_adjustments && target.miss(length::word_count(&content)).is_some() {
+                (reasoning, content) = length::adjust_length(&self.prompts, ai_provider, &content, &target)
+                    .instrument(tracing::info_span!(trace::REVISE_SPAN, pass = "length"))
+                    .await?;
+                length_adjustments += 1;
+                provenance.revised(provenance::LENGTH_REVISION);
+            }
+        }
+
+        // Check the scene against what the story has established, revising it when requested
+        let mut consistency_issues = Vec::new();
+        let mut consistency_revised = false;
+        if let Some(check) = self.settings.consistency.clone() {
+            consistency_issues = self
+                .find_contradictions(context_id, &content, ai_provider, premise)
+                .instrument(tracing::info_span!(trace::REVISE_SPAN, pass = "consistency_check"))
+                .await?;
+            if check.revise && !consistency_issues.is_empty() {
+                (reasoning, content) = consistency::revise_for_consistency(&self.prompts, ai_provider, &content, &consistency_issues)
+                    .instrument(tracing::info_span!(trace::REVISE_SPAN, pass = "consistency"))
+                    .await?;
+                consistency_issues = self
+                    .find_contradictions(context_id, &content, ai_provider, premise)
+                    .instrument(tracing::info_span!(trace::REVISE_SPAN, pass = "consistency_check"))
+                    .await?;
+                consistency_revised = true;
+                provenance.revised(provenance::CONSISTENCY_REVISION);
+            }
+        }
         
-        // Create new node with unique ID
-        let new_id = format!("node_{}", self.nodes.len());
-        debug!("Creating new node: {}", new_id);
+        // Let the processors of custom artifacts adjust the scene
+        let content = self.prompts.post_process(content);
+
+        // Screen the scene before it is committed, regenerating or redacting it
+        let screened = self
+            .screen_scene(ai_provider, prompt, reasoning, content)
+            .instrument(tracing::info_span!(trace::REVISE_SPAN, pass = "content_filter"))
+            .await?;
+        if screened.rejections > 0 || screened.matched.is_some() {
+            provenance.revised(provenance::FILTER_REVISION);
+        }
+        Ok(SceneDraft {
+            screened,
+            provenance: provenance.with_duration(start_time.elapsed()).with_usage(usage_before, ai_provider.usage()),
+            length_adjustments,
+            consistency_issues,
+            consistency_revised,
+        })
+    }
+
+    /// Adds a drafted scene after a node, checking it against the chain's
+    /// settings and recording its setups and metadata
+    ///
+    /// # Arguments
+    /// * `current_node_id` - ID of the node the new scene is attached after
+    /// * `draft` - The drafted scene
+    /// * `ai_provider` - The AI provider that enriches the scene
+    /// * `current_epoch` - Current epoch number
+    /// * `total_epochs` - Total number of epochs planned
+    pub(crate) async fn commit_scene(
+        &mut self,
+        current_node_id: &str,
+        draft: SceneDraft,
+        ai_provider: &dyn AIProvider,
+        current_epoch: usize,
+        total_epochs: usize,
+    ) -> Result<Vec<String>, StoryChainError> {
+        let SceneDraft { screened, provenance, length_adjustments, consistency_issues, consistency_revised } = draft;
+        let (reasoning, content) = (screened.reasoning, screened.content);
+
+        // Read the scene card, setups, and viewpoint out of the scene and check it
+        let (new_id, new_node, setup_markers) = tracing::info_span!(trace::PARSE_SPAN).in_scope(|| {
+            // Create new node with unique ID
+            let new_id = self.next_node_id();
+            debug!("Creating new node: {}", new_id);
+
+            // Pull setup and payoff markers out of the scene
+            let (content, setup_markers) = if self.settings.track_setups {
+                setups::extract_setup_markers(&content)
+            } else {
+                (content, setups::SetupMarkers::default())
+            };
+
+            // Separate the scene card from the scene content
+            let (content, scene_card) = if self.settings.scene_cards {
+                scene_cards::split_scene_card(&content)
+            } else {
+                (content, None)
+            };
+
+            // Record the viewpoint character of the new scene
+            let (content, pov) = self.resolve_pov(current_node_id, content);
+
+            // Check character names against the registry before committing the scene
+            let (content, name_issues) = self.character_registry.review_scene(&new_id, content);
+            let mut metadata = HashMap::new();
+            if let Some(pov) = pov {
+                metadata.insert("pov".to_string(), pov);
+            }
+            if !name_issues.is_empty() {
+                let issues: Vec<String> = name_issues.iter().map(|i| i.to_string()).collect();
+                metadata.insert("name_issues".to_string(), issues.join("; "));
+            }
+            if !consistency_issues.is_empty() {
+                metadata.insert(consistency::CONSISTENCY_ISSUES_KEY.to_string(), consistency_issues.join("; "));
+            }
+            if consistency_revised {
+                metadata.insert(consistency::CONSISTENCY_REVISED_KEY.to_string(), "true".to_string());
+            }
+            if screened.rejections > 0 {
+                metadata.insert(filters::FILTER_REJECTIONS_KEY.to_string(), screened.rejections.to_string());
+            }
+            if let Some(matched) = screened.matched {
+                metadata.insert(filters::FILTER_MATCHES_KEY.to_string(), matched);
+            }
+
+            // Flag scenes whose card is missing or incomplete
+            if self.settings.scene_cards {
+                let missing = scene_card.as_ref().map(|c| c.missing_fields()).unwrap_or_else(|| vec!["card"]);
+                if !missing.is_empty() {
+                    warn!("Scene {} has an incomplete scene card: missing {}", new_id, missing.join(", "));
+                    metadata.insert("scene_card_missing".to_string(), missing.join(", "));
+                }
+            }
+
+            // Record the dialogue ratio when a target is configured
+            if self.settings.dialogue.is_some() {
+                metadata.insert("dialogue_ratio".to_string(), format!("{:.3}", dialogue::dialogue_ratio(&content)));
+            }
+
+            // Record the length of the scene when a target is configured
+            if let Some(target) = &self.settings.length {
+                let words = length::word_count(&content);
+                if target.miss(words).is_some() {
+                    warn!("Scene {} is {} words, outside the target of {}", new_id, words, target.words);
+                }
+                metadata.insert(length::WORD_COUNT_KEY.to_string(), words.to_string());
+                if length_adjustments > 0 {
+                    metadata.insert(length::LENGTH_ADJUSTMENTS_KEY.to_string(), length_adjustments.to_string());
+                }
+            }
+
+            // Validate the scene against the chain's structural format
+            let format_issues = self.settings.format.validate(&content);
+            if !format_issues.is_empty() {
+                warn!("Scene {} does not follow the {:?} format: {}", new_id, self.settings.format, format_issues.join("; "));
+                metadata.insert("format_issues".to_string(), format_issues.join("; "));
+            }
+
+            // Check the scene against the chain's generation rules
+            if let Some(constraints) = &self.settings.constraints {
+                let violations: Vec<String> = constraints
+                    .validate(&content)
+                    .iter()
+                    .map(|v| format!("{} ({})", v.rule, v.detail))
+                    .collect();
+                if !violations.is_empty() {
+                    warn!("Scene {} broke generation rules: {}", new_id, violations.join("; "));
+                    metadata.insert("constraint_violations".to_string(), violations.join("; "));
+                }
+            }
+
+            // Check the scene against the style guide
+            if let Some(style) = &self.prompts.style {
+                let style_issues = style.check(&content);
+                if !style_issues.is_empty() {
+                    warn!("Scene {} departs from the style guide: {}", new_id, style_issues.join("; "));
+                    metadata.insert(style::STYLE_ISSUES_KEY.to_string(), style_issues.join("; "));
+                }
+            }
+
+            // Score the scene against the tension curve
+            if let Some(curve) = &self.settings.tension_curve {
+                let target = curve.target_at(current_epoch as f64 / total_epochs.max(1) as f64);
+                metadata.insert("target_tension".to_string(), format!("{:.1}", target));
+                metadata.insert("tension".to_string(), format!("{:.1}", stats::estimate_tension(&content)));
+            }
+
+            // Record which planned beats this scene was asked to foreshadow
+            let beats = self.upcoming_beats(current_node_id);
+            if !beats.is_empty() {
+                metadata.insert("foreshadows".to_string(), foreshadowing::join_beats(&beats));
+            }
         
-        let new_node = StoryNode {
-            id: new_id.clone(),
-            content,
-            reasoning,
-            predecessor: Some(current_node_id.to_string()),
-            successor: None,
-            metadata: HashMap::new(),
-        };
+            let new_node = StoryNode {
+                id: new_id.clone(),
+                content,
+                reasoning,
+                predecessors: vec![current_node_id.to_string()],
+                successors: Vec::new(),
+                metadata,
+                feedback: Vec::new(),
+                scene_card,
+                embedding: None,
+                provenance: Some(provenance),
+            };
+            (new_id, new_node, setup_markers)
+        });
         
-        // Update the current node's successor reference
+        // Add the new node to the current node's successors, after any alternatives
         if let Some(node) = self.nodes.get_mut(current_node_id) {
-            node.successor = Some(new_id.clone());
+            node.successors.push(new_id.clone());
             debug!("Updated successor for node: {}", current_node_id);
         }
 
         self.nodes.insert(new_id.clone(), new_node);
-        let total_time = start_time.elapsed();
-        info!("Total node generation took: {:?}", total_time);
+        self.record_setup_markers(&new_id, &setup_markers);
+
+        // Record who, where, when, and the mood of the scene
+        if let Some(mode) = self.settings.enrichment {
+            self.enrich_node(&new_id, mode, ai_provider).await?;
+        }
+        self.notify_hooks(|hooks| hooks.node_generated(self, &new_id));
         Ok(vec![new_id])
     }
 
     /// Exports the story chain to a JSON file
+    ///
+    /// The file is replaced atomically, and compressed when its name ends
+    /// in `.json.gz`, or `.json.zst` with the `zstd` feature.
     pub fn export_to_file(&self, path: &str) -> Result<(), StoryChainError> {
         info!("Exporting story chain to file: {}", path);
         let serialized = serde_json::to_string_pretty(&self)?;
-        std::fs::write(path, serialized)?;
+        files::write_compressed(path, serialized.as_bytes())?;
         info!("Successfully exported story chain");
         Ok(())
     }
 
+    /// Loads a story chain previously exported with `export_to_file`
+    ///
+    /// Compressed files are decompressed, and files written in an older
+    /// format are migrated to the current one.
+    /// Prompt templates are not saved with the chain; the loaded chain uses
+    /// the built-in ones until others are assigned.
+    ///
+    /// # Arguments
+    /// * `path` - The path of the JSON file
+    pub fn load_from_file(path: &str) -> Result<Self, StoryChainError> {
+        info!("Loading story chain from file: {}", path);
+        let chain = migrations::chain_from_value(serde_json::from_slice(&files::read_decompressed(path)?)?)?;
+        if !chain.nodes.contains_key(&chain.root_node_id) {
+            return Err(StoryChainError::InvalidChainOperation(format!(
+                "{} has no root node {}",
+                path, chain.root_node_id
+            )));
+        }
+        info!("Loaded {} nodes", chain.nodes.len());
+        Ok(chain)
+    }
+
+    /// Returns the number of epochs generated so far
+    ///
+    /// Uses the count recorded in the chain's `epochs_completed` metadata,
+    /// falling back to the number of main-plot scenes after the opening in
+    /// the longest storyline.
+    pub fn completed_epochs(&self) -> usize {
+        if let Some(completed) = self.metadata.get(EPOCHS_COMPLETED_KEY).and_then(|c| c.parse().ok()) {
+            return completed;
+        }
+        self.storylines()
+            .iter()
+            .map(|line| line.iter().filter(|n| !n.metadata.contains_key("subplot")).count())
+            .max()
+            .unwrap_or(1)
+            .saturating_sub(1)
+    }
+
     /// Exports the story chain to a markdown file
     /// 
     /// # Arguments
     /// * `path` - The path where the markdown file should be saved
     pub fn export_to_markdown(&self, path: &str) -> Result<(), StoryChainError> {
+        self.export_to_markdown_with_options(path, &MarkdownOptions::default())
+    }
+
+    /// Exports the story chain to a markdown file using the given options
+    /// 
+    /// # Arguments
+    /// * `path` - The path where the markdown file should be saved
+    /// * `options` - Controls which optional sections are rendered
+    pub fn export_to_markdown_with_options(
+        &self,
+        path: &str,
+        options: &MarkdownOptions,
+    ) -> Result<(), StoryChainError> {
         let mut content = String::new();
         
-        // Add header
-        content.push_str("# Generated Story\n\n");
+        // Add header, preferring the generated title when one exists
+        let title = self.metadata.get("title").map(String::as_str).unwrap_or("Generated Story");
+        content.push_str(&format!("# {}\n\n", title));
+        if let Some(logline) = self.metadata.get("logline") {
+            content.push_str(&format!("> {}\n\n", logline));
+        }
         content.push_str(&format!("*Generated on {}*\n\n", chrono::Local::now().format("%Y-%m-%d %H:%M:%S")));
+        if let Some(blurb) = self.metadata.get("blurb") {
+            content.push_str(blurb);
+            content.push_str("\n\n");
+        }
+        for (key, label) in [("genre_tags", "Genres"), ("content_warnings", "Content warnings")] {
+            if let Some(value) = self.metadata.get(key).filter(|v| !v.is_empty()) {
+                content.push_str(&format!("**{}:** {}  \n", label, value));
+            }
+        }
+        if self.metadata.contains_key("genre_tags") {
+            content.push('\n');
+        }
         content.push_str("---\n\n");
 
-        // Start with root node
-        let mut current_id = &self.root_node_id;
-        let mut scene_num = 1;
-
         // Process each node in sequence
-        while let Some(node) = self.nodes.get(current_id) {
+        let (act_level, chapter_level, scene_level) = self.heading_levels();
+        for (index, node) in self.nodes_in_reading_order().into_iter().enumerate() {
+            // Add the act and chapter headings, and a recap of the previous
+            // chapter, when a new chapter starts
+            if let Some((chapter_index, chapter, act)) = self.chapter_starting_at(&node.id) {
+                if let Some(act) = act {
+                    content.push_str(&format!("{} {}\n\n", "#".repeat(act_level), act.trim()));
+                }
+                content.push_str(&format!("{} {}\n\n", "#".repeat(chapter_level), chapter.title.trim()));
+                if let Some(recap) = self.chapter_recap(chapter_index).filter(|_| options.chapter_recaps) {
+                    content.push_str(&format!("> *Previously:* {}\n\n", recap.replace('\n', " ")));
+                }
+            }
+
             // Add scene header
-            content.push_str(&format!("## Scene {}\n\n", scene_num));
+            content.push_str(&format!("{} Scene {}\n\n", "#".repeat(scene_level), index + 1));
+            if let Some(pov) = node.metadata.get("pov") {
+                content.push_str(&format!("*{}*\n\n", pov));
+            }
             
             // Add scene content
             content.push_str(&node.content);
             content.push_str("\n\n");
             
             // Add AI's reasoning in a collapsible section
-            content.push_str("<details>\n<summary>AI's Reasoning</summary>\n\n");
-            content.push_str(&node.reasoning);
-            content.push_str("\n</details>\n\n---\n\n");
-            
-            // Move to next node if it exists
-            if let Some(next_id) = &node.successor {
-                current_id = next_id;
-                scene_num += 1;
-            } else {
-                break;
+            if options.include_reasoning {
+                content.push_str("<details>\n<summary>AI's Reasoning</summary>\n\n");
+                content.push_str(&node.reasoning);
+                content.push_str("\n</details>\n\n");
             }
+            content.push_str("---\n\n");
+        }
+
+        // Add the alternative branches that leave the main storyline
+        content.push_str(&self.alternative_branches_markdown());
+
+        // Add the glossary appendix when one has been generated
+        if !self.glossary.is_empty() {
+            content.push_str(&self.glossary_markdown());
+        }
+
+        // Add the reading-time and pacing report when requested
+        if let Some(words_per_minute) = options.stats_words_per_minute {
+            content.push_str(&self.stats(words_per_minute).to_markdown());
         }
 
         // Write to file
-        std::fs::write(path, content)?;
+        files::write_atomic(path, content)?;
         Ok(())
     }
-} 
\ No newline at end of file
+}
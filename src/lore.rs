@@ -0,0 +1,193 @@
+//! Lore Retrieval
+//!
+//! This module keeps the facts established in `WorldBuilding` artifacts in
+//! front of the model without sending the whole lore bible with every prompt.
+//! Artifacts are split into paragraph-sized chunks and indexed by keyword,
+//! and each prompt includes the few chunks that score highest (BM25) against
+//! the text being continued.
+
+use std::collections::{HashMap, HashSet};
+use log::debug;
+use crate::artifacts::{ArtifactManager, ArtifactType};
+
+/// Number of lore snippets included in each prompt unless configured otherwise
+pub const DEFAULT_LORE_SNIPPETS: usize = 3;
+
+/// Longest chunk, in words; longer paragraphs are split at sentence ends
+const MAX_CHUNK_WORDS: usize = 120;
+
+/// BM25 term-frequency saturation
+const K1: f64 = 1.2;
+
+/// BM25 length normalization
+const B: f64 = 0.75;
+
+/// Words too common to tell chunks apart
+const STOP_WORDS: [&str; 40] = [
+    "the", "and", "for", "are", "but", "not", "you", "all", "any", "can", "her", "was", "one", "our", "out", "his",
+    "has", "had", "him", "its", "who", "did", "get", "how", "she", "they", "them", "their", "there", "then", "than",
+    "that", "this", "with", "from", "have", "were", "what", "when", "into",
+];
+
+/// Splits text into lowercase keywords, dropping short and common words
+fn keywords(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 2)
+        .map(str::to_lowercase)
+        .filter(|word| !STOP_WORDS.contains(&word.as_str()))
+        .collect()
+}
+
+/// Splits lore text into chunks: one per paragraph, with paragraphs longer
+/// than `MAX_CHUNK_WORDS` split after the sentence that passes half of it
+///
+/// # Arguments
+/// * `text` - The lore text
+pub fn chunk_lore(text: &str) -> Vec<String> {
+    let mut chunks = Vec::new();
+    for paragraph in text.split("\n\n").map(str::trim).filter(|p| !p.is_empty()) {
+        let mut chunk: Vec<&str> = Vec::new();
+        for word in paragraph.split_whitespace() {
+            chunk.push(word);
+            let sentence_end = word.ends_with(['.', '!', '?']);
+            if chunk.len() >= MAX_CHUNK_WORDS || (sentence_end && chunk.len() >= MAX_CHUNK_WORDS / 2) {
+                chunks.push(chunk.join(" "));
+                chunk.clear();
+            }
+        }
+        if !chunk.is_empty() {
+            chunks.push(chunk.join(" "));
+        }
+    }
+    chunks
+}
+
+/// A piece of lore that can be retrieved on its own
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoreChunk {
+    /// ID of the artifact the chunk comes from
+    pub artifact_id: String,
+
+    /// The text of the chunk
+    pub text: String,
+
+    /// Occurrences of each keyword in the chunk
+    term_counts: HashMap<String, usize>,
+
+    /// Number of keywords in the chunk
+    length: usize,
+}
+
+/// Keyword index over the chunks of the lore artifacts
+#[derive(Debug, Clone)]
+pub struct LoreIndex {
+    /// Indexed chunks in the order they were added
+    chunks: Vec<LoreChunk>,
+
+    /// Number of chunks each keyword occurs in
+    document_frequency: HashMap<String, usize>,
+
+    /// Number of snippets included in each prompt; 0 disables retrieval
+    pub top_k: usize,
+}
+
+impl Default for LoreIndex {
+    fn default() -> Self {
+        Self { chunks: Vec::new(), document_frequency: HashMap::new(), top_k: DEFAULT_LORE_SNIPPETS }
+    }
+}
+
+impl LoreIndex {
+    /// Chunks and indexes a lore text
+    ///
+    /// # Arguments
+    /// * `artifact_id` - ID of the artifact the text comes from
+    /// * `text` - The lore text
+    pub fn add_document(&mut self, artifact_id: &str, text: &str) {
+        for chunk in chunk_lore(text) {
+            let words = keywords(&chunk);
+            let mut term_counts = HashMap::new();
+            for word in &words {
+                *term_counts.entry(word.clone()).or_insert(0) += 1;
+            }
+            for term in term_counts.keys() {
+                *self.document_frequency.entry(term.clone()).or_insert(0) += 1;
+            }
+            self.chunks.push(LoreChunk { artifact_id: artifact_id.to_string(), text: chunk, term_counts, length: words.len() });
+        }
+    }
+
+    /// Indexes every `WorldBuilding` artifact
+    ///
+    /// # Arguments
+    /// * `artifact_manager` - The artifact manager to read artifacts from
+    pub fn add_artifacts(&mut self, artifact_manager: &ArtifactManager) {
+        for artifact in artifact_manager.get_artifacts_by_type(&ArtifactType::WorldBuilding) {
+            self.add_document(&artifact.id, &artifact.content);
+        }
+        debug!("Indexed {} lore chunks", self.chunks.len());
+    }
+
+    /// Returns the number of indexed chunks
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Returns true if no lore has been indexed
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Finds the chunks most relevant to a text
+    ///
+    /// # Arguments
+    /// * `query` - The text to find lore for, such as the previous scene
+    /// * `k` - Maximum number of chunks to return
+    ///
+    /// # Returns
+    /// The chunks sharing keywords with the query, best first
+    pub fn retrieve(&self, query: &str, k: usize) -> Vec<&LoreChunk> {
+        if self.chunks.is_empty() || k == 0 {
+            return Vec::new();
+        }
+        let query_terms: HashSet<String> = keywords(query).into_iter().collect();
+        let chunk_count = self.chunks.len() as f64;
+        let average_length = self.chunks.iter().map(|c| c.length).sum::<usize>() as f64 / chunk_count;
+
+        let mut scored: Vec<(f64, &LoreChunk)> = self
+            .chunks
+            .iter()
+            .map(|chunk| {
+                let score: f64 = query_terms
+                    .iter()
+                    .filter_map(|term| {
+                        let count = *chunk.term_counts.get(term)? as f64;
+                        let frequency = self.document_frequency[term] as f64;
+                        let idf = ((chunk_count - frequency + 0.5) / (frequency + 0.5) + 1.0).ln();
+                        let norm = K1 * (1.0 - B + B * chunk.length as f64 / average_length.max(1.0));
+                        Some(idf * count * (K1 + 1.0) / (count + norm))
+                    })
+                    .sum();
+                (score, chunk)
+            })
+            .filter(|(score, _)| *score > 0.0)
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.into_iter().take(k).map(|(_, chunk)| chunk).collect()
+    }
+
+    /// Lays out the `top_k` chunks most relevant to a text for a prompt
+    ///
+    /// # Arguments
+    /// * `query` - The text to find lore for
+    ///
+    /// # Returns
+    /// One line per chunk, or an empty string when none is relevant
+    pub fn lore_section(&self, query: &str) -> String {
+        self.retrieve(query, self.top_k)
+            .iter()
+            .map(|chunk| format!("- {}", chunk.text))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
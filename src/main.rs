@@ -4,9 +4,67 @@
 //! linear narratives using AI models. The application takes a premise file as input
 //! and generates a sequence of connected scenes that form a coherent story.
 
-use storychain::{StoryChain, DeepseekProvider, AIProvider, StoryChainError};
-use log::info;
-use clap::{Command, Arg};
+use storychain::{StoryChain, AIProvider, StoryChainError, GenerationConfig, ArtifactManager, MarkdownOptions, CharacterRegistry, NodeProvenance, PromptTemplate, PromptTemplates};
+use storychain::EPOCHS_KEY;
+use storychain::interactive::{InteractiveSession, Review, Reviewer};
+use storychain::review::STEERING_KEY;
+use storychain::passes::SynopsisLength;
+use storychain::stats::DEFAULT_WORDS_PER_MINUTE;
+use storychain::pov::{PovMode, PovSchedule};
+use storychain::formats::StoryFormat;
+use storychain::dialogue::DialogueTarget;
+use storychain::length::LengthTarget;
+use storychain::style::STYLE_ISSUES_KEY;
+use storychain::setups::SetupSource;
+use storychain::foreshadowing::ForeshadowingPlan;
+use storychain::subplots::{Subplot, DEFAULT_SUBPLOT_INTERVAL};
+use storychain::strands::{Strand, StrandOrder};
+use storychain::tension::TensionCurve;
+use storychain::memory::{RollingSummary, DEFAULT_SUMMARY_WORDS};
+use storychain::genres::GenrePreset;
+use storychain::graph::DEFAULT_COLOR_KEY;
+use storychain::exports::ExportFormat;
+use storychain::consistency::ConsistencyCheck;
+use storychain::enrichment::EnrichmentMode;
+use storychain::constraints::{ConstraintSet, ContentRating};
+use storychain::filters::{ContentFilterSettings, FilterAction, KeywordFilter, DEFAULT_FILTER_RETRIES};
+use storychain::project::Project;
+use storychain::usage::UsageTracker;
+use storychain::interaction_log::InteractionLogger;
+use storychain::trace::{self, TraceRecorder};
+use storychain::runner::StoryRunner;
+use storychain::compare::{comparison_markdown, generate_comparison_run, interleaved_markdown};
+use storychain::selection::CandidateSelection;
+use storychain::cancellation::{CancellableProvider, CancellationToken};
+use storychain::events::RunEvent;
+use storychain::translation::language_name;
+use storychain::illustrations::{HttpImageProvider, DEFAULT_IMAGE_SIZE};
+use tracing::Instrument;
+use tracing_subscriber::layer::SubscriberExt;
+use storychain::files::{read_decompressed, uncompressed_path, write_atomic, GZIP_EXTENSION, ZSTD_EXTENSION};
+use storychain::migrations::{migrate, schema_version, SCHEMA_VERSION};
+use storychain::storage::{open_store, ChainStore, JsonFileStore};
+use storychain::config::{ProviderFactory, ProviderKind, StoryChainConfig, DEFAULT_CONFIG_FILE};
+use storychain::providers::{
+    clear_cache, CachingProvider, RecordingProvider, ReplayProvider, RetryingProvider, StreamingProvider,
+    DEFAULT_CACHE_DIR, DEFAULT_MAX_ATTEMPTS,
+};
+use storychain::revision::{RevisionCriteria, DEFAULT_REVISION_ROUNDS};
+use storychain::quality::{QualityGate, DEFAULT_MAX_RETRIES};
+use storychain::progress::{init_logging, scenes_left, RunProgress};
+use storychain::lore::DEFAULT_LORE_SNIPPETS;
+use storychain::parsers::ResponseFormat;
+use storychain::prompts::{CONTINUATION, INITIAL};
+use storychain::embeddings::{OllamaEmbeddingProvider, SceneRecall, DEFAULT_EMBEDDING_MODEL, DEFAULT_RECALL_EXCERPT_CHARS};
+use storychain::premise::{load_premise, premise_name, resolve_premise_path, Premise, STDIN_PREMISE};
+use storychain::characters::slugify;
+use storychain::wizard::{generate_character_arcs, PremiseInterview, DEFAULT_INTERVIEW_QUESTIONS};
+use std::path::Path;
+use indicatif::MultiProgress;
+use std::sync::Arc;
+use log::{info, warn};
+use clap::{Arg, ArgMatches, Command};
+use clap::parser::ValueSource;
 
 /// The main entry point for the StoryChain application.
 /// 
@@ -15,121 +73,2882 @@ use clap::{Command, Arg};
 /// or file operations.
 #[tokio::main]
 async fn main() -> Result<(), StoryChainError> {
-    // Initialize logging system for application-wide logging
-    env_logger::init();
+    // Initialize logging system for application-wide logging, printed above the progress bar
+    let bars = init_logging()?;
     info!("Starting StoryChain application");
 
     // Set up command-line argument parsing using clap
     let matches = Command::new("storychain")
         .version("0.1.0")
-        .about("Generates a linear narrative using AI")
+        .about("Generates narratives using AI")
+        .subcommand_required(true)
+        .arg_required_else_help(true)
         .arg(
-            // Required premise file argument that specifies the story's foundation
-            Arg::new("premise")
-                .help("The premise file to use")
-                .required(true)
-                .index(1),
+            Arg::new("project")
+                .long("project")
+                .global(true)
+                .help("Work in this project directory (see `init`): its artifacts, configuration, and chains; other paths are relative to it"),
         )
-        .arg(
-            // Optional number of epochs (story generation iterations)
-            Arg::new("epochs")
-                .long("epochs")
-                .help("Number of epochs to generate")
-                .default_value("5")
-                .value_parser(clap::value_parser!(usize)),
+        .subcommand(
+            // A new project directory
+            Command::new("init")
+                .about("Create a project directory with a premise to fill in, a configuration, and directories for artifacts, chains, and checkpoints")
+                .arg(
+                    Arg::new("name")
+                        .help("Name of the project and of its premise")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("dir")
+                        .long("dir")
+                        .help("Directory of the project (default: the project name)"),
+                ),
         )
-        .arg(
-            // Optional output file path for the generated story
-            Arg::new("output")
-                .long("output")
-                .help("Output file path")
-                .default_value("story.json"),
+        .subcommand(
+            // A new story from a premise
+            Command::new("generate")
+                .about("Generate a new story from a premise")
+                .arg(premise_arg(1))
+                .args(generation_args())
+                .args(provider_args()),
+        )
+        .subcommand(
+            // More epochs of a saved story
+            Command::new("continue")
+                .about("Continue a saved story, generating the epochs it is missing")
+                .arg(
+                    Arg::new("story")
+                        .help("The story to continue: a JSON file, or a markdown export edited by hand")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(premise_arg(2))
+                .args(generation_args())
+                .args(provider_args()),
+        )
+        .subcommand(
+            // Human-in-the-loop generation, one approved scene at a time
+            Command::new("interactive")
+                .about("Generate a story one scene at a time, accepting, rejecting, editing, or steering each scene")
+                .arg(premise_arg(1))
+                .arg(
+                    Arg::new("epochs")
+                        .long("epochs")
+                        .help("Number of epochs to generate")
+                        .default_value("5")
+                        .value_parser(clap::value_parser!(usize)),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .help("Output file path")
+                        .default_value("story.json"),
+                )
+                .arg(
+                    Arg::new("resume")
+                        .long("resume")
+                        .help("Continue the story in this JSON file, or in a markdown export edited by hand"),
+                )
+                .args(provider_args()),
+        )
+        .subcommand(
+            // Exports of a saved story
+            Command::new("convert")
+                .about("Convert a saved story to another format")
+                .arg(
+                    Arg::new("story")
+                        .help("The story to convert: a JSON file, or a markdown export")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("to")
+                        .long("to")
+                        .help("Format to convert to")
+                        .default_value("markdown")
+                        .value_parser(clap::builder::PossibleValuesParser::new(ExportFormat::NAMES)),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .help("Output file path (default: the story file with the format's extension)"),
+                )
+                .arg(
+                    Arg::new("color-by")
+                        .long("color-by")
+                        .help("Node metadata key that colors the dot and mermaid graphs")
+                        .default_value(DEFAULT_COLOR_KEY),
+                )
+                .arg(
+                    Arg::new("premise")
+                        .long("premise")
+                        .help("Premise the story was generated from, for the prompts of the jsonl format: a file path, - for standard input, or a name in the artifacts directory"),
+                ),
+        )
+        .subcommand(
+            // Translation of a saved story
+            Command::new("translate")
+                .about("Translate a saved story into another language")
+                .arg(
+                    Arg::new("story")
+                        .help("The story to translate: a JSON file, or a markdown export")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("to")
+                        .long("to")
+                        .help("Language to translate into: an ISO 639-1 code such as de, or a language name")
+                        .required(true),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .help("Output file path (default: the story file with the language code before its extension)"),
+                )
+                .args(provider_args()),
+        )
+        .subcommand(
+            // Audiobook of a saved story
+            Command::new("audiobook")
+                .about("Read a saved story aloud into an audiobook directory")
+                .arg(
+                    Arg::new("story")
+                        .help("The story to read: a JSON file, or a markdown export")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .help("Directory to write the audiobook to (default: the story file's name with _audiobook)"),
+                )
+                .arg(
+                    Arg::new("piper-model")
+                        .long("piper-model")
+                        .help("Piper voice model (.onnx) to read with, running piper locally")
+                        .required_unless_present("tts-url")
+                        .conflicts_with("tts-url"),
+                )
+                .arg(
+                    Arg::new("speaker")
+                        .long("speaker")
+                        .help("Speaker of a multi-speaker Piper voice model")
+                        .value_parser(clap::value_parser!(u32))
+                        .requires("piper-model"),
+                )
+                .arg(
+                    Arg::new("tts-url")
+                        .long("tts-url")
+                        .help("OpenAI-compatible speech endpoint to read with, e.g. http://localhost:8880/v1/audio/speech; the API key is read from TTS_API_KEY"),
+                )
+                .arg(
+                    Arg::new("tts-model")
+                        .long("tts-model")
+                        .help("Speech model of the endpoint")
+                        .default_value("tts-1"),
+                )
+                .arg(
+                    Arg::new("voice")
+                        .long("voice")
+                        .help("Voice of the endpoint")
+                        .default_value("alloy"),
+                ),
+        )
+        .subcommand(
+            // The same story from several models
+            Command::new("compare")
+                .about("Generate the same story with several models and write a side-by-side report")
+                .arg(
+                    Arg::new("premise")
+                        .long("premise")
+                        .help("The premise: a file path, - for standard input, or a name in the artifacts directory (default: the project's)")
+                        .required_unless_present("project"),
+                )
+                .arg(
+                    Arg::new("models")
+                        .long("models")
+                        .help("Comma-separated models to compare, e.g. deepseek-r1:32b,qwq:32b")
+                        .required(true)
+                        .value_delimiter(','),
+                )
+                .arg(
+                    Arg::new("epochs")
+                        .long("epochs")
+                        .help("Number of scenes to generate after the opening scene")
+                        .default_value("5")
+                        .value_parser(clap::value_parser!(usize)),
+                )
+                .arg(
+                    Arg::new("output")
+                        .long("output")
+                        .help("Output file path for the report")
+                        .default_value("comparison.md"),
+                )
+                .arg(
+                    Arg::new("interleaved")
+                        .long("interleaved")
+                        .help("Also export every model's version of each scene one after another")
+                        .action(clap::ArgAction::SetTrue),
+                )
+                .args(provider_args()),
+        )
+        .subcommand(
+            // Summary of a saved story
+            Command::new("inspect")
+                .about("Show the statistics and structure of a saved story, or one of its scenes")
+                .arg(
+                    Arg::new("story")
+                        .help("The story to inspect: a JSON file, or a markdown export")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("node")
+                        .long("node")
+                        .help("ID of a scene to show in full"),
+                ),
+        )
+        .subcommand(
+            // Reading time and pacing of a saved story
+            Command::new("stats")
+                .about("Print the reading-time and pacing report of a saved story")
+                .arg(
+                    Arg::new("story")
+                        .help("The story: a JSON file, or a markdown export")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("words-per-minute")
+                        .long("words-per-minute")
+                        .help("Reading speed the reading times are based on (default: 238)")
+                        .value_parser(clap::value_parser!(usize)),
+                ),
+        )
+        .subcommand(
+            // Reading order of a saved story
+            Command::new("reorder")
+                .about("Set the order a saved story's scenes are read in, or restore the generation order")
+                .arg(
+                    Arg::new("story")
+                        .help("The JSON story file, or with --store the name of a stored story")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("order")
+                        .help("Comma-separated node IDs in reading order, or causal for the generation order")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    Arg::new("store")
+                        .long("store")
+                        .help("Read and save the story in this store, such as sqlite://stories.db or a directory, instead of its file"),
+                ),
+        )
+        .subcommand(
+            // Character sheets of a finished story
+            Command::new("characters")
+                .about("Write a character sheet for each character the story established, merged with the existing ones")
+                .arg(
+                    Arg::new("story")
+                        .help("The story: a JSON file, a markdown export, or with --store the name of a stored story")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("dir")
+                        .long("dir")
+                        .help("Artifacts directory the sheets are written to")
+                        .default_value("artifacts"),
+                )
+                .arg(
+                    Arg::new("store")
+                        .long("store")
+                        .help("Read the story from this store, such as sqlite://stories.db or a directory, instead of its file"),
+                )
+                .args(provider_args()),
+        )
+        .subcommand(
+            // Reader ratings of scenes
+            Command::new("feedback")
+                .about("Rate a scene of a saved story from 1 to 5, with an optional comment, for --feedback-guidance to respond to")
+                .arg(
+                    Arg::new("story")
+                        .help("The JSON story file")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("node")
+                        .help("ID of the scene to rate")
+                        .required(true)
+                        .index(2),
+                )
+                .arg(
+                    Arg::new("rating")
+                        .help("Rating from 1 (poor) to 5 (excellent)")
+                        .required(true)
+                        .value_parser(clap::value_parser!(u8))
+                        .index(3),
+                )
+                .arg(
+                    Arg::new("comment")
+                        .help("What the reader thought of the scene")
+                        .index(4),
+                ),
+        )
+        .subcommand(
+            // Terminal browser of a saved story
+            Command::new("tui")
+                .about("Browse a saved story in the terminal, marking scenes for regeneration and continuing it")
+                .arg(
+                    Arg::new("story")
+                        .help("The story to browse: a JSON file, or a markdown export")
+                        .required(true)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("premise")
+                        .long("premise")
+                        .help("Premise the story was generated from, for regenerating and continuing it: a file path or a name in the artifacts directory"),
+                )
+                .args(provider_args()),
+        )
+        .subcommand(
+            // The artifacts directory
+            Command::new("artifacts")
+                .about("List the artifacts, or show one of them")
+                .arg(
+                    Arg::new("id")
+                        .help("ID of the artifact to show")
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("dir")
+                        .long("dir")
+                        .help("Artifacts directory")
+                        .global(true)
+                        .default_value("artifacts"),
+                )
+                .subcommand(
+                    Command::new("check")
+                        .about("Check that the artifacts' references resolve without cycles, and list them in the order prompts present them"),
+                )
+                .subcommand(
+                    Command::new("search")
+                        .about("Find the artifacts whose content, metadata, or tags contain every word of the query")
+                        .arg(
+                            Arg::new("query")
+                                .help("Words to search for, ignoring case; tag:<name> keeps only artifacts with that tag")
+                                .required(true)
+                                .num_args(1..)
+                                .index(1),
+                        ),
+                )
+                .subcommand(
+                    Command::new("tag")
+                        .about("Add tags to an artifact, or remove them")
+                        .arg(
+                            Arg::new("artifact")
+                                .help("ID of the artifact to tag")
+                                .required(true)
+                                .index(1),
+                        )
+                        .arg(
+                            Arg::new("tags")
+                                .help("The tags")
+                                .required(true)
+                                .num_args(1..)
+                                .index(2),
+                        )
+                        .arg(
+                            Arg::new("remove")
+                                .long("remove")
+                                .help("Remove the tags instead of adding them")
+                                .action(clap::ArgAction::SetTrue),
+                        ),
+                )
+                .subcommand(
+                    Command::new("delete")
+                        .about("Delete an artifact and drop it from the references of other artifacts")
+                        .arg(
+                            Arg::new("artifact")
+                                .help("ID of the artifact to delete")
+                                .required(true)
+                                .index(1),
+                        ),
+                )
+                .subcommand(
+                    Command::new("rename")
+                        .about("Rename an artifact and update the references of other artifacts")
+                        .arg(
+                            Arg::new("artifact")
+                                .help("ID of the artifact to rename")
+                                .required(true)
+                                .index(1),
+                        )
+                        .arg(
+                            Arg::new("new_id")
+                                .help("The artifact's new ID")
+                                .required(true)
+                                .index(2),
+                        ),
+                )
+                .subcommand(
+                    Command::new("generate")
+                        .about("Have the AI write artifacts: a premise, interviewing you about the story, with an arc for each character")
+                        .arg(
+                            Arg::new("kind")
+                                .help("Kind of artifact to write")
+                                .required(true)
+                                .value_parser(["premise"])
+                                .index(1),
+                        )
+                        .arg(
+                            Arg::new("interactive")
+                                .long("interactive")
+                                .help("Answer the AI's questions about the genre, setting, characters, and themes")
+                                .action(clap::ArgAction::SetTrue)
+                                .required_unless_present("idea"),
+                        )
+                        .arg(
+                            Arg::new("idea")
+                                .long("idea")
+                                .help("Write the premise from this idea without asking anything")
+                                .conflicts_with("interactive"),
+                        )
+                        .arg(
+                            Arg::new("questions")
+                                .long("questions")
+                                .help("Most questions the AI asks (default: 8)")
+                                .value_parser(clap::value_parser!(usize)),
+                        )
+                        .arg(
+                            Arg::new("name")
+                                .long("name")
+                                .help("Name of the premise file in the artifacts directory (default: the project's premise, or the premise's title)"),
+                        )
+                        .arg(
+                            Arg::new("force")
+                                .long("force")
+                                .help("Replace an existing premise")
+                                .action(clap::ArgAction::SetTrue),
+                        )
+                        .args(provider_args()),
+                ),
+        )
+        .subcommand(
+            // Story files in an older format
+            Command::new("migrate")
+                .about("Rewrite story files saved by older versions in the current format, keeping a backup of each")
+                .arg(
+                    Arg::new("stories")
+                        .help("The JSON story files to migrate")
+                        .required(true)
+                        .num_args(1..)
+                        .index(1),
+                )
+                .arg(
+                    Arg::new("dry-run")
+                        .long("dry-run")
+                        .help("Only report the migrations each file needs")
+                        .action(clap::ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            // HTTP API for web front-ends
+            Command::new("serve")
+                .about("Serve an HTTP API to create projects, start and follow generation runs, and fetch and export chains")
+                .arg(
+                    Arg::new("dir")
+                        .long("dir")
+                        .help("Directory holding the projects")
+                        .default_value("."),
+                )
+                .arg(
+                    Arg::new("addr")
+                        .long("addr")
+                        .help("Address to listen on")
+                        .default_value("127.0.0.1:8080"),
+                )
+                .args(provider_args()),
         )
         .get_matches();
 
+    // Work inside the project directory, if one is given
+    let project = match matches.subcommand() {
+        Some((name, sub_matches)) if name != "init" => match sub_matches.get_one::<String>("project") {
+            Some(dir) => {
+                let mut project = Project::open(dir)?;
+                project.enter()?;
+                Some(project)
+            }
+            None => None,
+        },
+        _ => None,
+    };
+    let project = project.as_ref();
+
+    match matches.subcommand() {
+        Some(("init", init_matches)) => run_init(init_matches),
+        Some(("generate", generate_matches)) => run_generate(generate_matches, None, project, &bars).await,
+        Some(("continue", continue_matches)) => {
+            run_generate(continue_matches, continue_matches.get_one::<String>("story"), project, &bars).await
+        }
+        Some(("interactive", interactive_matches)) => run_interactive(interactive_matches, project).await,
+        Some(("convert", convert_matches)) => run_convert(convert_matches, project),
+        Some(("translate", translate_matches)) => run_translate(translate_matches, project).await,
+        #[cfg(feature = "audio")]
+        Some(("audiobook", audiobook_matches)) => run_audiobook(audiobook_matches, project).await,
+        #[cfg(not(feature = "audio"))]
+        Some(("audiobook", _)) => Err(StoryChainError::ConfigError("Audiobook export is not available; rebuild with --features audio".to_string())),
+        Some(("compare", compare_matches)) => run_compare(compare_matches, project).await,
+        Some(("inspect", inspect_matches)) => run_inspect(inspect_matches, project),
+        Some(("stats", stats_matches)) => run_stats(stats_matches, project),
+        Some(("reorder", reorder_matches)) => run_reorder(reorder_matches, project),
+        Some(("characters", characters_matches)) => run_characters(characters_matches, project).await,
+        Some(("feedback", feedback_matches)) => run_feedback(feedback_matches, project),
+        #[cfg(feature = "tui")]
+        Some(("tui", tui_matches)) => run_tui(tui_matches, project).await,
+        #[cfg(not(feature = "tui"))]
+        Some(("tui", _)) => Err(StoryChainError::ConfigError("The terminal browser is not available; rebuild with --features tui".to_string())),
+        Some(("artifacts", artifacts_matches)) => match artifacts_matches.subcommand() {
+            Some(("generate", generate_matches)) => run_artifacts_generate(generate_matches, project).await,
+            Some(("check", check_matches)) => run_artifacts_check(check_matches),
+            Some(("search", search_matches)) => run_artifacts_search(search_matches),
+            Some(("tag", tag_matches)) => run_artifacts_tag(tag_matches),
+            Some(("delete", delete_matches)) => run_artifacts_delete(delete_matches),
+            Some(("rename", rename_matches)) => run_artifacts_rename(rename_matches),
+            _ => run_artifacts(artifacts_matches),
+        },
+        Some(("migrate", migrate_matches)) => run_migrate(migrate_matches, project),
+        #[cfg(feature = "serve")]
+        Some(("serve", serve_matches)) => run_serve(serve_matches).await,
+        #[cfg(not(feature = "serve"))]
+        Some(("serve", _)) => Err(StoryChainError::ConfigError("The HTTP API is not available; rebuild with --features serve".to_string())),
+        _ => unreachable!("a subcommand is required"),
+    }
+}
+
+/// Creates a project directory
+///
+/// # Arguments
+/// * `matches` - The arguments of the `init` subcommand
+fn run_init(matches: &ArgMatches) -> Result<(), StoryChainError> {
+    let name = matches.get_one::<String>("name").unwrap();
+    let dir = matches.get_one::<String>("dir").unwrap_or(name);
+    let project = Project::init(dir, name)?;
+    println!("Created project {} in {}", name, dir);
+    println!("Fill in the premise in {}, then run `storychain generate --project {}`", project.premise_path().display(), dir);
+    Ok(())
+}
+
+/// Returns the story argument of a subcommand, looked up among the
+/// project's chains when it is not a file
+///
+/// # Arguments
+/// * `matches` - The arguments of the subcommand
+/// * `project` - The project worked in, if any
+fn story_arg(matches: &ArgMatches, project: Option<&Project>) -> String {
+    let story = matches.get_one::<String>("story").unwrap();
+    project.map_or_else(|| story.clone(), |project| project.resolve_story(story))
+}
+
+/// Returns the premise argument of a subcommand, or the project's premise
+///
+/// # Arguments
+/// * `matches` - The arguments of the subcommand
+/// * `project` - The project worked in, if any
+fn premise_arg_value(matches: &ArgMatches, project: Option<&Project>) -> Result<String, StoryChainError> {
+    matches
+        .get_one::<String>("premise")
+        .cloned()
+        .or_else(|| project.map(|project| project.manifest.premise.clone()))
+        .ok_or_else(|| StoryChainError::ConfigError("No premise given".to_string()))
+}
+
+/// Generates a new story, or continues a saved one, as the `generate` and
+/// `continue` subcommands ask
+///
+/// # Arguments
+/// * `matches` - The arguments of the subcommand
+/// * `resume_file` - The story to continue, if any
+/// * `project` - The project worked in, if any
+/// * `bars` - The progress bars that log lines are printed around
+async fn run_generate(
+    matches: &ArgMatches,
+    resume_file: Option<&String>,
+    project: Option<&Project>,
+    bars: &MultiProgress,
+) -> Result<(), StoryChainError> {
     // Extract command line arguments
-    let premise_file = matches.get_one::<String>("premise").unwrap();
+    let premise_file = &premise_arg_value(matches, project)?;
     let epochs = *matches.get_one::<usize>("epochs").unwrap();
-    let output_file = matches.get_one::<String>("output").unwrap();
+    // A story continued in a project is one of its chains, unless it is in another store
+    let resume_file = match (resume_file, project, matches.get_one::<String>("store")) {
+        (Some(_), Some(project), None) => Some(story_arg(matches, Some(project))),
+        _ => resume_file.cloned(),
+    };
+    let resume_file = resume_file.as_ref();
+    // A continued story is saved over its own file unless --output says otherwise, and a
+    // new story in a project is saved among its chains
+    let output_file = match (resume_file, matches.value_source("output"), project) {
+        (Some(story), Some(ValueSource::DefaultValue), _) => json_path(story),
+        (None, Some(ValueSource::DefaultValue), Some(project)) => {
+            project.chain_path(&project.manifest.name).to_string_lossy().to_string()
+        }
+        _ => matches.get_one::<String>("output").unwrap().clone(),
+    };
+    let output_file = output_file.as_str();
+    // Exports are named after the story file without its compression extension
+    let export_base = uncompressed_path(output_file);
+    // The story is saved to its JSON file unless --store names another store, where it is
+    // named by the story argument when continued, or after the output file
+    let store_url = matches.get_one::<String>("store");
+    let (json_store, output_name) = JsonFileStore::for_file(output_file);
+    let (store, chain_name): (Box<dyn ChainStore>, String) = match (store_url, resume_file) {
+        (Some(url), Some(story)) => (open_store(url)?, story.clone()),
+        (Some(url), None) => (open_store(url)?, output_name),
+        (None, _) => (Box::new(json_store), output_name),
+    };
+    let title_blurb = matches.get_flag("title-blurb");
+    let synopsis = matches.get_flag("synopsis");
+    let scenes_per_chapter = matches.get_one::<usize>("scenes-per-chapter").copied();
+    let chapter_summaries = matches.get_flag("chapter-summaries");
+    let acts = matches.get_one::<usize>("acts").copied();
+    let sequel_of = matches.get_one::<String>("sequel-of");
+    let fix_names = matches.get_flag("fix-names");
+    let check_names = matches.get_flag("check-names") || fix_names;
+    let glossary = matches.get_flag("glossary");
+    let tags = matches.get_flag("tags");
+    let illustration_briefs = matches.get_flag("illustration-briefs");
+    let image_provider = matches.get_one::<String>("image-url").map(|url| {
+        let mut images = HttpImageProvider::new(url, matches.get_one::<String>("image-model").unwrap().clone())
+            .with_size(matches.get_one::<String>("image-size").unwrap().clone());
+        if let Ok(api_key) = std::env::var("IMAGE_API_KEY") {
+            images = images.with_api_key(api_key);
+        }
+        images
+    });
+    let stats = matches.get_flag("stats");
+    let trace_file = matches.get_one::<String>("trace");
+    let twee = matches.get_flag("twee");
+    let ink = matches.get_flag("ink");
+    let choice_labels = matches.get_flag("choice-labels");
+    let ai_screenplay = matches.get_flag("ai-screenplay");
+    let templates: Vec<String> = matches.get_many::<String>("template").map(|v| v.cloned().collect()).unwrap_or_default();
+    let fountain = matches.get_flag("fountain") || ai_screenplay;
+    let dot = matches.get_flag("dot");
+    let mermaid = matches.get_flag("mermaid");
+    let color_by = matches.get_one::<String>("color-by").unwrap();
+    let text = matches.get_flag("text");
+    let scenes_dir = matches.get_one::<String>("scenes-dir");
+    let html = matches.get_flag("html");
+    let pdf = matches.get_flag("pdf");
+    let docx = matches.get_flag("docx");
+    let epub = matches.get_flag("epub");
+    let jsonl = matches.get_flag("jsonl");
+    let interactive_html = matches.get_flag("interactive-html");
+    let pov_names: Vec<String> = matches.get_many::<String>("pov").map(|v| v.cloned().collect()).unwrap_or_default();
+    let format: StoryFormat = matches.get_one::<String>("format").unwrap().parse().unwrap_or_default();
+    let dialogue_ratio = matches.get_one::<f64>("dialogue-ratio").copied();
+    let revise_dialogue = matches.get_flag("revise-dialogue");
+    let scene_cards = matches.get_flag("scene-cards");
+    let planted: Vec<String> = matches.get_many::<String>("plant").map(|v| v.cloned().collect()).unwrap_or_default();
+    let resolve_setups = matches.get_flag("resolve-setups");
+    let track_setups = matches.get_flag("track-setups") || resolve_setups || !planted.is_empty();
+    let outline = matches.get_one::<String>("outline");
+    let foreshadow_lead = *matches.get_one::<usize>("foreshadow-lead").unwrap();
+    let verify_foreshadowing = matches.get_flag("verify-foreshadowing");
+    let subplot_ids: Vec<String> = matches.get_many::<String>("subplot").map(|v| v.cloned().collect()).unwrap_or_default();
+    let subplot_every = matches.get_one::<usize>("subplot-every").copied().unwrap_or(DEFAULT_SUBPLOT_INTERVAL);
+    let strand_ids: Vec<String> = matches.get_many::<String>("strand").map(|v| v.cloned().collect()).unwrap_or_default();
+    let strand_order = matches.get_one::<StrandOrder>("strand-order").copied().unwrap_or_default();
+    let tension_curve = matches.get_one::<TensionCurve>("tension-curve").cloned();
+    let genre_flag = matches.get_one::<GenrePreset>("genre").copied();
+    let constraints_id = matches.get_one::<String>("constraints");
+    let filter_rating = matches.get_one::<ContentRating>("filter-rating").copied();
+    let filter_list = matches.get_one::<String>("content-filter");
+    let content_filter = if filter_rating.is_some() || filter_list.is_some() {
+        let mut settings = ContentFilterSettings {
+            rating: filter_rating,
+            action: matches.get_one::<FilterAction>("filter-action").copied().unwrap_or_default(),
+            max_retries: matches.get_one::<usize>("filter-retries").copied().unwrap_or(DEFAULT_FILTER_RETRIES),
+            ..ContentFilterSettings::default()
+        };
+        if let Some(path) = filter_list {
+            settings.parse_list(&std::fs::read_to_string(path)?);
+        }
+        // Fail before generating anything when a pattern does not compile
+        KeywordFilter::new(&settings)?;
+        Some(settings)
+    } else {
+        None
+    };
+    let lore_snippets = matches.get_one::<usize>("lore-snippets").copied().unwrap_or(DEFAULT_LORE_SNIPPETS);
+    let revision_criteria = matches.get_one::<f64>("revise-until").map(|threshold| RevisionCriteria {
+        criteria: match matches.get_many::<String>("revision-criteria") {
+            Some(criteria) => criteria.map(|c| c.trim().to_string()).filter(|c| !c.is_empty()).collect(),
+            None => RevisionCriteria::default().criteria,
+        },
+        threshold: threshold.clamp(0.0, 10.0),
+    });
+    let revision_rounds = matches.get_one::<usize>("revision-rounds").copied().unwrap_or(DEFAULT_REVISION_ROUNDS);
+    let config = load_config(matches)?;
+    let max_attempts = matches.get_one::<usize>("max-attempts").copied().unwrap_or(DEFAULT_MAX_ATTEMPTS);
+    let generation = config.generation.clone();
+    let min_score = matches.get_one::<f64>("min-score").copied();
+    let max_retries = matches.get_one::<usize>("max-retries").copied().unwrap_or(DEFAULT_MAX_RETRIES);
+    let best_of = matches.get_one::<usize>("best-of").copied();
+    let mut critic_config = config.critic.clone().unwrap_or_default();
+    if let Some(critic_model) = matches.get_one::<String>("critic-model") {
+        critic_config.model = critic_model.clone();
+    }
+    if let Some(request_timeout) = matches.get_one::<u64>("request-timeout") {
+        critic_config.timeout_secs = Some(*request_timeout);
+    }
+    let system_prompt = match matches.get_one::<String>("system-prompt-file") {
+        Some(path) => Some(std::fs::read_to_string(path)?.trim().to_string()),
+        None => matches.get_one::<String>("system-prompt").cloned(),
+    };
+    let pov_mode = match matches.get_one::<String>("pov-mode").map(String::as_str) {
+        Some("ai") => PovMode::AiChosen,
+        _ => PovMode::Rotation,
+    };
 
     info!("Starting story generation with {} epochs", epochs);
 
-    // Load the premise from the specified YAML file in the artifacts directory
+    // Record the spans of the run to report where its time went
+    let trace = TraceRecorder::new();
+    if let Err(e) = tracing::subscriber::set_global_default(tracing_subscriber::registry().with(trace.clone())) {
+        warn!("Cannot record the run's timings: {}", e);
+    }
+
+    // Load the premise from its file, standard input, or the artifacts directory
     let start_time = std::time::Instant::now();
-    let premise = std::fs::read_to_string(format!("artifacts/{}.yaml", premise_file))
-        .map_err(|e| StoryChainError::IOError(e))?;
-    info!("Loaded premise from artifacts/{}.yaml", premise_file);
-
-    // Initialize the AI provider with the Deepseek model for story generation
-    let provider = DeepseekProvider::new(
-        "deepseek-r1:32b".to_string(),  // Using the 32B parameter Deepseek model
-        "ai_responses.log".to_string(),  // Log file for AI responses
-    );
+    let premise_text = load_premise(premise_file, Path::new("artifacts"))?;
+    let premise_file = premise_name(premise_file);
+
+    // Lay a YAML premise out as labelled fields and requirements for the prompts
+    let mut premise = Premise::parse(&premise_text)?.to_prompt_section();
+
+    // Use the requested genre preset, or the one named in the premise
+    let genre = genre_flag.or_else(|| GenrePreset::from_premise(&premise_text));
+    if let Some(genre) = genre {
+        info!("Using the {:?} genre preset", genre);
+    }
+
+    // Ctrl-C or SIGTERM cancels the run, dropping the request in flight
+    let cancellation = CancellationToken::new();
+
+    // Score main-plot scenes with a critic when a quality gate is requested,
+    // or have it judge the candidates of best-of-N selection
+    let critic = match min_score.is_some() || best_of.is_some() {
+        true => Some(CancellableProvider::new(
+            RetryingProvider::new(UsageTracker::new(ProviderFactory::from_config(&critic_config)?)).with_max_attempts(max_attempts),
+            cancellation.clone(),
+        )),
+        false => None,
+    };
+
+    // Initialize the configured AI provider for story generation
+    let provider = build_provider(matches, &config)?;
+
+    // Show the progress of the run, counting the tokens streamed from the
+    // model; --stream prints them instead
+    let progress = Arc::new(RunProgress::new(bars, matches.get_flag("quiet") || matches.get_flag("stream")));
+    let provider: Box<dyn AIProvider> = if progress.is_hidden() {
+        provider
+    } else {
+        let counter = Arc::clone(&progress);
+        Box::new(StreamingProvider::new(provider, move |token| counter.add_tokens(token)))
+    };
+    let provider: Box<dyn AIProvider> = Box::new(CancellableProvider::new(provider, cancellation.clone()));
+
+    // Load the artifacts directory so that generated artifacts are merged with existing ones
+    let mut artifact_manager = ArtifactManager::new("artifacts");
+    artifact_manager.load_from_dir()?;
+    artifact_manager.validate()?;
+
+    // Carry continuity over from the previous story when generating a sequel
+    if let Some(previous_file) = sequel_of {
+        info!("Extracting continuity from {}", previous_file);
+        let mut previous = StoryChain::load_from_file(previous_file)?;
+        previous.prompts = load_prompt_templates(matches)?;
+        let packet = previous.extract_continuity_packet(provider.as_ref()).await?;
+
+        artifact_manager.update_artifact(packet.to_artifact(format!("continuity_{}", premise_file))?)?;
+
+        premise.push_str("\n\n");
+        premise.push_str(&packet.to_prompt_section());
+    }
+
+    // Load the prompt templates and expose the artifacts to them
+    let mut prompt_templates = load_prompt_templates(matches)?;
+    for name in [INITIAL, CONTINUATION] {
+        if let Some(path) = matches.get_one::<String>(&format!("{}-template", name)) {
+            prompt_templates.set_template(PromptTemplate::from_file(name, Path::new(path))?)?;
+        }
+    }
+    prompt_templates.lore.top_k = lore_snippets;
+    prompt_templates.add_artifacts(&artifact_manager);
+
+    // Continue a saved story, or start a new one from an initial scene based on the premise
+    let imported = store_url.is_none() && resume_file.is_some_and(|f| f.ends_with(".md"));
+    let mut initial_prompt = None;
+    let mut chain = match (resume_file, store_url) {
+        (Some(_), Some(_)) => load_stored_story(store.as_ref(), &chain_name)?,
+        (Some(resume_file), None) => load_story(resume_file)?,
+        (None, _) => {
+            // Generate the initial scene based on the premise
+            info!("Generating initial scene");
+            let initial_start = std::time::Instant::now();
+            let mut instructions = Vec::new();
+            instructions.extend(format.instructions().map(|i| format!("Format: {}", i)));
+            instructions.extend(genre.map(|g| format!("Genre: {}", g.opening_instructions())));
+            let prompt = prompt_templates.initial_prompt(&premise, &instructions)?;
+            let provenance =
+                NodeProvenance::new(provider.as_ref(), &prompt_templates, INITIAL, system_prompt.as_deref(), &generation);
+            let usage_before = provider.usage();
+            let (reasoning, content) = provider
+                .generate_with_config(system_prompt.as_deref(), &prompt, &generation)
+                .instrument(tracing::info_span!(trace::AI_GENERATE_SPAN, node = "root"))
+                .await?;
+            initial_prompt = Some(prompt);
+            let initial_time = initial_start.elapsed();
+
+            // Initialize the story chain with the generated content and reasoning
+            let mut chain = StoryChain::new(prompt_templates.post_process(content), reasoning);
+            if let Some(root) = chain.nodes.get_mut(&chain.root_node_id) {
+                root.provenance = Some(provenance.with_duration(initial_time).with_usage(usage_before, provider.usage()));
+            }
+            chain
+        }
+    };
+    chain.prompts = prompt_templates;
+
+    // A resumed story keeps the settings it was saved with; markdown has none, so an imported one is set up anew
+    if resume_file.is_none() || imported {
+        chain.settings.system_prompt = system_prompt;
+        chain.settings.max_context_tokens = matches.get_one::<usize>("max-context-tokens").copied().or(generation.num_ctx);
+        chain.settings.generation = generation;
+        if let Some(previous_file) = sequel_of {
+            chain.metadata.insert("sequel_of".to_string(), previous_file.clone());
+        }
+
+        // Keep every scene in the selected structural format
+        chain.settings.format = format;
+        for issue in format.validate(&chain.nodes[&chain.root_node_id].content) {
+            warn!("Opening scene does not follow the {:?} format: {}", format, issue);
+        }
+
+        // Check the opening scene against the style guide, as every later scene is
+        let style_issues = chain.prompts.style.as_ref().map(|style| style.check(&chain.nodes[&chain.root_node_id].content));
+        if let (Some(style_issues), Some(root)) = (style_issues.filter(|i| !i.is_empty()), chain.nodes.get_mut(&chain.root_node_id)) {
+            warn!("Opening scene departs from the style guide: {}", style_issues.join("; "));
+            root.metadata.insert(STYLE_ISSUES_KEY.to_string(), style_issues.join("; "));
+        }
+
+        // Track goal, conflict, outcome, and hook for every scene
+        chain.settings.scene_cards = scene_cards;
+
+        // Track setups, starting with the ones planted by the user
+        chain.settings.track_setups = track_setups;
+        for description in &planted {
+            chain.plant_setup(description, None, SetupSource::User);
+        }
+
+        // Steer scenes toward the target dialogue ratio
+        if let Some(ratio) = dialogue_ratio {
+            chain.settings.dialogue = Some(DialogueTarget {
+                ratio: ratio.clamp(0.0, 1.0),
+                revise: revise_dialogue,
+                ..DialogueTarget::default()
+            });
+        }
+
+        // Keep every scene near the target word count
+        chain.settings.length = config.length.clone();
+
+        // Check each scene for contradictions with the story so far
+        let revise_inconsistencies = matches.get_flag("revise-inconsistencies");
+        if matches.get_flag("check-consistency") || revise_inconsistencies {
+            chain.settings.consistency = Some(ConsistencyCheck { revise: revise_inconsistencies });
+        }
+
+        // Screen every scene for unsuitable content, starting with the opening one
+        if content_filter.is_some() {
+            chain.settings.content_filter = content_filter;
+            if let Some(prompt) = &initial_prompt {
+                let root_id = chain.root_node_id.clone();
+                chain.screen_node(&root_id, provider.as_ref(), prompt).await?;
+            }
+        }
+
+        // Record the characters, location, time, and mood of every scene, starting with the opening one
+        chain.settings.enrichment = matches.get_one::<EnrichmentMode>("enrich").copied();
+        if let Some(mode) = chain.settings.enrichment {
+            let root_id = chain.root_node_id.clone();
+            chain.enrich_node(&root_id, mode, provider.as_ref()).await?;
+        }
+
+        // Foreshadow the beats of the plot outline in the scenes leading up to them
+        if let Some(outline_id) = outline {
+            match artifact_manager.get_artifact(outline_id) {
+                Some(artifact) => {
+                    chain.settings.foreshadowing = Some(ForeshadowingPlan::from_outline(&artifact.content, foreshadow_lead));
+                }
+                None => warn!("No outline artifact found with ID {}", outline_id),
+            }
+        }
+
+        // Apply the genre preset to every continuation prompt
+        chain.settings.genre = genre;
+
+        // Inject and enforce the generation rules
+        if let Some(constraints_id) = constraints_id {
+            match artifact_manager.get_artifact(constraints_id) {
+                Some(artifact) => chain.settings.constraints = Some(ConstraintSet::parse(&artifact.content)?),
+                None => warn!("No constraints artifact found with ID {}", constraints_id),
+            }
+        }
+
+        // Regenerate scenes the critic scores below the threshold
+        chain.settings.quality_gate = min_score.map(|min_score| QualityGate { min_score: min_score.clamp(0.0, 10.0), max_retries });
+
+        // Keep the best of several candidates for every main-plot scene
+        chain.settings.selection = best_of.map(|candidates| CandidateSelection { candidates: candidates.max(1) });
+
+        // Nudge each scene toward the desired tension curve
+        chain.settings.tension_curve = tension_curve;
+
+        // Keep a running summary of earlier scenes when requested
+        chain.settings.memory = matches.get_one::<usize>("summary-every").map(|interval| RollingSummary {
+            interval: *interval,
+            max_words: matches.get_one::<usize>("summary-words").copied().unwrap_or(DEFAULT_SUMMARY_WORDS),
+        });
+
+        // Recall similar earlier scenes when requested
+        chain.settings.recall = matches.get_one::<usize>("recall").map(|scenes| SceneRecall {
+            scenes: *scenes,
+            excerpt_chars: matches.get_one::<usize>("recall-chars").copied().unwrap_or(DEFAULT_RECALL_EXCERPT_CHARS),
+        });
+
+        // Weave subplots into the main chain
+        for subplot_id in &subplot_ids {
+            match artifact_manager.get_artifact(subplot_id) {
+                Some(artifact) => chain.subplots.push(Subplot::from_artifact(artifact, subplot_every)),
+                None => warn!("No subplot artifact found with ID {}", subplot_id),
+            }
+        }
+
+        // Tell the story as parallel strands interleaved into the chain
+        for strand_id in &strand_ids {
+            match artifact_manager.get_artifact(strand_id) {
+                Some(artifact) => chain.strands.push(Strand::from_artifact(artifact)),
+                None => warn!("No strand artifact found with ID {}", strand_id),
+            }
+        }
+
+        // Alternate viewpoint characters between scenes
+        if !pov_names.is_empty() {
+            chain.settings.pov = Some(PovSchedule::from_artifacts(&pov_names, pov_mode, &artifact_manager));
+        }
+
+        // Seed the name registry from the premise and the opening scene
+        if check_names {
+            let mut registry = CharacterRegistry::from_premise(&premise_text);
+            registry.auto_correct = fix_names;
+            let root_content = chain.nodes[&chain.root_node_id].content.clone();
+            registry.review_scene(&chain.root_node_id, root_content);
+            chain.character_registry = registry;
+        }
+
+        // Branch into alternative continuations when requested
+        if let Some(branches) = matches.get_one::<usize>("branches").copied() {
+            chain.branch_ratio = branches.max(1);
+            let leaves = chain.branch_ratio.checked_pow(epochs as u32).unwrap_or(usize::MAX);
+            if leaves > 100 {
+                warn!("{} branches over {} epochs end in {} storylines", chain.branch_ratio, epochs, leaves);
+            }
+        }
+    }
 
-    // Generate the initial scene based on the premise
-    info!("Generating initial scene");
-    let initial_start = std::time::Instant::now();
-    let (reasoning, content) = provider.generate(&format!(
-        // Construct the prompt for the initial scene generation
-        "You are tasked with writing a scene in the style specified by the premise.\n\n\
-        IMPORTANT: Format your response EXACTLY as follows:\n\
-        <think>\n\
-        Write your reasoning here in a single paragraph, explaining your narrative choices and how they connect to the premise.\n\
-        </think>\n\
-        Write your scene content here, using proper paragraphs and formatting.\n\n\
-        Story Premise:\n{}\n\n\
-        Remember: \n\
-        - Put your reasoning in a SINGLE paragraph inside <think> tags\n\
-        - Write your scene content immediately after the </think> tag\n\
-        - Use proper paragraphs in your scene content\n\
-        - Do NOT add any extra formatting or tags",
-        premise
-    )).await?;
-    let initial_time = initial_start.elapsed();
-    info!("Initial scene generation took: {:?}", initial_time);
-
-    // Initialize the story chain with the generated content and reasoning
-    let mut chain = StoryChain::new(content, reasoning);
-
-    // Generate subsequent scenes for the specified number of epochs
-    let mut current_node_id = "root".to_string();
-    for epoch in 0..epochs {
-        let epoch_start = std::time::Instant::now();
-        info!("Starting epoch {} of {}", epoch + 1, epochs);
-        
-        // Generate the next scene based on the current one
-        let next_node_ids = chain
-            .generate_next_nodes(
-                &current_node_id,
-                &provider,
-                Some(&premise),
-                epoch + 1,  // current epoch (1-indexed)
-                epochs     // total epochs
-            )
-            .await?;
-            
-        // Break if no more nodes can be generated
-        if next_node_ids.is_empty() {
-            break;
-        }
-        
-        // Update the current node to the first generated successor
-        current_node_id = next_node_ids[0].clone();
-        let epoch_time = epoch_start.elapsed();
-        info!("Epoch {} took: {:?}", epoch + 1, epoch_time);
-    }
-
-    // Export the complete story chain to the specified output file
-    chain.export_to_file(output_file)?;
-    info!("Story chain exported to {}", output_file);
+    // Generate alternative continuations concurrently when requested, also when continuing a story
+    if let Some(limit) = matches.get_one::<usize>("parallel") {
+        chain.settings.parallel_candidates = Some((*limit).max(1));
+    }
+
+    // Respond to the ratings and comments of readers, which a resumed story has been given since it was saved
+    if matches.get_flag("feedback-guidance") {
+        chain.settings.feedback_guidance = true;
+    }
+
+    // Embed scenes with the configured server when the story recalls earlier scenes
+    if chain.settings.recall.is_some() {
+        let model = matches.get_one::<String>("embedding-model").map(String::as_str).unwrap_or(DEFAULT_EMBEDDING_MODEL);
+        let mut embedder = OllamaEmbeddingProvider::new(model.to_string()).with_base_url(&config.provider.endpoint());
+        if let Some(api_key) = config.provider.api_key() {
+            embedder = embedder.with_api_key(api_key);
+        }
+        chain.embedder = Some(Arc::new(embedder));
+    }
+
+    // Regenerate the scenes marked for it, for example in the terminal browser
+    if resume_file.is_some() {
+        let regenerated = chain.regenerate_marked(provider.as_ref(), Some(&premise)).await?;
+        if !regenerated.is_empty() {
+            info!("Regenerated the marked scenes {}", regenerated.join(", "));
+        }
+    }
+    let track_setups = chain.settings.track_setups;
+    let scene_cards = chain.settings.scene_cards;
+    let tension_report = chain.settings.tension_curve.is_some();
+
+    // Generate the story until it is done or the run is interrupted with
+    // Ctrl-C or SIGTERM, which cancels the request in flight
+    let interruption = tokio::spawn({
+        let cancellation = cancellation.clone();
+        async move {
+            match shutdown_signal().await {
+                Ok(()) => cancellation.cancel(),
+                Err(e) => warn!("Cannot listen for interruptions: {}", e),
+            }
+        }
+    });
+    let result = async {
+        // Generate subsequent scenes for the specified number of epochs, continuing
+        // every storyline of the tree
+        let epochs = match resume_file {
+            // Continue up to the length the story was started with, unless --epochs says otherwise
+            Some(_) if matches.value_source("epochs") != Some(ValueSource::CommandLine) => {
+                chain.metadata.get(EPOCHS_KEY).and_then(|e| e.parse().ok()).unwrap_or(epochs)
+            }
+            _ => epochs,
+        };
+        let completed = chain.completed_epochs().min(epochs);
+        if let Some(resume_file) = resume_file {
+            info!("Resuming {} after epoch {} of {}", resume_file, completed, epochs);
+        }
+        progress.start_run(epochs, completed);
+        let mut runner = StoryRunner::new(provider.as_ref())
+            .premise(premise.as_str())
+            .epochs(epochs)
+            .cancellation(cancellation.clone())
+            .on_event(|event| {
+                if let RunEvent::EpochStarted { epoch } = event {
+                    progress.start_epoch(*epoch);
+                }
+            })
+            .on_epoch(|chain, report| {
+                let epochs_left = report.epochs - report.epoch;
+                progress.finish_epoch(
+                    report.duration,
+                    report.node_ids.len(),
+                    scenes_left(report.frontier, chain.branch_ratio, epochs_left),
+                );
+
+                // Save the story so far, so that an interrupted run can be resumed
+                tracing::info_span!(trace::PERSIST_SPAN, epoch = report.epoch).in_scope(|| {
+                    store.append_nodes(&chain_name, chain, &report.node_ids)?;
+                    if let Some(project) = project {
+                        let checkpoint = project.save_checkpoint(&chain_name, chain, report.epoch)?;
+                        info!("Checkpoint saved to {}", checkpoint.display());
+                    }
+                    Ok(())
+                })
+            });
+        if let Some(critic) = &critic {
+            runner = runner.critic(critic);
+        }
+        if let Some(criteria) = &revision_criteria {
+            runner = runner.revision(criteria.clone(), revision_rounds);
+        }
+        runner.continue_chain(&mut chain).await?;
+
+        // Present the strands interleaved or grouped
+        if !chain.strands.is_empty() {
+            chain.arrange_strands(strand_order)?;
+        }
+
+        // Report setups that were never paid off, optionally resolving them in a closing scene
+        if track_setups {
+            if resolve_setups {
+                if let Some(node_id) = chain.generate_resolving_scene(provider.as_ref(), Some(&premise)).await? {
+                    info!("Generated resolving scene {}", node_id);
+                }
+            }
+            let open = chain.warn_unresolved_setups();
+            info!("{} of {} setups left unresolved", open, chain.setups.len());
+        }
+
+        // Optionally check that the planned foreshadowing made it into the scenes
+        if verify_foreshadowing {
+            for check in chain.verify_foreshadowing(provider.as_ref()).await? {
+                info!(
+                    "Beat at scene {} foreshadowed in {} of {} scenes: {}",
+                    check.beat.scene,
+                    check.confirmed_ids.len(),
+                    check.carrier_ids.len(),
+                    check.beat.description
+                );
+            }
+        }
+
+        // Optionally generate a title, blurb, and logline for the exports
+        if title_blurb {
+            chain.generate_title_and_blurb(provider.as_ref()).await?;
+        }
+
+        // Optionally tag the story for catalogues and content warnings
+        if tags {
+            chain.generate_tags(provider.as_ref()).await?;
+        }
+
+        // Optionally have the AI write the choices leading into alternative branches
+        if choice_labels {
+            chain.generate_choice_labels(provider.as_ref()).await?;
+        }
+
+        // Optionally have the AI adapt the scenes for the screenplay export
+        if ai_screenplay {
+            chain.adapt_to_screenplay(provider.as_ref()).await?;
+        }
+
+        // Group scenes into chapters and optionally summarize each chapter
+        if scenes_per_chapter.is_some() || chapter_summaries || acts.is_some() {
+            chain.group_into_chapters(scenes_per_chapter.unwrap_or(3));
+        }
+        if let Some(acts) = acts {
+            chain.group_into_acts(acts);
+        }
+        if chapter_summaries {
+            chain.generate_chapter_summaries(provider.as_ref()).await?;
+        }
+
+        // Optionally extract a glossary for the export appendix and the artifacts directory
+        if glossary {
+            chain.glossary = chain.generate_glossary(provider.as_ref()).await?;
+            artifact_manager.update_artifact(chain.glossary_artifact(format!("glossary_{}", premise_file))?)?;
+        }
+
+        // Optionally write image prompts for the cover and chapters
+        if illustration_briefs {
+            for artifact in chain.generate_illustration_briefs(provider.as_ref()).await? {
+                info!("Saving illustration brief {}", artifact.id);
+                artifact_manager.update_artifact(artifact)?;
+            }
+        }
+
+        // Optionally illustrate every scene for the HTML exports
+        if let Some(images) = &image_provider {
+            let directory = format!("{}_images", export_base.trim_end_matches(".json"));
+            chain.illustrate_scenes(provider.as_ref(), images, &directory).await?;
+        }
+
+        // Optionally summarize the finished story into synopsis artifacts,
+        // named after the premise file so that stories sharing the artifacts
+        // directory keep their own
+        if synopsis {
+            for length in [SynopsisLength::Paragraph, SynopsisLength::Page] {
+                let artifact = chain.generate_synopsis(provider.as_ref(), length, &premise_file).await?;
+                info!("Saving synopsis artifact {}", artifact.id);
+                artifact_manager.update_artifact(artifact)?;
+            }
+        }
+
+        Ok::<(), StoryChainError>(())
+    }
+    .await;
+    interruption.abort();
+    progress.finish();
+
+    // Account for the tokens spent by the run, the critic's included
+    let run_usage = provider.usage().unwrap_or_default() + critic.as_ref().and_then(|c| c.usage()).unwrap_or_default();
+    chain.usage.record_run(run_usage, config.pricing.as_ref());
+    println!("{}", chain.usage.to_text());
+
+    // Keep the scenes generated so far instead of losing the run
+    if chain.save_cancelled_run(result, store.as_ref(), &chain_name, &export_base.replace(".json", ".md"))? {
+        info!("Partial story saved; continue it with `storychain continue`");
+        return finish_trace(&trace, trace_file);
+    }
+
+    // Save the complete story chain to the output file or the store
+    tracing::info_span!(trace::PERSIST_SPAN).in_scope(|| store.save_chain(&chain_name, &chain))?;
+    info!("Story chain saved as {}", chain_name);
 
     // Also export to markdown
-    let markdown_file = output_file.replace(".json", ".md");
-    chain.export_to_markdown(&markdown_file)?;
+    let markdown_file = export_base.replace(".json", ".md");
+    let markdown_options = MarkdownOptions {
+        chapter_recaps: chapter_summaries,
+        stats_words_per_minute: stats.then_some(DEFAULT_WORDS_PER_MINUTE),
+        ..MarkdownOptions::default()
+    };
+    chain.export_to_markdown_with_options(&markdown_file, &markdown_options)?;
     info!("Story exported to markdown at {}", markdown_file);
 
+    // Export the scene-card outline
+    if scene_cards {
+        let outline_file = export_base.replace(".json", "_outline.md");
+        chain.export_outline(&outline_file)?;
+        info!("Outline exported to {}", outline_file);
+    }
+
+    // Compare the measured tension with the requested curve
+    if tension_report {
+        let tension_file = export_base.replace(".json", "_tension.md");
+        write_atomic(&tension_file, chain.tension_report_markdown())?;
+        info!("Tension report exported to {}", tension_file);
+    }
+
+    // Report the scenes that broke a generation rule
+    if chain.settings.constraints.is_some() {
+        let constraints_file = export_base.replace(".json", "_constraints.md");
+        write_atomic(&constraints_file, chain.constraint_report_markdown())?;
+        info!("Constraint report exported to {}", constraints_file);
+    }
+
+    // Report the critic scores and regenerations of the quality gate
+    if chain.settings.quality_gate.is_some() {
+        let quality_file = export_base.replace(".json", "_quality.md");
+        write_atomic(&quality_file, chain.quality_report_markdown())?;
+        info!("Quality report exported to {}", quality_file);
+    }
+
+    // Optionally export the interactive fiction for Twine
+    if twee {
+        let twee_file = export_base.replace(".json", ".twee");
+        chain.export_to_twee(&twee_file)?;
+        info!("Story exported to Twee at {}", twee_file);
+    }
+
+    if ink {
+        let ink_file = export_base.replace(".json", ".ink");
+        chain.export_to_ink(&ink_file)?;
+        info!("Story exported to Ink at {}", ink_file);
+    }
+
+    // Optionally export the main storyline as a screenplay
+    if fountain {
+        let fountain_file = export_base.replace(".json", ".fountain");
+        chain.export_to_fountain(&fountain_file)?;
+        info!("Screenplay exported to {}", fountain_file);
+    }
+
+    // Optionally export the story through user templates
+    for template in &templates {
+        let name = std::path::Path::new(template)
+            .file_name()
+            .map(|n| n.to_string_lossy().trim_end_matches(".tera").to_string())
+            .unwrap_or_default();
+        let template_file = export_base.replace(".json", &format!("_{}", name));
+        chain.export_with_template(template, &template_file)?;
+        info!("Story exported with template {} to {}", template, template_file);
+    }
+
+    // Optionally export the nodes as training records
+    if jsonl {
+        let jsonl_file = export_base.replace(".json", ".jsonl");
+        let records = chain.export_to_jsonl(&jsonl_file, Some(&premise))?;
+        info!("Exported {} training records to {}", records, jsonl_file);
+    }
+
+    // Optionally export the scene graph for visualizing branches
+    if dot {
+        let dot_file = export_base.replace(".json", ".dot");
+        chain.export_to_dot(&dot_file, color_by)?;
+        info!("Scene graph exported to {}", dot_file);
+    }
+    if mermaid {
+        let mermaid_file = export_base.replace(".json", ".mmd");
+        chain.export_to_mermaid(&mermaid_file, color_by)?;
+        info!("Scene graph exported to {}", mermaid_file);
+    }
+
+    // Optionally export the plain prose and the individual scenes
+    if text {
+        let text_file = export_base.replace(".json", ".txt");
+        chain.export_to_text(&text_file)?;
+        info!("Story exported to plain text at {}", text_file);
+    }
+    if let Some(scenes_dir) = scenes_dir {
+        let files = chain.export_scenes_to_dir(scenes_dir)?;
+        info!("{} scenes exported to {}", files.len(), scenes_dir);
+    }
+
+    // Optionally export the styled HTML version
+    if html {
+        let html_file = export_base.replace(".json", ".html");
+        chain.export_to_html_with_options(&html_file, &markdown_options)?;
+        info!("Story exported to HTML at {}", html_file);
+    }
+
+    // Optionally export the typeset PDF manuscript
+    if pdf {
+        #[cfg(feature = "pdf")]
+        {
+            let pdf_file = export_base.replace(".json", ".pdf");
+            chain.export_to_pdf_with_options(&pdf_file, &markdown_options)?;
+            info!("Story exported to PDF at {}", pdf_file);
+        }
+        #[cfg(not(feature = "pdf"))]
+        warn!("PDF export is not available; rebuild with --features pdf");
+    }
+
+    // Optionally export the Word document for editors
+    if docx {
+        #[cfg(feature = "docx")]
+        {
+            let docx_file = export_base.replace(".json", ".docx");
+            chain.export_to_docx_with_options(&docx_file, &markdown_options)?;
+            info!("Story exported to DOCX at {}", docx_file);
+        }
+        #[cfg(not(feature = "docx"))]
+        warn!("DOCX export is not available; rebuild with --features docx");
+    }
+
+    // Optionally export the e-book
+    if epub {
+        #[cfg(feature = "epub")]
+        {
+            let epub_file = export_base.replace(".json", ".epub");
+            chain.export_to_epub_with_options(&epub_file, &markdown_options)?;
+            info!("Story exported to EPUB at {}", epub_file);
+        }
+        #[cfg(not(feature = "epub"))]
+        warn!("EPUB export is not available; rebuild with --features epub");
+    }
+
+    // Optionally export the interactive HTML version
+    if interactive_html {
+        let html_file = export_base.replace(".json", "_interactive.html");
+        chain.export_to_interactive_html(&html_file)?;
+        info!("Interactive story exported to {}", html_file);
+    }
+
     let total_time = start_time.elapsed();
     info!("Total story generation took: {:?}", total_time);
 
+    finish_trace(&trace, trace_file)
+}
+
+/// Logs where a run spent its time and writes the trace file, if requested
+///
+/// # Arguments
+/// * `trace` - The spans recorded during the run
+/// * `trace_file` - The JSON trace file to write, if any
+fn finish_trace(trace: &TraceRecorder, trace_file: Option<&String>) -> Result<(), StoryChainError> {
+    info!("Time by phase: {}", trace.summary());
+    if let Some(trace_file) = trace_file {
+        trace.export(trace_file)?;
+        info!("Trace written to {}", trace_file);
+    }
+    Ok(())
+}
+
+/// Returns the argument naming the premise
+///
+/// # Arguments
+/// * `index` - Position of the argument on the command line
+fn premise_arg(index: usize) -> Arg {
+    // Required premise file argument that specifies the story's foundation
+    Arg::new("premise")
+        .help("The premise: a file path, - for standard input, or a name in the artifacts directory (default: the project's)")
+        .required_unless_present("project")
+        .index(index)
+}
+
+/// Returns the arguments of the `generate` and `continue` subcommands that
+/// shape the story: its length, the generation passes, and the exports
+fn generation_args() -> Vec<Arg> {
+    vec![
+        // Optional number of epochs (story generation iterations)
+        Arg::new("epochs")
+            .long("epochs")
+            .help("Number of epochs to generate")
+            .default_value("5")
+            .value_parser(clap::value_parser!(usize)),
+        // Optional output file path for the generated story
+        Arg::new("output")
+            .long("output")
+            .help("Output file path")
+            .default_value("story.json"),
+        // Optional database the story is saved to instead of its JSON file
+        Arg::new("store")
+            .long("store")
+            .help("Save the story to this store, such as sqlite://stories.db or a directory, instead of its JSON file; \
+                   when continuing, the story argument names the stored story"),
+        // Optional silence instead of the progress bar
+        Arg::new("quiet")
+            .long("quiet")
+            .help("Do not show the progress bar with the current epoch, time left, and streamed tokens")
+            .action(clap::ArgAction::SetTrue),
+        // Optional pass that generates a title, blurb, and logline after the run
+        Arg::new("title-blurb")
+            .long("title-blurb")
+            .help("Generate a title, back-cover blurb, and logline for the finished story")
+            .action(clap::ArgAction::SetTrue),
+        // Optional pass that writes paragraph and page synopses as artifacts
+        Arg::new("synopsis")
+            .long("synopsis")
+            .help("Generate one-paragraph and one-page synopses and save them as artifacts")
+            .action(clap::ArgAction::SetTrue),
+        // Optional grouping of scenes into chapters
+        Arg::new("scenes-per-chapter")
+            .long("scenes-per-chapter")
+            .help("Group the finished story into chapters of this many scenes")
+            .value_parser(clap::value_parser!(usize)),
+        // Optional grouping of chapters into acts
+        Arg::new("acts")
+            .long("acts")
+            .help("Group the chapters into this many acts (Act I, Act II, ...) rendered as headings in the exports")
+            .value_parser(clap::value_parser!(usize)),
+        // Optional "previously on" recaps rendered at chapter heads
+        Arg::new("chapter-summaries")
+            .long("chapter-summaries")
+            .help("Generate chapter summaries and render them as recaps in the markdown export")
+            .action(clap::ArgAction::SetTrue),
+        // Optional previous story whose continuity seeds this one
+        Arg::new("sequel-of")
+            .long("sequel-of")
+            .help("Generate a sequel to the given story.json, carrying over its continuity"),
+        // Optional character name consistency checking
+        Arg::new("check-names")
+            .long("check-names")
+            .help("Flag misspelled and unintroduced character names in generated scenes")
+            .action(clap::ArgAction::SetTrue),
+        // Optional automatic correction of misspelled character names
+        Arg::new("fix-names")
+            .long("fix-names")
+            .help("Replace misspelled character names with their registered spelling")
+            .action(clap::ArgAction::SetTrue),
+        // Optional glossary appendix of invented terms
+        Arg::new("glossary")
+            .long("glossary")
+            .help("Generate a glossary of invented terms and add it as an appendix to the markdown export")
+            .action(clap::ArgAction::SetTrue),
+        // Optional genre tags, content warnings, and keywords
+        Arg::new("tags")
+            .long("tags")
+            .help("Generate genre tags, content warnings, and keywords for the story metadata")
+            .action(clap::ArgAction::SetTrue),
+        // Optional image-generation prompts for the cover and chapters
+        Arg::new("illustration-briefs")
+            .long("illustration-briefs")
+            .help("Generate image prompts for a cover and per-chapter illustrations and save them as artifacts")
+            .action(clap::ArgAction::SetTrue),
+        // Optional illustration of every scene by an image-generation model
+        Arg::new("image-url")
+            .long("image-url")
+            .help("OpenAI-compatible image endpoint to illustrate every scene with, e.g. https://api.openai.com/v1/images/generations; the API key is read from IMAGE_API_KEY"),
+        Arg::new("image-model")
+            .long("image-model")
+            .help("Image model of the endpoint")
+            .default_value("dall-e-3"),
+        Arg::new("image-size")
+            .long("image-size")
+            .help("Size of the scene illustrations")
+            .default_value(DEFAULT_IMAGE_SIZE),
+        // Optional profile of where the run spent its time
+        Arg::new("trace")
+            .long("trace")
+            .help("Write the timings of every scene's prompt building, generation, revision, and parsing, and of saving, \
+                   to this JSON trace file (open it in chrome://tracing or Perfetto)"),
+        // Optional reading-time and pacing appendix
+        Arg::new("stats")
+            .long("stats")
+            .help("Append a reading-time and pacing report to the markdown export")
+            .action(clap::ArgAction::SetTrue),
+        // Optional styled HTML export
+        Arg::new("html")
+            .long("html")
+            .help("Also export a styled HTML page with a table of contents and branch links")
+            .action(clap::ArgAction::SetTrue),
+        // Optional Twine export
+        Arg::new("twee")
+            .long("twee")
+            .help("Also export the story as a Twee 3 file for Twine, one passage per scene")
+            .action(clap::ArgAction::SetTrue),
+        // Optional Ink export
+        Arg::new("ink")
+            .long("ink")
+            .help("Also export the story as an Ink script for inklecate, one knot per scene")
+            .action(clap::ArgAction::SetTrue),
+        // Optional pass writing the text of branch choices
+        Arg::new("choice-labels")
+            .long("choice-labels")
+            .help("Have the AI write the choice text of links to alternative branches")
+            .action(clap::ArgAction::SetTrue),
+        // Optional screenplay export
+        Arg::new("fountain")
+            .long("fountain")
+            .help("Also export the main storyline as a Fountain screenplay")
+            .action(clap::ArgAction::SetTrue),
+        // Optional pass reformatting scenes as a screenplay
+        Arg::new("ai-screenplay")
+            .long("ai-screenplay")
+            .help("Have the AI reformat each scene as a screenplay for the Fountain export instead of converting it with heuristics")
+            .action(clap::ArgAction::SetTrue),
+        // Optional exports through user templates
+        Arg::new("template")
+            .long("template")
+            .help("Also export the story through a Tera template, written next to the output as <output>_<template name> (repeatable)")
+            .action(clap::ArgAction::Append),
+        // Optional dataset export
+        Arg::new("jsonl")
+            .long("jsonl")
+            .help("Also export one JSON Lines training record per node, with the reconstructed prompt and the completion")
+            .action(clap::ArgAction::SetTrue),
+        // Optional Graphviz export of the chain topology
+        Arg::new("dot")
+            .long("dot")
+            .help("Also export the scene graph as a Graphviz DOT file")
+            .action(clap::ArgAction::SetTrue),
+        // Optional Mermaid export of the chain topology
+        Arg::new("mermaid")
+            .long("mermaid")
+            .help("Also export the scene graph as a Mermaid flowchart")
+            .action(clap::ArgAction::SetTrue),
+        // Metadata key the scene graph is colored by
+        Arg::new("color-by")
+            .long("color-by")
+            .help("Node metadata key that colors the scene graph")
+            .default_value(DEFAULT_COLOR_KEY),
+        // Optional plain-text export
+        Arg::new("text")
+            .long("text")
+            .help("Also export the story as plain prose without markdown")
+            .action(clap::ArgAction::SetTrue),
+        // Optional export of one file per scene
+        Arg::new("scenes-dir")
+            .long("scenes-dir")
+            .help("Also write every scene to its own text file in this directory"),
+        // Optional Word export
+        Arg::new("docx")
+            .long("docx")
+            .help("Also export a Word document with scene headings and the AI's reasoning as comments (requires the docx feature)")
+            .action(clap::ArgAction::SetTrue),
+        // Optional e-book export
+        Arg::new("epub")
+            .long("epub")
+            .help("Also export an EPUB e-book with the title, blurb, and tags as its metadata (requires the epub feature)")
+            .action(clap::ArgAction::SetTrue),
+        // Optional typeset PDF export
+        Arg::new("pdf")
+            .long("pdf")
+            .help("Also export a typeset PDF manuscript (requires the pdf feature)")
+            .action(clap::ArgAction::SetTrue),
+        // Optional choose-your-own-adventure HTML export
+        Arg::new("interactive-html")
+            .long("interactive-html")
+            .help("Also export an interactive HTML page where readers choose between branches")
+            .action(clap::ArgAction::SetTrue),
+        // Optional viewpoint characters to alternate between scenes
+        Arg::new("pov")
+            .long("pov")
+            .help("Comma-separated POV character artifacts (IDs or names) to alternate between scenes")
+            .value_delimiter(','),
+        // How the POV of each scene is chosen
+        Arg::new("pov-mode")
+            .long("pov-mode")
+            .help("How to select each scene's POV character")
+            .value_parser(["rotation", "ai"])
+            .default_value("rotation"),
+        // Structural format every scene must follow
+        Arg::new("format")
+            .long("format")
+            .help("Structural format for every scene")
+            .value_parser(["prose", "epistolary", "diary", "transcript"])
+            .default_value("prose"),
+        // Optional target share of dialogue in each scene
+        Arg::new("dialogue-ratio")
+            .long("dialogue-ratio")
+            .help("Target fraction of each scene's words that should be dialogue (0.0-1.0)")
+            .value_parser(clap::value_parser!(f64)),
+        // Optional revision of scenes that miss the dialogue target
+        Arg::new("revise-dialogue")
+            .long("revise-dialogue")
+            .help("Revise scenes that drift from the dialogue target instead of only nudging the next prompt")
+            .action(clap::ArgAction::SetTrue),
+        // Optional critic pass checking each scene for contradictions
+        Arg::new("check-consistency")
+            .long("check-consistency")
+            .help("Check every new scene against the premise, character sheets, lore, and earlier scenes for contradictions")
+            .action(clap::ArgAction::SetTrue),
+        // Optional revision of scenes with contradictions
+        Arg::new("revise-inconsistencies")
+            .long("revise-inconsistencies")
+            .help("Revise scenes in which --check-consistency finds contradictions (implies --check-consistency)")
+            .action(clap::ArgAction::SetTrue),
+        // Optional extraction of per-scene metadata
+        Arg::new("enrich")
+            .long("enrich")
+            .help("Record the characters, location, time, and mood of every scene in its metadata (heuristic or ai)")
+            .value_parser(|name: &str| name.parse::<EnrichmentMode>()),
+        // Optional revision loop scoring each scene on several criteria
+        Arg::new("revise-until")
+            .long("revise-until")
+            .help("Critique and rewrite each new scene until every criterion scores at least this (0-10)")
+            .value_parser(clap::value_parser!(f64)),
+        // Criteria of the revision loop
+        Arg::new("revision-criteria")
+            .long("revision-criteria")
+            .help("Comma-separated criteria scored by --revise-until (default: pacing, continuity, prose quality)")
+            .value_delimiter(',')
+            .value_parser(clap::value_parser!(String)),
+        // Rewrite limit of the revision loop
+        Arg::new("revision-rounds")
+            .long("revision-rounds")
+            .help("Maximum rewrites per scene under --revise-until (default: 3)")
+            .value_parser(clap::value_parser!(usize)),
+        // Optional scene cards with an outline export
+        Arg::new("scene-cards")
+            .long("scene-cards")
+            .help("Have the model emit a scene card (goal, conflict, outcome, hook) per scene and export an outline")
+            .action(clap::ArgAction::SetTrue),
+        // Optional tracking of planted setups and their payoffs
+        Arg::new("track-setups")
+            .long("track-setups")
+            .help("Track setups planted by the model and warn about any never paid off")
+            .action(clap::ArgAction::SetTrue),
+        // Setups planted by the user before generation
+        Arg::new("plant")
+            .long("plant")
+            .help("Plant a setup that must be paid off later (repeatable; implies --track-setups)")
+            .action(clap::ArgAction::Append),
+        // Optional closing scene for open setups
+        Arg::new("resolve-setups")
+            .long("resolve-setups")
+            .help("Generate an extra scene that pays off any setups left open (implies --track-setups)")
+            .action(clap::ArgAction::SetTrue),
+        // Optional plot outline whose beats are foreshadowed in earlier scenes
+        Arg::new("outline")
+            .long("outline")
+            .help("ID of a plot outline artifact with lines like 'Scene 7: ...' to foreshadow")
+            .value_parser(clap::value_parser!(String)),
+        // How early foreshadowing starts
+        Arg::new("foreshadow-lead")
+            .long("foreshadow-lead")
+            .help("Number of scenes before each outline beat that foreshadow it")
+            .default_value("3")
+            .value_parser(clap::value_parser!(usize)),
+        // Optional check that the foreshadowing landed
+        Arg::new("verify-foreshadowing")
+            .long("verify-foreshadowing")
+            .help("After generation, check that each scene asked to foreshadow a beat actually does")
+            .action(clap::ArgAction::SetTrue),
+        // Alternative continuations per scene
+        Arg::new("branches")
+            .long("branches")
+            .help("Generate this many alternative continuations of every scene, turning the story into a tree")
+            .value_parser(clap::value_parser!(usize)),
+        // Concurrent generation of the alternative continuations
+        Arg::new("parallel")
+            .long("parallel")
+            .help("Generate the alternative continuations of a scene concurrently, at most this many at a time")
+            .value_parser(clap::value_parser!(usize)),
+        // Optional subplots woven into the main chain
+        Arg::new("subplot")
+            .long("subplot")
+            .help("ID of an artifact describing a subplot to weave into the story (repeatable)")
+            .action(clap::ArgAction::Append),
+        // Optional parallel storylines interleaved into the chain
+        Arg::new("strand")
+            .long("strand")
+            .help("ID of an artifact describing a parallel storyline, with optional 'name' and 'pov' metadata (repeatable)")
+            .action(clap::ArgAction::Append),
+        // Export order of the parallel storylines
+        Arg::new("strand-order")
+            .long("strand-order")
+            .help("Order of the strands' scenes in exports: interleaved (default) or grouped")
+            .value_parser(|name: &str| name.parse::<StrandOrder>()),
+        // How often subplot scenes appear
+        Arg::new("subplot-every")
+            .long("subplot-every")
+            .help("Insert a subplot scene after every Nth main-plot scene, unless the artifact sets 'every'")
+            .value_parser(clap::value_parser!(usize)),
+        // Running summary of earlier scenes
+        Arg::new("summary-every")
+            .long("summary-every")
+            .help("Keep a running summary of earlier scenes in continuation prompts, updated every N scenes")
+            .value_parser(clap::value_parser!(usize)),
+        // Length of the running summary
+        Arg::new("summary-words")
+            .long("summary-words")
+            .help("Maximum length of the running summary in words")
+            .value_parser(clap::value_parser!(usize)),
+        // Reader feedback in continuation prompts
+        Arg::new("feedback-guidance")
+            .long("feedback-guidance")
+            .help("Tell the model what readers thought of the scenes so far, from the ratings and comments recorded with `feedback`")
+            .action(clap::ArgAction::SetTrue),
+        // Optional recall of earlier scenes by embedding similarity
+        Arg::new("recall")
+            .long("recall")
+            .help("Include this many of the earlier scenes most similar to the previous one in each continuation prompt")
+            .value_parser(clap::value_parser!(usize)),
+        // Length of each recalled scene
+        Arg::new("recall-chars")
+            .long("recall-chars")
+            .help("Characters of each recalled scene to include (default: 1200)")
+            .value_parser(clap::value_parser!(usize)),
+        // Model embedding scenes for recall
+        Arg::new("embedding-model")
+            .long("embedding-model")
+            .help("Ollama embedding model used by --recall (default: nomic-embed-text)")
+            .value_parser(clap::value_parser!(String)),
+        // Optional tension curve the story should track
+        Arg::new("tension-curve")
+            .long("tension-curve")
+            .help("Desired tension curve, e.g. 'rising, dip@60, spike@90' (shapes: rising, falling, flat, arc)")
+            .value_parser(|spec: &str| spec.parse::<TensionCurve>()),
+        // Optional genre preset, overriding any preset named in the premise
+        Arg::new("genre")
+            .long("genre")
+            .help("Genre preset: noir, cozy-mystery, high-fantasy, or hard-sf")
+            .value_parser(|name: &str| name.parse::<GenrePreset>()),
+        // Optional generation rules checked against every scene
+        Arg::new("constraints")
+            .long("constraints")
+            .help("ID of an artifact listing generation rules, one per line (e.g. 'no character deaths', 'keep it PG-13')")
+            .value_parser(clap::value_parser!(String)),
+        // Optional content filter list screening every scene
+        Arg::new("content-filter")
+            .long("content-filter")
+            .help("File listing words (and /regular expressions/), one per line, that no scene may contain")
+            .value_parser(clap::value_parser!(String)),
+        // Optional audience rating screened for by the content filter
+        Arg::new("filter-rating")
+            .long("filter-rating")
+            .help("Screen every scene for the vocabulary not allowed under this rating (G, PG, or PG-13)")
+            .value_parser(|name: &str| name.parse::<ContentRating>()),
+        // What happens to scenes the content filter objects to
+        Arg::new("filter-action")
+            .long("filter-action")
+            .help("Reject and regenerate scenes the content filter objects to, or redact the matches (reject or redact; default: reject)")
+            .value_parser(|name: &str| name.parse::<FilterAction>()),
+        // Regenerations of scenes the content filter rejects
+        Arg::new("filter-retries")
+            .long("filter-retries")
+            .help("Times a rejected scene is regenerated before it is redacted instead (default: 2)")
+            .value_parser(clap::value_parser!(usize)),
+        // Optional template files for single prompts, taking precedence over --prompt-templates
+        Arg::new("initial-template")
+            .long("initial-template")
+            .help("Tera template file for the opening scene prompt")
+            .value_parser(clap::value_parser!(String)),
+        Arg::new("continuation-template")
+            .long("continuation-template")
+            .help("Tera template file for the continuation prompts")
+            .value_parser(clap::value_parser!(String)),
+        // Optional number of world-building snippets retrieved for each prompt
+        Arg::new("lore-snippets")
+            .long("lore-snippets")
+            .help("Number of the most relevant WorldBuilding snippets included in each prompt (default: 3, 0 to disable)")
+            .value_parser(clap::value_parser!(usize)),
+        // Optional system prompt kept separate from the scene prompts
+        Arg::new("system-prompt")
+            .long("system-prompt")
+            .help("System prompt (author persona, global style rules) sent with every scene prompt")
+            .value_parser(clap::value_parser!(String)),
+        // Optional system prompt read from a file
+        Arg::new("system-prompt-file")
+            .long("system-prompt-file")
+            .help("File containing the system prompt")
+            .conflicts_with("system-prompt")
+            .value_parser(clap::value_parser!(String)),
+        // Prompt budget
+        Arg::new("max-context-tokens")
+            .long("max-context-tokens")
+            .help("Context window budget in tokens; continuation prompts are trimmed to fit it (default: --num-ctx if given)")
+            .value_parser(clap::value_parser!(usize)),
+        // Optional quality gate regenerating low-scoring scenes
+        Arg::new("min-score")
+            .long("min-score")
+            .help("Regenerate scenes a critic model scores below this (0-10)")
+            .value_parser(clap::value_parser!(f64)),
+        // How often a low-scoring scene is regenerated
+        Arg::new("max-retries")
+            .long("max-retries")
+            .help("Maximum regenerations per scene under --min-score")
+            .value_parser(clap::value_parser!(usize)),
+        // Best-of-N selection of every main-plot scene
+        Arg::new("best-of")
+            .long("best-of")
+            .help("Generate this many candidates for every scene and keep the one a critic model ranks best")
+            .value_parser(clap::value_parser!(usize)),
+        // Model acting as critic for the quality gate and best-of-N selection
+        Arg::new("critic-model")
+            .long("critic-model")
+            .help("Model that scores scenes under --min-score and judges --best-of candidates, overriding the configuration file")
+            .value_parser(clap::value_parser!(String)),
+    ]
+}
+
+/// Returns the arguments that choose and configure the AI provider, shared
+/// by every subcommand that generates scenes
+fn provider_args() -> Vec<Arg> {
+    vec![
+        // Optional directory of custom prompt templates
+        Arg::new("prompt-templates")
+            .long("prompt-templates")
+            .help("Directory of <name>.tera files overriding the built-in prompts, such as initial.tera or synopsis.tera")
+            .value_parser(clap::value_parser!(String)),
+        // Optional chat mode keeping the conversation across scenes
+        Arg::new("chat")
+            .long("chat")
+            .help("Hold one chat conversation with the model across all scenes instead of standalone prompts")
+            .action(clap::ArgAction::SetTrue),
+        // How often a failed request is attempted
+        Arg::new("max-attempts")
+            .long("max-attempts")
+            .help("Attempts per AI request before a transient error aborts the run, with exponential backoff between them")
+            .value_parser(clap::value_parser!(usize)),
+        // Escape hatch from the response cache
+        Arg::new("no-cache")
+            .long("no-cache")
+            .help("Always ask the model instead of reusing cached responses to identical prompts")
+            .action(clap::ArgAction::SetTrue),
+        // Directory of the response cache
+        Arg::new("cache-dir")
+            .long("cache-dir")
+            .help("Directory of the response cache")
+            .default_value(DEFAULT_CACHE_DIR),
+        // Optional invalidation of the whole response cache
+        Arg::new("clear-cache")
+            .long("clear-cache")
+            .help("Remove every cached response before generating")
+            .action(clap::ArgAction::SetTrue),
+        // Optional recording of every prompt and response
+        Arg::new("record")
+            .long("record")
+            .help("Write every prompt and response to this JSON file for later replay"),
+        // Optional replay of a recording instead of a model
+        Arg::new("replay")
+            .long("replay")
+            .help("Answer with the responses recorded in this JSON file instead of asking a model")
+            .conflicts_with("record"),
+        // Optional structured log of every request
+        Arg::new("ai-log")
+            .long("ai-log")
+            .help("Append every request to the model and its response, timing, or error to this JSON Lines file"),
+        // Optional live output of every response
+        Arg::new("stream")
+            .long("stream")
+            .help("Print the model's output live as it is generated")
+            .action(clap::ArgAction::SetTrue),
+        // Optional HTTP API instead of the ollama command-line client
+        Arg::new("http")
+            .long("http")
+            .help("Talk to Ollama through its HTTP API instead of running the ollama command")
+            .action(clap::ArgAction::SetTrue),
+        // Shape of the model's responses
+        Arg::new("response-format")
+            .long("response-format")
+            .help("How responses split into reasoning and content: think-tags (default), json, markdown, or no-reasoning")
+            .value_parser(|name: &str| name.parse::<ResponseFormat>()),
+        // How long Ollama keeps the model loaded in HTTP mode
+        Arg::new("keep-alive")
+            .long("keep-alive")
+            .help("How long Ollama keeps the model loaded between requests in HTTP mode, e.g. 10m or -1")
+            .value_parser(clap::value_parser!(String)),
+        // Longest wait for a response before the request is abandoned
+        Arg::new("request-timeout")
+            .long("request-timeout")
+            .help("Abandon a request the model has not answered within this many seconds; it is retried like a server error")
+            .value_parser(clap::value_parser!(u64)),
+        // Sampling temperature in HTTP mode
+        Arg::new("temperature")
+            .long("temperature")
+            .help("Sampling temperature in HTTP mode")
+            .value_parser(clap::value_parser!(f64)),
+        // Nucleus sampling in HTTP mode
+        Arg::new("top-p")
+            .long("top-p")
+            .help("Nucleus sampling probability mass in HTTP mode")
+            .value_parser(clap::value_parser!(f64)),
+        // Top-k sampling in HTTP mode
+        Arg::new("top-k")
+            .long("top-k")
+            .help("Number of most likely tokens sampled from in HTTP mode")
+            .value_parser(clap::value_parser!(usize)),
+        // Response length limit in HTTP mode
+        Arg::new("max-tokens")
+            .long("max-tokens")
+            .help("Maximum number of tokens generated per response in HTTP mode")
+            .value_parser(clap::value_parser!(usize)),
+        // Repetition penalty in HTTP mode
+        Arg::new("repeat-penalty")
+            .long("repeat-penalty")
+            .help("Penalty for repeating recent tokens in HTTP mode; 1.0 disables it")
+            .value_parser(clap::value_parser!(f64)),
+        // Stop sequences in HTTP mode
+        Arg::new("stop")
+            .long("stop")
+            .help("Sequence that ends a response in HTTP mode; can be given several times")
+            .action(clap::ArgAction::Append)
+            .value_parser(clap::value_parser!(String)),
+        // Context window in HTTP mode
+        Arg::new("num-ctx")
+            .long("num-ctx")
+            .help("Context window size in tokens in HTTP mode")
+            .value_parser(clap::value_parser!(usize)),
+        // Sampling seed in HTTP mode
+        Arg::new("seed")
+            .long("seed")
+            .help("Sampling seed in HTTP mode; the same premise and seed reproduce the same story")
+            .value_parser(clap::value_parser!(u64)),
+        // Optional target length of each scene
+        Arg::new("scene-words")
+            .long("scene-words")
+            .help("Target number of words per scene; scenes that miss it are expanded or trimmed")
+            .value_parser(clap::value_parser!(usize)),
+        // Tolerance of the scene length target
+        Arg::new("length-tolerance")
+            .long("length-tolerance")
+            .help("Fraction of --scene-words a scene may deviate by before it is adjusted (default: 0.2)")
+            .value_parser(clap::value_parser!(f64)),
+        // Ollama server used in chat and HTTP mode
+        Arg::new("ollama-url")
+            .long("ollama-url")
+            .help("Base URL of the Ollama server used in chat and HTTP mode (default: http://localhost:11434)")
+            .value_parser(clap::value_parser!(String)),
+        // Provider configuration file
+        Arg::new("config")
+            .long("config")
+            .help("Configuration file describing the provider, critic, and generation parameters (default: storychain.toml if present)")
+            .value_parser(clap::value_parser!(String)),
+        // Model override
+        Arg::new("model")
+            .long("model")
+            .help("Model to generate with, overriding the configuration file (default: deepseek-r1:32b)")
+            .value_parser(clap::value_parser!(String)),
+        // History budget in chat mode
+        Arg::new("chat-history-chars")
+            .long("chat-history-chars")
+            .help("Characters of chat history kept before older messages are summarized")
+            .value_parser(clap::value_parser!(usize)),
+    ]
+}
+
+/// Reads the configuration file named by `--config`, or `storychain.toml`
+/// when present, and applies the provider flags over it
+///
+/// # Arguments
+/// * `matches` - The arguments of a subcommand taking the provider arguments
+fn load_config(matches: &ArgMatches) -> Result<StoryChainConfig, StoryChainError> {
+    let mut config = match matches.get_one::<String>("config") {
+        Some(path) => StoryChainConfig::load(path)?,
+        None => StoryChainConfig::load_or_default(DEFAULT_CONFIG_FILE)?,
+    };
+
+    // Command-line flags take precedence over the configuration file
+    let provider_config = &mut config.provider;
+    if matches.get_flag("chat") {
+        provider_config.kind = ProviderKind::OllamaChat;
+    } else if matches.get_flag("http") {
+        provider_config.kind = ProviderKind::OllamaHttp;
+    }
+    if let Some(model) = matches.get_one::<String>("model") {
+        provider_config.model = model.clone();
+    }
+    if let Some(ollama_url) = matches.get_one::<String>("ollama-url") {
+        provider_config.endpoint = Some(ollama_url.clone());
+    }
+    if let Some(response_format) = matches.get_one::<ResponseFormat>("response-format") {
+        provider_config.response_format = *response_format;
+    }
+    if let Some(keep_alive) = matches.get_one::<String>("keep-alive") {
+        provider_config.keep_alive = Some(keep_alive.clone());
+    }
+    if let Some(request_timeout) = matches.get_one::<u64>("request-timeout") {
+        provider_config.timeout_secs = Some(*request_timeout);
+    }
+    if let Some(chat_history_chars) = matches.get_one::<usize>("chat-history-chars") {
+        provider_config.max_history_chars = Some(*chat_history_chars);
+    }
+    config.generation = GenerationConfig {
+        temperature: matches.get_one::<f64>("temperature").copied(),
+        top_p: matches.get_one::<f64>("top-p").copied(),
+        top_k: matches.get_one::<usize>("top-k").copied(),
+        max_tokens: matches.get_one::<usize>("max-tokens").copied(),
+        repeat_penalty: matches.get_one::<f64>("repeat-penalty").copied(),
+        stop: matches.get_many::<String>("stop").map(|s| s.cloned().collect()).unwrap_or_default(),
+        num_ctx: matches.get_one::<usize>("num-ctx").copied(),
+        seed: matches.get_one::<u64>("seed").copied(),
+    }
+    .merged_over(&config.generation);
+    if let Some(words) = matches.get_one::<usize>("scene-words") {
+        config.length = Some(LengthTarget { words: *words, ..config.length.unwrap_or_default() });
+    }
+    if let (Some(tolerance), Some(length)) = (matches.get_one::<f64>("length-tolerance"), config.length.as_mut()) {
+        length.tolerance = tolerance.clamp(0.0, 1.0);
+    }
+    if config.provider.kind != ProviderKind::OllamaHttp && !config.generation.is_empty() {
+        warn!("Generation parameters are only sent to the model in HTTP mode (--http)");
+    }
+    Ok(config)
+}
+
+/// Loads the prompt templates, with the overrides in the `--prompt-templates`
+/// directory when one is given
+///
+/// # Arguments
+/// * `matches` - The arguments of a subcommand taking the provider arguments
+fn load_prompt_templates(matches: &ArgMatches) -> Result<PromptTemplates, StoryChainError> {
+    match matches.get_one::<String>("prompt-templates") {
+        Some(dir) => PromptTemplates::from_dir(dir),
+        None => Ok(PromptTemplates::default()),
+    }
+}
+
+/// Creates the configured AI provider, wrapped as the provider flags ask
+///
+/// Requests are retried on transient errors and answered from the response
+/// cache when possible; the run can be recorded or replayed, and responses
+/// printed live as they arrive.
+///
+/// # Arguments
+/// * `matches` - The arguments of a subcommand taking the provider arguments
+/// * `config` - The configuration returned by `load_config`
+fn build_provider(matches: &ArgMatches, config: &StoryChainConfig) -> Result<Box<dyn AIProvider>, StoryChainError> {
+    // Talk to the model through the ollama command or its HTTP API, or, in
+    // chat mode, hold one conversation with it across all scenes
+    let max_attempts = matches.get_one::<usize>("max-attempts").copied().unwrap_or(DEFAULT_MAX_ATTEMPTS);
+    let provider: Box<dyn AIProvider> = Box::new(UsageTracker::new(ProviderFactory::from_config_with_templates(&config.provider, &load_prompt_templates(matches)?)?));
+
+    // Log every attempt, failed ones included, for later analysis
+    let provider: Box<dyn AIProvider> = match matches.get_one::<String>("ai-log") {
+        Some(log_file) => Box::new(InteractionLogger::new(provider, log_file)),
+        None => provider,
+    };
+    let provider = RetryingProvider::new(provider).with_max_attempts(max_attempts);
+
+    // Answer identical prompts from the response cache; chat mode depends on
+    // the conversation so far and is never cached
+    let cache_dir = matches.get_one::<String>("cache-dir").unwrap();
+    if matches.get_flag("clear-cache") {
+        clear_cache(cache_dir)?;
+    }
+    let provider: Box<dyn AIProvider> = if matches.get_flag("no-cache") || config.provider.kind == ProviderKind::OllamaChat {
+        Box::new(provider)
+    } else {
+        Box::new(CachingProvider::new(provider, cache_dir, config.provider.model.clone()))
+    };
+
+    // Record the run for golden tests, or replay a recorded run without a model
+    let provider: Box<dyn AIProvider> = match (matches.get_one::<String>("record"), matches.get_one::<String>("replay")) {
+        (_, Some(replay_file)) => Box::new(ReplayProvider::from_file(replay_file)?),
+        (Some(record_file), None) => Box::new(RecordingProvider::new(provider, record_file)),
+        (None, None) => provider,
+    };
+
+    // Print every response live while the scenes are assembled as usual
+    if matches.get_flag("stream") {
+        return Ok(Box::new(StreamingProvider::new(provider, |token| {
+            print!("{}", token);
+            let _ = std::io::Write::flush(&mut std::io::stdout());
+        })));
+    }
+    Ok(provider)
+}
+
+/// Returns the path of the JSON file a story file is saved to
+fn json_path(path: &str) -> String {
+    if path.ends_with(GZIP_EXTENSION) || path.ends_with(ZSTD_EXTENSION) {
+        return path.to_string();
+    }
+    std::path::Path::new(path).with_extension("json").to_string_lossy().to_string()
+}
+
+/// Loads a saved story from its JSON file, or imports it from a markdown export
+fn load_story(path: &str) -> Result<StoryChain, StoryChainError> {
+    if path.ends_with(".md") {
+        StoryChain::import_from_markdown(path)
+    } else {
+        StoryChain::load_from_file(path)
+    }
+}
+
+/// Loads a story from a store, listing the stored stories when it is missing
+fn load_stored_story(store: &dyn ChainStore, name: &str) -> Result<StoryChain, StoryChainError> {
+    store.load_chain(name).map_err(|e| match (e, store.list_chains()) {
+        (StoryChainError::StorageError(message), Ok(names)) if !names.is_empty() => {
+            StoryChainError::StorageError(format!("{}; the store holds: {}", message, names.join(", ")))
+        }
+        (e, _) => e,
+    })
+}
+
+/// Waits until the process is asked to stop with Ctrl-C or, on Unix, SIGTERM
+async fn shutdown_signal() -> Result<(), StoryChainError> {
+    #[cfg(unix)]
+    {
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
+
+/// Prints a prompt and reads a line from standard input
+///
+/// # Returns
+/// The line without its line ending, or `None` at the end of the input
+fn read_line(prompt: &str) -> Result<Option<String>, StoryChainError> {
+    use std::io::Write;
+    print!("{}", prompt);
+    std::io::stdout().flush()?;
+    let mut line = String::new();
+    if std::io::stdin().read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+/// Opens text in the author's editor ($VISUAL, then $EDITOR, then vi)
+///
+/// # Returns
+/// The edited text, or the original text when the editor fails
+fn edit_in_editor(text: &str) -> Result<String, StoryChainError> {
+    let path = std::env::temp_dir().join(format!("storychain_scene_{}.md", std::process::id()));
+    std::fs::write(&path, text)?;
+    let editor = std::env::var("VISUAL").or_else(|_| std::env::var("EDITOR")).unwrap_or_else(|_| "vi".to_string());
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or("vi");
+    let status = std::process::Command::new(program).args(parts).arg(&path).status()?;
+    let edited = std::fs::read_to_string(&path)?;
+    std::fs::remove_file(&path)?;
+    if !status.success() {
+        warn!("{} exited with {}; keeping the scene as it was", program, status);
+        return Ok(text.to_string());
+    }
+    Ok(edited.trim().to_string())
+}
+
+/// Reviews scenes on the terminal, reading the author's choices from
+/// standard input and opening edits in their editor
+struct TerminalReviewer;
+
+impl Reviewer for TerminalReviewer {
+    /// Shows a scene and asks the author what to do with it until they accept,
+    /// reject, or quit; edits and steering notes are applied along the way
+    fn review(&mut self, chain: &mut StoryChain, node_id: &str, label: &str) -> Result<Review, StoryChainError> {
+        println!("\n=== {} ===\n\n{}\n", label, chain.nodes[node_id].content.trim());
+        loop {
+            if let Some(steering) = chain.nodes[node_id].metadata.get(STEERING_KEY) {
+                println!("Steering for the next scene: {}", steering.replace('\n', "; "));
+            }
+            let Some(choice) = read_line("[a]ccept, [r]eject and regenerate, [e]dit, [s]teer the next scene, show the AI's [w]hy, [q]uit: ")? else {
+                return Ok(Review::Quit);
+            };
+            match choice.trim() {
+                "a" | "" => return Ok(Review::Accept),
+                "r" => return Ok(Review::Reject(read_line("Reason (optional): ")?)),
+                "e" => {
+                    let edited = edit_in_editor(&chain.nodes[node_id].content)?;
+                    chain.edit_node(node_id, edited)?;
+                    println!("\n{}\n", chain.nodes[node_id].content.trim());
+                }
+                "s" => {
+                    if let Some(note) = read_line("Steering note: ")? {
+                        chain.add_steering_note(node_id, &note)?;
+                    }
+                }
+                "w" => println!("\n{}\n", chain.nodes[node_id].reasoning.trim()),
+                "q" => return Ok(Review::Quit),
+                other => println!("Unknown choice: {}", other),
+            }
+        }
+    }
+
+    fn generating(&mut self, label: &str) {
+        println!("Generating {}...", label.to_lowercase());
+    }
+}
+
+/// Generates a story one scene at a time along the main storyline, asking
+/// the author to review every scene before continuing
+///
+/// The story is saved after every accepted scene and when the author quits.
+///
+/// # Arguments
+/// * `matches` - The arguments of the `interactive` subcommand
+/// * `project` - The project worked in, if any
+async fn run_interactive(matches: &ArgMatches, project: Option<&Project>) -> Result<(), StoryChainError> {
+    let premise_file = &premise_arg_value(matches, project)?;
+    let epochs = *matches.get_one::<usize>("epochs").unwrap();
+    let output_file = &match (matches.value_source("output"), project) {
+        (Some(ValueSource::DefaultValue), Some(project)) => {
+            project.chain_path(&project.manifest.name).to_string_lossy().to_string()
+        }
+        _ => matches.get_one::<String>("output").unwrap().clone(),
+    };
+    let resume_file = matches.get_one::<String>("resume").map(|story| match project {
+        Some(project) => project.resolve_story(story),
+        None => story.clone(),
+    });
+    let export_base = uncompressed_path(output_file);
+    if premise_file == STDIN_PREMISE {
+        return Err(StoryChainError::ConfigError(
+            "Interactive mode reads the review from standard input; give the premise as a file".to_string(),
+        ));
+    }
+    let config = load_config(matches)?;
+    let provider = build_provider(matches, &config)?;
+
+    let premise = Premise::parse(&load_premise(premise_file, Path::new("artifacts"))?)?.to_prompt_section();
+    let mut artifact_manager = ArtifactManager::new("artifacts");
+    artifact_manager.load_from_dir()?;
+    let mut prompt_templates = load_prompt_templates(matches)?;
+    prompt_templates.add_artifacts(&artifact_manager);
+
+    let resumed = resume_file.as_deref().map(load_story).transpose()?;
+    let save = |chain: &StoryChain| -> Result<(), StoryChainError> {
+        chain.export_to_file(output_file)?;
+        chain.export_to_markdown(&export_base.replace(".json", ".md"))?;
+        println!("Saved the story to {}", output_file);
+        Ok(())
+    };
+    let session = InteractiveSession::new(provider.as_ref(), premise, epochs)
+        .templates(prompt_templates)
+        .generation(config.generation.clone())
+        .length(config.length.clone());
+    if session.run(resumed, &mut TerminalReviewer, save).await? {
+        println!("The story is complete.");
+    }
+    Ok(())
+}
+
+/// Converts a saved story to another format
+///
+/// # Arguments
+/// * `matches` - The arguments of the `convert` subcommand
+/// * `project` - The project worked in, if any
+fn run_convert(matches: &ArgMatches, project: Option<&Project>) -> Result<(), StoryChainError> {
+    let story_file = &story_arg(matches, project);
+    let format: ExportFormat = matches.get_one::<String>("to").unwrap().parse().map_err(StoryChainError::ConfigError)?;
+    let output_file = match matches.get_one::<String>("output") {
+        Some(output_file) => output_file.clone(),
+        None => {
+            let stem = std::path::Path::new(uncompressed_path(story_file)).with_extension("");
+            format!("{}{}", stem.to_string_lossy(), format.file_suffix())
+        }
+    };
+    if output_file == *story_file {
+        return Err(StoryChainError::ConfigError(format!("Converting {} would overwrite it; choose another --output", story_file)));
+    }
+
+    let chain = load_story(story_file)?;
+    match format {
+        ExportFormat::Dot => chain.export_to_dot(&output_file, matches.get_one::<String>("color-by").unwrap())?,
+        ExportFormat::Mermaid => chain.export_to_mermaid(&output_file, matches.get_one::<String>("color-by").unwrap())?,
+        ExportFormat::Jsonl => {
+            let premise = match matches.get_one::<String>("premise") {
+                Some(premise_file) => Some(Premise::parse(&load_premise(premise_file, Path::new("artifacts"))?)?.to_prompt_section()),
+                None => None,
+            };
+            chain.export_to_jsonl(&output_file, premise.as_deref())?;
+        }
+        format => chain.export_as(format, &output_file)?,
+    }
+    println!("Converted {} to {}", story_file, output_file);
+    Ok(())
+}
+
+/// Translates a saved story into another language, writing the translation
+/// next to the original with a markdown export
+///
+/// # Arguments
+/// * `matches` - The arguments of the `translate` subcommand
+/// * `project` - The project worked in, if any
+async fn run_translate(matches: &ArgMatches, project: Option<&Project>) -> Result<(), StoryChainError> {
+    let story_file = &story_arg(matches, project);
+    let language = matches.get_one::<String>("to").unwrap().trim();
+    let output_file = match matches.get_one::<String>("output") {
+        Some(output_file) => output_file.clone(),
+        None => {
+            let stem = std::path::Path::new(uncompressed_path(story_file)).with_extension("");
+            let suffix = language.to_lowercase().replace(char::is_whitespace, "-");
+            format!("{}.{}.json", stem.to_string_lossy(), suffix)
+        }
+    };
+    if output_file == *story_file {
+        return Err(StoryChainError::ConfigError(format!("Translating {} would overwrite it; choose another --output", story_file)));
+    }
+    let config = load_config(matches)?;
+    let provider = build_provider(matches, &config)?;
+
+    let mut chain = load_story(story_file)?;
+    chain.prompts = load_prompt_templates(matches)?;
+    let translated = chain.translate(provider.as_ref(), language).await?;
+    translated.export_to_file(&output_file)?;
+    translated.export_to_markdown(&uncompressed_path(&output_file).replace(".json", ".md"))?;
+    println!("Translated {} into {}: {}", story_file, language_name(language), output_file);
+    Ok(())
+}
+
+/// Reads a saved story aloud into an audiobook directory
+///
+/// # Arguments
+/// * `matches` - The arguments of the `audiobook` subcommand
+/// * `project` - The project worked in, if any
+#[cfg(feature = "audio")]
+async fn run_audiobook(matches: &ArgMatches, project: Option<&Project>) -> Result<(), StoryChainError> {
+    use storychain::audio::{HttpTtsProvider, PiperTtsProvider, TtsProvider, AUDIOBOOK_FILE};
+
+    let story_file = &story_arg(matches, project);
+    let directory = match matches.get_one::<String>("output") {
+        Some(directory) => directory.clone(),
+        None => {
+            let stem = std::path::Path::new(uncompressed_path(story_file)).with_extension("");
+            format!("{}_audiobook", stem.to_string_lossy())
+        }
+    };
+    let tts: Box<dyn TtsProvider> = match matches.get_one::<String>("piper-model") {
+        Some(model) => {
+            let mut piper = PiperTtsProvider::new(model.clone());
+            if let Some(speaker) = matches.get_one::<u32>("speaker") {
+                piper = piper.with_speaker(*speaker);
+            }
+            Box::new(piper)
+        }
+        None => {
+            let mut http = HttpTtsProvider::new(
+                matches.get_one::<String>("tts-url").unwrap(),
+                matches.get_one::<String>("tts-model").unwrap().clone(),
+                matches.get_one::<String>("voice").unwrap().clone(),
+            );
+            if let Ok(api_key) = std::env::var("TTS_API_KEY") {
+                http = http.with_api_key(api_key);
+            }
+            Box::new(http)
+        }
+    };
+
+    let chain = load_story(story_file)?;
+    let markers = chain.export_to_audio(tts.as_ref(), &directory).await?;
+    let length = markers.last().map_or(0, |marker| marker.end.as_secs());
+    println!(
+        "Read {} aloud: {} chapters, {}:{:02}:{:02}, in {}",
+        story_file,
+        markers.len(),
+        length / 3600,
+        length / 60 % 60,
+        length % 60,
+        Path::new(&directory).join(AUDIOBOOK_FILE).display()
+    );
+    Ok(())
+}
+
+/// Rewrites story files in the current format, keeping the originals as
+/// `<file>.v<version>.bak`
+///
+/// # Arguments
+/// * `matches` - The arguments of the `migrate` subcommand
+/// * `project` - The project worked in, if any
+fn run_migrate(matches: &ArgMatches, project: Option<&Project>) -> Result<(), StoryChainError> {
+    let dry_run = matches.get_flag("dry-run");
+    for story in matches.get_many::<String>("stories").unwrap() {
+        let story_file = project.map_or_else(|| story.clone(), |project| project.resolve_story(story));
+        let original = std::fs::read(&story_file)?;
+        let mut value: serde_json::Value = serde_json::from_slice(&read_decompressed(&story_file)?)?;
+        let version = schema_version(&value);
+        let applied = migrate(&mut value)?;
+        if applied.is_empty() {
+            println!("{} is already at version {}", story_file, SCHEMA_VERSION);
+            continue;
+        }
+        println!("{}: version {} to {}", story_file, version, SCHEMA_VERSION);
+        for description in &applied {
+            println!("  - {}", description);
+        }
+        if dry_run {
+            continue;
+        }
+
+        // Read the migrated story back before replacing the original
+        let chain: StoryChain = serde_json::from_value(value)?;
+        let backup = format!("{}.v{}.bak", story_file, version);
+        write_atomic(&backup, original)?;
+        chain.export_to_file(&story_file)?;
+        println!("  saved; the original is in {}", backup);
+    }
+    Ok(())
+}
+
+/// Generates the same story with each of several models from identical
+/// prompts and writes a side-by-side report
+///
+/// # Arguments
+/// * `matches` - The arguments of the `compare` subcommand
+/// * `project` - The project worked in, if any
+async fn run_compare(matches: &ArgMatches, project: Option<&Project>) -> Result<(), StoryChainError> {
+    let premise_file = &premise_arg_value(matches, project)?;
+    let models: Vec<&String> = matches.get_many::<String>("models").unwrap().collect();
+    let epochs = *matches.get_one::<usize>("epochs").unwrap();
+    let output_file = matches.get_one::<String>("output").unwrap();
+    let config = load_config(matches)?;
+    // The per-model stories and the interleaved export are named after the report
+    let output_path = Path::new(output_file);
+    let stem = output_path.file_stem().and_then(|s| s.to_str()).unwrap_or("comparison");
+
+    let premise = Premise::parse(&load_premise(premise_file, Path::new("artifacts"))?)?.to_prompt_section();
+    let mut artifact_manager = ArtifactManager::new("artifacts");
+    artifact_manager.load_from_dir()?;
+    let mut templates = load_prompt_templates(matches)?;
+    templates.add_artifacts(&artifact_manager);
+
+    // Generate one story per model, each through the configured provider
+    let mut runs = Vec::new();
+    for model in models {
+        let mut model_config = config.clone();
+        model_config.provider.model = model.clone();
+        let provider = build_provider(matches, &model_config)?;
+        let mut run =
+            generate_comparison_run(model, provider.as_ref(), &premise, epochs, &templates, &config.generation).await?;
+        if let Some(seed) = config.generation.seed {
+            run.chain.metadata.insert("seed".to_string(), seed.to_string());
+        }
+
+        let chain_file = output_path.with_file_name(format!("{}_{}.json", stem, model.replace([':', '/'], "_")));
+        let chain_file = chain_file.to_string_lossy();
+        run.chain.export_to_file(&chain_file)?;
+        info!("{} finished in {:.0}s, story exported to {}", model, run.seconds, chain_file);
+        runs.push(run);
+    }
+
+    storychain::files::write_atomic(output_file, comparison_markdown(&runs))?;
+    info!("Comparison report written to {}", output_file);
+
+    if matches.get_flag("interleaved") {
+        let interleaved_file = output_path.with_file_name(format!("{}_interleaved.md", stem));
+        storychain::files::write_atomic(&interleaved_file, interleaved_markdown(&runs))?;
+        info!("Interleaved scenes written to {}", interleaved_file.display());
+    }
+    Ok(())
+}
+
+/// Prints the reading-time and pacing report of a saved story
+///
+/// # Arguments
+/// * `matches` - The arguments of the `stats` subcommand
+/// * `project` - The project worked in, if any
+fn run_stats(matches: &ArgMatches, project: Option<&Project>) -> Result<(), StoryChainError> {
+    let chain = load_story(&story_arg(matches, project))?;
+    let words_per_minute = matches.get_one::<usize>("words-per-minute").copied().unwrap_or(DEFAULT_WORDS_PER_MINUTE);
+    print!("{}", chain.stats(words_per_minute).to_markdown());
+    Ok(())
+}
+
+/// Sets the reading order of a saved story
+///
+/// # Arguments
+/// * `matches` - The arguments of the `reorder` subcommand
+/// * `project` - The project worked in, if any
+fn run_reorder(matches: &ArgMatches, project: Option<&Project>) -> Result<(), StoryChainError> {
+    // The story is written back to the store it was read from, in its compression
+    let (store, name): (Box<dyn ChainStore>, String) = match matches.get_one::<String>("store") {
+        Some(url) => (open_store(url)?, matches.get_one::<String>("story").unwrap().clone()),
+        None => {
+            let (store, name) = JsonFileStore::for_file(&story_arg(matches, project));
+            (Box::new(store), name)
+        }
+    };
+    let mut chain = load_stored_story(store.as_ref(), &name)?;
+
+    // "causal" restores the generation order; anything else is a list of node IDs
+    let order = matches.get_one::<String>("order").unwrap();
+    if order == "causal" {
+        chain.reset_reading_order();
+    } else {
+        chain.set_reading_order(order.split(',').map(|id| id.trim().to_string()).collect())?;
+    }
+    store.save_chain(&name, &chain)?;
+
+    let order: Vec<&str> = chain.nodes_in_reading_order().iter().map(|n| n.id.as_str()).collect();
+    println!("Reading order: {}", order.join(" -> "));
+    Ok(())
+}
+
+/// Writes character sheets for the characters a saved story established
+///
+/// # Arguments
+/// * `matches` - The arguments of the `characters` subcommand
+/// * `project` - The project worked in, if any
+async fn run_characters(matches: &ArgMatches, project: Option<&Project>) -> Result<(), StoryChainError> {
+    let mut chain = match matches.get_one::<String>("store") {
+        Some(url) => load_stored_story(open_store(url)?.as_ref(), matches.get_one::<String>("story").unwrap())?,
+        None => load_story(&story_arg(matches, project))?,
+    };
+    chain.prompts = load_prompt_templates(matches)?;
+    let config = load_config(matches)?;
+    let provider = build_provider(matches, &config)?;
+
+    // Load existing artifacts so that sheets are merged with them
+    let artifact_dir = matches.get_one::<String>("dir").unwrap();
+    let mut artifact_manager = ArtifactManager::new(artifact_dir);
+    artifact_manager.load_from_dir()?;
+
+    let ids = chain.generate_character_sheets(provider.as_ref(), &mut artifact_manager).await?;
+    for id in &ids {
+        println!("Wrote character sheet {}/{}.json", artifact_dir, id);
+    }
+    Ok(())
+}
+
+/// Records a reader's rating of a scene in a saved story
+///
+/// # Arguments
+/// * `matches` - The arguments of the `feedback` subcommand
+/// * `project` - The project worked in, if any
+fn run_feedback(matches: &ArgMatches, project: Option<&Project>) -> Result<(), StoryChainError> {
+    let story_file = &story_arg(matches, project);
+    let node_id = matches.get_one::<String>("node").unwrap();
+    let rating = *matches.get_one::<u8>("rating").unwrap();
+
+    let mut chain = StoryChain::load_from_file(story_file)?;
+    chain.add_feedback(node_id, rating, matches.get_one::<String>("comment").cloned())?;
+    chain.export_to_file(story_file)?;
+
+    let summary = chain.feedback_summary();
+    println!(
+        "Recorded rating {} for {}; average is now {:.1}/5 across {} ratings",
+        rating,
+        node_id,
+        summary.average_rating.unwrap_or_default(),
+        summary.count
+    );
+    Ok(())
+}
+
+/// Prints the structure and statistics of a saved story, or one of its scenes
+///
+/// # Arguments
+/// * `matches` - The arguments of the `inspect` subcommand
+/// * `project` - The project worked in, if any
+fn run_inspect(matches: &ArgMatches, project: Option<&Project>) -> Result<(), StoryChainError> {
+    let story_file = &story_arg(matches, project);
+    let chain = load_story(story_file)?;
+
+    if let Some(node_id) = matches.get_one::<String>("node") {
+        let node = chain.nodes.get(node_id).ok_or_else(|| {
+            StoryChainError::InvalidChainOperation(format!("{} has no node {}", story_file, node_id))
+        })?;
+        println!("# {}\n", node.id);
+        println!("Predecessors: {}", node.predecessors.join(", "));
+        println!("Successors: {}", node.successors.join(", "));
+        if let Some(provenance) = &node.provenance {
+            println!("\n## Provenance\n");
+            for line in provenance.to_lines() {
+                println!("{}", line);
+            }
+            println!();
+        }
+        let mut metadata: Vec<_> = node.metadata.iter().collect();
+        metadata.sort();
+        for (key, value) in metadata {
+            println!("{}: {}", key, value);
+        }
+        println!("\n## Reasoning\n\n{}\n\n## Content\n\n{}", node.reasoning.trim(), node.content.trim());
+        return Ok(());
+    }
+
+    let storylines = chain.storylines();
+    println!("# {}\n", chain.metadata.get("title").map(String::as_str).unwrap_or(story_file));
+    println!("Nodes: {}", chain.nodes.len());
+    println!("Storylines: {}", storylines.len());
+    println!("Main storyline: {} scenes", storylines.first().map_or(0, |line| line.len()));
+    println!(
+        "Epochs: {} of {}",
+        chain.completed_epochs(),
+        chain.metadata.get(EPOCHS_KEY).map(String::as_str).unwrap_or("?")
+    );
+    println!("Chapters: {}", chain.chapters.len());
+    println!("Setups: {}", chain.setups.len());
+    if !chain.usage.is_empty() {
+        println!("{}", chain.usage.total_text());
+    }
+    let mut metadata: Vec<_> = chain.metadata.iter().filter(|(key, _)| key.as_str() != "title").collect();
+    metadata.sort();
+    for (key, value) in metadata {
+        println!("{}: {}", key, value.replace('\n', " "));
+    }
+    println!("\n{}", chain.stats(DEFAULT_WORDS_PER_MINUTE).to_markdown().trim_end());
+    Ok(())
+}
+
+/// Lists the artifacts in the artifacts directory, or prints one of them
+///
+/// # Arguments
+/// * `matches` - The arguments of the `artifacts` subcommand
+fn run_artifacts(matches: &ArgMatches) -> Result<(), StoryChainError> {
+    let mut artifact_manager = ArtifactManager::new(matches.get_one::<String>("dir").unwrap());
+    artifact_manager.load_from_dir()?;
+
+    if let Some(id) = matches.get_one::<String>("id") {
+        let artifact = artifact_manager.get_artifact(id).ok_or_else(|| {
+            StoryChainError::IOError(std::io::Error::new(std::io::ErrorKind::NotFound, format!("No artifact found with ID {}", id)))
+        })?;
+        println!("{}", artifact.content.trim_end());
+        return Ok(());
+    }
+
+    for artifact in artifact_manager.get_all_artifacts() {
+        let first_line = artifact.content.lines().find(|l| !l.trim().is_empty()).unwrap_or_default().trim();
+        let tags = if artifact.tags.is_empty() { String::new() } else { format!(" [{}]", artifact.tags.join(", ")) };
+        println!("{}\t{:?}\t{}{}", artifact.id, artifact.artifact_type, first_line.chars().take(60).collect::<String>(), tags);
+    }
+    Ok(())
+}
+
+/// Lists the artifacts matching a full-text query, best matches first
+///
+/// # Arguments
+/// * `matches` - The arguments of the `artifacts search` subcommand
+fn run_artifacts_search(matches: &ArgMatches) -> Result<(), StoryChainError> {
+    let mut artifact_manager = ArtifactManager::new(matches.get_one::<String>("dir").unwrap());
+    artifact_manager.load_from_dir()?;
+
+    let query = matches.get_many::<String>("query").unwrap().cloned().collect::<Vec<_>>().join(" ");
+    let results = artifact_manager.search(&query);
+    if results.is_empty() {
+        println!("No artifacts match \"{}\".", query);
+        return Ok(());
+    }
+    for result in &results {
+        println!("{}\t{:?}\t{}: {}", result.artifact.id, result.artifact.artifact_type, result.field, result.snippet);
+    }
+    println!("{} artifacts match.", results.len());
+    Ok(())
+}
+
+/// Adds tags to an artifact, or removes them, and saves it
+///
+/// # Arguments
+/// * `matches` - The arguments of the `artifacts tag` subcommand
+fn run_artifacts_tag(matches: &ArgMatches) -> Result<(), StoryChainError> {
+    let mut artifact_manager = ArtifactManager::new(matches.get_one::<String>("dir").unwrap());
+    artifact_manager.load_from_dir()?;
+
+    let id = matches.get_one::<String>("artifact").unwrap();
+    let mut artifact = artifact_manager.get_artifact(id).cloned().ok_or_else(|| {
+        StoryChainError::IOError(std::io::Error::new(std::io::ErrorKind::NotFound, format!("No artifact found with ID {}", id)))
+    })?;
+    for tag in matches.get_many::<String>("tags").unwrap() {
+        if matches.get_flag("remove") {
+            artifact.remove_tag(tag);
+        } else {
+            artifact.add_tag(tag);
+        }
+    }
+    println!("{}: {}", artifact.id, if artifact.tags.is_empty() { "no tags".to_string() } else { artifact.tags.join(", ") });
+    artifact_manager.update_artifact(artifact)
+}
+
+/// Checks the artifacts' references and lists the artifacts in dependency order
+///
+/// # Arguments
+/// * `matches` - The arguments of the `artifacts check` subcommand
+fn run_artifacts_check(matches: &ArgMatches) -> Result<(), StoryChainError> {
+    let mut artifact_manager = ArtifactManager::new(matches.get_one::<String>("dir").unwrap());
+    artifact_manager.load_from_dir()?;
+    artifact_manager.validate()?;
+
+    for artifact in artifact_manager.topological_order()? {
+        match artifact.references().as_slice() {
+            [] => println!("{}\t{:?}", artifact.id, artifact.artifact_type),
+            references => println!("{}\t{:?}\treferences {}", artifact.id, artifact.artifact_type, references.join(", ")),
+        }
+    }
+    println!("All artifact references resolve.");
+    Ok(())
+}
+
+/// Deletes an artifact
+///
+/// # Arguments
+/// * `matches` - The arguments of the `artifacts delete` subcommand
+fn run_artifacts_delete(matches: &ArgMatches) -> Result<(), StoryChainError> {
+    let mut artifact_manager = ArtifactManager::new(matches.get_one::<String>("dir").unwrap());
+    artifact_manager.load_from_dir()?;
+
+    let artifact = artifact_manager.delete_artifact(matches.get_one::<String>("artifact").unwrap())?;
+    println!("Deleted {} ({:?})", artifact.id, artifact.artifact_type);
+    Ok(())
+}
+
+/// Renames an artifact
+///
+/// # Arguments
+/// * `matches` - The arguments of the `artifacts rename` subcommand
+fn run_artifacts_rename(matches: &ArgMatches) -> Result<(), StoryChainError> {
+    let mut artifact_manager = ArtifactManager::new(matches.get_one::<String>("dir").unwrap());
+    artifact_manager.load_from_dir()?;
+
+    let old_id = matches.get_one::<String>("artifact").unwrap();
+    let new_id = matches.get_one::<String>("new_id").unwrap();
+    artifact_manager.rename_artifact(old_id, new_id)?;
+    println!("Renamed {} to {}", old_id, new_id.trim());
+    Ok(())
+}
+
+/// Writes a premise, from an interview with the author or a one-line idea,
+/// and an arc for each of its characters into the artifacts directory
+///
+/// # Arguments
+/// * `matches` - The arguments of the `artifacts generate` subcommand
+/// * `project` - The project worked in, if any
+async fn run_artifacts_generate(matches: &ArgMatches, project: Option<&Project>) -> Result<(), StoryChainError> {
+    let artifact_dir = Path::new(matches.get_one::<String>("dir").unwrap());
+    let config = load_config(matches)?;
+    let provider = build_provider(matches, &config)?;
+    let templates = load_prompt_templates(matches)?;
+
+    let mut interview = match matches.get_one::<String>("idea") {
+        Some(idea) => PremiseInterview::from_idea(idea),
+        None => {
+            println!("Answer each question, press enter to leave it to the AI, or end the input to stop early.\n");
+            let mut interview = PremiseInterview::new(matches.get_one::<usize>("questions").copied().unwrap_or(DEFAULT_INTERVIEW_QUESTIONS));
+            interview
+                .run(&templates, provider.as_ref(), |question| {
+                    let answer = read_line(&format!("{}\n> ", question));
+                    println!();
+                    answer
+                })
+                .await?;
+            interview
+        }
+    };
+    if interview.exchanges.is_empty() {
+        interview = PremiseInterview::from_idea("Surprise me.");
+    }
+    let premise = interview.write_premise(&templates, provider.as_ref()).await?;
+
+    // Name the file after the project's premise, or the story's title
+    let name = match (matches.get_one::<String>("name"), project) {
+        (Some(name), _) => name.clone(),
+        (None, Some(project)) => project.manifest.premise.clone(),
+        (None, None) => Some(slugify(premise.title.as_deref().unwrap_or_default())).filter(|slug| !slug.is_empty()).unwrap_or_else(|| "premise".to_string()),
+    };
+    let premise_file = resolve_premise_path(&name, artifact_dir).unwrap_or_default();
+    // An empty premise, like the one `init` writes, is filled in rather than kept
+    let unfilled = std::fs::read_to_string(&premise_file)
+        .ok()
+        .and_then(|text| serde_yaml::from_str::<Premise>(&text).ok())
+        .is_some_and(|existing| existing.premise.trim().is_empty());
+    if premise_file.exists() && !unfilled && !matches.get_flag("force") {
+        return Err(StoryChainError::ConfigError(format!("{} already exists; pass --force to replace it", premise_file.display())));
+    }
+    std::fs::create_dir_all(artifact_dir)?;
+    write_atomic(&premise_file, premise.to_yaml()?)?;
+    println!("Wrote the premise to {}", premise_file.display());
+
+    let mut artifact_manager = ArtifactManager::new(&artifact_dir.to_string_lossy());
+    artifact_manager.load_from_dir()?;
+    for id in generate_character_arcs(&templates, provider.as_ref(), &premise, &mut artifact_manager).await? {
+        println!("Wrote the character arc {}", id);
+    }
+    println!("Generate the story with: storychain generate {}", premise_name(&name));
+    Ok(())
+}
+
+/// Serves the generation API for the projects in a directory
+///
+/// # Arguments
+/// * `matches` - The arguments of the `serve` subcommand
+#[cfg(feature = "serve")]
+async fn run_serve(matches: &ArgMatches) -> Result<(), StoryChainError> {
+    use storychain::server::{serve, StoryServer};
+
+    let config = load_config(matches)?;
+    let provider: Arc<dyn AIProvider> = Arc::from(build_provider(matches, &config)?);
+    let listener = tokio::net::TcpListener::bind(matches.get_one::<String>("addr").unwrap()).await?;
+    println!("Serving the projects in {} on http://{}", matches.get_one::<String>("dir").unwrap(), listener.local_addr()?);
+    serve(listener, Arc::new(StoryServer::new(matches.get_one::<String>("dir").unwrap(), provider))).await
+}
+
+/// Browses a saved story in the terminal, regenerating marked scenes and
+/// continuing the story when asked
+///
+/// The story is saved after every regeneration or continuation and when the
+/// browser is closed.
+///
+/// # Arguments
+/// * `matches` - The arguments of the `tui` subcommand
+/// * `project` - The project worked in, if any
+#[cfg(feature = "tui")]
+async fn run_tui(matches: &ArgMatches, project: Option<&Project>) -> Result<(), StoryChainError> {
+    use storychain::tui::{ChainBrowser, TuiRequest};
+    use storychain::EPOCHS_COMPLETED_KEY;
+
+    let story_file = &story_arg(matches, project);
+    let output_file = json_path(story_file);
+    let premise = match matches.get_one::<String>("premise") {
+        Some(premise_file) => Some(Premise::parse(&load_premise(premise_file, Path::new("artifacts"))?)?.to_prompt_section()),
+        None => None,
+    };
+    let config = load_config(matches)?;
+    let provider = build_provider(matches, &config)?;
+
+    let mut chain = load_story(story_file)?;
+    chain.prompts = load_prompt_templates(matches)?;
+    let mut artifact_manager = ArtifactManager::new("artifacts");
+    artifact_manager.load_from_dir()?;
+    chain.prompts.add_artifacts(&artifact_manager);
+
+    let mut browser = ChainBrowser::new(&chain);
+    loop {
+        let result = match storychain::tui::run(&mut chain, &mut browser)? {
+            TuiRequest::Quit => break,
+            TuiRequest::RegenerateMarked => {
+                println!("Regenerating the marked scenes...");
+                chain
+                    .regenerate_marked(provider.as_ref(), premise.as_deref())
+                    .await
+                    .map(|ids| format!("Regenerated {}", ids.join(", ")))
+            }
+            TuiRequest::Continue => {
+                // Continue past the planned length if the story already has it
+                let epoch = chain.completed_epochs() + 1;
+                let total = chain.metadata.get(EPOCHS_KEY).and_then(|e| e.parse().ok()).unwrap_or(epoch).max(epoch);
+                println!("Generating epoch {} of {}...", epoch, total);
+                let leaves = chain.frontier_ids();
+                let mut generated = 0;
+                let mut result = Ok(());
+                for leaf in &leaves {
+                    match chain.generate_next_nodes(leaf, provider.as_ref(), premise.as_deref(), epoch, total).await {
+                        Ok(ids) => generated += ids.len(),
+                        Err(error) => {
+                            result = Err(error);
+                            break;
+                        }
+                    }
+                }
+                chain.metadata.insert(EPOCHS_KEY.to_string(), total.to_string());
+                if result.is_ok() {
+                    chain.metadata.insert(EPOCHS_COMPLETED_KEY.to_string(), epoch.to_string());
+                }
+                result.map(|()| format!("Generated {} scenes in epoch {} of {}", generated, epoch, total))
+            }
+        };
+        match result {
+            Ok(status) => browser.set_status(status),
+            Err(error) => browser.set_status(format!("Failed: {}", error)),
+        }
+        chain.export_to_file(&output_file)?;
+        browser.refresh(&chain);
+    }
+    chain.export_to_file(&output_file)?;
+    println!("Saved the story to {}", output_file);
     Ok(())
 }
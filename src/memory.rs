@@ -0,0 +1,152 @@
+//! Story Memory
+//!
+//! Continuation prompts only include the scene being continued, so the model
+//! loses track of earlier events in long stories. This module keeps a running
+//! summary of the story, which the continuation template shows as "Story So
+//! Far". Every few scenes an extra AI call folds the scenes written since the
+//! last update into the summary.
+//!
+//! Each summary is stored in the `summary` metadata of the last scene it
+//! covers, so every branch of a branching chain keeps its own summary. The
+//! latest one is also copied to the chain's `summary` metadata.
+
+use serde::{Deserialize, Serialize};
+use log::{info, debug};
+use tera::Context;
+use crate::{AIProvider, StoryChain, StoryChainError};
+
+/// Number of scenes between summary updates used when none is configured
+pub const DEFAULT_SUMMARY_INTERVAL: usize = 5;
+
+/// Word limit of the summary used when none is configured
+pub const DEFAULT_SUMMARY_WORDS: usize = 300;
+
+/// Node and chain metadata key holding the running summary
+pub const SUMMARY_KEY: &str = "summary";
+
+/// Chain metadata key holding the ID of the last scene folded into the latest summary
+pub const SUMMARY_THROUGH_KEY: &str = "summary_through";
+
+/// Name of the running summary prompt template
+pub const RUNNING_SUMMARY: &str = "running_summary";
+
+/// Built-in template of the running summary prompt
+///
+/// Variables: `previous_summary`, the summary so far, empty before the first
+/// update; `scenes`, the scenes written since; `max_words`, the word limit.
+pub const RUNNING_SUMMARY_TEMPLATE: &str = r#"You are keeping the running summary of a story in progress, so that its author remembers earlier events while writing later scenes. {% if previous_summary %}Update the summary below with the events of the new scenes.{% else %}Summarize the events of the scenes below.{% endif %} Keep every plot thread, character fate, secret, and promise that may matter later, and drop descriptive detail. Write at most {{ max_words }} words, in the past tense.
+
+{% if previous_summary %}Summary So Far:
+{{ previous_summary }}
+
+{% endif %}New Scenes:
+{% for scene in scenes %}{{ scene }}
+
+{% endfor %}IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your reasoning about which events must be remembered.
+</think>
+Write the summary here, with no heading or extra formatting."#;
+
+/// How the running summary is maintained
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RollingSummary {
+    /// Number of new scenes that triggers an update
+    pub interval: usize,
+
+    /// Maximum length of the summary in words
+    pub max_words: usize,
+}
+
+impl Default for RollingSummary {
+    fn default() -> Self {
+        Self { interval: DEFAULT_SUMMARY_INTERVAL, max_words: DEFAULT_SUMMARY_WORDS }
+    }
+}
+
+impl StoryChain {
+    /// Returns the IDs of the scenes leading up to a node, in causal order,
+    /// that are not part of its storyline's running summary yet
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node the next scene continues; it is shown in
+    ///   full in the prompt and is not summarized
+    pub fn unsummarized_scenes(&self, node_id: &str) -> Vec<String> {
+        let path = self.path_to(node_id);
+        let end = path.len().saturating_sub(1);
+        let start = path[..end]
+            .iter()
+            .rposition(|n| n.metadata.contains_key(SUMMARY_KEY))
+            .map_or(0, |i| i + 1);
+        path[start..end].iter().map(|n| n.id.clone()).collect()
+    }
+
+    /// Returns the running summary of the storyline leading up to a node
+    ///
+    /// Falls back to the chain's `summary` metadata when it was set by hand
+    /// rather than by the running summary.
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node the next scene continues
+    pub fn story_summary(&self, node_id: &str) -> Option<&str> {
+        self.path_to(node_id)
+            .into_iter()
+            .rev()
+            .find_map(|n| n.metadata.get(SUMMARY_KEY))
+            .or_else(|| self.metadata.get(SUMMARY_KEY).filter(|_| !self.metadata.contains_key(SUMMARY_THROUGH_KEY)))
+            .map(String::as_str)
+    }
+
+    /// Folds the scenes before a node that are not summarized yet into the
+    /// running summary
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node the next scene continues
+    /// * `ai_provider` - The AI provider to use for generation
+    ///
+    /// # Returns
+    /// Whether the summary was updated
+    pub async fn update_summary(&mut self, node_id: &str, ai_provider: &dyn AIProvider) -> Result<bool, StoryChainError> {
+        let scene_ids = self.unsummarized_scenes(node_id);
+        let Some(last_id) = scene_ids.last().cloned() else {
+            return Ok(false);
+        };
+        let max_words = self.settings.memory.as_ref().map_or(DEFAULT_SUMMARY_WORDS, |m| m.max_words);
+
+        let scenes: Vec<&str> = scene_ids.iter().map(|id| self.nodes[id].content.trim()).collect();
+        let mut context = Context::new();
+        context.insert("previous_summary", self.story_summary(node_id).unwrap_or_default().trim());
+        context.insert("scenes", &scenes);
+        context.insert("max_words", &max_words);
+        let prompt = self.render_prompt(RUNNING_SUMMARY, context)?;
+
+        let (_, summary) = ai_provider.generate(&prompt).await?;
+        info!("Updated the story summary with {} scenes, through {}", scene_ids.len(), last_id);
+        let summary = summary.trim().to_string();
+        if let Some(node) = self.nodes.get_mut(&last_id) {
+            node.metadata.insert(SUMMARY_KEY.to_string(), summary.clone());
+        }
+        self.metadata.insert(SUMMARY_KEY.to_string(), summary);
+        self.metadata.insert(SUMMARY_THROUGH_KEY.to_string(), last_id);
+        Ok(true)
+    }
+
+    /// Updates the running summary once enough scenes have been written
+    /// since the last update, when the chain has memory enabled
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node the next scene continues
+    /// * `ai_provider` - The AI provider to use for generation
+    pub(crate) async fn maintain_summary(&mut self, node_id: &str, ai_provider: &dyn AIProvider) -> Result<(), StoryChainError> {
+        let Some(memory) = &self.settings.memory else {
+            return Ok(());
+        };
+        let pending = self.unsummarized_scenes(node_id).len();
+        if pending < memory.interval.max(1) {
+            debug!("{} scenes since the last summary update", pending);
+            return Ok(());
+        }
+        self.update_summary(node_id, ai_provider).await?;
+        Ok(())
+    }
+}
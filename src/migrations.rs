@@ -0,0 +1,125 @@
+//! Schema Migrations
+//!
+//! This module keeps story files written by older versions of StoryChain
+//! loadable. Every saved chain records the `version` of the format it was
+//! written in, and loading runs the migrations between that version and the
+//! current one on the raw JSON before it is read into a `StoryChain`, so a
+//! format change never silently drops or misreads fields of an old file.
+//! `storychain migrate` rewrites old files in the current format.
+//!
+//! Versions:
+//! - 1: every file written before versioning, including the chains from
+//!   before branching that link each node to a single `predecessor` and
+//!   `successor`
+//! - 2: the `version` field, node links as `predecessors`/`successors` lists,
+//!   and node `metadata` always present
+
+use serde_json::{Map, Value};
+use log::info;
+use crate::{StoryChain, StoryChainError};
+
+/// Version of the format chains are written in
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Version of files written before chains recorded one
+pub const UNVERSIONED: u32 = 1;
+
+/// An upgrade of the serialized format from one version to the next
+pub struct Migration {
+    /// Version the migration upgrades from, to the one after it
+    pub from: u32,
+
+    /// What the migration changes, shown by `storychain migrate`
+    pub description: &'static str,
+
+    /// Rewrites a serialized chain in place
+    apply: fn(&mut Value) -> Result<(), StoryChainError>,
+}
+
+/// The migrations, in version order
+pub const MIGRATIONS: &[Migration] = &[Migration {
+    from: 1,
+    description: "turn single predecessor/successor links into lists and add missing node metadata",
+    apply: link_lists,
+}];
+
+/// Returns the default version of a deserialized chain that records none
+pub(crate) fn unversioned() -> u32 {
+    UNVERSIONED
+}
+
+/// Returns the format version of a serialized chain
+///
+/// # Arguments
+/// * `value` - The serialized chain
+pub fn schema_version(value: &Value) -> u32 {
+    value.get("version").and_then(Value::as_u64).map_or(UNVERSIONED, |version| version as u32)
+}
+
+/// Upgrades a serialized chain to the current format
+///
+/// # Arguments
+/// * `value` - The serialized chain, rewritten in place
+///
+/// # Returns
+/// The descriptions of the migrations applied, empty when the chain was
+/// already current, or an error for a chain written by a newer version
+pub fn migrate(value: &mut Value) -> Result<Vec<&'static str>, StoryChainError> {
+    let mut version = schema_version(value);
+    if version > SCHEMA_VERSION {
+        return Err(StoryChainError::InvalidChainOperation(format!(
+            "The story was written in format version {}, newer than this StoryChain's {}; upgrade StoryChain to read it",
+            version, SCHEMA_VERSION
+        )));
+    }
+    if !value.is_object() {
+        return Err(StoryChainError::InvalidChainOperation("A story file must hold a JSON object".to_string()));
+    }
+
+    let mut applied = Vec::new();
+    while version < SCHEMA_VERSION {
+        let migration = MIGRATIONS
+            .iter()
+            .find(|migration| migration.from == version)
+            .ok_or_else(|| StoryChainError::InvalidChainOperation(format!("No migration from version {}", version)))?;
+        info!("Migrating story from version {}: {}", version, migration.description);
+        (migration.apply)(value)?;
+        version += 1;
+        value["version"] = Value::from(version);
+        applied.push(migration.description);
+    }
+    Ok(applied)
+}
+
+/// Reads a serialized chain of any supported version
+///
+/// # Arguments
+/// * `value` - The serialized chain
+pub fn chain_from_value(mut value: Value) -> Result<StoryChain, StoryChainError> {
+    migrate(&mut value)?;
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Migration from version 1: single links become lists
+fn link_lists(value: &mut Value) -> Result<(), StoryChainError> {
+    let Some(nodes) = value.get_mut("nodes").and_then(Value::as_object_mut) else {
+        return Ok(());
+    };
+    for node in nodes.values_mut().filter_map(Value::as_object_mut) {
+        for (single, list) in [("predecessor", "predecessors"), ("successor", "successors")] {
+            if let Some(link) = node.remove(single) {
+                if !node.contains_key(list) {
+                    let links = match link {
+                        Value::Null => Vec::new(),
+                        Value::Array(links) => links,
+                        link => vec![link],
+                    };
+                    node.insert(list.to_string(), Value::Array(links));
+                }
+            }
+            node.entry(list).or_insert_with(|| Value::Array(Vec::new()));
+        }
+        node.entry("metadata").or_insert_with(|| Value::Object(Map::new()));
+    }
+    Ok(())
+}
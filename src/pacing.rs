@@ -0,0 +1,87 @@
+//! Arc Pacing
+//!
+//! This module tells the model where each scene falls in the story arc, so
+//! that openings establish, middles complicate, climaxes confront, and the
+//! last epoch actually ends the story instead of setting up another scene.
+//! The stage is derived from the epoch and the total number of epochs.
+
+use serde::{Deserialize, Serialize};
+
+/// Fraction of the story, at most, spent on the opening
+const OPENING_END: f64 = 0.25;
+
+/// Fraction of the story after which the climax begins
+const MIDPOINT_END: f64 = 0.6;
+
+/// Stage of the story arc a scene belongs to
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ArcStage {
+    /// The first quarter: establishing characters, setting, and conflict
+    Opening,
+
+    /// Up to 60%: complications, rising stakes, and the midpoint turn
+    Midpoint,
+
+    /// After 60%, up to the final epoch: confrontation and peak tension
+    Climax,
+
+    /// The final epoch: resolution and ending
+    Finale,
+}
+
+impl ArcStage {
+    /// Returns the stage of the scene generated at the given epoch
+    ///
+    /// # Arguments
+    /// * `current_epoch` - Epoch of the scene being generated, counting from 1
+    /// * `total_epochs` - Total number of epochs planned
+    pub fn for_epoch(current_epoch: usize, total_epochs: usize) -> Self {
+        if current_epoch >= total_epochs {
+            return ArcStage::Finale;
+        }
+        let progress = current_epoch as f64 / total_epochs as f64;
+        if progress <= OPENING_END {
+            ArcStage::Opening
+        } else if progress <= MIDPOINT_END {
+            ArcStage::Midpoint
+        } else {
+            ArcStage::Climax
+        }
+    }
+
+    /// Returns the name of the stage, as shown in prompts
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArcStage::Opening => "opening",
+            ArcStage::Midpoint => "midpoint",
+            ArcStage::Climax => "climax",
+            ArcStage::Finale => "finale",
+        }
+    }
+
+    /// Returns the pacing instruction for scenes of this stage
+    ///
+    /// # Arguments
+    /// * `epochs_remaining` - Number of epochs after the scene being generated
+    pub fn instruction(&self, epochs_remaining: usize) -> String {
+        match self {
+            ArcStage::Opening => "The story is in its opening. Establish the characters, the setting, and the central \
+                conflict, and give the protagonist a reason to act. Do not resolve anything yet."
+                .to_string(),
+            ArcStage::Midpoint => "The story is building toward its midpoint. Complicate the protagonist's plans, \
+                raise the stakes, and deliver a turn that changes what the characters believe or want."
+                .to_string(),
+            ArcStage::Climax => format!(
+                "The story is approaching its climax, with {} scenes left after this one. Drive the central conflict \
+                toward a direct confrontation, close off minor threads, and do not introduce new characters or \
+                subplots.",
+                epochs_remaining
+            ),
+            ArcStage::Finale => "This is the final scene of the story. Bring it to a conclusive ending: resolve the \
+                central conflict, settle the main characters' arcs, and leave the reader with a sense of closure. \
+                Do not introduce new threads, and do not end on a cliffhanger or a setup for another scene."
+                .to_string(),
+        }
+    }
+}
@@ -0,0 +1,190 @@
+//! Response Parsers
+//!
+//! This module splits raw model responses into (reasoning, content). Models
+//! answer in different shapes, so the strategy is chosen per provider with
+//! its `response_format`: `<think>` tags (the default, used by reasoning
+//! models such as DeepSeek-R1), a JSON object, markdown headers, or plain
+//! text with no reasoning at all.
+
+use std::str::FromStr;
+use std::sync::Arc;
+use serde::{Deserialize, Serialize};
+use log::{debug, error};
+use crate::{parse_think_response, StoryChainError};
+
+/// Headers whose section holds the reasoning in markdown responses
+const REASONING_HEADERS: [&str; 3] = ["reasoning", "thinking", "thoughts"];
+
+/// Headers whose section holds the content in markdown responses
+const CONTENT_HEADERS: [&str; 4] = ["scene", "content", "story", "response"];
+
+/// Strategy that splits a raw model response into reasoning and content
+pub trait ResponseParser: Send + Sync {
+    /// Splits a response
+    ///
+    /// # Arguments
+    /// * `response` - The raw response of the model
+    ///
+    /// # Returns
+    /// A tuple of (reasoning, content) strings or an error
+    fn parse(&self, response: &str) -> Result<(String, String), StoryChainError>;
+}
+
+impl std::fmt::Debug for dyn ResponseParser {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ResponseParser")
+    }
+}
+
+/// Reads the reasoning from `<think>...</think>` and the content after it
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThinkTagParser;
+
+impl ResponseParser for ThinkTagParser {
+    fn parse(&self, response: &str) -> Result<(String, String), StoryChainError> {
+        parse_think_response(response)
+    }
+}
+
+/// Reads a JSON object with `content` and optional `reasoning` fields,
+/// ignoring any text or code fence around it
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonResponseParser;
+
+impl ResponseParser for JsonResponseParser {
+    fn parse(&self, response: &str) -> Result<(String, String), StoryChainError> {
+        let object = match (response.find('{'), response.rfind('}')) {
+            (Some(start), Some(end)) if start < end => &response[start..=end],
+            _ => {
+                error!("Failed to parse AI response - no JSON object found");
+                return Err(StoryChainError::InvalidReasoningFormat("No JSON object found in response".to_string()));
+            }
+        };
+        let value: serde_json::Value = serde_json::from_str(object)
+            .map_err(|e| StoryChainError::InvalidReasoningFormat(format!("Invalid JSON response: {}", e)))?;
+        let field = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| value[*name].as_str())
+                .map(|text| text.trim().to_string())
+                .unwrap_or_default()
+        };
+        let reasoning = field(&["reasoning", "thinking"]);
+        let content = field(&["content", "scene"]);
+        if content.is_empty() {
+            error!("JSON response has no content");
+            return Err(StoryChainError::InvalidReasoningFormat("JSON response has no content field".to_string()));
+        }
+        debug!("Parsed JSON response with {} characters of content", content.len());
+        Ok((reasoning, content))
+    }
+}
+
+/// Reads the sections under markdown headers such as `## Reasoning` and
+/// `## Scene`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MarkdownHeaderParser;
+
+impl ResponseParser for MarkdownHeaderParser {
+    fn parse(&self, response: &str) -> Result<(String, String), StoryChainError> {
+        let mut sections: Vec<(String, Vec<&str>)> = Vec::new();
+        for line in response.lines() {
+            match line.trim_start().strip_prefix('#') {
+                Some(header) => {
+                    let title = header.trim_start_matches('#').trim().trim_end_matches(':').to_lowercase();
+                    sections.push((title, Vec::new()));
+                }
+                None => {
+                    if let Some((_, lines)) = sections.last_mut() {
+                        lines.push(line);
+                    }
+                }
+            }
+        }
+        let section = |headers: &[&str]| {
+            sections
+                .iter()
+                .find(|(title, _)| headers.contains(&title.as_str()))
+                .map(|(_, lines)| lines.join("\n").trim().to_string())
+        };
+        let reasoning = section(&REASONING_HEADERS).unwrap_or_default();
+        match section(&CONTENT_HEADERS).filter(|content| !content.is_empty()) {
+            Some(content) => Ok((reasoning, content)),
+            None => {
+                error!("Failed to parse AI response - no scene header found");
+                Err(StoryChainError::InvalidReasoningFormat(format!(
+                    "No non-empty section under any of the headers: {}",
+                    CONTENT_HEADERS.join(", ")
+                )))
+            }
+        }
+    }
+}
+
+/// Takes the whole response as content with no reasoning, dropping a
+/// `<think>` block if the model wrote one anyway
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoReasoningParser;
+
+impl ResponseParser for NoReasoningParser {
+    fn parse(&self, response: &str) -> Result<(String, String), StoryChainError> {
+        let content = match (response.find("<think>"), response.find("</think>")) {
+            (Some(start), Some(end)) if start < end => {
+                format!("{}{}", &response[..start], &response[end + "</think>".len()..])
+            }
+            _ => response.to_string(),
+        };
+        let content = content.trim();
+        if content.is_empty() {
+            return Err(StoryChainError::InvalidReasoningFormat("Empty content in response".to_string()));
+        }
+        Ok((String::new(), content.to_string()))
+    }
+}
+
+/// The response shapes a provider can be configured to expect
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ResponseFormat {
+    /// Reasoning in `<think>` tags followed by the content
+    #[default]
+    ThinkTags,
+    /// A JSON object with `reasoning` and `content` fields
+    Json,
+    /// `## Reasoning` and `## Scene` markdown sections
+    Markdown,
+    /// The whole response is the content
+    NoReasoning,
+}
+
+impl FromStr for ResponseFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "think-tags" | "think" => Ok(ResponseFormat::ThinkTags),
+            "json" => Ok(ResponseFormat::Json),
+            "markdown" | "md" => Ok(ResponseFormat::Markdown),
+            "no-reasoning" | "plain" => Ok(ResponseFormat::NoReasoning),
+            other => Err(format!("Unknown response format: {}", other)),
+        }
+    }
+}
+
+impl ResponseFormat {
+    /// Returns true for the default `<think>` tag format, so that it is left
+    /// out of saved configurations
+    pub fn is_default(&self) -> bool {
+        *self == ResponseFormat::default()
+    }
+
+    /// Returns the parser for this format
+    pub fn parser(&self) -> Arc<dyn ResponseParser> {
+        match self {
+            ResponseFormat::ThinkTags => Arc::new(ThinkTagParser),
+            ResponseFormat::Json => Arc::new(JsonResponseParser),
+            ResponseFormat::Markdown => Arc::new(MarkdownHeaderParser),
+            ResponseFormat::NoReasoning => Arc::new(NoReasoningParser),
+        }
+    }
+}
@@ -0,0 +1,368 @@
+//! Post-Generation Passes
+//!
+//! This module contains optional passes that run over a completed story chain,
+//! asking the AI provider for supplementary material such as a title, blurb,
+//! logline, tags, or synopsis. Results are stored as chain-level metadata or returned
+//! as artifacts so that exporters can pick them up.
+
+use std::collections::HashMap;
+use log::{info, debug};
+use tera::Context;
+use crate::artifacts::{Artifact, ArtifactType};
+use crate::{AIProvider, StoryChain, StoryChainError};
+
+/// Maximum number of characters taken from each scene when condensing the
+/// story into a prompt for a post-generation pass
+pub(crate) const SCENE_EXCERPT_CHARS: usize = 600;
+
+/// Number of closing scenes a synopsis prompt shows in full, so that the
+/// model sees how the story ends
+const SYNOPSIS_FULL_SCENES: usize = 2;
+
+/// Name of the title, blurb, and logline prompt template
+pub const TITLE_BLURB: &str = "title_blurb";
+
+/// Built-in template of the title, blurb, and logline prompt
+///
+/// Variables: `story`, excerpts of every scene.
+pub const TITLE_BLURB_TEMPLATE: &str = r#"You are a book editor preparing a finished story for publication. Read the scene excerpts below and write marketing copy for it.
+
+Story:
+{{ story }}
+IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your reasoning about the story's hook, tone, and audience.
+</think>
+TITLE: A short, evocative title
+LOGLINE: A single sentence describing the protagonist, goal, and stakes
+BLURB: A back-cover blurb of one or two short paragraphs that does not spoil the ending"#;
+
+/// Name of the synopsis prompt template
+pub const SYNOPSIS: &str = "synopsis";
+
+/// Built-in template of the synopsis prompt
+///
+/// Variables: `story`, the running summary or excerpts of the scenes
+/// followed by the closing scenes in full; `length`, the length instruction.
+pub const SYNOPSIS_TEMPLATE: &str = r#"You are a literary agent's assistant. Read the story below and write a synopsis of the complete story in the present tense, covering the main characters, the central conflict, the major turning points, and how the story ends.
+
+Story:
+{{ story }}
+The synopsis must be {{ length }}.
+
+IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your reasoning about which events are essential to the synopsis.
+</think>
+Write the synopsis here, with no heading or extra formatting."#;
+
+/// Name of the genre tags, content warnings, and keywords prompt template
+pub const TAGS: &str = "tags";
+
+/// Built-in template of the genre tags, content warnings, and keywords prompt
+///
+/// Variables: `story`, excerpts of every scene.
+pub const TAGS_TEMPLATE: &str = r#"You are cataloguing a finished story for a digital bookstore. Read the scene excerpts below and classify it.
+
+Story:
+{{ story }}
+IMPORTANT: Format your response EXACTLY as follows, using comma-separated lists:
+<think>
+Your reasoning about the story's genre, mature content, and subject matter.
+</think>
+GENRES: Up to five genre or subgenre tags
+CONTENT WARNINGS: Mature or potentially distressing content present in the story, or None
+KEYWORDS: Up to ten search keywords describing the setting, themes, and subject matter"#;
+
+/// Target length of a generated synopsis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SynopsisLength {
+    /// A single paragraph suitable for a query letter
+    Paragraph,
+
+    /// Roughly one page covering every major plot turn
+    Page,
+}
+
+impl SynopsisLength {
+    /// Returns the identifier used for artifact IDs and metadata
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SynopsisLength::Paragraph => "paragraph",
+            SynopsisLength::Page => "page",
+        }
+    }
+
+    /// Returns the length instruction included in the prompt
+    fn instruction(&self) -> &'static str {
+        match self {
+            SynopsisLength::Paragraph => "a single paragraph of no more than 150 words",
+            SynopsisLength::Page => "about one page (400 to 600 words) in several paragraphs",
+        }
+    }
+}
+
+/// Extracts labeled fields (e.g. `TITLE: ...`) from an AI response
+///
+/// A field starts at a line beginning with one of the given labels followed by
+/// a colon and continues until the next labeled line or the end of the text.
+/// Labels are matched case-insensitively and markdown emphasis around them is ignored.
+///
+/// # Arguments
+/// * `text` - The response text to parse
+/// * `labels` - The labels to look for, in upper case
+pub fn parse_labeled_fields(text: &str, labels: &[&str]) -> HashMap<String, String> {
+    let mut fields: HashMap<String, String> = HashMap::new();
+    let mut current: Option<String> = None;
+
+    for line in text.lines() {
+        let stripped = line.trim().trim_start_matches(['*', '#', '-', ' ']);
+        let matched = labels.iter().find_map(|label| {
+            let (head, rest) = stripped.split_once(':')?;
+            if head.trim_end_matches('*').trim().eq_ignore_ascii_case(label) {
+                Some((label.to_string(), rest.trim_start_matches('*').trim().to_string()))
+            } else {
+                None
+            }
+        });
+
+        match matched {
+            Some((label, rest)) => {
+                fields.insert(label.clone(), rest);
+                current = Some(label);
+            }
+            None => {
+                if let Some(label) = &current {
+                    let value = fields.entry(label.clone()).or_default();
+                    if !value.is_empty() {
+                        value.push('\n');
+                    }
+                    value.push_str(line.trim());
+                }
+            }
+        }
+    }
+
+    fields
+        .into_iter()
+        .map(|(k, v)| (k, v.trim().to_string()))
+        .filter(|(_, v)| !v.is_empty())
+        .collect()
+}
+
+/// Splits a response into blocks that each start with a line labeled `start_label:`
+///
+/// Any text before the first labeled line forms its own leading block.
+///
+/// # Arguments
+/// * `text` - The response text to split
+/// * `start_label` - The label (in upper case) that begins each block
+pub fn split_blocks(text: &str, start_label: &str) -> Vec<String> {
+    let prefix = format!("{}:", start_label);
+    let mut blocks: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let stripped = line.trim().trim_start_matches(['*', '#', '-', ' ']);
+        if stripped.to_uppercase().starts_with(&prefix) || blocks.is_empty() {
+            blocks.push(String::new());
+        }
+        let block = blocks.last_mut().expect("a block was pushed above");
+        block.push_str(line);
+        block.push('\n');
+    }
+    blocks
+}
+
+/// Removes a code fence the AI may have wrapped its answer in
+pub fn strip_code_fence(text: &str) -> &str {
+    let text = text.trim();
+    match text.strip_prefix("```") {
+        Some(rest) => {
+            let rest = rest.split_once('\n').map_or("", |(_, body)| body);
+            rest.trim_end().strip_suffix("```").unwrap_or(rest).trim()
+        }
+        None => text,
+    }
+}
+
+impl StoryChain {
+    /// Condenses the chain into a prompt-friendly outline containing an
+    /// excerpt of every scene in causal order, labeled with its node ID
+    ///
+    /// # Arguments
+    /// * `chars_per_scene` - Maximum number of characters kept from each scene
+    pub fn condensed_text(&self, chars_per_scene: usize) -> String {
+        let mut text = String::new();
+        for (index, node) in self.nodes_in_order().into_iter().enumerate() {
+            let excerpt: String = node.content.chars().take(chars_per_scene).collect();
+            text.push_str(&format!("Scene {} [{}]:\n{}", index + 1, node.id, excerpt.trim()));
+            if node.content.chars().count() > chars_per_scene {
+                text.push_str("...");
+            }
+            text.push_str("\n\n");
+        }
+        text
+    }
+
+    /// Generates a title, a back-cover blurb, and a one-sentence logline for
+    /// the story and stores them in the chain metadata under the `title`,
+    /// `blurb`, and `logline` keys
+    ///
+    /// # Arguments
+    /// * `ai_provider` - The AI provider to use for generation
+    pub async fn generate_title_and_blurb(
+        &mut self,
+        ai_provider: &dyn AIProvider,
+    ) -> Result<(), StoryChainError> {
+        info!("Generating title, blurb, and logline");
+
+        let mut context = Context::new();
+        context.insert("story", &self.condensed_text(SCENE_EXCERPT_CHARS));
+        let prompt = self.render_prompt(TITLE_BLURB, context)?;
+
+        let (_, content) = ai_provider.generate(&prompt).await?;
+        let fields = parse_labeled_fields(&content, &["TITLE", "LOGLINE", "BLURB"]);
+        debug!("Parsed title fields: {:?}", fields);
+
+        let title = fields.get("TITLE").ok_or_else(|| {
+            StoryChainError::InvalidReasoningFormat("Response did not contain a TITLE field".to_string())
+        })?;
+        self.metadata.insert("title".to_string(), title.trim_matches('"').to_string());
+
+        for (label, key) in [("LOGLINE", "logline"), ("BLURB", "blurb")] {
+            if let Some(value) = fields.get(label) {
+                self.metadata.insert(key.to_string(), value.clone());
+            }
+        }
+
+        info!("Generated title: {}", self.metadata["title"]);
+        Ok(())
+    }
+
+    /// Returns the story as a synopsis prompt shows it
+    ///
+    /// With memory enabled and a running summary written, that is the
+    /// summary of the main storyline followed by the scenes it does not
+    /// cover yet; otherwise excerpts of every scene. Either way the closing
+    /// scenes are shown in full.
+    fn synopsis_text(&self) -> String {
+        let scene = |index: usize, node: &crate::StoryNode, chars: usize| {
+            let excerpt: String = node.content.chars().take(chars).collect();
+            let cut = if node.content.chars().count() > chars { "..." } else { "" };
+            format!("Scene {} [{}]:\n{}{}\n\n", index + 1, node.id, excerpt.trim(), cut)
+        };
+
+        let storyline = self.nodes_in_order();
+        let Some(last) = storyline.last() else {
+            return String::new();
+        };
+        let ending = storyline.len().saturating_sub(SYNOPSIS_FULL_SCENES);
+        let summary = self.settings.memory.as_ref().and_then(|_| self.story_summary(&last.id)).filter(|s| !s.trim().is_empty());
+        if let Some(summary) = summary {
+            let unsummarized = self.unsummarized_scenes(&last.id);
+            let first_shown = storyline
+                .iter()
+                .position(|node| unsummarized.first() == Some(&node.id))
+                .map_or(ending, |index| index.min(ending));
+            debug!("Writing the synopsis from the running summary and {} scenes", storyline.len() - first_shown);
+            let mut text = format!("Summary of the Story:\n{}\n\nFinal Scenes:\n", summary.trim());
+            for (index, node) in storyline.iter().enumerate().skip(first_shown) {
+                text.push_str(&scene(index, node, usize::MAX));
+            }
+            return text;
+        }
+
+        storyline
+            .iter()
+            .enumerate()
+            .map(|(index, node)| scene(index, node, if index >= ending { usize::MAX } else { SCENE_EXCERPT_CHARS }))
+            .collect()
+    }
+
+    /// Generates a synopsis of the whole story, including its ending
+    ///
+    /// The model reads the running summary when memory is enabled, and
+    /// excerpts of every scene otherwise, along with the closing scenes in
+    /// full.
+    ///
+    /// # Arguments
+    /// * `ai_provider` - The AI provider to use for generation
+    /// * `length` - Whether to produce a one-paragraph or one-page synopsis
+    /// * `story` - Name the synopsis is stored under, such as the premise file
+    ///   the story was written from. The chain does not record its premise
+    ///   file, and without a name of their own the synopses of stories
+    ///   sharing an artifacts directory would overwrite each other
+    ///
+    /// # Returns
+    /// A `Synopsis` artifact with the ID `synopsis_<story>_<length>`, ready to be
+    /// stored through an `ArtifactManager`
+    pub async fn generate_synopsis(
+        &self,
+        ai_provider: &dyn AIProvider,
+        length: SynopsisLength,
+        story: &str,
+    ) -> Result<Artifact, StoryChainError> {
+        info!("Generating {} synopsis", length.as_str());
+
+        let mut context = Context::new();
+        context.insert("story", &self.synopsis_text());
+        context.insert("length", length.instruction());
+        let prompt = self.render_prompt(SYNOPSIS, context)?;
+
+        let (_, content) = ai_provider.generate(&prompt).await?;
+
+        let mut metadata = HashMap::new();
+        metadata.insert("length".to_string(), length.as_str().to_string());
+        if let Some(title) = self.metadata.get("title") {
+            metadata.insert("title".to_string(), title.clone());
+        }
+
+        Ok(Artifact {
+            id: format!("synopsis_{}_{}", story, length.as_str()),
+            content: content.trim().to_string(),
+            artifact_type: ArtifactType::Synopsis,
+            metadata,
+            tags: Vec::new(),
+        })
+    }
+
+    /// Generates genre tags, content warnings, and keywords for the story and
+    /// stores them in the chain metadata as comma-separated lists under the
+    /// `genre_tags`, `content_warnings`, and `keywords` keys
+    ///
+    /// # Arguments
+    /// * `ai_provider` - The AI provider to use for generation
+    pub async fn generate_tags(
+        &mut self,
+        ai_provider: &dyn AIProvider,
+    ) -> Result<(), StoryChainError> {
+        info!("Generating tags, content warnings, and keywords");
+
+        let mut context = Context::new();
+        context.insert("story", &self.condensed_text(SCENE_EXCERPT_CHARS));
+        let prompt = self.render_prompt(TAGS, context)?;
+
+        let (_, content) = ai_provider.generate(&prompt).await?;
+        let fields = parse_labeled_fields(&content, &["GENRES", "CONTENT WARNINGS", "KEYWORDS"]);
+        debug!("Parsed tag fields: {:?}", fields);
+
+        for (label, key) in [
+            ("GENRES", "genre_tags"),
+            ("CONTENT WARNINGS", "content_warnings"),
+            ("KEYWORDS", "keywords"),
+        ] {
+            let items: Vec<String> = fields
+                .get(label)
+                .map(|value| {
+                    value
+                        .split([',', '\n'])
+                        .map(|item| item.trim().trim_start_matches(['-', '*']).trim().to_lowercase())
+                        .filter(|item| !item.is_empty() && item != "none")
+                        .collect()
+                })
+                .unwrap_or_default();
+            self.metadata.insert(key.to_string(), items.join(", "));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,362 @@
+//! PDF Export
+//!
+//! This module typesets the main storyline of a chain as a printable PDF
+//! manuscript: a title page, chapters starting on new pages with their
+//! headings, scene breaks, and page numbers. It uses the standard Times fonts
+//! every PDF reader ships with, so no fonts are embedded and no external
+//! typesetting toolchain is needed.
+//!
+//! Available with the `pdf` feature.
+
+use log::info;
+use pdf_writer::{Content, Finish, Name, Pdf, Rect, Ref, Str, TextStr};
+use crate::{MarkdownOptions, StoryChain, StoryChainError, StoryNode};
+
+/// Page width in points (US Letter)
+const PAGE_WIDTH: f32 = 612.0;
+
+/// Page height in points (US Letter)
+const PAGE_HEIGHT: f32 = 792.0;
+
+/// Page margin in points
+const MARGIN: f32 = 72.0;
+
+/// Body text size in points
+const BODY_SIZE: f32 = 12.0;
+
+/// Distance between body text lines in points
+const LEADING: f32 = 17.0;
+
+/// Indent of the first line of a paragraph in points
+const PARAGRAPH_INDENT: f32 = 18.0;
+
+/// Marker set between scenes of a chapter
+const SCENE_BREAK: &str = "*   *   *";
+
+/// The standard fonts used in the manuscript
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Font {
+    Regular,
+    Bold,
+    Italic,
+}
+
+impl Font {
+    /// Resource name of the font on every page
+    fn resource(self) -> Name<'static> {
+        match self {
+            Font::Regular => Name(b"F1"),
+            Font::Bold => Name(b"F2"),
+            Font::Italic => Name(b"F3"),
+        }
+    }
+
+    /// Name of the standard font
+    fn base_font(self) -> Name<'static> {
+        match self {
+            Font::Regular => Name(b"Times-Roman"),
+            Font::Bold => Name(b"Times-Bold"),
+            Font::Italic => Name(b"Times-Italic"),
+        }
+    }
+}
+
+/// Advance widths of the printable ASCII characters in Times-Roman, in
+/// thousandths of the font size
+const TIMES_WIDTHS: [u16; 95] = [
+    250, 333, 408, 500, 500, 833, 778, 333, 333, 333, 500, 564, 250, 333, 250, 278, // space to /
+    500, 500, 500, 500, 500, 500, 500, 500, 500, 500, 278, 278, 564, 564, 564, 444, // 0 to ?
+    921, 722, 667, 667, 722, 611, 556, 722, 722, 333, 389, 722, 611, 889, 722, 722, // @ to O
+    556, 722, 667, 556, 611, 722, 722, 944, 722, 722, 611, 333, 278, 333, 469, 500, // P to _
+    333, 444, 500, 444, 500, 444, 333, 500, 500, 278, 278, 500, 278, 778, 500, 500, // ` to o
+    500, 500, 333, 389, 278, 500, 500, 722, 500, 500, 444, 480, 200, 480, 541, // p to ~
+];
+
+/// Returns the approximate width of a text in points
+///
+/// Widths of bold text and of characters outside ASCII are estimated.
+fn text_width(text: &str, font: Font, size: f32) -> f32 {
+    let units: f32 = text
+        .chars()
+        .map(|c| match c {
+            ' '..='~' => TIMES_WIDTHS[c as usize - 32] as f32,
+            _ => 500.0,
+        })
+        .sum();
+    let weight = if font == Font::Bold { 1.05 } else { 1.0 };
+    units * weight * size / 1000.0
+}
+
+/// Encodes text for the standard fonts' WinAnsi encoding, replacing
+/// characters it cannot represent with `?`
+fn encode(text: &str) -> Vec<u8> {
+    text.chars()
+        .map(|c| match c {
+            ' '..='~' => c as u8,
+            '\u{a0}'..='\u{ff}' => c as u32 as u8,
+            '\u{2018}' => 0x91,
+            '\u{2019}' => 0x92,
+            '\u{201c}' => 0x93,
+            '\u{201d}' => 0x94,
+            '\u{2022}' => 0x95,
+            '\u{2013}' => 0x96,
+            '\u{2014}' => 0x97,
+            '\u{2026}' => 0x85,
+            _ => b'?',
+        })
+        .collect()
+}
+
+/// Breaks a text into lines no wider than the given widths
+///
+/// # Arguments
+/// * `text` - The text to break
+/// * `font` - Font the text is set in
+/// * `size` - Font size in points
+/// * `first_width` - Width available to the first line
+/// * `width` - Width available to the other lines
+fn wrap(text: &str, font: Font, size: f32, first_width: f32, width: f32) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        let available = if lines.is_empty() { first_width } else { width };
+        let candidate = if line.is_empty() { word.to_string() } else { format!("{} {}", line, word) };
+        if !line.is_empty() && text_width(&candidate, font, size) > available {
+            lines.push(std::mem::replace(&mut line, word.to_string()));
+        } else {
+            line = candidate;
+        }
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// A line of text placed on a page
+struct PlacedLine {
+    font: Font,
+    size: f32,
+    x: f32,
+    y: f32,
+    text: String,
+}
+
+/// Lays text out top to bottom, starting new pages as needed
+struct Typesetter {
+    pages: Vec<Vec<PlacedLine>>,
+    y: f32,
+}
+
+impl Typesetter {
+    /// Starts with an empty title page
+    fn new() -> Self {
+        Self { pages: vec![Vec::new()], y: PAGE_HEIGHT - MARGIN }
+    }
+
+    /// Starts a new page unless the current one is still empty
+    fn new_page(&mut self) {
+        if self.pages.last().is_some_and(|page| !page.is_empty()) {
+            self.pages.push(Vec::new());
+        }
+        self.y = PAGE_HEIGHT - MARGIN;
+    }
+
+    /// Moves down, starting a new page when the given height no longer fits
+    fn advance(&mut self, height: f32) {
+        if self.y - height < MARGIN {
+            self.new_page();
+        }
+        self.y -= height;
+    }
+
+    /// Places a line at the current position
+    fn place(&mut self, text: String, font: Font, size: f32, x: f32) {
+        let y = self.y;
+        self.pages.last_mut().expect("there is always a page").push(PlacedLine { font, size, x, y, text });
+    }
+
+    /// Sets text centered between the margins, wrapping long lines
+    fn centered(&mut self, text: &str, font: Font, size: f32, leading: f32) {
+        let width = PAGE_WIDTH - 2.0 * MARGIN;
+        for line in wrap(text, font, size, width, width) {
+            self.advance(leading);
+            let x = (PAGE_WIDTH - text_width(&line, font, size)) / 2.0;
+            self.place(line, font, size, x);
+        }
+    }
+
+    /// Sets a body paragraph, optionally indenting its first line
+    fn paragraph(&mut self, text: &str, indent: bool) {
+        let width = PAGE_WIDTH - 2.0 * MARGIN;
+        let indent = if indent { PARAGRAPH_INDENT } else { 0.0 };
+        for (index, line) in wrap(text, Font::Regular, BODY_SIZE, width - indent, width).into_iter().enumerate() {
+            self.advance(LEADING);
+            let x = if index == 0 { MARGIN + indent } else { MARGIN };
+            self.place(line, Font::Regular, BODY_SIZE, x);
+        }
+    }
+
+    /// Sets the recap of the previous chapter in italics below a chapter heading
+    fn recap(&mut self, text: &str) {
+        let width = PAGE_WIDTH - 2.0 * MARGIN;
+        let text = format!("Previously: {}", text.split_whitespace().collect::<Vec<_>>().join(" "));
+        for line in wrap(&text, Font::Italic, BODY_SIZE, width, width) {
+            self.advance(LEADING);
+            self.place(line, Font::Italic, BODY_SIZE, MARGIN);
+        }
+        self.advance(LEADING);
+    }
+
+    /// Sets the paragraphs of a scene; the first one follows a heading or
+    /// break and is not indented
+    fn scene(&mut self, node: &StoryNode) {
+        let paragraphs = node.content.split("\n\n").map(|p| p.split_whitespace().collect::<Vec<_>>().join(" "));
+        for (index, paragraph) in paragraphs.filter(|p| !p.is_empty()).enumerate() {
+            self.paragraph(&paragraph, index > 0);
+        }
+    }
+}
+
+impl StoryChain {
+    /// Exports the main storyline as a typeset PDF manuscript
+    ///
+    /// The manuscript opens with a title page showing the title, logline, and
+    /// blurb from the chain's metadata. Each chapter starts on a new page
+    /// under its heading, after a page with the act title when it opens an
+    /// act; without chapters, the scenes follow the title page
+    /// in reading order. Scenes are separated by a centered break, and every
+    /// page after the title page is numbered.
+    ///
+    /// # Arguments
+    /// * `path` - The path where the PDF file should be saved
+    pub fn export_to_pdf(&self, path: &str) -> Result<(), StoryChainError> {
+        self.export_to_pdf_with_options(path, &MarkdownOptions::default())
+    }
+
+    /// Exports the main storyline as a typeset PDF manuscript, with a recap
+    /// of the previous chapter below each chapter heading when
+    /// `chapter_recaps` is set
+    ///
+    /// # Arguments
+    /// * `path` - The path where the PDF file should be saved
+    /// * `options` - Controls which optional sections are rendered
+    pub fn export_to_pdf_with_options(&self, path: &str, options: &MarkdownOptions) -> Result<(), StoryChainError> {
+        info!("Exporting story to PDF: {}", path);
+        let title = self.metadata.get("title").map(String::as_str).unwrap_or("Generated Story");
+        let mut typesetter = Typesetter::new();
+
+        // Title page
+        typesetter.advance(PAGE_HEIGHT / 4.0);
+        typesetter.centered(title, Font::Bold, 28.0, 36.0);
+        if let Some(logline) = self.metadata.get("logline") {
+            typesetter.advance(LEADING);
+            typesetter.centered(logline, Font::Italic, 14.0, 20.0);
+        }
+        if let Some(blurb) = self.metadata.get("blurb") {
+            typesetter.advance(3.0 * LEADING);
+            for paragraph in blurb.split("\n\n").filter(|p| !p.trim().is_empty()) {
+                typesetter.paragraph(&paragraph.split_whitespace().collect::<Vec<_>>().join(" "), false);
+                typesetter.advance(LEADING / 2.0);
+            }
+        }
+
+        // Chapters, or the scenes in reading order when the story has none
+        let sections: Vec<(Option<&str>, Option<&str>, Vec<&StoryNode>)> = if self.chapters.is_empty() {
+            vec![(None, None, self.nodes_in_reading_order())]
+        } else {
+            // An act opens with a page of its own before its first chapter
+            let mut sections = Vec::new();
+            for (index, chapter) in self.chapters.iter().enumerate() {
+                let act = chapter.node_ids.first().and_then(|id| self.chapter_starting_at(id)).and_then(|(_, _, act)| act);
+                if let Some(act) = act {
+                    sections.push((Some(act), None, Vec::new()));
+                }
+                sections.push((
+                    Some(chapter.title.as_str()),
+                    self.chapter_recap(index).filter(|_| options.chapter_recaps),
+                    chapter.node_ids.iter().filter_map(|id| self.nodes.get(id)).collect(),
+                ));
+            }
+            sections
+        };
+        for (heading, recap, nodes) in sections {
+            typesetter.new_page();
+            if let Some(heading) = heading {
+                typesetter.advance(PAGE_HEIGHT / 8.0);
+                typesetter.centered(heading, Font::Bold, 20.0, 26.0);
+                typesetter.advance(2.0 * LEADING);
+            }
+            if let Some(recap) = recap {
+                typesetter.recap(recap);
+            }
+            for (index, node) in nodes.into_iter().enumerate() {
+                if index > 0 {
+                    typesetter.advance(LEADING / 2.0);
+                    typesetter.centered(SCENE_BREAK, Font::Regular, BODY_SIZE, LEADING);
+                    typesetter.advance(LEADING / 2.0);
+                }
+                typesetter.scene(node);
+            }
+        }
+
+        crate::files::write_atomic(path, render(&typesetter.pages, title))?;
+        info!("Exported {} pages", typesetter.pages.len());
+        Ok(())
+    }
+}
+
+/// Writes the laid-out pages as a PDF document, numbering every page after
+/// the title page
+fn render(pages: &[Vec<PlacedLine>], title: &str) -> Vec<u8> {
+    const FONTS: [Font; 3] = [Font::Regular, Font::Bold, Font::Italic];
+    let catalog_id = Ref::new(1);
+    let page_tree_id = Ref::new(2);
+    let info_id = Ref::new(3);
+    let font_ids: Vec<Ref> = (0..FONTS.len() as i32).map(|i| Ref::new(4 + i)).collect();
+    let first_page = 4 + FONTS.len() as i32;
+    let page_ids: Vec<Ref> = (0..pages.len() as i32).map(|i| Ref::new(first_page + 2 * i)).collect();
+
+    let mut pdf = Pdf::new();
+    pdf.catalog(catalog_id).pages(page_tree_id);
+    pdf.pages(page_tree_id).kids(page_ids.iter().copied()).count(pages.len() as i32);
+    pdf.document_info(info_id).title(TextStr(title));
+    for (font, id) in FONTS.iter().zip(&font_ids) {
+        pdf.type1_font(*id).base_font(font.base_font()).encoding_predefined(Name(b"WinAnsiEncoding"));
+    }
+
+    for (index, (lines, page_id)) in pages.iter().zip(&page_ids).enumerate() {
+        let content_id = Ref::new(page_id.get() + 1);
+        let mut page = pdf.page(*page_id);
+        page.media_box(Rect::new(0.0, 0.0, PAGE_WIDTH, PAGE_HEIGHT));
+        page.parent(page_tree_id);
+        page.contents(content_id);
+        let mut resources = page.resources();
+        let mut fonts = resources.fonts();
+        for (font, id) in FONTS.iter().zip(&font_ids) {
+            fonts.pair(font.resource(), *id);
+        }
+        fonts.finish();
+        resources.finish();
+        page.finish();
+
+        let mut content = Content::new();
+        let mut show = |text: &str, font: Font, size: f32, x: f32, y: f32| {
+            content.begin_text();
+            content.set_font(font.resource(), size);
+            content.next_line(x, y);
+            content.show(Str(&encode(text)));
+            content.end_text();
+        };
+        for line in lines {
+            show(&line.text, line.font, line.size, line.x, line.y);
+        }
+        if index > 0 {
+            let number = index.to_string();
+            let x = (PAGE_WIDTH - text_width(&number, Font::Regular, 10.0)) / 2.0;
+            show(&number, Font::Regular, 10.0, x, MARGIN / 2.0);
+        }
+        pdf.stream(content_id, &content.finish());
+    }
+    pdf.finish()
+}
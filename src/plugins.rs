@@ -0,0 +1,161 @@
+//! Artifact Processors
+//!
+//! This module lets applications give their own artifact types a part in
+//! generation. An `ArtifactProcessor` handles one type, typically an
+//! `ArtifactType::Custom` such as a "MagicSystem": it validates the
+//! artifacts of that type, lays each one out for the prompts, and may
+//! post-process every generated scene, for example to enforce the rules an
+//! artifact sets. Processors are registered in an `ArtifactRegistry`, keyed
+//! by the type they handle, and `PromptTemplates::add_processed_artifacts`
+//! exposes the processed artifacts to the templates as `custom_artifacts`.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use log::debug;
+use crate::artifacts::{Artifact, ArtifactManager, ArtifactType};
+use crate::{PromptTemplates, StoryChainError};
+
+/// Gives artifacts of one type a part in generation
+pub trait ArtifactProcessor: Send + Sync {
+    /// Returns the type of artifacts the processor handles
+    fn artifact_type(&self) -> ArtifactType;
+
+    /// Checks that an artifact is well-formed
+    ///
+    /// # Arguments
+    /// * `artifact` - The artifact to check
+    ///
+    /// # Returns
+    /// What is wrong with the artifact, if anything
+    fn validate(&self, _artifact: &Artifact) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Lays out an artifact for the prompts
+    ///
+    /// # Arguments
+    /// * `artifact` - The artifact to lay out
+    ///
+    /// # Returns
+    /// The prompt section, or `None` to leave the artifact out of the prompts
+    fn render_to_prompt(&self, artifact: &Artifact) -> Option<String> {
+        Some(artifact.content.trim().to_string())
+    }
+
+    /// Post-processes a generated scene
+    ///
+    /// # Arguments
+    /// * `artifact` - The artifact the scene was generated with
+    /// * `content` - The scene
+    ///
+    /// # Returns
+    /// The scene to keep
+    fn post_process(&self, _artifact: &Artifact, content: String) -> String {
+        content
+    }
+}
+
+impl std::fmt::Debug for dyn ArtifactProcessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ArtifactProcessor({:?})", self.artifact_type())
+    }
+}
+
+/// The artifact processors available to a run, keyed by artifact type
+#[derive(Debug, Clone, Default)]
+pub struct ArtifactRegistry {
+    /// Processor of each artifact type
+    processors: HashMap<ArtifactType, Arc<dyn ArtifactProcessor>>,
+}
+
+impl ArtifactRegistry {
+    /// Creates a new, empty ArtifactRegistry
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a processor, replacing any processor of the same artifact type
+    ///
+    /// # Arguments
+    /// * `processor` - The processor to register
+    pub fn register(&mut self, processor: Arc<dyn ArtifactProcessor>) {
+        debug!("Registered a processor for {:?} artifacts", processor.artifact_type());
+        self.processors.insert(processor.artifact_type(), processor);
+    }
+
+    /// Returns the processor of an artifact type
+    ///
+    /// # Arguments
+    /// * `artifact_type` - The artifact type
+    pub fn get(&self, artifact_type: &ArtifactType) -> Option<&Arc<dyn ArtifactProcessor>> {
+        self.processors.get(artifact_type)
+    }
+
+    /// Returns whether no processor is registered
+    pub fn is_empty(&self) -> bool {
+        self.processors.is_empty()
+    }
+
+    /// Checks every artifact of a registered type
+    ///
+    /// # Arguments
+    /// * `artifact_manager` - The artifacts to check
+    ///
+    /// # Returns
+    /// An error naming the first artifact its processor rejects
+    pub fn validate(&self, artifact_manager: &ArtifactManager) -> Result<(), StoryChainError> {
+        for artifact in artifact_manager.get_all_artifacts() {
+            if let Some(processor) = self.get(&artifact.artifact_type) {
+                processor.validate(artifact).map_err(|problem| {
+                    StoryChainError::ConfigError(format!(
+                        "Artifact {} is not a valid {:?} artifact: {}",
+                        artifact.id, artifact.artifact_type, problem
+                    ))
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PromptTemplates {
+    /// Validates the artifacts of the registered types and exposes them to
+    /// the templates as `custom_artifacts`, laid out by their processors,
+    /// which also post-process every scene generated with the templates
+    ///
+    /// # Arguments
+    /// * `artifact_manager` - The artifact manager to read artifacts from
+    /// * `registry` - The processors of the custom artifact types
+    pub fn add_processed_artifacts(
+        &mut self,
+        artifact_manager: &ArtifactManager,
+        registry: &ArtifactRegistry,
+    ) -> Result<(), StoryChainError> {
+        registry.validate(artifact_manager)?;
+        for artifact in crate::prompts::ordered_artifacts(artifact_manager) {
+            let Some(processor) = registry.get(&artifact.artifact_type) else {
+                continue;
+            };
+            if let Some(section) = processor.render_to_prompt(artifact).filter(|s| !s.is_empty()) {
+                self.custom_artifacts.push(section);
+            }
+            self.processed.push((processor.clone(), artifact.clone()));
+        }
+        debug!("Exposed {} processed artifacts to prompt templates", self.custom_artifacts.len());
+        Ok(())
+    }
+
+    /// Passes a generated scene through the processors of the processed
+    /// artifacts, in the order of the artifact IDs
+    ///
+    /// # Arguments
+    /// * `content` - The scene
+    ///
+    /// # Returns
+    /// The processed scene
+    pub fn post_process(&self, content: String) -> String {
+        self.processed
+            .iter()
+            .fold(content, |content, (processor, artifact)| processor.post_process(artifact, content))
+    }
+}
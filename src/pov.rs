@@ -0,0 +1,177 @@
+//! Point of View Scheduling
+//!
+//! This module alternates the viewpoint character between scenes. POV
+//! characters are defined as artifacts and either rotated in a fixed order or
+//! chosen by the AI for each scene. The POV of every node is recorded in its
+//! `pov` metadata and used by the prompts and exports.
+
+use serde::{Deserialize, Serialize};
+use log::{debug, warn};
+use crate::artifacts::{ArtifactManager, ArtifactType};
+use crate::StoryChain;
+
+/// How the viewpoint character of the next scene is selected
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PovMode {
+    /// Cycle through the POV characters in order
+    #[default]
+    Rotation,
+
+    /// Let the AI choose the most fitting POV character for each scene
+    AiChosen,
+}
+
+/// A viewpoint character available to the POV schedule
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PovCharacter {
+    /// Name of the character, as recorded in node metadata
+    pub name: String,
+
+    /// Description of the character's voice and perspective
+    pub description: String,
+}
+
+/// Schedule of viewpoint characters for a chain
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct PovSchedule {
+    /// The characters whose viewpoints are used
+    pub characters: Vec<PovCharacter>,
+
+    /// How the next viewpoint is selected
+    pub mode: PovMode,
+}
+
+impl PovSchedule {
+    /// Builds a schedule from POV artifacts, looked up by ID or by `name` metadata
+    ///
+    /// Artifacts of type `PovCharacter` are preferred, but any artifact can be
+    /// referenced; names that match no artifact are used without a description.
+    ///
+    /// # Arguments
+    /// * `names` - Names or artifact IDs of the POV characters, in rotation order
+    /// * `mode` - How the next viewpoint is selected
+    /// * `artifact_manager` - The manager holding the POV artifacts
+    pub fn from_artifacts(names: &[String], mode: PovMode, artifact_manager: &ArtifactManager) -> Self {
+        let pov_artifacts = artifact_manager.get_artifacts_by_type(&ArtifactType::PovCharacter);
+        let characters = names
+            .iter()
+            .map(|name| {
+                let artifact = artifact_manager.get_artifact(name).or_else(|| {
+                    pov_artifacts
+                        .iter()
+                        .find(|a| a.metadata.get("name") == Some(name))
+                        .copied()
+                });
+                match artifact {
+                    Some(artifact) => PovCharacter {
+                        name: artifact.metadata.get("name").cloned().unwrap_or_else(|| name.clone()),
+                        description: artifact.content.clone(),
+                    },
+                    None => {
+                        warn!("No artifact found for POV character {}", name);
+                        PovCharacter { name: name.clone(), description: String::new() }
+                    }
+                }
+            })
+            .collect();
+
+        Self { characters, mode }
+    }
+}
+
+impl StoryChain {
+    /// Returns the POV character scheduled for the scene following the given
+    /// node, or `None` when no rotation schedule is configured
+    ///
+    /// # Arguments
+    /// * `current_node_id` - ID of the node the next scene will follow
+    pub fn next_pov(&self, current_node_id: &str) -> Option<&PovCharacter> {
+        let schedule = self.settings.pov.as_ref().filter(|s| s.mode == PovMode::Rotation)?;
+        let current_pov = self.nodes.get(current_node_id)?.metadata.get("pov");
+        let index = current_pov
+            .and_then(|pov| schedule.characters.iter().position(|c| &c.name == pov))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        schedule.characters.get(index % schedule.characters.len().max(1))
+    }
+
+    /// Builds the POV instruction for the scene following the given node
+    ///
+    /// # Arguments
+    /// * `current_node_id` - ID of the node the next scene will follow
+    pub fn pov_guidance(&self, current_node_id: &str) -> Option<String> {
+        let schedule = self.settings.pov.as_ref().filter(|s| !s.characters.is_empty())?;
+        let describe = |c: &PovCharacter| {
+            if c.description.is_empty() {
+                c.name.clone()
+            } else {
+                format!("{} ({})", c.name, c.description.replace('\n', " "))
+            }
+        };
+
+        match schedule.mode {
+            PovMode::Rotation => {
+                let pov = self.next_pov(current_node_id)?;
+                Some(format!(
+                    "Write this scene from the point of view of {}. Stay inside their perspective \
+                    and voice for the entire scene.",
+                    describe(pov)
+                ))
+            }
+            PovMode::AiChosen => {
+                let previous = self
+                    .nodes
+                    .get(current_node_id)
+                    .and_then(|n| n.metadata.get("pov"))
+                    .map(|p| format!(" The previous scene was told by {}.", p))
+                    .unwrap_or_default();
+                let options: Vec<String> = schedule.characters.iter().map(describe).collect();
+                Some(format!(
+                    "Choose the point-of-view character best suited to this scene from: {}.{} \
+                    Start your scene content with a line of the form \"POV: <name>\" and then \
+                    stay inside that character's perspective.",
+                    options.join("; "),
+                    previous
+                ))
+            }
+        }
+    }
+
+    /// Determines the POV of a newly generated scene, stripping the AI's
+    /// `POV:` line from the content when the AI chose the viewpoint
+    ///
+    /// # Arguments
+    /// * `current_node_id` - ID of the node the new scene follows
+    /// * `content` - The generated scene content
+    ///
+    /// # Returns
+    /// The (possibly trimmed) content and the POV character name, if any
+    pub fn resolve_pov(&self, current_node_id: &str, content: String) -> (String, Option<String>) {
+        let Some(schedule) = self.settings.pov.as_ref() else {
+            return (content, None);
+        };
+
+        match schedule.mode {
+            PovMode::Rotation => (content, self.next_pov(current_node_id).map(|c| c.name.clone())),
+            PovMode::AiChosen => {
+                let first_line = content.lines().next().unwrap_or_default().trim();
+                let chosen = first_line
+                    .trim_matches('*')
+                    .strip_prefix("POV:")
+                    .map(|name| name.trim().trim_matches('*').trim().to_string());
+                match chosen {
+                    Some(name) => {
+                        debug!("AI chose POV: {}", name);
+                        let rest = content.lines().skip(1).collect::<Vec<_>>().join("\n");
+                        (rest.trim().to_string(), Some(name))
+                    }
+                    None => {
+                        warn!("AI did not declare a POV character for the scene");
+                        (content, None)
+                    }
+                }
+            }
+        }
+    }
+}
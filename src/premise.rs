@@ -0,0 +1,393 @@
+//! Premise Loading
+//!
+//! This module finds the premise a story is generated from. The premise can
+//! be given as a path to a file of any extension, as `-` to read it from
+//! standard input, or as a bare name, which is looked up as a YAML file in
+//! the artifacts directory.
+//!
+//! A YAML premise is read into a [`Premise`], whose fields are validated and
+//! laid out as a labelled prompt section. Premises that are not YAML are
+//! used as plain text.
+
+use std::collections::HashSet;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use crate::StoryChainError;
+
+/// Premise argument that reads the premise from standard input
+pub const STDIN_PREMISE: &str = "-";
+
+/// Extension of the premise files in the artifacts directory
+pub const PREMISE_EXTENSION: &str = "yaml";
+
+/// Returns true if the argument is a bare name rather than a path: a single
+/// path component without an extension
+fn is_bare_name(premise: &str) -> bool {
+    let path = Path::new(premise);
+    path.components().count() == 1 && path.extension().is_none() && !premise.contains(['/', '\\'])
+}
+
+/// Resolves a premise argument to the file it names
+///
+/// Paths are used as given. A bare name is used as given when such a file
+/// exists, and otherwise names `<artifact_dir>/<name>.yaml`.
+///
+/// # Arguments
+/// * `premise` - The premise argument
+/// * `artifact_dir` - The artifacts directory bare names are looked up in
+///
+/// # Returns
+/// The path of the premise file, or `None` when the premise is read from standard input
+pub fn resolve_premise_path(premise: &str, artifact_dir: &Path) -> Option<PathBuf> {
+    if premise == STDIN_PREMISE {
+        return None;
+    }
+    let path = PathBuf::from(premise);
+    if is_bare_name(premise) && !path.is_file() {
+        return Some(artifact_dir.join(format!("{}.{}", premise, PREMISE_EXTENSION)));
+    }
+    Some(path)
+}
+
+/// Returns the name a premise argument is known by in artifact IDs: the
+/// file name without its extension, or `stdin`
+///
+/// # Arguments
+/// * `premise` - The premise argument
+pub fn premise_name(premise: &str) -> String {
+    if premise == STDIN_PREMISE {
+        return "stdin".to_string();
+    }
+    Path::new(premise)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| premise.to_string())
+}
+
+/// Reads a premise given as a path, a bare name, or `-` for standard input
+///
+/// # Arguments
+/// * `premise` - The premise argument
+/// * `artifact_dir` - The artifacts directory bare names are looked up in
+///
+/// # Returns
+/// The text of the premise; errors name the file that was tried
+pub fn load_premise(premise: &str, artifact_dir: &Path) -> Result<String, StoryChainError> {
+    let text = match resolve_premise_path(premise, artifact_dir) {
+        Some(path) => {
+            let text = std::fs::read_to_string(&path).map_err(|e| {
+                let hint = if is_bare_name(premise) { format!(" (no file named {} either)", premise) } else { String::new() };
+                std::io::Error::new(e.kind(), format!("Cannot read premise {}{}: {}", path.display(), hint, e))
+            })?;
+            info!("Loaded premise from {}", path.display());
+            text
+        }
+        None => {
+            let mut text = String::new();
+            std::io::stdin()
+                .read_to_string(&mut text)
+                .map_err(|e| std::io::Error::new(e.kind(), format!("Cannot read premise from standard input: {}", e)))?;
+            info!("Loaded premise from standard input");
+            text
+        }
+    };
+    if text.trim().is_empty() {
+        return Err(StoryChainError::ConfigError(match premise {
+            STDIN_PREMISE => "The premise read from standard input is empty".to_string(),
+            _ => format!("The premise {} is empty", resolve_premise_path(premise, artifact_dir).unwrap_or_default().display()),
+        }));
+    }
+    Ok(text)
+}
+
+/// Top-level keys that mark a premise as structured YAML rather than plain text
+const PREMISE_KEYS: &[&str] = &[
+    "title", "genre", "tone", "pov", "setting", "time_period", "premise",
+    "characters", "themes", "plot_elements", "constraints", "target_length",
+];
+
+/// A character named in a premise
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PremiseCharacter {
+    /// The character's name, optionally with a quoted nickname
+    pub name: String,
+
+    /// Who the character is
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+
+    /// How the character changes over the story
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub arc: Option<String>,
+}
+
+/// How long the story should be
+///
+/// Given in YAML either as a number of words or as a map with `words`
+/// and/or `scenes`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "TargetLengthSpec")]
+pub struct TargetLength {
+    /// Approximate length of the story in words
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub words: Option<usize>,
+
+    /// Approximate number of scenes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scenes: Option<usize>,
+}
+
+/// The forms a target length can take in YAML
+#[derive(Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum TargetLengthSpec {
+    Words(usize),
+    Parts {
+        #[serde(default)]
+        words: Option<usize>,
+        #[serde(default)]
+        scenes: Option<usize>,
+    },
+}
+
+impl From<TargetLengthSpec> for TargetLength {
+    fn from(spec: TargetLengthSpec) -> Self {
+        match spec {
+            TargetLengthSpec::Words(words) => TargetLength { words: Some(words), scenes: None },
+            TargetLengthSpec::Parts { words, scenes } => TargetLength { words, scenes },
+        }
+    }
+}
+
+/// A story premise
+///
+/// Read from YAML, or wrapping the plain text of a premise that is not
+/// YAML, in which case only `premise` is set.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Premise {
+    /// Working title of the story
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+
+    /// Genre, e.g. "Crime / Thriller"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub genre: Option<String>,
+
+    /// Tone the prose should keep, e.g. "darkly comic"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tone: Option<String>,
+
+    /// Point of view the story is told from, e.g. "close third person, Lola"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pov: Option<String>,
+
+    /// Where the story takes place
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub setting: Option<String>,
+
+    /// When the story takes place
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_period: Option<String>,
+
+    /// The premise itself
+    pub premise: String,
+
+    /// Main characters
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub characters: Vec<PremiseCharacter>,
+
+    /// Themes the story explores
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub themes: Vec<String>,
+
+    /// Events and elements the plot should include
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub plot_elements: Vec<String>,
+
+    /// Rules every scene must respect
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub constraints: Vec<String>,
+
+    /// How long the story should be
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target_length: Option<TargetLength>,
+}
+
+impl Premise {
+    /// Reads a premise from its text
+    ///
+    /// Text that is a YAML map with any of the premise keys is read as a
+    /// structured premise and validated; any other text is used as is.
+    ///
+    /// # Arguments
+    /// * `text` - The text of the premise
+    ///
+    /// # Returns
+    /// The premise, or a `ConfigError` listing what is wrong with it
+    pub fn parse(text: &str) -> Result<Self, StoryChainError> {
+        let value: serde_yaml::Value = match serde_yaml::from_str(text) {
+            Ok(value) => value,
+            Err(e) if Self::looks_structured(text) => {
+                return Err(StoryChainError::ConfigError(format!("Invalid premise YAML: {}", e)));
+            }
+            Err(e) => {
+                debug!("Using the premise as plain text, as it is not YAML: {}", e);
+                return Ok(Self::from_text(text));
+            }
+        };
+        let structured = value
+            .as_mapping()
+            .is_some_and(|map| map.keys().any(|key| key.as_str().is_some_and(|key| PREMISE_KEYS.contains(&key))));
+        if !structured {
+            return Ok(Self::from_text(text));
+        }
+
+        let premise: Premise = serde_yaml::from_value(value)
+            .map_err(|e| StoryChainError::ConfigError(format!("Invalid premise: {}", e)))?;
+        premise.validate()?;
+        Ok(premise)
+    }
+
+    /// Returns the premise as YAML that `parse` reads back
+    pub fn to_yaml(&self) -> Result<String, StoryChainError> {
+        serde_yaml::to_string(self).map_err(|e| StoryChainError::ConfigError(format!("Cannot write premise YAML: {}", e)))
+    }
+
+    /// Wraps the plain text of a premise
+    pub fn from_text(text: &str) -> Self {
+        Premise { premise: text.trim().to_string(), ..Default::default() }
+    }
+
+    /// Returns true if a line of the text starts with a premise key, so that
+    /// a YAML syntax error is reported rather than the text used as is
+    fn looks_structured(text: &str) -> bool {
+        text.lines().any(|line| {
+            line.split_once(':')
+                .is_some_and(|(key, _)| PREMISE_KEYS.contains(&key.trim_end()))
+        })
+    }
+
+    /// Checks that the premise is complete and its fields are usable
+    ///
+    /// # Returns
+    /// A `ConfigError` listing every problem found
+    pub fn validate(&self) -> Result<(), StoryChainError> {
+        let mut problems = Vec::new();
+        if self.premise.trim().is_empty() {
+            problems.push("`premise` is missing or empty".to_string());
+        }
+        let fields = [
+            ("title", &self.title),
+            ("genre", &self.genre),
+            ("tone", &self.tone),
+            ("pov", &self.pov),
+            ("setting", &self.setting),
+            ("time_period", &self.time_period),
+        ];
+        for (key, value) in fields {
+            if value.as_deref().is_some_and(|v| v.trim().is_empty()) {
+                problems.push(format!("`{}` is empty", key));
+            }
+        }
+
+        let mut names = HashSet::new();
+        for (index, character) in self.characters.iter().enumerate() {
+            let name = character.name.trim();
+            if name.is_empty() {
+                problems.push(format!("character {} has no name", index + 1));
+            } else if !names.insert(name.to_lowercase()) {
+                problems.push(format!("character {} is listed more than once", name));
+            }
+        }
+        for (key, items) in [("themes", &self.themes), ("plot_elements", &self.plot_elements), ("constraints", &self.constraints)] {
+            if items.iter().any(|item| item.trim().is_empty()) {
+                problems.push(format!("`{}` has an empty entry", key));
+            }
+        }
+
+        if let Some(length) = self.target_length {
+            if length.words.is_none() && length.scenes.is_none() {
+                problems.push("`target_length` needs `words` or `scenes`".to_string());
+            }
+            if length.words == Some(0) || length.scenes == Some(0) {
+                problems.push("`target_length` must be greater than zero".to_string());
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(StoryChainError::ConfigError(format!("Invalid premise: {}", problems.join("; "))))
+        }
+    }
+
+    /// Returns the requirements every scene must keep to: the tone, point of
+    /// view, constraints, and target length
+    pub fn requirements(&self) -> Vec<String> {
+        let mut requirements = Vec::new();
+        requirements.extend(self.tone.as_ref().map(|tone| format!("Keep the tone {}.", tone.trim())));
+        requirements.extend(self.pov.as_ref().map(|pov| format!("Write from the point of view: {}.", pov.trim())));
+        requirements.extend(self.constraints.iter().map(|constraint| constraint.trim().to_string()));
+        if let Some(length) = self.target_length {
+            let words = length.words.map(|words| format!("about {} words", words));
+            let scenes = length.scenes.map(|scenes| format!("about {} scenes", scenes));
+            let length = words.into_iter().chain(scenes).collect::<Vec<_>>().join(" over ");
+            requirements.push(format!("Pace the story to run {} in total.", length));
+        }
+        requirements
+    }
+
+    /// Lays the premise out as a labelled section for scene prompts
+    ///
+    /// A plain-text premise is returned as is.
+    pub fn to_prompt_section(&self) -> String {
+        if *self == Self::from_text(&self.premise) {
+            return self.premise.clone();
+        }
+
+        let mut lines = Vec::new();
+        let fields = [
+            ("Title", &self.title),
+            ("Genre", &self.genre),
+            ("Setting", &self.setting),
+            ("Time period", &self.time_period),
+        ];
+        for (label, value) in fields {
+            if let Some(value) = value {
+                lines.push(format!("{}: {}", label, value.trim()));
+            }
+        }
+        let premise = self.premise.trim();
+        lines.push(if lines.is_empty() { premise.to_string() } else { format!("\n{}", premise) });
+
+        if !self.characters.is_empty() {
+            lines.push("\nCharacters:".to_string());
+            for character in &self.characters {
+                let mut line = format!("- {}", character.name.trim());
+                if let Some(description) = &character.description {
+                    line.push_str(&format!(": {}", description.trim()));
+                }
+                if let Some(arc) = &character.arc {
+                    line.push_str(&format!(" Arc: {}", arc.trim()));
+                }
+                lines.push(line);
+            }
+        }
+        for (label, items) in [("Themes", &self.themes), ("Plot elements", &self.plot_elements)] {
+            if !items.is_empty() {
+                lines.push(format!("\n{}:", label));
+                lines.extend(items.iter().map(|item| format!("- {}", item.trim())));
+            }
+        }
+        let requirements = self.requirements();
+        if !requirements.is_empty() {
+            lines.push("\nRequirements:".to_string());
+            lines.extend(requirements.iter().map(|requirement| format!("- {}", requirement)));
+        }
+        lines.join("\n")
+    }
+}
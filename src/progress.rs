@@ -0,0 +1,184 @@
+//! Progress Reporting
+//!
+//! This module shows a progress bar while a story is generated, so that long
+//! runs are not silent without `RUST_LOG`. The bar shows the current epoch,
+//! the time elapsed, an estimate of the time left based on how long the
+//! scenes of the earlier epochs took, and the number of tokens streamed from
+//! the model so far. Log lines are printed above the bar instead of through
+//! it.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use indicatif::{HumanDuration, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use indicatif_log_bridge::LogWrapper;
+use crate::tokenizer::count_tokens;
+use crate::StoryChainError;
+
+/// How often the spinner and elapsed time are redrawn
+const TICK_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Layout of the progress bar
+const TEMPLATE: &str = "{spinner} {prefix} [{bar:30}] {pos}/{len} done · {elapsed} elapsed · {msg}";
+
+/// Sets up `env_logger` so that log lines are printed above progress bars
+///
+/// # Returns
+/// The progress bars that log lines are printed around
+pub fn init_logging() -> Result<MultiProgress, StoryChainError> {
+    let logger = env_logger::Builder::from_default_env().build();
+    let level = logger.filter();
+    let bars = MultiProgress::new();
+    LogWrapper::new(bars.clone(), logger)
+        .try_init()
+        .map_err(|e| StoryChainError::ConfigError(format!("Cannot initialize logging: {}", e)))?;
+    log::set_max_level(level);
+    Ok(bars)
+}
+
+/// Estimates the time left in a run from the time the scenes so far took
+#[derive(Debug, Clone, Default)]
+pub struct EpochTimings {
+    /// Time spent in the finished epochs
+    elapsed: Duration,
+
+    /// Scenes generated in the finished epochs
+    scenes: usize,
+}
+
+impl EpochTimings {
+    /// Records a finished epoch
+    ///
+    /// # Arguments
+    /// * `duration` - How long the epoch took
+    /// * `scenes` - How many scenes it generated
+    pub fn record(&mut self, duration: Duration, scenes: usize) {
+        self.elapsed += duration;
+        self.scenes += scenes;
+    }
+
+    /// Estimates how long generating more scenes will take, at the average
+    /// time per scene so far
+    ///
+    /// # Arguments
+    /// * `scenes_left` - Scenes still to generate
+    ///
+    /// # Returns
+    /// The estimate, or `None` before any scene was generated
+    pub fn remaining(&self, scenes_left: usize) -> Option<Duration> {
+        if self.scenes == 0 {
+            return None;
+        }
+        Some(self.elapsed.mul_f64(scenes_left as f64 / self.scenes as f64))
+    }
+}
+
+/// Counts the scenes the remaining epochs of a run will generate when every
+/// storyline keeps branching
+///
+/// # Arguments
+/// * `frontier` - Number of storylines the next epoch continues
+/// * `branch_ratio` - Continuations generated per scene
+/// * `epochs_left` - Epochs still to generate
+pub fn scenes_left(frontier: usize, branch_ratio: usize, epochs_left: usize) -> usize {
+    let mut storylines = frontier;
+    let mut scenes = 0usize;
+    for _ in 0..epochs_left {
+        storylines = storylines.saturating_mul(branch_ratio.max(1));
+        scenes = scenes.saturating_add(storylines);
+    }
+    scenes
+}
+
+/// A progress bar over the epochs of a run
+pub struct RunProgress {
+    bar: ProgressBar,
+    timings: Mutex<EpochTimings>,
+    eta: Mutex<Option<Duration>>,
+    tokens: AtomicUsize,
+}
+
+impl RunProgress {
+    /// Creates a progress bar, which counts tokens from the start and shows
+    /// the epochs once `start_run` is called
+    ///
+    /// # Arguments
+    /// * `bars` - The progress bars returned by `init_logging`
+    /// * `hidden` - Whether to track progress without drawing the bar, e.g. with `--quiet`
+    pub fn new(bars: &MultiProgress, hidden: bool) -> Self {
+        let bar = if hidden {
+            ProgressBar::with_draw_target(Some(0), ProgressDrawTarget::hidden())
+        } else {
+            let bar = bars.add(ProgressBar::new(0));
+            bar.enable_steady_tick(TICK_INTERVAL);
+            bar
+        };
+        bar.set_style(ProgressStyle::with_template(TEMPLATE).expect("valid template").progress_chars("=> "));
+        bar.set_prefix("Opening scene");
+        bar.set_message("0 tokens");
+        RunProgress {
+            bar,
+            timings: Mutex::new(EpochTimings::default()),
+            eta: Mutex::new(None),
+            tokens: AtomicUsize::new(0),
+        }
+    }
+
+    /// Sets the epochs of the run
+    ///
+    /// # Arguments
+    /// * `total_epochs` - Number of epochs in the run
+    /// * `completed_epochs` - Epochs finished before the run started, when continuing a story
+    pub fn start_run(&self, total_epochs: usize, completed_epochs: usize) {
+        self.bar.set_length(total_epochs as u64);
+        self.bar.set_position(completed_epochs as u64);
+    }
+
+
+    /// Returns whether the bar is drawn
+    pub fn is_hidden(&self) -> bool {
+        self.bar.is_hidden()
+    }
+
+    /// Shows the epoch being generated
+    ///
+    /// # Arguments
+    /// * `epoch` - The epoch, 1-indexed
+    pub fn start_epoch(&self, epoch: usize) {
+        let eta = match *self.eta.lock().expect("eta lock") {
+            Some(remaining) => format!("ETA {}", HumanDuration(remaining)),
+            None => "ETA unknown".to_string(),
+        };
+        self.bar.set_prefix(format!("Epoch {}/{} ({})", epoch, self.bar.length().unwrap_or_default(), eta));
+    }
+
+    /// Records a finished epoch and updates the estimate of the time left
+    ///
+    /// # Arguments
+    /// * `duration` - How long the epoch took
+    /// * `scenes` - How many scenes it generated
+    /// * `scenes_left` - Scenes the remaining epochs will generate
+    pub fn finish_epoch(&self, duration: Duration, scenes: usize, scenes_left: usize) {
+        let mut timings = self.timings.lock().expect("timings lock");
+        timings.record(duration, scenes);
+        *self.eta.lock().expect("eta lock") = timings.remaining(scenes_left);
+        self.bar.inc(1);
+    }
+
+    /// Counts a piece of a streamed response
+    pub fn add_tokens(&self, text: &str) {
+        let count = count_tokens(text);
+        let tokens = self.tokens.fetch_add(count, Ordering::Relaxed) + count;
+        self.bar.set_message(format!("{} tokens", tokens));
+    }
+
+    /// Returns the number of tokens streamed so far
+    pub fn tokens(&self) -> usize {
+        self.tokens.load(Ordering::Relaxed)
+    }
+
+    /// Removes the bar once the run is over
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+}
@@ -0,0 +1,222 @@
+//! Project Workspaces
+//!
+//! This module bundles everything a story is made from under one directory:
+//! the premise and other artifacts, the configuration file, the checkpoints
+//! saved after every epoch, and the generated chains. `Project::init`
+//! scaffolds the directory and `Project::open` reads it back:
+//!
+//! ```text
+//! dragon/
+//!   project.toml        name and premise of the project
+//!   storychain.toml     provider and generation settings
+//!   artifacts/          premise (dragon.yaml), characters, lore, ...
+//!   chains/             generated stories
+//!   checkpoints/        the stories as they were after each epoch
+//! ```
+
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use log::info;
+use crate::config::{StoryChainConfig, DEFAULT_CONFIG_FILE};
+use crate::premise::PREMISE_EXTENSION;
+use crate::storage::JsonFileStore;
+use crate::{StoryChain, StoryChainError};
+
+/// File that marks a directory as a project
+pub const PROJECT_FILE: &str = "project.toml";
+
+/// Directory of a project's artifacts
+pub const ARTIFACTS_DIR: &str = "artifacts";
+
+/// Directory of a project's generated chains
+pub const CHAINS_DIR: &str = "chains";
+
+/// Directory of a project's checkpoints
+pub const CHECKPOINTS_DIR: &str = "checkpoints";
+
+/// Contents of a project file
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ProjectManifest {
+    /// Name of the project, also the default name of its story
+    pub name: String,
+
+    /// Name of the premise in the artifacts directory
+    pub premise: String,
+
+    /// When the project was created, in RFC 3339 format
+    pub created_at: String,
+}
+
+/// A directory holding one story's premise, artifacts, configuration,
+/// checkpoints, and chains
+#[derive(Debug, Clone, PartialEq)]
+pub struct Project {
+    /// Directory of the project
+    pub root: PathBuf,
+
+    /// Contents of the project file
+    pub manifest: ProjectManifest,
+}
+
+/// Returns the premise written into a new project, to be filled in
+fn premise_template(name: &str) -> String {
+    format!(
+        "# Premise of {name}. Fill in the premise and any of the optional fields, then run\n\
+        # `storychain generate --project <dir>`.\n\
+        title: {name}\n\
+        # genre: Fantasy\n\
+        # tone: Wry and warm\n\
+        # setting: A mountain village\n\
+        premise: \"\"\n\
+        # characters:\n\
+        #   - name: Mara\n\
+        #     description: A baker who hears the mountain speak\n\
+        # themes: [belonging]\n\
+        # target_length:\n\
+        #   scenes: 12\n",
+        name = name
+    )
+}
+
+impl Project {
+    /// Scaffolds a new project: its directories, project file, default
+    /// configuration, and a premise to fill in
+    ///
+    /// Files that already exist, such as a configuration, are kept.
+    ///
+    /// # Arguments
+    /// * `dir` - Directory of the project, created when needed
+    /// * `name` - Name of the project
+    ///
+    /// # Returns
+    /// The project, or a `ConfigError` when the directory already is one
+    pub fn init(dir: impl AsRef<Path>, name: &str) -> Result<Self, StoryChainError> {
+        let root = dir.as_ref().to_path_buf();
+        if name.trim().is_empty() || name.contains(['/', '\\']) {
+            return Err(StoryChainError::ConfigError(format!("Invalid project name: {:?}", name)));
+        }
+        if root.join(PROJECT_FILE).exists() {
+            return Err(StoryChainError::ConfigError(format!("{} is already a project", root.display())));
+        }
+        for subdir in [ARTIFACTS_DIR, CHAINS_DIR, CHECKPOINTS_DIR] {
+            std::fs::create_dir_all(root.join(subdir))?;
+        }
+
+        let project = Self {
+            root,
+            manifest: ProjectManifest {
+                name: name.to_string(),
+                premise: name.to_string(),
+                created_at: chrono::Utc::now().to_rfc3339(),
+            },
+        };
+        let manifest = toml::to_string(&project.manifest).map_err(|e| StoryChainError::ConfigError(e.to_string()))?;
+        crate::files::write_atomic(project.root.join(PROJECT_FILE), manifest)?;
+        if !project.config_path().exists() {
+            let config = toml::to_string(&StoryChainConfig::default())
+                .map_err(|e| StoryChainError::ConfigError(e.to_string()))?;
+            crate::files::write_atomic(project.config_path(), config)?;
+        }
+        if !project.premise_path().exists() {
+            crate::files::write_atomic(project.premise_path(), premise_template(name))?;
+        }
+        info!("Created project {} in {}", name, project.root.display());
+        Ok(project)
+    }
+
+    /// Opens an existing project
+    ///
+    /// # Arguments
+    /// * `dir` - Directory of the project
+    ///
+    /// # Returns
+    /// The project, or a `ConfigError` when the directory has no project file
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self, StoryChainError> {
+        let root = dir.as_ref().to_path_buf();
+        let path = root.join(PROJECT_FILE);
+        if !path.is_file() {
+            return Err(StoryChainError::ConfigError(format!(
+                "{} is not a project; create one with `storychain init`",
+                root.display()
+            )));
+        }
+        let manifest: ProjectManifest = toml::from_str(&std::fs::read_to_string(&path)?)
+            .map_err(|e| StoryChainError::ConfigError(format!("{}: {}", path.display(), e)))?;
+        info!("Opened project {} in {}", manifest.name, root.display());
+        Ok(Self { root, manifest })
+    }
+
+    /// Makes the project directory the working directory, so that the
+    /// artifacts directory, configuration file, and other relative paths
+    /// resolve inside the project
+    pub fn enter(&mut self) -> Result<(), StoryChainError> {
+        std::env::set_current_dir(&self.root)?;
+        self.root = PathBuf::new();
+        Ok(())
+    }
+
+    /// Returns the path of the project's configuration file
+    pub fn config_path(&self) -> PathBuf {
+        self.root.join(DEFAULT_CONFIG_FILE)
+    }
+
+    /// Returns the project's artifacts directory
+    pub fn artifacts_dir(&self) -> PathBuf {
+        self.root.join(ARTIFACTS_DIR)
+    }
+
+    /// Returns the path of the project's premise
+    pub fn premise_path(&self) -> PathBuf {
+        self.artifacts_dir().join(format!("{}.{}", self.manifest.premise, PREMISE_EXTENSION))
+    }
+
+    /// Returns the store holding the project's chains
+    pub fn chain_store(&self) -> JsonFileStore {
+        JsonFileStore::new(self.root.join(CHAINS_DIR))
+    }
+
+    /// Returns the path of a chain of the project
+    ///
+    /// # Arguments
+    /// * `name` - Name of the chain
+    pub fn chain_path(&self, name: &str) -> PathBuf {
+        self.chain_store().path(name)
+    }
+
+    /// Returns the path of a chain's checkpoint after an epoch
+    ///
+    /// # Arguments
+    /// * `name` - Name of the chain
+    /// * `epoch` - The epoch just completed
+    pub fn checkpoint_path(&self, name: &str, epoch: usize) -> PathBuf {
+        self.root.join(CHECKPOINTS_DIR).join(format!("{}_epoch_{}.json", name, epoch))
+    }
+
+    /// Saves a copy of a chain as it is after an epoch
+    ///
+    /// # Arguments
+    /// * `name` - Name of the chain
+    /// * `chain` - The chain to save
+    /// * `epoch` - The epoch just completed
+    pub fn save_checkpoint(&self, name: &str, chain: &StoryChain, epoch: usize) -> Result<PathBuf, StoryChainError> {
+        let path = self.checkpoint_path(name, epoch);
+        std::fs::create_dir_all(self.root.join(CHECKPOINTS_DIR))?;
+        chain.export_to_file(&path.to_string_lossy())?;
+        chain.notify_hooks(|hooks| hooks.checkpoint_saved(chain, epoch, &path));
+        Ok(path)
+    }
+
+    /// Resolves a story argument: an existing file is used as given, and
+    /// anything else names one of the project's chains
+    ///
+    /// # Arguments
+    /// * `story` - A story file, or the name of a chain of the project
+    pub fn resolve_story(&self, story: &str) -> String {
+        if Path::new(story).is_file() {
+            return story.to_string();
+        }
+        let name = Path::new(story).file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+        self.chain_path(&name).to_string_lossy().to_string()
+    }
+}
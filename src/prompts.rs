@@ -0,0 +1,460 @@
+//! Prompt Templates
+//!
+//! This module renders every prompt from Tera templates so they can be
+//! customized without recompiling. The built-in templates can be replaced by
+//! placing `<name>.tera`, such as `initial.tera` or `continuation.tera`, in
+//! a template directory, or one at a time by loading a `PromptTemplate` from
+//! any file. The scene prompts are defined here; the prompts of the other
+//! passes, such as the title and blurb or the consistency critic, are
+//! defined next to the code that sends them, each documenting its
+//! variables, and are all listed in `BUILT_IN_TEMPLATES`.
+//!
+//! Variables available to both scene templates:
+//! * `premise` - The story premise (empty when none was given)
+//! * `artifacts` - Map of artifact ID to artifact content, e.g. `{{ artifacts.world }}`
+//! * `artifact_order` - The artifact IDs in dependency order, each after the
+//!   artifacts it references, e.g. `{% for id in artifact_order %}{{ artifacts[id] }}{% endfor %}`
+//! * `exemplars` - Contents of the `Exemplar` artifacts, used as few-shot examples
+//! * `style` - The `StyleGuide` artifact laid out for the prompt (empty when none)
+//! * `lore` - The `WorldBuilding` snippets most relevant to the premise (initial)
+//!   or the previous scene and planned beat (continuation), one per line
+//! * `custom_artifacts` - Artifacts of the types with a registered
+//!   `ArtifactProcessor`, each laid out by its processor
+//!
+//! Additionally available to the initial template:
+//! * `instructions` - List of extra instructions such as the format and genre
+//!
+//! Additionally available to the continuation template:
+//! * `last_scene`, `last_reasoning` - Content and reasoning of the previous scene
+//! * `summary` - The chain's `summary` metadata (empty when not set)
+//! * `recalled` - Earlier scenes most similar to the previous one, each
+//!   headed by its node ID (empty unless scene recall is enabled)
+//! * `beat` - The outline beat planned for this scene (empty when none)
+//! * `characters` - Character sheets of the characters mentioned in the
+//!   previous scene (empty when none)
+//! * `guidance` - List of guidance notes derived from the chain settings
+//! * `epoch`, `total_epochs`, `epochs_remaining`, `phase` - Story progress
+//! * `arc_stage`, `pacing` - Stage of the story arc (opening, midpoint,
+//!   climax, or finale) and the pacing instruction for it; the finale, on
+//!   the last epoch, asks for a conclusive ending
+//! * `metadata` - The chain metadata; each entry is also available as a
+//!   top-level variable unless it clashes with one of the names above
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+use log::{info, debug, warn};
+use tera::{Context, Tera};
+use sha2::{Digest, Sha256};
+use crate::artifacts::{Artifact, ArtifactManager, ArtifactType};
+use crate::characters::CharacterSheet;
+use crate::lore::LoreIndex;
+use crate::plugins::ArtifactProcessor;
+use crate::style::StyleGuide;
+use crate::{StoryChain, StoryChainError};
+
+/// Maximum number of characters of each exemplar included in prompts
+pub const MAX_EXEMPLAR_CHARS: usize = 3000;
+
+/// Name of the template for the opening scene
+pub const INITIAL: &str = "initial";
+
+/// Name of the template for every following scene
+pub const CONTINUATION: &str = "continuation";
+
+/// Built-in template for the opening scene
+pub const INITIAL_TEMPLATE: &str = r#"You are tasked with writing a scene in the style specified by the premise.
+
+IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Write your reasoning here in a single paragraph, explaining your narrative choices and how they connect to the premise.
+</think>
+Write your scene content here, using proper paragraphs and formatting.
+
+Story Premise:
+{{ premise }}
+
+{% if lore %}Established Lore (do not contradict these facts):
+{{ lore }}
+
+{% endif %}{% for section in custom_artifacts %}{{ section }}
+
+{% endfor %}{% if exemplars %}Examples Of The Desired Voice (match their style, not their events):
+{% for example in exemplars %}--- Example {{ loop.index }} ---
+{{ example }}
+
+{% endfor %}{% endif %}{% if style %}Style Guide (follow it in every scene):
+{{ style }}
+
+{% endif %}{% for instruction in instructions %}{{ instruction }}
+
+{% endfor %}Remember:
+- Put your reasoning in a SINGLE paragraph inside <think> tags
+- Write your scene content immediately after the </think> tag
+- Use proper paragraphs in your scene content
+- Do NOT add any extra formatting or tags"#;
+
+/// Built-in template for continuation scenes
+pub const CONTINUATION_TEMPLATE: &str = r#"{% if premise %}Story Premise:
+{{ premise }}
+
+{% endif %}{% if summary %}Story So Far:
+{{ summary }}
+
+{% endif %}{% if recalled %}Relevant Earlier Scenes:
+{{ recalled }}
+
+{% endif %}Story Progress:
+- Current epoch: {{ epoch }} of {{ total_epochs }}
+- Story phase: {{ phase }}
+- Arc stage: {{ arc_stage }}
+- Epochs remaining: {{ epochs_remaining }}
+
+You are continuing a story. Here is the previous scene and its reasoning:
+
+Previous Scene Reasoning:
+{{ last_reasoning }}
+
+Previous Scene Content:
+{{ last_scene }}
+
+{% if lore %}Established Lore (do not contradict these facts):
+{{ lore }}
+
+{% endif %}{% for section in custom_artifacts %}{{ section }}
+
+{% endfor %}{% if characters %}Characters In The Previous Scene (keep their characterization consistent):
+{{ characters }}
+
+{% endif %}{% if beat %}Planned Beat For This Scene:
+{{ beat }}
+
+{% endif %}{% if exemplars %}Examples Of The Desired Voice (match their style, not their events):
+{% for example in exemplars %}--- Example {{ loop.index }} ---
+{{ example }}
+
+{% endfor %}{% endif %}{% if style %}Style Guide (follow it in every scene):
+{{ style }}
+
+{% endif %}{% if guidance %}Guidance For This Scene:
+{% for note in guidance %}- {{ note }}
+{% endfor %}
+{% endif %}{% if pacing %}Pacing For This Scene:
+{{ pacing }}
+
+{% endif %}Now continue the story, maintaining consistency with the previous scene and the overall premise.
+Consider the current story phase ({{ phase }}) and remaining epochs ({{ epochs_remaining }}) when deciding how to progress the plot.
+
+IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your reasoning about how this scene continues the story and develops the narrative.
+</think>
+Write your scene content here, making sure it flows naturally from the previous scene..."#;
+
+/// Every built-in template by name
+pub const BUILT_IN_TEMPLATES: [(&str, &str); 32] = [
+    (INITIAL, INITIAL_TEMPLATE),
+    (CONTINUATION, CONTINUATION_TEMPLATE),
+    (crate::branches::MERGE, crate::branches::MERGE_TEMPLATE),
+    (crate::passes::TITLE_BLURB, crate::passes::TITLE_BLURB_TEMPLATE),
+    (crate::passes::SYNOPSIS, crate::passes::SYNOPSIS_TEMPLATE),
+    (crate::passes::TAGS, crate::passes::TAGS_TEMPLATE),
+    (crate::chapters::CHAPTER_SUMMARY, crate::chapters::CHAPTER_SUMMARY_TEMPLATE),
+    (crate::characters::CHARACTER_SHEETS, crate::characters::CHARACTER_SHEETS_TEMPLATE),
+    (crate::glossary::GLOSSARY, crate::glossary::GLOSSARY_TEMPLATE),
+    (crate::series::CONTINUITY_PACKET, crate::series::CONTINUITY_PACKET_TEMPLATE),
+    (crate::illustrations::COVER_BRIEF, crate::illustrations::COVER_BRIEF_TEMPLATE),
+    (crate::illustrations::INTERIOR_BRIEF, crate::illustrations::INTERIOR_BRIEF_TEMPLATE),
+    (crate::illustrations::SCENE_BRIEF, crate::illustrations::SCENE_BRIEF_TEMPLATE),
+    (crate::memory::RUNNING_SUMMARY, crate::memory::RUNNING_SUMMARY_TEMPLATE),
+    (crate::consistency::CONTRADICTIONS, crate::consistency::CONTRADICTIONS_TEMPLATE),
+    (crate::consistency::CONSISTENCY_REVISION, crate::consistency::CONSISTENCY_REVISION_TEMPLATE),
+    (crate::dialogue::DIALOGUE_REVISION, crate::dialogue::DIALOGUE_REVISION_TEMPLATE),
+    (crate::length::LENGTH_REVISION, crate::length::LENGTH_REVISION_TEMPLATE),
+    (crate::quality::CRITIQUE, crate::quality::CRITIQUE_TEMPLATE),
+    (crate::revision::EVALUATION, crate::revision::EVALUATION_TEMPLATE),
+    (crate::revision::REWRITE, crate::revision::REWRITE_TEMPLATE),
+    (crate::selection::RANKING, crate::selection::RANKING_TEMPLATE),
+    (crate::enrichment::SCENE_METADATA, crate::enrichment::SCENE_METADATA_TEMPLATE),
+    (crate::foreshadowing::FORESHADOWING_CHECK, crate::foreshadowing::FORESHADOWING_CHECK_TEMPLATE),
+    (crate::fountain::SCREENPLAY, crate::fountain::SCREENPLAY_TEMPLATE),
+    (crate::translation::TRANSLATION, crate::translation::TRANSLATION_TEMPLATE),
+    (crate::translation::LABEL_TRANSLATION, crate::translation::LABEL_TRANSLATION_TEMPLATE),
+    (crate::twee::CHOICE_LABELS, crate::twee::CHOICE_LABELS_TEMPLATE),
+    (crate::chat::CHAT_SUMMARY, crate::chat::CHAT_SUMMARY_TEMPLATE),
+    (crate::wizard::INTERVIEW_QUESTION, crate::wizard::INTERVIEW_QUESTION_TEMPLATE),
+    (crate::wizard::PREMISE, crate::wizard::PREMISE_TEMPLATE),
+    (crate::wizard::CHARACTER_ARCS, crate::wizard::CHARACTER_ARCS_TEMPLATE),
+];
+
+/// Variable names reserved by the continuation template
+const RESERVED_VARIABLES: [&str; 22] = [
+    "premise", "artifacts", "artifact_order", "exemplars", "style", "lore", "instructions", "last_scene", "last_reasoning", "summary", "recalled",
+    "beat", "characters", "guidance", "epoch", "total_epochs", "epochs_remaining", "phase", "arc_stage", "pacing",
+    "metadata", "custom_artifacts",
+];
+
+/// Converts a Tera error, including its causes, into a StoryChainError
+pub(crate) fn template_error(error: tera::Error) -> StoryChainError {
+    let mut message = error.to_string();
+    let mut source = std::error::Error::source(&error);
+    while let Some(cause) = source {
+        message.push_str(&format!(": {}", cause));
+        source = cause.source();
+    }
+    StoryChainError::TemplateError(message)
+}
+
+/// A single prompt template loaded from a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptTemplate {
+    /// Name of the prompt it replaces, such as `initial` or `continuation`
+    pub name: String,
+
+    /// Tera source of the template
+    pub source: String,
+}
+
+impl PromptTemplate {
+    /// Loads a template from a file, checking that it parses
+    ///
+    /// # Arguments
+    /// * `name` - Name of the prompt it replaces, one of `BUILT_IN_TEMPLATES`
+    /// * `path` - Path to the template file
+    pub fn from_file(name: &str, path: &Path) -> Result<Self, StoryChainError> {
+        if !BUILT_IN_TEMPLATES.iter().any(|(built_in, _)| *built_in == name) {
+            let names: Vec<&str> = BUILT_IN_TEMPLATES.iter().map(|(name, _)| *name).collect();
+            return Err(StoryChainError::ConfigError(format!(
+                "Unknown prompt template '{}', expected one of: {}",
+                name,
+                names.join(", ")
+            )));
+        }
+        let source = std::fs::read_to_string(path).map_err(|e| {
+            StoryChainError::ConfigError(format!("Cannot read {} prompt template {}: {}", name, path.display(), e))
+        })?;
+        Tera::default().add_raw_template(name, &source).map_err(template_error)?;
+        Ok(Self { name: name.to_string(), source })
+    }
+}
+
+/// The set of templates used to build generation prompts
+#[derive(Debug, Clone)]
+pub struct PromptTemplates {
+    /// Template engine holding every prompt template
+    tera: Tera,
+
+    /// Source of each template, from which provenance digests are computed
+    sources: BTreeMap<String, String>,
+
+    /// Artifact contents exposed to templates as `artifacts`
+    pub artifacts: BTreeMap<String, String>,
+
+    /// Artifact IDs in dependency order, exposed to templates as `artifact_order`
+    pub artifact_order: Vec<String>,
+
+    /// Sample scenes exposed to templates as `exemplars`
+    pub exemplars: Vec<String>,
+
+    /// Style guide exposed to templates as `style`, which new scenes are checked against
+    pub style: Option<StyleGuide>,
+
+    /// Character sheets, of which those mentioned in the previous scene are
+    /// exposed to the continuation template as `characters`
+    pub character_sheets: Vec<CharacterSheet>,
+
+    /// Index of the `WorldBuilding` artifacts, searched for the snippets
+    /// exposed to templates as `lore`
+    pub lore: LoreIndex,
+
+    /// Artifacts laid out by their processors, exposed to templates as `custom_artifacts`
+    pub custom_artifacts: Vec<String>,
+
+    /// Processors that post-process every scene, each with its artifact
+    pub processed: Vec<(Arc<dyn ArtifactProcessor>, Artifact)>,
+}
+
+impl Default for PromptTemplates {
+    fn default() -> Self {
+        let mut tera = Tera::default();
+        tera.autoescape_on(vec![]);
+        tera.add_raw_templates(BUILT_IN_TEMPLATES).expect("built-in prompt templates are valid");
+        Self {
+            tera,
+            sources: BUILT_IN_TEMPLATES
+                .into_iter()
+                .map(|(name, source)| (name.to_string(), source.to_string()))
+                .collect(),
+            artifacts: BTreeMap::new(),
+            artifact_order: Vec::new(),
+            exemplars: Vec::new(),
+            style: None,
+            character_sheets: Vec::new(),
+            lore: LoreIndex::default(),
+            custom_artifacts: Vec::new(),
+            processed: Vec::new(),
+        }
+    }
+}
+
+impl PromptTemplates {
+    /// Loads the built-in templates, overridden by any `<name>.tera`, such
+    /// as `initial.tera` or `continuation.tera`, found in the given directory
+    ///
+    /// # Arguments
+    /// * `dir` - Directory containing the custom templates
+    pub fn from_dir(dir: &str) -> Result<Self, StoryChainError> {
+        let mut templates = Self::default();
+        for (name, _) in BUILT_IN_TEMPLATES {
+            let path = Path::new(dir).join(format!("{}.tera", name));
+            if path.exists() {
+                templates.set_template(PromptTemplate::from_file(name, &path)?)?;
+            }
+        }
+        Ok(templates)
+    }
+
+    /// Replaces one of the templates
+    ///
+    /// # Arguments
+    /// * `template` - The template to use instead of the current one
+    pub fn set_template(&mut self, template: PromptTemplate) -> Result<(), StoryChainError> {
+        info!("Using custom {} prompt template", template.name);
+        self.tera.add_raw_template(&template.name, &template.source).map_err(template_error)?;
+        self.sources.insert(template.name, template.source);
+        Ok(())
+    }
+
+    /// Returns a short digest of a template's source, which tells apart
+    /// scenes generated with different versions of a custom template
+    ///
+    /// # Arguments
+    /// * `name` - Name of the template, such as `initial` or `continuation`
+    ///
+    /// # Returns
+    /// The first 16 hex digits of the source's SHA-256 digest, or `None`
+    /// for an unknown template
+    pub fn template_digest(&self, name: &str) -> Option<String> {
+        let source = self.sources.get(name)?;
+        Some(Sha256::digest(source.as_bytes()).iter().take(8).map(|b| format!("{:02x}", b)).collect())
+    }
+
+    /// Exposes every loaded artifact to the templates under `artifacts`, with
+    /// their dependency order under `artifact_order`, the
+    /// `Exemplar` artifacts as few-shot examples under `exemplars`, the
+    /// `StyleGuide` artifact under `style`, the
+    /// `CharacterSheet` artifacts under `characters`, and the `WorldBuilding`
+    /// artifacts, indexed for retrieval, under `lore`
+    ///
+    /// # Arguments
+    /// * `artifact_manager` - The artifact manager to read artifacts from
+    pub fn add_artifacts(&mut self, artifact_manager: &ArtifactManager) {
+        let ordered = ordered_artifacts(artifact_manager);
+        for artifact in &ordered {
+            self.artifacts.insert(artifact.id.clone(), artifact.content.clone());
+            self.artifact_order.retain(|id| *id != artifact.id);
+            self.artifact_order.push(artifact.id.clone());
+        }
+        for exemplar in ordered {
+            if exemplar.artifact_type == ArtifactType::Exemplar {
+                let text: String = exemplar.content.trim().chars().take(MAX_EXEMPLAR_CHARS).collect();
+                self.exemplars.push(text);
+            }
+        }
+        if let Some(style) = StyleGuide::from_artifacts(artifact_manager) {
+            self.style = Some(style);
+        }
+        self.character_sheets.extend(artifact_manager.character_sheets());
+        self.lore.add_artifacts(artifact_manager);
+        debug!(
+            "Exposed {} artifacts, {} exemplars, and {} character sheets to prompt templates",
+            self.artifacts.len(),
+            self.exemplars.len(),
+            self.character_sheets.len()
+        );
+    }
+
+    /// Lays out the character sheets of the characters mentioned in a scene
+    ///
+    /// # Arguments
+    /// * `scene` - The scene text to look for names in
+    ///
+    /// # Returns
+    /// The sheets, separated by blank lines, or an empty string when no
+    /// character with a sheet is mentioned
+    pub fn character_section(&self, scene: &str) -> String {
+        self.character_sheets
+            .iter()
+            .filter(|sheet| sheet.is_mentioned_in(scene))
+            .map(CharacterSheet::to_prompt_section)
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    /// Renders a template with the given variables, adding the artifacts
+    ///
+    /// # Arguments
+    /// * `name` - Name of the template to render
+    /// * `context` - Variables for the template
+    pub fn render(&self, name: &str, context: &Context) -> Result<String, StoryChainError> {
+        let mut context = context.clone();
+        context.insert("artifacts", &self.artifacts);
+        context.insert("artifact_order", &self.artifact_order);
+        context.insert("exemplars", &self.exemplars);
+        context.insert("style", &self.style.as_ref().map(StyleGuide::to_prompt_section).unwrap_or_default());
+        context.insert("custom_artifacts", &self.custom_artifacts);
+        self.tera.render(name, &context).map_err(template_error)
+    }
+
+    /// Renders the prompt for the opening scene
+    ///
+    /// # Arguments
+    /// * `premise` - The story premise
+    /// * `instructions` - Extra instructions such as the format and genre
+    pub fn initial_prompt(&self, premise: &str, instructions: &[String]) -> Result<String, StoryChainError> {
+        let mut context = Context::new();
+        context.insert("premise", premise);
+        context.insert("lore", &self.lore.lore_section(premise));
+        context.insert("instructions", instructions);
+        self.render(INITIAL, &context)
+    }
+}
+
+impl StoryChain {
+    /// Renders one of the chain's prompt templates with the given variables,
+    /// which take precedence over the chain metadata also exposed to it
+    ///
+    /// # Arguments
+    /// * `name` - Name of the template to render
+    /// * `variables` - Variables for the template
+    pub(crate) fn render_prompt(&self, name: &str, variables: Context) -> Result<String, StoryChainError> {
+        let mut context = Context::new();
+        insert_metadata(&mut context, &self.metadata);
+        context.extend(variables);
+        self.prompts.render(name, &context)
+    }
+}
+
+/// Returns the artifacts in dependency order, or in ID order when their
+/// references form a cycle
+pub(crate) fn ordered_artifacts(artifact_manager: &ArtifactManager) -> Vec<&Artifact> {
+    artifact_manager.topological_order().unwrap_or_else(|e| {
+        warn!("{}; using the artifacts in ID order", e);
+        artifact_manager.get_all_artifacts()
+    })
+}
+
+/// Adds chain metadata to a template context, both as `metadata` and as
+/// top-level variables that do not clash with the documented ones
+///
+/// # Arguments
+/// * `context` - The context to extend
+/// * `metadata` - The chain metadata
+pub(crate) fn insert_metadata(context: &mut Context, metadata: &HashMap<String, String>) {
+    for (key, value) in metadata {
+        if !RESERVED_VARIABLES.contains(&key.as_str()) {
+            context.insert(key.as_str(), value);
+        }
+    }
+    context.insert("metadata", metadata);
+}
@@ -0,0 +1,160 @@
+//! Node Provenance
+//!
+//! This module records how every generated scene was made: the provider and
+//! model that wrote it, the prompt template and a digest of its source, the
+//! sampling parameters, when it was generated, how long it took and how many
+//! tokens it used, and the revision passes it went through afterwards.
+//! Provenance is kept in its own structure on each node instead of the
+//! free-form metadata map, so exports and `storychain inspect --node` can
+//! report it exactly.
+
+use serde::{Deserialize, Serialize};
+use crate::prompts::PromptTemplates;
+use crate::{AIProvider, GenerationConfig, TokenUsage};
+
+/// Revision recorded when the dialogue pass rewrote a scene
+pub const DIALOGUE_REVISION: &str = "dialogue";
+
+/// Revision recorded when a scene was lengthened or shortened to its target
+pub const LENGTH_REVISION: &str = "length";
+
+/// Revision recorded when the consistency check rewrote a scene
+pub const CONSISTENCY_REVISION: &str = "consistency";
+
+/// Revision recorded when the content filters regenerated or redacted a scene
+pub const FILTER_REVISION: &str = "content_filter";
+
+/// Revision recorded when a scene was rewritten from an editor's evaluation
+pub const EDITOR_REVISION: &str = "editor";
+
+/// Revision recorded when a scene was edited by hand
+pub const EDIT_REVISION: &str = "edit";
+
+/// Revision recorded when a scene was translated into another language
+pub const TRANSLATION_REVISION: &str = "translation";
+
+/// How a node's scene was generated
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct NodeProvenance {
+    /// Name of the provider that generated the scene, such as `ollama-http`
+    pub provider: String,
+
+    /// Model the provider generated with, if it reported one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+
+    /// Prompt template the scene was generated from, such as `continuation`
+    pub template: String,
+
+    /// Digest of the template's source, telling apart versions of a custom template
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub template_digest: Option<String>,
+
+    /// Whether the request carried a system prompt
+    pub system_prompt: bool,
+
+    /// Sampling parameters sent with the request
+    pub generation: GenerationConfig,
+
+    /// When the scene was generated, in RFC 3339 format
+    pub created_at: String,
+
+    /// How long generating the scene took, revisions included, in milliseconds
+    pub duration_ms: u64,
+
+    /// Revision passes applied after generation, in order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub revisions: Vec<String>,
+
+    /// Tokens spent on the scene, revisions and checks included, when the provider counts them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<TokenUsage>,
+}
+
+impl NodeProvenance {
+    /// Records a scene generated now
+    ///
+    /// # Arguments
+    /// * `ai_provider` - The provider that generated the scene
+    /// * `templates` - The chain's prompt templates
+    /// * `template` - Name of the template the prompt was rendered from
+    /// * `system_prompt` - The system prompt sent with the request, if any
+    /// * `generation` - The sampling parameters sent with the request
+    pub fn new(
+        ai_provider: &dyn AIProvider,
+        templates: &PromptTemplates,
+        template: &str,
+        system_prompt: Option<&str>,
+        generation: &GenerationConfig,
+    ) -> Self {
+        Self {
+            provider: ai_provider.provider_name().to_string(),
+            model: ai_provider.model_name().map(str::to_string),
+            template: template.to_string(),
+            template_digest: templates.template_digest(template),
+            system_prompt: system_prompt.is_some_and(|s| !s.trim().is_empty()),
+            generation: generation.clone(),
+            created_at: chrono::Utc::now().to_rfc3339(),
+            duration_ms: 0,
+            revisions: Vec::new(),
+            usage: None,
+        }
+    }
+
+    /// Sets how long generating the scene took
+    ///
+    /// # Arguments
+    /// * `duration` - Time from the request to the finished scene
+    pub fn with_duration(mut self, duration: std::time::Duration) -> Self {
+        self.duration_ms = duration.as_millis() as u64;
+        self
+    }
+
+    /// Sets the tokens spent on the scene from the provider's totals
+    ///
+    /// # Arguments
+    /// * `before` - The provider's usage before the scene was generated
+    /// * `after` - The provider's usage once the scene was finished
+    pub fn with_usage(mut self, before: Option<TokenUsage>, after: Option<TokenUsage>) -> Self {
+        self.usage = after.map(|after| after.since(&before.unwrap_or_default()));
+        self
+    }
+
+    /// Records a revision pass applied to the scene
+    ///
+    /// # Arguments
+    /// * `revision` - Name of the pass, such as `dialogue`
+    pub fn revised(&mut self, revision: &str) {
+        self.revisions.push(revision.to_string());
+    }
+
+    /// Returns the provenance as lines of `key: value` for inspection
+    pub fn to_lines(&self) -> Vec<String> {
+        let mut lines = vec![format!("provider: {}", self.provider)];
+        if let Some(model) = &self.model {
+            lines.push(format!("model: {}", model));
+        }
+        lines.push(match &self.template_digest {
+            Some(digest) => format!("template: {} ({})", self.template, digest),
+            None => format!("template: {}", self.template),
+        });
+        lines.push(format!("system prompt: {}", if self.system_prompt { "yes" } else { "no" }));
+        if !self.generation.is_empty() {
+            let generation = serde_json::to_string(&self.generation).unwrap_or_default();
+            lines.push(format!("generation: {}", generation));
+        }
+        lines.push(format!("created at: {}", self.created_at));
+        lines.push(format!("duration: {} ms", self.duration_ms));
+        if let Some(usage) = &self.usage {
+            lines.push(format!(
+                "tokens: {} prompt + {} completion over {} requests",
+                usage.prompt_tokens, usage.completion_tokens, usage.requests
+            ));
+        }
+        if !self.revisions.is_empty() {
+            lines.push(format!("revisions: {}", self.revisions.join(", ")));
+        }
+        lines
+    }
+}
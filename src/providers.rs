@@ -0,0 +1,1151 @@
+//! AI Providers
+//!
+//! This module holds AI providers that talk to model servers directly over
+//! HTTP instead of shelling out to a command-line client, and wrappers that
+//! add behaviour to any provider. Every provider returns the same
+//! (reasoning, content) tuple as `DeepseekProvider`.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use sha2::{Digest, Sha256};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use log::{info, debug, error, warn};
+use crate::parsers::{ResponseParser, ThinkTagParser};
+use crate::{prepend_system_prompt, AIProvider, GenerationConfig, StoryChainError, TokenUsage};
+
+/// Default host of a local Ollama server
+pub const DEFAULT_OLLAMA_HOST: &str = "localhost";
+
+/// Default port of a local Ollama server
+pub const DEFAULT_OLLAMA_PORT: u16 = 11434;
+
+/// Implementation of AIProvider using Ollama's `/api/generate` endpoint
+pub struct OllamaHttpProvider {
+    /// HTTP client used for requests
+    client: reqwest::Client,
+
+    /// Base URL of the Ollama server, e.g. `http://localhost:11434`
+    base_url: String,
+
+    /// Name of the model to generate with
+    model: String,
+
+    /// How long Ollama keeps the model loaded after a request, e.g. `10m`
+    keep_alive: Option<String>,
+
+    /// Default sampling parameters, overridden by those of each request
+    options: GenerationConfig,
+
+    /// System prompt sent with every request
+    system_prompt: Option<String>,
+
+    /// API key sent as a bearer token
+    api_key: Option<String>,
+
+    /// Splits responses into reasoning and content
+    parser: Arc<dyn ResponseParser>,
+
+    /// Tokens spent so far, as reported by Ollama
+    usage: std::sync::Mutex<TokenUsage>,
+}
+
+impl OllamaHttpProvider {
+    /// Creates a new OllamaHttpProvider talking to a local Ollama server
+    ///
+    /// # Arguments
+    /// * `model` - Name of the model to generate with
+    pub fn new(model: String) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            base_url: format!("http://{}:{}", DEFAULT_OLLAMA_HOST, DEFAULT_OLLAMA_PORT),
+            model,
+            keep_alive: None,
+            options: GenerationConfig::default(),
+            system_prompt: None,
+            api_key: None,
+            parser: Arc::new(ThinkTagParser),
+            usage: std::sync::Mutex::new(TokenUsage::default()),
+        }
+    }
+
+    /// Sets the host and port of the Ollama server
+    pub fn with_host(mut self, host: &str, port: u16) -> Self {
+        self.base_url = format!("http://{}:{}", host, port);
+        self
+    }
+
+    /// Sets the base URL of the Ollama server, e.g. `https://ollama.example.com`
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Sets how long Ollama keeps the model loaded after a request, e.g. `10m` or `-1`
+    pub fn with_keep_alive(mut self, keep_alive: String) -> Self {
+        self.keep_alive = Some(keep_alive);
+        self
+    }
+
+    /// Sets the default sampling parameters, overridden by those of each request
+    pub fn with_options(mut self, options: GenerationConfig) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Sets the system prompt sent with every request
+    pub fn with_system_prompt(mut self, system_prompt: String) -> Self {
+        self.system_prompt = Some(system_prompt);
+        self
+    }
+
+    /// Sets an API key sent as a bearer token, for servers behind an authenticating proxy
+    pub fn with_api_key(mut self, api_key: String) -> Self {
+        self.api_key = Some(api_key);
+        self
+    }
+
+    /// Sets how responses are split into reasoning and content, `<think>` tags by default
+    pub fn with_parser(mut self, parser: Arc<dyn ResponseParser>) -> Self {
+        self.parser = parser;
+        self
+    }
+
+    /// Sends one request to `/api/generate` and returns the response
+    ///
+    /// # Arguments
+    /// * `system_prompt` - Optional system prompt for this request
+    /// * `prompt` - The prompt to send
+    /// * `config` - Sampling parameters of this request
+    /// * `stream` - Whether Ollama should stream the response as JSON lines
+    async fn send(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+        stream: bool,
+    ) -> Result<reqwest::Response, StoryChainError> {
+        let url = format!("{}/api/generate", self.base_url);
+        info!("Sending request to {} for model: {}", url, self.model);
+        debug!("Prompt: {}", prompt);
+
+        let mut body = json!({ "model": self.model, "prompt": prompt, "stream": stream });
+        if let Some(system_prompt) = system_prompt.filter(|s| !s.trim().is_empty()) {
+            body["system"] = json!(system_prompt);
+        }
+        if let Some(keep_alive) = &self.keep_alive {
+            body["keep_alive"] = json!(keep_alive);
+        }
+        let options = config.merged_over(&self.options);
+        if !options.is_empty() {
+            body["options"] = options.to_ollama_options();
+        }
+
+        let mut request = self.client.post(&url).json(&body);
+        if let Some(api_key) = &self.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        let response = request.send().await.map_err(|e| {
+            error!("Ollama request failed: {}", e);
+            StoryChainError::AIServerError(format!("Ollama request failed: {}", e))
+        })?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let text = response.text().await.unwrap_or_default();
+            error!("Ollama request failed with {}: {}", status, text);
+            return Err(StoryChainError::AIServerError(format!("Ollama request failed with {}: {}", status, text)));
+        }
+        Ok(response)
+    }
+
+    /// Sends one request to `/api/generate` and returns the raw response text
+    ///
+    /// # Arguments
+    /// * `system_prompt` - Optional system prompt for this request
+    /// * `prompt` - The prompt to send
+    /// * `config` - Sampling parameters of this request
+    async fn request(&self, system_prompt: Option<&str>, prompt: &str, config: &GenerationConfig) -> Result<String, StoryChainError> {
+        let response = self.send(system_prompt, prompt, config, false).await?;
+        let body: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| StoryChainError::AIServerError(format!("Invalid Ollama response: {}", e)))?;
+        let text = body["response"]
+            .as_str()
+            .ok_or_else(|| StoryChainError::AIServerError("Ollama response has no text".to_string()))?;
+        debug!("Raw AI response: {}", text);
+        self.record_usage(&body);
+        Ok(join_thinking(body["thinking"].as_str().unwrap_or_default(), text))
+    }
+
+    /// Adds the token counts Ollama reports in a finished response
+    ///
+    /// # Arguments
+    /// * `body` - The response, or the last line of a streamed one
+    fn record_usage(&self, body: &serde_json::Value) {
+        let prompt_tokens = body["prompt_eval_count"].as_u64().unwrap_or_default();
+        let completion_tokens = body["eval_count"].as_u64().unwrap_or_default();
+        *self.usage.lock().unwrap() += TokenUsage::request(prompt_tokens, completion_tokens);
+    }
+
+    /// Combines the system prompt of a request with the provider's own
+    fn combined_system_prompt(&self, system_prompt: Option<&str>) -> Option<String> {
+        match (&self.system_prompt, system_prompt) {
+            (Some(own), Some(system_prompt)) => Some(prepend_system_prompt(own, system_prompt)),
+            (own, system_prompt) => own.clone().or(system_prompt.map(str::to_string)),
+        }
+    }
+}
+
+/// Rejoins reasoning returned in a separate `thinking` field into `<think>` tags
+fn join_thinking(thinking: &str, text: &str) -> String {
+    if thinking.trim().is_empty() {
+        text.to_string()
+    } else {
+        format!("<think>{}</think>\n{}", thinking, text)
+    }
+}
+
+#[async_trait::async_trait]
+impl AIProvider for OllamaHttpProvider {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        self.generate_with_config(None, prompt, &GenerationConfig::default()).await
+    }
+
+    /// Sends the system prompt in Ollama's `system` field, after the provider's own system prompt if any
+    async fn generate_with_system(&self, system_prompt: &str, prompt: &str) -> Result<(String, String), StoryChainError> {
+        self.generate_with_config(Some(system_prompt), prompt, &GenerationConfig::default()).await
+    }
+
+    /// Sends the parameters as Ollama options, over the provider's defaults
+    async fn generate_with_config(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+    ) -> Result<(String, String), StoryChainError> {
+        let response = self.request(self.combined_system_prompt(system_prompt).as_deref(), prompt, config).await?;
+        self.parser.parse(&response)
+    }
+
+    /// Streams the JSON lines of Ollama's response, forwarding each piece of
+    /// reasoning and text as it arrives
+    async fn generate_stream(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+        tokens: UnboundedSender<String>,
+    ) -> Result<(String, String), StoryChainError> {
+        let mut response = self.send(self.combined_system_prompt(system_prompt).as_deref(), prompt, config, true).await?;
+
+        let mut pending = Vec::new();
+        let (mut thinking, mut text) = (String::new(), String::new());
+        let mut handle_line = |line: &[u8]| -> Result<(), StoryChainError> {
+            if line.iter().all(u8::is_ascii_whitespace) {
+                return Ok(());
+            }
+            let chunk: serde_json::Value = serde_json::from_slice(line)?;
+            if let Some(message) = chunk["error"].as_str() {
+                return Err(StoryChainError::AIServerError(format!("Ollama stream failed: {}", message)));
+            }
+            for (field, target) in [("thinking", &mut thinking), ("response", &mut text)] {
+                if let Some(piece) = chunk[field].as_str().filter(|p| !p.is_empty()) {
+                    target.push_str(piece);
+                    let _ = tokens.send(piece.to_string());
+                }
+            }
+            if chunk["done"].as_bool() == Some(true) {
+                self.record_usage(&chunk);
+            }
+            Ok(())
+        };
+
+        while let Some(bytes) = response
+            .chunk()
+            .await
+            .map_err(|e| StoryChainError::AIServerError(format!("Ollama stream failed: {}", e)))?
+        {
+            pending.extend_from_slice(&bytes);
+            while let Some(end) = pending.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = pending.drain(..=end).collect();
+                handle_line(&line)?;
+            }
+        }
+        handle_line(&pending)?;
+
+        let response = join_thinking(&thinking, &text);
+        debug!("Raw AI response: {}", response);
+        self.parser.parse(&response)
+    }
+
+    fn provider_name(&self) -> &str {
+        "ollama-http"
+    }
+
+    fn model_name(&self) -> Option<&str> {
+        Some(&self.model)
+    }
+
+    fn usage(&self) -> Option<TokenUsage> {
+        Some(*self.usage.lock().unwrap())
+    }
+}
+
+/// Wraps a provider so that every response is streamed to a callback as it
+/// is generated, e.g. to print it live
+pub struct StreamingProvider<P: AIProvider> {
+    /// The provider generating the responses
+    inner: P,
+
+    /// Called with each piece of every response
+    on_token: Box<dyn Fn(&str) + Send + Sync>,
+}
+
+impl<P: AIProvider> StreamingProvider<P> {
+    /// Creates a new StreamingProvider
+    ///
+    /// # Arguments
+    /// * `inner` - The provider generating the responses
+    /// * `on_token` - Called with each piece of every response, and with a
+    ///   blank line when a response is complete
+    pub fn new(inner: P, on_token: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        Self { inner, on_token: Box::new(on_token) }
+    }
+
+    /// Streams one response to the callback while it is generated
+    async fn stream(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+    ) -> Result<(String, String), StoryChainError> {
+        let (sender, mut receiver) = unbounded_channel();
+        let generation = async move { self.inner.generate_stream(system_prompt, prompt, config, sender).await };
+        let printing = async {
+            while let Some(token) = receiver.recv().await {
+                (self.on_token)(&token);
+            }
+            (self.on_token)("\n\n");
+        };
+        let (result, _) = tokio::join!(generation, printing);
+        result
+    }
+}
+
+#[async_trait::async_trait]
+impl<P: AIProvider> AIProvider for StreamingProvider<P> {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        self.stream(None, prompt, &GenerationConfig::default()).await
+    }
+
+    async fn generate_with_system(&self, system_prompt: &str, prompt: &str) -> Result<(String, String), StoryChainError> {
+        self.stream(Some(system_prompt), prompt, &GenerationConfig::default()).await
+    }
+
+    async fn generate_with_config(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+    ) -> Result<(String, String), StoryChainError> {
+        self.stream(system_prompt, prompt, config).await
+    }
+
+    async fn generate_stream(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+        tokens: UnboundedSender<String>,
+    ) -> Result<(String, String), StoryChainError> {
+        self.inner.generate_stream(system_prompt, prompt, config, tokens).await
+    }
+
+    fn provider_name(&self) -> &str {
+        self.inner.provider_name()
+    }
+
+    fn model_name(&self) -> Option<&str> {
+        self.inner.model_name()
+    }
+
+    fn usage(&self) -> Option<TokenUsage> {
+        self.inner.usage()
+    }
+}
+
+/// Default number of attempts per request, including the first
+pub const DEFAULT_MAX_ATTEMPTS: usize = 3;
+
+/// How long to wait between attempts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backoff {
+    /// The same delay before every retry
+    Fixed(Duration),
+
+    /// A delay that doubles after every retry, up to a maximum
+    Exponential {
+        /// Delay before the first retry
+        initial: Duration,
+
+        /// Longest delay between two attempts
+        max: Duration,
+    },
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff::Exponential { initial: Duration::from_secs(2), max: Duration::from_secs(60) }
+    }
+}
+
+impl Backoff {
+    /// Returns the delay before the given retry
+    ///
+    /// # Arguments
+    /// * `retry` - Number of the retry, starting at 1
+    pub fn delay(&self, retry: usize) -> Duration {
+        match *self {
+            Backoff::Fixed(delay) => delay,
+            Backoff::Exponential { initial, max } => {
+                let factor = 2u32.saturating_pow(retry.saturating_sub(1).min(31) as u32);
+                initial.saturating_mul(factor).min(max)
+            }
+        }
+    }
+}
+
+/// Returns whether an error may go away when the request is repeated
+///
+/// Server errors, malformed responses, and timeouts are treated as
+/// transient; errors in the request itself or on the local side are not.
+///
+/// # Arguments
+/// * `error` - The error a request failed with
+pub fn is_transient(error: &StoryChainError) -> bool {
+    matches!(
+        error,
+        StoryChainError::AIServerError(_) | StoryChainError::InvalidReasoningFormat(_) | StoryChainError::Timeout(_)
+    )
+}
+
+/// Wraps a provider so that requests the model does not answer in time fail
+/// with `StoryChainError::Timeout` instead of stalling the run
+pub struct TimeoutProvider<P: AIProvider> {
+    /// The provider answering the requests
+    inner: P,
+
+    /// Longest time a request may take, its whole response included
+    timeout: Duration,
+}
+
+impl<P: AIProvider> TimeoutProvider<P> {
+    /// Creates a new TimeoutProvider
+    ///
+    /// # Arguments
+    /// * `inner` - The provider answering the requests
+    /// * `timeout` - Longest time a request may take, its whole response included
+    pub fn new(inner: P, timeout: Duration) -> Self {
+        Self { inner, timeout }
+    }
+
+    /// Runs a request, dropping it when it takes longer than the timeout
+    async fn limit(
+        &self,
+        request: impl Future<Output = Result<(String, String), StoryChainError>>,
+    ) -> Result<(String, String), StoryChainError> {
+        match tokio::time::timeout(self.timeout, request).await {
+            Ok(result) => result,
+            Err(_) => {
+                warn!("Request to {} timed out after {:?}", self.inner.provider_name(), self.timeout);
+                Err(StoryChainError::Timeout(self.timeout))
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<P: AIProvider> AIProvider for TimeoutProvider<P> {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        self.limit(self.inner.generate(prompt)).await
+    }
+
+    async fn generate_with_system(&self, system_prompt: &str, prompt: &str) -> Result<(String, String), StoryChainError> {
+        self.limit(self.inner.generate_with_system(system_prompt, prompt)).await
+    }
+
+    async fn generate_with_config(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+    ) -> Result<(String, String), StoryChainError> {
+        self.limit(self.inner.generate_with_config(system_prompt, prompt, config)).await
+    }
+
+    async fn generate_stream(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+        tokens: UnboundedSender<String>,
+    ) -> Result<(String, String), StoryChainError> {
+        self.limit(self.inner.generate_stream(system_prompt, prompt, config, tokens)).await
+    }
+
+    fn provider_name(&self) -> &str {
+        self.inner.provider_name()
+    }
+
+    fn model_name(&self) -> Option<&str> {
+        self.inner.model_name()
+    }
+
+    fn usage(&self) -> Option<TokenUsage> {
+        self.inner.usage()
+    }
+}
+
+/// Wraps a provider so that requests failing with a transient error are
+/// repeated with a backoff between attempts
+pub struct RetryingProvider<P: AIProvider> {
+    /// The provider answering the requests
+    inner: P,
+
+    /// Maximum number of attempts per request, including the first
+    max_attempts: usize,
+
+    /// How long to wait between attempts
+    backoff: Backoff,
+
+    /// Decides which errors are worth retrying
+    is_retryable: fn(&StoryChainError) -> bool,
+}
+
+impl<P: AIProvider> RetryingProvider<P> {
+    /// Creates a new RetryingProvider retrying transient errors with the default backoff
+    ///
+    /// # Arguments
+    /// * `inner` - The provider answering the requests
+    pub fn new(inner: P) -> Self {
+        Self { inner, max_attempts: DEFAULT_MAX_ATTEMPTS, backoff: Backoff::default(), is_retryable: is_transient }
+    }
+
+    /// Sets the maximum number of attempts per request, including the first
+    pub fn with_max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Sets how long to wait between attempts
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets which errors are retried
+    pub fn with_retryable(mut self, is_retryable: fn(&StoryChainError) -> bool) -> Self {
+        self.is_retryable = is_retryable;
+        self
+    }
+
+    /// Runs a request until it succeeds, fails with a permanent error, or
+    /// runs out of attempts
+    async fn retry<F, Fut>(&self, mut request: F) -> Result<(String, String), StoryChainError>
+    where
+        F: FnMut() -> Fut + Send,
+        Fut: Future<Output = Result<(String, String), StoryChainError>> + Send,
+    {
+        let mut attempt = 1;
+        loop {
+            match request().await {
+                Err(e) if attempt < self.max_attempts && (self.is_retryable)(&e) => {
+                    let delay = self.backoff.delay(attempt);
+                    warn!("Attempt {} of {} failed, retrying in {:?}: {}", attempt, self.max_attempts, delay, e);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<P: AIProvider> AIProvider for RetryingProvider<P> {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        self.retry(|| self.inner.generate(prompt)).await
+    }
+
+    async fn generate_with_system(&self, system_prompt: &str, prompt: &str) -> Result<(String, String), StoryChainError> {
+        self.retry(|| self.inner.generate_with_system(system_prompt, prompt)).await
+    }
+
+    async fn generate_with_config(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+    ) -> Result<(String, String), StoryChainError> {
+        self.retry(|| self.inner.generate_with_config(system_prompt, prompt, config)).await
+    }
+
+    /// Retries the stream from the start; pieces of failed attempts have already been sent
+    async fn generate_stream(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+        tokens: UnboundedSender<String>,
+    ) -> Result<(String, String), StoryChainError> {
+        self.retry(|| self.inner.generate_stream(system_prompt, prompt, config, tokens.clone())).await
+    }
+
+    fn provider_name(&self) -> &str {
+        self.inner.provider_name()
+    }
+
+    fn model_name(&self) -> Option<&str> {
+        self.inner.model_name()
+    }
+
+    fn usage(&self) -> Option<TokenUsage> {
+        self.inner.usage()
+    }
+}
+
+/// Default directory of the response cache
+pub const DEFAULT_CACHE_DIR: &str = ".storychain_cache";
+
+/// A response stored in the cache
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedResponse {
+    /// Namespace the response was generated under
+    namespace: String,
+
+    /// System prompt of the request, if any
+    system_prompt: Option<String>,
+
+    /// Prompt of the request
+    prompt: String,
+
+    /// Sampling parameters of the request
+    #[serde(default, skip_serializing_if = "GenerationConfig::is_empty")]
+    config: GenerationConfig,
+
+    /// How many identical requests came before this one in the same run
+    #[serde(default, skip_serializing_if = "is_zero")]
+    attempt: usize,
+
+    /// Reasoning of the response
+    reasoning: String,
+
+    /// Content of the response
+    content: String,
+
+    /// When the response was generated, as a Unix timestamp
+    created_at: i64,
+}
+
+/// Returns whether a count is zero, for skipping it when serializing
+fn is_zero(count: &usize) -> bool {
+    *count == 0
+}
+
+tokio::task_local! {
+    /// Set while requests must reach the model rather than the cache
+    static SKIP_CACHE: ();
+}
+
+/// Runs a future whose requests are answered by the model even when the
+/// cache holds a response, such as regenerating a scene the author rejected
+///
+/// The new responses are still cached, for later runs that repeat the
+/// same requests.
+///
+/// # Arguments
+/// * `future` - The future making the requests
+pub async fn skipping_cache<F: Future>(future: F) -> F::Output {
+    SKIP_CACHE.scope((), future).await
+}
+
+/// Wraps a provider so that responses are stored in a cache directory,
+/// keyed by a hash of the request, and identical requests are answered from
+/// the cache instead of the model
+///
+/// A request repeated within one run, such as a scene regenerated after
+/// the quality gate rejected it or the candidates of best-of-N selection,
+/// is a new attempt rather than a cache hit: every attempt is cached under
+/// its own key, so a later run sending the same requests replays each
+/// attempt's response in turn.
+pub struct CachingProvider<P: AIProvider> {
+    /// The provider answering requests missing from the cache
+    inner: P,
+
+    /// Directory holding one JSON file per cached response
+    cache_dir: PathBuf,
+
+    /// Identifies the model and parameters, so different setups never share responses
+    namespace: String,
+
+    /// Cached responses older than this are regenerated
+    max_age: Option<chrono::Duration>,
+
+    /// How often each request has been sent so far, by its first attempt's cache entry
+    attempts: Mutex<HashMap<PathBuf, usize>>,
+}
+
+impl<P: AIProvider> CachingProvider<P> {
+    /// Creates a new CachingProvider
+    ///
+    /// # Arguments
+    /// * `inner` - The provider answering requests missing from the cache
+    /// * `cache_dir` - Directory holding the cached responses
+    /// * `namespace` - Model name and generation parameters; responses are
+    ///   only reused for the same namespace
+    pub fn new(inner: P, cache_dir: impl Into<PathBuf>, namespace: impl Into<String>) -> Self {
+        Self { inner, cache_dir: cache_dir.into(), namespace: namespace.into(), max_age: None, attempts: Mutex::default() }
+    }
+
+    /// Regenerates cached responses older than the given age
+    pub fn with_max_age(mut self, max_age: chrono::Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Returns the path of the cache entry for an attempt at a request
+    ///
+    /// The key hashes the namespace, the system prompt, and the prompt, then
+    /// the sampling parameters when any are set and the attempt when it is
+    /// not the first; nothing else about the story goes into it.
+    fn entry_path(&self, system_prompt: Option<&str>, prompt: &str, config: &GenerationConfig, attempt: usize) -> PathBuf {
+        let mut hasher = Sha256::new();
+        for part in [self.namespace.as_str(), system_prompt.unwrap_or_default(), prompt] {
+            hasher.update(part.as_bytes());
+            hasher.update([0u8]);
+        }
+        // Requests without sampling parameters hash nothing for them
+        if !config.is_empty() {
+            hasher.update(serde_json::to_string(config).unwrap_or_default().as_bytes());
+        }
+        // A first attempt hashes no attempt number, so its key is the request's own
+        if attempt > 0 {
+            hasher.update(format!("\0attempt {}", attempt).as_bytes());
+        }
+        self.cache_dir.join(format!("{:x}.json", hasher.finalize()))
+    }
+
+    /// Counts a request and returns which attempt at it this is, from 0
+    fn next_attempt(&self, system_prompt: Option<&str>, prompt: &str, config: &GenerationConfig) -> usize {
+        let mut attempts = self.attempts.lock().expect("attempt counts are not poisoned");
+        let count = attempts.entry(self.entry_path(system_prompt, prompt, config, 0)).or_default();
+        *count += 1;
+        *count - 1
+    }
+
+    /// Returns the cached response for an attempt at a request, if there is
+    /// a fresh one and the cache is not being skipped
+    fn lookup(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+        attempt: usize,
+    ) -> Option<(String, String)> {
+        if SKIP_CACHE.try_with(|_| ()).is_ok() {
+            debug!("Skipping the cache for attempt {} at a request", attempt + 1);
+            return None;
+        }
+        let path = self.entry_path(system_prompt, prompt, config, attempt);
+        let entry: CachedResponse = serde_json::from_str(&std::fs::read_to_string(&path).ok()?).ok()?;
+        if entry.namespace != self.namespace
+            || entry.prompt != prompt
+            || entry.system_prompt.as_deref() != system_prompt
+            || &entry.config != config
+            || entry.attempt != attempt
+        {
+            warn!("Ignoring cache entry {} that belongs to a different request", path.display());
+            return None;
+        }
+        if self.max_age.is_some_and(|max_age| chrono::Utc::now().timestamp() - entry.created_at > max_age.num_seconds()) {
+            debug!("Cache entry {} has expired", path.display());
+            return None;
+        }
+        debug!("Answering from cache entry {}", path.display());
+        Some((entry.reasoning, entry.content))
+    }
+
+    /// Stores the response to an attempt at a request in the cache
+    fn store(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+        attempt: usize,
+        response: &(String, String),
+    ) -> Result<(), StoryChainError> {
+        std::fs::create_dir_all(&self.cache_dir)?;
+        let entry = CachedResponse {
+            namespace: self.namespace.clone(),
+            system_prompt: system_prompt.map(str::to_string),
+            prompt: prompt.to_string(),
+            config: config.clone(),
+            attempt,
+            reasoning: response.0.clone(),
+            content: response.1.clone(),
+            created_at: chrono::Utc::now().timestamp(),
+        };
+        crate::files::write_atomic(self.entry_path(system_prompt, prompt, config, attempt), serde_json::to_string_pretty(&entry)?)?;
+        Ok(())
+    }
+
+    /// Removes the cached responses to every attempt at a request, so they
+    /// are regenerated next time
+    ///
+    /// # Arguments
+    /// * `system_prompt` - System prompt of the request, if any
+    /// * `prompt` - Prompt of the request
+    /// * `config` - Sampling parameters of the request
+    ///
+    /// # Returns
+    /// Whether any cached response was removed
+    pub fn invalidate(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+    ) -> Result<bool, StoryChainError> {
+        if !self.cache_dir.exists() {
+            return Ok(false);
+        }
+        // Attempts are keyed apart, so the entries are found by the request they hold
+        let mut removed = 0;
+        for entry in std::fs::read_dir(&self.cache_dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|e| e != "json") {
+                continue;
+            }
+            let Some(cached) = std::fs::read_to_string(&path).ok().and_then(|json| serde_json::from_str::<CachedResponse>(&json).ok()) else {
+                continue;
+            };
+            if cached.namespace == self.namespace
+                && cached.prompt == prompt
+                && cached.system_prompt.as_deref() == system_prompt
+                && &cached.config == config
+            {
+                std::fs::remove_file(path)?;
+                removed += 1;
+            }
+        }
+        debug!("Invalidated {} cached attempts at a request", removed);
+        Ok(removed > 0)
+    }
+}
+
+/// Removes every cached response from a cache directory
+///
+/// # Arguments
+/// * `cache_dir` - The cache directory
+///
+/// # Returns
+/// The number of responses removed
+pub fn clear_cache(cache_dir: impl AsRef<Path>) -> Result<usize, StoryChainError> {
+    let cache_dir = cache_dir.as_ref();
+    if !cache_dir.exists() {
+        return Ok(0);
+    }
+    let mut removed = 0;
+    for entry in std::fs::read_dir(cache_dir)? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "json") {
+            std::fs::remove_file(path)?;
+            removed += 1;
+        }
+    }
+    info!("Removed {} cached responses from {}", removed, cache_dir.display());
+    Ok(removed)
+}
+
+#[async_trait::async_trait]
+impl<P: AIProvider> AIProvider for CachingProvider<P> {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        let config = GenerationConfig::default();
+        let attempt = self.next_attempt(None, prompt, &config);
+        if let Some(response) = self.lookup(None, prompt, &config, attempt) {
+            return Ok(response);
+        }
+        let response = self.inner.generate(prompt).await?;
+        self.store(None, prompt, &config, attempt, &response)?;
+        Ok(response)
+    }
+
+    async fn generate_with_system(&self, system_prompt: &str, prompt: &str) -> Result<(String, String), StoryChainError> {
+        let config = GenerationConfig::default();
+        let attempt = self.next_attempt(Some(system_prompt), prompt, &config);
+        if let Some(response) = self.lookup(Some(system_prompt), prompt, &config, attempt) {
+            return Ok(response);
+        }
+        let response = self.inner.generate_with_system(system_prompt, prompt).await?;
+        self.store(Some(system_prompt), prompt, &config, attempt, &response)?;
+        Ok(response)
+    }
+
+    async fn generate_with_config(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+    ) -> Result<(String, String), StoryChainError> {
+        let attempt = self.next_attempt(system_prompt, prompt, config);
+        if let Some(response) = self.lookup(system_prompt, prompt, config, attempt) {
+            return Ok(response);
+        }
+        let response = self.inner.generate_with_config(system_prompt, prompt, config).await?;
+        self.store(system_prompt, prompt, config, attempt, &response)?;
+        Ok(response)
+    }
+
+    /// Sends a cached response as a single piece
+    async fn generate_stream(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+        tokens: UnboundedSender<String>,
+    ) -> Result<(String, String), StoryChainError> {
+        let attempt = self.next_attempt(system_prompt, prompt, config);
+        if let Some(response) = self.lookup(system_prompt, prompt, config, attempt) {
+            let _ = tokens.send(response.1.clone());
+            return Ok(response);
+        }
+        let response = self.inner.generate_stream(system_prompt, prompt, config, tokens).await?;
+        self.store(system_prompt, prompt, config, attempt, &response)?;
+        Ok(response)
+    }
+
+    fn provider_name(&self) -> &str {
+        self.inner.provider_name()
+    }
+
+    fn model_name(&self) -> Option<&str> {
+        self.inner.model_name()
+    }
+
+    fn usage(&self) -> Option<TokenUsage> {
+        self.inner.usage()
+    }
+}
+
+/// A prompt and the response it received
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RecordedExchange {
+    /// System prompt of the request, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+
+    /// Prompt of the request
+    pub prompt: String,
+
+    /// Sampling parameters of the request
+    #[serde(default, skip_serializing_if = "GenerationConfig::is_empty")]
+    pub config: GenerationConfig,
+
+    /// Reasoning of the response
+    pub reasoning: String,
+
+    /// Content of the response
+    pub content: String,
+}
+
+/// Wraps a provider and writes every prompt and response to a JSON file,
+/// for later replay with `ReplayProvider`
+pub struct RecordingProvider<P: AIProvider> {
+    /// The provider answering the requests
+    inner: P,
+
+    /// File the exchanges are written to
+    path: PathBuf,
+
+    /// Exchanges recorded so far, in order
+    exchanges: std::sync::Mutex<Vec<RecordedExchange>>,
+}
+
+impl<P: AIProvider> RecordingProvider<P> {
+    /// Creates a new RecordingProvider
+    ///
+    /// # Arguments
+    /// * `inner` - The provider answering the requests
+    /// * `path` - File the exchanges are written to; it is rewritten after every exchange
+    pub fn new(inner: P, path: impl Into<PathBuf>) -> Self {
+        Self { inner, path: path.into(), exchanges: std::sync::Mutex::new(Vec::new()) }
+    }
+
+    /// Returns the exchanges recorded so far
+    pub fn exchanges(&self) -> Vec<RecordedExchange> {
+        self.exchanges.lock().unwrap().clone()
+    }
+
+    /// Appends an exchange and rewrites the recording file
+    fn record(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+        response: &(String, String),
+    ) -> Result<(), StoryChainError> {
+        let mut exchanges = self.exchanges.lock().unwrap();
+        exchanges.push(RecordedExchange {
+            system_prompt: system_prompt.map(str::to_string),
+            prompt: prompt.to_string(),
+            config: config.clone(),
+            reasoning: response.0.clone(),
+            content: response.1.clone(),
+        });
+        crate::files::write_atomic(&self.path, serde_json::to_string_pretty(&*exchanges)?)?;
+        debug!("Recorded exchange {} to {}", exchanges.len(), self.path.display());
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl<P: AIProvider> AIProvider for RecordingProvider<P> {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        let response = self.inner.generate(prompt).await?;
+        self.record(None, prompt, &GenerationConfig::default(), &response)?;
+        Ok(response)
+    }
+
+    async fn generate_with_system(&self, system_prompt: &str, prompt: &str) -> Result<(String, String), StoryChainError> {
+        let response = self.inner.generate_with_system(system_prompt, prompt).await?;
+        self.record(Some(system_prompt), prompt, &GenerationConfig::default(), &response)?;
+        Ok(response)
+    }
+
+    async fn generate_with_config(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+    ) -> Result<(String, String), StoryChainError> {
+        let response = self.inner.generate_with_config(system_prompt, prompt, config).await?;
+        self.record(system_prompt, prompt, config, &response)?;
+        Ok(response)
+    }
+
+    async fn generate_stream(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+        tokens: UnboundedSender<String>,
+    ) -> Result<(String, String), StoryChainError> {
+        let response = self.inner.generate_stream(system_prompt, prompt, config, tokens).await?;
+        self.record(system_prompt, prompt, config, &response)?;
+        Ok(response)
+    }
+
+    fn provider_name(&self) -> &str {
+        self.inner.provider_name()
+    }
+
+    fn model_name(&self) -> Option<&str> {
+        self.inner.model_name()
+    }
+
+    fn usage(&self) -> Option<TokenUsage> {
+        self.inner.usage()
+    }
+}
+
+/// Provider that answers with recorded responses, in the order they were
+/// recorded, without contacting any model
+pub struct ReplayProvider {
+    /// The recorded exchanges
+    exchanges: Vec<RecordedExchange>,
+
+    /// Index of the next exchange to replay
+    position: std::sync::Mutex<usize>,
+
+    /// Fail when a request differs from the recorded one
+    check_prompts: bool,
+}
+
+impl ReplayProvider {
+    /// Creates a new ReplayProvider that checks every request against the recording
+    ///
+    /// # Arguments
+    /// * `exchanges` - The exchanges to replay, in order
+    pub fn new(exchanges: Vec<RecordedExchange>) -> Self {
+        Self { exchanges, position: std::sync::Mutex::new(0), check_prompts: true }
+    }
+
+    /// Loads a recording written by `RecordingProvider`
+    ///
+    /// # Arguments
+    /// * `path` - The recording file
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, StoryChainError> {
+        let exchanges = serde_json::from_str(&std::fs::read_to_string(path.as_ref())?)?;
+        info!("Loaded recording {}", path.as_ref().display());
+        Ok(Self::new(exchanges))
+    }
+
+    /// Sets whether requests must match the recorded prompts; when off, the
+    /// recorded responses are returned in order whatever was asked
+    pub fn with_prompt_check(mut self, check_prompts: bool) -> Self {
+        self.check_prompts = check_prompts;
+        self
+    }
+
+    /// Returns the number of recorded exchanges not replayed yet
+    pub fn remaining(&self) -> usize {
+        self.exchanges.len() - *self.position.lock().unwrap()
+    }
+
+    /// Returns the next recorded response, checking it was recorded for this request
+    fn replay(&self, system_prompt: Option<&str>, prompt: &str) -> Result<(String, String), StoryChainError> {
+        let mut position = self.position.lock().unwrap();
+        let exchange = self.exchanges.get(*position).ok_or_else(|| {
+            StoryChainError::AIServerError(format!("Recording exhausted after {} exchanges", self.exchanges.len()))
+        })?;
+
+        if self.check_prompts && exchange.system_prompt.as_deref() != system_prompt {
+            return Err(StoryChainError::AIServerError(format!(
+                "Request {} has a different system prompt than the recording",
+                *position + 1
+            )));
+        }
+        if self.check_prompts && exchange.prompt != prompt {
+            let differs_at = exchange.prompt.chars().zip(prompt.chars()).take_while(|(a, b)| a == b).count();
+            return Err(StoryChainError::AIServerError(format!(
+                "Request {} differs from the recording at character {}: expected {:?}, got {:?}",
+                *position + 1,
+                differs_at,
+                exchange.prompt.chars().skip(differs_at).take(60).collect::<String>(),
+                prompt.chars().skip(differs_at).take(60).collect::<String>()
+            )));
+        }
+
+        *position += 1;
+        Ok((exchange.reasoning.clone(), exchange.content.clone()))
+    }
+}
+
+#[async_trait::async_trait]
+impl AIProvider for ReplayProvider {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        self.replay(None, prompt)
+    }
+
+    async fn generate_with_system(&self, system_prompt: &str, prompt: &str) -> Result<(String, String), StoryChainError> {
+        self.replay(Some(system_prompt), prompt)
+    }
+
+    fn provider_name(&self) -> &str {
+        "replay"
+    }
+}
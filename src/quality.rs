@@ -0,0 +1,279 @@
+//! Quality Gate
+//!
+//! This module has a critic model score every generated main-plot scene from
+//! 0 to 10. Scenes scoring below the configured threshold are thrown away and
+//! regenerated, up to a retry limit. The score, the number of attempts, and
+//! the scores of the discarded attempts are recorded in node metadata and
+//! summarized in the quality report.
+
+use serde::{Deserialize, Serialize};
+use log::{info, debug, warn};
+use tera::Context;
+use crate::passes::parse_labeled_fields;
+use crate::prompts::PromptTemplates;
+use crate::{AIProvider, StoryChain, StoryChainError};
+
+/// Name of the scene critique prompt template
+pub const CRITIQUE: &str = "critique";
+
+/// Built-in template of the scene critique prompt
+///
+/// Variables: `premise`, the story premise, empty when there is none;
+/// `previous_scene`, the scene the new one follows; `scene`, the new scene.
+pub const CRITIQUE_TEMPLATE: &str = r#"You are a demanding fiction editor. Score the new scene below from 0 to 10 for prose quality, pacing, and continuity with the previous scene{% if premise %} and the premise{% endif %}.
+
+{% if premise %}Story Premise:
+{{ premise }}
+
+{% endif %}Previous Scene:
+{{ previous_scene }}
+
+New Scene:
+{{ scene }}
+
+IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your assessment of the scene.
+</think>
+SCORE: A number from 0 to 10
+NOTES: The main reasons for the score"#;
+
+/// Minimum score used when none is configured
+pub const DEFAULT_MIN_SCORE: f64 = 6.0;
+
+/// Number of regenerations used when none is configured
+pub const DEFAULT_MAX_RETRIES: usize = 2;
+
+/// Threshold and retry limit of the quality gate
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct QualityGate {
+    /// Scenes scoring below this (0-10) are regenerated
+    pub min_score: f64,
+
+    /// Maximum number of regenerations per scene
+    pub max_retries: usize,
+}
+
+impl Default for QualityGate {
+    fn default() -> Self {
+        Self { min_score: DEFAULT_MIN_SCORE, max_retries: DEFAULT_MAX_RETRIES }
+    }
+}
+
+/// A critic's verdict on a scene
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct SceneCritique {
+    /// Score from 0 (unusable) to 10 (excellent)
+    pub score: f64,
+
+    /// The critic's reasons for the score
+    pub notes: String,
+}
+
+/// Quality gate outcome for a single scene
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct QualityRecord {
+    /// ID of the node
+    pub node_id: String,
+
+    /// Score of the kept scene
+    pub score: f64,
+
+    /// Number of attempts it took, including the kept one
+    pub attempts: usize,
+
+    /// Whether the kept scene reached the threshold
+    pub passed: bool,
+}
+
+/// Asks a critic model to score a scene
+///
+/// # Arguments
+/// * `templates` - The prompt templates holding the critique prompt
+/// * `critic` - The AI provider acting as critic
+/// * `premise` - Optional story premise
+/// * `previous_scene` - Content of the scene the new one follows
+/// * `content` - The scene to score
+pub async fn critique_scene(
+    templates: &PromptTemplates,
+    critic: &dyn AIProvider,
+    premise: Option<&str>,
+    previous_scene: &str,
+    content: &str,
+) -> Result<SceneCritique, StoryChainError> {
+    let mut context = Context::new();
+    context.insert("premise", premise.unwrap_or_default());
+    context.insert("previous_scene", previous_scene);
+    context.insert("scene", content);
+    let prompt = templates.render(CRITIQUE, &context)?;
+    let (_, response) = critic.generate(&prompt).await?;
+    let fields = parse_labeled_fields(&response, &["SCORE", "NOTES"]);
+
+    let score = fields
+        .get("SCORE")
+        .and_then(|s| {
+            let number: String = s.trim().chars().take_while(|c| c.is_ascii_digit() || *c == '.').collect();
+            number.parse::<f64>().ok()
+        })
+        .ok_or_else(|| StoryChainError::AIServerError(format!("Critic returned no score: {}", response)))?;
+
+    Ok(SceneCritique {
+        score: score.clamp(0.0, 10.0),
+        notes: fields.get("NOTES").cloned().unwrap_or_default(),
+    })
+}
+
+impl StoryChain {
+    /// Generates the next main-plot scene, regenerating it while the critic
+    /// scores it below the threshold of the chain's quality gate
+    ///
+    /// When every attempt falls short, the last one is kept and flagged with
+    /// `quality_below_threshold` metadata. Without a configured gate the
+    /// scene is scored once and kept. In a branching chain every alternative
+    /// passes through the gate on its own.
+    ///
+    /// # Arguments
+    /// * `current_node_id` - ID of the node to generate from
+    /// * `ai_provider` - The AI provider to use for generation
+    /// * `critic` - The AI provider scoring each attempt
+    /// * `premise` - Optional premise to include in generation
+    /// * `current_epoch` - Current epoch number
+    /// * `total_epochs` - Total number of epochs planned
+    pub async fn generate_with_quality_gate(
+        &mut self,
+        current_node_id: &str,
+        ai_provider: &dyn AIProvider,
+        critic: &dyn AIProvider,
+        premise: Option<&str>,
+        current_epoch: usize,
+        total_epochs: usize,
+    ) -> Result<Vec<String>, StoryChainError> {
+        let mut new_ids = Vec::new();
+        for _ in 0..self.branch_ratio.max(1) {
+            new_ids.extend(
+                self.generate_gated_scene(current_node_id, ai_provider, critic, premise, current_epoch, total_epochs)
+                    .await?,
+            );
+        }
+        Ok(new_ids)
+    }
+
+    /// Generates one continuation of a node through the quality gate
+    async fn generate_gated_scene(
+        &mut self,
+        current_node_id: &str,
+        ai_provider: &dyn AIProvider,
+        critic: &dyn AIProvider,
+        premise: Option<&str>,
+        current_epoch: usize,
+        total_epochs: usize,
+    ) -> Result<Vec<String>, StoryChainError> {
+        let gate = self.settings.quality_gate.clone().unwrap_or(QualityGate { min_score: 0.0, max_retries: 0 });
+        let context_id = self.main_plot_context(current_node_id);
+        let previous_scene = self.nodes.get(current_node_id).map(|n| n.content.clone()).unwrap_or_default();
+        let mut scores = Vec::new();
+
+        loop {
+            let new_ids = self
+                .generate_scene(current_node_id, &context_id, ai_provider, premise, current_epoch, total_epochs)
+                .await?;
+            let Some(new_id) = new_ids.first().cloned() else {
+                return Ok(new_ids);
+            };
+
+            let critique = critique_scene(&self.prompts, critic, premise, &previous_scene, &self.nodes[&new_id].content).await?;
+            scores.push(critique.score);
+            let passed = critique.score >= gate.min_score;
+            debug!("Attempt {} of scene {} scored {:.1}", scores.len(), new_id, critique.score);
+
+            if passed || scores.len() > gate.max_retries {
+                let node = self.nodes.get_mut(&new_id).expect("generated node exists");
+                node.metadata.insert("quality_score".to_string(), format!("{:.1}", critique.score));
+                node.metadata.insert("quality_attempts".to_string(), scores.len().to_string());
+                node.metadata.insert(
+                    "quality_scores".to_string(),
+                    scores.iter().map(|s| format!("{:.1}", s)).collect::<Vec<_>>().join(", "),
+                );
+                if !critique.notes.is_empty() {
+                    node.metadata.insert("quality_notes".to_string(), critique.notes);
+                }
+                if !passed {
+                    warn!(
+                        "Scene {} still scored {:.1} after {} attempts, below the threshold of {:.1}",
+                        new_id,
+                        critique.score,
+                        scores.len(),
+                        gate.min_score
+                    );
+                    node.metadata.insert("quality_below_threshold".to_string(), "true".to_string());
+                }
+                return Ok(new_ids);
+            }
+
+            info!("Scene {} scored {:.1}, below {:.1}; regenerating", new_id, critique.score, gate.min_score);
+            let reason = format!("scored {:.1}, below {:.1}", critique.score, gate.min_score);
+            self.notify_hooks(|hooks| hooks.node_rejected(self, Some(&new_id), &self.nodes[&new_id].content, &reason));
+            self.discard_scene(&new_id);
+        }
+    }
+
+    /// Removes a freshly generated scene from the end of its branch, along
+    /// with the setups it planted and the payoffs it recorded
+    fn discard_scene(&mut self, node_id: &str) {
+        let Some(node) = self.nodes.remove(node_id) else {
+            return;
+        };
+        for predecessor_id in &node.predecessors {
+            if let Some(predecessor) = self.nodes.get_mut(predecessor_id) {
+                predecessor.successors.retain(|id| id != node_id);
+            }
+        }
+        self.forget_setups_of(node_id);
+    }
+
+    /// Returns the quality gate outcome of every scored scene in reading order
+    pub fn quality_report(&self) -> Vec<QualityRecord> {
+        self.nodes_in_reading_order()
+            .into_iter()
+            .filter_map(|node| {
+                Some(QualityRecord {
+                    node_id: node.id.clone(),
+                    score: node.metadata.get("quality_score")?.parse().ok()?,
+                    attempts: node.metadata.get("quality_attempts").and_then(|a| a.parse().ok()).unwrap_or(1),
+                    passed: !node.metadata.contains_key("quality_below_threshold"),
+                })
+            })
+            .collect()
+    }
+
+    /// Renders the quality report as markdown
+    pub fn quality_report_markdown(&self) -> String {
+        let report = self.quality_report();
+        let mut content = String::from("## Quality Gate\n\n");
+        if report.is_empty() {
+            content.push_str("No scene was scored.\n");
+            return content;
+        }
+
+        content.push_str("| Scene | Score | Attempts | Passed |\n|---|---|---|---|\n");
+        for record in &report {
+            content.push_str(&format!(
+                "| {} | {:.1} | {} | {} |\n",
+                record.node_id,
+                record.score,
+                record.attempts,
+                if record.passed { "yes" } else { "no" }
+            ));
+        }
+
+        let regenerations: usize = report.iter().map(|r| r.attempts - 1).sum();
+        let failed = report.iter().filter(|r| !r.passed).count();
+        content.push_str(&format!(
+            "\n{} scenes scored, {} regenerations, {} below the threshold.\n",
+            report.len(),
+            regenerations,
+            failed
+        ));
+        content
+    }
+}
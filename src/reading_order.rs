@@ -0,0 +1,78 @@
+//! Reading Order
+//!
+//! This module decouples the order in which scenes are presented from the
+//! causal order in which they were generated. Generation always follows the
+//! predecessor/successor links, while exports follow the reading order, which
+//! allows flashbacks and interleaved timelines.
+
+use log::debug;
+use crate::{StoryChain, StoryChainError, StoryNode};
+
+impl StoryChain {
+    /// Returns the nodes in the order readers should see them
+    ///
+    /// Nodes listed in `reading_order` come first, in that order; any nodes
+    /// not listed follow in causal order. Without a custom reading order this
+    /// is the same as `nodes_in_order`.
+    pub fn nodes_in_reading_order(&self) -> Vec<&StoryNode> {
+        let mut ordered: Vec<&StoryNode> = self
+            .reading_order
+            .iter()
+            .filter_map(|id| self.nodes.get(id))
+            .collect();
+        for node in self.nodes_in_order() {
+            if !self.reading_order.contains(&node.id) {
+                ordered.push(node);
+            }
+        }
+        ordered
+    }
+
+    /// Sets a custom reading order for the chain
+    ///
+    /// # Arguments
+    /// * `node_ids` - Node IDs in the order readers should see them; nodes
+    ///   left out are appended in causal order
+    pub fn set_reading_order(&mut self, node_ids: Vec<String>) -> Result<(), StoryChainError> {
+        for (index, id) in node_ids.iter().enumerate() {
+            if !self.nodes.contains_key(id) {
+                return Err(StoryChainError::InvalidChainOperation(format!(
+                    "Reading order references unknown node: {}",
+                    id
+                )));
+            }
+            if node_ids[..index].contains(id) {
+                return Err(StoryChainError::InvalidChainOperation(format!(
+                    "Reading order lists node {} more than once",
+                    id
+                )));
+            }
+        }
+
+        debug!("Setting reading order: {:?}", node_ids);
+        self.reading_order = node_ids;
+        Ok(())
+    }
+
+    /// Moves a node to a new position in the reading order, e.g. to place a
+    /// flashback earlier than the scene that causally precedes it
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node to move
+    /// * `position` - New zero-based position in the reading order
+    pub fn move_in_reading_order(&mut self, node_id: &str, position: usize) -> Result<(), StoryChainError> {
+        let mut order: Vec<String> = self.nodes_in_reading_order().iter().map(|n| n.id.clone()).collect();
+        let current = order.iter().position(|id| id == node_id).ok_or_else(|| {
+            StoryChainError::InvalidChainOperation(format!("Node not found: {}", node_id))
+        })?;
+
+        let id = order.remove(current);
+        order.insert(position.min(order.len()), id);
+        self.set_reading_order(order)
+    }
+
+    /// Clears any custom reading order so exports follow the causal order again
+    pub fn reset_reading_order(&mut self) {
+        self.reading_order.clear();
+    }
+}
@@ -0,0 +1,149 @@
+//! Human Review
+//!
+//! This module supports writing a story with a human approving each scene.
+//! The author can attach a steering note to a scene, which is passed to the AI
+//! as guidance for the scene that follows it, and can reject a freshly
+//! generated scene. Rejected drafts are remembered on the scene they
+//! continued, so the next attempt is told what the author did not want.
+
+use serde::{Deserialize, Serialize};
+use log::{info, debug};
+use crate::{StoryChain, StoryChainError, StoryNode};
+
+/// Node metadata key holding the author's steering note for the next scene
+pub const STEERING_KEY: &str = "steering";
+
+/// Node metadata key holding the drafts rejected as continuations of a scene, as JSON
+pub const REJECTED_DRAFTS_KEY: &str = "rejected_drafts";
+
+/// Characters of a rejected draft kept to steer the next attempt away from it
+const REJECTED_EXCERPT_CHARS: usize = 300;
+
+/// Maximum number of rejected drafts quoted in the generation guidance
+const MAX_QUOTED_DRAFTS: usize = 3;
+
+/// A draft of a scene that the author rejected
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RejectedDraft {
+    /// The opening of the rejected scene
+    pub excerpt: String,
+
+    /// Why the author rejected it, if they said
+    pub reason: Option<String>,
+
+    /// When the draft was rejected, as a Unix timestamp
+    pub rejected_at: i64,
+}
+
+impl StoryChain {
+    /// Attaches a steering note to a scene, to guide the scene that follows it
+    ///
+    /// A second note is added to the first rather than replacing it.
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the scene the note applies after
+    /// * `note` - What the author wants to happen next
+    pub fn add_steering_note(&mut self, node_id: &str, note: &str) -> Result<(), StoryChainError> {
+        let node = self.nodes.get_mut(node_id).ok_or_else(|| {
+            StoryChainError::InvalidChainOperation(format!("Cannot steer after unknown node: {}", node_id))
+        })?;
+        let note = note.trim();
+        if note.is_empty() {
+            return Ok(());
+        }
+        debug!("Adding steering note after {}", node_id);
+        let notes = match node.metadata.remove(STEERING_KEY) {
+            Some(existing) => format!("{}\n{}", existing, note),
+            None => note.to_string(),
+        };
+        node.metadata.insert(STEERING_KEY.to_string(), notes);
+        Ok(())
+    }
+
+    /// Returns the drafts rejected as continuations of a scene, oldest first
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the scene the drafts continued
+    pub fn rejected_drafts(&self, node_id: &str) -> Vec<RejectedDraft> {
+        self.nodes
+            .get(node_id)
+            .and_then(|node| node.metadata.get(REJECTED_DRAFTS_KEY))
+            .and_then(|json| serde_json::from_str(json).ok())
+            .unwrap_or_default()
+    }
+
+    /// Rejects a newly generated scene, removing it from the chain
+    ///
+    /// Only scenes nothing has been generated after can be rejected. The
+    /// opening of the rejected scene and the reason are remembered on the
+    /// scene it continued, so that the next attempt steers away from it.
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the scene to reject
+    /// * `reason` - Why the author rejected it, if they said
+    ///
+    /// # Returns
+    /// The removed node
+    pub fn reject_node(&mut self, node_id: &str, reason: Option<String>) -> Result<StoryNode, StoryChainError> {
+        let node = self
+            .nodes
+            .get(node_id)
+            .ok_or_else(|| StoryChainError::InvalidChainOperation(format!("Cannot reject unknown node: {}", node_id)))?;
+        if !node.successors.is_empty() {
+            return Err(StoryChainError::InvalidChainOperation(format!(
+                "Node {} already has continuations; regenerate it instead",
+                node_id
+            )));
+        }
+        let reason = reason.filter(|r| !r.trim().is_empty());
+        self.notify_hooks(|hooks| {
+            hooks.node_rejected(self, Some(node_id), &node.content, reason.as_deref().unwrap_or("rejected by the author"))
+        });
+        let node = self.remove_node(node_id)?;
+
+        if let Some(predecessor_id) = node.predecessor() {
+            let mut drafts = self.rejected_drafts(predecessor_id);
+            drafts.push(RejectedDraft {
+                excerpt: node.content.chars().take(REJECTED_EXCERPT_CHARS).collect::<String>().trim().to_string(),
+                reason,
+                rejected_at: chrono::Utc::now().timestamp(),
+            });
+            if let Some(predecessor) = self.nodes.get_mut(predecessor_id) {
+                predecessor.metadata.insert(REJECTED_DRAFTS_KEY.to_string(), serde_json::to_string(&drafts)?);
+            }
+        }
+        info!("Rejected {}", node_id);
+        Ok(node)
+    }
+
+    /// Builds guidance for the scene following a node from the author's
+    /// steering note and the drafts they rejected there
+    ///
+    /// # Arguments
+    /// * `current_node_id` - ID of the node the next scene will follow
+    pub fn review_guidance(&self, current_node_id: &str) -> Vec<String> {
+        let mut notes = Vec::new();
+        let Some(node) = self.nodes.get(current_node_id) else {
+            return notes;
+        };
+        if let Some(steering) = node.metadata.get(STEERING_KEY) {
+            notes.push(format!("The author asks for the following in the next scene: {}", steering.replace('\n', " ")));
+        }
+
+        let drafts = self.rejected_drafts(current_node_id);
+        if !drafts.is_empty() {
+            let mut note = format!(
+                "The author rejected {} earlier draft(s) of this scene; write something different.",
+                drafts.len()
+            );
+            for draft in drafts.iter().rev().take(MAX_QUOTED_DRAFTS) {
+                note.push_str(&format!(" Rejected draft: \"{}...\"", draft.excerpt.replace('\n', " ")));
+                if let Some(reason) = &draft.reason {
+                    note.push_str(&format!(" (reason: {})", reason));
+                }
+            }
+            notes.push(note);
+        }
+        notes
+    }
+}
@@ -0,0 +1,270 @@
+//! Revision Loop
+//!
+//! This module polishes a scene in rounds: an evaluator model scores it on
+//! a set of criteria, such as pacing, continuity, and prose quality, and
+//! while any score falls below the threshold the scene is rewritten with the
+//! evaluator's notes. Every draft is kept in the node's `revision_drafts`
+//! metadata together with its scores.
+
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use log::{info, debug, warn};
+use tera::Context;
+use crate::passes::parse_labeled_fields;
+use crate::prompts::PromptTemplates;
+use crate::{AIProvider, StoryChain, StoryChainError};
+
+/// Node metadata key holding the drafts of a revised scene as JSON
+pub const REVISION_DRAFTS_KEY: &str = "revision_drafts";
+
+/// Node metadata key holding the lowest criterion score of the kept draft
+pub const REVISION_SCORE_KEY: &str = "revision_score";
+
+/// Name of the scene evaluation prompt template
+pub const EVALUATION: &str = "evaluation";
+
+/// Built-in template of the scene evaluation prompt
+///
+/// Variables: `criteria`, the names of the criteria to score;
+/// `previous_scene`, the scene the scene follows, empty when there is none;
+/// `scene`, the scene to score.
+pub const EVALUATION_TEMPLATE: &str = r#"You are a demanding fiction editor. Score the scene below from 0 to 10 on each of these criteria: {{ criteria | join(sep=", ") }}.
+
+{% if previous_scene %}Previous Scene:
+{{ previous_scene }}
+
+{% endif %}Scene:
+{{ scene }}
+
+IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your assessment of the scene.
+</think>
+SCORES:
+{% for criterion in criteria %}- {{ criterion }}: A number from 0 to 10
+{% endfor %}NOTES: The concrete changes that would raise the lowest scores"#;
+
+/// Name of the rewrite prompt template
+pub const REWRITE: &str = "rewrite";
+
+/// Built-in template of the prompt a scene is rewritten from after scoring
+/// below the threshold
+///
+/// Variables: `threshold`, the score every criterion must reach; `scores`,
+/// the scores by criterion; `notes`, the evaluator's notes;
+/// `previous_scene`, the scene the scene follows, empty when there is none;
+/// `scene`, the scene to rewrite.
+pub const REWRITE_TEMPLATE: &str = r#"Rewrite the scene below. An editor scored it from 0 to 10 as follows, and every criterion must reach {{ threshold }}:
+{% for criterion, score in scores %}- {{ criterion }}: {{ score }}
+{% endfor %}
+Editor's notes:
+{{ notes }}
+
+{% if previous_scene %}Previous Scene:
+{{ previous_scene }}
+
+{% endif %}Scene:
+{{ scene }}
+
+IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your reasoning about how to address the editor's notes.
+</think>
+Write the complete rewritten scene here."#;
+
+/// Criteria scored when none are configured
+pub const DEFAULT_CRITERIA: [&str; 3] = ["pacing", "continuity", "prose quality"];
+
+/// Threshold used when none is configured
+pub const DEFAULT_REVISION_THRESHOLD: f64 = 7.0;
+
+/// Number of rewrites used when none is configured
+pub const DEFAULT_REVISION_ROUNDS: usize = 3;
+
+/// What a scene is scored on, and the score every criterion must reach
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RevisionCriteria {
+    /// Names of the criteria, e.g. "pacing"
+    pub criteria: Vec<String>,
+
+    /// Score (0-10) every criterion must reach
+    pub threshold: f64,
+}
+
+impl Default for RevisionCriteria {
+    fn default() -> Self {
+        Self {
+            criteria: DEFAULT_CRITERIA.iter().map(|c| c.to_string()).collect(),
+            threshold: DEFAULT_REVISION_THRESHOLD,
+        }
+    }
+}
+
+/// An evaluator's scores for one draft
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Evaluation {
+    /// Score (0-10) per criterion; criteria the evaluator left out score 0
+    pub scores: BTreeMap<String, f64>,
+
+    /// What the evaluator would change
+    pub notes: String,
+}
+
+impl Evaluation {
+    /// Returns the lowest criterion score
+    pub fn lowest(&self) -> f64 {
+        self.scores.values().copied().fold(f64::INFINITY, f64::min).min(10.0)
+    }
+
+    /// Returns true if every criterion reaches the threshold
+    pub fn passes(&self, criteria: &RevisionCriteria) -> bool {
+        self.lowest() >= criteria.threshold
+    }
+}
+
+/// A draft of a revised scene with its evaluation
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Draft {
+    /// Content of the draft
+    pub content: String,
+
+    /// The evaluator's scores for it
+    pub evaluation: Evaluation,
+}
+
+/// Reads the criterion scores out of an evaluator response
+///
+/// Scores are `- <criterion>: <score>` lines under `SCORES:`; a score may
+/// be written as `7` or `7/10`.
+///
+/// # Arguments
+/// * `response` - The evaluator's response, without its reasoning
+/// * `criteria` - The criteria asked for
+pub fn parse_evaluation(response: &str, criteria: &RevisionCriteria) -> Evaluation {
+    let fields = parse_labeled_fields(response, &["SCORES", "NOTES"]);
+    let listed = fields.get("SCORES").map(String::as_str).unwrap_or(response);
+    let mut found: BTreeMap<String, f64> = BTreeMap::new();
+    for line in listed.lines() {
+        let Some((name, score)) = line.trim().trim_start_matches(['-', '*']).split_once(':') else {
+            continue;
+        };
+        let number: String = score.trim().chars().take_while(|c| c.is_ascii_digit() || *c == '.').collect();
+        if let Ok(score) = number.parse::<f64>() {
+            found.insert(name.trim().to_lowercase(), score.clamp(0.0, 10.0));
+        }
+    }
+
+    let scores = criteria
+        .criteria
+        .iter()
+        .map(|criterion| {
+            let score = found.get(&criterion.to_lowercase()).copied().unwrap_or_else(|| {
+                warn!("Evaluator gave no score for {}", criterion);
+                0.0
+            });
+            (criterion.clone(), score)
+        })
+        .collect();
+    Evaluation { scores, notes: fields.get("NOTES").cloned().unwrap_or_default() }
+}
+
+/// Asks an evaluator model to score a scene on each criterion
+///
+/// # Arguments
+/// * `templates` - The prompt templates holding the evaluation prompt
+/// * `evaluator` - The AI provider acting as evaluator
+/// * `criteria` - The criteria to score
+/// * `previous_scene` - Content of the scene the scene follows, if any
+/// * `content` - The scene to score
+pub async fn evaluate_scene(
+    templates: &PromptTemplates,
+    evaluator: &dyn AIProvider,
+    criteria: &RevisionCriteria,
+    previous_scene: Option<&str>,
+    content: &str,
+) -> Result<Evaluation, StoryChainError> {
+    let mut context = Context::new();
+    context.insert("criteria", &criteria.criteria);
+    context.insert("previous_scene", previous_scene.unwrap_or_default());
+    context.insert("scene", content);
+    let prompt = templates.render(EVALUATION, &context)?;
+    let (_, response) = evaluator.generate(&prompt).await?;
+    Ok(parse_evaluation(&response, criteria))
+}
+
+impl StoryChain {
+    /// Critiques and rewrites a scene until the evaluator scores every
+    /// criterion at or above the threshold, or the rounds run out
+    ///
+    /// The same provider evaluates and rewrites. Each draft and its scores
+    /// are stored in the node's `revision_drafts` metadata, ending with the
+    /// kept draft, whose lowest score is stored as `revision_score`.
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node to revise
+    /// * `ai_provider` - The AI provider evaluating and rewriting the scene
+    /// * `criteria` - The criteria and threshold
+    /// * `max_rounds` - Maximum number of rewrites
+    ///
+    /// # Returns
+    /// The evaluation of the kept draft
+    pub async fn generate_with_revision(
+        &mut self,
+        node_id: &str,
+        ai_provider: &dyn AIProvider,
+        criteria: &RevisionCriteria,
+        max_rounds: usize,
+    ) -> Result<Evaluation, StoryChainError> {
+        let node = self
+            .nodes
+            .get(node_id)
+            .ok_or_else(|| StoryChainError::InvalidChainOperation(format!("Cannot revise unknown node: {}", node_id)))?;
+        let previous_scene = node.predecessor().and_then(|id| self.nodes.get(id)).map(|n| n.content.clone());
+        let mut content = node.content.clone();
+        let mut reasoning = node.reasoning.clone();
+        let mut drafts = Vec::new();
+
+        let mut evaluation = evaluate_scene(&self.prompts, ai_provider, criteria, previous_scene.as_deref(), &content).await?;
+        while !evaluation.passes(criteria) && drafts.len() < max_rounds {
+            debug!("Draft {} of {} scored {:.1} at lowest", drafts.len() + 1, node_id, evaluation.lowest());
+            let scores: BTreeMap<&str, String> =
+                evaluation.scores.iter().map(|(criterion, score)| (criterion.as_str(), format!("{:.1}", score))).collect();
+            let mut context = Context::new();
+            context.insert("threshold", &format!("{:.1}", criteria.threshold));
+            context.insert("scores", &scores);
+            context.insert("notes", &evaluation.notes);
+            context.insert("previous_scene", previous_scene.as_deref().unwrap_or_default());
+            context.insert("scene", &content);
+            let prompt = self.render_prompt(REWRITE, context)?;
+            let (new_reasoning, new_content) = ai_provider.generate(&prompt).await?;
+            drafts.push(Draft { content: std::mem::replace(&mut content, new_content), evaluation });
+            reasoning = new_reasoning;
+            evaluation = evaluate_scene(&self.prompts, ai_provider, criteria, previous_scene.as_deref(), &content).await?;
+        }
+
+        if evaluation.passes(criteria) {
+            info!("Scene {} reached {:.1} after {} rewrites", node_id, evaluation.lowest(), drafts.len());
+        } else {
+            warn!(
+                "Scene {} still scored {:.1} after {} rewrites, below the threshold of {:.1}",
+                node_id,
+                evaluation.lowest(),
+                drafts.len(),
+                criteria.threshold
+            );
+        }
+        drafts.push(Draft { content: content.clone(), evaluation: evaluation.clone() });
+
+        let node = self.nodes.get_mut(node_id).expect("revised node exists");
+        node.content = content;
+        node.reasoning = reasoning;
+        node.metadata.insert(REVISION_DRAFTS_KEY.to_string(), serde_json::to_string(&drafts)?);
+        node.metadata.insert(REVISION_SCORE_KEY.to_string(), format!("{:.1}", evaluation.lowest()));
+        if drafts.len() > 1 {
+            if let Some(provenance) = &mut node.provenance {
+                provenance.revised(crate::provenance::EDITOR_REVISION);
+            }
+        }
+        Ok(evaluation)
+    }
+}
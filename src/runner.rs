@@ -0,0 +1,458 @@
+//! Story Runner
+//!
+//! This module holds the generation pipeline behind `storychain generate` as
+//! a builder, so that applications can embed it: an opening scene written
+//! from the premise, then every storyline continued epoch by epoch, with
+//! subplots woven in, strands scheduled, and the quality gate and revision
+//! passes applied when configured, and exports written at the end. Callbacks
+//! see every finished scene and epoch, and every `RunEvent`, as it happens:
+//!
+//! ```no_run
+//! # async fn example(provider: &dyn storychain::AIProvider) -> Result<(), storychain::StoryChainError> {
+//! use storychain::exports::ExportFormat;
+//! use storychain::runner::StoryRunner;
+//!
+//! let chain = StoryRunner::new(provider)
+//!     .premise("A lighthouse keeper finds a letter addressed to her future self.")
+//!     .epochs(5)
+//!     .on_node(|chain, node_id| println!("{}", chain.nodes[node_id].content))
+//!     .export(ExportFormat::Markdown, "lighthouse.md")
+//!     .run()
+//!     .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! `StoryRunner::continue_chain` runs the epochs on a chain set up by the
+//! caller instead, such as a saved story being continued.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use log::info;
+use crate::cancellation::{until_cancelled, CancellationToken};
+use crate::events::RunEvent;
+use crate::exports::ExportFormat;
+use crate::hooks::PipelineHooks;
+use crate::length::word_count;
+use crate::prompts::INITIAL;
+use crate::provenance::NodeProvenance;
+use crate::revision::RevisionCriteria;
+use crate::selection::CandidateSelection;
+use crate::{AIProvider, GenerationConfig, PromptTemplates, StoryChain, StoryChainError};
+use crate::{EPOCHS_COMPLETED_KEY, EPOCHS_KEY};
+
+/// Epochs a runner generates unless told otherwise
+pub const DEFAULT_EPOCHS: usize = 5;
+
+/// What an epoch generated
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpochReport {
+    /// The epoch, counting from 1; 0 for the opening scene of a new chain
+    pub epoch: usize,
+
+    /// Number of epochs the run generates
+    pub epochs: usize,
+
+    /// IDs of the scenes generated in the epoch, in the order they were generated
+    pub node_ids: Vec<String>,
+
+    /// Number of storylines continued in the next epoch
+    pub frontier: usize,
+
+    /// How long the epoch took
+    pub duration: Duration,
+}
+
+/// Called with every finished scene
+type NodeCallback<'a> = Box<dyn FnMut(&StoryChain, &str) + Send + 'a>;
+
+/// Called after every epoch; an error stops the run
+type EpochCallback<'a> = Box<dyn FnMut(&StoryChain, &EpochReport) -> Result<(), StoryChainError> + Send + 'a>;
+
+/// Called with every event of the run
+type EventCallback<'a> = Box<dyn FnMut(&RunEvent) + Send + 'a>;
+
+/// Builds and runs the generation pipeline
+pub struct StoryRunner<'a> {
+    /// Provider generating the scenes
+    provider: &'a dyn AIProvider,
+
+    /// Provider scoring main-plot scenes for the chain's quality gate
+    critic: Option<&'a dyn AIProvider>,
+
+    /// Premise included in every prompt
+    premise: Option<String>,
+
+    /// Prompt templates of a new chain
+    templates: PromptTemplates,
+
+    /// Sampling parameters of a new chain
+    generation: GenerationConfig,
+
+    /// Number of epochs to generate
+    epochs: usize,
+
+    /// Number of alternative continuations of every scene in a new chain
+    branches: usize,
+
+    /// Maximum number of alternative continuations generated at a time in a new chain
+    parallel: Option<usize>,
+
+    /// Candidates generated for every scene of a new chain, of which the best is kept
+    best_of: Option<usize>,
+
+    /// Criteria every new scene is revised against, with the maximum number of rounds
+    revision: Option<(RevisionCriteria, usize)>,
+
+    /// Formats and paths the finished chain is exported to
+    exports: Vec<(ExportFormat, String)>,
+
+    /// Called with every finished scene
+    on_node: Option<NodeCallback<'a>>,
+
+    /// Called after every epoch
+    on_epoch: Option<EpochCallback<'a>>,
+
+    /// Called with every event of the run
+    on_event: Option<EventCallback<'a>>,
+
+    /// Hooks added to the chain
+    hooks: Vec<Arc<dyn PipelineHooks>>,
+
+    /// Token stopping the run when cancelled
+    cancellation: Option<CancellationToken>,
+}
+
+impl<'a> StoryRunner<'a> {
+    /// Creates a new StoryRunner generating five epochs of a linear story
+    ///
+    /// # Arguments
+    /// * `provider` - Provider generating the scenes
+    pub fn new(provider: &'a dyn AIProvider) -> Self {
+        Self {
+            provider,
+            critic: None,
+            premise: None,
+            templates: PromptTemplates::default(),
+            generation: GenerationConfig::default(),
+            epochs: DEFAULT_EPOCHS,
+            branches: 1,
+            parallel: None,
+            best_of: None,
+            revision: None,
+            exports: Vec::new(),
+            on_node: None,
+            on_epoch: None,
+            on_event: None,
+            hooks: Vec::new(),
+            cancellation: None,
+        }
+    }
+
+    /// Sets the premise included in every prompt, used as given; lay out a
+    /// premise file with `Premise::to_prompt_section` first
+    ///
+    /// # Arguments
+    /// * `premise` - The premise
+    pub fn premise(mut self, premise: impl Into<String>) -> Self {
+        self.premise = Some(premise.into());
+        self
+    }
+
+    /// Sets the prompt templates of a new chain, with the artifacts added to them
+    ///
+    /// # Arguments
+    /// * `templates` - The prompt templates
+    pub fn templates(mut self, templates: PromptTemplates) -> Self {
+        self.templates = templates;
+        self
+    }
+
+    /// Sets the sampling parameters of a new chain, its opening scene included;
+    /// with a seed, the same premise reproduces the same story
+    ///
+    /// # Arguments
+    /// * `generation` - The sampling parameters
+    pub fn generation(mut self, generation: GenerationConfig) -> Self {
+        self.generation = generation;
+        self
+    }
+
+    /// Sets the number of epochs to generate
+    ///
+    /// # Arguments
+    /// * `epochs` - Number of epochs after the opening scene
+    pub fn epochs(mut self, epochs: usize) -> Self {
+        self.epochs = epochs;
+        self
+    }
+
+    /// Sets the number of alternative continuations of every scene in a new chain
+    ///
+    /// # Arguments
+    /// * `branches` - Continuations per scene, at least 1
+    pub fn branches(mut self, branches: usize) -> Self {
+        self.branches = branches.max(1);
+        self
+    }
+
+    /// Generates the alternative continuations of every scene in a new chain
+    /// concurrently rather than one after another
+    ///
+    /// # Arguments
+    /// * `limit` - Maximum number of continuations generated at a time
+    pub fn parallel(mut self, limit: usize) -> Self {
+        self.parallel = Some(limit.max(1));
+        self
+    }
+
+    /// Generates several candidates for every scene of a new chain and keeps
+    /// the one the critic, or else the provider, judges best
+    ///
+    /// # Arguments
+    /// * `candidates` - Number of candidates per scene
+    pub fn best_of(mut self, candidates: usize) -> Self {
+        self.best_of = Some(candidates.max(1));
+        self
+    }
+
+    /// Scores main-plot scenes with a critic, passing them through the
+    /// chain's quality gate
+    ///
+    /// # Arguments
+    /// * `critic` - Provider scoring the scenes
+    pub fn critic(mut self, critic: &'a dyn AIProvider) -> Self {
+        self.critic = Some(critic);
+        self
+    }
+
+    /// Revises every new scene until it meets the criteria
+    ///
+    /// # Arguments
+    /// * `criteria` - The criteria and the score to reach
+    /// * `rounds` - Maximum number of revision rounds per scene
+    pub fn revision(mut self, criteria: RevisionCriteria, rounds: usize) -> Self {
+        self.revision = Some((criteria, rounds));
+        self
+    }
+
+    /// Exports the finished chain
+    ///
+    /// # Arguments
+    /// * `format` - The format to write
+    /// * `path` - The file to write
+    pub fn export(mut self, format: ExportFormat, path: impl Into<String>) -> Self {
+        self.exports.push((format, path.into()));
+        self
+    }
+
+    /// Calls a function with every finished scene
+    ///
+    /// # Arguments
+    /// * `callback` - Called with the chain and the ID of the new node
+    pub fn on_node(mut self, callback: impl FnMut(&StoryChain, &str) + Send + 'a) -> Self {
+        self.on_node = Some(Box::new(callback));
+        self
+    }
+
+    /// Calls a function after every epoch, for example to save the chain;
+    /// an error it returns stops the run. A new chain is reported after its
+    /// opening scene too, as epoch 0
+    ///
+    /// # Arguments
+    /// * `callback` - Called with the chain and what the epoch generated
+    pub fn on_epoch(
+        mut self,
+        callback: impl FnMut(&StoryChain, &EpochReport) -> Result<(), StoryChainError> + Send + 'a,
+    ) -> Self {
+        self.on_epoch = Some(Box::new(callback));
+        self
+    }
+
+    /// Calls a function with every event of the run
+    ///
+    /// # Arguments
+    /// * `callback` - Called with each event
+    pub fn on_event(mut self, callback: impl FnMut(&RunEvent) + Send + 'a) -> Self {
+        self.on_event = Some(Box::new(callback));
+        self
+    }
+
+    /// Adds hooks observing the pipeline to the chain
+    ///
+    /// # Arguments
+    /// * `hooks` - The hooks
+    pub fn hooks(mut self, hooks: Arc<dyn PipelineHooks>) -> Self {
+        self.hooks.push(hooks);
+        self
+    }
+
+    /// Stops the run with `StoryChainError::Cancelled` once a token is
+    /// cancelled, dropping the request in flight; the chain keeps the scenes
+    /// generated so far
+    ///
+    /// # Arguments
+    /// * `token` - The token stopping the run
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = Some(token);
+        self
+    }
+
+    /// Passes an event to the event callback
+    fn emit(&mut self, event: RunEvent) {
+        if let Some(on_event) = &mut self.on_event {
+            on_event(&event);
+        }
+    }
+
+    /// Reports a finished scene to the callbacks
+    fn node_completed(&mut self, chain: &StoryChain, node_id: &str, epoch: usize) {
+        if let Some(on_node) = &mut self.on_node {
+            on_node(chain, node_id);
+        }
+        let words = word_count(&chain.nodes[node_id].content);
+        self.emit(RunEvent::NodeCompleted { node: node_id.to_string(), epoch, words });
+    }
+
+    /// Generates a new chain: the opening scene from the premise, then the
+    /// epochs, then the exports
+    ///
+    /// # Returns
+    /// The finished chain
+    pub async fn run(mut self) -> Result<StoryChain, StoryChainError> {
+        let premise = self.premise.clone().unwrap_or_default();
+        let started = Instant::now();
+        let provenance = NodeProvenance::new(self.provider, &self.templates, INITIAL, None, &self.generation);
+        let prompt = self.templates.initial_prompt(&premise, &[])?;
+        for hooks in &self.hooks {
+            hooks.prompt_built(INITIAL, &prompt);
+        }
+        let (reasoning, content) = until_cancelled(
+            self.cancellation.as_ref(),
+            self.provider.generate_with_config(None, &prompt, &self.generation),
+        )
+        .await?;
+        let mut chain = StoryChain::new(self.templates.post_process(content), reasoning);
+        chain.cancellation = self.cancellation.clone();
+        for hooks in &self.hooks {
+            chain.add_hooks(hooks.clone());
+        }
+        chain.prompts = std::mem::take(&mut self.templates);
+        chain.settings.generation = std::mem::take(&mut self.generation);
+        chain.branch_ratio = self.branches;
+        chain.settings.parallel_candidates = self.parallel;
+        chain.settings.selection = self.best_of.map(|candidates| CandidateSelection { candidates });
+        chain.metadata.insert(EPOCHS_KEY.to_string(), self.epochs.to_string());
+        let root_id = chain.root_node_id.clone();
+        if let Some(root) = chain.nodes.get_mut(&root_id) {
+            root.provenance = Some(provenance.with_duration(started.elapsed()));
+        }
+        chain.notify_hooks(|hooks| hooks.node_generated(&chain, &root_id));
+        self.node_completed(&chain, &root_id, 0);
+        if let Some(on_epoch) = &mut self.on_epoch {
+            let report = EpochReport {
+                epoch: 0,
+                epochs: self.epochs,
+                node_ids: vec![root_id],
+                frontier: 1,
+                duration: started.elapsed(),
+            };
+            on_epoch(&chain, &report)?;
+        }
+
+        self.generate_epochs(&mut chain).await?;
+        for (format, path) in &self.exports {
+            chain.export_as(*format, path)?;
+        }
+        chain.notify_hooks(|hooks| hooks.run_finished(&chain));
+        Ok(chain)
+    }
+
+    /// Continues every storyline of a chain up to the runner's number of
+    /// epochs, starting after the epochs it has completed
+    ///
+    /// The chain's own prompt templates and branching are used; the exports
+    /// are left to the caller.
+    ///
+    /// # Arguments
+    /// * `chain` - The chain to continue
+    pub async fn continue_chain(&mut self, chain: &mut StoryChain) -> Result<(), StoryChainError> {
+        for hooks in &self.hooks {
+            chain.add_hooks(hooks.clone());
+        }
+        if self.cancellation.is_some() {
+            chain.cancellation = self.cancellation.clone();
+        }
+        self.generate_epochs(chain).await?;
+        chain.notify_hooks(|hooks| hooks.run_finished(chain));
+        Ok(())
+    }
+
+    /// Generates the epochs a chain is missing
+    async fn generate_epochs(&mut self, chain: &mut StoryChain) -> Result<(), StoryChainError> {
+        let epochs = self.epochs;
+        let completed = chain.completed_epochs().min(epochs);
+        let premise = self.premise.clone();
+        let premise = premise.as_deref();
+        let mut frontier = chain.frontier_ids();
+        chain.metadata.insert(EPOCHS_KEY.to_string(), epochs.to_string());
+
+        for epoch in completed + 1..=epochs {
+            let epoch_start = Instant::now();
+            info!("Starting epoch {} of {}", epoch, epochs);
+            self.emit(RunEvent::EpochStarted { epoch });
+
+            let mut new_node_ids = Vec::new();
+            let mut next_frontier = Vec::new();
+            for current_node_id in &frontier {
+                chain.check_cancelled()?;
+
+                // Generate the next scene(s) based on the current one, plus any subplot scene due,
+                // or the next scene of the scheduled strand
+                let next_node_ids = if chain.strands.is_empty() {
+                    chain
+                        .generate_with_subplots(current_node_id, self.provider, self.critic, premise, epoch, epochs)
+                        .await?
+                } else {
+                    vec![chain.generate_strand_scene(current_node_id, self.provider, premise, epoch, epochs).await?]
+                };
+
+                // Polish each new scene until it reaches the revision threshold
+                if let Some((criteria, rounds)) = &self.revision {
+                    for node_id in &next_node_ids {
+                        chain.generate_with_revision(node_id, self.provider, criteria, *rounds).await?;
+                    }
+                }
+                for node_id in &next_node_ids {
+                    self.node_completed(chain, node_id, epoch);
+                }
+
+                // Continue from the new ends of the storylines
+                new_node_ids.extend(next_node_ids.iter().cloned());
+                next_frontier.extend(next_node_ids.into_iter().filter(|id| chain.nodes[id].successors.is_empty()));
+            }
+
+            // Stop if no more nodes can be generated
+            if next_frontier.is_empty() {
+                break;
+            }
+            frontier = next_frontier;
+            let report = EpochReport {
+                epoch,
+                epochs,
+                node_ids: new_node_ids,
+                frontier: frontier.len(),
+                duration: epoch_start.elapsed(),
+            };
+            info!("Epoch {} took: {:?}", epoch, report.duration);
+
+            // Let the caller save the story so far, so that an interrupted run can be resumed
+            chain.metadata.insert(EPOCHS_COMPLETED_KEY.to_string(), epoch.to_string());
+            if let Some(on_epoch) = &mut self.on_epoch {
+                on_epoch(chain, &report)?;
+            }
+            self.emit(RunEvent::EpochFinished { epoch, scenes: report.node_ids.len() });
+        }
+        Ok(())
+    }
+}
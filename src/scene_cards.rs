@@ -0,0 +1,151 @@
+//! Scene Cards
+//!
+//! This module asks the model to emit a structured scene card (goal,
+//! conflict, outcome, hook) alongside each scene. Cards are stored on the
+//! node, carried into the next prompt so the following scene picks up the
+//! outcome and hook, and rendered as an outline export.
+
+use serde::{Deserialize, Serialize};
+use log::{info, debug};
+use crate::passes::parse_labeled_fields;
+use crate::{StoryChain, StoryChainError, StoryNode};
+
+/// Labels of the scene card fields in the model's response
+const CARD_LABELS: [&str; 4] = ["GOAL", "CONFLICT", "OUTCOME", "HOOK"];
+
+/// Structured summary of what a scene does
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct SceneCard {
+    /// What the viewpoint character wants in this scene
+    pub goal: String,
+
+    /// What stands in the way
+    pub conflict: String,
+
+    /// How the scene ends for the character
+    pub outcome: String,
+
+    /// The open question that pulls the reader into the next scene
+    pub hook: String,
+}
+
+/// Instruction appended to prompts when scene cards are enabled
+pub const SCENE_CARD_INSTRUCTIONS: &str = "After the scene content, add a line reading SCENE CARD \
+    followed by four lines: GOAL: what the viewpoint character wants in this scene; CONFLICT: what \
+    stands in the way; OUTCOME: how the scene ends for them; HOOK: the open question that leads into \
+    the next scene.";
+
+/// Splits a scene card off the end of generated content
+///
+/// # Arguments
+/// * `content` - The generated content, possibly ending with a scene card
+///
+/// # Returns
+/// The scene content without the card, and the parsed card if one was found
+pub fn split_scene_card(content: &str) -> (String, Option<SceneCard>) {
+    let lines: Vec<&str> = content.lines().collect();
+    let start = lines.iter().rposition(|line| {
+        let stripped = line.trim().trim_matches(['*', '#', ' ']).to_uppercase();
+        stripped.starts_with("SCENE CARD")
+    });
+    let start = start.or_else(|| {
+        lines.iter().rposition(|line| line.trim().trim_start_matches('*').to_uppercase().starts_with("GOAL:"))
+    });
+
+    let Some(start) = start else {
+        return (content.to_string(), None);
+    };
+
+    let fields = parse_labeled_fields(&lines[start..].join("\n"), &CARD_LABELS);
+    if fields.is_empty() {
+        return (content.to_string(), None);
+    }
+
+    let field = |label: &str| fields.get(label).cloned().unwrap_or_default();
+    let card = SceneCard {
+        goal: field("GOAL"),
+        conflict: field("CONFLICT"),
+        outcome: field("OUTCOME"),
+        hook: field("HOOK"),
+    };
+    (lines[..start].join("\n").trim().to_string(), Some(card))
+}
+
+impl SceneCard {
+    /// Returns the names of any fields the model left empty
+    pub fn missing_fields(&self) -> Vec<&'static str> {
+        [
+            ("goal", &self.goal),
+            ("conflict", &self.conflict),
+            ("outcome", &self.outcome),
+            ("hook", &self.hook),
+        ]
+        .into_iter()
+        .filter(|(_, value)| value.is_empty())
+        .map(|(name, _)| name)
+        .collect()
+    }
+}
+
+impl StoryChain {
+    /// Builds the scene card guidance for the scene following the given node
+    ///
+    /// Asks for a card on the new scene and, when the previous scene has one,
+    /// requires the new scene to follow from its outcome and address its hook.
+    ///
+    /// # Arguments
+    /// * `current_node_id` - ID of the node the next scene will follow
+    pub fn scene_card_guidance(&self, current_node_id: &str) -> Option<String> {
+        if !self.settings.scene_cards {
+            return None;
+        }
+
+        let mut note = SCENE_CARD_INSTRUCTIONS.to_string();
+        let previous = self.nodes.get(current_node_id).and_then(|n| n.scene_card.as_ref());
+        if let Some(card) = previous {
+            note.push_str(&format!(
+                " For continuity, the previous scene ended with this outcome: {} Its hook was: {} \
+                The new scene must follow from that outcome and respond to that hook.",
+                card.outcome, card.hook
+            ));
+        }
+        Some(note)
+    }
+
+    /// Exports an outline of the story, one entry per scene in reading order,
+    /// built from the scene cards
+    ///
+    /// # Arguments
+    /// * `path` - The path where the markdown outline should be saved
+    pub fn export_outline(&self, path: &str) -> Result<(), StoryChainError> {
+        info!("Exporting scene-card outline to {}", path);
+        let title = self.metadata.get("title").map(String::as_str).unwrap_or("Generated Story");
+        let mut content = format!("# Outline: {}\n\n", title);
+
+        for (index, node) in self.nodes_in_reading_order().into_iter().enumerate() {
+            content.push_str(&format!("## Scene {} ({})\n\n", index + 1, node.id));
+            content.push_str(&outline_entry(node));
+            content.push('\n');
+        }
+
+        crate::files::write_atomic(path, content)?;
+        Ok(())
+    }
+}
+
+/// Renders a node's scene card as outline bullet points
+fn outline_entry(node: &StoryNode) -> String {
+    match &node.scene_card {
+        Some(card) => {
+            debug!("Rendering scene card for {}", node.id);
+            format!(
+                "- **Goal:** {}\n- **Conflict:** {}\n- **Outcome:** {}\n- **Hook:** {}\n",
+                card.goal, card.conflict, card.outcome, card.hook
+            )
+        }
+        None => {
+            let opening: String = node.content.chars().take(200).collect();
+            format!("- *No scene card.* {}...\n", opening.trim())
+        }
+    }
+}
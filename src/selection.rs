@@ -0,0 +1,211 @@
+//! Best-of-N Selection
+//!
+//! This module generates several candidate continuations of a scene
+//! concurrently from the same prompt and has a judge model rank them against
+//! the premise and the style guide. The winner continues the story as the
+//! first new successor; the other candidates are kept as alternative
+//! branches, marked with their rank, so they can be inspected later but are
+//! not continued.
+
+use serde::{Deserialize, Serialize};
+use log::{info, warn};
+use tera::Context;
+use crate::passes::parse_labeled_fields;
+use crate::prompts::PromptTemplates;
+use crate::{AIProvider, StoryChain, StoryChainError, StoryNode};
+
+/// Name of the candidate ranking prompt template
+pub const RANKING: &str = "ranking";
+
+/// Built-in template of the candidate ranking prompt
+///
+/// Variables: `premise`, the story premise, empty when there is none;
+/// `previous_scene`, the scene the candidates follow; `candidates`, the
+/// candidate scenes. The style guide is the `style` every template gets.
+pub const RANKING_TEMPLATE: &str = r#"You are a demanding fiction editor choosing how a story continues. Rank the {{ candidates | length }} candidate scenes below from best to worst by how well they continue the previous scene{% if premise and style %}, serve the premise, and follow the style guide{% elif premise %} and serve the premise{% elif style %} and follow the style guide{% endif %}, and by the quality of their prose.
+
+{% if premise %}Story Premise:
+{{ premise }}
+
+{% endif %}{% if style %}Style Guide:
+{{ style }}
+
+{% endif %}Previous Scene:
+{{ previous_scene }}
+
+{% for candidate in candidates %}Candidate {{ loop.index }}:
+{{ candidate }}
+
+{% endfor %}IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your comparison of the candidates.
+</think>
+RANKING: The candidate numbers from best to worst, separated by commas
+NOTES: Why the best candidate wins"#;
+
+/// Node metadata key holding a candidate's rank, 1 for the candidate kept
+pub const SELECTION_RANK_KEY: &str = "selection_rank";
+
+/// Node metadata key holding the judge's reasons for the ranking, on the kept candidate
+pub const SELECTION_NOTES_KEY: &str = "selection_notes";
+
+/// How many candidates are generated for every scene
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CandidateSelection {
+    /// Number of candidates the judge chooses from
+    pub candidates: usize,
+}
+
+/// A judge's ranking of candidate scenes
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Ranking {
+    /// Indices of the candidates from best to worst
+    pub order: Vec<usize>,
+
+    /// The judge's reasons for the ranking
+    pub notes: String,
+}
+
+/// Reads a ranking of candidates numbered from 1, such as "3, 1, 2"
+///
+/// Numbers out of range and repeats are skipped, and candidates the ranking
+/// leaves out follow in their original order.
+///
+/// # Arguments
+/// * `text` - The ranking
+/// * `count` - Number of candidates
+///
+/// # Returns
+/// Indices of the candidates from best to worst, counting from 0
+pub fn parse_ranking(text: &str, count: usize) -> Vec<usize> {
+    let mut order: Vec<usize> = Vec::new();
+    for number in text.split(|c: char| !c.is_ascii_digit()).filter_map(|n| n.parse::<usize>().ok()) {
+        if (1..=count).contains(&number) && !order.contains(&(number - 1)) {
+            order.push(number - 1);
+        }
+    }
+    order.extend((0..count).filter(|index| !order.contains(index)).collect::<Vec<_>>());
+    order
+}
+
+/// Asks a judge model to rank candidate continuations of a scene
+///
+/// # Arguments
+/// * `templates` - The prompt templates holding the ranking prompt, and the
+///   style guide the candidates should follow
+/// * `judge` - The AI provider acting as judge
+/// * `premise` - Optional story premise
+/// * `previous_scene` - Content of the scene the candidates follow
+/// * `candidates` - The candidate scenes
+pub async fn rank_candidates(
+    templates: &PromptTemplates,
+    judge: &dyn AIProvider,
+    premise: Option<&str>,
+    previous_scene: &str,
+    candidates: &[&str],
+) -> Result<Ranking, StoryChainError> {
+    let candidates: Vec<&str> = candidates.iter().map(|content| content.trim()).collect();
+    let mut context = Context::new();
+    context.insert("premise", premise.unwrap_or_default());
+    context.insert("previous_scene", previous_scene);
+    context.insert("candidates", &candidates);
+    let prompt = templates.render(RANKING, &context)?;
+    let (_, response) = judge.generate(&prompt).await?;
+    let fields = parse_labeled_fields(&response, &["RANKING", "NOTES"]);
+    let Some(ranking) = fields.get("RANKING") else {
+        warn!("Judge returned no ranking; keeping the first candidate: {}", response);
+        return Ok(Ranking { order: (0..candidates.len()).collect(), notes: String::new() });
+    };
+    Ok(Ranking {
+        order: parse_ranking(ranking, candidates.len()),
+        notes: fields.get("NOTES").cloned().unwrap_or_default(),
+    })
+}
+
+impl StoryChain {
+    /// Generates candidate continuations of a node and keeps the one the
+    /// judge ranks best, once per `branch_ratio`
+    ///
+    /// The candidates of each selection are generated concurrently from the
+    /// same prompt, at most `parallel_candidates` at a time when that is set.
+    /// Every candidate is added after the node in the order of the ranking,
+    /// with its rank in `selection_rank` metadata; only the winners are
+    /// returned, and the other candidates are not continued.
+    ///
+    /// # Arguments
+    /// * `current_node_id` - ID of the node to generate from
+    /// * `ai_provider` - The AI provider to use for generation
+    /// * `judge` - The AI provider ranking the candidates
+    /// * `premise` - Optional premise to include in generation
+    /// * `current_epoch` - Current epoch number
+    /// * `total_epochs` - Total number of epochs planned
+    ///
+    /// # Returns
+    /// The IDs of the kept candidates
+    pub async fn generate_best_of(
+        &mut self,
+        current_node_id: &str,
+        ai_provider: &dyn AIProvider,
+        judge: &dyn AIProvider,
+        premise: Option<&str>,
+        current_epoch: usize,
+        total_epochs: usize,
+    ) -> Result<Vec<String>, StoryChainError> {
+        let candidates = self.settings.selection.as_ref().map_or(1, |s| s.candidates).max(1);
+        let limit = self.settings.parallel_candidates.unwrap_or(candidates);
+        let context_id = self.main_plot_context(current_node_id);
+        let mut winners = Vec::new();
+
+        for _ in 0..self.branch_ratio.max(1) {
+            let prompt = self
+                .continuation_prompt(current_node_id, &context_id, ai_provider, premise, current_epoch, total_epochs)
+                .await?;
+            let drafts = self.draft_candidates(&context_id, &prompt, ai_provider, premise, candidates, limit).await?;
+
+            // Rank the candidates against the scene they continue
+            let ranking = if drafts.len() > 1 {
+                let previous_scene = self.nodes.get(&context_id).map(|n| n.content.clone()).unwrap_or_default();
+                let contents: Vec<&str> = drafts.iter().map(|d| d.content()).collect();
+                rank_candidates(&self.prompts, judge, premise, &previous_scene, &contents).await?
+            } else {
+                Ranking { order: vec![0], notes: String::new() }
+            };
+
+            // Add the winner first, so that it continues the storyline, and the others after it
+            let mut drafts: Vec<_> = drafts.into_iter().map(Some).collect();
+            for (rank, index) in ranking.order.iter().enumerate() {
+                let Some(draft) = drafts[*index].take() else {
+                    continue;
+                };
+                for new_id in self.commit_scene(current_node_id, draft, ai_provider, current_epoch, total_epochs).await? {
+                    let node = self.nodes.get_mut(&new_id).expect("committed node exists");
+                    node.metadata.insert(SELECTION_RANK_KEY.to_string(), (rank + 1).to_string());
+                    if rank == 0 {
+                        if !ranking.notes.is_empty() {
+                            node.metadata.insert(SELECTION_NOTES_KEY.to_string(), ranking.notes.clone());
+                        }
+                        info!("Kept candidate {} of {} as {}", index + 1, candidates, new_id);
+                        winners.push(new_id);
+                    }
+                }
+            }
+        }
+        Ok(winners)
+    }
+
+    /// Returns the IDs of the leaves generation continues from: every leaf
+    /// except the candidates best-of-N selection passed over
+    pub fn frontier_ids(&self) -> Vec<String> {
+        let mut leaves = self.leaf_ids();
+        leaves.dedup();
+        leaves.retain(|id| self.nodes.get(id).is_some_and(|node| !node.is_unselected_candidate()));
+        leaves
+    }
+}
+
+impl StoryNode {
+    /// Returns whether the node is a candidate best-of-N selection passed over
+    pub fn is_unselected_candidate(&self) -> bool {
+        self.metadata.get(SELECTION_RANK_KEY).is_some_and(|rank| rank != "1")
+    }
+}
@@ -0,0 +1,141 @@
+//! Series Continuity
+//!
+//! This module extracts a continuity packet from a finished story so that a
+//! sequel can be generated with knowledge of what happened before: where each
+//! character ended up, which threads were left open, and which facts about the
+//! world were established.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use log::{info, debug};
+use tera::Context;
+use crate::artifacts::{Artifact, ArtifactType};
+use crate::passes::{parse_labeled_fields, SCENE_EXCERPT_CHARS};
+use crate::{AIProvider, StoryChain, StoryChainError};
+
+/// Name of the continuity packet prompt template
+pub const CONTINUITY_PACKET: &str = "continuity_packet";
+
+/// Built-in template of the continuity packet prompt
+///
+/// Variables: `story`, excerpts of every scene.
+pub const CONTINUITY_PACKET_TEMPLATE: &str = r#"You are a series editor preparing notes for the sequel to the story below. Record everything the next book must remember.
+
+Story:
+{{ story }}
+IMPORTANT: Format your response EXACTLY as follows, with one bullet per item:
+<think>
+Your reasoning about what the sequel needs to stay consistent with.
+</think>
+CHARACTER STATES:
+- Character name: where they are, what they know, and how they changed
+UNRESOLVED THREADS:
+- A plot thread, mystery, or relationship left open
+WORLD FACTS:
+- A fact about the setting, rules, or history established in the story"#;
+
+/// Continuity information carried from one story into its sequel
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ContinuityPacket {
+    /// Title of the previous story, if known
+    pub previous_title: Option<String>,
+
+    /// Where each character stands at the end of the previous story
+    pub character_states: Vec<String>,
+
+    /// Plot threads left unresolved at the end of the previous story
+    pub unresolved_threads: Vec<String>,
+
+    /// Facts about the world established in the previous story
+    pub world_facts: Vec<String>,
+}
+
+/// Splits a labeled field into individual list items
+fn list_items(value: Option<&String>) -> Vec<String> {
+    value
+        .map(|v| {
+            v.lines()
+                .map(|line| line.trim().trim_start_matches(['-', '*', '•']).trim().to_string())
+                .filter(|line| !line.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl ContinuityPacket {
+    /// Renders the packet as a prompt section to be appended to a sequel's premise
+    pub fn to_prompt_section(&self) -> String {
+        let mut section = String::from("Continuity From The Previous Story");
+        if let Some(title) = &self.previous_title {
+            section.push_str(&format!(" ({})", title));
+        }
+        section.push_str(":\n");
+
+        for (heading, items) in [
+            ("Character states", &self.character_states),
+            ("Unresolved threads", &self.unresolved_threads),
+            ("Established world facts", &self.world_facts),
+        ] {
+            if items.is_empty() {
+                continue;
+            }
+            section.push_str(&format!("{}:\n", heading));
+            for item in items {
+                section.push_str(&format!("- {}\n", item));
+            }
+        }
+
+        section
+    }
+
+    /// Converts the packet into an artifact so it can be stored with the sequel's artifacts
+    ///
+    /// # Arguments
+    /// * `id` - Unique identifier for the artifact
+    pub fn to_artifact(&self, id: String) -> Result<Artifact, StoryChainError> {
+        let mut metadata = HashMap::new();
+        metadata.insert("packet".to_string(), serde_json::to_string(self)?);
+        if let Some(title) = &self.previous_title {
+            metadata.insert("previous_title".to_string(), title.clone());
+        }
+
+        Ok(Artifact {
+            id,
+            content: self.to_prompt_section(),
+            artifact_type: ArtifactType::Continuity,
+            metadata,
+            tags: Vec::new(),
+        })
+    }
+}
+
+impl StoryChain {
+    /// Extracts a continuity packet describing the end state of this story
+    ///
+    /// # Arguments
+    /// * `ai_provider` - The AI provider to use for extraction
+    pub async fn extract_continuity_packet(
+        &self,
+        ai_provider: &dyn AIProvider,
+    ) -> Result<ContinuityPacket, StoryChainError> {
+        info!("Extracting continuity packet from story chain");
+
+        let mut context = Context::new();
+        context.insert("story", &self.condensed_text(SCENE_EXCERPT_CHARS));
+        let prompt = self.render_prompt(CONTINUITY_PACKET, context)?;
+
+        let (_, content) = ai_provider.generate(&prompt).await?;
+        let fields = parse_labeled_fields(
+            &content,
+            &["CHARACTER STATES", "UNRESOLVED THREADS", "WORLD FACTS"],
+        );
+        debug!("Parsed continuity fields: {:?}", fields);
+
+        Ok(ContinuityPacket {
+            previous_title: self.metadata.get("title").cloned(),
+            character_states: list_items(fields.get("CHARACTER STATES")),
+            unresolved_threads: list_items(fields.get("UNRESOLVED THREADS")),
+            world_facts: list_items(fields.get("WORLD FACTS")),
+        })
+    }
+}
@@ -0,0 +1,639 @@
+//! Generation Server
+//!
+//! This module serves StoryChain over HTTP, so that a web front-end can
+//! create projects, start generation runs and follow them, read the chains,
+//! and export them. It is built with the `serve` feature and started with
+//! `storychain serve`. Every project is a directory under the server's root,
+//! laid out as `storychain init` creates it:
+//!
+//! ```text
+//! GET  /projects                                                 list the projects
+//! POST /projects                                                 create a project: {"name", "premise"}
+//! GET  /projects/{project}                                       the project and its chains
+//! POST /projects/{project}/runs                                  start a run: {"chain", "epochs", "branches", "seed"}
+//! GET  /projects/{project}/chains/{chain}                        a chain, as saved
+//! POST /projects/{project}/chains/{chain}/nodes/{node}/feedback  rate a scene: {"rating", "comment"}
+//! POST /projects/{project}/chains/{chain}/exports                export a chain: {"format"}
+//! GET  /runs                                                     every run and its status
+//! GET  /runs/{run}                                               the status of a run
+//! POST /runs/{run}/cancel                                        cancel a run
+//! GET  /runs/{run}/events                                        the run's events, as server-sent events
+//! GET  /runs/{run}/ws                                            the run's events, over a WebSocket
+//! ```
+//!
+//! Runs generate in the background with the provider the server was started
+//! with, saving the chain and a checkpoint after every epoch. Their progress
+//! is reported as `RunEvent`s, the model's output streamed token by token
+//! among them; a client subscribing late is sent the events so far, without
+//! the tokens, before the live ones.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use axum::extract::ws::{Message, WebSocketUpgrade};
+use axum::extract::{Path as UrlPath, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use log::{info, warn, error};
+use crate::cancellation::{CancellableProvider, CancellationToken};
+use crate::events::RunEvent;
+use crate::exports::ExportFormat;
+use crate::feedback::FeedbackSummary;
+use crate::premise::{load_premise, Premise};
+use crate::project::{Project, ProjectManifest, PROJECT_FILE};
+use crate::providers::StreamingProvider;
+use crate::runner::StoryRunner;
+use crate::storage::ChainStore;
+use crate::{AIProvider, ArtifactManager, GenerationConfig, PromptTemplates, StoryChain, StoryChainError};
+
+/// Epochs a run generates when the request names none
+pub const DEFAULT_RUN_EPOCHS: usize = 5;
+
+/// Events kept for clients that fall behind a run's event stream
+const EVENT_BUFFER: usize = 256;
+
+/// Whether a run is still generating
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RunState {
+    /// The run is generating
+    Running,
+    /// Every epoch was generated
+    Finished,
+    /// The run stopped with an error
+    Failed,
+    /// The run was cancelled
+    Cancelled,
+}
+
+/// Progress of a run
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RunStatus {
+    /// ID of the run
+    pub id: String,
+
+    /// Name of the project the run generates in
+    pub project: String,
+
+    /// Name of the chain being generated
+    pub chain: String,
+
+    /// Whether the run is still generating
+    pub state: RunState,
+
+    /// Number of epochs the run generates
+    pub epochs: usize,
+
+    /// Number of epochs generated so far
+    pub completed_epochs: usize,
+
+    /// Number of scenes generated so far, the opening scene included
+    pub scenes: usize,
+
+    /// Why the run failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    /// When the run started, in RFC 3339 format
+    pub started_at: String,
+}
+
+/// Body of a request creating a project
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CreateProject {
+    /// Name of the project, also the name of its directory
+    pub name: String,
+
+    /// The premise, as a premise file would hold it; the project is created
+    /// with a premise to fill in when it is missing
+    pub premise: Option<String>,
+}
+
+/// Body of a request starting a run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StartRun {
+    /// Name of the chain to generate, the project's name when missing
+    pub chain: Option<String>,
+
+    /// Number of epochs to generate
+    pub epochs: Option<usize>,
+
+    /// Number of alternative continuations of every scene
+    pub branches: Option<usize>,
+
+    /// Sampling seed, so that the run can be reproduced with backends supporting it
+    pub seed: Option<u64>,
+}
+
+/// Body of a request exporting a chain
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExportRequest {
+    /// Name of the format, as `storychain convert --to` takes it
+    pub format: String,
+}
+
+/// Body of a request recording a reader's rating of a scene
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeedbackRequest {
+    /// Rating from 1 (poor) to 5 (excellent)
+    pub rating: u8,
+
+    /// Optional free-form comment
+    pub comment: Option<String>,
+}
+
+/// A project with the chains generated in it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectSummary {
+    /// Contents of the project file
+    #[serde(flatten)]
+    pub manifest: ProjectManifest,
+
+    /// Names of the project's chains
+    pub chains: Vec<String>,
+}
+
+/// An error answered with an HTTP status
+#[derive(Debug)]
+pub struct ServerError {
+    /// Status of the response
+    status: StatusCode,
+
+    /// Message sent in the response body
+    message: String,
+}
+
+impl ServerError {
+    /// Creates an error answered with the given status
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        Self { status, message: message.into() }
+    }
+}
+
+impl From<StoryChainError> for ServerError {
+    fn from(e: StoryChainError) -> Self {
+        let status = match &e {
+            StoryChainError::ConfigError(_)
+            | StoryChainError::InvalidChainOperation(_)
+            | StoryChainError::InvalidFeedback(_)
+            | StoryChainError::TemplateError(_) => StatusCode::BAD_REQUEST,
+            StoryChainError::StorageError(_) => StatusCode::NOT_FOUND,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self::new(status, e.to_string())
+    }
+}
+
+impl IntoResponse for ServerError {
+    fn into_response(self) -> Response {
+        (self.status, Json(serde_json::json!({ "error": self.message }))).into_response()
+    }
+}
+
+/// Status and events of one run
+struct RunHandle {
+    /// Progress of the run
+    status: Mutex<RunStatus>,
+
+    /// Every event of the run so far
+    events: Mutex<Vec<RunEvent>>,
+
+    /// Sends the events to the clients following the run
+    sender: broadcast::Sender<RunEvent>,
+
+    /// Stops the run when cancelled
+    cancellation: CancellationToken,
+}
+
+impl RunHandle {
+    /// Records an event and sends it to the clients following the run
+    fn emit(&self, event: RunEvent) {
+        {
+            let mut status = self.status.lock().unwrap();
+            match &event {
+                RunEvent::NodeCompleted { .. } => status.scenes += 1,
+                RunEvent::EpochFinished { epoch, .. } => status.completed_epochs = *epoch,
+                RunEvent::Finished { .. } => status.state = RunState::Finished,
+                RunEvent::Failed { error } => {
+                    status.state = RunState::Failed;
+                    status.error = Some(error.clone());
+                }
+                RunEvent::Cancelled => status.state = RunState::Cancelled,
+                RunEvent::Started { .. } | RunEvent::EpochStarted { .. } | RunEvent::Token { .. } => {}
+            }
+        }
+        let mut events = self.events.lock().unwrap();
+        if event.is_replayed() {
+            events.push(event.clone());
+        }
+        let _ = self.sender.send(event);
+    }
+
+    /// Returns the events so far, then the live ones until the run ends
+    ///
+    /// # Arguments
+    /// * `id` - ID of the run, for logging
+    fn follow(&self, id: String) -> mpsc::Receiver<RunEvent> {
+        let (history, mut receiver) = {
+            let events = self.events.lock().unwrap();
+            (events.clone(), self.sender.subscribe())
+        };
+
+        // Forward the events until the one ending the run, then close the channel
+        let (sender, events) = mpsc::channel(EVENT_BUFFER);
+        tokio::spawn(async move {
+            for event in history {
+                let terminal = event.is_terminal();
+                if sender.send(event).await.is_err() || terminal {
+                    return;
+                }
+            }
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => {
+                        let terminal = event.is_terminal();
+                        if sender.send(event).await.is_err() || terminal {
+                            return;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(missed)) => warn!("Client of run {} missed {} events", id, missed),
+                    Err(broadcast::error::RecvError::Closed) => return,
+                }
+            }
+        });
+        events
+    }
+}
+
+/// Serves the projects under one directory and runs their generations
+pub struct StoryServer {
+    /// Directory holding the projects
+    root: PathBuf,
+
+    /// Provider every run generates with
+    provider: Arc<dyn AIProvider>,
+
+    /// Runs started since the server started, by ID
+    runs: Mutex<HashMap<String, Arc<RunHandle>>>,
+
+    /// Number of runs started so far
+    started_runs: AtomicUsize,
+}
+
+impl StoryServer {
+    /// Creates a new StoryServer
+    ///
+    /// # Arguments
+    /// * `root` - Directory holding the projects, created when needed
+    /// * `provider` - Provider every run generates with
+    pub fn new(root: impl Into<PathBuf>, provider: Arc<dyn AIProvider>) -> Self {
+        Self { root: root.into(), provider, runs: Mutex::new(HashMap::new()), started_runs: AtomicUsize::new(0) }
+    }
+
+    /// Opens a project under the root
+    ///
+    /// # Arguments
+    /// * `name` - Name of the project's directory
+    fn project(&self, name: &str) -> Result<Project, ServerError> {
+        check_name(name)?;
+        let dir = self.root.join(name);
+        if !dir.join(PROJECT_FILE).is_file() {
+            return Err(ServerError::new(StatusCode::NOT_FOUND, format!("No project named {}", name)));
+        }
+        Ok(Project::open(dir)?)
+    }
+
+    /// Returns a run started by the server
+    ///
+    /// # Arguments
+    /// * `id` - ID of the run
+    fn run(&self, id: &str) -> Result<Arc<RunHandle>, ServerError> {
+        self.runs
+            .lock()
+            .unwrap()
+            .get(id)
+            .cloned()
+            .ok_or_else(|| ServerError::new(StatusCode::NOT_FOUND, format!("No run with ID {}", id)))
+    }
+
+    /// Returns the status of every run, oldest first
+    pub fn run_statuses(&self) -> Vec<RunStatus> {
+        let mut statuses: Vec<RunStatus> =
+            self.runs.lock().unwrap().values().map(|run| run.status.lock().unwrap().clone()).collect();
+        statuses.sort_by(|a, b| a.started_at.cmp(&b.started_at).then_with(|| a.id.cmp(&b.id)));
+        statuses
+    }
+
+    /// Starts generating a chain of a project in the background
+    ///
+    /// # Arguments
+    /// * `server` - The server, shared with the run
+    /// * `project` - The project to generate in
+    /// * `request` - The chain, epochs, branches, and seed of the run
+    ///
+    /// # Returns
+    /// The status of the new run
+    pub fn start_run(server: &Arc<Self>, project: Project, request: StartRun) -> Result<RunStatus, StoryChainError> {
+        let chain_name = request.chain.clone().unwrap_or_else(|| project.manifest.name.clone());
+        check_name(&chain_name).map_err(|e| StoryChainError::ConfigError(e.message))?;
+        let epochs = request.epochs.unwrap_or(DEFAULT_RUN_EPOCHS);
+        let id = format!("run-{}", server.started_runs.fetch_add(1, Ordering::SeqCst) + 1);
+        let status = RunStatus {
+            id: id.clone(),
+            project: project.manifest.name.clone(),
+            chain: chain_name.clone(),
+            state: RunState::Running,
+            epochs,
+            completed_epochs: 0,
+            scenes: 0,
+            error: None,
+            started_at: chrono::Utc::now().to_rfc3339(),
+        };
+        let run = Arc::new(RunHandle {
+            status: Mutex::new(status.clone()),
+            events: Mutex::new(Vec::new()),
+            sender: broadcast::channel(EVENT_BUFFER).0,
+            cancellation: CancellationToken::new(),
+        });
+        server.runs.lock().unwrap().insert(id.clone(), run.clone());
+        info!("Starting run {} of {} in project {}", id, chain_name, project.manifest.name);
+
+        // Stream the model's output to the clients following the run, until it is cancelled
+        let streaming = run.clone();
+        let provider = StreamingProvider::new(server.provider.clone(), move |token| {
+            streaming.emit(RunEvent::Token { text: token.to_string() })
+        });
+        let provider = CancellableProvider::new(provider, run.cancellation.clone());
+        tokio::spawn(async move {
+            run.emit(RunEvent::Started { run: id.clone(), chain: chain_name.clone(), epochs });
+            let event = match generate(&provider, &project, &chain_name, &request, epochs, &run).await {
+                Ok(scenes) => RunEvent::Finished { scenes },
+                Err(StoryChainError::Cancelled) => {
+                    info!("Run {} cancelled", id);
+                    RunEvent::Cancelled
+                }
+                Err(e) => {
+                    error!("Run {} failed: {}", id, e);
+                    RunEvent::Failed { error: e.to_string() }
+                }
+            };
+            run.emit(event);
+        });
+        Ok(status)
+    }
+}
+
+/// Rejects names that would reach outside the server's root
+fn check_name(name: &str) -> Result<(), ServerError> {
+    if name.trim().is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(ServerError::new(StatusCode::BAD_REQUEST, format!("Invalid name: {:?}", name)));
+    }
+    Ok(())
+}
+
+/// Generates a chain of a project: an opening scene from the premise, then
+/// the given number of epochs continuing every storyline
+///
+/// # Returns
+/// The number of scenes in the finished chain
+async fn generate(
+    provider: &dyn AIProvider,
+    project: &Project,
+    chain_name: &str,
+    request: &StartRun,
+    epochs: usize,
+    run: &RunHandle,
+) -> Result<usize, StoryChainError> {
+    let artifacts_dir = project.artifacts_dir();
+    let premise = Premise::parse(&load_premise(&project.manifest.premise, &artifacts_dir)?)?.to_prompt_section();
+    let mut artifact_manager = ArtifactManager::new(&artifacts_dir.to_string_lossy());
+    artifact_manager.load_from_dir()?;
+    artifact_manager.validate()?;
+    let mut templates = PromptTemplates::default();
+    templates.add_artifacts(&artifact_manager);
+
+    // Write the opening scene from the premise and continue every storyline,
+    // saving after each epoch
+    let store = project.chain_store();
+    let chain = StoryRunner::new(provider)
+        .premise(premise)
+        .templates(templates)
+        .epochs(epochs)
+        .branches(request.branches.unwrap_or(1))
+        .generation(GenerationConfig { seed: request.seed, ..GenerationConfig::default() })
+        .cancellation(run.cancellation.clone())
+        .on_event(|event| run.emit(event.clone()))
+        .on_epoch(|chain, report| {
+            if report.epoch == 0 {
+                return store.save_chain(chain_name, chain);
+            }
+            store.append_nodes(chain_name, chain, &report.node_ids)?;
+            project.save_checkpoint(chain_name, chain, report.epoch)?;
+            Ok(())
+        })
+        .run()
+        .await?;
+    Ok(chain.nodes.len())
+}
+
+/// Returns the projects under the server's root
+async fn list_projects(State(server): State<Arc<StoryServer>>) -> Result<Json<Vec<ProjectManifest>>, ServerError> {
+    let mut projects = Vec::new();
+    if server.root.is_dir() {
+        for entry in std::fs::read_dir(&server.root).map_err(StoryChainError::from)? {
+            let path = entry.map_err(StoryChainError::from)?.path();
+            if path.join(PROJECT_FILE).is_file() {
+                projects.push(Project::open(&path)?.manifest);
+            }
+        }
+    }
+    projects.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(Json(projects))
+}
+
+/// Creates a project under the server's root
+async fn create_project(
+    State(server): State<Arc<StoryServer>>,
+    Json(request): Json<CreateProject>,
+) -> Result<(StatusCode, Json<ProjectManifest>), ServerError> {
+    check_name(&request.name)?;
+    if let Some(premise) = &request.premise {
+        Premise::parse(premise)?;
+    }
+    let project = Project::init(server.root.join(&request.name), &request.name)?;
+    if let Some(premise) = &request.premise {
+        crate::files::write_atomic(project.premise_path(), premise)?;
+    }
+    Ok((StatusCode::CREATED, Json(project.manifest)))
+}
+
+/// Returns a project and the names of its chains
+async fn get_project(
+    State(server): State<Arc<StoryServer>>,
+    UrlPath(name): UrlPath<String>,
+) -> Result<Json<ProjectSummary>, ServerError> {
+    let project = server.project(&name)?;
+    let chains = project.chain_store().list_chains()?;
+    Ok(Json(ProjectSummary { manifest: project.manifest, chains }))
+}
+
+/// Starts a run in a project
+async fn start_run(
+    State(server): State<Arc<StoryServer>>,
+    UrlPath(name): UrlPath<String>,
+    Json(request): Json<StartRun>,
+) -> Result<(StatusCode, Json<RunStatus>), ServerError> {
+    let project = server.project(&name)?;
+    Ok((StatusCode::ACCEPTED, Json(StoryServer::start_run(&server, project, request)?)))
+}
+
+/// Returns a chain of a project
+async fn get_chain(
+    State(server): State<Arc<StoryServer>>,
+    UrlPath((name, chain)): UrlPath<(String, String)>,
+) -> Result<Json<StoryChain>, ServerError> {
+    check_name(&chain)?;
+    Ok(Json(server.project(&name)?.chain_store().load_chain(&chain)?))
+}
+
+/// Exports a chain of a project next to its file
+async fn export_chain(
+    State(server): State<Arc<StoryServer>>,
+    UrlPath((name, chain_name)): UrlPath<(String, String)>,
+    Json(request): Json<ExportRequest>,
+) -> Result<Json<serde_json::Value>, ServerError> {
+    check_name(&chain_name)?;
+    let format: ExportFormat = request.format.parse().map_err(|e: String| ServerError::new(StatusCode::BAD_REQUEST, e))?;
+    let project = server.project(&name)?;
+    let chain = project.chain_store().load_chain(&chain_name)?;
+    let path = export_path(&project.chain_path(&chain_name), format);
+    chain.export_as(format, &path)?;
+    Ok(Json(serde_json::json!({ "format": request.format, "path": path })))
+}
+
+/// Records a reader's rating of a scene of a chain and saves the chain
+async fn add_feedback(
+    State(server): State<Arc<StoryServer>>,
+    UrlPath((name, chain_name, node)): UrlPath<(String, String, String)>,
+    Json(request): Json<FeedbackRequest>,
+) -> Result<Json<FeedbackSummary>, ServerError> {
+    check_name(&chain_name)?;
+    let store = server.project(&name)?.chain_store();
+    let mut chain = store.load_chain(&chain_name)?;
+    chain.add_feedback(&node, request.rating, request.comment)?;
+    store.save_chain(&chain_name, &chain)?;
+    Ok(Json(chain.feedback_summary()))
+}
+
+/// Returns the path an export of a chain file is written to
+fn export_path(chain_path: &Path, format: ExportFormat) -> String {
+    chain_path.with_extension("").to_string_lossy().to_string() + format.file_suffix()
+}
+
+/// Returns every run and its status
+async fn list_runs(State(server): State<Arc<StoryServer>>) -> Json<Vec<RunStatus>> {
+    Json(server.run_statuses())
+}
+
+/// Returns the status of a run
+async fn get_run(
+    State(server): State<Arc<StoryServer>>,
+    UrlPath(id): UrlPath<String>,
+) -> Result<Json<RunStatus>, ServerError> {
+    Ok(Json(server.run(&id)?.status.lock().unwrap().clone()))
+}
+
+/// Cancels a run, which stops once the request in flight is dropped; the
+/// chain keeps the epochs saved before
+async fn cancel_run(
+    State(server): State<Arc<StoryServer>>,
+    UrlPath(id): UrlPath<String>,
+) -> Result<(StatusCode, Json<RunStatus>), ServerError> {
+    let run = server.run(&id)?;
+    let status = run.status.lock().unwrap().clone();
+    if status.state != RunState::Running {
+        return Err(ServerError::new(StatusCode::CONFLICT, format!("Run {} is no longer running", id)));
+    }
+    info!("Cancelling run {}", id);
+    run.cancellation.cancel();
+    Ok((StatusCode::ACCEPTED, Json(status)))
+}
+
+/// Streams the events of a run, from its start, as server-sent events; the
+/// stream ends with the run
+async fn run_events(
+    State(server): State<Arc<StoryServer>>,
+    UrlPath(id): UrlPath<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ServerError> {
+    let events = server.run(&id)?.follow(id);
+    let events = ReceiverStream::new(events)
+        .map(|event| Ok(Event::default().event(event.name()).json_data(&event).unwrap_or_default()));
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Streams the events of a run, from its start, over a WebSocket: one JSON
+/// text message per event, and a close frame when the run ends
+async fn run_socket(
+    State(server): State<Arc<StoryServer>>,
+    UrlPath(id): UrlPath<String>,
+    socket: WebSocketUpgrade,
+) -> Result<Response, ServerError> {
+    let mut events = server.run(&id)?.follow(id);
+    Ok(socket.on_upgrade(|mut socket| async move {
+        while let Some(event) = events.recv().await {
+            let Ok(text) = serde_json::to_string(&event) else {
+                continue;
+            };
+            if socket.send(Message::Text(text)).await.is_err() {
+                return;
+            }
+        }
+        let _ = socket.send(Message::Close(None)).await;
+    }))
+}
+
+/// Returns the routes of the generation API
+///
+/// # Arguments
+/// * `server` - The server answering the requests
+pub fn router(server: Arc<StoryServer>) -> Router {
+    Router::new()
+        .route("/projects", get(list_projects).post(create_project))
+        .route("/projects/:project", get(get_project))
+        .route("/projects/:project/runs", post(start_run))
+        .route("/projects/:project/chains/:chain", get(get_chain))
+        .route("/projects/:project/chains/:chain/nodes/:node/feedback", post(add_feedback))
+        .route("/projects/:project/chains/:chain/exports", post(export_chain))
+        .route("/runs", get(list_runs))
+        .route("/runs/:run", get(get_run))
+        .route("/runs/:run/cancel", post(cancel_run))
+        .route("/runs/:run/events", get(run_events))
+        .route("/runs/:run/ws", get(run_socket))
+        .with_state(server)
+}
+
+/// Serves the generation API until the process is stopped
+///
+/// # Arguments
+/// * `listener` - The socket to accept connections on
+/// * `server` - The server answering the requests
+pub async fn serve(listener: TcpListener, server: Arc<StoryServer>) -> Result<(), StoryChainError> {
+    std::fs::create_dir_all(&server.root)?;
+    info!("Serving the projects in {} on http://{}", server.root.display(), listener.local_addr()?);
+    axum::serve(listener, router(server)).await?;
+    Ok(())
+}
@@ -0,0 +1,97 @@
+//! Chain Settings
+//!
+//! This module defines the chain-level settings that shape how new scenes are
+//! generated. Settings are serialized with the chain so that a story keeps its
+//! generation behaviour when it is continued later.
+
+use serde::{Deserialize, Serialize};
+use crate::consistency::ConsistencyCheck;
+use crate::constraints::ConstraintSet;
+use crate::dialogue::DialogueTarget;
+use crate::embeddings::SceneRecall;
+use crate::enrichment::EnrichmentMode;
+use crate::foreshadowing::ForeshadowingPlan;
+use crate::filters::ContentFilterSettings;
+use crate::formats::StoryFormat;
+use crate::generation::GenerationConfig;
+use crate::genres::GenrePreset;
+use crate::length::LengthTarget;
+use crate::memory::RollingSummary;
+use crate::pov::PovSchedule;
+use crate::quality::QualityGate;
+use crate::selection::CandidateSelection;
+use crate::tension::TensionCurve;
+
+/// Chain-level settings applied when generating new scenes
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ChainSettings {
+    /// Condition continuation prompts on aggregated reader feedback
+    pub feedback_guidance: bool,
+
+    /// Viewpoint characters and how they alternate between scenes
+    pub pov: Option<PovSchedule>,
+
+    /// Structural format every scene must follow
+    pub format: StoryFormat,
+
+    /// Target dialogue-to-narration ratio for generated scenes
+    pub dialogue: Option<DialogueTarget>,
+
+    /// Target word count of every scene
+    pub length: Option<LengthTarget>,
+
+    /// Ask the model for a scene card (goal, conflict, outcome, hook) with every scene
+    pub scene_cards: bool,
+
+    /// Track planted setups and their payoffs through markers in generated scenes
+    pub track_setups: bool,
+
+    /// Planned beats to foreshadow in the scenes leading up to them
+    pub foreshadowing: Option<ForeshadowingPlan>,
+
+    /// Desired tension over the course of the story
+    pub tension_curve: Option<TensionCurve>,
+
+    /// Genre preset whose style, vocabulary, and beats shape every prompt
+    pub genre: Option<GenrePreset>,
+
+    /// Rules every scene must obey, checked after generation
+    pub constraints: Option<ConstraintSet>,
+
+    /// System prompt (author persona, global style rules) sent with every scene prompt
+    pub system_prompt: Option<String>,
+
+    /// Minimum critic score a scene must reach before it is kept
+    pub quality_gate: Option<QualityGate>,
+
+    /// Sampling parameters sent with every scene prompt
+    pub generation: GenerationConfig,
+
+    /// Token budget of the model's context window; continuation prompts are
+    /// trimmed to fit it, leaving room for the response
+    pub max_context_tokens: Option<usize>,
+
+    /// Keep a running summary of earlier scenes for continuation prompts
+    pub memory: Option<RollingSummary>,
+
+    /// Recall the earlier scenes most similar to the previous one into
+    /// continuation prompts; needs an embedding provider on the chain
+    pub recall: Option<SceneRecall>,
+
+    /// Check every new scene for contradictions with the story so far
+    pub consistency: Option<ConsistencyCheck>,
+
+    /// Words, patterns, and rating every new scene is screened for before it is committed
+    pub content_filter: Option<ContentFilterSettings>,
+
+    /// Extract the characters, location, time, and mood of every new scene into its metadata
+    pub enrichment: Option<EnrichmentMode>,
+
+    /// Generate the alternative continuations of a node concurrently, at
+    /// most this many at a time, instead of one after another
+    pub parallel_candidates: Option<usize>,
+
+    /// Candidates generated for every scene, of which a judge keeps the best
+    pub selection: Option<CandidateSelection>,
+}
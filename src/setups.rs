@@ -0,0 +1,254 @@
+//! Setup and Payoff Tracking
+//!
+//! This module keeps a ledger of "Chekhov's guns": details planted in the
+//! story that promise a later payoff. Setups are marked either by the user or
+//! by the model through `PLANTED:` lines in its response, and paid off through
+//! `PAYOFF:` lines. Setups still open at the end of a run can be reported or
+//! resolved with an extra scene.
+
+use serde::{Deserialize, Serialize};
+use log::{info, debug, warn};
+use crate::{AIProvider, StoryChain, StoryChainError};
+
+/// Who marked a setup as planted
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SetupSource {
+    /// Declared by the model in a generated scene
+    #[default]
+    Model,
+
+    /// Declared by the user
+    User,
+}
+
+/// A planted story element awaiting its payoff
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Setup {
+    /// Short description of the planted element
+    pub description: String,
+
+    /// ID of the node where the element was planted, if known
+    pub planted_in: Option<String>,
+
+    /// ID of the node that paid the element off, once it has been
+    pub paid_off_in: Option<String>,
+
+    /// Who marked the element as planted
+    #[serde(default)]
+    pub source: SetupSource,
+}
+
+/// Setup markers removed from a generated scene
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SetupMarkers {
+    /// Descriptions from `PLANTED:` lines
+    pub planted: Vec<String>,
+
+    /// References from `PAYOFF:` lines, either a setup number or a description
+    pub payoffs: Vec<String>,
+}
+
+/// Instruction appended to prompts when setup tracking is enabled
+const SETUP_INSTRUCTIONS: &str = "If this scene plants a detail that must matter later (an object, \
+    a secret, a threat, a skill), add a line at the end reading PLANTED: followed by a short description. \
+    If it pays off an earlier setup, add a line reading PAYOFF: followed by that setup's number.";
+
+/// Removes `PLANTED:` and `PAYOFF:` lines from generated content
+///
+/// # Arguments
+/// * `content` - The generated scene content
+///
+/// # Returns
+/// The content without marker lines, and the markers that were found
+pub fn extract_setup_markers(content: &str) -> (String, SetupMarkers) {
+    let mut markers = SetupMarkers::default();
+    let mut kept = Vec::new();
+
+    for line in content.lines() {
+        let stripped = line.trim().trim_start_matches(['*', '-', ' ']);
+        let marker = stripped.split_once(':').and_then(|(head, rest)| {
+            let value = rest.trim_start_matches('*').trim().to_string();
+            match head.trim_end_matches('*').trim().to_uppercase().as_str() {
+                "PLANTED" => Some((true, value)),
+                "PAYOFF" => Some((false, value)),
+                _ => None,
+            }
+        });
+
+        match marker {
+            Some((_, value)) if value.is_empty() => {}
+            Some((true, value)) => markers.planted.push(value),
+            Some((false, value)) => markers.payoffs.push(value),
+            None => kept.push(line),
+        }
+    }
+
+    (kept.join("\n").trim().to_string(), markers)
+}
+
+impl StoryChain {
+    /// Records a setup on the chain
+    ///
+    /// # Arguments
+    /// * `description` - Short description of the planted element
+    /// * `node_id` - ID of the node where the element is planted, if known
+    /// * `source` - Who marked the element as planted
+    pub fn plant_setup(&mut self, description: &str, node_id: Option<&str>, source: SetupSource) {
+        debug!("Planting setup: {}", description);
+        self.setups.push(Setup {
+            description: description.trim().to_string(),
+            planted_in: node_id.map(str::to_string),
+            paid_off_in: None,
+            source,
+        });
+    }
+
+    /// Returns the setups that have not been paid off, in the order they were planted
+    pub fn unresolved_setups(&self) -> Vec<&Setup> {
+        self.setups.iter().filter(|s| s.paid_off_in.is_none()).collect()
+    }
+
+    /// Marks an open setup as paid off by the given node
+    ///
+    /// # Arguments
+    /// * `reference` - The setup's number among the open setups (as listed in
+    ///   prompts, starting at 1) or text matching its description
+    /// * `node_id` - ID of the node containing the payoff
+    ///
+    /// # Returns
+    /// Whether a matching open setup was found
+    pub fn pay_off_setup(&mut self, reference: &str, node_id: &str) -> bool {
+        let reference = reference.trim().trim_start_matches(['#', '[']).to_lowercase();
+        let open: Vec<usize> = (0..self.setups.len()).filter(|&i| self.setups[i].paid_off_in.is_none()).collect();
+
+        let number: String = reference.chars().take_while(char::is_ascii_digit).collect();
+        let index = number
+            .parse::<usize>()
+            .ok()
+            .and_then(|n| open.get(n.checked_sub(1)?).copied())
+            .or_else(|| {
+                open.iter().copied().find(|&i| {
+                    let description = self.setups[i].description.to_lowercase();
+                    description.contains(&reference) || reference.contains(&description)
+                })
+            });
+
+        match index {
+            Some(index) => {
+                debug!("Setup '{}' paid off in {}", self.setups[index].description, node_id);
+                self.setups[index].paid_off_in = Some(node_id.to_string());
+                true
+            }
+            None => {
+                warn!("Scene {} claims a payoff for an unknown setup: {}", node_id, reference);
+                false
+            }
+        }
+    }
+
+    /// Applies the markers extracted from a generated scene to the ledger
+    ///
+    /// Payoffs are applied before new plants so that setup numbers refer to
+    /// the list the model was shown.
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node the markers came from
+    /// * `markers` - The markers extracted from the scene
+    pub fn record_setup_markers(&mut self, node_id: &str, markers: &SetupMarkers) {
+        for payoff in &markers.payoffs {
+            self.pay_off_setup(payoff, node_id);
+        }
+        for planted in &markers.planted {
+            self.plant_setup(planted, Some(node_id), SetupSource::Model);
+        }
+    }
+
+    /// Removes the setups planted in a node and reopens the ones it paid off,
+    /// when the node is discarded or its scene replaced
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node
+    pub fn forget_setups_of(&mut self, node_id: &str) {
+        self.setups.retain(|s| s.planted_in.as_deref() != Some(node_id));
+        for setup in &mut self.setups {
+            if setup.paid_off_in.as_deref() == Some(node_id) {
+                setup.paid_off_in = None;
+            }
+        }
+    }
+
+    /// Builds the setup-tracking guidance, listing the setups still awaiting a payoff
+    pub fn setup_guidance(&self) -> Option<String> {
+        if !self.settings.track_setups {
+            return None;
+        }
+
+        let mut note = SETUP_INSTRUCTIONS.to_string();
+        let open = self.unresolved_setups();
+        if !open.is_empty() {
+            let listed: Vec<String> = open
+                .iter()
+                .enumerate()
+                .map(|(index, setup)| format!("[{}] {}", index + 1, setup.description))
+                .collect();
+            note.push_str(&format!(" Setups still awaiting a payoff: {}.", listed.join("; ")));
+        }
+        Some(note)
+    }
+
+    /// Logs a warning for every setup that was never paid off
+    ///
+    /// # Returns
+    /// The number of unresolved setups
+    pub fn warn_unresolved_setups(&self) -> usize {
+        let open = self.unresolved_setups();
+        for setup in &open {
+            match &setup.planted_in {
+                Some(node_id) => warn!("Setup planted in {} was never paid off: {}", node_id, setup.description),
+                None => warn!("Setup was never paid off: {}", setup.description),
+            }
+        }
+        open.len()
+    }
+
+    /// Appends a scene that pays off every unresolved setup
+    ///
+    /// The scene follows the last node in causal order and is generated with
+    /// the usual continuation prompt, with the open setups added to the premise.
+    ///
+    /// # Arguments
+    /// * `ai_provider` - The AI provider to use for generation
+    /// * `premise` - Optional premise to include in generation
+    ///
+    /// # Returns
+    /// The ID of the new node, or `None` when there was nothing to resolve
+    pub async fn generate_resolving_scene(
+        &mut self,
+        ai_provider: &dyn AIProvider,
+        premise: Option<&str>,
+    ) -> Result<Option<String>, StoryChainError> {
+        let open: Vec<String> = self.unresolved_setups().iter().map(|s| s.description.clone()).collect();
+        if open.is_empty() {
+            return Ok(None);
+        }
+        info!("Generating a scene to resolve {} open setups", open.len());
+
+        let last_id = self
+            .nodes_in_order()
+            .last()
+            .map(|n| n.id.clone())
+            .unwrap_or_else(|| self.root_node_id.clone());
+        let resolution = format!(
+            "{}This scene must pay off every setup the story has left open:\n- {}",
+            premise.map(|p| format!("{}\n\n", p)).unwrap_or_default(),
+            open.join("\n- ")
+        );
+        let scenes = self.nodes.len();
+        let new_ids = self
+            .generate_next_nodes(&last_id, ai_provider, Some(&resolution), scenes, scenes)
+            .await?;
+
+        Ok(new_ids.into_iter().next())
+    }
+}
@@ -0,0 +1,161 @@
+//! Reading-Time and Pacing Statistics
+//!
+//! This module computes per-scene and whole-story statistics for a chain:
+//! word counts, estimated reading time, and a pacing analysis that compares
+//! each scene's length with its tension score. Tension is taken from a node's
+//! `tension` metadata when present and otherwise estimated from the text.
+
+use serde::Serialize;
+use crate::StoryChain;
+
+/// Average adult silent reading speed used when none is specified
+pub const DEFAULT_WORDS_PER_MINUTE: usize = 238;
+
+/// Words that tend to appear in high-tension prose
+const TENSION_WORDS: [&str; 24] = [
+    "blood", "scream", "gun", "knife", "dead", "death", "kill", "fear", "run", "ran", "shot",
+    "fight", "threat", "danger", "panic", "heart", "pounding", "shout", "attack", "trapped",
+    "betray", "chase", "explosion", "terror",
+];
+
+/// Statistics for a single scene
+#[derive(Debug, Clone, Serialize)]
+pub struct SceneStats {
+    /// ID of the node
+    pub node_id: String,
+
+    /// Position of the scene in reading order, starting at 1
+    pub scene_number: usize,
+
+    /// Number of words in the scene
+    pub word_count: usize,
+
+    /// Estimated reading time in minutes
+    pub reading_minutes: f64,
+
+    /// Tension score from 0 (calm) to 10 (peak)
+    pub tension: f64,
+
+    /// Short pacing assessment of the scene
+    pub pacing: String,
+}
+
+/// Statistics for a whole story
+#[derive(Debug, Clone, Serialize)]
+pub struct StoryStats {
+    /// Per-scene statistics in reading order
+    pub scenes: Vec<SceneStats>,
+
+    /// Total number of words in the story
+    pub total_words: usize,
+
+    /// Estimated total reading time in minutes
+    pub total_reading_minutes: f64,
+
+    /// Reading speed the estimates are based on
+    pub words_per_minute: usize,
+}
+
+/// Estimates the tension of a passage on a 0-10 scale from the density of
+/// tension vocabulary, exclamations, and short sentences
+///
+/// # Arguments
+/// * `text` - The passage to score
+pub fn estimate_tension(text: &str) -> f64 {
+    let words: Vec<String> = text
+        .split_whitespace()
+        .map(|w| w.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase())
+        .filter(|w| !w.is_empty())
+        .collect();
+    if words.is_empty() {
+        return 0.0;
+    }
+
+    let tension_words = words.iter().filter(|w| TENSION_WORDS.contains(&w.as_str())).count();
+    let exclamations = text.matches('!').count();
+    let sentences: Vec<&str> = text.split(['.', '!', '?']).filter(|s| !s.trim().is_empty()).collect();
+    let short_sentences = sentences.iter().filter(|s| s.split_whitespace().count() <= 6).count();
+
+    let per_hundred = |count: usize| count as f64 * 100.0 / words.len() as f64;
+    let short_ratio = short_sentences as f64 / sentences.len().max(1) as f64;
+    let score = per_hundred(tension_words) * 2.0 + per_hundred(exclamations) + short_ratio * 4.0;
+
+    (score.min(10.0) * 10.0).round() / 10.0
+}
+
+/// Describes the pace of a scene from its relative length and tension
+fn pacing_label(word_count: usize, average_words: f64, tension: f64) -> String {
+    let relative = word_count as f64 / average_words.max(1.0);
+    match (relative, tension) {
+        (r, t) if r > 1.3 && t < 4.0 => "slow: long, low-tension scene may drag".to_string(),
+        (r, t) if r < 0.7 && t >= 6.0 => "fast: short, high-tension scene".to_string(),
+        (r, _) if r < 0.7 => "brisk: shorter than average".to_string(),
+        (_, t) if t >= 6.0 => "intense".to_string(),
+        _ => "steady".to_string(),
+    }
+}
+
+impl StoryChain {
+    /// Computes reading-time and pacing statistics for the chain in reading order
+    ///
+    /// # Arguments
+    /// * `words_per_minute` - Reading speed used for time estimates
+    pub fn stats(&self, words_per_minute: usize) -> StoryStats {
+        let nodes = self.nodes_in_reading_order();
+        let counts: Vec<usize> = nodes.iter().map(|n| n.content.split_whitespace().count()).collect();
+        let total_words: usize = counts.iter().sum();
+        let average_words = total_words as f64 / counts.len().max(1) as f64;
+        let minutes = |words: usize| words as f64 / words_per_minute.max(1) as f64;
+
+        let scenes = nodes
+            .iter()
+            .zip(&counts)
+            .enumerate()
+            .map(|(index, (node, &word_count))| {
+                let tension = node
+                    .metadata
+                    .get("tension")
+                    .and_then(|t| t.parse::<f64>().ok())
+                    .unwrap_or_else(|| estimate_tension(&node.content));
+                SceneStats {
+                    node_id: node.id.clone(),
+                    scene_number: index + 1,
+                    word_count,
+                    reading_minutes: minutes(word_count),
+                    tension,
+                    pacing: pacing_label(word_count, average_words, tension),
+                }
+            })
+            .collect();
+
+        StoryStats {
+            scenes,
+            total_words,
+            total_reading_minutes: minutes(total_words),
+            words_per_minute,
+        }
+    }
+}
+
+impl StoryStats {
+    /// Renders the statistics as a markdown report with a per-scene table
+    pub fn to_markdown(&self) -> String {
+        let mut report = String::from("## Appendix: Reading Time and Pacing\n\n");
+        report.push_str(&format!(
+            "{} words, about {:.0} minutes at {} words per minute.\n\n",
+            self.total_words,
+            self.total_reading_minutes.ceil(),
+            self.words_per_minute
+        ));
+        report.push_str("| Scene | Words | Minutes | Tension | Pacing |\n");
+        report.push_str("|------:|------:|--------:|--------:|:-------|\n");
+        for scene in &self.scenes {
+            report.push_str(&format!(
+                "| {} | {} | {:.1} | {:.1} | {} |\n",
+                scene.scene_number, scene.word_count, scene.reading_minutes, scene.tension, scene.pacing
+            ));
+        }
+        report.push('\n');
+        report
+    }
+}
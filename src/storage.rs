@@ -0,0 +1,482 @@
+//! Chain Storage
+//!
+//! This module abstracts where story chains are kept behind the `ChainStore`
+//! trait: whole chains are saved and loaded by name, and scenes generated
+//! during a run are appended as they are written, so the generation code
+//! does not depend on the backend. `JsonFileStore`, the default, keeps each
+//! chain in a JSON file of a directory; `MemoryStore` keeps chains in memory
+//! for tests. With the `sqlite` feature, `SqliteStore` keeps many chains, and
+//! the artifacts they were generated with, in one SQLite database, which
+//! scales to hundreds of nodes across many projects better than JSON files.
+//! Stores are opened from URLs such as `sqlite://stories.db`, `file://stories`,
+//! or a plain directory path.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use log::{info, debug};
+use crate::files::{GZIP_EXTENSION, ZSTD_EXTENSION};
+use crate::{StoryChain, StoryChainError};
+
+/// URL scheme of SQLite stores
+pub const SQLITE_SCHEME: &str = "sqlite://";
+
+/// URL scheme of JSON file stores, which plain paths also open
+pub const FILE_SCHEME: &str = "file://";
+
+/// URL of an in-memory store
+pub const MEMORY_URL: &str = "memory://";
+
+/// Persists story chains by name
+pub trait ChainStore: Send + Sync {
+    /// Saves a whole chain, replacing any chain stored under the same name
+    ///
+    /// # Arguments
+    /// * `name` - Name the chain is stored under
+    /// * `chain` - The chain to save
+    fn save_chain(&self, name: &str, chain: &StoryChain) -> Result<(), StoryChainError>;
+
+    /// Loads a chain
+    ///
+    /// # Arguments
+    /// * `name` - Name the chain is stored under
+    ///
+    /// # Returns
+    /// The chain, or an error when no chain is stored under the name
+    fn load_chain(&self, name: &str) -> Result<StoryChain, StoryChainError>;
+
+    /// Saves a node newly added to a chain, along with the nodes linked to
+    /// it and the chain-level fields, without rewriting the other nodes
+    ///
+    /// # Arguments
+    /// * `name` - Name the chain is stored under
+    /// * `chain` - The chain the node was added to
+    /// * `node_id` - ID of the new node
+    fn append_node(&self, name: &str, chain: &StoryChain, node_id: &str) -> Result<(), StoryChainError>;
+
+    /// Saves several nodes newly added to a chain, such as the scenes of an
+    /// epoch
+    ///
+    /// # Arguments
+    /// * `name` - Name the chain is stored under
+    /// * `chain` - The chain the nodes were added to
+    /// * `node_ids` - IDs of the new nodes
+    fn append_nodes(&self, name: &str, chain: &StoryChain, node_ids: &[String]) -> Result<(), StoryChainError> {
+        for node_id in node_ids {
+            self.append_node(name, chain, node_id)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the names of the stored chains, sorted
+    fn list_chains(&self) -> Result<Vec<String>, StoryChainError>;
+}
+
+impl std::fmt::Debug for dyn ChainStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ChainStore")
+    }
+}
+
+/// Opens the store a URL points to
+///
+/// # Arguments
+/// * `url` - The store URL: `sqlite://stories.db`, `memory://`, or a
+///   directory of JSON files as `file://stories` or a plain path
+pub fn open_store(url: &str) -> Result<Box<dyn ChainStore>, StoryChainError> {
+    if url == MEMORY_URL {
+        return Ok(Box::new(MemoryStore::default()));
+    }
+    if let Some(dir) = url.strip_prefix(FILE_SCHEME) {
+        return Ok(Box::new(JsonFileStore::new(dir)));
+    }
+    match url.strip_prefix(SQLITE_SCHEME) {
+        #[cfg(feature = "sqlite")]
+        Some(path) => Ok(Box::new(SqliteStore::open(path)?)),
+        #[cfg(not(feature = "sqlite"))]
+        Some(_) => Err(StoryChainError::ConfigError(
+            "SQLite stores are not available; rebuild with --features sqlite".to_string(),
+        )),
+        None if url.contains("://") => Err(StoryChainError::ConfigError(format!("Unsupported store URL: {}", url))),
+        None => Ok(Box::new(JsonFileStore::new(url))),
+    }
+}
+
+/// Returns the error for a chain missing from a store
+fn missing_chain(name: &str) -> StoryChainError {
+    StoryChainError::StorageError(format!("No chain named {} in the store", name))
+}
+
+/// Keeps each chain in a JSON file named after it, in the format written by
+/// `StoryChain::export_to_file`
+///
+/// JSON files cannot be appended to, so appending nodes rewrites the file,
+/// atomically. Files are compressed when the store's extension is `json.gz`
+/// or `json.zst`.
+#[derive(Debug, Clone)]
+pub struct JsonFileStore {
+    /// Directory holding the chain files
+    dir: PathBuf,
+
+    /// Extension of the chain files, without the leading dot
+    extension: String,
+}
+
+impl JsonFileStore {
+    /// Creates a store over a directory, which is created on the first save
+    ///
+    /// # Arguments
+    /// * `dir` - Directory holding the chain files
+    pub fn new(dir: impl AsRef<Path>) -> Self {
+        Self { dir: dir.as_ref().to_path_buf(), extension: "json".to_string() }
+    }
+
+    /// Sets the extension of the chain files, such as `json.gz` to compress them
+    ///
+    /// # Arguments
+    /// * `extension` - The extension, without the leading dot
+    pub fn with_extension(mut self, extension: &str) -> Self {
+        self.extension = extension.trim_start_matches('.').to_string();
+        self
+    }
+
+    /// Creates a store over the directory of a chain file, returning it with
+    /// the name the file is stored under
+    ///
+    /// # Arguments
+    /// * `path` - Path of a chain's JSON file, such as `stories/dragon.json`
+    ///   or `stories/dragon.json.gz`
+    pub fn for_file(path: &str) -> (Self, String) {
+        let path = Path::new(path);
+        let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+        let store = Self::new(path.parent().unwrap_or(Path::new("")));
+        for extension in [GZIP_EXTENSION, ZSTD_EXTENSION] {
+            if let Some(name) = file_name.strip_suffix(extension) {
+                return (store.with_extension(extension), name.to_string());
+            }
+        }
+        match file_name.rsplit_once('.') {
+            Some((name, extension)) => (store.with_extension(extension), name.to_string()),
+            None => (store, file_name),
+        }
+    }
+
+    /// Returns the path of the file a chain is stored in
+    ///
+    /// # Arguments
+    /// * `name` - Name the chain is stored under
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", name, self.extension))
+    }
+}
+
+impl ChainStore for JsonFileStore {
+    fn save_chain(&self, name: &str, chain: &StoryChain) -> Result<(), StoryChainError> {
+        if !self.dir.as_os_str().is_empty() {
+            std::fs::create_dir_all(&self.dir)?;
+        }
+        chain.export_to_file(&self.path(name).to_string_lossy())
+    }
+
+    fn load_chain(&self, name: &str) -> Result<StoryChain, StoryChainError> {
+        let path = self.path(name);
+        if !path.exists() {
+            return Err(missing_chain(name));
+        }
+        StoryChain::load_from_file(&path.to_string_lossy())
+    }
+
+    fn append_node(&self, name: &str, chain: &StoryChain, node_id: &str) -> Result<(), StoryChainError> {
+        self.append_nodes(name, chain, &[node_id.to_string()])
+    }
+
+    fn append_nodes(&self, name: &str, chain: &StoryChain, node_ids: &[String]) -> Result<(), StoryChainError> {
+        debug!("Rewriting chain {} for {} new nodes", name, node_ids.len());
+        self.save_chain(name, chain)
+    }
+
+    fn list_chains(&self) -> Result<Vec<String>, StoryChainError> {
+        if !self.dir.as_os_str().is_empty() && !self.dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let dir = if self.dir.as_os_str().is_empty() { Path::new(".") } else { self.dir.as_path() };
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let file_name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+            if let Some(name) = file_name.strip_suffix(&format!(".{}", self.extension)) {
+                names.push(name.to_string());
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+}
+
+/// Keeps chains in memory, serialized so that loaded chains are independent
+/// copies, for tests
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    /// Serialized chains by name
+    chains: Mutex<BTreeMap<String, String>>,
+}
+
+impl MemoryStore {
+    /// Locks the stored chains
+    fn chains(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, String>> {
+        self.chains.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl ChainStore for MemoryStore {
+    fn save_chain(&self, name: &str, chain: &StoryChain) -> Result<(), StoryChainError> {
+        info!("Saving chain {} in memory", name);
+        self.chains().insert(name.to_string(), serde_json::to_string(chain)?);
+        Ok(())
+    }
+
+    fn load_chain(&self, name: &str) -> Result<StoryChain, StoryChainError> {
+        let chains = self.chains();
+        let data = chains.get(name).ok_or_else(|| missing_chain(name))?;
+        Ok(serde_json::from_str(data)?)
+    }
+
+    fn append_node(&self, name: &str, chain: &StoryChain, _node_id: &str) -> Result<(), StoryChainError> {
+        self.save_chain(name, chain)
+    }
+
+    fn list_chains(&self) -> Result<Vec<String>, StoryChainError> {
+        Ok(self.chains().keys().cloned().collect())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+/// Serializes the chain-level fields of a chain, leaving out its nodes
+fn chain_header(chain: &StoryChain) -> Result<String, StoryChainError> {
+    let mut header = serde_json::to_value(chain)?;
+    header["nodes"] = serde_json::Value::Object(serde_json::Map::new());
+    Ok(header.to_string())
+}
+
+#[cfg(feature = "sqlite")]
+/// Splits a chain into its chain-level fields and its nodes, serialized
+fn split_chain(chain: &StoryChain) -> Result<(String, Vec<(String, String)>), StoryChainError> {
+    let nodes = chain
+        .nodes
+        .values()
+        .map(|node| Ok((node.id.clone(), serde_json::to_string(node)?)))
+        .collect::<Result<Vec<_>, StoryChainError>>()?;
+    Ok((chain_header(chain)?, nodes))
+}
+
+#[cfg(feature = "sqlite")]
+/// Rebuilds a chain from its chain-level fields and its serialized nodes
+fn join_chain(name: &str, header: &str, nodes: Vec<(String, String)>) -> Result<StoryChain, StoryChainError> {
+    let mut value: serde_json::Value = serde_json::from_str(header)?;
+    let mut map = serde_json::Map::new();
+    for (id, node) in nodes {
+        map.insert(id, serde_json::from_str(&node)?);
+    }
+    value["nodes"] = serde_json::Value::Object(map);
+    let chain = crate::migrations::chain_from_value(value)?;
+    if !chain.nodes.contains_key(&chain.root_node_id) {
+        return Err(StoryChainError::InvalidChainOperation(format!(
+            "Stored chain {} has no root node {}",
+            name, chain.root_node_id
+        )));
+    }
+    Ok(chain)
+}
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+
+#[cfg(feature = "sqlite")]
+mod sqlite {
+    use std::sync::Mutex;
+    use log::{info, debug};
+    use rusqlite::{params, Connection, OptionalExtension};
+    use crate::artifacts::Artifact;
+    use crate::{StoryChain, StoryChainError};
+    use super::{chain_header, join_chain, missing_chain, split_chain, ChainStore};
+
+    /// Tables of a store, created when it is opened
+    const SCHEMA: &str = "
+        CREATE TABLE IF NOT EXISTS chains (
+            name TEXT PRIMARY KEY,
+            header TEXT NOT NULL,
+            updated_at TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS nodes (
+            chain TEXT NOT NULL REFERENCES chains(name) ON DELETE CASCADE,
+            id TEXT NOT NULL,
+            data TEXT NOT NULL,
+            PRIMARY KEY (chain, id)
+        );
+        CREATE TABLE IF NOT EXISTS artifacts (
+            chain TEXT NOT NULL,
+            id TEXT NOT NULL,
+            data TEXT NOT NULL,
+            PRIMARY KEY (chain, id)
+        );";
+
+    /// Converts a SQLite error
+    fn sql_error(e: rusqlite::Error) -> StoryChainError {
+        StoryChainError::StorageError(e.to_string())
+    }
+
+    /// Keeps chains, one row per node, and their artifacts in a SQLite database
+    #[derive(Debug)]
+    pub struct SqliteStore {
+        /// Connection to the database
+        connection: Mutex<Connection>,
+    }
+
+    impl SqliteStore {
+        /// Opens a database file, creating it and its tables when needed
+        ///
+        /// # Arguments
+        /// * `path` - Path of the database file
+        pub fn open(path: &str) -> Result<Self, StoryChainError> {
+            info!("Opening SQLite store {}", path);
+            Self::from_connection(Connection::open(path).map_err(sql_error)?)
+        }
+
+        /// Opens a database that only lives in memory, for tests
+        pub fn in_memory() -> Result<Self, StoryChainError> {
+            Self::from_connection(Connection::open_in_memory().map_err(sql_error)?)
+        }
+
+        /// Creates the tables on a new connection
+        fn from_connection(connection: Connection) -> Result<Self, StoryChainError> {
+            connection.execute_batch("PRAGMA foreign_keys = ON;").map_err(sql_error)?;
+            connection.execute_batch(SCHEMA).map_err(sql_error)?;
+            Ok(Self { connection: Mutex::new(connection) })
+        }
+
+        /// Locks the connection
+        fn connection(&self) -> std::sync::MutexGuard<'_, Connection> {
+            self.connection.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+        }
+
+        /// Saves an artifact a chain was generated with
+        ///
+        /// # Arguments
+        /// * `name` - Name of the chain
+        /// * `artifact` - The artifact to save, replacing one with the same ID
+        pub fn save_artifact(&self, name: &str, artifact: &Artifact) -> Result<(), StoryChainError> {
+            self.connection()
+                .execute(
+                    "INSERT OR REPLACE INTO artifacts (chain, id, data) VALUES (?1, ?2, ?3)",
+                    params![name, artifact.id, serde_json::to_string(artifact)?],
+                )
+                .map_err(sql_error)?;
+            Ok(())
+        }
+
+        /// Loads the artifacts saved for a chain, sorted by ID
+        ///
+        /// # Arguments
+        /// * `name` - Name of the chain
+        pub fn load_artifacts(&self, name: &str) -> Result<Vec<Artifact>, StoryChainError> {
+            let connection = self.connection();
+            let mut statement = connection
+                .prepare("SELECT data FROM artifacts WHERE chain = ?1 ORDER BY id")
+                .map_err(sql_error)?;
+            let rows = statement
+                .query_map(params![name], |row| row.get::<_, String>(0))
+                .map_err(sql_error)?
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(sql_error)?;
+            rows.iter().map(|data| Ok(serde_json::from_str(data)?)).collect()
+        }
+    }
+
+    impl ChainStore for SqliteStore {
+        fn save_chain(&self, name: &str, chain: &StoryChain) -> Result<(), StoryChainError> {
+            let (header, nodes) = split_chain(chain)?;
+            let mut connection = self.connection();
+            let transaction = connection.transaction().map_err(sql_error)?;
+            transaction
+                .execute(
+                    "INSERT INTO chains (name, header, updated_at) VALUES (?1, ?2, ?3)
+                     ON CONFLICT(name) DO UPDATE SET header = excluded.header, updated_at = excluded.updated_at",
+                    params![name, header, chrono::Utc::now().to_rfc3339()],
+                )
+                .map_err(sql_error)?;
+            transaction.execute("DELETE FROM nodes WHERE chain = ?1", params![name]).map_err(sql_error)?;
+            for (id, data) in &nodes {
+                transaction
+                    .execute("INSERT INTO nodes (chain, id, data) VALUES (?1, ?2, ?3)", params![name, id, data])
+                    .map_err(sql_error)?;
+            }
+            transaction.commit().map_err(sql_error)?;
+            debug!("Saved chain {} with {} nodes", name, nodes.len());
+            Ok(())
+        }
+
+        fn load_chain(&self, name: &str) -> Result<StoryChain, StoryChainError> {
+            let connection = self.connection();
+            let header: Option<String> = connection
+                .query_row("SELECT header FROM chains WHERE name = ?1", params![name], |row| row.get(0))
+                .optional()
+                .map_err(sql_error)?;
+            let header = header.ok_or_else(|| missing_chain(name))?;
+            let mut statement = connection.prepare("SELECT id, data FROM nodes WHERE chain = ?1").map_err(sql_error)?;
+            let nodes = statement
+                .query_map(params![name], |row| Ok((row.get(0)?, row.get(1)?)))
+                .map_err(sql_error)?
+                .collect::<Result<Vec<(String, String)>, _>>()
+                .map_err(sql_error)?;
+            info!("Loaded chain {} with {} nodes from the store", name, nodes.len());
+            join_chain(name, &header, nodes)
+        }
+
+        fn append_node(&self, name: &str, chain: &StoryChain, node_id: &str) -> Result<(), StoryChainError> {
+            let node = chain
+                .nodes
+                .get(node_id)
+                .ok_or_else(|| StoryChainError::InvalidChainOperation(format!("Node {} not found", node_id)))?;
+            let exists: bool = self
+                .connection()
+                .query_row("SELECT EXISTS(SELECT 1 FROM chains WHERE name = ?1)", params![name], |row| row.get(0))
+                .map_err(sql_error)?;
+            if !exists {
+                return self.save_chain(name, chain);
+            }
+
+            let header = chain_header(chain)?;
+            let mut connection = self.connection();
+            let transaction = connection.transaction().map_err(sql_error)?;
+            transaction
+                .execute(
+                    "UPDATE chains SET header = ?2, updated_at = ?3 WHERE name = ?1",
+                    params![name, header, chrono::Utc::now().to_rfc3339()],
+                )
+                .map_err(sql_error)?;
+            // The predecessors' successor lists changed along with the new node
+            for linked in std::iter::once(node).chain(node.predecessors.iter().filter_map(|id| chain.nodes.get(id))) {
+                transaction
+                    .execute(
+                        "INSERT OR REPLACE INTO nodes (chain, id, data) VALUES (?1, ?2, ?3)",
+                        params![name, linked.id, serde_json::to_string(linked)?],
+                    )
+                    .map_err(sql_error)?;
+            }
+            transaction.commit().map_err(sql_error)?;
+            debug!("Appended node {} to chain {}", node_id, name);
+            Ok(())
+        }
+
+        fn list_chains(&self) -> Result<Vec<String>, StoryChainError> {
+            let connection = self.connection();
+            let mut statement = connection.prepare("SELECT name FROM chains ORDER BY name").map_err(sql_error)?;
+            let names = statement
+                .query_map([], |row| row.get(0))
+                .map_err(sql_error)?
+                .collect::<Result<Vec<String>, _>>()
+                .map_err(sql_error)?;
+            Ok(names)
+        }
+    }
+}
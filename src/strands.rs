@@ -0,0 +1,186 @@
+//! Parallel Storylines
+//!
+//! This module tells a story as several parallel strands, such as the same
+//! events seen by two viewpoint characters. Each strand is a sub-chain with
+//! its own premise and viewpoint: a strand scene continues from the strand's
+//! previous scene, while a scheduler interleaves the strands into one chain.
+//! All strands share the chain's running summary, so each one knows what
+//! happened in the others. Exports either keep the interleaved order or
+//! group each strand's scenes together.
+
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use log::{info, debug};
+use crate::artifacts::Artifact;
+use crate::{AIProvider, StoryChain, StoryChainError};
+
+/// Node metadata key holding the ID of the strand a scene belongs to
+pub const STRAND_KEY: &str = "strand";
+
+/// A storyline told in parallel with the others
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Strand {
+    /// Unique identifier, stored in the `strand` metadata of its scenes
+    pub id: String,
+
+    /// Display name of the strand
+    pub name: String,
+
+    /// Viewpoint character of the strand's scenes, if it has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pov: Option<String>,
+
+    /// What the strand is about and where it should go
+    pub premise: String,
+
+    /// IDs of the strand's scenes, in order
+    #[serde(default)]
+    pub node_ids: Vec<String>,
+}
+
+impl Strand {
+    /// Creates a new strand with no scenes yet
+    pub fn new(id: String, name: String, pov: Option<String>, premise: String) -> Self {
+        Self { id, name, pov, premise, node_ids: Vec::new() }
+    }
+
+    /// Creates a strand from an artifact
+    ///
+    /// The artifact's content is the strand premise; its `name` and `pov`
+    /// metadata set the display name and viewpoint character.
+    ///
+    /// # Arguments
+    /// * `artifact` - The artifact describing the strand
+    pub fn from_artifact(artifact: &Artifact) -> Self {
+        let name = artifact.metadata.get("name").cloned().unwrap_or_else(|| artifact.id.clone());
+        let pov = artifact.metadata.get("pov").cloned().filter(|p| !p.trim().is_empty());
+        Self::new(artifact.id.clone(), name, pov, artifact.content.clone())
+    }
+}
+
+/// How the scenes of parallel strands are ordered in exports
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum StrandOrder {
+    /// In the order they were generated, alternating between strands
+    #[default]
+    Interleaved,
+
+    /// Each strand's scenes together, strand after strand
+    Grouped,
+}
+
+impl FromStr for StrandOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "interleaved" => Ok(StrandOrder::Interleaved),
+            "grouped" => Ok(StrandOrder::Grouped),
+            other => Err(format!("Unknown strand order: {}", other)),
+        }
+    }
+}
+
+impl StoryChain {
+    /// Returns the index of the strand whose scene comes next
+    ///
+    /// The strand with the fewest scenes goes next, earlier strands first on
+    /// a tie, so strands alternate in the order they were added.
+    pub fn next_strand(&self) -> Option<usize> {
+        (0..self.strands.len()).min_by_key(|&i| self.strands[i].node_ids.len())
+    }
+
+    /// Generates the next scene of the scheduled strand and attaches it
+    /// after the given node
+    ///
+    /// The scene continues from the strand's previous scene, or from the
+    /// given node when the strand has not started yet. The running summary
+    /// and the given node's scene, when it belongs to another strand, keep
+    /// the scene consistent with the other strands.
+    ///
+    /// # Arguments
+    /// * `current_node_id` - ID of the node the strand scene is attached after
+    /// * `ai_provider` - The AI provider to use for generation
+    /// * `premise` - Optional premise of the whole story
+    /// * `current_epoch` - Current epoch number
+    /// * `total_epochs` - Total number of epochs planned
+    pub async fn generate_strand_scene(
+        &mut self,
+        current_node_id: &str,
+        ai_provider: &dyn AIProvider,
+        premise: Option<&str>,
+        current_epoch: usize,
+        total_epochs: usize,
+    ) -> Result<String, StoryChainError> {
+        let strand_index = self
+            .next_strand()
+            .ok_or_else(|| StoryChainError::InvalidChainOperation("The chain has no strands".to_string()))?;
+        let strand = self.strands[strand_index].clone();
+        info!("Generating scene {} of strand {}", strand.node_ids.len() + 1, strand.name);
+
+        let context_id = strand.node_ids.last().cloned().unwrap_or_else(|| current_node_id.to_string());
+        let mut strand_premise = format!(
+            "{}Storyline: {}\n{}\n\n{}This scene belongs to the storyline above. Continue it from its previous \
+            scene; the story so far also covers the other storylines told in parallel, so stay consistent with them.",
+            premise.map(|p| format!("{}\n\n", p)).unwrap_or_default(),
+            strand.name,
+            strand.premise,
+            strand
+                .pov
+                .as_ref()
+                .map(|pov| format!("Write this scene from the point of view of {}.\n\n", pov))
+                .unwrap_or_default()
+        );
+        // The summary stops before the latest scene, which may belong to another strand
+        if context_id != current_node_id {
+            if let Some(latest) = self.nodes.get(current_node_id) {
+                strand_premise.push_str(&format!("\n\nLatest Scene, From Another Storyline:\n{}", latest.content.trim()));
+            }
+        }
+
+        let new_ids = self
+            .generate_scene(current_node_id, &context_id, ai_provider, Some(&strand_premise), current_epoch, total_epochs)
+            .await?;
+        let new_id = new_ids.into_iter().next().ok_or_else(|| {
+            StoryChainError::AIServerError(format!("No scene generated for strand {}", strand.name))
+        })?;
+
+        if let Some(node) = self.nodes.get_mut(&new_id) {
+            node.metadata.insert(STRAND_KEY.to_string(), strand.id.clone());
+            if let Some(pov) = &strand.pov {
+                node.metadata.insert("pov".to_string(), pov.clone());
+            }
+        }
+        self.strands[strand_index].node_ids.push(new_id.clone());
+        debug!("Interleaved strand scene {} into the chain", new_id);
+        Ok(new_id)
+    }
+
+    /// Sets the reading order of the strands' scenes
+    ///
+    /// Scenes before the strands start come first and scenes outside any
+    /// strand after them last. Interleaved order is the causal order;
+    /// grouped order lists each strand's scenes in turn.
+    ///
+    /// # Arguments
+    /// * `order` - The export order policy
+    pub fn arrange_strands(&mut self, order: StrandOrder) -> Result<(), StoryChainError> {
+        match order {
+            StrandOrder::Interleaved => {
+                self.reset_reading_order();
+                Ok(())
+            }
+            StrandOrder::Grouped => {
+                let mut ids: Vec<String> = self
+                    .nodes_in_order()
+                    .iter()
+                    .take_while(|node| !node.metadata.contains_key(STRAND_KEY))
+                    .map(|node| node.id.clone())
+                    .collect();
+                ids.extend(self.strands.iter().flat_map(|strand| strand.node_ids.iter().cloned()));
+                self.set_reading_order(ids)
+            }
+        }
+    }
+}
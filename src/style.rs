@@ -0,0 +1,241 @@
+//! Style Guide
+//!
+//! This module reads a `StyleGuide` artifact describing the voice a book is
+//! written in: its narrative voice, tense, banned words, and example
+//! passages. When the artifact is present the guide is added to every scene
+//! prompt, and each generated scene is checked against it afterwards so that
+//! drifts in person, tense, or vocabulary are recorded in the scene's
+//! `style_issues` metadata.
+//!
+//! The artifact content uses labeled fields, with example passages
+//! separated by `---` lines:
+//!
+//! ```text
+//! VOICE: Close third person, wry and understated
+//! TENSE: past
+//! BANNED WORDS: suddenly, delve, tapestry
+//! EXAMPLES:
+//! The rain had opinions about Harold, and none of them were kind.
+//! ---
+//! She counted the spoons twice, which was once more than trust allowed.
+//! ```
+
+use serde::{Deserialize, Serialize};
+use log::debug;
+use crate::artifacts::{Artifact, ArtifactManager, ArtifactType};
+use crate::passes::parse_labeled_fields;
+
+/// Node metadata key listing the ways a scene departs from the style guide
+pub const STYLE_ISSUES_KEY: &str = "style_issues";
+
+/// Labels of the style guide fields
+const STYLE_LABELS: [&str; 4] = ["VOICE", "TENSE", "BANNED WORDS", "EXAMPLES"];
+
+/// Maximum number of characters of each example passage included in prompts
+const MAX_EXAMPLE_CHARS: usize = 1500;
+
+/// Verbs that mark past-tense narration
+const PAST_MARKERS: [&str; 8] = ["was", "were", "had", "said", "did", "went", "looked", "felt"];
+
+/// Verbs that mark present-tense narration
+const PRESENT_MARKERS: [&str; 8] = ["is", "are", "has", "says", "does", "goes", "looks", "feels"];
+
+/// Pronouns that mark first-person narration
+const FIRST_PERSON: [&str; 5] = ["i", "me", "my", "we", "our"];
+
+/// Pronouns that mark third-person narration
+const THIRD_PERSON: [&str; 7] = ["he", "she", "his", "her", "him", "they", "their"];
+
+/// Tense the narration is written in
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Tense {
+    /// "She opened the door."
+    Past,
+
+    /// "She opens the door."
+    Present,
+}
+
+/// Grammatical person the narration is written in
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Person {
+    /// "I opened the door."
+    First,
+
+    /// "You open the door."
+    Second,
+
+    /// "She opened the door."
+    Third,
+}
+
+/// The voice every scene of a book should be written in
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct StyleGuide {
+    /// Description of the narrative voice
+    pub voice: String,
+
+    /// Person of the narration, read from the voice description
+    pub person: Option<Person>,
+
+    /// Tense of the narration
+    pub tense: Option<Tense>,
+
+    /// Words that must never appear in a scene
+    pub banned_words: Vec<String>,
+
+    /// Passages written in the desired voice
+    pub examples: Vec<String>,
+}
+
+/// Returns the text outside quoted dialogue
+fn narration(text: &str) -> String {
+    regex::Regex::new(r#""[^"]*"|“[^”]*”"#).unwrap().replace_all(text, " ").to_string()
+}
+
+/// Counts the occurrences of any of the given lowercase words in a text
+fn count_words(text: &str, words: &[&str]) -> usize {
+    text.split(|c: char| !c.is_alphanumeric() && c != '\'')
+        .filter(|word| words.contains(&word.to_lowercase().as_str()))
+        .count()
+}
+
+impl StyleGuide {
+    /// Parses a style guide from its labeled fields
+    ///
+    /// # Arguments
+    /// * `text` - The style guide text
+    pub fn parse(text: &str) -> Self {
+        let fields = parse_labeled_fields(text, &STYLE_LABELS);
+        let voice = fields.get("VOICE").cloned().unwrap_or_default();
+        let lower_voice = voice.to_lowercase();
+        let person = if lower_voice.contains("first person") || lower_voice.contains("first-person") {
+            Some(Person::First)
+        } else if lower_voice.contains("second person") || lower_voice.contains("second-person") {
+            Some(Person::Second)
+        } else if lower_voice.contains("third person") || lower_voice.contains("third-person") {
+            Some(Person::Third)
+        } else {
+            None
+        };
+        let tense = fields.get("TENSE").and_then(|tense| {
+            let tense = tense.to_lowercase();
+            if tense.contains("present") {
+                Some(Tense::Present)
+            } else if tense.contains("past") {
+                Some(Tense::Past)
+            } else {
+                None
+            }
+        });
+        let banned_words = fields
+            .get("BANNED WORDS")
+            .map(|words| {
+                words
+                    .split([',', '\n'])
+                    .map(|word| word.trim().trim_matches(['"', '\'']).to_string())
+                    .filter(|word| !word.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let examples = fields
+            .get("EXAMPLES")
+            .map(|examples| {
+                examples
+                    .split("\n---")
+                    .map(|example| example.trim_start_matches('-').trim().chars().take(MAX_EXAMPLE_CHARS).collect())
+                    .filter(|example: &String| !example.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self { voice, person, tense, banned_words, examples }
+    }
+
+    /// Reads the style guide from an artifact
+    ///
+    /// # Arguments
+    /// * `artifact` - The `StyleGuide` artifact
+    pub fn from_artifact(artifact: &Artifact) -> Self {
+        Self::parse(&artifact.content)
+    }
+
+    /// Returns the style guide among the loaded artifacts, the first by ID
+    /// when there are several
+    ///
+    /// # Arguments
+    /// * `artifact_manager` - The artifact manager to read artifacts from
+    pub fn from_artifacts(artifact_manager: &ArtifactManager) -> Option<Self> {
+        artifact_manager
+            .get_all_artifacts()
+            .into_iter()
+            .find(|artifact| artifact.artifact_type == ArtifactType::StyleGuide)
+            .map(Self::from_artifact)
+    }
+
+    /// Lays the guide out for a prompt
+    pub fn to_prompt_section(&self) -> String {
+        let mut lines = Vec::new();
+        if !self.voice.is_empty() {
+            lines.push(format!("Narrative voice: {}", self.voice));
+        }
+        if let Some(tense) = self.tense {
+            lines.push(format!("Tense: write the narration in the {} tense", if tense == Tense::Past { "past" } else { "present" }));
+        }
+        if !self.banned_words.is_empty() {
+            lines.push(format!("Never use these words: {}", self.banned_words.join(", ")));
+        }
+        for (index, example) in self.examples.iter().enumerate() {
+            lines.push(format!("--- Style Example {} (match its voice, not its events) ---\n{}", index + 1, example));
+        }
+        lines.join("\n")
+    }
+
+    /// Checks a scene against the guide
+    ///
+    /// Banned words are matched as whole words regardless of case; person
+    /// and tense are judged from pronouns and common verbs in the narration
+    /// outside quoted dialogue.
+    ///
+    /// # Arguments
+    /// * `content` - The scene to check
+    ///
+    /// # Returns
+    /// A description of each departure from the guide
+    pub fn check(&self, content: &str) -> Vec<String> {
+        let mut issues = Vec::new();
+        for word in &self.banned_words {
+            let pattern = format!(r"(?i)\b{}\b", regex::escape(word));
+            let count = regex::Regex::new(&pattern).map(|re| re.find_iter(content).count()).unwrap_or(0);
+            if count > 0 {
+                issues.push(format!("uses the banned word \"{}\" {} time(s)", word, count));
+            }
+        }
+
+        let narration = narration(content);
+        if let Some(tense) = self.tense {
+            let past = count_words(&narration, &PAST_MARKERS);
+            let present = count_words(&narration, &PRESENT_MARKERS);
+            debug!("Tense markers: {} past, {} present", past, present);
+            match tense {
+                Tense::Past if present > past => issues.push("narration drifts into the present tense".to_string()),
+                Tense::Present if past > present => issues.push("narration drifts into the past tense".to_string()),
+                _ => {}
+            }
+        }
+        if let Some(person) = self.person {
+            let first = count_words(&narration, &FIRST_PERSON);
+            let third = count_words(&narration, &THIRD_PERSON);
+            debug!("Person markers: {} first, {} third", first, third);
+            match person {
+                Person::First if first == 0 && third > 0 => {
+                    issues.push("narration is not in the first person".to_string())
+                }
+                Person::Third if first > third => issues.push("narration drifts into the first person".to_string()),
+                _ => {}
+            }
+        }
+        issues
+    }
+}
@@ -0,0 +1,209 @@
+//! Subplots
+//!
+//! This module weaves secondary plot lines into the main chain. Each subplot
+//! is a mini-chain of scenes with its own premise and its own context window:
+//! a subplot scene continues from the subplot's previous scene rather than
+//! from whatever main-plot scene precedes it, and main-plot scenes skip over
+//! subplot scenes when looking for the scene they continue. Subplot scenes
+//! are interleaved into the chain on a schedule so B-plots stay in view.
+
+use serde::{Deserialize, Serialize};
+use log::{info, debug};
+use crate::artifacts::Artifact;
+use crate::{AIProvider, StoryChain, StoryChainError};
+
+/// Default number of main-plot scenes between subplot scenes
+pub const DEFAULT_SUBPLOT_INTERVAL: usize = 3;
+
+/// A secondary plot line woven into the main chain
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Subplot {
+    /// Unique identifier, stored in the `subplot` metadata of its scenes
+    pub id: String,
+
+    /// Display name of the subplot
+    pub name: String,
+
+    /// What the subplot is about and where it should go
+    pub premise: String,
+
+    /// A subplot scene follows every Nth main-plot scene
+    pub every: usize,
+
+    /// IDs of the subplot's scenes, in order
+    #[serde(default)]
+    pub node_ids: Vec<String>,
+}
+
+impl Subplot {
+    /// Creates a new subplot with no scenes yet
+    pub fn new(id: String, name: String, premise: String, every: usize) -> Self {
+        Self {
+            id,
+            name,
+            premise,
+            every: every.max(1),
+            node_ids: Vec::new(),
+        }
+    }
+
+    /// Creates a subplot from an artifact
+    ///
+    /// The artifact's content is the subplot premise; its `name` and `every`
+    /// metadata override the ID and the default interval.
+    ///
+    /// # Arguments
+    /// * `artifact` - The artifact describing the subplot
+    /// * `default_every` - Interval used when the artifact does not set one
+    pub fn from_artifact(artifact: &Artifact, default_every: usize) -> Self {
+        let every = artifact
+            .metadata
+            .get("every")
+            .and_then(|e| e.parse().ok())
+            .unwrap_or(default_every);
+        let name = artifact.metadata.get("name").cloned().unwrap_or_else(|| artifact.id.clone());
+        Self::new(artifact.id.clone(), name, artifact.content.clone(), every)
+    }
+}
+
+impl StoryChain {
+    /// Returns the node a main-plot scene following the given node continues from
+    ///
+    /// Walks back over subplot scenes to the most recent main-plot scene.
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node the new scene is attached after
+    pub fn main_plot_context(&self, node_id: &str) -> String {
+        let mut current = node_id;
+        while let Some(node) = self.nodes.get(current) {
+            match (node.predecessor(), node.metadata.contains_key("subplot")) {
+                (Some(predecessor), true) => current = predecessor,
+                _ => break,
+            }
+        }
+        current.to_string()
+    }
+
+    /// Returns the index of the subplot due after the given main-plot epoch, if any
+    ///
+    /// When several subplots fall due together, the one with the fewest scenes
+    /// so far goes first.
+    ///
+    /// # Arguments
+    /// * `epoch` - The main-plot epoch just generated (1-indexed)
+    pub fn due_subplot(&self, epoch: usize) -> Option<usize> {
+        (0..self.subplots.len())
+            .filter(|&i| epoch > 0 && epoch.is_multiple_of(self.subplots[i].every))
+            .min_by_key(|&i| self.subplots[i].node_ids.len())
+    }
+
+    /// Generates the next scene of a subplot and attaches it after the given node
+    ///
+    /// The scene continues from the subplot's previous scene, or from the
+    /// given node when the subplot has not started yet.
+    ///
+    /// # Arguments
+    /// * `subplot_index` - Index of the subplot in `subplots`
+    /// * `current_node_id` - ID of the node the subplot scene is attached after
+    /// * `ai_provider` - The AI provider to use for generation
+    /// * `premise` - Optional premise of the main story
+    /// * `current_epoch` - Current epoch number
+    /// * `total_epochs` - Total number of epochs planned
+    pub async fn generate_subplot_scene(
+        &mut self,
+        subplot_index: usize,
+        current_node_id: &str,
+        ai_provider: &dyn AIProvider,
+        premise: Option<&str>,
+        current_epoch: usize,
+        total_epochs: usize,
+    ) -> Result<String, StoryChainError> {
+        let subplot = self.subplots.get(subplot_index).cloned().ok_or_else(|| {
+            StoryChainError::InvalidChainOperation(format!("No subplot at index {}", subplot_index))
+        })?;
+        info!("Generating scene {} of subplot {}", subplot.node_ids.len() + 1, subplot.name);
+
+        let context_id = subplot.node_ids.last().cloned().unwrap_or_else(|| current_node_id.to_string());
+        let subplot_premise = format!(
+            "{}Subplot: {}\n{}\n\nThis scene belongs to the subplot above. Continue its thread from its \
+            previous scene while staying consistent with the main story.",
+            premise.map(|p| format!("{}\n\n", p)).unwrap_or_default(),
+            subplot.name,
+            subplot.premise
+        );
+
+        let new_ids = self
+            .generate_scene(current_node_id, &context_id, ai_provider, Some(&subplot_premise), current_epoch, total_epochs)
+            .await?;
+        let new_id = new_ids.into_iter().next().ok_or_else(|| {
+            StoryChainError::AIServerError(format!("No scene generated for subplot {}", subplot.name))
+        })?;
+
+        if let Some(node) = self.nodes.get_mut(&new_id) {
+            node.metadata.insert("subplot".to_string(), subplot.id.clone());
+        }
+        self.subplots[subplot_index].node_ids.push(new_id.clone());
+        debug!("Wove subplot scene {} into the chain", new_id);
+        Ok(new_id)
+    }
+
+    /// Generates the next main-plot scene, or one per branch, each followed by
+    /// any subplot scene due after it
+    ///
+    /// When the chain selects the best of several candidates, the critic, or
+    /// else the generating provider, judges them. Otherwise, when a critic is
+    /// given, the main-plot scene passes through the chain's quality gate.
+    /// Subplot scenes are neither judged nor scored.
+    ///
+    /// # Arguments
+    /// * `current_node_id` - ID of the node to generate from
+    /// * `ai_provider` - The AI provider to use for generation
+    /// * `critic` - Optional AI provider scoring main-plot scenes
+    /// * `premise` - Optional premise to include in generation
+    /// * `current_epoch` - Current epoch number
+    /// * `total_epochs` - Total number of epochs planned
+    ///
+    /// # Returns
+    /// The IDs of the new nodes in chain order, each alternative followed by its
+    /// subplot scene; in a linear chain the last one is the new end of the chain
+    pub async fn generate_with_subplots(
+        &mut self,
+        current_node_id: &str,
+        ai_provider: &dyn AIProvider,
+        critic: Option<&dyn AIProvider>,
+        premise: Option<&str>,
+        current_epoch: usize,
+        total_epochs: usize,
+    ) -> Result<Vec<String>, StoryChainError> {
+        let scene_ids = match critic {
+            // Keep the best of several candidates, judged by the critic when there is one
+            _ if self.settings.selection.is_some() => {
+                let judge = critic.unwrap_or(ai_provider);
+                self.generate_best_of(current_node_id, ai_provider, judge, premise, current_epoch, total_epochs)
+                    .await?
+            }
+            Some(critic) => {
+                self.generate_with_quality_gate(current_node_id, ai_provider, critic, premise, current_epoch, total_epochs)
+                    .await?
+            }
+            None => {
+                self.generate_next_nodes(current_node_id, ai_provider, premise, current_epoch, total_epochs)
+                    .await?
+            }
+        };
+
+        // Every alternative continuation gets the subplot scene that is due
+        let mut new_ids = Vec::new();
+        for scene_id in scene_ids {
+            new_ids.push(scene_id.clone());
+            if let Some(subplot_index) = self.due_subplot(current_epoch) {
+                let subplot_id = self
+                    .generate_subplot_scene(subplot_index, &scene_id, ai_provider, premise, current_epoch, total_epochs)
+                    .await?;
+                new_ids.push(subplot_id);
+            }
+        }
+
+        Ok(new_ids)
+    }
+}
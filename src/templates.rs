@@ -0,0 +1,72 @@
+//! Template Exports
+//!
+//! This module renders a chain through a user-supplied Tera template, so new
+//! output layouts can be defined without changing the crate. Templates whose
+//! names end in `.html`, `.htm`, or `.xml` have their variables HTML-escaped
+//! automatically; use the `safe` filter to opt out.
+//!
+//! Variables available to export templates:
+//! * `title` - The chain's `title` metadata (empty when not set)
+//! * `metadata` - The chain metadata
+//! * `root_id` - ID of the opening scene
+//! * `nodes` - Map of node ID to node, each with `id`, `content`,
+//!   `reasoning`, `predecessors`, `successors`, `metadata`, and `scene_card`
+//! * `scenes` - The main storyline in reading order, as nodes
+//! * `storylines` - Every storyline from the root to an ending, as lists of
+//!   nodes, with the main storyline first
+//! * `chapters` - Chapters, each with `title`, `node_ids`, and `metadata`
+//! * `characters` - Registered characters, each with `name`, `aliases`, and
+//!   `introduced_in`
+//! * `glossary` - Glossary entries
+
+use log::info;
+use std::path::Path;
+use tera::{Context, Tera};
+use crate::prompts::template_error;
+use crate::{StoryChain, StoryChainError};
+
+impl StoryChain {
+    /// Builds the variables exposed to export templates
+    pub fn template_context(&self) -> Context {
+        let mut context = Context::new();
+        context.insert("title", self.metadata.get("title").map(String::as_str).unwrap_or_default());
+        context.insert("metadata", &self.metadata);
+        context.insert("root_id", &self.root_node_id);
+        context.insert("nodes", &self.nodes);
+        context.insert("scenes", &self.nodes_in_reading_order());
+        context.insert("storylines", &self.storylines());
+        context.insert("chapters", &self.chapters);
+        context.insert("characters", &self.character_registry.characters);
+        context.insert("glossary", &self.glossary);
+        context
+    }
+
+    /// Renders the chain through a Tera template file
+    ///
+    /// # Arguments
+    /// * `template_path` - Path of the template to render
+    ///
+    /// # Returns
+    /// The rendered output
+    pub fn render_template(&self, template_path: &str) -> Result<String, StoryChainError> {
+        let name = Path::new(template_path)
+            .file_name()
+            .map(|n| n.to_string_lossy().trim_end_matches(".tera").to_string())
+            .unwrap_or_default();
+        let source = std::fs::read_to_string(template_path)?;
+        let mut tera = Tera::default();
+        tera.add_raw_template(&name, &source).map_err(template_error)?;
+        tera.render(&name, &self.template_context()).map_err(template_error)
+    }
+
+    /// Exports the chain through a Tera template file
+    ///
+    /// # Arguments
+    /// * `template_path` - Path of the template to render
+    /// * `output_path` - The path where the rendered output should be saved
+    pub fn export_with_template(&self, template_path: &str, output_path: &str) -> Result<(), StoryChainError> {
+        info!("Exporting story with template {}: {}", template_path, output_path);
+        crate::files::write_atomic(output_path, self.render_template(template_path)?)?;
+        Ok(())
+    }
+}
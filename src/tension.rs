@@ -0,0 +1,261 @@
+//! Tension-Curve Targeting
+//!
+//! This module lets the user describe the tension curve a story should
+//! follow, such as `rising, dip@60, spike@90`. Each generated scene is scored
+//! for tension, and the next prompt is nudged to raise or ease the stakes so
+//! the story tracks the curve. A report compares target and actual tension.
+
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use log::debug;
+use crate::stats::estimate_tension;
+use crate::StoryChain;
+
+/// Distance from the target, on the 0-10 scale, tolerated before prompts are nudged
+pub const TENSION_TOLERANCE: f64 = 1.5;
+
+/// Half-width, as a fraction of the story, of a dip or spike
+const FEATURE_WIDTH: f64 = 0.1;
+
+/// Height of a dip or spike on the 0-10 scale
+const FEATURE_HEIGHT: f64 = 4.0;
+
+/// Overall shape a tension curve starts from
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CurveShape {
+    /// Steadily rising from calm to climax
+    Rising,
+
+    /// Steadily falling from a high opening
+    Falling,
+
+    /// Constant moderate tension
+    Flat,
+
+    /// Rising to a climax at 80% and falling into the resolution
+    Arc,
+}
+
+/// A local feature added on top of the base shape
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CurveFeature {
+    /// A drop in tension centered at the given fraction of the story
+    Dip(f64),
+
+    /// A jump in tension centered at the given fraction of the story
+    Spike(f64),
+
+    /// An exact target tension at the given fraction of the story
+    Point(f64, f64),
+}
+
+/// Desired tension over the course of a story
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct TensionCurve {
+    /// Base shape of the curve
+    pub shape: CurveShape,
+
+    /// Dips, spikes, and pinned points applied on top of the shape
+    pub features: Vec<CurveFeature>,
+}
+
+/// Target and measured tension of one scene
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct TensionSample {
+    /// ID of the node
+    pub node_id: String,
+
+    /// Position of the scene in causal order, starting at 1 for the root
+    pub scene_number: usize,
+
+    /// Tension the curve asked for
+    pub target: f64,
+
+    /// Tension measured in the scene
+    pub actual: f64,
+}
+
+/// Parses a fraction written as `60`, `60%`, or `0.6`
+fn parse_fraction(text: &str) -> Result<f64, String> {
+    let trimmed = text.trim().trim_end_matches('%');
+    let value: f64 = trimmed.parse().map_err(|_| format!("invalid position '{}'", text))?;
+    let fraction = if value > 1.0 { value / 100.0 } else { value };
+    if (0.0..=1.0).contains(&fraction) {
+        Ok(fraction)
+    } else {
+        Err(format!("position '{}' is outside the story", text))
+    }
+}
+
+impl FromStr for TensionCurve {
+    type Err = String;
+
+    /// Parses a comma-separated curve description
+    ///
+    /// The first item may name a base shape (`rising`, `falling`, `flat`, or
+    /// `arc`; `rising` when omitted). Further items are `dip@60`, `spike@90`,
+    /// or pinned points such as `50%:7`.
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut curve = TensionCurve { shape: CurveShape::Rising, features: Vec::new() };
+
+        for (index, item) in spec.split(',').map(str::trim).filter(|i| !i.is_empty()).enumerate() {
+            let item = item.to_lowercase();
+            let shape = match item.as_str() {
+                "rising" => Some(CurveShape::Rising),
+                "falling" => Some(CurveShape::Falling),
+                "flat" => Some(CurveShape::Flat),
+                "arc" | "three-act" => Some(CurveShape::Arc),
+                _ => None,
+            };
+            if let Some(shape) = shape {
+                if index > 0 {
+                    return Err(format!("the base shape '{}' must come first", item));
+                }
+                curve.shape = shape;
+                continue;
+            }
+
+            let feature = if let Some((kind, at)) = item.split_once(['@', ' ']) {
+                let at = parse_fraction(at.trim_start_matches("at").trim())?;
+                match kind.trim() {
+                    "dip" => CurveFeature::Dip(at),
+                    "spike" => CurveFeature::Spike(at),
+                    other => return Err(format!("unknown curve feature '{}'", other)),
+                }
+            } else if let Some((at, tension)) = item.split_once(':') {
+                let tension: f64 = tension.trim().parse().map_err(|_| format!("invalid tension '{}'", tension))?;
+                CurveFeature::Point(parse_fraction(at)?, tension.clamp(0.0, 10.0))
+            } else {
+                return Err(format!("unrecognized curve item '{}'", item));
+            };
+            curve.features.push(feature);
+        }
+
+        Ok(curve)
+    }
+}
+
+impl TensionCurve {
+    /// Returns the target tension, on a 0-10 scale, at the given point of the story
+    ///
+    /// # Arguments
+    /// * `progress` - Fraction of the story completed, from 0.0 to 1.0
+    pub fn target_at(&self, progress: f64) -> f64 {
+        let progress = progress.clamp(0.0, 1.0);
+        let mut target = match self.shape {
+            CurveShape::Rising => 2.0 + 7.0 * progress,
+            CurveShape::Falling => 8.0 - 6.0 * progress,
+            CurveShape::Flat => 5.0,
+            CurveShape::Arc if progress <= 0.8 => 2.0 + 7.0 * progress / 0.8,
+            CurveShape::Arc => 9.0 - 5.0 * (progress - 0.8) / 0.2,
+        };
+
+        let bump = |at: f64| (1.0 - (progress - at).abs() / FEATURE_WIDTH).max(0.0) * FEATURE_HEIGHT;
+        for feature in &self.features {
+            match *feature {
+                CurveFeature::Dip(at) => target -= bump(at),
+                CurveFeature::Spike(at) => target += bump(at),
+                CurveFeature::Point(..) => {}
+            }
+        }
+
+        // Pinned points override the shape near them
+        for feature in &self.features {
+            if let CurveFeature::Point(at, tension) = *feature {
+                let weight = (1.0 - (progress - at).abs() / FEATURE_WIDTH).max(0.0);
+                target = target * (1.0 - weight) + tension * weight;
+            }
+        }
+
+        (target.clamp(0.0, 10.0) * 10.0).round() / 10.0
+    }
+}
+
+impl StoryChain {
+    /// Returns the tension of a node, from its `tension` metadata when present
+    /// and otherwise estimated from its content
+    ///
+    /// # Arguments
+    /// * `node_id` - ID of the node to score
+    pub fn scene_tension(&self, node_id: &str) -> Option<f64> {
+        let node = self.nodes.get(node_id)?;
+        Some(
+            node.metadata
+                .get("tension")
+                .and_then(|t| t.parse().ok())
+                .unwrap_or_else(|| estimate_tension(&node.content)),
+        )
+    }
+
+    /// Builds the tension guidance for the scene generated at the given epoch
+    ///
+    /// # Arguments
+    /// * `current_node_id` - ID of the node the next scene will follow
+    /// * `current_epoch` - Epoch of the scene being generated
+    /// * `total_epochs` - Total number of epochs planned
+    pub fn tension_guidance(&self, current_node_id: &str, current_epoch: usize, total_epochs: usize) -> Option<String> {
+        let curve = self.settings.tension_curve.as_ref()?;
+        let target = curve.target_at(current_epoch as f64 / total_epochs.max(1) as f64);
+        let previous = self.scene_tension(current_node_id)?;
+        debug!("Tension target {:.1}, previous scene {:.1}", target, previous);
+
+        let note = if target - previous > TENSION_TOLERANCE {
+            format!(
+                "Raise the stakes: the previous scene's tension was about {:.0}/10 and this scene should reach \
+                about {:.0}/10. Add danger, urgency, conflict, or a reversal.",
+                previous, target
+            )
+        } else if previous - target > TENSION_TOLERANCE {
+            format!(
+                "Ease off: the previous scene's tension was about {:.0}/10 and this scene should settle to \
+                about {:.0}/10. Give the characters room to breathe, reflect, or regroup.",
+                previous, target
+            )
+        } else {
+            format!("Hold the tension at about {:.0}/10.", target)
+        };
+        Some(note)
+    }
+
+    /// Compares the target and measured tension of every scene generated with
+    /// a tension curve, in causal order
+    pub fn tension_report(&self) -> Vec<TensionSample> {
+        self.nodes_in_order()
+            .iter()
+            .enumerate()
+            .filter_map(|(index, node)| {
+                Some(TensionSample {
+                    node_id: node.id.clone(),
+                    scene_number: index + 1,
+                    target: node.metadata.get("target_tension")?.parse().ok()?,
+                    actual: self.scene_tension(&node.id)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Renders the tension report as a markdown table
+    pub fn tension_report_markdown(&self) -> String {
+        let mut report = String::from("## Tension: Target vs. Actual\n\n");
+        report.push_str("| Scene | Target | Actual | Difference |\n");
+        report.push_str("|------:|-------:|-------:|:-----------|\n");
+        for sample in self.tension_report() {
+            let difference = sample.actual - sample.target;
+            let verdict = if difference.abs() <= TENSION_TOLERANCE {
+                "on target"
+            } else if difference > 0.0 {
+                "too tense"
+            } else {
+                "too slack"
+            };
+            report.push_str(&format!(
+                "| {} | {:.1} | {:.1} | {:+.1} ({}) |\n",
+                sample.scene_number, sample.target, sample.actual, difference, verdict
+            ));
+        }
+        report.push('\n');
+        report
+    }
+}
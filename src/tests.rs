@@ -4,157 +4,268 @@
 //! of the story generation system, including story chain creation, artifact
 //! management, and AI provider interactions.
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
-    use tempfile::tempdir;
-
-    /// Mock implementation of AIProvider for testing purposes
-    /// Returns predefined responses instead of calling actual AI services
-    struct MockAIProvider;
-
-    #[async_trait::async_trait]
-    impl AIProvider for MockAIProvider {
-        async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
-            Ok((
-                "Test reasoning".to_string(),
-                "Test content".to_string(),
-            ))
-        }
-    }
+use crate::*;
+use tempfile::tempdir;
 
-    /// Tests the basic creation of a StoryChain
-    /// 
-    /// Verifies:
-    /// - Initial node creation
-    /// - Root node ID assignment
-    /// - Branch ratio setting
-    /// - Node content and reasoning
-    #[tokio::test]
-    async fn test_story_chain_creation() {
-        let mut chain = StoryChain::new(
-            "Initial content".to_string(),
-            "Initial reasoning".to_string(),
-            2,
-        );
-
-        // Verify basic chain properties
-        assert_eq!(chain.nodes.len(), 1);
-        assert_eq!(chain.root_node_id, "root");
-        assert_eq!(chain.branch_ratio, 2);
-
-        // Verify root node properties
-        let root_node = chain.nodes.get("root").unwrap();
-        assert_eq!(root_node.content, "Initial content");
-        assert_eq!(root_node.reasoning, "Initial reasoning");
-        assert!(root_node.predecessors.is_empty());
-        assert!(root_node.successors.is_empty());
-    }
+/// Mock implementation of AIProvider for testing purposes
+/// Returns predefined responses instead of calling actual AI services
+struct MockAIProvider;
 
-    /// Tests the generation of new story nodes
-    /// 
-    /// Verifies:
-    /// - Node generation using AI provider
-    /// - Node linking (predecessors/successors)
-    /// - Node ID generation
-    #[tokio::test]
-    async fn test_story_chain_generation() {
-        let mut chain = StoryChain::new(
-            "Initial content".to_string(),
-            "Initial reasoning".to_string(),
-        );
-
-        let ai_provider = MockAIProvider;
-        let mut current_node_id = "root".to_string();
-        let total_epochs = 3;
-        
-        for epoch in 0..total_epochs {
-            let new_nodes = chain
-                .generate_next_nodes(
-                    &current_node_id,
-                    &ai_provider,
-                    Some("Test premise"),
-                    epoch + 1,
-                    total_epochs
-                )
-                .await
-                .unwrap();
-
-            // Verify new node creation
-            assert_eq!(new_nodes.len(), 1);
-            assert_eq!(chain.nodes.len(), epoch + 2); // +2 because we start with root node
-
-            // Update current node for next iteration
-            current_node_id = new_nodes[0].clone();
-        }
+#[async_trait::async_trait]
+impl AIProvider for MockAIProvider {
+    async fn generate(&self, _prompt: &str) -> Result<(String, String), StoryChainError> {
+        Ok((
+            "Test reasoning".to_string(),
+            "Test content".to_string(),
+        ))
     }
+}
+
+/// Tests the basic creation of a StoryChain
+/// 
+/// Verifies:
+/// - Initial node creation
+/// - Root node ID assignment
+/// - Branch ratio setting
+/// - Node content and reasoning
+#[tokio::test]
+async fn test_story_chain_creation() {
+    let chain = StoryChain::new(
+        "Initial content".to_string(),
+        "Initial reasoning".to_string(),
+    )
+    .with_branch_ratio(2);
+
+    // Verify basic chain properties
+    assert_eq!(chain.nodes.len(), 1);
+    assert_eq!(chain.root_node_id, "root");
+    assert_eq!(chain.branch_ratio, 2);
 
-    /// Tests the ArtifactManager functionality
-    /// 
-    /// Verifies:
-    /// - Artifact creation and storage
-    /// - Loading artifacts from directory
-    /// - Artifact updates
-    /// - Artifact type filtering
-    #[test]
-    fn test_artifact_manager() {
-        // Create temporary directory for test artifacts
-        let temp_dir = tempdir().unwrap();
-        let mut manager = ArtifactManager::new(temp_dir.path().to_str().unwrap());
-
-        // Test artifact creation
-        manager
-            .create_artifact(
-                "test".to_string(),
-                "Test content".to_string(),
-                ArtifactType::Premise,
+    // Verify root node properties
+    let root_node = chain.nodes.get("root").unwrap();
+    assert_eq!(root_node.content, "Initial content");
+    assert_eq!(root_node.reasoning, "Initial reasoning");
+    assert!(root_node.predecessors.is_empty());
+    assert!(root_node.successors.is_empty());
+}
+
+/// Tests the generation of new story nodes
+/// 
+/// Verifies:
+/// - Node generation using AI provider
+/// - Node linking (predecessors/successors)
+/// - Node ID generation
+#[tokio::test]
+async fn test_story_chain_generation() {
+    let mut chain = StoryChain::new(
+        "Initial content".to_string(),
+        "Initial reasoning".to_string(),
+    );
+
+    let ai_provider = MockAIProvider;
+    let mut current_node_id = "root".to_string();
+    let total_epochs = 3;
+
+    for epoch in 0..total_epochs {
+        let new_nodes = chain
+            .generate_next_nodes(
+                &current_node_id,
+                &ai_provider,
+                Some("Test premise"),
+                epoch + 1,
+                total_epochs
             )
+            .await
             .unwrap();
 
-        // Test loading artifacts from directory
-        manager.load_from_dir().unwrap();
-        let artifact = manager.get_artifact("test").unwrap();
-        assert_eq!(artifact.content, "Test content");
-        assert_eq!(artifact.artifact_type, ArtifactType::Premise);
-
-        // Test artifact updates
-        let mut updated_artifact = artifact.clone();
-        updated_artifact.content = "Updated content".to_string();
-        manager.update_artifact(updated_artifact.clone()).unwrap();
-
-        // Verify update persistence
-        manager.load_from_dir().unwrap();
-        let artifact = manager.get_artifact("test").unwrap();
-        assert_eq!(artifact.content, "Updated content");
-
-        // Test artifact type filtering
-        let premises = manager.get_artifacts_by_type(&ArtifactType::Premise);
-        assert_eq!(premises.len(), 1);
-        assert_eq!(premises[0].id, "test");
+        // Verify new node creation
+        assert_eq!(new_nodes.len(), 1);
+        assert_eq!(chain.nodes.len(), epoch + 2); // +2 because we start with root node
+
+        // Update current node for next iteration
+        current_node_id = new_nodes[0].clone();
     }
+}
+
+/// Tests the ArtifactManager functionality
+/// 
+/// Verifies:
+/// - Artifact creation and storage
+/// - Loading artifacts from directory
+/// - Artifact updates
+/// - Artifact type filtering
+#[test]
+fn test_artifact_manager() {
+    // Create temporary directory for test artifacts
+    let temp_dir = tempdir().unwrap();
+    let mut manager = ArtifactManager::new(temp_dir.path().to_str().unwrap());
+
+    // Test artifact creation
+    manager
+        .create_artifact(
+            "test".to_string(),
+            "Test content".to_string(),
+            ArtifactType::Premise,
+        )
+        .unwrap();
+
+    // Test loading artifacts from directory
+    manager.load_from_dir().unwrap();
+    let artifact = manager.get_artifact("test").unwrap();
+    assert_eq!(artifact.content, "Test content");
+    assert_eq!(artifact.artifact_type, ArtifactType::Premise);
+
+    // Test artifact updates
+    let mut updated_artifact = artifact.clone();
+    updated_artifact.content = "Updated content".to_string();
+    manager.update_artifact(updated_artifact.clone()).unwrap();
+
+    // Verify update persistence
+    manager.load_from_dir().unwrap();
+    let artifact = manager.get_artifact("test").unwrap();
+    assert_eq!(artifact.content, "Updated content");
+
+    // Test artifact type filtering
+    let premises = manager.get_artifacts_by_type(&ArtifactType::Premise);
+    assert_eq!(premises.len(), 1);
+    assert_eq!(premises[0].id, "test");
+}
+
+/// Tests StoryChain serialization and deserialization
+/// 
+/// Verifies:
+/// - JSON serialization
+/// - JSON deserialization
+/// - Preservation of chain properties
+#[test]
+fn test_story_chain_serialization() {
+    let chain = StoryChain::new(
+        "Initial content".to_string(),
+        "Initial reasoning".to_string(),
+    )
+    .with_branch_ratio(2);
 
-    /// Tests StoryChain serialization and deserialization
-    /// 
-    /// Verifies:
-    /// - JSON serialization
-    /// - JSON deserialization
-    /// - Preservation of chain properties
-    #[test]
-    fn test_story_chain_serialization() {
-        let chain = StoryChain::new(
-            "Initial content".to_string(),
-            "Initial reasoning".to_string(),
-            2,
-        );
-
-        // Test serialization/deserialization roundtrip
-        let serialized = serde_json::to_string(&chain).unwrap();
-        let deserialized: StoryChain = serde_json::from_str(&serialized).unwrap();
-
-        // Verify preservation of properties
-        assert_eq!(chain.nodes.len(), deserialized.nodes.len());
-        assert_eq!(chain.root_node_id, deserialized.root_node_id);
-        assert_eq!(chain.branch_ratio, deserialized.branch_ratio);
+    // Test serialization/deserialization roundtrip
+    let serialized = serde_json::to_string(&chain).unwrap();
+    let deserialized: StoryChain = serde_json::from_str(&serialized).unwrap();
+
+    // Verify preservation of properties
+    assert_eq!(chain.nodes.len(), deserialized.nodes.len());
+    assert_eq!(chain.root_node_id, deserialized.root_node_id);
+    assert_eq!(chain.branch_ratio, deserialized.branch_ratio);
+}
+
+/// Mock provider numbering its responses, so regenerated scenes can be told apart
+struct CountingProvider(std::sync::atomic::AtomicUsize);
+
+#[async_trait::async_trait]
+impl AIProvider for CountingProvider {
+    async fn generate(&self, _prompt: &str) -> Result<(String, String), StoryChainError> {
+        let n = self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+        Ok(("Test reasoning".to_string(), format!("Draft {}", n)))
+    }
+}
+
+/// Reviewer answering from a script, then accepting every other scene
+struct ScriptedReviewer {
+    answers: std::collections::VecDeque<interactive::Review>,
+    labels: Vec<String>,
+}
+
+impl ScriptedReviewer {
+    fn new(answers: Vec<interactive::Review>) -> Self {
+        Self { answers: answers.into(), labels: Vec::new() }
+    }
+}
+
+impl interactive::Reviewer for ScriptedReviewer {
+    fn review(&mut self, chain: &mut StoryChain, node_id: &str, label: &str) -> Result<interactive::Review, StoryChainError> {
+        self.labels.push(format!("{}: {}", label, chain.nodes[node_id].content));
+        Ok(self.answers.pop_front().unwrap_or(interactive::Review::Accept))
     }
-} 
\ No newline at end of file
+}
+
+/// Tests an interactive session writing a new story
+///
+/// Verifies:
+/// - The opening is reviewed and regenerated when rejected
+/// - A rejected continuation is removed and remembered on the scene before it
+/// - The story is saved after every accepted scene
+#[tokio::test]
+async fn test_interactive_session() {
+    use interactive::{InteractiveSession, Review};
+
+    let provider = CountingProvider(Default::default());
+    let mut reviewer = ScriptedReviewer::new(vec![
+        Review::Reject(None),
+        Review::Accept,
+        Review::Reject(Some("Too quiet".to_string())),
+    ]);
+    let mut saved = Vec::new();
+    let completed = InteractiveSession::new(&provider, "Test premise", 2)
+        .run(None, &mut reviewer, |chain| {
+            saved.push(chain.storylines()[0].len());
+            Ok(())
+        })
+        .await
+        .unwrap();
+
+    assert!(completed);
+    assert_eq!(
+        reviewer.labels,
+        [
+            "Scene 1 of 3: Draft 1",
+            "Scene 1 of 3: Draft 2",
+            "Scene 2 of 3: Draft 3",
+            "Scene 2 of 3: Draft 4",
+            "Scene 3 of 3: Draft 5",
+        ]
+    );
+    assert_eq!(saved, [1, 2, 3]);
+}
+
+/// Tests quitting and resuming an interactive session
+///
+/// Verifies:
+/// - Quitting saves the story without completing it
+/// - A resumed story skips the review of its opening and continues after its completed epochs
+#[tokio::test]
+async fn test_interactive_session_resume() {
+    use interactive::{InteractiveSession, Review};
+
+    let provider = CountingProvider(Default::default());
+    let mut reviewer = ScriptedReviewer::new(vec![Review::Accept, Review::Quit]);
+    let mut saves = 0;
+    let completed = InteractiveSession::new(&provider, "Test premise", 3)
+        .run(None, &mut reviewer, |chain| {
+            saves += 1;
+            assert!(!chain.metadata.contains_key(EPOCHS_COMPLETED_KEY));
+            Ok(())
+        })
+        .await
+        .unwrap();
+    assert!(!completed);
+    assert_eq!(saves, 2);
+
+    // A story of one epoch, resumed for three
+    let mut saved = None;
+    InteractiveSession::new(&provider, "Test premise", 1)
+        .run(None, &mut ScriptedReviewer::new(Vec::new()), |chain| {
+            saved = Some(chain.clone());
+            Ok(())
+        })
+        .await
+        .unwrap();
+    let mut reviewer = ScriptedReviewer::new(Vec::new());
+    let mut completed_epochs = Vec::new();
+    let completed = InteractiveSession::new(&provider, "Test premise", 3)
+        .run(saved, &mut reviewer, |chain| {
+            completed_epochs.push(chain.completed_epochs());
+            Ok(())
+        })
+        .await
+        .unwrap();
+    assert!(completed);
+    assert_eq!(reviewer.labels, ["Scene 3 of 4: Draft 5", "Scene 4 of 4: Draft 6"]);
+    assert_eq!(completed_epochs, [2, 3]);
+}
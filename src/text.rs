@@ -0,0 +1,101 @@
+//! Plain-Text Exports
+//!
+//! This module writes story chains as plain prose for tools that do not
+//! understand markdown: a single text file of the main storyline, or one file
+//! per scene.
+
+use log::info;
+use std::path::{Path, PathBuf};
+use crate::{StoryChain, StoryChainError, StoryNode};
+
+/// Line set between scenes in the plain-text export
+const SCENE_BREAK: &str = "* * *";
+
+impl StoryChain {
+    /// Renders the main storyline as plain prose
+    ///
+    /// The title comes first when the chain has one, followed by the scenes
+    /// in reading order separated by scene breaks. Act and chapter titles
+    /// head their chapters; the AI's reasoning and all other markup are left
+    /// out.
+    pub fn to_plain_text(&self) -> String {
+        let mut text = String::new();
+        if let Some(title) = self.metadata.get("title") {
+            text.push_str(title.trim());
+            text.push_str("\n\n\n");
+        }
+        for (index, node) in self.nodes_in_reading_order().into_iter().enumerate() {
+            match self.chapter_starting_at(&node.id) {
+                Some((_, chapter, act)) => {
+                    if index > 0 {
+                        text.push('\n');
+                    }
+                    if let Some(act) = act {
+                        text.push_str(act.trim());
+                        text.push_str("\n\n");
+                    }
+                    text.push_str(chapter.title.trim());
+                    text.push_str("\n\n");
+                }
+                None if index > 0 => {
+                    text.push_str(SCENE_BREAK);
+                    text.push_str("\n\n");
+                }
+                None => {}
+            }
+            text.push_str(node.content.trim());
+            text.push_str("\n\n");
+        }
+        text.truncate(text.trim_end().len());
+        text.push('\n');
+        text
+    }
+
+    /// Exports the main storyline to a plain-text file
+    ///
+    /// # Arguments
+    /// * `path` - The path where the text file should be saved
+    pub fn export_to_text(&self, path: &str) -> Result<(), StoryChainError> {
+        info!("Exporting story to plain text: {}", path);
+        crate::files::write_atomic(path, self.to_plain_text())?;
+        Ok(())
+    }
+
+    /// Writes every scene to its own text file in a directory
+    ///
+    /// Files are named `scene_<number>_<node id>.txt`. Scenes of the main
+    /// storyline are numbered in reading order; scenes of alternative
+    /// branches continue the numbering in the order the branches leave the
+    /// main storyline. Each file holds the scene's content only.
+    ///
+    /// # Arguments
+    /// * `dir` - The directory to write to; it is created if missing
+    ///
+    /// # Returns
+    /// The paths of the written files, in scene order
+    pub fn export_scenes_to_dir(&self, dir: &str) -> Result<Vec<PathBuf>, StoryChainError> {
+        info!("Exporting scenes to directory: {}", dir);
+        std::fs::create_dir_all(dir)?;
+
+        let mut scenes: Vec<&StoryNode> = self.nodes_in_reading_order();
+        for line in self.storylines() {
+            for node in line {
+                if !scenes.iter().any(|n| n.id == node.id) {
+                    scenes.push(node);
+                }
+            }
+        }
+        let width = scenes.len().to_string().len().max(3);
+
+        let mut paths = Vec::new();
+        for (index, node) in scenes.into_iter().enumerate() {
+            // Keep hand-edited IDs from escaping the directory
+            let id = node.id.replace(['/', '\\'], "_");
+            let path = Path::new(dir).join(format!("scene_{:0width$}_{}.txt", index + 1, id, width = width));
+            crate::files::write_atomic(&path, format!("{}\n", node.content.trim()))?;
+            paths.push(path);
+        }
+        info!("Exported {} scenes", paths.len());
+        Ok(paths)
+    }
+}
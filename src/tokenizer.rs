@@ -0,0 +1,178 @@
+//! Token Counting
+//!
+//! This module estimates how many tokens a text takes up in a model's context
+//! window and trims prompts to fit a budget. Ollama silently drops the start
+//! of prompts longer than the context window, so the continuation prompt is
+//! measured before it is sent and its longest parts are shortened when it
+//! would not fit.
+//!
+//! Counts are approximate: like BPE tokenizers, runs of letters and digits
+//! take about one token per four characters and every punctuation mark is a
+//! token of its own. This errs on the high side for English prose.
+
+use log::{debug, warn};
+use crate::prompts::PromptTemplates;
+use crate::StoryChainError;
+
+/// Characters of a word that make up roughly one token
+const CHARS_PER_TOKEN: usize = 4;
+
+/// Marker left where text was cut
+pub const TRIM_MARKER: &str = "[...]";
+
+/// Which part of a text to keep when trimming it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keep {
+    /// Keep the beginning and cut the end
+    Start,
+
+    /// Keep the end and cut the beginning
+    End,
+}
+
+/// Template variables of the continuation prompt that may be shortened, in
+/// the order they are trimmed: reasoning goes first, then the summary, the
+/// recalled scenes, the character sheets and lore, the start of the previous
+/// scene, and the premise only as a last resort
+const TRIMMABLE: [(&str, Keep); 7] = [
+    ("last_reasoning", Keep::End),
+    ("summary", Keep::End),
+    ("recalled", Keep::End),
+    ("characters", Keep::Start),
+    ("lore", Keep::Start),
+    ("last_scene", Keep::End),
+    ("premise", Keep::Start),
+];
+
+/// Estimates the number of tokens in a text
+///
+/// # Arguments
+/// * `text` - The text to measure
+pub fn count_tokens(text: &str) -> usize {
+    text.split_whitespace().map(count_word_tokens).sum()
+}
+
+/// Estimates the number of tokens in a single whitespace-free word
+fn count_word_tokens(word: &str) -> usize {
+    let mut tokens = 0;
+    let mut run: usize = 0;
+    for c in word.chars() {
+        if c.is_alphanumeric() {
+            run += 1;
+        } else {
+            tokens += run.div_ceil(CHARS_PER_TOKEN) + 1;
+            run = 0;
+        }
+    }
+    tokens + run.div_ceil(CHARS_PER_TOKEN)
+}
+
+/// Shortens a text to at most the given number of tokens, cutting at word
+/// boundaries and marking the cut with `[...]`
+///
+/// # Arguments
+/// * `text` - The text to shorten
+/// * `max_tokens` - Token budget of the shortened text, including the marker
+/// * `keep` - Which part of the text to keep
+pub fn truncate_to_tokens(text: &str, max_tokens: usize, keep: Keep) -> String {
+    if count_tokens(text) <= max_tokens {
+        return text.to_string();
+    }
+    let budget = max_tokens.saturating_sub(count_tokens(TRIM_MARKER));
+    let words: Vec<&str> = text.split_whitespace().collect();
+
+    let mut used = 0;
+    let mut kept = 0;
+    let ordered: Box<dyn Iterator<Item = &&str>> = match keep {
+        Keep::Start => Box::new(words.iter()),
+        Keep::End => Box::new(words.iter().rev()),
+    };
+    for word in ordered {
+        let tokens = count_word_tokens(word);
+        if used + tokens > budget {
+            break;
+        }
+        used += tokens;
+        kept += 1;
+    }
+
+    // Cut at the byte offset of the first dropped or kept word, keeping the original whitespace
+    let offset = |word: &str| word.as_ptr() as usize - text.as_ptr() as usize;
+    match keep {
+        Keep::Start if kept == 0 => TRIM_MARKER.to_string(),
+        Keep::Start => format!("{} {}", text[..offset(words[kept])].trim_end(), TRIM_MARKER),
+        Keep::End if kept == 0 => TRIM_MARKER.to_string(),
+        Keep::End => format!("{} {}", TRIM_MARKER, &text[offset(words[words.len() - kept])..]),
+    }
+}
+
+/// Renders a prompt template, shortening the longest variables until the
+/// system prompt and the rendered prompt fit the token budget
+///
+/// Trimming is logged as a warning. If the prompt still does not fit once
+/// every trimmable variable is exhausted, it is returned anyway.
+///
+/// # Arguments
+/// * `templates` - The prompt templates
+/// * `name` - Name of the template to render
+/// * `context` - Variables for the template; trimmed variables are replaced
+/// * `system_prompt` - System prompt sent along with the prompt, if any
+/// * `budget` - Maximum number of tokens of the system prompt and prompt together
+pub fn render_within_budget(
+    templates: &PromptTemplates,
+    name: &str,
+    context: &mut tera::Context,
+    system_prompt: Option<&str>,
+    budget: usize,
+) -> Result<String, StoryChainError> {
+    let system_tokens = system_prompt.map(count_tokens).unwrap_or_default();
+    let mut prompt = templates.render(name, context)?;
+    let original = system_tokens + count_tokens(&prompt);
+    let mut total = original;
+    if total <= budget {
+        debug!("Prompt takes about {} of {} tokens", total, budget);
+        return Ok(prompt);
+    }
+
+    let mut trimmed = Vec::new();
+    for (variable, keep) in TRIMMABLE {
+        let Some(mut value) = context.get(variable).and_then(|v| v.as_str()).map(str::to_string) else {
+            continue;
+        };
+        // Counts of the rendered prompt and its parts can differ slightly, so shorten until it fits
+        while total > budget {
+            let shortened = truncate_to_tokens(&value, count_tokens(&value).saturating_sub(total - budget), keep);
+            if shortened == value {
+                break;
+            }
+            value = shortened;
+            context.insert(variable, &value);
+            prompt = templates.render(name, context)?;
+            total = system_tokens + count_tokens(&prompt);
+            if !trimmed.contains(&variable) {
+                trimmed.push(variable);
+            }
+        }
+        if total <= budget {
+            break;
+        }
+    }
+
+    if total > budget {
+        warn!(
+            "Prompt takes about {} tokens, over the budget of {} even after trimming {}",
+            total,
+            budget,
+            trimmed.join(", ")
+        );
+    } else {
+        warn!(
+            "Prompt took about {} tokens, over the budget of {}; trimmed {} to {} tokens",
+            original,
+            budget,
+            trimmed.join(", "),
+            total
+        );
+    }
+    Ok(prompt)
+}
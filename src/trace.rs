@@ -0,0 +1,224 @@
+//! Run Tracing
+//!
+//! This module profiles where a run spends its time. Scene generation is
+//! divided into `tracing` spans: `scene` for each generated scene, with
+//! `prompt_build` (summaries, recall, and rendering the prompt),
+//! `ai_generate` (the request to the model), `revise` (the dialogue, length,
+//! consistency, and content-filter passes), and `parse` (pulling the scene
+//! card, setups, and viewpoint out of the response and checking it) inside
+//! it, and `persist` around saving the story. `TraceRecorder` is a
+//! `tracing-subscriber` layer that collects the closed spans, logs their
+//! durations at debug level, sums them per phase, and exports them in the
+//! Chrome trace event format, which `chrome://tracing` and Perfetto open.
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::Subscriber;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+use log::debug;
+use crate::StoryChainError;
+
+/// Span around the generation of one scene
+pub const SCENE_SPAN: &str = "scene";
+
+/// Span around building a scene's prompt
+pub const PROMPT_BUILD_SPAN: &str = "prompt_build";
+
+/// Span around a request to the model
+pub const AI_GENERATE_SPAN: &str = "ai_generate";
+
+/// Span around the revision passes of a scene
+pub const REVISE_SPAN: &str = "revise";
+
+/// Span around reading and checking a generated scene
+pub const PARSE_SPAN: &str = "parse";
+
+/// Span around saving the story
+pub const PERSIST_SPAN: &str = "persist";
+
+/// A closed span
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct SpanRecord {
+    /// Name of the span, such as `ai_generate`
+    pub name: String,
+
+    /// Fields recorded on the span, such as the node ID
+    pub fields: BTreeMap<String, String>,
+
+    /// When the span was opened, from the start of the recording
+    pub start: Duration,
+
+    /// How long the span was open
+    pub duration: Duration,
+
+    /// Number of spans the span was nested in
+    pub depth: usize,
+}
+
+/// Time spent in one kind of span
+#[derive(Debug, Clone, PartialEq)]
+pub struct PhaseTotal {
+    /// Name of the spans
+    pub name: String,
+
+    /// Number of spans closed
+    pub count: usize,
+
+    /// Time the spans were open, summed
+    pub total: Duration,
+}
+
+/// Collects the fields of a span as text
+struct FieldVisitor<'a>(&'a mut BTreeMap<String, String>);
+
+impl Visit for FieldVisitor<'_> {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0.insert(field.name().to_string(), format!("{:?}", value));
+    }
+}
+
+/// Timing and fields kept on an open span
+struct OpenSpan {
+    /// When the span was opened
+    opened: Instant,
+
+    /// Fields recorded on the span so far
+    fields: BTreeMap<String, String>,
+}
+
+/// Records the spans of a run; clones share the same recording
+#[derive(Debug, Clone)]
+pub struct TraceRecorder {
+    /// When the recording started
+    started: Instant,
+
+    /// The closed spans, in the order they closed
+    spans: Arc<Mutex<Vec<SpanRecord>>>,
+}
+
+impl Default for TraceRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TraceRecorder {
+    /// Creates a new, empty recording
+    pub fn new() -> Self {
+        Self { started: Instant::now(), spans: Arc::new(Mutex::new(Vec::new())) }
+    }
+
+    /// Returns the spans closed so far
+    pub fn spans(&self) -> Vec<SpanRecord> {
+        self.spans.lock().unwrap().clone()
+    }
+
+    /// Returns the time spent in each kind of span, longest first
+    pub fn phase_totals(&self) -> Vec<PhaseTotal> {
+        let mut totals: BTreeMap<String, PhaseTotal> = BTreeMap::new();
+        for span in self.spans.lock().unwrap().iter() {
+            let total = totals.entry(span.name.clone()).or_insert_with(|| PhaseTotal {
+                name: span.name.clone(),
+                count: 0,
+                total: Duration::ZERO,
+            });
+            total.count += 1;
+            total.total += span.duration;
+        }
+        let mut totals: Vec<PhaseTotal> = totals.into_values().collect();
+        totals.sort_by_key(|phase| std::cmp::Reverse(phase.total));
+        totals
+    }
+
+    /// Returns the time spent in each kind of span as one line
+    pub fn summary(&self) -> String {
+        self.phase_totals()
+            .iter()
+            .map(|phase| format!("{} {:.1}s ({})", phase.name, phase.total.as_secs_f64(), phase.count))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Returns the spans in the Chrome trace event format
+    pub fn to_chrome_trace(&self) -> serde_json::Value {
+        let events: Vec<serde_json::Value> = self
+            .spans
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|span| {
+                json!({
+                    "name": span.name,
+                    "cat": "storychain",
+                    "ph": "X",
+                    "ts": span.start.as_micros() as u64,
+                    "dur": span.duration.as_micros() as u64,
+                    "pid": 1,
+                    "tid": 1,
+                    "args": span.fields,
+                })
+            })
+            .collect();
+        json!({ "traceEvents": events, "displayTimeUnit": "ms" })
+    }
+
+    /// Writes the spans to a JSON trace file
+    ///
+    /// # Arguments
+    /// * `path` - The file to write
+    pub fn export(&self, path: impl AsRef<Path>) -> Result<(), StoryChainError> {
+        crate::files::write_atomic(path, serde_json::to_string_pretty(&self.to_chrome_trace())?)
+    }
+}
+
+impl<S> Layer<S> for TraceRecorder
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut fields = BTreeMap::new();
+        attrs.record(&mut FieldVisitor(&mut fields));
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(OpenSpan { opened: Instant::now(), fields });
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(open) = span.extensions_mut().get_mut::<OpenSpan>() {
+                values.record(&mut FieldVisitor(&mut open.fields));
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(open) = span.extensions_mut().remove::<OpenSpan>() else {
+            return;
+        };
+        let duration = open.opened.elapsed();
+        debug!("{} took {:?}", span.name(), duration);
+        self.spans.lock().unwrap().push(SpanRecord {
+            name: span.name().to_string(),
+            fields: open.fields,
+            start: open.opened.saturating_duration_since(self.started),
+            duration,
+            depth: span.scope().skip(1).count(),
+        });
+    }
+}
@@ -0,0 +1,212 @@
+//! Translation
+//!
+//! This module translates a finished story into another language.
+//! `StoryChain::translate` walks the chain in reading order and has the
+//! model translate every scene, showing it the translation of the scene
+//! before so that names, terms, and voice stay consistent, then the
+//! chapter titles and the title, logline, and blurb. The result is a
+//! parallel chain with the same nodes, links, and chapters, so each
+//! translated scene can be matched with its original; metadata derived
+//! from the prose, such as summaries and moods, stays in the original
+//! language.
+
+use log::{debug, info, warn};
+use tera::Context;
+use crate::passes::parse_labeled_fields;
+use crate::provenance::TRANSLATION_REVISION;
+use crate::{AIProvider, StoryChain, StoryChainError};
+
+/// Chain metadata key holding the language a chain was translated into
+pub const LANGUAGE_KEY: &str = "language";
+
+/// Name of the scene translation prompt template
+pub const TRANSLATION: &str = "translation";
+
+/// Built-in template of the scene translation prompt
+///
+/// Variables: `language`, the name of the language to translate into;
+/// `previous_scene`, the translation of the scene before, empty when there
+/// is none; `scene`, the scene to translate.
+pub const TRANSLATION_TEMPLATE: &str = r#"You are a literary translator. Translate the scene below into {{ language }}. Keep its meaning, tone, paragraphing, and dialogue, following the punctuation conventions of {{ language }}. Keep the names of characters and places unless they have an established {{ language }} form. Do not add, leave out, or summarize anything.
+
+{% if previous_scene %}Previous Scene, Already Translated:
+{{ previous_scene }}
+
+{% endif %}Scene:
+{{ scene }}
+
+IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your notes on names, terms, and voice.
+</think>
+Write the translated scene here, with no commentary."#;
+
+/// Name of the title and chapter title translation prompt template
+pub const LABEL_TRANSLATION: &str = "label_translation";
+
+/// Built-in template of the prompt translating the chapter titles and the
+/// title, logline, and blurb
+///
+/// Variables: `language`, the name of the language to translate into;
+/// `texts`, the texts to translate, each after its label.
+pub const LABEL_TRANSLATION_TEMPLATE: &str = r#"You are a literary translator. Translate the text after each label below into {{ language }}, keeping the labels as they are.
+
+{% for text in texts %}{{ text }}
+{% endfor %}
+IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your notes on the translation.
+</think>
+Each label, followed by a colon and the translated text, on a line of its own."#;
+
+/// Chain metadata keys translated along with the scenes
+const TRANSLATED_METADATA: [(&str, &str); 3] = [("TITLE", "title"), ("LOGLINE", "logline"), ("BLURB", "blurb")];
+
+/// Names of the languages most often translated into, by ISO 639-1 code
+const LANGUAGE_NAMES: [(&str, &str); 22] = [
+    ("ar", "Arabic"),
+    ("cs", "Czech"),
+    ("da", "Danish"),
+    ("de", "German"),
+    ("el", "Greek"),
+    ("en", "English"),
+    ("es", "Spanish"),
+    ("fi", "Finnish"),
+    ("fr", "French"),
+    ("hi", "Hindi"),
+    ("it", "Italian"),
+    ("ja", "Japanese"),
+    ("ko", "Korean"),
+    ("nl", "Dutch"),
+    ("no", "Norwegian"),
+    ("pl", "Polish"),
+    ("pt", "Portuguese"),
+    ("ru", "Russian"),
+    ("sv", "Swedish"),
+    ("tr", "Turkish"),
+    ("uk", "Ukrainian"),
+    ("zh", "Chinese"),
+];
+
+/// Returns the name of a language for the prompts
+///
+/// # Arguments
+/// * `language` - An ISO 639-1 code such as `de`, or a language name such as
+///   `Brazilian Portuguese`, which is used as given
+pub fn language_name(language: &str) -> String {
+    let language = language.trim();
+    LANGUAGE_NAMES
+        .iter()
+        .find(|(code, _)| code.eq_ignore_ascii_case(language))
+        .map_or_else(|| language.to_string(), |(_, name)| name.to_string())
+}
+
+/// Returns the ISO 639-1 code of a language, if it is one of the languages
+/// most often translated into
+///
+/// # Arguments
+/// * `language` - An ISO 639-1 code such as `de`, or a language name such as `German`
+pub fn language_code(language: &str) -> Option<&'static str> {
+    let language = language.trim();
+    LANGUAGE_NAMES
+        .iter()
+        .find(|(code, name)| code.eq_ignore_ascii_case(language) || name.eq_ignore_ascii_case(language))
+        .map(|(code, _)| *code)
+}
+
+impl StoryChain {
+    /// Translates the chain into another language
+    ///
+    /// # Arguments
+    /// * `ai_provider` - The AI provider to translate with
+    /// * `target_language` - An ISO 639-1 code such as `de`, or a language name
+    ///
+    /// # Returns
+    /// A copy of the chain with its scenes, chapter titles, and title, logline,
+    /// and blurb translated, and the language in its `language` metadata
+    pub async fn translate(
+        &self,
+        ai_provider: &dyn AIProvider,
+        target_language: &str,
+    ) -> Result<StoryChain, StoryChainError> {
+        let language = language_name(target_language);
+        if language.is_empty() {
+            return Err(StoryChainError::ConfigError("No language to translate into".to_string()));
+        }
+        let mut translated = self.clone();
+        let node_ids: Vec<String> = self.nodes_in_reading_order().iter().map(|node| node.id.clone()).collect();
+        info!("Translating {} scenes into {}", node_ids.len(), language);
+
+        for (index, node_id) in node_ids.iter().enumerate() {
+            // Show the translation of the scene this one follows, for consistent names and terms
+            let previous = self.nodes[node_id]
+                .predecessors
+                .first()
+                .filter(|id| node_ids[..index].contains(id))
+                .map(|id| translated.nodes[id].content.clone());
+            let mut context = Context::new();
+            context.insert("language", &language);
+            context.insert("previous_scene", &previous.unwrap_or_default());
+            context.insert("scene", self.nodes[node_id].content.trim());
+            let prompt = self.render_prompt(TRANSLATION, context)?;
+            let (_, content) = ai_provider.generate(&prompt).await?;
+            if content.trim().is_empty() {
+                return Err(StoryChainError::InvalidReasoningFormat(format!("Translation of {} was empty", node_id)));
+            }
+            let node = translated.nodes.get_mut(node_id).expect("scene exists");
+            node.content = content.trim().to_string();
+            if let Some(provenance) = &mut node.provenance {
+                provenance.revised(TRANSLATION_REVISION);
+            }
+            debug!("Translated {} ({} of {})", node_id, index + 1, node_ids.len());
+        }
+
+        translated.translate_labels(ai_provider, &language).await?;
+        translated.metadata.insert(LANGUAGE_KEY.to_string(), target_language.trim().to_string());
+        info!("Translated the story into {}", language);
+        Ok(translated)
+    }
+
+    /// Translates the chapter titles and the title, logline, and blurb in one request
+    ///
+    /// # Arguments
+    /// * `ai_provider` - The AI provider to translate with
+    /// * `language` - Name of the language to translate into
+    async fn translate_labels(&mut self, ai_provider: &dyn AIProvider, language: &str) -> Result<(), StoryChainError> {
+        let mut labels: Vec<String> = Vec::new();
+        let mut texts = Vec::new();
+        for (label, key) in TRANSLATED_METADATA {
+            if let Some(value) = self.metadata.get(key) {
+                labels.push(label.to_string());
+                texts.push(format!("{}: {}", label, value));
+            }
+        }
+        for (index, chapter) in self.chapters.iter().enumerate() {
+            labels.push(format!("CHAPTER {}", index + 1));
+            texts.push(format!("CHAPTER {}: {}", index + 1, chapter.title));
+        }
+        if labels.is_empty() {
+            return Ok(());
+        }
+
+        let mut context = Context::new();
+        context.insert("language", language);
+        context.insert("texts", &texts);
+        let prompt = self.render_prompt(LABEL_TRANSLATION, context)?;
+        let (_, content) = ai_provider.generate(&prompt).await?;
+        let label_refs: Vec<&str> = labels.iter().map(String::as_str).collect();
+        let fields = parse_labeled_fields(&content, &label_refs);
+        for (label, key) in TRANSLATED_METADATA {
+            if let Some(value) = fields.get(label).filter(|v| !v.is_empty()) {
+                self.metadata.insert(key.to_string(), value.trim_matches('"').to_string());
+            }
+        }
+        for (index, chapter) in self.chapters.iter_mut().enumerate() {
+            match fields.get(&format!("CHAPTER {}", index + 1)).filter(|v| !v.is_empty()) {
+                Some(title) => chapter.title = title.clone(),
+                None => warn!("Chapter {} title was not translated; keeping \"{}\"", index + 1, chapter.title),
+            }
+        }
+        Ok(())
+    }
+}
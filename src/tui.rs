@@ -0,0 +1,249 @@
+//! Terminal Browser
+//!
+//! This module is a terminal interface for inspecting a generated chain. The
+//! node graph is shown as a tree, with alternative branches indented under
+//! the scene they leave from, next to the selected scene's content and the
+//! AI's reasoning for it. Scenes can be marked for regeneration, and the
+//! browser hands control back to its caller to regenerate the marked scenes
+//! or continue the story, since both need an AI provider.
+//!
+//! Available with the `tui` feature.
+
+use std::collections::HashSet;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use crate::editing::REGENERATE_KEY;
+use crate::{StoryChain, StoryChainError};
+
+/// Characters of a scene shown next to its ID in the graph
+const EXCERPT_CHARS: usize = 40;
+
+/// Lines scrolled by Page Up and Page Down
+const SCROLL_LINES: u16 = 10;
+
+/// Key help shown when there is no status message
+const HELP: &str = "↑/↓ select  PgUp/PgDn scroll  m mark  g regenerate marked  c continue  q quit";
+
+/// A row of the graph tree
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphRow {
+    /// ID of the node shown in the row
+    pub node_id: String,
+
+    /// How many alternative branches deep the node is; 0 on the main storyline
+    pub branch_level: usize,
+}
+
+/// What the browser asks its caller to do when it returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TuiRequest {
+    /// Save the story and stop
+    Quit,
+    /// Regenerate the scenes marked for regeneration
+    RegenerateMarked,
+    /// Generate the next epoch at the ends of every storyline
+    Continue,
+}
+
+/// Lays the chain out as a tree: each storyline in order, with alternative
+/// branches placed under the scene they leave from, and nodes not reachable
+/// from the root at the end
+pub fn graph_rows(chain: &StoryChain) -> Vec<GraphRow> {
+    fn visit(chain: &StoryChain, start: &str, level: usize, rows: &mut Vec<GraphRow>, seen: &mut HashSet<String>) {
+        let mut current = Some(start.to_string());
+        while let Some(id) = current.take() {
+            let Some(node) = chain.nodes.get(&id) else { break };
+            if !seen.insert(id.clone()) {
+                break;
+            }
+            rows.push(GraphRow { node_id: id, branch_level: level });
+            for branch in node.successors.iter().skip(1) {
+                visit(chain, branch, level + 1, rows, seen);
+            }
+            current = node.successors.first().cloned();
+        }
+    }
+
+    let mut rows = Vec::new();
+    let mut seen = HashSet::new();
+    visit(chain, &chain.root_node_id, 0, &mut rows, &mut seen);
+    let mut unreachable: Vec<&String> = chain.nodes.keys().filter(|id| !seen.contains(*id)).collect();
+    unreachable.sort();
+    rows.extend(unreachable.into_iter().map(|id| GraphRow { node_id: id.clone(), branch_level: 0 }));
+    rows
+}
+
+/// State of the terminal browser, kept across calls to `run` so that the
+/// selection survives regeneration and continuation
+pub struct ChainBrowser {
+    rows: Vec<GraphRow>,
+    selected: usize,
+    scroll: u16,
+    status: Option<String>,
+}
+
+impl ChainBrowser {
+    /// Creates a browser showing the chain with the opening scene selected
+    pub fn new(chain: &StoryChain) -> Self {
+        ChainBrowser { rows: graph_rows(chain), selected: 0, scroll: 0, status: None }
+    }
+
+    /// Returns the rows of the graph tree
+    pub fn rows(&self) -> &[GraphRow] {
+        &self.rows
+    }
+
+    /// Returns the ID of the selected node
+    pub fn selected_id(&self) -> Option<&str> {
+        self.rows.get(self.selected).map(|row| row.node_id.as_str())
+    }
+
+    /// Shows a message in the status line until the next key press
+    pub fn set_status(&mut self, status: impl Into<String>) {
+        self.status = Some(status.into());
+    }
+
+    /// Lays the graph out again after the chain changed, keeping the
+    /// selected node selected
+    pub fn refresh(&mut self, chain: &StoryChain) {
+        let selected_id = self.selected_id().map(str::to_string);
+        self.rows = graph_rows(chain);
+        self.selected = selected_id
+            .and_then(|id| self.rows.iter().position(|row| row.node_id == id))
+            .unwrap_or(0);
+    }
+
+    /// Selects a row, scrolling the scene back to its start
+    fn select(&mut self, index: usize) {
+        self.selected = index.min(self.rows.len().saturating_sub(1));
+        self.scroll = 0;
+    }
+
+    /// Applies a key press
+    ///
+    /// # Arguments
+    /// * `chain` - The chain being browsed; marking changes its metadata
+    /// * `key` - The key pressed
+    ///
+    /// # Returns
+    /// The request for the caller when the key asks for one
+    pub fn handle_key(&mut self, chain: &mut StoryChain, key: KeyCode) -> Option<TuiRequest> {
+        self.status = None;
+        match key {
+            KeyCode::Up | KeyCode::Char('k') => self.select(self.selected.saturating_sub(1)),
+            KeyCode::Down | KeyCode::Char('j') => self.select(self.selected + 1),
+            KeyCode::Home => self.select(0),
+            KeyCode::End => self.select(self.rows.len()),
+            KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(SCROLL_LINES),
+            KeyCode::PageDown => self.scroll = self.scroll.saturating_add(SCROLL_LINES),
+            KeyCode::Char('m') | KeyCode::Char(' ') => {
+                let id = self.selected_id()?.to_string();
+                let marked = !chain.nodes[&id].metadata.contains_key(REGENERATE_KEY);
+                match chain.mark_for_regeneration(&id, marked) {
+                    Ok(()) if marked => self.set_status(format!("Marked {} for regeneration", id)),
+                    Ok(()) => self.set_status(format!("Unmarked {}", id)),
+                    Err(error) => self.set_status(error.to_string()),
+                }
+            }
+            KeyCode::Char('g') => {
+                if chain.marked_for_regeneration().is_empty() {
+                    self.set_status("No scenes are marked for regeneration");
+                } else {
+                    return Some(TuiRequest::RegenerateMarked);
+                }
+            }
+            KeyCode::Char('c') => return Some(TuiRequest::Continue),
+            KeyCode::Char('q') | KeyCode::Esc => return Some(TuiRequest::Quit),
+            _ => {}
+        }
+        None
+    }
+
+    /// Draws the graph, the selected scene and its reasoning, and the status line
+    pub fn render(&self, chain: &StoryChain, frame: &mut Frame) {
+        let [main, status] = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [graph, content, reasoning] =
+            Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(40), Constraint::Percentage(30)]).areas(main);
+
+        let items: Vec<ListItem> = self
+            .rows
+            .iter()
+            .map(|row| {
+                let node = &chain.nodes[&row.node_id];
+                let indent = if row.branch_level == 0 { String::new() } else { format!("{}└ ", "  ".repeat(row.branch_level - 1)) };
+                let mark = if node.metadata.contains_key(REGENERATE_KEY) { "● " } else { "" };
+                let excerpt: String = node.content.split_whitespace().collect::<Vec<_>>().join(" ").chars().take(EXCERPT_CHARS).collect();
+                ListItem::new(format!("{}{}{}  {}", indent, mark, node.id, excerpt))
+            })
+            .collect();
+        let mut list_state = ListState::default().with_selected(Some(self.selected));
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::bordered().title(format!("Graph ({} nodes)", chain.nodes.len())))
+                .highlight_style(Style::default().add_modifier(Modifier::REVERSED)),
+            graph,
+            &mut list_state,
+        );
+
+        if let Some(node) = self.selected_id().and_then(|id| chain.nodes.get(id)) {
+            let mut title = node.id.clone();
+            if !node.predecessors.is_empty() {
+                title.push_str(&format!(" ← {}", node.predecessors.join(", ")));
+            }
+            if !node.successors.is_empty() {
+                title.push_str(&format!(" → {}", node.successors.join(", ")));
+            }
+            frame.render_widget(
+                Paragraph::new(node.content.trim())
+                    .block(Block::bordered().title(title))
+                    .wrap(Wrap { trim: false })
+                    .scroll((self.scroll, 0)),
+                content,
+            );
+            frame.render_widget(
+                Paragraph::new(node.reasoning.trim())
+                    .block(Block::bordered().title("Reasoning"))
+                    .wrap(Wrap { trim: false })
+                    .scroll((self.scroll, 0)),
+                reasoning,
+            );
+        }
+
+        frame.render_widget(Line::from(self.status.as_deref().unwrap_or(HELP)), status);
+    }
+
+    /// Draws the browser and applies key presses until one asks for a request
+    fn event_loop(&mut self, chain: &mut StoryChain, terminal: &mut DefaultTerminal) -> Result<TuiRequest, StoryChainError> {
+        loop {
+            terminal.draw(|frame| self.render(chain, frame))?;
+            if let Event::Key(key) = event::read()? {
+                if key.kind != KeyEventKind::Press {
+                    continue;
+                }
+                if let Some(request) = self.handle_key(chain, key.code) {
+                    return Ok(request);
+                }
+            }
+        }
+    }
+}
+
+/// Takes over the terminal and browses the chain until the user asks to
+/// quit, regenerate the marked scenes, or continue the story
+///
+/// The terminal is restored before returning, so the caller can print
+/// progress while it carries out the request and then call `run` again.
+///
+/// # Arguments
+/// * `chain` - The chain to browse
+/// * `browser` - The browser state
+pub fn run(chain: &mut StoryChain, browser: &mut ChainBrowser) -> Result<TuiRequest, StoryChainError> {
+    let mut terminal = ratatui::init();
+    let result = browser.event_loop(chain, &mut terminal);
+    ratatui::restore();
+    result
+}
@@ -0,0 +1,211 @@
+//! Twine Export
+//!
+//! This module exports branching chains as interactive fiction in the Twee 3
+//! source format, which Twine imports and Tweego compiles. Every node becomes
+//! a passage and its successors become links. Link text comes from the
+//! successor's `choice` metadata, which an optional pass can ask the AI to
+//! write, and otherwise from the successor's opening sentence.
+
+use log::{info, debug};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use tera::Context;
+use crate::html::choice_label;
+use crate::passes::{parse_labeled_fields, SCENE_EXCERPT_CHARS};
+use crate::{AIProvider, StoryChain, StoryChainError, StoryNode};
+
+/// Name of the choice label prompt template
+pub const CHOICE_LABELS: &str = "choice_labels";
+
+/// Built-in template of the prompt the choice texts of a branching scene
+/// are written from
+///
+/// Variables: `ending`, the end of the scene; `options`, the openings of the
+/// scenes it branches into.
+pub const CHOICE_LABELS_TEMPLATE: &str = r#"The reader of an interactive story has reached the end of the scene below and must choose how the story continues. For each option, write the choice as the reader sees it: a short imperative phrase of at most eight words, such as "Follow the stranger", that does not give away what happens next.
+
+End of the Scene:
+{{ ending }}
+
+{% for option in options %}Option {{ loop.index }}:
+{{ option }}
+
+{% endfor %}IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your reasoning about what distinguishes the options.
+</think>
+{% for option in options %}CHOICE {{ loop.index }}: ...{% if not loop.last %}
+{% endif %}{% endfor %}"#;
+
+/// Story format the exported story is set up for
+const STORY_FORMAT: &str = "Harlowe";
+
+/// Version of the story format
+const STORY_FORMAT_VERSION: &str = "3.3.8";
+
+/// Escapes characters that have a meaning in Twee passage headers and links
+fn escape_passage_name(name: &str) -> String {
+    let mut escaped = String::with_capacity(name.len());
+    for c in name.chars() {
+        if matches!(c, '[' | ']' | '{' | '}' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Makes text safe to use as the text of a Twee link
+fn link_text(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('[', "(")
+        .replace(']', ")")
+        .replace("->", "-")
+        .replace('|', "/")
+}
+
+/// Escapes lines of passage text that Twee would read as passage headers
+fn escape_passage_text(text: &str) -> String {
+    text.trim()
+        .lines()
+        .map(|line| if line.starts_with("::") { format!("\\{}", line) } else { line.to_string() })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl StoryChain {
+    /// Returns the Interactive Fiction ID of the story
+    ///
+    /// Uses the chain's `ifid` metadata when present and otherwise derives a
+    /// stable version 4 UUID from the opening scene, so that re-exporting the
+    /// same story keeps its identity in Twine.
+    pub fn ifid(&self) -> String {
+        if let Some(ifid) = self.metadata.get("ifid") {
+            return ifid.to_uppercase();
+        }
+        let mut hasher = Sha256::new();
+        hasher.update(self.root_node_id.as_bytes());
+        if let Some(root) = self.nodes.get(&self.root_node_id) {
+            hasher.update(root.content.as_bytes());
+        }
+        let mut bytes: [u8; 16] = hasher.finalize()[..16].try_into().expect("a SHA-256 digest has 32 bytes");
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        let hex: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+    }
+
+    /// Asks the AI for the text of the choices leading to alternative
+    /// branches and stores it in each successor's `choice` metadata
+    ///
+    /// Only successors of nodes with several successors are labeled, and
+    /// existing `choice` metadata is kept.
+    ///
+    /// # Arguments
+    /// * `ai_provider` - The AI provider to use for generation
+    ///
+    /// # Returns
+    /// The number of successors that were given a choice text
+    pub async fn generate_choice_labels(&mut self, ai_provider: &dyn AIProvider) -> Result<usize, StoryChainError> {
+        let decisions: Vec<(String, Vec<String>)> = self
+            .nodes_in_graph_order()
+            .into_iter()
+            .filter(|node| node.successors.len() > 1)
+            .filter(|node| node.successors.iter().any(|id| self.nodes.get(id).is_some_and(|n| !n.metadata.contains_key("choice"))))
+            .map(|node| (node.id.clone(), node.successors.clone()))
+            .collect();
+
+        let mut labeled = 0;
+        for (node_id, successor_ids) in decisions {
+            // The choice follows the end of the scene and leads into the start of each option
+            let scene: Vec<char> = self.nodes[&node_id].content.chars().collect();
+            let ending: String = scene[scene.len().saturating_sub(SCENE_EXCERPT_CHARS)..].iter().collect();
+            let options: Vec<String> = successor_ids
+                .iter()
+                .map(|id| self.nodes[id].content.chars().take(SCENE_EXCERPT_CHARS).collect::<String>().trim().to_string())
+                .collect();
+            let mut context = Context::new();
+            context.insert("ending", ending.trim());
+            context.insert("options", &options);
+            let prompt = self.render_prompt(CHOICE_LABELS, context)?;
+
+            let (_, response) = ai_provider.generate(&prompt).await?;
+            let labels: Vec<String> = (1..=successor_ids.len()).map(|i| format!("CHOICE {}", i)).collect();
+            let label_refs: Vec<&str> = labels.iter().map(String::as_str).collect();
+            let fields = parse_labeled_fields(&response, &label_refs);
+            for (label, id) in labels.iter().zip(&successor_ids) {
+                let Some(choice) = fields.get(label) else {
+                    debug!("No choice text for {} in the response", id);
+                    continue;
+                };
+                let node = self.nodes.get_mut(id).expect("successor exists");
+                if !node.metadata.contains_key("choice") {
+                    node.metadata.insert("choice".to_string(), choice.trim_matches('"').to_string());
+                    labeled += 1;
+                }
+            }
+        }
+        info!("Generated choice text for {} branches", labeled);
+        Ok(labeled)
+    }
+
+    /// Renders the chain as a Twee 3 story
+    ///
+    /// Each node becomes a passage named after its ID, laid out in columns by
+    /// depth. A node with one successor links to it with "Continue"; a node
+    /// with several offers one link per successor, labeled with its
+    /// `choice` metadata or its opening sentence. The story starts at the
+    /// root and uses the Harlowe story format.
+    pub fn to_twee(&self) -> String {
+        let title = self.metadata.get("title").map(String::as_str).unwrap_or("Generated Story");
+        let story_data = serde_json::json!({
+            "ifid": self.ifid(),
+            "format": STORY_FORMAT,
+            "format-version": STORY_FORMAT_VERSION,
+            "start": self.root_node_id,
+        });
+        let mut twee = format!(
+            ":: StoryTitle\n{}\n\n:: StoryData\n{}\n\n",
+            title.trim(),
+            serde_json::to_string_pretty(&story_data).expect("story data serializes")
+        );
+
+        // Lay the passages out in one column per scene depth
+        let mut rows: HashMap<usize, usize> = HashMap::new();
+        for node in self.nodes_in_graph_order() {
+            let depth = self.path_to(&node.id).len().saturating_sub(1);
+            let row = rows.entry(depth).or_default();
+            let position = format!("{},{}", 100 + depth * 200, 100 + *row * 150);
+            *row += 1;
+
+            twee.push_str(&format!(
+                ":: {} {{\"position\":\"{}\"}}\n{}\n",
+                escape_passage_name(&node.id),
+                position,
+                escape_passage_text(&node.content)
+            ));
+            let successors: Vec<&StoryNode> = node.successors.iter().filter_map(|id| self.nodes.get(id)).collect();
+            if !successors.is_empty() {
+                twee.push('\n');
+            }
+            for successor in &successors {
+                let text = if successors.len() == 1 { "Continue".to_string() } else { link_text(&choice_label(successor)) };
+                twee.push_str(&format!("[[{}->{}]]\n", text, escape_passage_name(&successor.id)));
+            }
+            twee.push('\n');
+        }
+        twee
+    }
+
+    /// Exports the chain as a Twee 3 file that Twine can import
+    ///
+    /// # Arguments
+    /// * `path` - The path where the Twee file should be saved
+    pub fn export_to_twee(&self, path: &str) -> Result<(), StoryChainError> {
+        info!("Exporting story to Twee: {}", path);
+        crate::files::write_atomic(path, self.to_twee())?;
+        Ok(())
+    }
+}
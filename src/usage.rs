@@ -0,0 +1,283 @@
+//! Token Usage and Cost
+//!
+//! This module accounts for the tokens a run spends. Providers whose API
+//! reports token counts, such as Ollama's HTTP endpoint, keep their own
+//! totals; `UsageTracker` wraps any other provider and estimates the counts
+//! with the local tokenizer. Every generated node records the usage of the
+//! requests that made it in its provenance, and the chain keeps a running
+//! total across runs in `StoryChain::usage`, with an estimated cost when the
+//! configuration gives the provider's prices:
+//!
+//! ```toml
+//! [pricing]
+//! prompt_per_million = 0.55
+//! completion_per_million = 2.19
+//! currency = "USD"
+//! ```
+
+use std::ops::{Add, AddAssign};
+use std::sync::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::UnboundedSender;
+use log::info;
+use crate::tokenizer::count_tokens;
+use crate::{AIProvider, GenerationConfig, StoryChainError};
+
+/// Currency prices are given in when the configuration names none
+pub const DEFAULT_CURRENCY: &str = "USD";
+
+/// Tokens spent by one or more requests
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct TokenUsage {
+    /// Tokens sent to the model, system prompts included
+    pub prompt_tokens: u64,
+
+    /// Tokens the model generated, reasoning included
+    pub completion_tokens: u64,
+
+    /// Number of requests
+    pub requests: u64,
+}
+
+impl TokenUsage {
+    /// Returns the usage of a single request
+    ///
+    /// # Arguments
+    /// * `prompt_tokens` - Tokens sent to the model
+    /// * `completion_tokens` - Tokens the model generated
+    pub fn request(prompt_tokens: u64, completion_tokens: u64) -> Self {
+        Self { prompt_tokens, completion_tokens, requests: 1 }
+    }
+
+    /// Returns the number of prompt and completion tokens together
+    pub fn total_tokens(&self) -> u64 {
+        self.prompt_tokens + self.completion_tokens
+    }
+
+    /// Returns whether no request was counted
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns the usage added since an earlier reading of the same totals
+    ///
+    /// # Arguments
+    /// * `earlier` - The totals as they were before
+    pub fn since(&self, earlier: &TokenUsage) -> TokenUsage {
+        TokenUsage {
+            prompt_tokens: self.prompt_tokens.saturating_sub(earlier.prompt_tokens),
+            completion_tokens: self.completion_tokens.saturating_sub(earlier.completion_tokens),
+            requests: self.requests.saturating_sub(earlier.requests),
+        }
+    }
+}
+
+impl Add for TokenUsage {
+    type Output = TokenUsage;
+
+    fn add(self, other: TokenUsage) -> TokenUsage {
+        TokenUsage {
+            prompt_tokens: self.prompt_tokens + other.prompt_tokens,
+            completion_tokens: self.completion_tokens + other.completion_tokens,
+            requests: self.requests + other.requests,
+        }
+    }
+}
+
+impl AddAssign for TokenUsage {
+    fn add_assign(&mut self, other: TokenUsage) {
+        *self = *self + other;
+    }
+}
+
+/// Prices of a paid provider, per million tokens
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Pricing {
+    /// Price of a million prompt tokens
+    pub prompt_per_million: f64,
+
+    /// Price of a million completion tokens
+    pub completion_per_million: f64,
+
+    /// Currency of the prices, such as `USD`
+    pub currency: String,
+}
+
+impl Default for Pricing {
+    fn default() -> Self {
+        Self { prompt_per_million: 0.0, completion_per_million: 0.0, currency: DEFAULT_CURRENCY.to_string() }
+    }
+}
+
+impl Pricing {
+    /// Returns the estimated cost of some usage
+    ///
+    /// # Arguments
+    /// * `usage` - The tokens spent
+    pub fn cost(&self, usage: &TokenUsage) -> f64 {
+        (usage.prompt_tokens as f64 * self.prompt_per_million + usage.completion_tokens as f64 * self.completion_per_million)
+            / 1_000_000.0
+    }
+}
+
+/// Tokens and estimated cost a chain has spent over all of its runs
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct UsageSummary {
+    /// Tokens spent by every run
+    pub total: TokenUsage,
+
+    /// Tokens spent by the latest run
+    pub last_run: TokenUsage,
+
+    /// Number of runs counted
+    pub runs: usize,
+
+    /// Estimated cost of every run, when prices were configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<f64>,
+
+    /// Estimated cost of the latest run, when prices were configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_run_cost: Option<f64>,
+
+    /// Currency of the costs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+}
+
+impl UsageSummary {
+    /// Returns whether no run was counted
+    pub fn is_empty(&self) -> bool {
+        self.runs == 0
+    }
+
+    /// Adds a run to the totals
+    ///
+    /// # Arguments
+    /// * `usage` - The tokens the run spent
+    /// * `pricing` - The provider's prices, if it is a paid one
+    pub fn record_run(&mut self, usage: TokenUsage, pricing: Option<&Pricing>) {
+        self.total += usage;
+        self.last_run = usage;
+        self.runs += 1;
+        self.last_run_cost = pricing.map(|pricing| pricing.cost(&usage));
+        if let Some(pricing) = pricing {
+            self.cost = Some(self.cost.unwrap_or_default() + pricing.cost(&usage));
+            self.currency = Some(pricing.currency.clone());
+        }
+        info!("Run used {} tokens over {} requests", usage.total_tokens(), usage.requests);
+    }
+
+    /// Describes some usage and its cost on one line
+    fn describe(&self, usage: &TokenUsage, cost: Option<f64>) -> String {
+        let mut line = format!(
+            "{} prompt + {} completion = {} tokens over {} requests",
+            usage.prompt_tokens,
+            usage.completion_tokens,
+            usage.total_tokens(),
+            usage.requests
+        );
+        if let Some(cost) = cost {
+            line.push_str(&format!(", about {:.4} {}", cost, self.currency.as_deref().unwrap_or(DEFAULT_CURRENCY)));
+        }
+        line
+    }
+
+    /// Returns the summary printed at the end of a run
+    pub fn to_text(&self) -> String {
+        let mut text = format!("Token usage this run: {}", self.describe(&self.last_run, self.last_run_cost));
+        if self.runs > 1 {
+            text.push_str(&format!("\n{}", self.total_text()));
+        }
+        text
+    }
+
+    /// Returns the usage of every run on one line
+    pub fn total_text(&self) -> String {
+        format!("Token usage over {} runs: {}", self.runs, self.describe(&self.total, self.cost))
+    }
+}
+
+/// Wraps a provider so that the tokens of every request are counted
+///
+/// When the wrapped provider reports usage from its API, those figures are
+/// used; otherwise prompts and responses are measured with the local
+/// tokenizer.
+pub struct UsageTracker<P: AIProvider> {
+    /// The provider answering the requests
+    inner: P,
+
+    /// Usage estimated with the local tokenizer
+    counted: Mutex<TokenUsage>,
+}
+
+impl<P: AIProvider> UsageTracker<P> {
+    /// Creates a new UsageTracker
+    ///
+    /// # Arguments
+    /// * `inner` - The provider answering the requests
+    pub fn new(inner: P) -> Self {
+        Self { inner, counted: Mutex::new(TokenUsage::default()) }
+    }
+
+    /// Counts a request once it has succeeded
+    fn count(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        result: Result<(String, String), StoryChainError>,
+    ) -> Result<(String, String), StoryChainError> {
+        if let Ok((reasoning, content)) = &result {
+            let prompt_tokens = count_tokens(system_prompt.unwrap_or_default()) + count_tokens(prompt);
+            let completion_tokens = count_tokens(reasoning) + count_tokens(content);
+            *self.counted.lock().unwrap() += TokenUsage::request(prompt_tokens as u64, completion_tokens as u64);
+        }
+        result
+    }
+}
+
+#[async_trait::async_trait]
+impl<P: AIProvider> AIProvider for UsageTracker<P> {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        self.count(None, prompt, self.inner.generate(prompt).await)
+    }
+
+    async fn generate_with_system(&self, system_prompt: &str, prompt: &str) -> Result<(String, String), StoryChainError> {
+        self.count(Some(system_prompt), prompt, self.inner.generate_with_system(system_prompt, prompt).await)
+    }
+
+    async fn generate_with_config(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+    ) -> Result<(String, String), StoryChainError> {
+        self.count(system_prompt, prompt, self.inner.generate_with_config(system_prompt, prompt, config).await)
+    }
+
+    async fn generate_stream(
+        &self,
+        system_prompt: Option<&str>,
+        prompt: &str,
+        config: &GenerationConfig,
+        tokens: UnboundedSender<String>,
+    ) -> Result<(String, String), StoryChainError> {
+        self.count(system_prompt, prompt, self.inner.generate_stream(system_prompt, prompt, config, tokens).await)
+    }
+
+    fn provider_name(&self) -> &str {
+        self.inner.provider_name()
+    }
+
+    fn model_name(&self) -> Option<&str> {
+        self.inner.model_name()
+    }
+
+    /// Returns the wrapped provider's own figures, or the estimated ones
+    fn usage(&self) -> Option<TokenUsage> {
+        self.inner.usage().or_else(|| Some(*self.counted.lock().unwrap()))
+    }
+}
@@ -0,0 +1,307 @@
+//! Premise Wizard
+//!
+//! This module bootstraps a story from a conversation with its author. The
+//! model interviews the author one question at a time about the genre, the
+//! setting, the characters, and the themes, writes a structured premise from
+//! the answers, and then a character arc for each main character, saved as
+//! artifacts for the scene prompts.
+
+use std::collections::HashMap;
+use log::{debug, info};
+use tera::Context;
+use crate::artifacts::{Artifact, ArtifactManager, ArtifactType};
+use crate::characters::slugify;
+use crate::passes::{parse_labeled_fields, split_blocks, strip_code_fence};
+use crate::premise::Premise;
+use crate::prompts::PromptTemplates;
+use crate::{AIProvider, StoryChainError};
+
+/// Questions asked in an interview unless configured otherwise
+pub const DEFAULT_INTERVIEW_QUESTIONS: usize = 8;
+
+/// What an interview should find out about the story
+const INTERVIEW_TOPICS: [&str; 7] = [
+    "the genre and tone",
+    "the setting and time period",
+    "the central premise or conflict",
+    "the main characters, what they want, and how they change",
+    "the themes",
+    "events the plot should include",
+    "anything the story must avoid, and how long it should be",
+];
+
+/// Name of the interview question prompt template
+pub const INTERVIEW_QUESTION: &str = "interview_question";
+
+/// Built-in template of the prompt each interview question is asked from
+///
+/// Variables: `topics`, what the interview should find out; `transcript`,
+/// the questions and answers so far, empty before the first question.
+pub const INTERVIEW_QUESTION_TEMPLATE: &str = r#"You are a story editor interviewing an author about the story they want to write, so that you can write its premise. Find out about:
+{% for topic in topics %}- {{ topic }}
+{% endfor %}
+Interview so far:
+{% if transcript %}{{ transcript }}{% else %}(no questions asked yet){% endif %}
+
+Ask the single most useful next question, building on the answers so far and covering one topic at a time. Keep it short and friendly, and suggest a few options when the author may not have decided. If you already know enough to write the premise, answer DONE instead.
+
+IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your reasoning about what is still missing.
+</think>
+QUESTION: The question
+or, when the interview is complete:
+DONE"#;
+
+/// Name of the premise writing prompt template
+pub const PREMISE: &str = "premise";
+
+/// Built-in template of the prompt the premise is written from
+///
+/// Variables: `transcript`, the questions and answers of the interview.
+pub const PREMISE_TEMPLATE: &str = r#"You are a story editor. Write the premise of the story the author described in the interview below. Keep to what the author said, and fill in whatever they left open with choices that suit the rest.
+
+Interview:
+{{ transcript }}
+
+IMPORTANT: Format your response EXACTLY as follows:
+<think>
+Your reasoning about the story.
+</think>
+A YAML document with these keys, and nothing else:
+title: Working title
+genre: Genre
+tone: Tone of the prose
+pov: Point of view
+setting: Where the story takes place
+time_period: When it takes place
+premise: Two to four sentences setting up the central conflict
+characters:
+  - name: Name
+    description: Who they are and what they want
+    arc: How they change
+themes: [theme, theme]
+plot_elements: [event, event]
+constraints: [rule every scene must respect]
+target_length:
+  scenes: Number of scenes"#;
+
+/// Name of the character arc prompt template
+pub const CHARACTER_ARCS: &str = "character_arcs";
+
+/// Built-in template of the character arc prompt
+///
+/// Variables: `premise`, the premise naming the characters.
+pub const CHARACTER_ARCS_TEMPLATE: &str = r#"You are a story editor. For each main character of the story below, work out what drives them and how they change from beginning to end.
+
+Story Premise:
+{{ premise }}
+
+IMPORTANT: Format your response EXACTLY as follows, repeating the block for each character:
+<think>
+Your reasoning about the characters.
+</think>
+CHARACTER: The character's name as given
+WANT: What the character consciously pursues
+NEED: What the character actually needs
+FLAW: The flaw or belief standing in their way
+ARC: How the character changes, from the first scene to the last"#;
+
+/// Answer recorded when the author skips a question
+const NO_PREFERENCE: &str = "No preference; you decide.";
+
+/// Labels used in the character arc response format
+const ARC_LABELS: [&str; 5] = ["CHARACTER", "WANT", "NEED", "FLAW", "ARC"];
+
+/// An interview with an author about the story they want to tell
+#[derive(Debug, Clone, PartialEq)]
+pub struct PremiseInterview {
+    /// The questions asked and the author's answers, in order
+    pub exchanges: Vec<(String, String)>,
+
+    /// Most questions to ask
+    pub max_questions: usize,
+}
+
+impl Default for PremiseInterview {
+    fn default() -> Self {
+        Self::new(DEFAULT_INTERVIEW_QUESTIONS)
+    }
+}
+
+impl PremiseInterview {
+    /// Creates an interview that asks at most `max_questions` questions
+    pub fn new(max_questions: usize) -> Self {
+        Self { exchanges: Vec::new(), max_questions }
+    }
+
+    /// Creates an interview already answered by a one-line idea, for writing
+    /// a premise without asking anything
+    ///
+    /// # Arguments
+    /// * `idea` - The idea for the story
+    pub fn from_idea(idea: &str) -> Self {
+        Self {
+            exchanges: vec![("What story do you want to tell?".to_string(), idea.trim().to_string())],
+            max_questions: 0,
+        }
+    }
+
+    /// Returns the questions and answers so far as prompt text
+    fn transcript(&self) -> String {
+        self.exchanges
+            .iter()
+            .map(|(question, answer)| format!("Q: {}\nA: {}", question, answer))
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    /// Asks the model for the next question
+    ///
+    /// # Arguments
+    /// * `templates` - The prompt templates holding the interview prompt
+    /// * `ai_provider` - The AI provider conducting the interview
+    ///
+    /// # Returns
+    /// The question, or `None` once the model knows enough or the questions
+    /// are used up
+    pub async fn next_question(
+        &self,
+        templates: &PromptTemplates,
+        ai_provider: &dyn AIProvider,
+    ) -> Result<Option<String>, StoryChainError> {
+        if self.exchanges.len() >= self.max_questions {
+            return Ok(None);
+        }
+        let mut context = Context::new();
+        context.insert("topics", &INTERVIEW_TOPICS);
+        context.insert("transcript", &self.transcript());
+        let prompt = templates.render(INTERVIEW_QUESTION, &context)?;
+        let (_, content) = ai_provider.generate(&prompt).await?;
+        if content.lines().any(|line| line.trim().trim_matches(['*', '.']).eq_ignore_ascii_case("DONE")) {
+            debug!("The interview is complete after {} questions", self.exchanges.len());
+            return Ok(None);
+        }
+        let question = parse_labeled_fields(&content, &["QUESTION"])
+            .remove("QUESTION")
+            .unwrap_or_else(|| content.trim().to_string());
+        Ok(Some(question).filter(|question| !question.is_empty()))
+    }
+
+    /// Records the author's answer to a question; an empty answer leaves the
+    /// choice to the model
+    pub fn record(&mut self, question: String, answer: &str) {
+        let answer = if answer.trim().is_empty() { NO_PREFERENCE } else { answer.trim() };
+        self.exchanges.push((question, answer.to_string()));
+    }
+
+    /// Conducts the interview
+    ///
+    /// # Arguments
+    /// * `templates` - The prompt templates holding the interview prompt
+    /// * `ai_provider` - The AI provider conducting the interview
+    /// * `answer` - Puts a question to the author and returns their answer,
+    ///   or `None` when they want to stop
+    pub async fn run<F>(
+        &mut self,
+        templates: &PromptTemplates,
+        ai_provider: &dyn AIProvider,
+        mut answer: F,
+    ) -> Result<(), StoryChainError>
+    where
+        F: FnMut(&str) -> Result<Option<String>, StoryChainError>,
+    {
+        while let Some(question) = self.next_question(templates, ai_provider).await? {
+            let Some(reply) = answer(&question)? else {
+                break;
+            };
+            self.record(question, &reply);
+        }
+        info!("Interview finished after {} questions", self.exchanges.len());
+        Ok(())
+    }
+
+    /// Writes a structured premise from the interview
+    ///
+    /// # Arguments
+    /// * `templates` - The prompt templates holding the premise prompt
+    /// * `ai_provider` - The AI provider writing the premise
+    ///
+    /// # Returns
+    /// The validated premise, or an error when the model's YAML is unusable
+    pub async fn write_premise(
+        &self,
+        templates: &PromptTemplates,
+        ai_provider: &dyn AIProvider,
+    ) -> Result<Premise, StoryChainError> {
+        let mut context = Context::new();
+        context.insert("transcript", &self.transcript());
+        let prompt = templates.render(PREMISE, &context)?;
+        let (_, content) = ai_provider.generate(&prompt).await?;
+        let premise = Premise::parse(strip_code_fence(&content))?;
+        if premise == Premise::from_text(&premise.premise) {
+            return Err(StoryChainError::InvalidReasoningFormat(
+                "The premise was not written as YAML".to_string(),
+            ));
+        }
+        info!("Wrote the premise {:?}", premise.title.as_deref().unwrap_or("untitled"));
+        Ok(premise)
+    }
+}
+
+/// Writes a character arc for each character of a premise and saves them as
+/// `CharacterArc` artifacts with the ID `character_<name>`
+///
+/// # Arguments
+/// * `templates` - The prompt templates holding the character arc prompt
+/// * `ai_provider` - The AI provider writing the arcs
+/// * `premise` - The premise naming the characters
+/// * `artifact_manager` - The manager that will store the arcs
+///
+/// # Returns
+/// The IDs of the created or updated artifacts
+pub async fn generate_character_arcs(
+    templates: &PromptTemplates,
+    ai_provider: &dyn AIProvider,
+    premise: &Premise,
+    artifact_manager: &mut ArtifactManager,
+) -> Result<Vec<String>, StoryChainError> {
+    if premise.characters.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut context = Context::new();
+    context.insert("premise", &premise.to_prompt_section());
+    let prompt = templates.render(CHARACTER_ARCS, &context)?;
+    let (_, content) = ai_provider.generate(&prompt).await?;
+
+    let mut artifact_ids = Vec::new();
+    for block in split_blocks(&content, "CHARACTER") {
+        let fields = parse_labeled_fields(&block, &ARC_LABELS);
+        let Some(name) = fields.get("CHARACTER") else {
+            continue;
+        };
+        let mut metadata = HashMap::new();
+        metadata.insert("name".to_string(), name.clone());
+        for (label, key) in [("WANT", "want"), ("NEED", "need"), ("FLAW", "flaw"), ("ARC", "arc")] {
+            metadata.insert(key.to_string(), fields.get(label).cloned().unwrap_or_default());
+        }
+        metadata.insert("source".to_string(), "premise".to_string());
+
+        let artifact = Artifact {
+            id: format!("character_{}", slugify(name)),
+            content: format!(
+                "Name: {}\nWant: {}\nNeed: {}\nFlaw: {}\nArc: {}",
+                name, metadata["want"], metadata["need"], metadata["flaw"], metadata["arc"]
+            ),
+            artifact_type: ArtifactType::CharacterArc,
+            metadata,
+            tags: Vec::new(),
+        };
+        debug!("Saving character arc: {}", artifact.id);
+        artifact_ids.push(artifact.id.clone());
+        artifact_manager.update_artifact(artifact)?;
+    }
+
+    info!("Wrote {} character arcs", artifact_ids.len());
+    Ok(artifact_ids)
+}
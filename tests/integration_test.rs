@@ -1,5 +1,57 @@
-use storychain::{StoryChain, DeepseekProvider, AIProvider, StoryChainError};
+use storychain::{StoryChain, AIProvider, StoryChainError, GenerationConfig, ArtifactManager, ArtifactType, MarkdownOptions, CharacterRegistry, GlossaryEntry, PromptTemplate, PromptTemplates, prepend_system_prompt, TokenUsage, EPOCHS_COMPLETED_KEY, EPOCHS_KEY, SEED_DRAWS_KEY};
+use storychain::passes::SynopsisLength;
+use storychain::pov::{PovMode, PovSchedule};
+use storychain::formats::StoryFormat;
+use storychain::dialogue::{dialogue_ratio, extract_dialogue, DialogueTarget};
+use storychain::setups::SetupSource;
+use storychain::foreshadowing::ForeshadowingPlan;
+use storychain::subplots::Subplot;
+use storychain::strands::{Strand, StrandOrder};
+use storychain::tension::TensionCurve;
+use storychain::genres::GenrePreset;
+use storychain::consistency::{parse_contradictions, ConsistencyCheck, CONSISTENCY_ISSUES_KEY, CONSISTENCY_REVISED_KEY};
+use storychain::constraints::{Constraint, ConstraintSet, ContentRating};
+use storychain::chat::{ChatMessage, ChatModel, ChatProvider, ChatRole};
+use storychain::dataset::NodeRecord;
+use storychain::exports::ExportFormat;
+use storychain::progress::{scenes_left, EpochTimings, RunProgress};
+use storychain::characters::CharacterSheet;
+use storychain::lore::chunk_lore;
+use storychain::pacing::ArcStage;
+use storychain::project::Project;
+use storychain::usage::{Pricing, UsageTracker};
+use storychain::interaction_log::{read_interactions, InteractionLogger};
+use storychain::runner::StoryRunner;
+use storychain::hooks::PipelineHooks;
+use storychain::plugins::{ArtifactProcessor, ArtifactRegistry};
+use storychain::selection::{parse_ranking, CandidateSelection, SELECTION_NOTES_KEY, SELECTION_RANK_KEY};
+use storychain::trace::{TraceRecorder, AI_GENERATE_SPAN, PARSE_SPAN, PROMPT_BUILD_SPAN, REVISE_SPAN, SCENE_SPAN};
+use storychain::files::{uncompressed_path, write_atomic, Compression};
+use storychain::migrations::{migrate, schema_version, SCHEMA_VERSION};
+use storychain::storage::{open_store, ChainStore, JsonFileStore, MemoryStore};
+use storychain::filters::{redact, ContentFilter, ContentFilterSettings, FilterAction, FilterMatch, KeywordFilter, FILTER_MATCHES_KEY, FILTER_REJECTIONS_KEY};
+use storychain::style::{Person, StyleGuide, Tense, STYLE_ISSUES_KEY};
+use storychain::length::{word_count, LengthTarget, LENGTH_ADJUSTMENTS_KEY, WORD_COUNT_KEY};
+use storychain::enrichment::{EnrichmentMode, SceneMetadata, LOCATION_KEY, MOOD_KEY};
+use storychain::parsers::ResponseFormat;
+use storychain::embeddings::{cosine_similarity, EmbeddingProvider, SceneRecall};
+use storychain::premise::{load_premise, premise_name, resolve_premise_path, Premise, TargetLength, STDIN_PREMISE};
+use storychain::revision::{parse_evaluation, Draft, RevisionCriteria, REVISION_DRAFTS_KEY, REVISION_SCORE_KEY};
+use storychain::quality::QualityGate;
+use storychain::providers::{
+    clear_cache, is_transient, Backoff, CachingProvider, OllamaHttpProvider, RecordingProvider, ReplayProvider,
+    RetryingProvider, StreamingProvider, TimeoutProvider,
+};
+use storychain::cancellation::{CancellableProvider, CancellationToken};
+use storychain::config::{ProviderConfig, ProviderFactory, ProviderKind, StoryChainConfig};
+use storychain::tokenizer::{count_tokens, truncate_to_tokens, Keep};
+use storychain::memory::RollingSummary;
+use storychain::compare::{comparison_markdown, generate_comparison_run, interleaved_markdown};
+use storychain::translation::{language_name, LANGUAGE_KEY};
+use storychain::illustrations::{ImageProvider, IMAGE_KEY, IMAGE_PROMPT_KEY};
 use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 
 /// A mock AI provider for testing that returns predefined responses
 struct MockAIProvider;
@@ -37,6 +89,8 @@ async fn test_basic_story_generation() -> Result<(), StoryChainError> {
             &current_node,
             &ai_provider,
             Some("A story about a quiet neighborhood."),
+            1,
+            2,
         ).await?;
         
         if next_nodes.is_empty() {
@@ -72,4 +126,4135 @@ fn test_story_export() -> Result<(), StoryChainError> {
     std::fs::remove_file(test_output)?;
 
     Ok(())
-} 
\ No newline at end of file
+} 
+/// A provider that answers every prompt with the same fixed content
+struct FixedResponseProvider(&'static str);
+
+#[async_trait::async_trait]
+impl AIProvider for FixedResponseProvider {
+    async fn generate(&self, _prompt: &str) -> Result<(String, String), StoryChainError> {
+        Ok(("Fixed reasoning".to_string(), self.0.to_string()))
+    }
+}
+
+/// A provider that answers every prompt with the prompt itself
+struct EchoProvider;
+
+#[async_trait::async_trait]
+impl AIProvider for EchoProvider {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        Ok(("Echo reasoning".to_string(), prompt.to_string()))
+    }
+}
+
+#[tokio::test]
+async fn test_title_and_blurb_generation() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new(
+        "Test content".to_string(),
+        "Test reasoning".to_string(),
+    );
+
+    let provider = FixedResponseProvider(
+        "TITLE: \"The Quiet Street\"\n\
+        LOGLINE: A neighborhood keeps its secrets.\n\
+        BLURB: Nothing ever happens here.\n\
+        Until it does.",
+    );
+    chain.generate_title_and_blurb(&provider).await?;
+
+    assert_eq!(chain.metadata["title"], "The Quiet Street");
+    assert_eq!(chain.metadata["logline"], "A neighborhood keeps its secrets.");
+    assert_eq!(chain.metadata["blurb"], "Nothing ever happens here.\nUntil it does.");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_character_sheet_generation() -> Result<(), StoryChainError> {
+    let temp_dir = tempfile::tempdir()?;
+    let mut manager = ArtifactManager::new(temp_dir.path().to_str().unwrap());
+    let chain = StoryChain::new(
+        "Elara walked home.".to_string(),
+        "Test reasoning".to_string(),
+    );
+
+    let provider = FixedResponseProvider(
+        "CHARACTER: Elara Voss\n\
+        APPEARANCE: Tall, with a grey coat\n\
+        ARC: Learns to trust her neighbors\n\
+        KEY SCENES: root, node_99",
+    );
+    let ids = chain.generate_character_sheets(&provider, &mut manager).await?;
+
+    assert_eq!(ids, vec!["character_elara_voss".to_string()]);
+    let sheet = manager.get_artifact("character_elara_voss").unwrap();
+    assert_eq!(sheet.artifact_type, ArtifactType::CharacterArc);
+    assert_eq!(sheet.metadata["key_scenes"], "root");
+    assert!(temp_dir.path().join("character_elara_voss.json").exists());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_synopsis_generation() -> Result<(), StoryChainError> {
+    let chain = StoryChain::new(
+        "Test content".to_string(),
+        "Test reasoning".to_string(),
+    );
+
+    // The synopsis is stored under the name of the story it was given
+    let provider = FixedResponseProvider("  A quiet street hides a loud secret.  ");
+    let artifact = chain.generate_synopsis(&provider, SynopsisLength::Paragraph, "street").await?;
+
+    assert_eq!(artifact.id, "synopsis_street_paragraph");
+    assert_eq!(artifact.artifact_type, ArtifactType::Synopsis);
+    assert_eq!(artifact.content, "A quiet street hides a loud secret.");
+
+    // The closing scenes are shown in full, earlier ones as excerpts
+    let mut chain = StoryChain::new("x".repeat(1000), "Reasoning".to_string());
+    let mut current = "root".to_string();
+    for epoch in 1..=3 {
+        current = chain.generate_next_nodes(&current, &FixedResponseProvider("scene"), None, epoch, 3).await?[0].clone();
+    }
+    let ending = "y".repeat(900) + " and they all lived happily ever after.";
+    chain.nodes.get_mut(&current).unwrap().content = ending.clone();
+    let artifact = chain.generate_synopsis(&EchoProvider, SynopsisLength::Page, "street").await?;
+    assert!(artifact.content.contains(&ending));
+    assert!(!artifact.content.contains(&"x".repeat(601)));
+
+    // With memory on, the running summary stands in for the scenes it covers
+    chain.settings.memory = Some(Default::default());
+    chain.nodes.get_mut("node_1").unwrap().metadata.insert("summary".to_string(), "A vault is robbed.".to_string());
+    let artifact = chain.generate_synopsis(&EchoProvider, SynopsisLength::Page, "street").await?;
+    assert!(artifact.content.contains("Summary of the Story:\nA vault is robbed."));
+    assert!(!artifact.content.contains("xxxx"));
+    assert!(artifact.content.contains(&ending));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_chapter_summaries() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new(
+        "Test content".to_string(),
+        "Test reasoning".to_string(),
+    );
+    let ai_provider = MockAIProvider;
+    let mut current_node = "root".to_string();
+    for epoch in 1..=3 {
+        current_node = chain.generate_next_nodes(&current_node, &ai_provider, None, epoch, 3).await?[0].clone();
+    }
+
+    chain.group_into_chapters(3);
+    assert_eq!(chain.chapters.len(), 2);
+    assert_eq!(chain.chapters[1].node_ids, vec![current_node.clone()]);
+
+    chain.generate_chapter_summaries(&FixedResponseProvider("Things happened.")).await?;
+    assert_eq!(chain.chapters[0].metadata["summary"], "Things happened.");
+
+    let test_output = "test_chapter_recaps.md";
+    let options = MarkdownOptions { chapter_recaps: true, ..MarkdownOptions::default() };
+    chain.export_to_markdown_with_options(test_output, &options)?;
+    let markdown = std::fs::read_to_string(test_output)?;
+    std::fs::remove_file(test_output)?;
+    assert_eq!(markdown.matches("> *Previously:* Things happened.").count(), 1);
+
+    // The HTML export renders the recap under the same option
+    let html_output = "test_chapter_recaps.html";
+    chain.export_to_html_with_options(html_output, &options)?;
+    let html = std::fs::read_to_string(html_output)?;
+    chain.export_to_html(html_output)?;
+    let plain_html = std::fs::read_to_string(html_output)?;
+    std::fs::remove_file(html_output)?;
+    assert_eq!(html.matches("<h2>Chapter 2</h2>\n<p class=\"recap\"><em>Previously:</em> Things happened.</p>").count(), 1);
+    assert!(!plain_html.contains("Previously:"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_continuity_packet_extraction() -> Result<(), StoryChainError> {
+    let chain = StoryChain::new(
+        "Test content".to_string(),
+        "Test reasoning".to_string(),
+    );
+
+    let provider = FixedResponseProvider(
+        "CHARACTER STATES:\n\
+        - Elara: moved to the city\n\
+        UNRESOLVED THREADS:\n\
+        - Who sent the letter?\n\
+        - The locked basement\n\
+        WORLD FACTS:\n\
+        - The street has no name",
+    );
+    let packet = chain.extract_continuity_packet(&provider).await?;
+
+    assert_eq!(packet.character_states, vec!["Elara: moved to the city"]);
+    assert_eq!(packet.unresolved_threads.len(), 2);
+    assert!(packet.to_prompt_section().contains("- The street has no name"));
+
+    Ok(())
+}
+
+#[test]
+fn test_character_registry_consistency() {
+    let mut registry = CharacterRegistry::from_premise(
+        "characters:\n  - name: \"Elara 'Ella' Voss\"\n  - name: \"Rico Sinistra\"",
+    );
+    assert_eq!(registry.lookup("Ella").unwrap().name, "Elara Voss");
+
+    registry.auto_correct = true;
+    let (content, issues) = registry.review_scene(
+        "node_1",
+        "Later that night, Elera met Rico and a stranger called Tobias.".to_string(),
+    );
+
+    assert_eq!(issues.len(), 2);
+    assert!(issues.iter().any(|i| i.to_string().contains("'Elera' looks like a misspelling of 'Elara'")));
+    assert_eq!(content, "Later that night, Elara met Rico and a stranger called Tobias.");
+    assert_eq!(registry.lookup("Tobias").unwrap().introduced_in.as_deref(), Some("node_1"));
+}
+
+#[tokio::test]
+async fn test_glossary_generation() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new(
+        "They met at the Hollow Market.".to_string(),
+        "Test reasoning".to_string(),
+    );
+
+    let provider = FixedResponseProvider(
+        "TERM: Hollow Market\n\
+        CATEGORY: Place\n\
+        DEFINITION: A night bazaar.\n\
+        TERM: Aether\n\
+        CATEGORY: Concept\n\
+        DEFINITION: Ambient magic.",
+    );
+    chain.glossary = chain.generate_glossary(&provider).await?;
+
+    assert_eq!(chain.glossary.len(), 2);
+    assert_eq!(chain.glossary[0].term, "Aether");
+    assert_eq!(chain.glossary[0].first_node_id, None);
+    assert_eq!(chain.glossary[1].first_node_id.as_deref(), Some("root"));
+    assert!(chain.glossary_markdown().contains("[Scene 1](#scene-1)"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tag_generation() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new(
+        "Test content".to_string(),
+        "Test reasoning".to_string(),
+    );
+
+    let provider = FixedResponseProvider(
+        "GENRES: Crime, Dark Comedy\n\
+        CONTENT WARNINGS: None\n\
+        KEYWORDS: soho, heist, art",
+    );
+    chain.generate_tags(&provider).await?;
+
+    assert_eq!(chain.metadata["genre_tags"], "crime, dark comedy");
+    assert_eq!(chain.metadata["content_warnings"], "");
+    assert_eq!(chain.metadata["keywords"], "soho, heist, art");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_illustration_briefs() -> Result<(), StoryChainError> {
+    let chain = StoryChain::new(
+        "Test content".to_string(),
+        "Test reasoning".to_string(),
+    );
+
+    let provider = FixedResponseProvider(
+        "PROMPT: A rain-soaked street at dusk, oil painting\n\
+        NEGATIVE PROMPT: text, watermark\n\
+        BRIEF: Convey quiet dread.",
+    );
+    let briefs = chain.generate_illustration_briefs(&provider).await?;
+
+    assert_eq!(briefs.len(), 2);
+    assert_eq!(briefs[0].id, "cover_prompt");
+    assert_eq!(briefs[1].metadata["node_ids"], "root");
+    assert_eq!(briefs[1].content, "A rain-soaked street at dusk, oil painting");
+    assert_eq!(briefs[1].metadata["negative_prompt"], "text, watermark");
+
+    Ok(())
+}
+
+#[test]
+fn test_reading_time_and_pacing_stats() {
+    let mut chain = StoryChain::new(
+        "one two three four five six seven eight nine ten".to_string(),
+        "Test reasoning".to_string(),
+    );
+    chain.nodes.get_mut("root").unwrap().metadata.insert("tension".to_string(), "7.5".to_string());
+
+    let stats = chain.stats(5);
+    assert_eq!(stats.total_words, 10);
+    assert_eq!(stats.total_reading_minutes, 2.0);
+    assert_eq!(stats.scenes[0].tension, 7.5);
+    assert!(stats.to_markdown().contains("| 1 | 10 | 2.0 | 7.5 | intense |"));
+}
+
+#[test]
+fn test_interactive_html_export() -> Result<(), StoryChainError> {
+    let chain = StoryChain::new(
+        "A <quiet> street.\n\nThen </script> nothing.".to_string(),
+        "Test reasoning".to_string(),
+    );
+
+    let test_output = "test_interactive.html";
+    chain.export_to_interactive_html(test_output)?;
+    let html = std::fs::read_to_string(test_output)?;
+    std::fs::remove_file(test_output)?;
+
+    assert!(html.contains("&lt;quiet&gt;"));
+    assert!(!html.contains("</script> nothing"));
+    assert!(html.contains("show(\"root\");"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_html_export() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("A <quiet> street.".to_string(), "Open <calmly>".to_string()).with_branch_ratio(2);
+    chain.metadata.insert("title".to_string(), "Night & Day".to_string());
+    let branches = chain.generate_next_nodes("root", &FixedResponseProvider("The door opens."), None, 1, 1).await?;
+
+    let test_output = "test_story.html";
+    chain.export_to_html(test_output)?;
+    let html = std::fs::read_to_string(test_output)?;
+    std::fs::remove_file(test_output)?;
+
+    assert!(html.contains("<title>Night &amp; Day</title>"));
+    assert!(html.contains("&lt;quiet&gt;"));
+
+    // Contents, collapsible reasoning, and links to both branches
+    assert!(html.contains("<h2>Contents</h2>"));
+    assert!(html.contains("<li><a href=\"#root\">Scene 1</a></li>"));
+    assert!(html.contains("<summary>AI's Reasoning</summary>\n<p>Open &lt;calmly&gt;</p>"));
+    assert!(html.contains(&format!("<a href=\"#{}\">Scene 2</a>: The door opens.", branches[0])));
+    assert!(html.contains(&format!("<a href=\"#{}\">Branch 1, scene 1</a>: The door opens.", branches[1])));
+    assert!(html.contains("<h3 id=\"branch-1\">Branch 1 (after Scene 1)</h3>"));
+    assert!(html.contains(&format!("<section class=\"scene\" id=\"{}\">", branches[1])));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_html_export_with_branch_in_reading_order() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("A quiet street.".to_string(), "Open calmly".to_string()).with_branch_ratio(2);
+    let provider = FixedResponseProvider("The door opens.");
+    let first = chain.generate_next_nodes("root", &provider, None, 1, 1).await?;
+    let second = chain.generate_next_nodes(&first[1], &provider, None, 2, 1).await?;
+    let third = chain.generate_next_nodes(&second[0], &provider, None, 3, 1).await?;
+
+    // A branch scene read early, without the branch scene that leads to it
+    chain.set_reading_order(vec!["root".to_string(), second[0].clone()])?;
+    let test_output = "test_story_reading_order.html";
+    chain.export_to_html(test_output)?;
+    let html = std::fs::read_to_string(test_output)?;
+    std::fs::remove_file(test_output)?;
+
+    assert!(html.contains(&format!("<a href=\"#{}\">Scene 2</a>", second[0])));
+    assert!(html.contains("<h3 id=\"branch-1\">Branch 1 (after Scene 1)</h3>"));
+    assert!(html.contains("<h3 id=\"branch-1-3\">Branch 1, continued (after Scene 2)</h3>"));
+    for id in &third {
+        assert!(html.contains(&format!("<section class=\"scene\" id=\"{}\">", id)));
+    }
+    Ok(())
+}
+
+#[cfg(feature = "pdf")]
+#[tokio::test]
+async fn test_pdf_export() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("It was a dark night.\n\nThe rain fell.".to_string(), "Reasoning".to_string());
+    chain.metadata.insert("title".to_string(), "The Long Night".to_string());
+    let mut current = "root".to_string();
+    for epoch in 1..=3 {
+        current = chain.generate_next_nodes(&current, &MockAIProvider, None, epoch, 3).await?[0].clone();
+        chain.nodes.get_mut(&current).unwrap().content = "The storm went on and the town waited for morning. ".repeat(60);
+    }
+    chain.group_into_chapters(2);
+
+    let test_output = "test_story.pdf";
+    chain.export_to_pdf(test_output)?;
+    let pdf = std::fs::read(test_output)?;
+    std::fs::remove_file(test_output)?;
+    let text = String::from_utf8_lossy(&pdf);
+
+    assert!(pdf.starts_with(b"%PDF-"));
+    assert!(text.contains("/Times-Roman"));
+    assert!(text.contains("(The Long Night)"));
+    assert!(text.contains("(It was a dark night.)"));
+
+    // A title page followed by two chapters, each starting on a new page, with numbered pages
+    let pages = text.matches("/Type /Page\n").count() + text.matches("/Type /Page ").count();
+    assert!(pages >= 3, "expected at least 3 pages, found {}", pages);
+    assert!(text.contains(&format!("({})", chain.chapters[1].title)));
+    assert!(text.contains("(2) Tj"));
+    Ok(())
+}
+
+#[cfg(feature = "docx")]
+#[tokio::test]
+async fn test_docx_export() -> Result<(), StoryChainError> {
+    use std::io::Read;
+
+    let mut chain = StoryChain::new("Salt & pepper.\n\nA second paragraph.".to_string(), "Open <quietly>.".to_string());
+    chain.metadata.insert("title".to_string(), "The Kitchen".to_string());
+    chain.generate_next_nodes("root", &FixedResponseProvider("Dinner is served."), None, 1, 1).await?;
+    chain.group_into_chapters(1);
+
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("story.docx");
+    chain.export_to_docx(path.to_str().unwrap())?;
+
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(&path)?).unwrap();
+    let mut read_part = |name: &str| {
+        let mut part = String::new();
+        archive.by_name(name).unwrap().read_to_string(&mut part).unwrap();
+        part
+    };
+    assert!(read_part("[Content_Types].xml").contains("/word/comments.xml"));
+    let document = read_part("word/document.xml");
+    assert!(document.contains("<w:pStyle w:val=\"Title\"/></w:pPr><w:r><w:t xml:space=\"preserve\">The Kitchen</w:t>"));
+    assert!(document.contains("<w:pStyle w:val=\"Heading1\"/></w:pPr><w:r><w:t xml:space=\"preserve\">Chapter 2</w:t>"));
+    assert!(document.contains("Salt &amp; pepper.</w:t></w:r></w:p><w:p><w:pPr><w:pStyle w:val=\"Normal\"/></w:pPr><w:r><w:t xml:space=\"preserve\">A second paragraph."));
+    assert_eq!(document.matches("<w:commentReference").count(), 2);
+    let comments = read_part("word/comments.xml");
+    assert!(comments.contains("w:id=\"0\" w:author=\"StoryChain\""));
+    assert!(comments.contains("Open &lt;quietly&gt;."));
+    assert!(comments.contains("Fixed reasoning"));
+    Ok(())
+}
+
+#[cfg(feature = "epub")]
+#[tokio::test]
+async fn test_epub_export() -> Result<(), StoryChainError> {
+    use std::io::Read;
+
+    let mut chain = StoryChain::new("Salt & pepper.\nA wrapped line.".to_string(), "Reasoning".to_string());
+    for (key, value) in [
+        ("title", "The Kitchen"),
+        ("logline", "A cook faces the dinner rush."),
+        ("blurb", "One night, one kitchen."),
+        ("genre_tags", "Drama, Slice of life"),
+        ("content_warnings", "Knives"),
+        ("keywords", "cooking, drama, restaurants"),
+        ("language", "French"),
+    ] {
+        chain.metadata.insert(key.to_string(), value.to_string());
+    }
+    let mut current = "root".to_string();
+    for epoch in 1..=2 {
+        current = chain.generate_next_nodes(&current, &FixedResponseProvider("Dinner is served."), None, epoch, 2).await?[0].clone();
+    }
+    chain.group_into_chapters(2);
+
+    // Illustrations are packed into the book; missing images are left out
+    let dir = tempfile::tempdir()?;
+    let image = dir.path().join("root.png");
+    std::fs::write(&image, b"\x89PNG\r\n\x1a\n")?;
+    let root = chain.nodes.get_mut("root").unwrap();
+    root.metadata.insert(IMAGE_KEY.to_string(), image.to_string_lossy().to_string());
+    root.metadata.insert(IMAGE_PROMPT_KEY.to_string(), "A busy <kitchen>".to_string());
+    let missing = dir.path().join("missing.png").to_string_lossy().to_string();
+    chain.nodes.get_mut(&current).unwrap().metadata.insert(IMAGE_KEY.to_string(), missing);
+
+    let path = dir.path().join("story.epub");
+    chain.export_as(ExportFormat::Epub, path.to_str().unwrap())?;
+
+    // The media type is stored first and uncompressed
+    let bytes = std::fs::read(&path)?;
+    assert_eq!(&bytes[30..38], b"mimetype");
+    assert_eq!(&bytes[38..58], b"application/epub+zip");
+
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(&path)?).unwrap();
+    let mut read_part = |name: &str| {
+        let mut part = String::new();
+        archive.by_name(name).unwrap().read_to_string(&mut part).unwrap();
+        part
+    };
+    assert!(read_part("META-INF/container.xml").contains("full-path=\"OEBPS/content.opf\""));
+
+    // The title, blurb, tags, and keywords are the book's metadata
+    let package = read_part("OEBPS/content.opf");
+    assert!(package.contains("<dc:title>The Kitchen</dc:title>"));
+    assert!(package.contains("<dc:language>fr</dc:language>"));
+    assert!(package.contains("<dc:description>One night, one kitchen.</dc:description>"));
+    assert_eq!(package.matches("<dc:subject>").count(), 4);
+    assert!(package.contains("<dc:subject>restaurants</dc:subject>"));
+    assert!(package.contains("<itemref idref=\"title\"/>\n<itemref idref=\"section_1\"/>\n<itemref idref=\"section_2\"/>\n</spine>"));
+
+    let title_page = read_part("OEBPS/title.xhtml");
+    assert!(title_page.contains("<p class=\"logline\">A cook faces the dinner rush.</p>"));
+    assert!(title_page.contains("<strong>Content warnings:</strong> Knives"));
+    assert!(read_part("OEBPS/nav.xhtml").contains("<li><a href=\"section_2.xhtml\">Chapter 2</a></li>"));
+    let chapter = read_part("OEBPS/section_1.xhtml");
+    assert!(chapter.contains("<h2>Chapter 1</h2>\n<section id=\"root\">\n<h3>Scene 1</h3>\n<figure"));
+    assert!(chapter.contains(
+        "<figure class=\"illustration\"><img src=\"images/root.png\" alt=\"A busy &lt;kitchen&gt;\"/></figure>\n\
+        <p>Salt &amp; pepper.<br/>A wrapped line.</p>"
+    ));
+    assert!(chapter.contains("<h3>Scene 2</h3>"));
+    assert!(!read_part("OEBPS/section_2.xhtml").contains("Previously:"));
+    assert!(package.contains("<item id=\"image_1\" href=\"images/root.png\" media-type=\"image/png\"/>"));
+    assert!(!package.contains("image_2"));
+    let mut packed = Vec::new();
+    archive.by_name("OEBPS/images/root.png").unwrap().read_to_end(&mut packed)?;
+    assert_eq!(packed, std::fs::read(&image)?);
+
+    // Chapter recaps open every chapter after the first when asked for
+    chain.chapters[0].metadata.insert("summary".to_string(), "The <first> course.".to_string());
+    let options = MarkdownOptions { chapter_recaps: true, ..MarkdownOptions::default() };
+    chain.export_to_epub_with_options(path.to_str().unwrap(), &options)?;
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(&path)?).unwrap();
+    let mut read_part = |name: &str| {
+        let mut part = String::new();
+        archive.by_name(name).unwrap().read_to_string(&mut part).unwrap();
+        part
+    };
+    assert!(!read_part("OEBPS/section_1.xhtml").contains("Previously:"));
+    assert!(read_part("OEBPS/section_2.xhtml")
+        .contains("<h2>Chapter 2</h2>\n<p class=\"recap\"><em>Previously:</em> The &lt;first&gt; course.</p>\n<section"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_text_export() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("  The first scene.  ".to_string(), "Reasoning".to_string()).with_branch_ratio(2);
+    chain.metadata.insert("title".to_string(), "Plain Tale".to_string());
+    let branches = chain.generate_next_nodes("root", &FixedResponseProvider("The **next** scene."), None, 1, 1).await?;
+
+    // Prose only: title, scenes, and a scene break, without reasoning or headers
+    let text = chain.to_plain_text();
+    assert_eq!(text, "Plain Tale\n\n\nThe first scene.\n\n* * *\n\nThe **next** scene.\n");
+
+    // Chapter titles replace the scene break at the start of a chapter
+    chain.group_into_chapters(1);
+    let text = chain.to_plain_text();
+    assert!(text.contains(&format!("{}\n\nThe first scene.\n\n\n{}\n\n", chain.chapters[0].title, chain.chapters[1].title)));
+    assert!(!text.contains("* * *"));
+
+    // One file per node, including the alternative branch
+    let temp_dir = tempfile::tempdir()?;
+    let dir = temp_dir.path().join("scenes");
+    let files = chain.export_scenes_to_dir(dir.to_str().unwrap())?;
+    let names: Vec<String> = files.iter().map(|f| f.file_name().unwrap().to_string_lossy().to_string()).collect();
+    assert_eq!(
+        names,
+        vec!["scene_001_root.txt".to_string(), format!("scene_002_{}.txt", branches[0]), format!("scene_003_{}.txt", branches[1])]
+    );
+    assert_eq!(std::fs::read_to_string(&files[0])?, "The first scene.\n");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_graph_export() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("The \"opening\" scene of a story that runs past the label limit.".to_string(), "R".to_string())
+        .with_branch_ratio(2);
+    let branches = chain.generate_next_nodes("root", &FixedResponseProvider("Next."), None, 1, 1).await?;
+    chain.nodes.get_mut("root").unwrap().metadata.insert("pov".to_string(), "Mara".to_string());
+    chain.nodes.get_mut(&branches[1]).unwrap().metadata.insert("pov".to_string(), "Tomas".to_string());
+
+    let dot = chain.to_dot("pov");
+    assert!(dot.starts_with("digraph story {"));
+    assert!(dot.contains("\"root\" [label=\"root\\nThe \\\"opening\\\" scene of a story that runs...\", fillcolor=\"#8dd3c7\"];"));
+    assert!(dot.contains(&format!("\"{}\" [label=\"{}\\nNext.\"];", branches[0], branches[0])));
+    assert!(dot.contains(&format!("\"root\" -> \"{}\";", branches[0])));
+    assert!(dot.contains(&format!("\"root\" -> \"{}\" [style=dashed];", branches[1])));
+    assert!(dot.contains("\"legend_1\" [label=\"Tomas\", fillcolor=\"#ffffb3\"];"));
+
+    let mermaid = chain.to_mermaid("pov");
+    assert!(mermaid.starts_with("flowchart TD\n"));
+    assert!(mermaid.contains("root[\"root<br>The #quot;opening#quot; scene"));
+    assert!(mermaid.contains(&format!("root --> {}", branches[0])));
+    assert!(mermaid.contains(&format!("root -.-> {}", branches[1])));
+    assert!(mermaid.contains("classDef pov_0 fill:#8dd3c7\n    class root pov_0"));
+    assert!(mermaid.contains(&format!("class {} pov_1", branches[1])));
+
+    // Without the metadata key nothing is colored
+    assert!(!chain.to_dot("mood").contains("fillcolor=\"#8dd3c7\""));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_twee_export() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("The road forks.\n:: not a header".to_string(), "R".to_string()).with_branch_ratio(2);
+    chain.metadata.insert("title".to_string(), "Forked Road".to_string());
+    let branches = chain.generate_next_nodes("root", &FixedResponseProvider("You walk on. The end."), None, 1, 2).await?;
+    chain.branch_ratio = 1;
+    let after = chain.generate_next_nodes(&branches[0], &FixedResponseProvider("Home at last."), None, 2, 2).await?;
+
+    // The AI writes choice text for branches that have none
+    chain.nodes.get_mut(&branches[1]).unwrap().metadata.insert("choice".to_string(), "Take the [river] path".to_string());
+    let labeled = chain
+        .generate_choice_labels(&FixedResponseProvider("<think>R</think>\nCHOICE 1: \"Climb the hill\"\nCHOICE 2: Ignored"))
+        .await?;
+    assert_eq!(labeled, 1);
+    assert_eq!(chain.nodes[&branches[0]].metadata["choice"], "Climb the hill");
+    assert_eq!(chain.nodes[&branches[1]].metadata["choice"], "Take the [river] path");
+
+    let twee = chain.to_twee();
+    assert!(twee.starts_with(":: StoryTitle\nForked Road\n\n:: StoryData\n"));
+    assert!(twee.contains("\"start\": \"root\""));
+    assert!(twee.contains(&format!("\"ifid\": \"{}\"", chain.ifid())));
+    assert_eq!(chain.ifid().len(), 36);
+    assert_eq!(&chain.ifid()[14..15], "4");
+    assert!(twee.contains(":: root {\"position\":\"100,100\"}\nThe road forks.\n\\:: not a header\n"));
+    assert!(twee.contains(&format!("[[Climb the hill->{}]]\n[[Take the (river) path->{}]]", branches[0], branches[1])));
+    assert!(twee.contains(&format!(":: {} {{\"position\":\"300,250\"}}\nYou walk on. The end.\n\n", branches[1])));
+    assert!(twee.contains(&format!("[[Continue->{}]]", after[0])));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ink_export() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("The road forks. {Braces} -> and # tags\n\n* Not a choice".to_string(), "R".to_string())
+        .with_branch_ratio(2);
+    chain.metadata.insert("title".to_string(), "Forked Road".to_string());
+    let branches = chain.generate_next_nodes("root", &FixedResponseProvider("You walk on."), None, 1, 2).await?;
+    chain.nodes.get_mut(&branches[1]).unwrap().metadata.insert("choice".to_string(), "Take the river path".to_string());
+    chain.nodes.get_mut(&branches[1]).unwrap().metadata.insert("pov".to_string(), "Mara".to_string());
+    chain.branch_ratio = 1;
+    let after = chain.generate_next_nodes(&branches[0], &FixedResponseProvider("Home at last."), None, 2, 2).await?;
+
+    let ink = chain.to_ink();
+    assert!(ink.starts_with("# title: Forked Road\n\n-> root\n"));
+    assert!(ink.contains("=== root ===\nThe road forks. \\{Braces\\} \\-> and \\# tags\n\n\\* Not a choice\n"));
+    assert!(ink.contains(&format!("* [You walk on.] -> {}\n* [Take the river path] -> {}\n", branches[0], branches[1])));
+    assert!(ink.contains(&format!("=== {} ===\nYou walk on.\n-> {}\n", branches[0], after[0])));
+    assert!(ink.contains(&format!("=== {} ===\n# pov: Mara\nYou walk on.\n-> END\n", branches[1])));
+    assert!(ink.contains(&format!("=== {} ===\nHome at last.\n-> END\n", after[0])));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_fountain_export() -> Result<(), StoryChainError> {
+    let opening = "The kitchen was dark.\n\n\"Who's there?\" Mara asked, reaching for the lamp.\n\n\"Only me,\" said Tom. He stepped into the light.\n\nINT. was written on the door.";
+    let mut chain = StoryChain::new(opening.to_string(), "R".to_string());
+    chain.metadata.insert("title".to_string(), "Night Shift".to_string());
+    chain.nodes.get_mut("root").unwrap().metadata.insert("location".to_string(), "Int. kitchen - night".to_string());
+    chain.character_registry.register("Thomas Reed", None);
+    chain.character_registry.characters[0].aliases.push("Tom".to_string());
+    chain.generate_next_nodes("root", &FixedResponseProvider("The road was empty."), None, 1, 1).await?;
+
+    let fountain = chain.to_fountain();
+    assert!(fountain.starts_with("Title: Night Shift\n\n===\n\nINT. KITCHEN - NIGHT\n\nThe kitchen was dark.\n\n"));
+    assert!(fountain.contains("MARA\n(reaching for the lamp)\nWho's there?\n\n"));
+    assert!(fountain.contains("THOMAS REED\nOnly me,\n\nHe stepped into the light.\n\n"));
+    assert!(fountain.contains("!INT. was written on the door.\n\n.SCENE 2\n\nThe road was empty.\n"));
+
+    // The AI's adaptation replaces the heuristics
+    let adapted = chain.adapt_to_screenplay(&FixedResponseProvider("```fountain\nEXT. ROAD - DAY\n\nNothing moves.\n```")).await?;
+    assert_eq!(adapted, 2);
+    assert!(chain.to_fountain().ends_with("===\n\nEXT. ROAD - DAY\n\nNothing moves.\n\nEXT. ROAD - DAY\n\nNothing moves.\n"));
+    assert_eq!(chain.adapt_to_screenplay(&FixedResponseProvider("unused")).await?, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_jsonl_export() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("A lighthouse keeper finds a message.".to_string(), "Open on the mystery.".to_string())
+        .with_branch_ratio(2);
+    chain.metadata.insert(EPOCHS_KEY.to_string(), "4".to_string());
+    let branches = chain.generate_next_nodes("root", &FixedResponseProvider("She reads it twice."), None, 1, 4).await?;
+
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("story.jsonl");
+    assert_eq!(chain.export_to_jsonl(path.to_str().unwrap(), Some("A keeper alone on an island."))?, 3);
+    let records: Vec<NodeRecord> = std::fs::read_to_string(&path)?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+
+    assert_eq!(records[0].id, "root");
+    assert_eq!(records[0].depth, 0);
+    assert!(records[0].prompt.contains("A keeper alone on an island."));
+    assert_eq!(records[0].completion, "<think>\nOpen on the mystery.\n</think>\nA lighthouse keeper finds a message.");
+
+    // Continuations are prompted with the scene they continue
+    assert_eq!(records[1].id, branches[0]);
+    assert!(records[1].main_storyline);
+    assert!(!records[2].main_storyline);
+    assert_eq!((records[1].depth, records[1].predecessors.clone()), (1, vec!["root".to_string()]));
+    assert!(records[1].prompt.contains("A lighthouse keeper finds a message."));
+    assert!(records[1].prompt.contains("A keeper alone on an island."));
+    assert_eq!(records[1].content, "She reads it twice.");
+    assert_eq!(records[1].reasoning, "Fixed reasoning");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_import_from_markdown() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("The door creaks open.\n\nWind follows her in.".to_string(), "Open with tension.".to_string())
+        .with_branch_ratio(2);
+    chain.metadata.insert("title".to_string(), "The Visitor".to_string());
+    chain.metadata.insert("logline".to_string(), "A stranger arrives at night.".to_string());
+    chain.metadata.insert("blurb".to_string(), "Nobody expected company.".to_string());
+    chain.nodes.get_mut("root").unwrap().metadata.insert("pov".to_string(), "Mara".to_string());
+    let branches = chain.generate_next_nodes("root", &FixedResponseProvider("She lights a candle."), None, 1, 2).await?;
+    chain.branch_ratio = 1;
+    chain.generate_next_nodes(&branches[0], &FixedResponseProvider("The stranger speaks."), None, 2, 2).await?;
+    chain.generate_next_nodes(&branches[1], &FixedResponseProvider("She hides."), None, 2, 2).await?;
+
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("story.md");
+    chain.export_to_markdown(path.to_str().unwrap())?;
+
+    // Edit a scene by hand before re-importing
+    let edited = std::fs::read_to_string(&path)?.replacen("She lights a candle.", "She lights two candles.", 1);
+    std::fs::write(&path, edited)?;
+    let imported = StoryChain::import_from_markdown(path.to_str().unwrap())?;
+
+    assert_eq!(imported.metadata["title"], "The Visitor");
+    assert_eq!(imported.metadata["logline"], "A stranger arrives at night.");
+    assert_eq!(imported.metadata["blurb"], "Nobody expected company.");
+    assert_eq!(imported.nodes.len(), chain.nodes.len());
+    let root = &imported.nodes["root"];
+    assert_eq!(root.content, "The door creaks open.\n\nWind follows her in.");
+    assert_eq!(root.reasoning, "Open with tension.");
+    assert_eq!(root.metadata["pov"], "Mara");
+
+    let main: Vec<&str> = imported.storylines()[0].iter().map(|n| n.content.as_str()).collect();
+    assert_eq!(main[1..], ["She lights two candles.", "The stranger speaks."]);
+    assert_eq!(imported.nodes[&imported.storylines()[0][1].id].reasoning, "Fixed reasoning");
+
+    // The alternative branch keeps its IDs and leaves from the opening scene
+    assert_eq!(imported.nodes[&branches[1]].content, "She lights a candle.");
+    assert_eq!(imported.nodes[&branches[1]].predecessors, vec!["root".to_string()]);
+    assert_eq!(imported.nodes["root"].successors.len(), 2);
+    assert_eq!(imported.storylines().len(), 2);
+
+    std::fs::write(&path, "# Empty\n\n---\n\n")?;
+    assert!(StoryChain::import_from_markdown(path.to_str().unwrap()).is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_acts_and_chapters() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("Scene one.".to_string(), "R".to_string());
+    let mut current = "root".to_string();
+    for epoch in 1..=4 {
+        current = chain.generate_next_nodes(&current, &FixedResponseProvider("Another scene."), None, epoch, 4).await?[0].clone();
+    }
+    chain.group_into_chapters(1);
+    chain.group_into_acts(2);
+    let acts: Vec<Option<&str>> = chain.chapters.iter().map(|c| c.act.as_deref()).collect();
+    assert_eq!(acts, [Some("Act I"), Some("Act I"), Some("Act I"), Some("Act II"), Some("Act II")]);
+
+    // Nodes can be moved between chapters, which keep reading order
+    chain.assign_to_chapter("root", "Chapter 2")?;
+    assert_eq!(chain.chapters.len(), 4);
+    assert_eq!(chain.chapters[0].title, "Chapter 2");
+    assert_eq!(chain.chapters[0].node_ids[0], "root");
+    chain.assign_to_chapter(&current, "Epilogue")?;
+    assert_eq!(chain.chapters.last().unwrap().title, "Epilogue");
+    assert_eq!(chain.chapters.last().unwrap().act.as_deref(), Some("Act II"));
+    assert!(chain.assign_to_chapter("missing", "Chapter 1").is_err());
+    assert_eq!(chain.chapter_starting_at("root").map(|(i, _, act)| (i, act)), Some((0, Some("Act I"))));
+
+    // Acts and chapters head the markdown export and survive re-import
+    chain.chapters[0].metadata.insert("summary".to_string(), "It began.".to_string());
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("story.md");
+    let options = MarkdownOptions { chapter_recaps: true, ..MarkdownOptions::default() };
+    chain.export_to_markdown_with_options(path.to_str().unwrap(), &options)?;
+    let markdown = std::fs::read_to_string(&path)?;
+    assert!(markdown.contains("---\n\n## Act I\n\n### Chapter 2\n\n#### Scene 1\n\nScene one."));
+    assert_eq!(markdown.matches("\n## Act II\n").count(), 1);
+
+    let imported = StoryChain::import_from_markdown(path.to_str().unwrap())?;
+    assert_eq!(imported.nodes["root"].content, "Scene one.");
+    let structure: Vec<(&str, Option<&str>, usize)> =
+        imported.chapters.iter().map(|c| (c.title.as_str(), c.act.as_deref(), c.node_ids.len())).collect();
+    let expected: Vec<(&str, Option<&str>, usize)> =
+        chain.chapters.iter().map(|c| (c.title.as_str(), c.act.as_deref(), c.node_ids.len())).collect();
+    assert_eq!(structure, expected);
+    assert_eq!(imported.chapters[0].metadata["summary"], "It began.");
+    assert!(!imported.metadata.contains_key("title"));
+
+    // The HTML export nests its headings the same way
+    let html_path = dir.path().join("story.html");
+    chain.export_to_html(html_path.to_str().unwrap())?;
+    let html = std::fs::read_to_string(&html_path)?;
+    assert!(html.contains("<h2 class=\"act\">Act I</h2>\n<h3>Chapter 2</h3>\n<h4>Scene 1</h4>"));
+
+    // Exports written before the headings nested still import
+    let legacy = dir.path().join("legacy.md");
+    std::fs::write(&legacy, "# Story\n\n---\n\n# Act I\n\n## Opening\n\n## Scene 1\n\nOne.\n\n---\n\n## Scene 2\n\nTwo.\n")?;
+    let imported = StoryChain::import_from_markdown(legacy.to_str().unwrap())?;
+    let chapter = &imported.chapters[0];
+    assert_eq!((chapter.title.as_str(), chapter.act.as_deref(), chapter.node_ids.len()), ("Opening", Some("Act I"), 2));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_human_review() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("The storm gathers.".to_string(), "R".to_string());
+    let first = chain.generate_next_nodes("root", &FixedResponseProvider("Everyone goes home."), None, 1, 3).await?;
+
+    // A rejected scene is removed and remembered on the scene it continued
+    let rejected = chain.reject_node(&first[0], Some("Too quiet".to_string()))?;
+    assert_eq!(rejected.content, "Everyone goes home.");
+    assert!(!chain.nodes.contains_key(&first[0]));
+    assert!(chain.nodes["root"].successors.is_empty());
+    let drafts = chain.rejected_drafts("root");
+    assert_eq!(drafts.len(), 1);
+    assert_eq!((drafts[0].excerpt.as_str(), drafts[0].reason.as_deref()), ("Everyone goes home.", Some("Too quiet")));
+
+    // Steering notes and rejections guide the next attempt
+    chain.add_steering_note("root", "The storm breaks.")?;
+    chain.add_steering_note("root", "Someone is hurt.")?;
+    let guidance = chain.guidance_notes("root");
+    assert!(guidance.iter().any(|n| n.contains("The storm breaks. Someone is hurt.")));
+    assert!(guidance.iter().any(|n| n.contains("rejected 1 earlier draft") && n.contains("Everyone goes home.") && n.contains("Too quiet")));
+    let second = chain.generate_next_nodes("root", &EchoProvider, None, 1, 3).await?;
+    let prompt = &chain.nodes[&second[0]].content;
+    assert!(prompt.contains("The storm breaks."));
+    assert!(prompt.contains("Everyone goes home."));
+
+    // Only the newest scene can be rejected, and edits are archived
+    let third = chain.generate_next_nodes(&second[0], &FixedResponseProvider("Lightning."), None, 2, 3).await?;
+    assert!(chain.reject_node(&second[0], None).is_err());
+    assert!(chain.reject_node("root", None).is_err());
+    chain.edit_node(&third[0], "Thunder.".to_string())?;
+    assert_eq!(chain.nodes[&third[0]].content, "Thunder.");
+    assert_eq!(chain.previous_versions(&third[0])[0].content, "Lightning.");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_export_formats() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("The lighthouse stood alone.".to_string(), "Set the scene".to_string());
+    chain.generate_next_nodes("root", &FixedResponseProvider("The lamp went dark."), None, 1, 1).await?;
+    let dir = tempfile::tempdir()?;
+
+    assert_eq!("md".parse::<ExportFormat>(), Ok(ExportFormat::Markdown));
+    assert!("rss".parse::<ExportFormat>().is_err());
+    for name in ExportFormat::NAMES {
+        assert!(name.parse::<ExportFormat>().is_ok(), "{} is accepted", name);
+    }
+
+    let markdown = dir.path().join(format!("story{}", ExportFormat::Markdown.file_suffix()));
+    chain.export_as(ExportFormat::Markdown, markdown.to_str().unwrap())?;
+    assert!(std::fs::read_to_string(&markdown)?.contains("The lamp went dark."));
+    let twee = dir.path().join("story.twee");
+    chain.export_as(ExportFormat::Twee, twee.to_str().unwrap())?;
+    assert!(std::fs::read_to_string(&twee)?.contains("The lighthouse stood alone."));
+
+    // A markdown export converts back to a chain
+    let json = dir.path().join("story.json");
+    StoryChain::import_from_markdown(markdown.to_str().unwrap())?.export_as(ExportFormat::Json, json.to_str().unwrap())?;
+    assert_eq!(StoryChain::load_from_file(json.to_str().unwrap())?.nodes.len(), 2);
+
+    #[cfg(not(feature = "pdf"))]
+    assert!(matches!(
+        chain.export_as(ExportFormat::Pdf, dir.path().join("story.pdf").to_str().unwrap()),
+        Err(StoryChainError::ConfigError(_))
+    ));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_marked_regeneration() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("Opening".to_string(), "R".to_string());
+    let first = chain.generate_next_nodes("root", &FixedResponseProvider("First draft."), None, 1, 2).await?;
+    let second = chain.generate_next_nodes(&first[0], &FixedResponseProvider("Second draft."), None, 2, 2).await?;
+
+    assert!(chain.mark_for_regeneration("root", true).is_err());
+    chain.mark_for_regeneration(&second[0], true)?;
+    chain.mark_for_regeneration(&first[0], true)?;
+    assert_eq!(chain.marked_for_regeneration(), vec![first[0].clone(), second[0].clone()]);
+    chain.mark_for_regeneration(&second[0], false)?;
+
+    let regenerated = chain.regenerate_marked(&FixedResponseProvider("Better draft."), None).await?;
+    assert_eq!(regenerated, vec![first[0].clone()]);
+    assert_eq!(chain.nodes[&first[0]].content, "Better draft.");
+    assert_eq!(chain.nodes[&second[0]].content, "Second draft.");
+    assert!(chain.marked_for_regeneration().is_empty());
+    assert_eq!(chain.previous_versions(&first[0])[0].content, "First draft.");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_progress_estimate() -> Result<(), StoryChainError> {
+    use std::time::Duration;
+
+    let mut timings = EpochTimings::default();
+    assert_eq!(timings.remaining(3), None);
+    timings.record(Duration::from_secs(20), 1);
+    timings.record(Duration::from_secs(40), 2);
+    assert_eq!(timings.remaining(3), Some(Duration::from_secs(60)));
+
+    assert_eq!(scenes_left(1, 1, 3), 3);
+    assert_eq!(scenes_left(2, 2, 2), 4 + 8);
+    assert_eq!(scenes_left(1, 0, 2), 2);
+
+    // Streamed tokens are counted while the bar is hidden
+    let progress = std::sync::Arc::new(RunProgress::new(&indicatif::MultiProgress::new(), true));
+    assert!(progress.is_hidden());
+    progress.start_run(3, 1);
+    progress.start_epoch(2);
+    let counter = std::sync::Arc::clone(&progress);
+    let provider = StreamingProvider::new(FixedResponseProvider("one two three four"), move |token| counter.add_tokens(token));
+    let (_, content) = provider.generate("Prompt").await?;
+    assert_eq!(progress.tokens(), count_tokens(&content));
+    progress.finish_epoch(Duration::from_secs(10), 1, 1);
+    progress.finish();
+    Ok(())
+}
+
+#[test]
+fn test_premise_paths() -> Result<(), StoryChainError> {
+    let dir = tempfile::tempdir()?;
+    let artifacts = dir.path().join("artifacts");
+    std::fs::create_dir_all(&artifacts)?;
+    std::fs::write(artifacts.join("harbor.yaml"), "title: Harbor")?;
+    let custom = dir.path().join("my premise.txt");
+    std::fs::write(&custom, "A lighthouse keeper's last winter.")?;
+
+    // Bare names fall back to the artifacts directory; paths are used as given
+    assert_eq!(resolve_premise_path("harbor", &artifacts), Some(artifacts.join("harbor.yaml")));
+    assert_eq!(resolve_premise_path(custom.to_str().unwrap(), &artifacts), Some(custom.clone()));
+    assert_eq!(resolve_premise_path("notes/harbor", &artifacts), Some(Path::new("notes/harbor").to_path_buf()));
+    assert_eq!(resolve_premise_path(STDIN_PREMISE, &artifacts), None);
+    assert_eq!(load_premise("harbor", &artifacts)?, "title: Harbor");
+    assert_eq!(load_premise(custom.to_str().unwrap(), &artifacts)?, "A lighthouse keeper's last winter.");
+
+    assert_eq!(premise_name(custom.to_str().unwrap()), "my premise");
+    assert_eq!(premise_name("harbor"), "harbor");
+    assert_eq!(premise_name(STDIN_PREMISE), "stdin");
+
+    // Errors name the file that was tried
+    let missing = load_premise("missing", &artifacts).unwrap_err().to_string();
+    assert!(missing.contains(&artifacts.join("missing.yaml").display().to_string()), "{}", missing);
+    let missing = load_premise("other/missing.md", &artifacts).unwrap_err().to_string();
+    assert!(missing.contains("other/missing.md"), "{}", missing);
+    std::fs::write(artifacts.join("empty.yaml"), "\n")?;
+    assert!(matches!(load_premise("empty", &artifacts), Err(StoryChainError::ConfigError(_))));
+    Ok(())
+}
+
+#[test]
+fn test_structured_premise() -> Result<(), StoryChainError> {
+    let premise = Premise::parse(
+        r#"
+title: "Harbor Lights"
+genre: Mystery
+tone: wistful
+pov: first person, the keeper
+premise: |
+  A lighthouse keeper's last winter.
+characters:
+  - name: Ada Quill
+    description: The keeper
+    arc: Learns to leave
+constraints:
+  - Nobody dies on the page
+target_length:
+  words: 5000
+  scenes: 8
+"#,
+    )?;
+    assert_eq!(premise.title.as_deref(), Some("Harbor Lights"));
+    assert_eq!(premise.characters[0].name, "Ada Quill");
+    assert_eq!(premise.target_length, Some(TargetLength { words: Some(5000), scenes: Some(8) }));
+
+    let section = premise.to_prompt_section();
+    assert!(section.starts_with("Title: Harbor Lights\nGenre: Mystery"), "{}", section);
+    assert!(section.contains("- Ada Quill: The keeper Arc: Learns to leave"), "{}", section);
+    assert!(section.contains("- Keep the tone wistful."), "{}", section);
+    assert!(section.contains("- Nobody dies on the page"), "{}", section);
+    assert!(section.contains("about 5000 words over about 8 scenes"), "{}", section);
+
+    // Plain text premises are used as they are
+    let plain = "A lighthouse keeper's last winter.\nStorms: many.";
+    assert_eq!(Premise::parse(plain)?.to_prompt_section(), plain);
+    assert_eq!(Premise::parse("premise: A short one\ntarget_length: 900")?.target_length.unwrap().words, Some(900));
+
+    // Every problem of an invalid premise is reported
+    let error = Premise::parse("title: \"\"\ncharacters:\n  - description: nameless\ntarget_length: 0").unwrap_err().to_string();
+    for problem in ["`premise` is missing", "`title` is empty", "character 1 has no name", "greater than zero"] {
+        assert!(error.contains(problem), "{}", error);
+    }
+    assert!(Premise::parse("title: [unclosed").unwrap_err().to_string().contains("Invalid premise YAML"));
+    Ok(())
+}
+
+#[cfg(feature = "tui")]
+#[tokio::test]
+async fn test_tui_browser() -> Result<(), StoryChainError> {
+    use ratatui::crossterm::event::KeyCode;
+    use storychain::tui::{graph_rows, ChainBrowser, TuiRequest};
+
+    let mut chain = StoryChain::new("The road forks.".to_string(), "Open on the fork".to_string()).with_branch_ratio(2);
+    let branches = chain.generate_next_nodes("root", &FixedResponseProvider("You walk on."), None, 1, 2).await?;
+    chain.branch_ratio = 1;
+    let after = chain.generate_next_nodes(&branches[0], &FixedResponseProvider("Home at last."), None, 2, 2).await?;
+
+    // Branches are listed under the scene they leave from
+    let rows: Vec<(String, usize)> = graph_rows(&chain).into_iter().map(|r| (r.node_id, r.branch_level)).collect();
+    assert_eq!(rows, vec![("root".to_string(), 0), (branches[1].clone(), 1), (branches[0].clone(), 0), (after[0].clone(), 0)]);
+
+    let mut browser = ChainBrowser::new(&chain);
+    assert_eq!(browser.selected_id(), Some("root"));
+    assert_eq!(browser.handle_key(&mut chain, KeyCode::Char('g')), None);
+    browser.handle_key(&mut chain, KeyCode::Down);
+    browser.handle_key(&mut chain, KeyCode::Down);
+    assert_eq!(browser.selected_id(), Some(branches[0].as_str()));
+    browser.handle_key(&mut chain, KeyCode::Char('m'));
+    assert_eq!(chain.marked_for_regeneration(), vec![branches[0].clone()]);
+    assert_eq!(browser.handle_key(&mut chain, KeyCode::Char('g')), Some(TuiRequest::RegenerateMarked));
+    assert_eq!(browser.handle_key(&mut chain, KeyCode::Char('c')), Some(TuiRequest::Continue));
+
+    // The selection follows its node when the graph changes
+    chain.insert_node_after("root", "A detour.".to_string(), String::new())?;
+    browser.refresh(&chain);
+    assert_eq!(browser.selected_id(), Some(branches[0].as_str()));
+
+    let mut terminal = ratatui::Terminal::new(ratatui::backend::TestBackend::new(120, 12))?;
+    terminal.draw(|frame| browser.render(&chain, frame))?;
+    let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+    assert!(screen.contains("● "));
+    assert!(screen.contains("You walk on."));
+    assert!(screen.contains("Reasoning"));
+    assert_eq!(browser.handle_key(&mut chain, KeyCode::Char('q')), Some(TuiRequest::Quit));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_template_export() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("Rain & <thunder>.".to_string(), "R".to_string());
+    chain.metadata.insert("title".to_string(), "Storm".to_string());
+    chain.generate_next_nodes("root", &FixedResponseProvider("The sky cleared."), None, 1, 1).await?;
+
+    let dir = tempfile::tempdir()?;
+    let markdown = dir.path().join("book.md.tera");
+    std::fs::write(&markdown, "# {{ title }}\n{% for scene in scenes %}{{ loop.index }}: {{ scene.content }} -> {{ scene.successors | length }}\n{% endfor %}{{ nodes[root_id].content }}")?;
+    let output = dir.path().join("book.md");
+    chain.export_with_template(markdown.to_str().unwrap(), output.to_str().unwrap())?;
+    assert_eq!(
+        std::fs::read_to_string(&output)?,
+        "# Storm\n1: Rain & <thunder>. -> 1\n2: The sky cleared. -> 0\nRain & <thunder>."
+    );
+
+    // HTML templates escape their variables
+    let html = dir.path().join("page.html.tera");
+    std::fs::write(&html, "<p>{{ scenes.0.content }}</p>{{ storylines | length }}")?;
+    assert_eq!(chain.render_template(html.to_str().unwrap())?, "<p>Rain &amp; &lt;thunder&gt;.</p>1");
+
+    // Template errors are reported instead of writing partial output
+    let broken = dir.path().join("broken.tera");
+    std::fs::write(&broken, "{% for scene in scenes %}")?;
+    assert!(matches!(chain.render_template(broken.to_str().unwrap()), Err(StoryChainError::TemplateError(_))));
+    Ok(())
+}
+
+#[test]
+fn test_reader_feedback_guidance() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new(
+        "Test content".to_string(),
+        "Test reasoning".to_string(),
+    );
+    chain.settings.feedback_guidance = true;
+    assert!(chain.guidance_notes("root").is_empty());
+
+    chain.add_feedback("root", 2, Some("pacing was slow".to_string()))?;
+    chain.add_feedback("root", 3, None)?;
+    assert!(chain.add_feedback("root", 6, None).is_err());
+    assert!(chain.add_feedback("missing", 4, None).is_err());
+
+    let summary = chain.feedback_summary();
+    assert_eq!(summary.count, 2);
+    assert_eq!(summary.average_rating, Some(2.5));
+
+    let notes = chain.guidance_notes("root");
+    assert_eq!(notes.len(), 1);
+    assert!(notes[0].contains("2.5/5") && notes[0].contains("\"pacing was slow\""));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_pov_rotation() -> Result<(), StoryChainError> {
+    let temp_dir = tempfile::tempdir()?;
+    let mut manager = ArtifactManager::new(temp_dir.path().to_str().unwrap());
+    manager.create_artifact("vince".to_string(), "A tormented artist".to_string(), ArtifactType::PovCharacter)?;
+
+    let mut chain = StoryChain::new(
+        "Test content".to_string(),
+        "Test reasoning".to_string(),
+    );
+    let names = vec!["vince".to_string(), "Lola".to_string()];
+    chain.settings.pov = Some(PovSchedule::from_artifacts(&names, PovMode::Rotation, &manager));
+    assert!(chain.guidance_notes("root")[0].contains("point of view of vince (A tormented artist)"));
+
+    let mut current_node = "root".to_string();
+    let mut povs = Vec::new();
+    for epoch in 1..=3 {
+        current_node = chain.generate_next_nodes(&current_node, &MockAIProvider, None, epoch, 3).await?[0].clone();
+        povs.push(chain.nodes[&current_node].metadata["pov"].clone());
+    }
+    assert_eq!(povs, vec!["vince", "Lola", "vince"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_story_format_validation() {
+    assert!(StoryFormat::Prose.validate("Anything goes.").is_empty());
+    assert!(StoryFormat::Epistolary
+        .validate("March 3rd, 1921\n\nDear Margaret,\n\nThe house is quiet.\n\nYours, Tom")
+        .is_empty());
+    assert_eq!(StoryFormat::Diary.validate("I woke late today.").len(), 1);
+    assert!(StoryFormat::Transcript.validate("DETECTIVE: Where were you?\n[Pause]\nSMITH: Home.").is_empty());
+    assert!(!StoryFormat::Transcript.validate("He paced the room.\nShe said nothing.").is_empty());
+    assert_eq!("letters".parse::<StoryFormat>(), Ok(StoryFormat::Epistolary));
+}
+
+#[test]
+fn test_dialogue_ratio_guidance() {
+    let text = "\"Where were you last night?\" she asked. He shrugged and looked away toward the window.";
+    assert_eq!(extract_dialogue(text), vec!["Where were you last night?"]);
+    assert!((dialogue_ratio(text) - 5.0 / 15.0).abs() < 1e-9);
+
+    let mut chain = StoryChain::new(
+        "He walked for hours through the empty town without a word.".to_string(),
+        "Test reasoning".to_string(),
+    );
+    chain.settings.dialogue = Some(DialogueTarget { ratio: 0.4, ..DialogueTarget::default() });
+    let notes = chain.guidance_notes("root");
+    assert_eq!(notes.len(), 1);
+    assert!(notes[0].contains("too expository"));
+}
+
+#[tokio::test]
+async fn test_reading_order_decoupled_from_causal_order() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new(
+        "Test content".to_string(),
+        "Test reasoning".to_string(),
+    );
+    let mut current_node = "root".to_string();
+    for epoch in 1..=2 {
+        current_node = chain.generate_next_nodes(&current_node, &MockAIProvider, None, epoch, 2).await?[0].clone();
+    }
+
+    // Open with the last scene as a flashback-style cold open
+    chain.move_in_reading_order("node_2", 0)?;
+    let reading: Vec<&str> = chain.nodes_in_reading_order().iter().map(|n| n.id.as_str()).collect();
+    let causal: Vec<&str> = chain.nodes_in_order().iter().map(|n| n.id.as_str()).collect();
+    assert_eq!(reading, vec!["node_2", "root", "node_1"]);
+    assert_eq!(causal, vec!["root", "node_1", "node_2"]);
+
+    assert!(chain.set_reading_order(vec!["root".to_string(), "root".to_string()]).is_err());
+    assert!(chain.set_reading_order(vec!["missing".to_string()]).is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_scene_cards() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new(
+        "Test content".to_string(),
+        "Test reasoning".to_string(),
+    );
+    chain.settings.scene_cards = true;
+
+    let provider = FixedResponseProvider(
+        "She opened the door.\n\n\
+        SCENE CARD\n\
+        GOAL: Find the letter\n\
+        CONFLICT: The door is locked\n\
+        OUTCOME: She breaks in\n\
+        HOOK: Someone is watching",
+    );
+    let new_id = chain.generate_next_nodes("root", &provider, None, 1, 2).await?[0].clone();
+    let node = &chain.nodes[&new_id];
+    assert_eq!(node.content, "She opened the door.");
+    assert_eq!(node.scene_card.as_ref().unwrap().hook, "Someone is watching");
+    assert!(!node.metadata.contains_key("scene_card_missing"));
+
+    let notes = chain.guidance_notes(&new_id);
+    assert!(notes.iter().any(|n| n.contains("She breaks in")));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_setup_tracking() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new(
+        "Test content".to_string(),
+        "Test reasoning".to_string(),
+    );
+    chain.settings.track_setups = true;
+    chain.plant_setup("The locked drawer in the study", None, SetupSource::User);
+
+    let provider = FixedResponseProvider(
+        "He hid the revolver above the mantel.\n\
+        PLANTED: A revolver above the mantel\n\
+        PAYOFF: 1",
+    );
+    let new_id = chain.generate_next_nodes("root", &provider, None, 1, 2).await?[0].clone();
+    assert_eq!(chain.nodes[&new_id].content, "He hid the revolver above the mantel.");
+    assert_eq!(chain.setups[0].paid_off_in.as_deref(), Some(new_id.as_str()));
+
+    let open = chain.unresolved_setups();
+    assert_eq!(open.len(), 1);
+    assert_eq!(open[0].description, "A revolver above the mantel");
+    assert_eq!(open[0].planted_in.as_deref(), Some(new_id.as_str()));
+    assert!(chain.guidance_notes(&new_id).iter().any(|n| n.contains("[1] A revolver above the mantel")));
+
+    let resolver = FixedResponseProvider("The revolver fires.\nPAYOFF: revolver above the mantel");
+    let resolved = chain.generate_resolving_scene(&resolver, None).await?;
+    assert!(resolved.is_some());
+    assert!(chain.unresolved_setups().is_empty());
+    assert_eq!(chain.generate_resolving_scene(&resolver, None).await?, None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_foreshadowing() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new(
+        "Test content".to_string(),
+        "Test reasoning".to_string(),
+    );
+    let plan = ForeshadowingPlan::from_outline(
+        "Outline\nScene 2: The lighthouse keeper is revealed as the smuggler\n5: The storm floods the village",
+        1,
+    );
+    assert_eq!(plan.beats.len(), 2);
+    chain.settings.foreshadowing = Some(plan);
+
+    let guidance = chain.guidance_notes("root");
+    assert!(guidance.iter().any(|n| n.contains("lighthouse keeper")));
+    assert!(!guidance.iter().any(|n| n.contains("storm floods")));
+
+    let provider = FixedResponseProvider("The keeper's boots were wet with seawater.");
+    let first = chain.generate_next_nodes("root", &provider, None, 1, 5).await?[0].clone();
+    assert_eq!(chain.foreshadowed_by(&first), vec!["The lighthouse keeper is revealed as the smuggler"]);
+
+    let second = chain.generate_next_nodes(&first, &provider, None, 2, 5).await?[0].clone();
+    assert!(chain.foreshadowed_by(&second).is_empty());
+
+    let judge = FixedResponseProvider("VERDICT: yes\nHINT: the wet boots");
+    let checks = chain.verify_foreshadowing(&judge).await?;
+    assert_eq!(checks[0].confirmed_ids, vec![first.clone()]);
+    assert!(checks[1].carrier_ids.is_empty());
+    assert!(chain.nodes[&first].metadata.contains_key("foreshadowing_verified"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_subplots_are_interleaved() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new(
+        "Test content".to_string(),
+        "Test reasoning".to_string(),
+    );
+    chain.subplots.push(Subplot::new(
+        "romance".to_string(),
+        "The romance".to_string(),
+        "Two rivals slowly fall for each other.".to_string(),
+        2,
+    ));
+
+    let provider = MockAIProvider;
+    let mut current = "root".to_string();
+    for epoch in 1..=4 {
+        let ids = chain.generate_with_subplots(&current, &provider, None, None, epoch, 4).await?;
+        assert_eq!(ids.len(), if epoch.is_multiple_of(2) { 2 } else { 1 });
+        current = ids.last().unwrap().clone();
+    }
+
+    let subplot_nodes = &chain.subplots[0].node_ids;
+    assert_eq!(subplot_nodes.len(), 2);
+    assert_eq!(chain.nodes[&subplot_nodes[0]].metadata["subplot"], "romance");
+    assert_eq!(chain.nodes_in_order().len(), 7);
+
+    // Main-plot scenes continue from the main plot, not from the subplot scene before them
+    let after_subplot = chain.nodes[&subplot_nodes[0]].successors[0].clone();
+    assert_eq!(chain.nodes[&after_subplot].predecessor(), Some(&subplot_nodes[0]));
+    assert_eq!(&chain.main_plot_context(&subplot_nodes[0]), chain.nodes[&subplot_nodes[0]].predecessor().unwrap());
+
+    Ok(())
+}
+
+/// Writes each strand scene as "<viewpoint> scene <n>" and summaries as the
+/// list of scenes they have seen, recording every scene prompt
+#[derive(Default)]
+struct StrandProvider {
+    scenes: AtomicUsize,
+    prompts: std::sync::Mutex<Vec<String>>,
+}
+
+#[async_trait::async_trait]
+impl AIProvider for StrandProvider {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        let scene_names = regex::Regex::new(r"(Alice|Bob) scene \d").unwrap();
+        if prompt.contains("keeping the running summary") {
+            let seen: Vec<&str> = scene_names.find_iter(prompt).map(|m| m.as_str()).collect();
+            return Ok(("Summarizing".to_string(), seen.join(", ")));
+        }
+        self.prompts.lock().unwrap().push(prompt.to_string());
+        let pov = regex::Regex::new(r"point of view of (\w+)").unwrap().captures(prompt).unwrap()[1].to_string();
+        Ok(("Writing".to_string(), format!("{} scene {}", pov, self.scenes.fetch_add(1, Ordering::SeqCst) + 1)))
+    }
+}
+
+#[tokio::test]
+async fn test_parallel_strands() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("The city wakes.".to_string(), "Test reasoning".to_string());
+    chain.settings.memory = Some(RollingSummary { interval: 1, max_words: 100 });
+    chain.strands.push(Strand::new("alice".to_string(), "The heist".to_string(), Some("Alice".to_string()), "Alice robs the bank.".to_string()));
+    chain.strands.push(Strand::new("bob".to_string(), "The chase".to_string(), Some("Bob".to_string()), "Bob hunts the robber.".to_string()));
+
+    let provider = StrandProvider::default();
+    let mut current = "root".to_string();
+    for epoch in 1..=4 {
+        current = chain.generate_strand_scene(&current, &provider, Some("A city of thieves."), epoch, 4).await?;
+    }
+
+    // The strands alternate, each continuing its own previous scene
+    assert_eq!(chain.strands[0].node_ids, ["node_1", "node_3"]);
+    assert_eq!(chain.strands[1].node_ids, ["node_2", "node_4"]);
+    assert_eq!(chain.nodes["node_3"].content, "Alice scene 3");
+    assert_eq!(chain.nodes["node_3"].predecessor().map(String::as_str), Some("node_2"));
+    assert_eq!((chain.nodes["node_4"].metadata["strand"].as_str(), chain.nodes["node_4"].metadata["pov"].as_str()), ("bob", "Bob"));
+    let prompts = provider.prompts.lock().unwrap().clone();
+    assert!(prompts[2].contains("Storyline: The heist\nAlice robs the bank."));
+    assert!(prompts[2].contains("Previous Scene Content:\nAlice scene 1"));
+
+    // The other strand's latest scene is shown, and the shared summary covers the ones before it
+    assert!(prompts[2].contains("Latest Scene, From Another Storyline:\nBob scene 2"));
+    let summary = prompts[3].split("Story So Far:\n").nth(1).unwrap().lines().next().unwrap();
+    assert!(summary.contains("Alice scene 1") && summary.contains("Bob scene 2"), "summary: {}", summary);
+
+    // Exports read the strands interleaved or grouped
+    chain.arrange_strands(StrandOrder::Grouped)?;
+    let grouped: Vec<&str> = chain.nodes_in_reading_order().iter().map(|n| n.id.as_str()).collect();
+    assert_eq!(grouped, ["root", "node_1", "node_3", "node_2", "node_4"]);
+    chain.arrange_strands("interleaved".parse().unwrap())?;
+    let interleaved: Vec<&str> = chain.nodes_in_reading_order().iter().map(|n| n.id.as_str()).collect();
+    assert_eq!(interleaved, ["root", "node_1", "node_2", "node_3", "node_4"]);
+
+    // Removing a strand scene drops it from its strand, so the strands can still be arranged
+    chain.glossary.push(GlossaryEntry {
+        term: "Vault".to_string(),
+        category: "place".to_string(),
+        definition: "Where the money sleeps.".to_string(),
+        first_node_id: Some("node_3".to_string()),
+    });
+    chain.remove_node("node_3")?;
+    assert_eq!(chain.strands[0].node_ids, ["node_1"]);
+    assert_eq!(chain.glossary[0].first_node_id, None);
+    chain.arrange_strands(StrandOrder::Grouped)?;
+    let grouped: Vec<&str> = chain.nodes_in_reading_order().iter().map(|n| n.id.as_str()).collect();
+    assert_eq!(grouped, ["root", "node_1", "node_2", "node_4"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_tension_curve_targeting() -> Result<(), StoryChainError> {
+    let curve: TensionCurve = "rising, dip@60, spike@90".parse().unwrap();
+    assert!(curve.target_at(0.1) < curve.target_at(0.5));
+    assert!(curve.target_at(0.6) < curve.target_at(0.5));
+    assert!(curve.target_at(0.9) > curve.target_at(1.0));
+    assert!("spiral".parse::<TensionCurve>().is_err());
+
+    let mut chain = StoryChain::new(
+        "The garden was quiet and the tea was warm.".to_string(),
+        "Test reasoning".to_string(),
+    );
+    chain.settings.tension_curve = Some("flat, 100%:10".parse().unwrap());
+
+    let provider = FixedResponseProvider("They sat in the garden and talked about the weather for a long while.");
+    let first = chain.generate_next_nodes("root", &provider, None, 1, 2).await?[0].clone();
+    assert!(chain.nodes[&first].metadata.contains_key("tension"));
+    assert_eq!(chain.nodes[&first].metadata["target_tension"], "5.0");
+
+    let prompt_notes = chain.tension_guidance(&first, 2, 2).unwrap();
+    assert!(prompt_notes.starts_with("Raise the stakes"));
+
+    chain.generate_next_nodes(&first, &provider, None, 2, 2).await?;
+    let report = chain.tension_report();
+    assert_eq!(report.len(), 2);
+    assert_eq!(report[1].target, 10.0);
+    assert!(chain.tension_report_markdown().contains("too slack"));
+    assert_eq!(chain.stats(238).scenes[1].tension, report[0].actual);
+
+    Ok(())
+}
+
+#[test]
+fn test_genre_presets() {
+    assert_eq!("Cozy Mystery".parse::<GenrePreset>(), Ok(GenrePreset::CozyMystery));
+    assert_eq!("hard-sf".parse::<GenrePreset>(), Ok(GenrePreset::HardSf));
+    assert!("western".parse::<GenrePreset>().is_err());
+
+    assert_eq!(GenrePreset::from_premise("title: \"X\"\ngenre: \"noir\"\n"), Some(GenrePreset::Noir));
+    assert_eq!(
+        GenrePreset::from_premise("genre: \"Crime / Thriller\"\ngenre_preset: high-fantasy\n"),
+        Some(GenrePreset::HighFantasy)
+    );
+    assert_eq!(GenrePreset::from_premise("genre: \"Crime / Thriller / Dark Comedy\"\n"), None);
+
+    let mut chain = StoryChain::new("Test content".to_string(), "Test reasoning".to_string());
+    assert!(chain.genre_guidance(1, 10).is_empty());
+    chain.settings.genre = Some(GenrePreset::Noir);
+    let notes = chain.genre_guidance(6, 10);
+    assert!(notes[0].contains("hard-boiled"));
+    assert!(notes[1].contains("betrayal"));
+    assert_eq!(chain.genre_guidance(23, 100).len(), 1);
+}
+
+#[tokio::test]
+async fn test_constraints() -> Result<(), StoryChainError> {
+    let set = ConstraintSet::parse(
+        "# House rules\n\
+        - no character deaths\n\
+        - keep it PG-13\n\
+        - story must stay in one location: the lighthouse\n\
+        - avoid: dragons\n\
+        - The narrator never learns the killer's name\n",
+    )?;
+    assert_eq!(set.constraints.len(), 5);
+    assert_eq!(set.constraints[1], Constraint::Rating(ContentRating::Pg13));
+    assert!(ConstraintSet::parse("keep it NC-17").is_err());
+
+    let mut chain = StoryChain::new("Test content".to_string(), "Test reasoning".to_string());
+    chain.settings.constraints = Some(set);
+    assert!(chain.guidance_notes("root").iter().any(|n| n.contains("No character may die.")));
+
+    let provider = FixedResponseProvider("The old keeper died at dawn, and they drove to the city.");
+    let new_id = chain.generate_next_nodes("root", &provider, None, 1, 2).await?[0].clone();
+    let violations = &chain.nodes[&new_id].metadata["constraint_violations"];
+    assert!(violations.contains("No character may die."));
+    assert!(violations.contains("drove to"));
+    assert!(!violations.contains("dragons"));
+
+    let report = chain.constraint_report();
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].0, new_id);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_arc_pacing() -> Result<(), StoryChainError> {
+    let stages: Vec<ArcStage> = (1..=10).map(|epoch| ArcStage::for_epoch(epoch, 10)).collect();
+    assert_eq!(&stages[..2], &[ArcStage::Opening; 2]);
+    assert_eq!(&stages[2..6], &[ArcStage::Midpoint; 4]);
+    assert_eq!(&stages[6..9], &[ArcStage::Climax; 3]);
+    assert_eq!(stages[9], ArcStage::Finale);
+    assert_eq!(ArcStage::for_epoch(1, 1), ArcStage::Finale);
+
+    // The prompt names the stage and, on the last epoch, demands an ending
+    let mut chain = StoryChain::new("The first scene.".to_string(), "Test reasoning".to_string());
+    let opening_id = chain.generate_next_nodes("root", &EchoProvider, None, 1, 5).await?[0].clone();
+    let opening = &chain.nodes[&opening_id].content;
+    assert!(opening.contains("- Arc stage: opening"));
+    assert!(opening.contains("Pacing For This Scene:\nThe story is in its opening."));
+    assert!(!opening.contains("conclusive ending"));
+
+    let finale_id = chain.generate_next_nodes("root", &EchoProvider, None, 5, 5).await?[0].clone();
+    let finale = &chain.nodes[&finale_id].content;
+    assert!(finale.contains("- Arc stage: finale"));
+    assert!(finale.contains("This is the final scene of the story. Bring it to a conclusive ending"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_prompt_templates() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("The first scene.".to_string(), "Test reasoning".to_string());
+    let provider = EchoProvider;
+
+    // The built-in continuation template keeps the familiar prompt layout
+    let default_id = chain.generate_next_nodes("root", &provider, Some("A heist."), 1, 3).await?[0].clone();
+    let prompt = &chain.nodes[&default_id].content;
+    assert!(prompt.starts_with("Story Premise:\nA heist.\n\nStory Progress:\n- Current epoch: 1 of 3"));
+    assert!(prompt.contains("Previous Scene Content:\nThe first scene."));
+    assert!(!prompt.contains("Guidance For This Scene"));
+
+    // Custom templates see metadata variables and artifacts
+    let dir = tempfile::tempdir()?;
+    std::fs::write(
+        dir.path().join("continuation.tera"),
+        "{{ epoch }}/{{ total_epochs }} {{ mood }} {{ artifacts.world }} [{{ last_scene }}]",
+    )?;
+    chain.prompts = PromptTemplates::from_dir(dir.path().to_str().unwrap())?;
+    chain.prompts.artifacts.insert("world".to_string(), "A drowned city".to_string());
+    chain.metadata.insert("mood".to_string(), "gloomy".to_string());
+
+    let custom_id = chain.generate_next_nodes("root", &provider, None, 2, 3).await?[0].clone();
+    assert_eq!(chain.nodes[&custom_id].content, "2/3 gloomy A drowned city [The first scene.]");
+
+    // The other prompts are templates too, overridden the same way
+    std::fs::write(dir.path().join("synopsis.tera"), "Sum up the {{ mood }} story: {{ story | truncate(length=7, end=\"\") }}")?;
+    chain.prompts = PromptTemplates::from_dir(dir.path().to_str().unwrap())?;
+    let synopsis = chain.generate_synopsis(&provider, storychain::passes::SynopsisLength::Paragraph, "heist").await?;
+    assert_eq!(synopsis.content, "Sum up the gloomy story: Scene 1");
+    let defaults = PromptTemplates::default();
+    assert_ne!(chain.prompts.template_digest("synopsis"), defaults.template_digest("synopsis"));
+    assert_eq!(chain.prompts.template_digest("title_blurb"), defaults.template_digest("title_blurb"));
+
+    // Broken templates are reported rather than silently ignored
+    std::fs::write(dir.path().join("initial.tera"), "{% if premise %}unterminated")?;
+    assert!(matches!(
+        PromptTemplates::from_dir(dir.path().to_str().unwrap()),
+        Err(StoryChainError::TemplateError(_))
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_prompt_template_files() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("The first scene.".to_string(), "Test reasoning".to_string());
+    let dir = tempfile::tempdir()?;
+
+    // A single template file replaces just its prompt
+    let path = dir.path().join("terse.txt");
+    std::fs::write(&path, "Escribe la escena {{ epoch }}. Premisa: {{ premise }}. Antes: {{ last_scene }}")?;
+    let template = PromptTemplate::from_file("continuation", &path)?;
+    assert_eq!(template.name, "continuation");
+    chain.prompts.set_template(template)?;
+
+    let id = chain.generate_next_nodes("root", &EchoProvider, Some("Un robo."), 2, 3).await?[0].clone();
+    assert_eq!(chain.nodes[&id].content, "Escribe la escena 2. Premisa: Un robo.. Antes: The first scene.");
+    assert!(chain.prompts.initial_prompt("Un robo.", &[])?.contains("You are tasked with writing a scene"));
+
+    // Unknown names, missing files, and broken templates are rejected
+    assert!(matches!(PromptTemplate::from_file("epilogue", &path), Err(StoryChainError::ConfigError(_))));
+    assert!(matches!(
+        PromptTemplate::from_file("initial", &dir.path().join("missing.tera")),
+        Err(StoryChainError::ConfigError(_))
+    ));
+    std::fs::write(&path, "{{ premise")?;
+    assert!(matches!(PromptTemplate::from_file("initial", &path), Err(StoryChainError::TemplateError(_))));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_exemplars_in_prompts() -> Result<(), StoryChainError> {
+    let dir = tempfile::tempdir()?;
+    let mut manager = ArtifactManager::new(dir.path().to_str().unwrap());
+    manager.create_artifact(
+        "exemplar_rain".to_string(),
+        "The rain had opinions about Marlowe.".to_string(),
+        ArtifactType::Exemplar,
+    )?;
+    manager.create_artifact("world".to_string(), "A drowned city.".to_string(), ArtifactType::WorldBuilding)?;
+
+    let mut templates = PromptTemplates::default();
+    templates.add_artifacts(&manager);
+    assert_eq!(templates.exemplars, vec!["The rain had opinions about Marlowe.".to_string()]);
+
+    let initial = templates.initial_prompt("A noir story.", &[])?;
+    assert!(initial.contains("--- Example 1 ---\nThe rain had opinions about Marlowe."));
+    assert!(!initial.contains("A drowned city."));
+
+    let mut chain = StoryChain::new("The first scene.".to_string(), "Test reasoning".to_string());
+    chain.prompts = templates;
+    let new_id = chain.generate_next_nodes("root", &EchoProvider, None, 1, 2).await?[0].clone();
+    assert!(chain.nodes[&new_id].content.contains("Examples Of The Desired Voice"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_character_sheets_in_prompts() -> Result<(), StoryChainError> {
+    let dir = tempfile::tempdir()?;
+    let mut manager = ArtifactManager::new(dir.path().to_str().unwrap());
+    let lola = CharacterSheet {
+        name: "Lola 'Fang' Hernandez".to_string(),
+        traits: vec!["Sharp-tongued".to_string()],
+        goals: vec!["Pay off her brother's debt".to_string()],
+        relationships: [("Rico".to_string(), "owes him money".to_string())].into(),
+        voice: Some("Clipped, sardonic".to_string()),
+        ..Default::default()
+    };
+    manager.update_character_sheet(&lola)?;
+    manager.update_character_sheet(&CharacterSheet { name: "Rico Sinistra".to_string(), ..Default::default() })?;
+    manager.create_artifact("sheet_broken".to_string(), "traits: [unclosed".to_string(), ArtifactType::CharacterSheet)?;
+
+    // Sheets survive a reload from disk; unreadable ones are skipped
+    let mut reloaded = ArtifactManager::new(dir.path().to_str().unwrap());
+    reloaded.load_from_dir()?;
+    let sheets = reloaded.character_sheets();
+    assert_eq!(sheets.len(), 2);
+    assert_eq!(sheets[0], lola);
+    assert_eq!(lola.artifact_id(), "sheet_lola_fang_hernandez");
+
+    // Characters are found by any part of their name or their nickname
+    assert!(lola.is_mentioned_in("Fang wiped the counter."));
+    assert!(lola.is_mentioned_in("Hernandez was late."));
+    assert!(!lola.is_mentioned_in("Fangs of the city."));
+
+    let mut templates = PromptTemplates::default();
+    templates.add_artifacts(&reloaded);
+    let mut chain = StoryChain::new("Lola slid the coffee across the bar.".to_string(), "Test reasoning".to_string());
+    chain.prompts = templates;
+    let new_id = chain.generate_next_nodes("root", &EchoProvider, None, 1, 2).await?[0].clone();
+    let prompt = &chain.nodes[&new_id].content;
+    assert!(prompt.contains("Characters In The Previous Scene"), "{}", prompt);
+    assert!(prompt.contains("- Traits: Sharp-tongued\n- Goals: Pay off her brother's debt\n- With Rico: owes him money\n- Voice: Clipped, sardonic"), "{}", prompt);
+    assert!(!prompt.contains("Rico Sinistra"), "{}", prompt);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_lore_retrieval() -> Result<(), StoryChainError> {
+    // Long paragraphs are split at the first sentence end past half the chunk size
+    let long_paragraph = "The harbor wall is old. ".repeat(40);
+    let chunks = chunk_lore(&format!("First fact.\n\nSecond fact.\n\n{}", long_paragraph));
+    assert_eq!(chunks.len(), 6);
+    assert_eq!(chunks[0], "First fact.");
+    assert_eq!(chunks[2].split_whitespace().count(), 60);
+
+    let dir = tempfile::tempdir()?;
+    let mut manager = ArtifactManager::new(dir.path().to_str().unwrap());
+    manager.create_artifact(
+        "world".to_string(),
+        "The Glass Tower stands at the center of Veyra and only the Archivists may enter it.\n\n\
+        Lanterns in the lower city burn whale oil, which the Guild taxes heavily.\n\n\
+        The river Sel freezes every winter, cutting the city in two."
+            .to_string(),
+        ArtifactType::WorldBuilding,
+    )?;
+    manager.create_artifact("note".to_string(), "The Glass Tower is made of cheese.".to_string(), ArtifactType::Synopsis)?;
+
+    let mut templates = PromptTemplates::default();
+    templates.add_artifacts(&manager);
+    assert_eq!(templates.lore.len(), 3);
+
+    let found = templates.lore.retrieve("Mara climbed toward the Glass Tower, past the Archivists' gate.", 2);
+    assert!(found[0].text.starts_with("The Glass Tower stands"), "{:?}", found);
+    assert_eq!(found[0].artifact_id, "world");
+    assert!(templates.lore.retrieve("Nothing relevant here at all.", 2).is_empty());
+
+    // The initial prompt retrieves lore for the premise, continuations for the previous scene
+    assert!(templates.initial_prompt("A smuggler hides whale oil from the Guild.", &[])?.contains("- Lanterns in the lower city"));
+    templates.lore.top_k = 1;
+    let mut chain = StoryChain::new("The river Sel had frozen overnight.".to_string(), "Test reasoning".to_string());
+    chain.prompts = templates;
+    let new_id = chain.generate_next_nodes("root", &EchoProvider, None, 1, 2).await?[0].clone();
+    let prompt = &chain.nodes[&new_id].content;
+    assert!(prompt.contains("Established Lore (do not contradict these facts):\n- The river Sel freezes"), "{}", prompt);
+    assert!(!prompt.contains("Glass Tower"), "{}", prompt);
+
+    Ok(())
+}
+
+/// An embedding provider that counts a few keywords, counting its calls
+struct KeywordEmbedder(AtomicUsize);
+
+#[async_trait::async_trait]
+impl EmbeddingProvider for KeywordEmbedder {
+    async fn embed(&self, text: &str) -> Result<Vec<f32>, StoryChainError> {
+        self.0.fetch_add(1, Ordering::Relaxed);
+        let text = text.to_lowercase();
+        Ok(["dragon", "ruby", "bread", "harbor"].iter().map(|word| text.matches(word).count() as f32).collect())
+    }
+
+    fn model(&self) -> &str {
+        "keywords"
+    }
+}
+
+#[tokio::test]
+async fn test_scene_recall() -> Result<(), StoryChainError> {
+    assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+    assert_eq!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]), 0.0);
+    assert_eq!(cosine_similarity(&[1.0], &[1.0, 0.0]), 0.0);
+
+    let embedder = Arc::new(KeywordEmbedder(AtomicUsize::new(0)));
+    let mut chain = StoryChain::new("The dragon coiled around the ruby.".to_string(), "Opening".to_string())
+        .with_embedder(embedder.clone());
+    let bakery = chain.insert_node_after("root", "The baker sold bread at dawn.".to_string(), String::new())?;
+    let harbor = chain.insert_node_after(&bakery, "Ships crowded the harbor.".to_string(), String::new())?;
+    let last = chain.insert_node_after(&harbor, "Smoke rose: the dragon had left its ruby.".to_string(), String::new())?;
+    chain.settings.recall = Some(SceneRecall { scenes: 1, excerpt_chars: 20 });
+
+    let new_id = chain.generate_next_nodes(&last, &EchoProvider, None, 1, 2).await?[0].clone();
+    let prompt = &chain.nodes[&new_id].content;
+    assert!(prompt.contains("Relevant Earlier Scenes:\n[root]\nThe dragon coiled ar\n"), "{}", prompt);
+    assert!(!prompt.contains("The baker sold bread"), "{}", prompt);
+    assert_eq!(embedder.0.load(Ordering::Relaxed), 4);
+    assert_eq!(chain.nodes[&bakery].embedding.as_ref().unwrap().vector, vec![0.0, 0.0, 1.0, 0.0]);
+
+    // Only edited scenes are embedded again, and vectors survive saving
+    chain.edit_node(&bakery, "The baker sold bread by the harbor.".to_string())?;
+    assert_eq!(chain.embed_storyline(&last).await?, 1);
+    let saved: StoryChain = serde_json::from_str(&serde_json::to_string(&chain)?)?;
+    assert_eq!(saved.nodes[&bakery].embedding, chain.nodes[&bakery].embedding);
+    assert_eq!(saved.similar_earlier_scenes(&harbor, 1), vec![bakery.clone()]);
+
+    Ok(())
+}
+
+/// A provider that writes a scene with a contradiction, reports it when
+/// checking, and fixes it when asked to revise
+struct ContinuityProvider;
+
+#[async_trait::async_trait]
+impl AIProvider for ContinuityProvider {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        let response = if prompt.contains("You are a continuity editor") {
+            let scene = prompt.rsplit("New Scene:\n").next().unwrap_or_default();
+            if scene.contains("green eyes") {
+                "CONTRADICTIONS:\n- Mara's eyes are green, but she was established with blue eyes"
+            } else {
+                "CONTRADICTIONS:\nNONE"
+            }
+        } else if prompt.starts_with("Revise the scene below to fix") {
+            "Mara's blue eyes narrowed."
+        } else {
+            "Mara's green eyes narrowed."
+        };
+        Ok(("Reasoning".to_string(), response.to_string()))
+    }
+}
+
+#[tokio::test]
+async fn test_consistency_critic() -> Result<(), StoryChainError> {
+    assert_eq!(parse_contradictions("CONTRADICTIONS:\n- One\n* Two\n"), vec!["One".to_string(), "Two".to_string()]);
+    assert!(parse_contradictions("CONTRADICTIONS:\nNONE").is_empty());
+
+    // Findings are recorded in the node metadata
+    let mut chain = StoryChain::new("Mara had blue eyes.".to_string(), "Opening".to_string());
+    chain.settings.consistency = Some(ConsistencyCheck { revise: false });
+    let new_id = chain.generate_next_nodes("root", &ContinuityProvider, None, 1, 2).await?[0].clone();
+    assert_eq!(chain.nodes[&new_id].content, "Mara's green eyes narrowed.");
+    assert!(chain.nodes[&new_id].metadata[CONSISTENCY_ISSUES_KEY].contains("established with blue eyes"));
+
+    // With revision, the fixed scene is kept and checked again
+    chain.settings.consistency = Some(ConsistencyCheck { revise: true });
+    let revised_id = chain.generate_next_nodes("root", &ContinuityProvider, None, 1, 2).await?[0].clone();
+    let node = &chain.nodes[&revised_id];
+    assert_eq!(node.content, "Mara's blue eyes narrowed.");
+    assert!(!node.metadata.contains_key(CONSISTENCY_ISSUES_KEY));
+    assert_eq!(node.metadata[CONSISTENCY_REVISED_KEY], "true");
+
+    Ok(())
+}
+
+/// A provider that scores flat scenes low and rewrites them vividly, one
+/// adjective at a time
+struct EditorProvider;
+
+#[async_trait::async_trait]
+impl AIProvider for EditorProvider {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        let scene = prompt.rsplit("Scene:\n").next().unwrap_or_default();
+        let vivid = scene.matches("vivid").count();
+        if prompt.starts_with("You are a demanding fiction editor") {
+            let score = 4 + 2 * vivid;
+            Ok((String::new(), format!("SCORES:\n- Pacing: 8\n- prose quality: {}/10\nNOTES: Flat prose", score)))
+        } else {
+            Ok(("Rewrote it".to_string(), format!("A {}scene.", "vivid ".repeat(vivid + 1))))
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_revision_loop() -> Result<(), StoryChainError> {
+    let criteria = RevisionCriteria { criteria: vec!["pacing".to_string(), "prose quality".to_string()], threshold: 7.0 };
+    let evaluation = parse_evaluation("SCORES:\n- pacing: 7/10\nNOTES: Tighten", &criteria);
+    assert_eq!(evaluation.scores["pacing"], 7.0);
+    assert_eq!(evaluation.scores["prose quality"], 0.0);
+    assert_eq!(evaluation.notes, "Tighten");
+
+    let mut chain = StoryChain::new("Opening.".to_string(), "Opening".to_string());
+    let node_id = chain.insert_node_after("root", "A scene.".to_string(), "First draft".to_string())?;
+    let evaluation = chain.generate_with_revision(&node_id, &EditorProvider, &criteria, 5).await?;
+    assert!(evaluation.passes(&criteria));
+    assert_eq!(chain.nodes[&node_id].content, "A vivid vivid scene.");
+    assert_eq!(chain.nodes[&node_id].metadata[REVISION_SCORE_KEY], "8.0");
+    let drafts: Vec<Draft> = serde_json::from_str(&chain.nodes[&node_id].metadata[REVISION_DRAFTS_KEY])?;
+    let lowest: Vec<f64> = drafts.iter().map(|d| d.evaluation.lowest()).collect();
+    assert_eq!(lowest, vec![4.0, 6.0, 8.0]);
+    assert_eq!(drafts[0].content, "A scene.");
+
+    // The rounds are capped
+    let capped_id = chain.insert_node_after(&node_id, "A scene.".to_string(), String::new())?;
+    assert!(!chain.generate_with_revision(&capped_id, &EditorProvider, &criteria, 1).await?.passes(&criteria));
+    assert_eq!(chain.nodes[&capped_id].content, "A vivid scene.");
+    assert!(chain.generate_with_revision("missing", &EditorProvider, &criteria, 1).await.is_err());
+
+    Ok(())
+}
+
+/// A provider with a system role that records the system prompt it receives
+struct SystemRoleProvider;
+
+#[async_trait::async_trait]
+impl AIProvider for SystemRoleProvider {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        Ok(("No system".to_string(), prompt.to_string()))
+    }
+
+    async fn generate_with_system(&self, system_prompt: &str, _prompt: &str) -> Result<(String, String), StoryChainError> {
+        Ok(("System role".to_string(), system_prompt.to_string()))
+    }
+}
+
+#[tokio::test]
+async fn test_system_prompt() -> Result<(), StoryChainError> {
+    assert_eq!(prepend_system_prompt("You are a noir author.", "Write."), "You are a noir author.\n\nWrite.");
+    assert_eq!(prepend_system_prompt("  ", "Write."), "Write.");
+
+    let mut chain = StoryChain::new("The first scene.".to_string(), "Test reasoning".to_string());
+    chain.settings.system_prompt = Some("You are a noir author.".to_string());
+
+    // Providers without a system role get the system prompt prepended
+    let prepended = chain.generate_next_nodes("root", &EchoProvider, None, 1, 3).await?[0].clone();
+    assert!(chain.nodes[&prepended].content.starts_with("You are a noir author.\n\n"));
+
+    // Providers with a system role receive it separately
+    let separate = chain.generate_next_nodes(&prepended, &SystemRoleProvider, None, 2, 3).await?[0].clone();
+    assert_eq!(chain.nodes[&separate].content, "You are a noir author.");
+
+    Ok(())
+}
+
+/// A chat model that records every conversation it receives
+#[derive(Default)]
+struct RecordingChatModel {
+    conversations: std::sync::Mutex<Vec<Vec<ChatMessage>>>,
+}
+
+#[async_trait::async_trait]
+impl ChatModel for &RecordingChatModel {
+    async fn chat(&self, messages: &[ChatMessage]) -> Result<String, StoryChainError> {
+        let mut conversations = self.conversations.lock().unwrap();
+        conversations.push(messages.to_vec());
+        Ok(format!("<think>Reasoning</think>Reply {}", conversations.len()))
+    }
+}
+
+#[tokio::test]
+async fn test_chat_provider_keeps_history() -> Result<(), StoryChainError> {
+    let model = RecordingChatModel::default();
+    let provider = ChatProvider::new(&model).with_max_history_chars(60).with_keep_recent(2);
+
+    assert_eq!(provider.generate("First prompt").await?.1, "Reply 1");
+    assert_eq!(provider.generate_with_system("Be terse.", "Second prompt").await?.1, "Reply 2");
+    {
+        let conversations = model.conversations.lock().unwrap();
+        let second = &conversations[1];
+        assert_eq!(second[0], ChatMessage::new(ChatRole::System, "Be terse."));
+        assert_eq!(second[1], ChatMessage::new(ChatRole::User, "First prompt"));
+        assert_eq!(second[2], ChatMessage::new(ChatRole::Assistant, "Reply 1"));
+        assert_eq!(second[3], ChatMessage::new(ChatRole::User, "Second prompt"));
+    }
+
+    // Exceeding the budget summarizes everything but the most recent messages
+    provider.generate("A third prompt that pushes the history over its budget").await?;
+    let history = provider.history().await;
+    assert_eq!(history[0].role, ChatRole::System);
+    assert!(history[0].content.starts_with("Summary of the story so far:\nReply 3"));
+    assert_eq!(history[1], ChatMessage::new(ChatRole::User, "Second prompt"));
+    assert_eq!(history.len(), 5);
+
+    provider.reset().await;
+    assert!(provider.history().await.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_model_comparison() -> Result<(), StoryChainError> {
+    let templates = PromptTemplates::default();
+    let first = generate_comparison_run("model-a", &MockAIProvider, "A quiet street.", 2, &templates, &GenerationConfig::default()).await?;
+    let second = generate_comparison_run("model-b", &EchoProvider, "A quiet street.", 1, &templates, &GenerationConfig::default()).await?;
+    assert_eq!(first.chain.nodes.len(), 3);
+    assert_eq!(second.chain.metadata["model"], "model-b");
+
+    let runs = [first, second];
+    let report = comparison_markdown(&runs);
+    assert!(report.contains("| | model-a | model-b |"));
+    assert!(report.contains("| Scenes | 3 | 2 |"));
+    assert!(report.contains("| Scene 3 | The sun cast long shadows across the quiet street. |  |"));
+
+    let interleaved = interleaved_markdown(&runs);
+    let scene_two = interleaved.find("## Scene 2").unwrap();
+    assert!(interleaved[scene_two..].find("### model-a").unwrap() < interleaved[scene_two..].find("### model-b").unwrap());
+    assert!(!interleaved[interleaved.find("## Scene 3").unwrap()..].contains("### model-b"));
+    Ok(())
+}
+
+/// A critic that hands out the given scores in turn
+struct ScoreSequenceProvider {
+    scores: std::sync::Mutex<Vec<&'static str>>,
+}
+
+#[async_trait::async_trait]
+impl AIProvider for ScoreSequenceProvider {
+    async fn generate(&self, _prompt: &str) -> Result<(String, String), StoryChainError> {
+        let score = self.scores.lock().unwrap().remove(0);
+        Ok(("Critic reasoning".to_string(), format!("SCORE: {}\nNOTES: Scored {}", score, score)))
+    }
+}
+
+#[tokio::test]
+async fn test_quality_gate_regenerates_low_scores() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("Opening".to_string(), "Reasoning".to_string());
+    chain.settings.quality_gate = Some(QualityGate { min_score: 6.0, max_retries: 2 });
+    chain.settings.track_setups = true;
+    let critic = ScoreSequenceProvider { scores: std::sync::Mutex::new(vec!["3", "7.5/10", "2", "4", "5"]) };
+    let writer = FixedResponseProvider("A door creaks open.\nPLANTED: a rusty key");
+
+    let ids = chain.generate_with_subplots("root", &writer, Some(&critic), None, 1, 2).await?;
+    assert_eq!(ids, vec!["node_1".to_string()]);
+    assert_eq!(chain.nodes.len(), 2);
+    assert_eq!(chain.setups.len(), 1, "setups of discarded attempts are dropped");
+    let node = &chain.nodes["node_1"];
+    assert_eq!(node.metadata["quality_score"], "7.5");
+    assert_eq!(node.metadata["quality_attempts"], "2");
+    assert_eq!(node.metadata["quality_scores"], "3.0, 7.5");
+    assert!(!node.metadata.contains_key("quality_below_threshold"));
+
+    let ids = chain.generate_with_quality_gate("node_1", &writer, &critic, None, 2, 2).await?;
+    let node = &chain.nodes[&ids[0]];
+    assert_eq!(node.metadata["quality_attempts"], "3");
+    assert_eq!(node.metadata["quality_below_threshold"], "true");
+    assert_eq!(chain.nodes_in_order().len(), 3);
+
+    let report = chain.quality_report_markdown();
+    assert!(report.contains("| node_1 | 7.5 | 2 | yes |"));
+    assert!(report.contains("| node_2 | 5.0 | 3 | no |"));
+    assert!(report.contains("2 scenes scored, 3 regenerations, 1 below the threshold."));
+    Ok(())
+}
+
+/// Answers a single HTTP request with the given body and returns the request body
+async fn serve_once(response: String) -> (String, tokio::task::JoinHandle<serde_json::Value>) {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let handle = tokio::spawn(async move {
+        let (mut socket, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        let mut buffer = [0u8; 4096];
+        loop {
+            let read = socket.read(&mut buffer).await.unwrap();
+            request.extend_from_slice(&buffer[..read]);
+            let text = String::from_utf8_lossy(&request).to_string();
+            if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                let length: usize = head
+                    .lines()
+                    .find_map(|l| l.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse().unwrap()))
+                    .unwrap_or(0);
+                if body.len() >= length {
+                    let reply = &response;
+                    let http = format!(
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                        reply.len(),
+                        reply
+                    );
+                    socket.write_all(http.as_bytes()).await.unwrap();
+                    return serde_json::from_str(body).unwrap();
+                }
+            }
+        }
+    });
+    (format!("http://{}", address), handle)
+}
+
+#[tokio::test]
+async fn test_ollama_http_provider() -> Result<(), StoryChainError> {
+    let (url, request) = serve_once(serde_json::json!({
+        "response": "<think>Plan the scene</think>\nThe tide came in.",
+        "done": true
+    }).to_string())
+    .await;
+    let provider = OllamaHttpProvider::new("test-model".to_string())
+        .with_base_url(&url)
+        .with_keep_alive("10m".to_string())
+        .with_options(GenerationConfig { temperature: Some(0.7), num_ctx: Some(8192), ..GenerationConfig::default() });
+
+    let (reasoning, content) = provider.generate_with_system("Write in second person.", "Begin the story.").await?;
+    assert_eq!(reasoning, "Plan the scene");
+    assert_eq!(content, "The tide came in.");
+
+    let body = request.await.unwrap();
+    assert_eq!(body["model"], "test-model");
+    assert_eq!(body["prompt"], "Begin the story.");
+    assert_eq!(body["system"], "Write in second person.");
+    assert_eq!(body["stream"], false);
+    assert_eq!(body["keep_alive"], "10m");
+    assert_eq!(body["options"], serde_json::json!({ "temperature": 0.7, "num_ctx": 8192 }));
+
+    // Reasoning returned in a separate field is rejoined
+    let (url, _) = serve_once(serde_json::json!({ "response": "Waves.", "thinking": "Short scene" }).to_string()).await;
+    let provider = OllamaHttpProvider::new("test-model".to_string()).with_base_url(&url);
+    assert_eq!(provider.generate("Continue.").await?, ("Short scene".to_string(), "Waves.".to_string()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_generation_config() -> Result<(), StoryChainError> {
+    let (url, request) = serve_once(serde_json::json!({ "response": "<think>Birds</think>\nThe gulls scattered.", "done": true }).to_string()).await;
+    let provider = OllamaHttpProvider::new("test-model".to_string())
+        .with_base_url(&url)
+        .with_options(GenerationConfig { temperature: Some(0.7), num_ctx: Some(8192), ..GenerationConfig::default() });
+
+    let mut chain = StoryChain::new("The tide came in.".to_string(), "Opening".to_string());
+    chain.settings.generation = GenerationConfig {
+        temperature: Some(1.1),
+        max_tokens: Some(600),
+        stop: vec!["THE END".to_string()],
+        ..GenerationConfig::default()
+    };
+    let root_id = chain.nodes.keys().next().unwrap().clone();
+    let new_ids = chain.generate_next_nodes(&root_id, &provider, None, 1, 2).await?;
+    assert_eq!(chain.nodes[&new_ids[0]].content, "The gulls scattered.");
+
+    // The chain's parameters override the provider's defaults, which fill the rest
+    let body = request.await.unwrap();
+    assert_eq!(
+        body["options"],
+        serde_json::json!({ "temperature": 1.1, "num_predict": 600, "stop": ["THE END"], "num_ctx": 8192 })
+    );
+
+    // Parameters survive a save and load
+    let json = serde_json::to_string(&chain)?;
+    let loaded: StoryChain = serde_json::from_str(&json)?;
+    assert_eq!(loaded.settings.generation, chain.settings.generation);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_provider_config() -> Result<(), StoryChainError> {
+    let (url, request) = serve_once(serde_json::json!({ "response": "<think>Plan</think>\nThe tide came in." }).to_string()).await;
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("storychain.toml");
+    std::fs::write(
+        &path,
+        format!(
+            "[provider]\ntype = \"ollama-http\"\nmodel = \"test-model\"\nendpoint = \"{}\"\napi_key = \"secret\"\n\n\
+            [critic]\nmodel = \"critic-model\"\n\n[generation]\ntemperature = 0.8\nstop = [\"THE END\"]\n",
+            url
+        ),
+    )?;
+
+    let config = StoryChainConfig::load(&path)?;
+    assert_eq!(config.provider.kind, ProviderKind::OllamaHttp);
+    assert_eq!(config.provider.api_key().as_deref(), Some("secret"));
+    let critic = config.critic.clone().unwrap();
+    assert_eq!(critic.kind, ProviderKind::Ollama);
+    assert_eq!(critic.model, "critic-model");
+    assert_eq!(config.generation.temperature, Some(0.8));
+    assert_eq!(config.generation.stop, vec!["THE END".to_string()]);
+
+    let provider = ProviderFactory::from_config(&config.provider)?;
+    assert_eq!(provider.generate("Begin.").await?.1, "The tide came in.");
+    assert_eq!(request.await.unwrap()["model"], "test-model");
+
+    // A missing default file falls back to the defaults; bad files are reported
+    let defaults = StoryChainConfig::load_or_default(temp_dir.path().join("missing.toml"))?;
+    assert_eq!(defaults.provider, ProviderConfig::default());
+    assert!(StoryChainConfig::from_toml("[provider]\ntype = \"carrier-pigeon\"\n").is_err());
+    assert!(ProviderFactory::from_config(&ProviderConfig { model: String::new(), ..ProviderConfig::default() }).is_err());
+    Ok(())
+}
+
+struct JsonChatModel;
+
+#[async_trait::async_trait]
+impl ChatModel for JsonChatModel {
+    async fn chat(&self, _messages: &[ChatMessage]) -> Result<String, StoryChainError> {
+        Ok("```json\n{\"reasoning\": \"Raise the stakes.\", \"content\": \"The vault door groaned.\"}\n```".to_string())
+    }
+}
+
+#[tokio::test]
+async fn test_response_parsers() -> Result<(), StoryChainError> {
+    // Every built-in format splits its own shape of response
+    let think = ResponseFormat::ThinkTags.parser().parse("<think>Plan</think>\nThe scene.")?;
+    assert_eq!(think, ("Plan".to_string(), "The scene.".to_string()));
+    let json = ResponseFormat::Json.parser().parse("Sure! {\"reasoning\": \"Plan\", \"content\": \"The scene.\"}")?;
+    assert_eq!(json, ("Plan".to_string(), "The scene.".to_string()));
+    let markdown = ResponseFormat::Markdown.parser().parse("## Reasoning\nPlan\n\n## Scene:\nThe scene.\n\nIt ends.")?;
+    assert_eq!(markdown, ("Plan".to_string(), "The scene.\n\nIt ends.".to_string()));
+    let plain = ResponseFormat::NoReasoning.parser().parse("  The scene.  ")?;
+    assert_eq!(plain, (String::new(), "The scene.".to_string()));
+    let stray = ResponseFormat::NoReasoning.parser().parse("<think>Plan</think>The scene.")?;
+    assert_eq!(stray.1, "The scene.");
+
+    // Responses in another shape are rejected rather than misread
+    assert!(ResponseFormat::ThinkTags.parser().parse("The scene.").is_err());
+    assert!(matches!(
+        ResponseFormat::Json.parser().parse("{\"reasoning\": \"Plan\"}"),
+        Err(StoryChainError::InvalidReasoningFormat(_))
+    ));
+    assert!(ResponseFormat::Markdown.parser().parse("## Reasoning\nPlan").is_err());
+    assert!(ResponseFormat::NoReasoning.parser().parse("   ").is_err());
+
+    // The format is chosen by name or in the provider configuration
+    assert_eq!("no-reasoning".parse::<ResponseFormat>(), Ok(ResponseFormat::NoReasoning));
+    assert!("xml".parse::<ResponseFormat>().is_err());
+    let config = StoryChainConfig::from_toml("[provider]\nresponse_format = \"markdown\"\n")?;
+    assert_eq!(config.provider.response_format, ResponseFormat::Markdown);
+    assert!(!toml::to_string(&ProviderConfig::default()).unwrap().contains("response_format"));
+
+    // Providers use the parser they are given
+    let provider = ChatProvider::new(JsonChatModel).with_parser(ResponseFormat::Json.parser());
+    let mut chain = StoryChain::new("The crew gathered.".to_string(), "Test reasoning".to_string());
+    let new_ids = chain.generate_next_nodes("root", &provider, Some("A heist."), 1, 2).await?;
+    assert_eq!(chain.nodes[&new_ids[0]].content, "The vault door groaned.");
+    assert_eq!(chain.nodes[&new_ids[0]].reasoning, "Raise the stakes.");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_context_budget() -> Result<(), StoryChainError> {
+    assert_eq!(count_tokens(""), 0);
+    assert_eq!(count_tokens("The tide came in."), 5);
+    assert_eq!(truncate_to_tokens("one two three four five six", 6, Keep::Start), "one [...]");
+    assert_eq!(truncate_to_tokens("one two three four five six", 6, Keep::End), "[...] six");
+    assert_eq!(truncate_to_tokens("one two", 10, Keep::End), "one two");
+
+    let long_scene = format!("{} The final line.", "The storm raged on. ".repeat(500));
+    let mut chain = StoryChain::new(long_scene.clone(), "Lengthy reasoning. ".repeat(200));
+    chain.settings.max_context_tokens = Some(900);
+    chain.settings.generation.max_tokens = Some(300);
+    let root_id = chain.nodes.keys().next().unwrap().clone();
+
+    // The echoed prompt fits the budget minus the response, keeping the end of the previous scene
+    let new_ids = chain.generate_next_nodes(&root_id, &EchoProvider, Some("A lighthouse keeper"), 1, 3).await?;
+    let prompt = &chain.nodes[&new_ids[0]].content;
+    assert!(count_tokens(prompt) <= 600, "prompt has {} tokens", count_tokens(prompt));
+    assert!(prompt.contains("The final line."));
+    assert!(prompt.contains("A lighthouse keeper"));
+    assert!(!prompt.contains("Lengthy reasoning. Lengthy reasoning."));
+
+    // Without a budget the prompt is sent whole
+    chain.settings.max_context_tokens = None;
+    let new_ids = chain.generate_next_nodes(&root_id, &EchoProvider, None, 1, 3).await?;
+    assert!(chain.nodes[&new_ids[0]].content.contains(&long_scene));
+    Ok(())
+}
+
+/// A provider that summarizes with a numbered summary and otherwise echoes the prompt
+struct SummaryProvider(std::sync::atomic::AtomicUsize);
+
+#[async_trait::async_trait]
+impl AIProvider for SummaryProvider {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        if prompt.contains("running summary") {
+            let count = self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+            assert_eq!(prompt.contains("Summary So Far:"), count > 1);
+            return Ok(("Reasoning".to_string(), format!("Summary {}", count)));
+        }
+        Ok(("Reasoning".to_string(), prompt.to_string()))
+    }
+}
+
+#[tokio::test]
+async fn test_rolling_summary() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("Opening scene".to_string(), "Opening reasoning".to_string());
+    chain.settings.memory = Some(RollingSummary { interval: 2, max_words: 100 });
+    let provider = SummaryProvider(std::sync::atomic::AtomicUsize::new(0));
+
+    let mut current = chain.nodes.keys().next().unwrap().clone();
+    let mut prompts = Vec::new();
+    for epoch in 1..=5 {
+        current = chain.generate_next_nodes(&current, &provider, None, epoch, 5).await?[0].clone();
+        prompts.push(chain.nodes[&current].content.clone());
+    }
+
+    // Scenes 1 and 2 continue from recent scenes only; the summary appears
+    // once two scenes precede the one being continued
+    assert!(!prompts[0].contains("Story So Far"));
+    assert!(!prompts[1].contains("Story So Far"));
+    assert!(prompts[2].contains("Story So Far:\nSummary 1"));
+    assert!(prompts[3].contains("Story So Far:\nSummary 1"));
+    assert!(prompts[4].contains("Story So Far:\nSummary 2"));
+    assert_eq!(provider.0.load(std::sync::atomic::Ordering::SeqCst), 2);
+    assert_eq!(chain.metadata["summary"], "Summary 2");
+    assert!(chain.unsummarized_scenes(&current).len() < 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_branching_generation() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("Opening scene".to_string(), "Opening reasoning".to_string()).with_branch_ratio(2);
+
+    // Every node of the frontier gets two alternatives
+    let mut frontier = vec![chain.root_node_id.clone()];
+    for epoch in 1..=2 {
+        let mut next = Vec::new();
+        for id in &frontier {
+            let new_ids = chain.generate_next_nodes(id, &EchoProvider, None, epoch, 2).await?;
+            assert_eq!(new_ids.len(), 2);
+            next.extend(new_ids);
+        }
+        frontier = next;
+    }
+    assert_eq!(chain.nodes.len(), 7);
+    assert_eq!(chain.nodes["root"].successors.len(), 2);
+    assert_eq!(chain.leaf_ids(), frontier);
+    assert_eq!(chain.storylines().len(), 4);
+    assert_eq!(chain.nodes_in_order().len(), 3);
+
+    // The second alternative is steered away from the first
+    let second = &chain.nodes[&chain.nodes["root"].successors[1]];
+    assert!(second.content.contains("alternative continuation 2"));
+    assert!(!chain.nodes[&chain.nodes["root"].successors[0]].content.contains("alternative continuation"));
+    let path: Vec<&str> = chain.path_to(&frontier[3]).iter().map(|n| n.id.as_str()).collect();
+    assert_eq!(path, vec!["root", second.id.as_str(), frontier[3].as_str()]);
+
+    // The markdown export shows the main storyline and then each branch
+    let temp_dir = tempfile::tempdir()?;
+    let markdown_path = temp_dir.path().join("story.md");
+    chain.export_to_markdown(markdown_path.to_str().unwrap())?;
+    let markdown = std::fs::read_to_string(&markdown_path)?;
+    assert_eq!(markdown.matches("## Scene ").count(), 3);
+    assert!(markdown.contains("## Alternative Branches"));
+    assert!(markdown.contains("### Branch 1 (after Scene 2)"));
+    assert!(markdown.contains("### Branch 2 (after Scene 1)"));
+    assert!(markdown.contains(&format!("### Branch 3 (after {})", second.id)));
+
+    // Chains saved before branching still load
+    let legacy = r#"{"nodes": {
+        "root": {"id": "root", "content": "A", "reasoning": "R", "predecessor": null, "successor": "node_1", "metadata": {}},
+        "node_1": {"id": "node_1", "content": "B", "reasoning": "R", "predecessor": "root", "successor": null, "metadata": {}}
+    }, "root_node_id": "root"}"#;
+    let loaded: StoryChain = serde_json::from_str(legacy)?;
+    assert_eq!(loaded.branch_ratio, 1);
+    assert_eq!(loaded.nodes["root"].successors, vec!["node_1".to_string()]);
+    assert!(loaded.nodes["root"].predecessors.is_empty());
+    assert_eq!(loaded.nodes_in_order().len(), 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_merge_nodes() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("Opening scene".to_string(), "Opening reasoning".to_string()).with_branch_ratio(2);
+    let branches = chain.generate_next_nodes("root", &FixedResponseProvider("<think>R</think>\nBranch scene"), None, 1, 3).await?;
+    chain.nodes.get_mut(&branches[0]).unwrap().content = "The heroes take the mountain pass.".to_string();
+    chain.nodes.get_mut(&branches[1]).unwrap().content = "The heroes sail around the cape.".to_string();
+
+    let merged = chain.merge_nodes(&[&branches[0], &branches[1]], &EchoProvider).await?;
+    let node = &chain.nodes[&merged];
+    assert_eq!(node.predecessors, branches);
+    assert_eq!(node.metadata["merged_from"], branches.join(", "));
+    for branch in &branches {
+        assert_eq!(chain.nodes[branch].successors, vec![merged.clone()]);
+    }
+
+    // The prompt holds the shared history and both branches
+    assert!(node.content.contains("Scene Before the Split:\nOpening scene"));
+    assert!(node.content.contains("Branch 1:\nThe heroes take the mountain pass."));
+    assert!(node.content.contains("Branch 2:\nThe heroes sail around the cape."));
+
+    // Both storylines run through the merged scene, which the main storyline continues
+    let lines = chain.storylines();
+    assert_eq!(lines.len(), 2);
+    assert!(lines.iter().all(|line| line.last().unwrap().id == merged));
+    assert_eq!(chain.nodes_in_order().len(), 3);
+    assert!(chain.alternative_branches_markdown().contains("*Rejoins at Scene 3.*"));
+
+    // Invalid merges are rejected
+    assert!(chain.merge_nodes(&[&branches[0]], &EchoProvider).await.is_err());
+    assert!(chain.merge_nodes(&[&branches[0], "missing"], &EchoProvider).await.is_err());
+    assert!(chain.merge_nodes(&["root", &branches[0]], &EchoProvider).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_regenerate_node() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("Opening scene".to_string(), "Opening reasoning".to_string());
+    let first = chain.generate_next_nodes("root", &FixedResponseProvider("A weak scene"), None, 1, 2).await?[0].clone();
+    let second = chain.generate_next_nodes(&first, &FixedResponseProvider("Later scene"), None, 2, 2).await?[0].clone();
+    chain.nodes.get_mut(&first).unwrap().metadata.insert("mood".to_string(), "flat".to_string());
+
+    chain.regenerate_node(&first, &FixedResponseProvider("A stronger scene"), None).await?;
+    let node = &chain.nodes[&first];
+    assert_eq!(node.content, "A stronger scene");
+    assert!(!node.metadata.contains_key("mood"));
+
+    // The node keeps its place and the scenes after it are untouched
+    assert_eq!(chain.nodes.len(), 3);
+    assert_eq!(chain.nodes["root"].successors, vec![first.clone()]);
+    assert_eq!(node.predecessors, vec!["root".to_string()]);
+    assert_eq!(node.successors, vec![second.clone()]);
+    assert_eq!(chain.nodes[&second].content, "Later scene");
+
+    // Each replaced version is archived, oldest first
+    chain.regenerate_node(&first, &FixedResponseProvider("The best scene"), None).await?;
+    let versions = chain.previous_versions(&first);
+    assert_eq!(versions.len(), 2);
+    assert_eq!(versions[0].content, "A weak scene");
+    assert_eq!(versions[0].metadata["mood"], "flat");
+    assert_eq!(versions[1].content, "A stronger scene");
+
+    // The opening scene and unknown nodes cannot be regenerated
+    assert!(chain.regenerate_node("root", &MockAIProvider, None).await.is_err());
+    assert!(chain.regenerate_node("missing", &MockAIProvider, None).await.is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_chain_editing() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("Opening scene".to_string(), "Opening reasoning".to_string());
+    let first = chain.generate_next_nodes("root", &FixedResponseProvider("First scene"), None, 1, 2).await?[0].clone();
+    let second = chain.generate_next_nodes(&first, &FixedResponseProvider("Second scene"), None, 2, 2).await?[0].clone();
+    let contents = |chain: &StoryChain| chain.nodes_in_order().iter().map(|n| n.content.clone()).collect::<Vec<_>>();
+
+    // Inserted scenes sit between the node and its successors
+    let inserted = chain.insert_node_after(&first, "Interlude".to_string(), "Added by hand".to_string())?;
+    assert_eq!(contents(&chain), vec!["Opening scene", "First scene", "Interlude", "Second scene"]);
+    assert_eq!(chain.nodes[&second].predecessors, vec![inserted.clone()]);
+
+    // Removing a scene links its neighbours, and its ID is not reused
+    chain.reading_order = vec![first.clone(), "root".to_string()];
+    let removed = chain.remove_node(&first)?;
+    assert_eq!(removed.content, "First scene");
+    assert_eq!(contents(&chain), vec!["Opening scene", "Interlude", "Second scene"]);
+    assert_eq!(chain.nodes[&inserted].predecessors, vec!["root".to_string()]);
+    assert_eq!(chain.reading_order, vec!["root".to_string()]);
+    let replacement = chain.insert_node_after(&second, "Epilogue".to_string(), String::new())?;
+    assert_ne!(replacement, inserted);
+    assert_eq!(chain.nodes.len(), 4);
+
+    // A separately generated chain is spliced in with new IDs
+    let mut detour = StoryChain::new("Detour opening".to_string(), "R".to_string());
+    detour.generate_next_nodes("root", &FixedResponseProvider("Detour end"), None, 1, 1).await?;
+    let spliced = chain.splice_chain("root", detour)?;
+    assert_eq!(spliced.len(), 2);
+    assert!(spliced.iter().all(|id| id != "root" && id != &inserted));
+    assert_eq!(
+        contents(&chain),
+        vec!["Opening scene", "Detour opening", "Detour end", "Interlude", "Second scene", "Epilogue"]
+    );
+    assert_eq!(chain.nodes[&inserted].predecessors, vec![spliced[1].clone()]);
+
+    // Invalid edits are rejected
+    assert!(chain.remove_node("root").is_err());
+    assert!(chain.remove_node("missing").is_err());
+    assert!(chain.insert_node_after("missing", String::new(), String::new()).is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_load_from_file() -> Result<(), StoryChainError> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().join("story.json");
+    let path = path.to_str().unwrap();
+
+    let mut chain = StoryChain::new("Opening scene".to_string(), "Opening reasoning".to_string());
+    let first = chain.generate_next_nodes("root", &MockAIProvider, None, 1, 3).await?[0].clone();
+    chain.generate_next_nodes(&first, &MockAIProvider, None, 2, 3).await?;
+    chain.settings.track_setups = true;
+    chain.export_to_file(path)?;
+
+    // The loaded chain has the same scenes and settings and continues from its tail
+    let loaded = StoryChain::load_from_file(path)?;
+    assert_eq!(loaded.nodes.len(), 3);
+    assert_eq!(loaded.root_node_id, "root");
+    assert!(loaded.settings.track_setups);
+    assert_eq!(loaded.leaf_ids(), chain.leaf_ids());
+    assert_eq!(loaded.completed_epochs(), 2);
+
+    // The recorded epoch count takes precedence over the chain's length
+    let mut loaded = loaded;
+    loaded.metadata.insert(EPOCHS_COMPLETED_KEY.to_string(), "4".to_string());
+    assert_eq!(loaded.completed_epochs(), 4);
+
+    // Missing files and chains without their root are rejected
+    assert!(StoryChain::load_from_file(temp_dir.path().join("missing.json").to_str().unwrap()).is_err());
+    loaded.nodes.remove("root");
+    loaded.export_to_file(path)?;
+    assert!(StoryChain::load_from_file(path).is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_streaming_provider() -> Result<(), StoryChainError> {
+    let lines = [
+        serde_json::json!({ "thinking": "Open ", "response": "", "done": false }),
+        serde_json::json!({ "thinking": "quietly", "response": "", "done": false }),
+        serde_json::json!({ "response": "The tide ", "done": false }),
+        serde_json::json!({ "response": "came in.", "done": true }),
+    ];
+    let body: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+    let (url, request) = serve_once(body.join("\n")).await;
+
+    let tokens = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = tokens.clone();
+    let provider = StreamingProvider::new(OllamaHttpProvider::new("test-model".to_string()).with_base_url(&url), move |t| {
+        sink.lock().unwrap().push(t.to_string())
+    });
+    let (reasoning, content) = provider.generate("Begin.").await?;
+    assert_eq!(reasoning, "Open quietly");
+    assert_eq!(content, "The tide came in.");
+    assert_eq!(*tokens.lock().unwrap(), vec!["Open ", "quietly", "The tide ", "came in.", "\n\n"]);
+    assert_eq!(request.await.unwrap()["stream"], true);
+
+    // Providers that cannot stream send their content as a single piece
+    let tokens = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = tokens.clone();
+    let provider = StreamingProvider::new(MockAIProvider, move |t| sink.lock().unwrap().push(t.to_string()));
+    let mut chain = StoryChain::new("Opening".to_string(), "Reasoning".to_string());
+    let ids = chain.generate_next_nodes("root", &provider, None, 1, 1).await?;
+    assert_eq!(*tokens.lock().unwrap(), vec![chain.nodes[&ids[0]].content.clone(), "\n\n".to_string()]);
+    Ok(())
+}
+
+/// A provider that fails with the given errors before answering
+struct FlakyProvider {
+    failures: std::sync::Mutex<Vec<StoryChainError>>,
+}
+
+#[async_trait::async_trait]
+impl AIProvider for FlakyProvider {
+    async fn generate(&self, _prompt: &str) -> Result<(String, String), StoryChainError> {
+        match self.failures.lock().unwrap().pop() {
+            Some(error) => Err(error),
+            None => Ok(("Reasoning".to_string(), "Recovered".to_string())),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_retrying_provider() -> Result<(), StoryChainError> {
+    let flaky = |failures: Vec<StoryChainError>| FlakyProvider {
+        failures: std::sync::Mutex::new(failures),
+    };
+
+    // Transient errors are retried until the request succeeds
+    let provider = RetryingProvider::new(flaky(vec![
+        StoryChainError::InvalidReasoningFormat("no think tags".to_string()),
+        StoryChainError::AIServerError("connection reset".to_string()),
+    ]))
+    .with_backoff(Backoff::Fixed(std::time::Duration::ZERO));
+    assert_eq!(provider.generate("Prompt").await?.1, "Recovered");
+
+    // Attempts are limited
+    let inner = flaky((0..5).map(|_| StoryChainError::AIServerError("down".to_string())).collect());
+    let provider = RetryingProvider::new(inner).with_max_attempts(2).with_backoff(Backoff::Fixed(std::time::Duration::ZERO));
+    assert!(matches!(provider.generate("Prompt").await, Err(StoryChainError::AIServerError(_))));
+
+    // Permanent errors fail immediately
+    let provider = RetryingProvider::new(flaky(vec![StoryChainError::TemplateError("bad".to_string())]));
+    assert!(matches!(provider.generate("Prompt").await, Err(StoryChainError::TemplateError(_))));
+
+    let backoff = Backoff::Exponential { initial: std::time::Duration::from_secs(1), max: std::time::Duration::from_secs(5) };
+    assert_eq!(backoff.delay(1).as_secs(), 1);
+    assert_eq!(backoff.delay(3).as_secs(), 4);
+    assert_eq!(backoff.delay(10).as_secs(), 5);
+    Ok(())
+}
+
+/// A provider that numbers its responses
+struct CountingProvider(std::sync::atomic::AtomicUsize);
+
+#[async_trait::async_trait]
+impl AIProvider for CountingProvider {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        let n = self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+        Ok(("Reasoning".to_string(), format!("Response {} to {}", n, prompt)))
+    }
+}
+
+#[tokio::test]
+async fn test_caching_provider() -> Result<(), StoryChainError> {
+    let dir = tempfile::tempdir()?;
+    let provider = CachingProvider::new(CountingProvider(Default::default()), dir.path(), "model-a");
+
+    assert_eq!(provider.generate("Prompt").await?.1, "Response 1 to Prompt");
+    let rerun = CachingProvider::new(CountingProvider(Default::default()), dir.path(), "model-a");
+    assert_eq!(rerun.generate("Prompt").await?.1, "Response 1 to Prompt");
+    assert_eq!(provider.generate("Other").await?.1, "Response 2 to Other");
+    assert_eq!(provider.generate_with_system("Be terse.", "Prompt").await?.1, "Response 3 to Be terse.\n\nPrompt");
+
+    // Invalidated entries are regenerated
+    assert!(provider.invalidate(None, "Prompt", &GenerationConfig::default())?);
+    assert!(!provider.invalidate(None, "Prompt", &GenerationConfig::default())?);
+    let provider = CachingProvider::new(CountingProvider(std::sync::atomic::AtomicUsize::new(3)), dir.path(), "model-a");
+    assert_eq!(provider.generate("Prompt").await?.1, "Response 4 to Prompt");
+
+    // Responses survive the provider but are not shared across namespaces
+    let provider = CachingProvider::new(CountingProvider(Default::default()), dir.path(), "model-a");
+    assert_eq!(provider.generate("Prompt").await?.1, "Response 4 to Prompt");
+    let other = CachingProvider::new(CountingProvider(Default::default()), dir.path(), "model-b");
+    assert_eq!(other.generate("Prompt").await?.1, "Response 1 to Prompt");
+
+    // Expired entries are regenerated
+    let expiring = CachingProvider::new(CountingProvider(Default::default()), dir.path(), "model-a")
+        .with_max_age(chrono::Duration::seconds(-1));
+    assert_eq!(expiring.generate("Prompt").await?.1, "Response 1 to Prompt");
+
+    assert_eq!(clear_cache(dir.path())?, 4);
+    assert_eq!(provider.generate("Prompt").await?.1, "Response 1 to Prompt");
+
+    // Invalidating a request removes every attempt at it
+    let provider = CachingProvider::new(CountingProvider(Default::default()), dir.path(), "model-a");
+    provider.generate("Repeated").await?;
+    provider.generate("Repeated").await?;
+    assert!(provider.invalidate(None, "Repeated", &GenerationConfig::default())?);
+    assert!(!provider.invalidate(None, "Repeated", &GenerationConfig::default())?);
+    let rerun = CachingProvider::new(CountingProvider(std::sync::atomic::AtomicUsize::new(2)), dir.path(), "model-a");
+    assert_eq!(rerun.generate("Repeated").await?.1, "Response 3 to Repeated");
+    assert_eq!(rerun.generate("Repeated").await?.1, "Response 4 to Repeated");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_caching_provider_regenerations() -> Result<(), StoryChainError> {
+    let dir = tempfile::tempdir()?;
+    let writer = CachingProvider::new(CountingProvider(Default::default()), dir.path(), "model-a");
+    let critic = ScoreSequenceProvider { scores: std::sync::Mutex::new(vec!["3", "7.5"]) };
+    let mut chain = StoryChain::new("Opening".to_string(), "Reasoning".to_string());
+    chain.settings.quality_gate = Some(QualityGate { min_score: 6.0, max_retries: 2 });
+
+    // The quality gate's retry reaches the model instead of the rejected cached scene
+    let ids = chain.generate_with_quality_gate("root", &writer, &critic, None, 1, 1).await?;
+    assert!(chain.nodes[&ids[0]].content.starts_with("Response 2 to"));
+    assert_eq!(chain.nodes[&ids[0]].metadata["quality_attempts"], "2");
+
+    // A later run replays both attempts from the cache
+    let rerun = CachingProvider::new(FixedResponseProvider("A new scene."), dir.path(), "model-a");
+    let critic = ScoreSequenceProvider { scores: std::sync::Mutex::new(vec!["3", "7.5"]) };
+    let mut replayed = StoryChain::new("Opening".to_string(), "Reasoning".to_string());
+    replayed.settings.quality_gate = Some(QualityGate { min_score: 6.0, max_retries: 2 });
+    let ids = replayed.generate_with_quality_gate("root", &rerun, &critic, None, 1, 1).await?;
+    assert_eq!(replayed.nodes[&ids[0]].content, chain.nodes[&ids[0]].content);
+
+    // Regenerating a scene in a new run skips the cache holding the scene it replaces
+    let rerun = CachingProvider::new(FixedResponseProvider("A new scene."), dir.path(), "model-a");
+    replayed.regenerate_node(&ids[0], &rerun, None).await?;
+    assert_eq!(replayed.nodes[&ids[0]].content, "A new scene.");
+    assert_eq!(replayed.previous_versions(&ids[0]).len(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_record_and_replay() -> Result<(), StoryChainError> {
+    let dir = tempfile::tempdir()?;
+    let recording = dir.path().join("recording.json");
+
+    // Record a run
+    let recorder = RecordingProvider::new(CountingProvider(Default::default()), &recording);
+    let mut recorded = StoryChain::new("Opening".to_string(), "Reasoning".to_string());
+    recorded.settings.system_prompt = Some("Be terse.".to_string());
+    let mut current = "root".to_string();
+    for epoch in 1..=2 {
+        current = recorded.generate_next_nodes(&current, &recorder, Some("A premise"), epoch, 2).await?[0].clone();
+    }
+    assert_eq!(recorder.exchanges().len(), 2);
+    assert_eq!(recorder.exchanges()[0].system_prompt.as_deref(), Some("Be terse."));
+
+    // Replaying the same pipeline reproduces it exactly
+    let replay = ReplayProvider::from_file(&recording)?;
+    let mut replayed = StoryChain::new("Opening".to_string(), "Reasoning".to_string());
+    replayed.settings.system_prompt = Some("Be terse.".to_string());
+    let mut current = "root".to_string();
+    for epoch in 1..=2 {
+        current = replayed.generate_next_nodes(&current, &replay, Some("A premise"), epoch, 2).await?[0].clone();
+    }
+    assert_eq!(replay.remaining(), 0);
+    for (id, node) in &recorded.nodes {
+        assert_eq!(node.content, replayed.nodes[id].content);
+    }
+    assert!(matches!(replay.generate("One more").await, Err(StoryChainError::AIServerError(_))));
+
+    // A pipeline whose prompts changed fails instead of silently diverging
+    let replay = ReplayProvider::from_file(&recording)?;
+    let mut changed = StoryChain::new("Opening".to_string(), "Reasoning".to_string());
+    changed.settings.system_prompt = Some("Be terse.".to_string());
+    let error = changed.generate_next_nodes("root", &replay, Some("Another premise"), 1, 2).await.unwrap_err();
+    assert!(error.to_string().contains("differs from the recording"));
+
+    // Without the prompt check, responses are replayed in order
+    let replay = ReplayProvider::from_file(&recording)?.with_prompt_check(false);
+    assert_eq!(replay.generate("Anything").await?.1, recorder.exchanges()[0].content);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_scene_metadata_enrichment() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("Alice waited in the garden.".to_string(), "Test reasoning".to_string());
+    chain.character_registry = CharacterRegistry::from_premise("- name: \"Alice Moreau\"\n- name: \"Bob Hale\"\n");
+    chain.settings.enrichment = Some(EnrichmentMode::Heuristic);
+
+    let provider = FixedResponseProvider(
+        "At midnight Bob crept into the Iron Gate Tavern. Fear made him tremble; somewhere a scream \
+        split the night, and he thought of Moreau and the danger she had warned him about.",
+    );
+    let root = chain.root_node_id.clone();
+    let new_id = chain.generate_next_nodes(&root, &provider, None, 1, 1).await?.remove(0);
+
+    let metadata = chain.scene_metadata(&new_id).unwrap();
+    assert_eq!(metadata.characters, vec!["Alice Moreau".to_string(), "Bob Hale".to_string()]);
+    assert_eq!(metadata.location.as_deref(), Some("the Iron Gate Tavern"));
+    assert_eq!(metadata.time.as_deref(), Some("night"));
+    assert_eq!(metadata.mood.as_deref(), Some("tense"));
+
+    // The opening scene is only enriched on request
+    assert_eq!(chain.scene_metadata(&root), Some(SceneMetadata::default()));
+    chain.enrich_node(&root, EnrichmentMode::Heuristic, &provider).await?;
+    let featuring: Vec<&str> = chain.nodes_featuring("Alice").iter().map(|n| n.id.as_str()).collect();
+    assert_eq!(featuring, vec![root.as_str(), new_id.as_str()]);
+    assert_eq!(chain.nodes_featuring("Bob Hale").len(), 1);
+    assert_eq!(chain.nodes_at_location("iron gate").len(), 1);
+    assert_eq!(chain.nodes_with_mood("Tense")[0].id, new_id);
+
+    // The model's answer is read from labeled fields
+    let extractor = FixedResponseProvider(
+        "CHARACTERS: Alice, Bob\nLOCATION: The lighthouse\nTIME: The next morning\nMOOD: Hopeful",
+    );
+    let extracted = chain.enrich_node(&root, EnrichmentMode::Ai, &extractor).await?;
+    assert_eq!(extracted.characters, vec!["Alice".to_string(), "Bob".to_string()]);
+    assert_eq!(chain.nodes[&root].metadata[LOCATION_KEY], "The lighthouse");
+    assert_eq!(chain.nodes[&root].metadata[MOOD_KEY], "hopeful");
+    assert_eq!("AI".parse::<EnrichmentMode>(), Ok(EnrichmentMode::Ai));
+    Ok(())
+}
+
+/// A provider that writes a twelve-word scene, expands scenes to twenty
+/// words, and trims them to eight
+struct LengthProvider;
+
+#[async_trait::async_trait]
+impl AIProvider for LengthProvider {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        let words = if prompt.contains("Expand it") {
+            20
+        } else if prompt.contains("Trim it") {
+            8
+        } else {
+            12
+        };
+        Ok(("Reasoning".to_string(), vec!["word"; words].join(" ")))
+    }
+}
+
+#[tokio::test]
+async fn test_scene_length_targets() -> Result<(), StoryChainError> {
+    let target = LengthTarget { words: 10, tolerance: 0.3, max_adjustments: 2 };
+    assert_eq!(target.bounds(), (7, 13));
+    assert_eq!(target.miss(12), None);
+    assert_eq!(target.miss(20), Some(10));
+    assert_eq!(word_count("  one two\nthree "), 3);
+
+    // Scenes within the tolerance are kept as written
+    let mut chain = StoryChain::new("Opening.".to_string(), "Opening".to_string());
+    chain.settings.length = Some(target);
+    let kept = chain.generate_next_nodes("root", &LengthProvider, None, 1, 3).await?.remove(0);
+    assert_eq!(chain.nodes[&kept].metadata[WORD_COUNT_KEY], "12");
+    assert!(!chain.nodes[&kept].metadata.contains_key(LENGTH_ADJUSTMENTS_KEY));
+
+    // Scenes that miss are adjusted until they fit or the adjustments run out
+    chain.settings.length = Some(LengthTarget { words: 20, tolerance: 0.1, max_adjustments: 2 });
+    let expanded = chain.generate_next_nodes(&kept, &LengthProvider, None, 2, 3).await?.remove(0);
+    assert_eq!(word_count(&chain.nodes[&expanded].content), 20);
+    assert_eq!(chain.nodes[&expanded].metadata[LENGTH_ADJUSTMENTS_KEY], "1");
+    chain.settings.length = Some(LengthTarget { words: 4, tolerance: 0.1, max_adjustments: 2 });
+    let trimmed = chain.generate_next_nodes(&expanded, &LengthProvider, None, 3, 3).await?.remove(0);
+    assert_eq!(chain.nodes[&trimmed].metadata[WORD_COUNT_KEY], "8");
+    assert_eq!(chain.nodes[&trimmed].metadata[LENGTH_ADJUSTMENTS_KEY], "2");
+
+    // The target reaches the prompt, along with a miss by the previous scene
+    let guidance = chain.length_guidance(&trimmed).unwrap();
+    assert!(guidance.starts_with("Write about 4 words"));
+    assert!(guidance.contains("ran 8 words, too long"));
+
+    // The target can be set in the configuration file
+    let config = StoryChainConfig::from_toml("[length]\nwords = 1200\n")?;
+    assert_eq!(config.length, Some(LengthTarget { words: 1200, ..LengthTarget::default() }));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_style_guide() -> Result<(), StoryChainError> {
+    let guide = StyleGuide::parse(
+        "VOICE: Close third person, wry\nTENSE: past\nBANNED WORDS: suddenly, delve\n\
+        EXAMPLES:\nThe rain had opinions about Marlowe.\n---\nShe counted the spoons twice.",
+    );
+    assert_eq!(guide.person, Some(Person::Third));
+    assert_eq!(guide.tense, Some(Tense::Past));
+    assert_eq!(guide.banned_words, vec!["suddenly".to_string(), "delve".to_string()]);
+    assert_eq!(guide.examples.len(), 2);
+    assert!(guide.check("She was tired. \"I am fine,\" she said, and he looked away.").is_empty());
+    let issues = guide.check("Suddenly I walk in. I look around and my heart is racing. I delve deeper.");
+    assert_eq!(issues.len(), 4, "{:?}", issues);
+    assert!(issues[0].contains("\"suddenly\" 1 time"));
+
+    // A style guide among the artifacts reaches every prompt, and new scenes are checked against it
+    let dir = tempfile::tempdir()?;
+    let mut artifact_manager = ArtifactManager::new(dir.path().to_str().unwrap());
+    artifact_manager.create_artifact(
+        "style".to_string(),
+        "VOICE: Close third person\nTENSE: past\nBANNED WORDS: suddenly".to_string(),
+        ArtifactType::StyleGuide,
+    )?;
+    let mut templates = PromptTemplates::default();
+    templates.add_artifacts(&artifact_manager);
+    assert!(templates.initial_prompt("A premise", &[])?.contains("Never use these words: suddenly"));
+
+    let mut chain = StoryChain::new("Opening.".to_string(), "Opening".to_string());
+    chain.prompts = templates;
+    let provider = FixedResponseProvider("Suddenly she was gone, and he was alone.");
+    let new_id = chain.generate_next_nodes("root", &provider, None, 1, 2).await?.remove(0);
+    assert_eq!(chain.nodes[&new_id].metadata[STYLE_ISSUES_KEY], "uses the banned word \"suddenly\" 1 time(s)");
+    let echoed = chain.generate_next_nodes(&new_id, &EchoProvider, None, 2, 2).await?.remove(0);
+    assert!(chain.nodes[&echoed].content.contains("Style Guide (follow it in every scene):\nNarrative voice: Close third person"));
+    Ok(())
+}
+
+/// A provider that swears until it is told its scene was rejected
+struct SwearingProvider;
+
+#[async_trait::async_trait]
+impl AIProvider for SwearingProvider {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        let scene = if prompt.contains("previous attempt was rejected") {
+            "The dragon sneezed politely."
+        } else {
+            "Damn, said the dragon, and it drew a knife."
+        };
+        Ok(("Reasoning".to_string(), scene.to_string()))
+    }
+}
+
+/// A filter objecting to any mention of dragons
+struct NoDragons;
+
+impl ContentFilter for NoDragons {
+    fn name(&self) -> &str {
+        "no dragons"
+    }
+
+    fn check(&self, content: &str) -> Vec<FilterMatch> {
+        content
+            .match_indices("dragon")
+            .map(|(start, m)| FilterMatch { start, end: start + m.len(), reason: "dragon".to_string() })
+            .collect()
+    }
+}
+
+#[tokio::test]
+async fn test_content_filters() -> Result<(), StoryChainError> {
+    let mut settings = ContentFilterSettings { rating: Some(ContentRating::G), ..ContentFilterSettings::default() };
+    settings.parse_list("# words\nsneezed\n/d[aeiou]mn/\n");
+    assert_eq!(settings.terms, vec!["sneezed".to_string()]);
+    assert_eq!(settings.patterns, vec!["d[aeiou]mn".to_string()]);
+    let filter = KeywordFilter::new(&settings)?;
+    let matches = filter.check("Damn it, a damn knife.");
+    assert_eq!(redact("Damn it, a damn knife.", &matches), "[redacted] it, a [redacted] [redacted].");
+    assert!(KeywordFilter::new(&ContentFilterSettings { patterns: vec!["(".to_string()], ..settings.clone() }).is_err());
+
+    // Rejected scenes are regenerated before they reach the chain
+    let mut chain = StoryChain::new("Once upon a time.".to_string(), "Opening".to_string());
+    chain.settings.content_filter = Some(ContentFilterSettings { rating: Some(ContentRating::G), ..ContentFilterSettings::default() });
+    let kept = chain.generate_next_nodes("root", &SwearingProvider, None, 1, 3).await?.remove(0);
+    assert_eq!(chain.nodes[&kept].content, "The dragon sneezed politely.");
+    assert_eq!(chain.nodes[&kept].metadata[FILTER_REJECTIONS_KEY], "1");
+    assert!(!chain.nodes[&kept].metadata.contains_key(FILTER_MATCHES_KEY));
+
+    // Redaction keeps the scene, and custom filters run after the built-in one
+    chain.settings.content_filter = Some(ContentFilterSettings {
+        rating: Some(ContentRating::G),
+        action: FilterAction::Redact,
+        ..ContentFilterSettings::default()
+    });
+    chain.content_filters.push(Arc::new(NoDragons));
+    let redacted = chain.generate_next_nodes(&kept, &SwearingProvider, None, 2, 3).await?.remove(0);
+    assert_eq!(chain.nodes[&redacted].content, "[redacted], said the [redacted], and it drew a [redacted].");
+    assert_eq!(chain.nodes[&redacted].metadata[FILTER_MATCHES_KEY], "damn, dragon, knife");
+
+    // Scenes already in the chain can be screened too
+    chain.screen_node("root", &SwearingProvider, "Write an opening").await?;
+    assert_eq!(chain.nodes["root"].content, "Once upon a time.");
+    assert_eq!("redact".parse::<FilterAction>(), Ok(FilterAction::Redact));
+    Ok(())
+}
+
+#[test]
+fn test_open_store() {
+    assert!(matches!(open_store("ftp://stories"), Err(StoryChainError::ConfigError(_))));
+    #[cfg(not(feature = "sqlite"))]
+    assert!(matches!(open_store("sqlite://stories.db"), Err(StoryChainError::ConfigError(_))));
+}
+
+#[cfg(feature = "sqlite")]
+#[tokio::test]
+async fn test_sqlite_store() -> Result<(), StoryChainError> {
+    use storychain::storage::SqliteStore;
+    use storychain::artifacts::Artifact;
+
+    let store = SqliteStore::in_memory()?;
+    let mut chain = StoryChain::new("Once upon a time.".to_string(), "Opening".to_string());
+    chain.metadata.insert(EPOCHS_KEY.to_string(), "3".to_string());
+    store.save_chain("dragon", &chain)?;
+
+    // New scenes are appended without rewriting the chain, and their predecessors pick up the link
+    let mut current = "root".to_string();
+    for epoch in 1..=2 {
+        let next = chain.generate_next_nodes(&current, &MockAIProvider, None, epoch, 3).await?.remove(0);
+        store.append_node("dragon", &chain, &next)?;
+        current = next;
+    }
+    let loaded = store.load_chain("dragon")?;
+    assert_eq!(loaded.nodes.len(), 3);
+    assert_eq!(loaded.nodes["root"].successors, chain.nodes["root"].successors);
+    assert_eq!(loaded.nodes[&current].content, chain.nodes[&current].content);
+    assert_eq!(loaded.metadata[EPOCHS_KEY], "3");
+
+    // Appending to a chain not yet stored saves all of it
+    store.append_node("cave", &chain, &current)?;
+    assert_eq!(store.load_chain("cave")?.nodes.len(), 3);
+    assert_eq!(store.list_chains()?, vec!["cave".to_string(), "dragon".to_string()]);
+    assert!(matches!(store.load_chain("castle"), Err(StoryChainError::StorageError(_))));
+
+    let artifact = Artifact {
+        id: "premise".to_string(),
+        content: "A dragon guards a cave.".to_string(),
+        artifact_type: ArtifactType::Premise,
+        metadata: Default::default(),
+        tags: vec!["lore".to_string()],
+    };
+    store.save_artifact("dragon", &artifact)?;
+    assert_eq!(store.load_artifacts("dragon")?, vec![artifact]);
+    assert!(store.load_artifacts("cave")?.is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_chain_stores() -> Result<(), StoryChainError> {
+    let dir = tempfile::tempdir()?;
+    let stories = dir.path().join("stories");
+    let mut chain = StoryChain::new("Once upon a time.".to_string(), "Opening".to_string());
+    let next = chain.generate_next_nodes("root", &MockAIProvider, None, 1, 3).await?;
+
+    // Every backend behaves the same behind the trait
+    let stores: Vec<Box<dyn ChainStore>> = vec![
+        Box::new(JsonFileStore::new(&stories)),
+        Box::new(MemoryStore::default()),
+        open_store(&format!("file://{}", stories.join("nested").display()))?,
+        open_store("memory://")?,
+    ];
+    for store in &stores {
+        assert!(store.list_chains()?.is_empty());
+        assert!(matches!(store.load_chain("dragon"), Err(StoryChainError::StorageError(_))));
+        store.save_chain("dragon", &StoryChain::new("Once upon a time.".to_string(), "Opening".to_string()))?;
+        store.append_nodes("dragon", &chain, &next)?;
+        store.append_node("cave", &chain, &next[0])?;
+        let loaded = store.load_chain("dragon")?;
+        assert_eq!(loaded.nodes.len(), 2);
+        assert_eq!(loaded.nodes["root"].successors, next);
+        assert_eq!(store.list_chains()?, vec!["cave".to_string(), "dragon".to_string()]);
+    }
+
+    // The file store writes the same files as export_to_file
+    let (store, name) = JsonFileStore::for_file(&stories.join("dragon.json").to_string_lossy());
+    assert_eq!(name, "dragon");
+    assert_eq!(store.path(&name), stories.join("dragon.json"));
+    assert_eq!(StoryChain::load_from_file(&stories.join("dragon.json").to_string_lossy())?.nodes.len(), 2);
+    assert_eq!(open_store(&stories.to_string_lossy())?.list_chains()?, vec!["cave".to_string(), "dragon".to_string()]);
+    Ok(())
+}
+
+#[test]
+fn test_project_workspace() -> Result<(), StoryChainError> {
+    let dir = tempfile::tempdir()?;
+    let root = dir.path().join("dragon");
+    let project = Project::init(&root, "dragon")?;
+    for subdir in ["artifacts", "chains", "checkpoints"] {
+        assert!(root.join(subdir).is_dir());
+    }
+    assert_eq!(project.premise_path(), root.join("artifacts/dragon.yaml"));
+    assert!(StoryChainConfig::load(project.config_path()).is_ok());
+    // The premise is left for the author to fill in
+    let premise = load_premise("dragon", &project.artifacts_dir())?;
+    assert!(matches!(Premise::parse(&premise), Err(StoryChainError::ConfigError(_))));
+    assert!(matches!(Project::init(&root, "dragon"), Err(StoryChainError::ConfigError(_))));
+
+    // Chains and checkpoints are kept inside the project
+    let opened = Project::open(&root)?;
+    assert_eq!(opened.manifest, project.manifest);
+    let chain = StoryChain::new("Once upon a time.".to_string(), "Opening".to_string());
+    opened.chain_store().save_chain("dragon", &chain)?;
+    assert_eq!(opened.resolve_story("dragon"), root.join("chains/dragon.json").to_string_lossy());
+    assert_eq!(StoryChain::load_from_file(&opened.resolve_story("dragon"))?.nodes.len(), 1);
+    assert_eq!(opened.save_checkpoint("dragon", &chain, 2)?, root.join("checkpoints/dragon_epoch_2.json"));
+    assert!(root.join("checkpoints/dragon_epoch_2.json").is_file());
+
+    assert!(matches!(Project::open(dir.path()), Err(StoryChainError::ConfigError(_))));
+    assert!(Project::init(dir.path().join("other"), "a/b").is_err());
+    Ok(())
+}
+
+#[test]
+fn test_schema_migrations() -> Result<(), StoryChainError> {
+    // A chain from before branching, without a version or node metadata
+    let legacy = r#"{
+        "nodes": {
+            "root": {"id": "root", "content": "Once.", "reasoning": "r", "predecessor": null, "successor": "node_1", "metadata": {}},
+            "node_1": {"id": "node_1", "content": "Then.", "reasoning": "r", "predecessor": "root", "successor": null}
+        },
+        "root_node_id": "root"
+    }"#;
+    let mut value: serde_json::Value = serde_json::from_str(legacy)?;
+    assert_eq!(schema_version(&value), 1);
+    assert_eq!(migrate(&mut value)?.len(), 1);
+    assert_eq!(schema_version(&value), SCHEMA_VERSION);
+    assert!(migrate(&mut value)?.is_empty());
+
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("legacy.json");
+    std::fs::write(&path, legacy)?;
+    let chain = StoryChain::load_from_file(&path.to_string_lossy())?;
+    assert_eq!(chain.version, SCHEMA_VERSION);
+    assert_eq!(chain.nodes["root"].successors, vec!["node_1".to_string()]);
+    assert_eq!(chain.nodes["node_1"].predecessors, vec!["root".to_string()]);
+    assert!(chain.nodes["node_1"].metadata.is_empty());
+
+    // New chains record the current version, and newer files are refused
+    let saved = serde_json::to_value(StoryChain::new("Once.".to_string(), "r".to_string()))?;
+    assert_eq!(saved["version"], SCHEMA_VERSION);
+    let mut newer = serde_json::json!({"version": SCHEMA_VERSION + 1, "nodes": {}, "root_node_id": "root"});
+    assert!(matches!(migrate(&mut newer), Err(StoryChainError::InvalidChainOperation(_))));
+    Ok(())
+}
+
+#[test]
+fn test_compressed_atomic_files() -> Result<(), StoryChainError> {
+    let dir = tempfile::tempdir()?;
+    let mut chain = StoryChain::new("Once upon a time. ".repeat(200), "Opening".to_string());
+    chain.metadata.insert("title".to_string(), "The Long Night".to_string());
+
+    // Chains saved as .json.gz are gzipped and read back transparently
+    let plain = dir.path().join("story.json");
+    let gzipped = dir.path().join("story.json.gz");
+    chain.export_to_file(&plain.to_string_lossy())?;
+    chain.export_to_file(&gzipped.to_string_lossy())?;
+    let bytes = std::fs::read(&gzipped)?;
+    assert_eq!(Compression::detect(&bytes), Compression::Gzip);
+    assert!(bytes.len() * 4 < std::fs::metadata(&plain)?.len() as usize);
+    let loaded = StoryChain::load_from_file(&gzipped.to_string_lossy())?;
+    assert_eq!(loaded.nodes["root"].content, chain.nodes["root"].content);
+    assert_eq!(loaded.metadata["title"], "The Long Night");
+
+    // The file store keeps the compression of the file it was made for
+    let (store, name) = JsonFileStore::for_file(&gzipped.to_string_lossy());
+    assert_eq!((store.path(&name), name.as_str()), (gzipped.clone(), "story"));
+    assert_eq!(store.list_chains()?, vec!["story".to_string()]);
+    assert_eq!(uncompressed_path("out/story.json.gz"), "out/story.json");
+
+    // Writes replace files whole, leaving no temporary files behind
+    write_atomic(&plain, "replaced")?;
+    assert_eq!(std::fs::read_to_string(&plain)?, "replaced");
+    assert!(write_atomic(dir.path().join("missing/story.json"), "lost").is_err());
+    let names: Vec<String> = std::fs::read_dir(dir.path())?
+        .map(|entry| entry.map(|e| e.file_name().to_string_lossy().to_string()))
+        .collect::<Result<_, _>>()?;
+    assert_eq!(names.len(), 2, "unexpected files: {:?}", names);
+
+    #[cfg(feature = "zstd")]
+    {
+        let zstd = dir.path().join("story.json.zst");
+        chain.export_to_file(&zstd.to_string_lossy())?;
+        assert_eq!(Compression::detect(&std::fs::read(&zstd)?), Compression::Zstd);
+        assert_eq!(StoryChain::load_from_file(&zstd.to_string_lossy())?.nodes.len(), 1);
+    }
+    #[cfg(not(feature = "zstd"))]
+    assert!(matches!(Compression::Zstd.compress(b"{}"), Err(StoryChainError::ConfigError(_))));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_node_provenance() -> Result<(), StoryChainError> {
+    // Providers report their name and model, and wrappers report the provider they wrap
+    let http = RetryingProvider::new(OllamaHttpProvider::new("llama3".to_string()));
+    assert_eq!(http.provider_name(), "ollama-http");
+    assert_eq!(http.model_name(), Some("llama3"));
+    assert_eq!(FixedResponseProvider("Next.").provider_name(), "FixedResponseProvider");
+
+    let mut chain = StoryChain::new("The first scene.".to_string(), "Test reasoning".to_string());
+    assert!(chain.nodes["root"].provenance.is_none());
+    chain.settings.system_prompt = Some("You write fables.".to_string());
+    chain.settings.generation.temperature = Some(0.7);
+    let id = chain.generate_next_nodes("root", &FixedResponseProvider("The fox waits."), None, 1, 3).await?[0].clone();
+    let provenance = chain.nodes[&id].provenance.clone().expect("generated scenes record provenance");
+    assert_eq!(provenance.provider, "FixedResponseProvider");
+    assert_eq!(provenance.model, None);
+    assert_eq!(provenance.template, "continuation");
+    assert_eq!(provenance.template_digest, chain.prompts.template_digest("continuation"));
+    assert!(provenance.system_prompt);
+    assert_eq!(provenance.generation.temperature, Some(0.7));
+    assert!(chrono::DateTime::parse_from_rfc3339(&provenance.created_at).is_ok());
+    assert!(provenance.revisions.is_empty());
+
+    // A custom template changes the digest
+    let builtin = chain.prompts.template_digest("continuation");
+    chain.prompts.set_template(PromptTemplate { name: "continuation".to_string(), source: "Go on: {{ last_scene }}".to_string() })?;
+    assert_ne!(chain.prompts.template_digest("continuation"), builtin);
+    assert_eq!(chain.prompts.template_digest("unknown"), None);
+
+    // Hand edits are recorded as revisions, and provenance survives a save and reaches the dataset export
+    chain.edit_node(&id, "The fox waits by the well.".to_string())?;
+    assert_eq!(chain.nodes[&id].provenance.as_ref().unwrap().revisions, vec!["edit".to_string()]);
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("story.json").to_string_lossy().to_string();
+    chain.export_to_file(&path)?;
+    let loaded = StoryChain::load_from_file(&path)?;
+    assert_eq!(loaded.nodes[&id].provenance, chain.nodes[&id].provenance);
+    let records = loaded.to_jsonl_records(None)?;
+    let record = records.iter().find(|r| r.id == id).unwrap();
+    assert_eq!(record.provenance.as_ref().map(|p| p.provider.as_str()), Some("FixedResponseProvider"));
+    assert!(!serde_json::to_string(&loaded.nodes["root"])?.contains("provenance"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_token_usage_and_cost() -> Result<(), StoryChainError> {
+    // Providers without usage of their own are measured with the local tokenizer
+    assert_eq!(FixedResponseProvider("Next.").usage(), None);
+    let tracked: Box<dyn AIProvider> = Box::new(UsageTracker::new(FixedResponseProvider("The fox waits.")));
+    assert_eq!(tracked.usage(), Some(TokenUsage::default()));
+    assert_eq!(tracked.provider_name(), "FixedResponseProvider");
+    tracked.generate_with_config(Some("Be brief."), "Write a scene.", &GenerationConfig::default()).await?;
+    let usage = tracked.usage().unwrap();
+    assert_eq!(usage.requests, 1);
+    assert_eq!(usage.prompt_tokens as usize, count_tokens("Be brief.") + count_tokens("Write a scene."));
+    assert!(usage.completion_tokens > 0);
+
+    // Every generated node records the tokens it took
+    let mut chain = StoryChain::new("The first scene.".to_string(), "Test reasoning".to_string());
+    let before = tracked.usage().unwrap();
+    let id = chain.generate_next_nodes("root", tracked.as_ref(), None, 1, 3).await?[0].clone();
+    let node_usage = chain.nodes[&id].provenance.as_ref().unwrap().usage.expect("tracked providers report usage");
+    assert_eq!(node_usage, tracked.usage().unwrap().since(&before));
+    assert_eq!(node_usage.requests, 1);
+
+    // Runs add up, and prices give an estimated cost
+    let pricing: Pricing = StoryChainConfig::from_toml("[pricing]\nprompt_per_million = 2.0\ncompletion_per_million = 8.0\n")?
+        .pricing
+        .expect("pricing is configured");
+    assert_eq!(pricing.currency, "USD");
+    let run = TokenUsage::request(500_000, 250_000);
+    assert!((pricing.cost(&run) - 3.0).abs() < 1e-9);
+    assert!(chain.usage.is_empty());
+    chain.usage.record_run(run, Some(&pricing));
+    chain.usage.record_run(run, Some(&pricing));
+    assert_eq!(chain.usage.runs, 2);
+    assert_eq!(chain.usage.total, run + run);
+    assert_eq!(chain.usage.cost, Some(6.0));
+    let text = chain.usage.to_text();
+    assert!(text.contains("500000 prompt + 250000 completion = 750000 tokens over 1 requests, about 3.0000 USD"));
+    assert!(text.contains("over 2 runs"));
+
+    // The summary is saved with the chain
+    let loaded: StoryChain = serde_json::from_str(&serde_json::to_string(&chain)?)?;
+    assert_eq!(loaded.usage, chain.usage);
+    assert!(!serde_json::to_string(&StoryChain::new("Once.".to_string(), "r".to_string()))?.contains("\"usage\""));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_run_tracing() -> Result<(), StoryChainError> {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    let recorder = TraceRecorder::new();
+    let _guard = tracing::subscriber::set_default(tracing_subscriber::registry().with(recorder.clone()));
+    let mut chain = StoryChain::new("The first scene.".to_string(), "Test reasoning".to_string());
+    chain.generate_next_nodes("root", &FixedResponseProvider("The fox waits."), None, 1, 3).await?;
+
+    // Every phase of the scene is a span nested in the scene's own
+    let spans = recorder.spans();
+    let names: Vec<&str> = spans.iter().map(|s| s.name.as_str()).collect();
+    for name in [PROMPT_BUILD_SPAN, AI_GENERATE_SPAN, REVISE_SPAN, PARSE_SPAN, SCENE_SPAN] {
+        assert!(names.contains(&name), "no {} span in {:?}", name, names);
+    }
+    let scene = spans.iter().find(|s| s.name == SCENE_SPAN).unwrap();
+    assert_eq!(scene.fields["node"], "root");
+    assert_eq!(scene.fields["epoch"], "1");
+    assert_eq!(scene.depth, 0);
+    let generate = spans.iter().find(|s| s.name == AI_GENERATE_SPAN).unwrap();
+    assert_eq!(generate.depth, 1);
+    assert!(generate.start >= scene.start && generate.duration <= scene.duration);
+    assert_eq!(spans.iter().find(|s| s.name == REVISE_SPAN).unwrap().fields["pass"], "content_filter");
+
+    // Phases are summed, and the trace exports as Chrome trace events
+    let totals = recorder.phase_totals();
+    assert_eq!(totals[0].name, SCENE_SPAN);
+    assert_eq!(totals.iter().find(|t| t.name == AI_GENERATE_SPAN).unwrap().count, 1);
+    assert!(recorder.summary().starts_with("scene "));
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("trace.json");
+    recorder.export(&path)?;
+    let trace: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path)?)?;
+    let events = trace["traceEvents"].as_array().unwrap();
+    assert_eq!(events.len(), spans.len());
+    assert!(events.iter().all(|e| e["ph"] == "X" && e["dur"].is_u64()));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_interaction_log() -> Result<(), StoryChainError> {
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("ai.jsonl");
+
+    // Every attempt is appended, the failed one with its error
+    let flaky = FlakyProvider {
+        failures: std::sync::Mutex::new(vec![StoryChainError::AIServerError("connection reset".to_string())]),
+    };
+    let provider = RetryingProvider::new(InteractionLogger::new(flaky, &path))
+        .with_backoff(Backoff::Fixed(std::time::Duration::ZERO));
+    assert_eq!(provider.generate_with_system("Be terse.", "Prompt").await?.1, "Recovered");
+
+    let records = read_interactions(&path)?;
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].error.as_deref(), Some("AI server error: connection reset"));
+    assert!(records[0].content.is_none());
+    assert_eq!(records[1].system_prompt.as_deref(), Some("Be terse."));
+    assert_eq!(records[1].prompt, "Prompt");
+    assert_eq!(records[1].reasoning.as_deref(), Some("Reasoning"));
+    assert_eq!(records[1].content.as_deref(), Some("Recovered"));
+    assert_eq!(records[1].provider, "FlakyProvider");
+    assert!(records[1].error.is_none());
+    assert!(chrono::DateTime::parse_from_rfc3339(&records[1].timestamp).is_ok());
+
+    // Each line is a standalone JSON object, and a corrupt line is reported
+    let text = std::fs::read_to_string(&path)?;
+    for line in text.lines() {
+        assert!(serde_json::from_str::<serde_json::Value>(line)?.is_object());
+    }
+    std::fs::write(&path, format!("{}not json\n", text))?;
+    let error = read_interactions(&path).unwrap_err();
+    assert!(error.to_string().contains("line 3"));
+    Ok(())
+}
+
+#[cfg(feature = "serve")]
+#[tokio::test]
+async fn test_generation_server() -> Result<(), StoryChainError> {
+    use storychain::server::{serve, RunState, RunStatus, StoryServer};
+
+    let dir = tempfile::tempdir()?;
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let base = format!("http://{}", listener.local_addr()?);
+    let server = Arc::new(StoryServer::new(dir.path(), Arc::new(MockAIProvider)));
+    tokio::spawn(serve(listener, server));
+    let client = reqwest::Client::new();
+    let http = |e: reqwest::Error| StoryChainError::AIServerError(e.to_string());
+
+    // Create a project with its premise
+    let response = client
+        .post(format!("{}/projects", base))
+        .json(&serde_json::json!({ "name": "fox", "premise": "premise: A fox learns to fly." }))
+        .send()
+        .await
+        .map_err(http)?;
+    assert_eq!(response.status(), 201);
+    let projects: serde_json::Value = client.get(format!("{}/projects", base)).send().await.map_err(http)?.json().await.map_err(http)?;
+    assert_eq!(projects[0]["name"], "fox");
+
+    // Start a run and follow its events to the end
+    let run: RunStatus = client
+        .post(format!("{}/projects/fox/runs", base))
+        .json(&serde_json::json!({ "epochs": 2 }))
+        .send()
+        .await
+        .map_err(http)?
+        .json()
+        .await
+        .map_err(http)?;
+    assert_eq!(run.chain, "fox");
+    let events = client.get(format!("{}/runs/{}/events", base, run.id)).send().await.map_err(http)?.text().await.map_err(http)?;
+    assert!(events.starts_with("event: started"));
+    assert_eq!(events.matches("event: node_completed").count(), 3);
+    assert!(events.trim_end().lines().any(|line| line == "event: finished"));
+
+    let status: RunStatus = client.get(format!("{}/runs/{}", base, run.id)).send().await.map_err(http)?.json().await.map_err(http)?;
+    assert_eq!((status.state, status.completed_epochs, status.scenes), (RunState::Finished, 2, 3));
+
+    // The chain is saved in the project and can be fetched and exported
+    let project: serde_json::Value = client.get(format!("{}/projects/fox", base)).send().await.map_err(http)?.json().await.map_err(http)?;
+    assert_eq!(project["chains"], serde_json::json!(["fox"]));
+    let chain: StoryChain = client.get(format!("{}/projects/fox/chains/fox", base)).send().await.map_err(http)?.json().await.map_err(http)?;
+    assert_eq!(chain.nodes.len(), 3);
+    let export: serde_json::Value = client
+        .post(format!("{}/projects/fox/chains/fox/exports", base))
+        .json(&serde_json::json!({ "format": "markdown" }))
+        .send()
+        .await
+        .map_err(http)?
+        .json()
+        .await
+        .map_err(http)?;
+    assert!(Path::new(export["path"].as_str().unwrap()).is_file());
+
+    // Reader feedback is saved with the chain
+    let feedback = |node: &str, rating: u8| {
+        client
+            .post(format!("{}/projects/fox/chains/fox/nodes/{}/feedback", base, node))
+            .json(&serde_json::json!({ "rating": rating, "comment": "Too slow" }))
+            .send()
+    };
+    let summary: serde_json::Value = feedback("node_1", 2).await.map_err(http)?.json().await.map_err(http)?;
+    assert_eq!((summary["count"].as_u64(), summary["low_rated_nodes"].clone()), (Some(1), serde_json::json!(["node_1"])));
+    let chain: StoryChain = client.get(format!("{}/projects/fox/chains/fox", base)).send().await.map_err(http)?.json().await.map_err(http)?;
+    assert_eq!(chain.nodes["node_1"].feedback[0].comment.as_deref(), Some("Too slow"));
+    assert_eq!(feedback("node_1", 9).await.map_err(http)?.status(), 400);
+    assert_eq!(feedback("node_9", 3).await.map_err(http)?.status(), 400);
+
+    // Unknown projects and names leaving the root are refused
+    assert_eq!(client.get(format!("{}/projects/wolf", base)).send().await.map_err(http)?.status(), 404);
+    let response = client.post(format!("{}/projects", base)).json(&serde_json::json!({ "name": "../fox" })).send().await.map_err(http)?;
+    assert_eq!(response.status(), 400);
+    Ok(())
+}
+
+/// A provider that answers once the gate is opened
+#[cfg(feature = "serve")]
+struct GatedProvider(tokio::sync::watch::Receiver<bool>);
+
+#[cfg(feature = "serve")]
+#[async_trait::async_trait]
+impl AIProvider for GatedProvider {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        let _ = self.0.clone().wait_for(|open| *open).await;
+        MockAIProvider.generate(prompt).await
+    }
+}
+
+#[cfg(feature = "serve")]
+#[tokio::test]
+async fn test_run_websocket() -> Result<(), StoryChainError> {
+    use storychain::events::RunEvent;
+    use storychain::server::{serve, StoryServer};
+    use tokio_stream::StreamExt;
+    use tokio_tungstenite::tungstenite::Message;
+
+    let dir = tempfile::tempdir()?;
+    let (gate, gated) = tokio::sync::watch::channel(false);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?;
+    tokio::spawn(serve(listener, Arc::new(StoryServer::new(dir.path(), Arc::new(GatedProvider(gated))))));
+    let client = reqwest::Client::new();
+    let http = |e: reqwest::Error| StoryChainError::AIServerError(e.to_string());
+    client
+        .post(format!("http://{}/projects", address))
+        .json(&serde_json::json!({ "name": "fox", "premise": "premise: A fox learns to fly." }))
+        .send()
+        .await
+        .map_err(http)?;
+    client
+        .post(format!("http://{}/projects/fox/runs", address))
+        .json(&serde_json::json!({ "epochs": 1 }))
+        .send()
+        .await
+        .map_err(http)?;
+
+    // Follow the run from before its first response
+    let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/runs/run-1/ws", address))
+        .await
+        .map_err(|e| StoryChainError::AIServerError(e.to_string()))?;
+    gate.send(true).unwrap();
+    let mut events = Vec::new();
+    while let Some(Ok(message)) = socket.next().await {
+        match message {
+            Message::Text(text) => events.push(serde_json::from_str::<RunEvent>(&text)?),
+            Message::Close(_) => break,
+            _ => {}
+        }
+    }
+
+    // Tokens stream before each scene completes, and the run's end closes the socket
+    assert!(matches!(events[0], RunEvent::Started { epochs: 1, .. }));
+    let first_token = events.iter().position(|e| matches!(e, RunEvent::Token { .. })).unwrap();
+    let first_node = events.iter().position(|e| matches!(e, RunEvent::NodeCompleted { .. })).unwrap();
+    assert!(first_token < first_node);
+    assert!(events.iter().any(|e| matches!(e, RunEvent::Token { text } if text.contains("long shadows"))));
+    assert_eq!(events.iter().filter(|e| matches!(e, RunEvent::NodeCompleted { .. })).count(), 2);
+    assert_eq!(events.last(), Some(&RunEvent::Finished { scenes: 2 }));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_story_runner() -> Result<(), StoryChainError> {
+    let dir = tempfile::tempdir()?;
+    let export_path = dir.path().join("fox.md").to_string_lossy().to_string();
+    let store = MemoryStore::default();
+    let mut scenes = Vec::new();
+    let mut events = Vec::new();
+    let mut reports = Vec::new();
+
+    // Two branches over two epochs, saving the chain as the run goes
+    let chain = StoryRunner::new(&MockAIProvider)
+        .premise("A fox learns to fly.")
+        .epochs(2)
+        .branches(2)
+        .on_node(|_, node_id| scenes.push(node_id.to_string()))
+        .on_event(|event| events.push(event.name()))
+        .on_epoch(|chain, report| {
+            reports.push((report.epoch, report.node_ids.len(), report.frontier));
+            if report.epoch == 0 {
+                return store.save_chain("fox", chain);
+            }
+            store.append_nodes("fox", chain, &report.node_ids)
+        })
+        .export(ExportFormat::Markdown, export_path.as_str())
+        .run()
+        .await?;
+
+    // Every storyline is continued, and each scene and epoch reported as it finishes
+    assert_eq!(chain.nodes.len(), 7);
+    assert_eq!(chain.leaf_ids().len(), 4);
+    assert_eq!(scenes.len(), 7);
+    assert_eq!(scenes[0], chain.root_node_id);
+    assert_eq!(reports, vec![(0, 1, 1), (1, 2, 2), (2, 4, 4)]);
+    assert_eq!(events.iter().filter(|name| **name == "epoch_finished").count(), 2);
+    assert_eq!(events.first(), Some(&"node_completed"));
+    assert!(chain.nodes[&chain.root_node_id].provenance.is_some());
+    assert_eq!(chain.completed_epochs(), 2);
+    assert_eq!(store.load_chain("fox")?.nodes.len(), 7);
+    assert!(std::fs::read_to_string(&export_path)?.contains("long shadows"));
+
+    // A saved chain is continued from where it stopped
+    let mut saved = store.load_chain("fox")?;
+    StoryRunner::new(&MockAIProvider).epochs(3).continue_chain(&mut saved).await?;
+    assert_eq!(saved.nodes.len(), 15);
+    assert_eq!(saved.completed_epochs(), 3);
+    assert_eq!(saved.metadata.get(EPOCHS_KEY).map(String::as_str), Some("3"));
+
+    // An error from the epoch callback stops the run
+    let result = StoryRunner::new(&MockAIProvider)
+        .epochs(3)
+        .on_epoch(|_, report| match report.epoch {
+            1 => Err(StoryChainError::StorageError("disk full".to_string())),
+            _ => Ok(()),
+        })
+        .run()
+        .await;
+    assert!(matches!(result, Err(StoryChainError::StorageError(_))));
+    Ok(())
+}
+
+/// Records the pipeline events it observes
+#[derive(Default)]
+struct RecordingHooks {
+    events: Mutex<Vec<String>>,
+}
+
+impl PipelineHooks for RecordingHooks {
+    fn prompt_built(&self, template: &str, _prompt: &str) {
+        self.events.lock().unwrap().push(format!("prompt {}", template));
+    }
+
+    fn node_generated(&self, _chain: &StoryChain, node_id: &str) {
+        self.events.lock().unwrap().push(format!("generated {}", node_id));
+    }
+
+    fn node_rejected(&self, chain: &StoryChain, node_id: Option<&str>, content: &str, reason: &str) {
+        assert!(node_id.is_some_and(|id| chain.nodes[id].content == content));
+        self.events.lock().unwrap().push(format!("rejected {} ({})", node_id.unwrap_or_default(), reason));
+    }
+
+    fn checkpoint_saved(&self, _chain: &StoryChain, epoch: usize, path: &Path) {
+        assert!(path.is_file());
+        self.events.lock().unwrap().push(format!("checkpoint {}", epoch));
+    }
+
+    fn run_finished(&self, chain: &StoryChain) {
+        self.events.lock().unwrap().push(format!("finished {}", chain.nodes.len()));
+    }
+}
+
+#[tokio::test]
+async fn test_pipeline_hooks() -> Result<(), StoryChainError> {
+    let dir = tempfile::tempdir()?;
+    let project = Project::init(dir.path().join("fox"), "fox")?;
+    let hooks = Arc::new(RecordingHooks::default());
+
+    // The runner adds its hooks to the chain, which reports every step of the run
+    let mut chain = StoryRunner::new(&MockAIProvider)
+        .premise("A fox learns to fly.")
+        .epochs(2)
+        .hooks(hooks.clone())
+        .on_epoch(|chain, report| project.save_checkpoint("fox", chain, report.epoch).map(|_| ()))
+        .run()
+        .await?;
+    assert_eq!(
+        *hooks.events.lock().unwrap(),
+        vec![
+            "prompt initial",
+            "generated root",
+            "checkpoint 0",
+            "prompt continuation",
+            "generated node_1",
+            "checkpoint 1",
+            "prompt continuation",
+            "generated node_2",
+            "checkpoint 2",
+            "finished 3",
+        ]
+    );
+
+    // Rejections are reported before the scene is removed
+    hooks.events.lock().unwrap().clear();
+    chain.reject_node("node_2", None)?;
+    chain.reject_node("node_1", Some("Too quiet".to_string()))?;
+    assert_eq!(
+        *hooks.events.lock().unwrap(),
+        vec!["rejected node_2 (rejected by the author)", "rejected node_1 (Too quiet)"]
+    );
+
+    // Hooks already on the chain are not added twice
+    chain.add_hooks(hooks.clone());
+    assert_eq!(chain.hooks.len(), 1);
+    Ok(())
+}
+
+/// Handles "MagicSystem" artifacts, which must state the cost of magic,
+/// and keeps the story's sky in line with them
+struct MagicSystemProcessor;
+
+impl ArtifactProcessor for MagicSystemProcessor {
+    fn artifact_type(&self) -> ArtifactType {
+        ArtifactType::Custom("MagicSystem".to_string())
+    }
+
+    fn validate(&self, artifact: &storychain::Artifact) -> Result<(), String> {
+        match artifact.content.contains("Cost:") {
+            true => Ok(()),
+            false => Err("every magic system needs a cost".to_string()),
+        }
+    }
+
+    fn render_to_prompt(&self, artifact: &storychain::Artifact) -> Option<String> {
+        Some(format!("Magic System (never break its rules):\n{}", artifact.content.trim()))
+    }
+
+    fn post_process(&self, artifact: &storychain::Artifact, content: String) -> String {
+        match artifact.metadata.get("suns") {
+            Some(suns) => content.replace("The sun", &format!("The {} suns", suns)),
+            None => content,
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_artifact_processors() -> Result<(), StoryChainError> {
+    let dir = tempfile::tempdir()?;
+    let mut artifact_manager = ArtifactManager::new(&dir.path().to_string_lossy());
+    let magic = ArtifactType::Custom("MagicSystem".to_string());
+    let mut registry = ArtifactRegistry::new();
+    registry.register(Arc::new(MagicSystemProcessor));
+    assert!(registry.get(&magic).is_some());
+
+    // Artifacts the processor rejects are reported by ID
+    artifact_manager.create_artifact("runes".to_string(), "Runes glow when spoken.".to_string(), magic.clone())?;
+    let error = PromptTemplates::default().add_processed_artifacts(&artifact_manager, &registry).unwrap_err();
+    assert!(matches!(&error, StoryChainError::ConfigError(message) if message.contains("runes") && message.contains("cost")));
+
+    // Valid artifacts are laid out for both prompts by their processor
+    let mut runes = artifact_manager.get_artifact("runes").unwrap().clone();
+    runes.content = "Runes glow when spoken.\nCost: a memory per rune.".to_string();
+    runes.metadata.insert("suns".to_string(), "twin".to_string());
+    artifact_manager.update_artifact(runes)?;
+    let mut templates = PromptTemplates::default();
+    templates.add_artifacts(&artifact_manager);
+    templates.add_processed_artifacts(&artifact_manager, &registry)?;
+    let prompt = templates.initial_prompt("A fox learns to fly.", &[])?;
+    assert!(prompt.contains("Magic System (never break its rules):\nRunes glow when spoken.\nCost: a memory per rune."));
+
+    // Every scene generated with the templates is post-processed
+    let chain = StoryRunner::new(&MockAIProvider).templates(templates).epochs(2).run().await?;
+    assert_eq!(chain.nodes.len(), 3);
+    assert!(chain.nodes.values().all(|node| node.content.starts_with("The twin suns cast long shadows")));
+    Ok(())
+}
+
+/// Answers after a short delay, counting the most requests it had in flight at once
+#[derive(Default)]
+struct ConcurrencyProvider {
+    in_flight: AtomicUsize,
+    peak: AtomicUsize,
+    answered: AtomicUsize,
+}
+
+#[async_trait::async_trait]
+impl AIProvider for ConcurrencyProvider {
+    async fn generate(&self, _prompt: &str) -> Result<(String, String), StoryChainError> {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.peak.fetch_max(in_flight, Ordering::SeqCst);
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        let answer = self.answered.fetch_add(1, Ordering::SeqCst) + 1;
+        Ok(("Reasoning".to_string(), format!("Candidate {}", answer)))
+    }
+}
+
+#[tokio::test]
+async fn test_parallel_candidates() -> Result<(), StoryChainError> {
+    // Alternatives are generated one after another by default
+    let provider = ConcurrencyProvider::default();
+    let mut chain = StoryChain::new("The fox looks up.".to_string(), "Test reasoning".to_string()).with_branch_ratio(4);
+    chain.generate_next_nodes("root", &provider, None, 1, 2).await?;
+    assert_eq!(provider.peak.load(Ordering::SeqCst), 1);
+
+    // With a limit they are generated concurrently, up to the limit at a time
+    let provider = ConcurrencyProvider::default();
+    let mut chain = StoryChain::new("The fox looks up.".to_string(), "Test reasoning".to_string()).with_branch_ratio(4);
+    chain.settings.parallel_candidates = Some(2);
+    let new_ids = chain.generate_next_nodes("root", &provider, None, 1, 2).await?;
+    assert_eq!(provider.peak.load(Ordering::SeqCst), 2);
+    assert_eq!(new_ids, vec!["node_1", "node_2", "node_3", "node_4"]);
+    assert_eq!(chain.nodes["root"].successors, new_ids);
+    for id in &new_ids {
+        assert!(chain.nodes[id].content.starts_with("Candidate"));
+        assert_eq!(chain.nodes[id].predecessors, vec!["root"]);
+        assert!(chain.nodes[id].provenance.as_ref().is_some_and(|p| p.usage.is_none()));
+    }
+
+    // Every storyline of a run is branched the same way
+    let provider = ConcurrencyProvider::default();
+    let chain = StoryRunner::new(&provider).epochs(2).branches(3).parallel(3).run().await?;
+    assert_eq!(chain.nodes.len(), 13);
+    assert_eq!(provider.peak.load(Ordering::SeqCst), 3);
+    Ok(())
+}
+
+/// Writes numbered candidate scenes, and ranks them as a judge
+#[derive(Default)]
+struct SelectionProvider {
+    written: AtomicUsize,
+    judged: AtomicUsize,
+}
+
+#[async_trait::async_trait]
+impl AIProvider for SelectionProvider {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        if prompt.contains("candidate scenes") {
+            self.judged.fetch_add(1, Ordering::SeqCst);
+            return Ok(("Reasoning".to_string(), "RANKING: 2, 1, 3\nNOTES: The second candidate raises the stakes.".to_string()));
+        }
+        let number = self.written.fetch_add(1, Ordering::SeqCst) + 1;
+        Ok(("Reasoning".to_string(), format!("Candidate {}", number)))
+    }
+}
+
+#[tokio::test]
+async fn test_best_of_selection() -> Result<(), StoryChainError> {
+    assert_eq!(parse_ranking("3, 1, 2", 3), vec![2, 0, 1]);
+    assert_eq!(parse_ranking("Candidate 2 beats 2 and 7", 3), vec![1, 0, 2]);
+
+    // The judge's favourite comes first and is the only one continued
+    let provider = SelectionProvider::default();
+    let mut chain = StoryChain::new("The fox looks up.".to_string(), "Test reasoning".to_string());
+    chain.settings.selection = Some(CandidateSelection { candidates: 3 });
+    let kept = chain.generate_with_subplots("root", &provider, None, None, 1, 2).await?;
+    assert_eq!(provider.judged.load(Ordering::SeqCst), 1);
+    assert_eq!(kept.len(), 1);
+    let successors = chain.nodes["root"].successors.clone();
+    assert_eq!(successors.len(), 3);
+    assert_eq!(successors[0], kept[0]);
+    let winner = &chain.nodes[&kept[0]];
+    assert_eq!(winner.content, "Candidate 2");
+    assert_eq!(winner.metadata[SELECTION_RANK_KEY], "1");
+    assert_eq!(winner.metadata[SELECTION_NOTES_KEY], "The second candidate raises the stakes.");
+    assert_eq!(chain.nodes[&successors[1]].content, "Candidate 1");
+    assert_eq!(chain.nodes[&successors[1]].metadata[SELECTION_RANK_KEY], "2");
+    assert_eq!(chain.nodes[&successors[2]].metadata[SELECTION_RANK_KEY], "3");
+    assert!(chain.nodes[&successors[2]].is_unselected_candidate());
+    assert_eq!(chain.frontier_ids(), kept);
+
+    // A run continues only the winners
+    let provider = SelectionProvider::default();
+    let chain = StoryRunner::new(&provider).epochs(2).best_of(3).run().await?;
+    assert_eq!(provider.judged.load(Ordering::SeqCst), 2);
+    assert_eq!(chain.nodes.len(), 7);
+    assert_eq!(chain.frontier_ids().len(), 1);
+    Ok(())
+}
+
+/// A provider that never answers, like a hung server
+struct HangingProvider;
+
+#[async_trait::async_trait]
+impl AIProvider for HangingProvider {
+    async fn generate(&self, _prompt: &str) -> Result<(String, String), StoryChainError> {
+        std::future::pending().await
+    }
+}
+
+#[tokio::test]
+async fn test_timeout_and_cancellation() -> Result<(), StoryChainError> {
+    // A request the model never answers times out, and is worth retrying
+    let timeout = std::time::Duration::from_millis(20);
+    let provider = TimeoutProvider::new(HangingProvider, timeout);
+    let error = provider.generate("Prompt").await.unwrap_err();
+    assert!(matches!(error, StoryChainError::Timeout(t) if t == timeout));
+    assert!(is_transient(&error));
+    let config = StoryChainConfig::from_toml("[provider]\ntype = \"ollama-http\"\ntimeout_secs = 30")?;
+    assert_eq!(config.provider.timeout_secs, Some(30));
+    ProviderFactory::from_config(&config.provider)?;
+
+    // Cancelling drops the request in flight
+    let token = CancellationToken::new();
+    let provider = CancellableProvider::new(HangingProvider, token.clone());
+    let cancel = tokio::spawn({
+        let token = token.clone();
+        async move {
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            token.cancel();
+        }
+    });
+    assert!(matches!(provider.generate("Prompt").await, Err(StoryChainError::Cancelled)));
+    cancel.await.unwrap();
+
+    // A chain stops generating once its token is cancelled
+    let mut chain = StoryChain::new("The fox looks up.".to_string(), "Test reasoning".to_string());
+    chain.cancellation = Some(token);
+    assert!(matches!(
+        chain.generate_next_nodes("root", &MockAIProvider, None, 1, 2).await,
+        Err(StoryChainError::Cancelled)
+    ));
+    assert_eq!(chain.nodes.len(), 1);
+
+    // A run stops after the epoch during which it was cancelled, having reported it
+    let token = CancellationToken::new();
+    let mut reported = Vec::new();
+    let result = StoryRunner::new(&MockAIProvider)
+        .epochs(5)
+        .cancellation(token.clone())
+        .on_epoch(|_, report| {
+            reported.push(report.epoch);
+            if report.epoch == 2 {
+                token.cancel();
+            }
+            Ok(())
+        })
+        .run()
+        .await;
+    assert!(matches!(result, Err(StoryChainError::Cancelled)));
+    assert_eq!(reported, vec![0, 1, 2]);
+
+    // A cancelled run keeps the scenes it generated; other outcomes save nothing
+    let dir = tempfile::tempdir()?;
+    let store = MemoryStore::default();
+    let markdown = dir.path().join("story.md").to_string_lossy().to_string();
+    let token = CancellationToken::new();
+    let mut chain = StoryChain::new("The fox looks up.".to_string(), "Test reasoning".to_string());
+    let result = StoryRunner::new(&MockAIProvider)
+        .epochs(5)
+        .cancellation(token.clone())
+        .on_epoch(|_, report| {
+            if report.epoch == 1 {
+                token.cancel();
+            }
+            Ok(())
+        })
+        .continue_chain(&mut chain)
+        .await;
+    assert!(chain.save_cancelled_run(result, &store, "fox", &markdown)?);
+    assert_eq!(store.load_chain("fox")?.nodes.len(), chain.nodes.len());
+    assert!(std::fs::read_to_string(&markdown)?.contains("The fox looks up."));
+    assert!(!chain.save_cancelled_run(Ok(()), &store, "done", &markdown)?);
+    let failed = Err(StoryChainError::AIServerError("down".to_string()));
+    assert!(matches!(chain.save_cancelled_run(failed, &store, "failed", &markdown), Err(StoryChainError::AIServerError(_))));
+    assert!(store.load_chain("failed").is_err());
+    Ok(())
+}
+
+#[cfg(feature = "serve")]
+#[tokio::test]
+async fn test_server_cancel_run() -> Result<(), StoryChainError> {
+    use storychain::server::{serve, RunState, RunStatus, StoryServer};
+
+    // A run waiting on a model that does not answer
+    let dir = tempfile::tempdir()?;
+    let (_gate, gated) = tokio::sync::watch::channel(false);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?;
+    tokio::spawn(serve(listener, Arc::new(StoryServer::new(dir.path(), Arc::new(GatedProvider(gated))))));
+    let client = reqwest::Client::new();
+    let http = |e: reqwest::Error| StoryChainError::AIServerError(e.to_string());
+    client
+        .post(format!("http://{}/projects", address))
+        .json(&serde_json::json!({ "name": "fox", "premise": "premise: A fox learns to fly." }))
+        .send()
+        .await
+        .map_err(http)?;
+    client.post(format!("http://{}/projects/fox/runs", address)).json(&serde_json::json!({})).send().await.map_err(http)?;
+
+    // Cancelling drops the request in flight and ends the run
+    let response = client.post(format!("http://{}/runs/run-1/cancel", address)).send().await.map_err(http)?;
+    assert_eq!(response.status(), 202);
+    let mut status: RunStatus;
+    loop {
+        status = client.get(format!("http://{}/runs/run-1", address)).send().await.map_err(http)?.json().await.map_err(http)?;
+        if status.state != RunState::Running {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert_eq!(status.state, RunState::Cancelled);
+    assert_eq!(status.scenes, 0);
+
+    // A run that has ended cannot be cancelled
+    let response = client.post(format!("http://{}/runs/run-1/cancel", address)).send().await.map_err(http)?;
+    assert_eq!(response.status(), 409);
+    Ok(())
+}
+
+/// A provider that writes the seed it was sent, as a seeded model reproduces its output
+struct SeedProvider;
+
+#[async_trait::async_trait]
+impl AIProvider for SeedProvider {
+    async fn generate(&self, _prompt: &str) -> Result<(String, String), StoryChainError> {
+        Ok(("Reasoning".to_string(), "Unseeded".to_string()))
+    }
+
+    async fn generate_with_config(
+        &self,
+        _system_prompt: Option<&str>,
+        _prompt: &str,
+        config: &GenerationConfig,
+    ) -> Result<(String, String), StoryChainError> {
+        match config.seed {
+            Some(seed) => Ok(("Reasoning".to_string(), format!("Seed {}", seed))),
+            None => self.generate("").await,
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_seeded_generation() -> Result<(), StoryChainError> {
+    let seeded = GenerationConfig { seed: Some(7), ..GenerationConfig::default() };
+    assert_eq!(seeded.to_ollama_options()["seed"], 7);
+    assert_eq!(GenerationConfig::default().merged_over(&seeded).seed, Some(7));
+    assert_eq!(seeded.reseeded(3).seed, Some(10));
+    assert_eq!(StoryChainConfig::from_toml("[generation]\nseed = 42")?.generation.seed, Some(42));
+
+    // Every draft gets the next seed, recorded in its provenance
+    let mut chain = StoryChain::new("The fox looks up.".to_string(), "Test reasoning".to_string());
+    chain.settings.generation = seeded;
+    let first = chain.generate_next_nodes("root", &SeedProvider, None, 1, 3).await?.remove(0);
+    let second = chain.generate_next_nodes(&first, &SeedProvider, None, 2, 3).await?.remove(0);
+    assert_eq!(chain.nodes[&first].content, "Seed 7");
+    assert_eq!(chain.nodes[&second].content, "Seed 8");
+    assert_eq!(chain.nodes[&second].provenance.as_ref().unwrap().generation.seed, Some(8));
+    assert_eq!(chain.metadata[SEED_DRAWS_KEY], "2");
+
+    // Candidates sharing a prompt still differ
+    chain.branch_ratio = 3;
+    chain.settings.parallel_candidates = Some(3);
+    let candidates = chain.generate_next_nodes(&second, &SeedProvider, None, 3, 3).await?;
+    let contents: Vec<&str> = candidates.iter().map(|id| chain.nodes[id].content.as_str()).collect();
+    assert_eq!(contents, vec!["Seed 9", "Seed 10", "Seed 11"]);
+
+    // The same seed reproduces the same story, its opening included
+    let run = || {
+        StoryRunner::new(&SeedProvider).epochs(2).generation(GenerationConfig { seed: Some(42), ..GenerationConfig::default() })
+    };
+    let (a, b) = (run().run().await?, run().run().await?);
+    assert_eq!(a.nodes["root"].content, "Seed 42");
+    assert_eq!(a.nodes["root"].provenance.as_ref().unwrap().generation.seed, Some(42));
+    let story = |chain: &StoryChain| {
+        chain.storylines().iter().flatten().map(|node| node.content.clone()).collect::<Vec<_>>()
+    };
+    assert_eq!(story(&a), story(&b));
+    Ok(())
+}
+
+/// A provider that "translates" the scene in a translation prompt by shouting
+/// it, and answers the labeled request with fixed titles
+struct ShoutingTranslator;
+
+#[async_trait::async_trait]
+impl AIProvider for ShoutingTranslator {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        assert!(prompt.contains("into German"));
+        if let Some((_, rest)) = prompt.split_once("Scene:\n") {
+            let scene = rest.split("\n\nIMPORTANT").next().unwrap();
+            return Ok(("Translator notes".to_string(), scene.to_uppercase()));
+        }
+        Ok((
+            "Translator notes".to_string(),
+            "TITLE: \"Die stille Straße\"\nCHAPTER 1: Anfang\nCHAPTER 2: Ende".to_string(),
+        ))
+    }
+}
+
+#[tokio::test]
+async fn test_translation() -> Result<(), StoryChainError> {
+    let mut chain = StoryChain::new("The street was quiet.".to_string(), "R".to_string());
+    chain.metadata.insert("title".to_string(), "The Quiet Street".to_string());
+    let next = chain.generate_next_nodes("root", &FixedResponseProvider("Then it was not."), None, 1, 1).await?;
+    chain.group_into_chapters(1);
+    assert_eq!(chain.chapters.len(), 2);
+
+    let translated = chain.translate(&ShoutingTranslator, "de").await?;
+    assert_eq!(translated.nodes.len(), chain.nodes.len());
+    assert_eq!(translated.nodes["root"].content, "THE STREET WAS QUIET.");
+    assert_eq!(translated.nodes[&next[0]].content, "THEN IT WAS NOT.");
+    assert_eq!(translated.nodes[&next[0]].predecessors, vec!["root".to_string()]);
+    assert!(translated.nodes[&next[0]].provenance.as_ref().unwrap().revisions.contains(&"translation".to_string()));
+    assert_eq!(translated.metadata["title"], "Die stille Straße");
+    assert_eq!(translated.metadata[LANGUAGE_KEY], "de");
+    assert_eq!(translated.chapters[1].title, "Ende");
+    assert_eq!(translated.chapters[1].node_ids, chain.chapters[1].node_ids);
+
+    // The original is left as it was
+    assert_eq!(chain.nodes["root"].content, "The street was quiet.");
+    assert!(!chain.metadata.contains_key(LANGUAGE_KEY));
+    assert_eq!(language_name("FR"), "French");
+    assert_eq!(language_name("Brazilian Portuguese"), "Brazilian Portuguese");
+    Ok(())
+}
+
+/// A speech engine that reads every character as one 16-bit sample at 1 kHz,
+/// taking at most 40 characters at a time
+#[cfg(feature = "audio")]
+struct ClickingVoice(Mutex<Vec<String>>);
+
+#[cfg(feature = "audio")]
+#[async_trait::async_trait]
+impl storychain::audio::TtsProvider for ClickingVoice {
+    async fn synthesize(&self, text: &str) -> Result<Vec<u8>, StoryChainError> {
+        self.0.lock().unwrap().push(text.to_string());
+        let data = vec![1u8; text.chars().count() * 2];
+        let mut wav = b"RIFF".to_vec();
+        wav.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(b"WAVEfmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        // PCM, mono, 1000 Hz, 2000 bytes per second, 2 bytes per sample, 16 bits
+        for field in [&1u16.to_le_bytes()[..], &1u16.to_le_bytes(), &1000u32.to_le_bytes(), &2000u32.to_le_bytes(), &2u16.to_le_bytes(), &16u16.to_le_bytes()] {
+            wav.extend_from_slice(field);
+        }
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(&data);
+        Ok(wav)
+    }
+
+    fn voice(&self) -> &str {
+        "clicks"
+    }
+
+    fn max_input_chars(&self) -> Option<usize> {
+        Some(40)
+    }
+}
+
+#[cfg(feature = "audio")]
+#[tokio::test]
+async fn test_audiobook_export() -> Result<(), StoryChainError> {
+    use std::time::Duration;
+    use storychain::audio::{AUDIOBOOK_FILE, CHAPTERS_FILE};
+
+    let dir = tempfile::tempdir()?;
+    let mut chain = StoryChain::new("The bell rang.\n\nNobody came to the door of the old house.".to_string(), "R".to_string());
+    chain.metadata.insert("title".to_string(), "Bells".to_string());
+    let next = chain.generate_next_nodes("root", &FixedResponseProvider("Then it rang again."), None, 1, 1).await?;
+    chain.group_into_chapters(1);
+    chain.chapters[0].title = "One".to_string();
+    chain.chapters[1].title = "Two".to_string();
+
+    let voice = ClickingVoice(Mutex::new(Vec::new()));
+    let markers = chain.export_to_audio(&voice, &dir.path().to_string_lossy()).await?;
+
+    // Headings are read, and the long scene is split at its paragraph break and then between words
+    let read = voice.0.lock().unwrap().clone();
+    assert_eq!(read, vec!["One", "The bell rang.", "Nobody came to the door of the old", "house.", "Two", "Then it rang again."]);
+
+    // Each character plays for a millisecond, with 1.2 seconds between clips
+    assert_eq!(markers.len(), 2);
+    assert_eq!(markers[0].start, Duration::ZERO);
+    assert_eq!(markers[0].end, Duration::from_millis(3 + 1200 + 14 + 34 + 6));
+    assert_eq!(markers[1].start, markers[0].end);
+    assert_eq!(markers[1].end, markers[0].end + Duration::from_millis(1200 + 3 + 1200 + 19));
+
+    let scene = std::fs::read(dir.path().join(format!("002_{}.wav", next[0])))?;
+    assert_eq!(scene.len(), 44 + 19 * 2);
+    let audiobook = std::fs::read(dir.path().join(AUDIOBOOK_FILE))?;
+    assert_eq!(audiobook.len(), 44 + markers[1].end.as_millis() as usize * 2);
+    assert_eq!(&audiobook[40..44], &((markers[1].end.as_millis() * 2) as u32).to_le_bytes());
+    assert!(dir.path().join("001_root.wav").exists());
+
+    let chapters = std::fs::read_to_string(dir.path().join(CHAPTERS_FILE))?;
+    assert!(chapters.starts_with(";FFMETADATA1\ntitle=Bells\nartist=clicks\n"));
+    assert!(chapters.contains("[CHAPTER]\nTIMEBASE=1/1000\nSTART=0\nEND=1257\ntitle=One\n"));
+    Ok(())
+}
+
+/// An image model that returns the prompt and negative prompt it was given as the image
+struct PromptImages;
+
+#[async_trait::async_trait]
+impl ImageProvider for PromptImages {
+    async fn generate_image(&self, prompt: &str, negative_prompt: Option<&str>) -> Result<Vec<u8>, StoryChainError> {
+        Ok(format!("{} | {}", prompt, negative_prompt.unwrap_or_default()).into_bytes())
+    }
+}
+
+#[tokio::test]
+async fn test_scene_illustrations() -> Result<(), StoryChainError> {
+    let dir = tempfile::tempdir()?;
+    let mut chain = StoryChain::new("A lighthouse in a storm.".to_string(), "R".to_string());
+    let next = chain.generate_next_nodes("root", &FixedResponseProvider("The keeper climbs the stairs."), None, 1, 1).await?;
+    let brief = FixedResponseProvider("PROMPT: A lighthouse at night, oil painting\nNEGATIVE PROMPT: text\nBRIEF: Lonely.");
+
+    let images = dir.path().join("story_images");
+    assert_eq!(chain.illustrate_scenes(&brief, &PromptImages, &images.to_string_lossy()).await?, 2);
+    let image = images.join(format!("{}.png", next[0]));
+    assert_eq!(std::fs::read_to_string(&image)?, "A lighthouse at night, oil painting | text");
+    assert_eq!(chain.nodes[&next[0]].metadata[IMAGE_KEY], image.to_string_lossy());
+    assert_eq!(chain.nodes["root"].metadata[IMAGE_PROMPT_KEY], "A lighthouse at night, oil painting");
+
+    // Scenes already illustrated are skipped
+    assert_eq!(chain.illustrate_scenes(&brief, &PromptImages, &images.to_string_lossy()).await?, 0);
+
+    // The exports show the images relative to the page
+    let html_file = dir.path().join("story.html");
+    chain.export_to_html(&html_file.to_string_lossy())?;
+    let html = std::fs::read_to_string(&html_file)?;
+    assert!(html.contains(&format!(
+        "<figure class=\"illustration\"><img src=\"story_images/{}.png\" alt=\"A lighthouse at night, oil painting\"></figure>\n<p>The keeper climbs the stairs.</p>",
+        next[0]
+    )));
+    chain.export_to_interactive_html(&html_file.to_string_lossy())?;
+    assert!(std::fs::read_to_string(&html_file)?.contains("<img src=\\\"story_images/root.png\\\""));
+    Ok(())
+}
+
+/// A story editor that asks two questions, then writes a premise and the arcs of its characters
+struct InterviewingEditor;
+
+#[async_trait::async_trait]
+impl AIProvider for InterviewingEditor {
+    async fn generate(&self, prompt: &str) -> Result<(String, String), StoryChainError> {
+        let content = if prompt.contains("Ask the single most useful next question") {
+            match prompt.matches("\nQ: ").count() {
+                0 => "<think>Start with the genre.</think>\n**QUESTION:** What genre is it?".to_string(),
+                1 => "QUESTION: Who is the hero?".to_string(),
+                _ => "DONE".to_string(),
+            }
+        } else if prompt.contains("Write the premise") {
+            assert!(prompt.contains("Q: What genre is it?\nA: Cozy mystery\n\nQ: Who is the hero?\nA: No preference; you decide."));
+            "```yaml\ntitle: The Teapot Affair\ngenre: Cozy mystery\npremise: A retired baker solves a theft at the village fete.\n\
+            characters:\n  - name: Edna Pike\n    description: A retired baker\ntarget_length:\n  scenes: 10\n```"
+                .to_string()
+        } else {
+            assert!(prompt.contains("- Edna Pike: A retired baker"));
+            "CHARACTER: Edna Pike\nWANT: To win the fete\nNEED: To trust her neighbours\nFLAW: Pride\nARC: From rival to friend".to_string()
+        };
+        Ok(("Editor reasoning".to_string(), content))
+    }
+}
+
+#[tokio::test]
+async fn test_premise_wizard() -> Result<(), StoryChainError> {
+    use storychain::wizard::{generate_character_arcs, PremiseInterview};
+
+    let dir = tempfile::tempdir()?;
+    let templates = PromptTemplates::default();
+    let mut answers = vec!["Cozy mystery".to_string(), "  ".to_string(), "Never asked".to_string()].into_iter();
+    let mut asked = Vec::new();
+    let mut interview = PremiseInterview::default();
+    interview
+        .run(&templates, &InterviewingEditor, |question| {
+            asked.push(question.to_string());
+            Ok(answers.next())
+        })
+        .await?;
+    assert_eq!(asked, vec!["What genre is it?", "Who is the hero?"]);
+    assert_eq!(interview.exchanges.len(), 2);
+
+    let premise = interview.write_premise(&templates, &InterviewingEditor).await?;
+    assert_eq!(premise.title.as_deref(), Some("The Teapot Affair"));
+    assert_eq!(premise.characters[0].name, "Edna Pike");
+    let yaml = premise.to_yaml()?;
+    assert!(!yaml.contains("null") && !yaml.contains("themes"));
+    assert_eq!(Premise::parse(&yaml)?, premise);
+
+    let mut artifact_manager = ArtifactManager::new(&dir.path().to_string_lossy());
+    let ids = generate_character_arcs(&templates, &InterviewingEditor, &premise, &mut artifact_manager).await?;
+    assert_eq!(ids, vec!["character_edna_pike"]);
+    let arc = artifact_manager.get_artifact("character_edna_pike").unwrap();
+    assert_eq!(arc.artifact_type, ArtifactType::CharacterArc);
+    assert_eq!(arc.metadata["need"], "To trust her neighbours");
+    assert!(dir.path().join("character_edna_pike.json").exists());
+
+    // Without an interview, no question is asked
+    assert_eq!(PremiseInterview::from_idea("A theft at a fete").next_question(&templates, &InterviewingEditor).await?, None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_artifact_references() -> Result<(), StoryChainError> {
+    use storychain::artifacts::{Artifact, REFERENCES_KEY};
+
+    let dir = tempfile::tempdir()?;
+    let mut artifact_manager = ArtifactManager::new(&dir.path().to_string_lossy());
+    let mut add = |id: &str, artifact_type: ArtifactType, references: &str| {
+        let mut metadata = std::collections::HashMap::new();
+        if !references.is_empty() {
+            metadata.insert(REFERENCES_KEY.to_string(), references.to_string());
+        }
+        artifact_manager.update_artifact(Artifact { id: id.to_string(), content: format!("<{}>", id), artifact_type, metadata, tags: Vec::new() })
+    };
+    add("a_hero", ArtifactType::CharacterArc, "world_city, world_magic")?;
+    add("world_city", ArtifactType::WorldBuilding, " world_magic ")?;
+    add("world_magic", ArtifactType::WorldBuilding, "")?;
+    add("outline", ArtifactType::PlotOutline, "a_hero")?;
+    artifact_manager.validate()?;
+
+    // Every artifact follows the ones it references
+    let order: Vec<&str> = artifact_manager.topological_order()?.iter().map(|a| a.id.as_str()).collect();
+    assert_eq!(order, vec!["world_magic", "world_city", "a_hero", "outline"]);
+    let mut templates = PromptTemplates::default();
+    templates.add_artifacts(&artifact_manager);
+    templates.set_template(PromptTemplate {
+        name: "initial".to_string(),
+        source: "{% for id in artifact_order %}{{ artifacts[id] }}{% endfor %}".to_string(),
+    })?;
+    assert_eq!(templates.initial_prompt("", &[])?, "<world_magic><world_city><a_hero><outline>");
+
+    // Dangling references and cycles are reported together
+    let mut add = |id: &str, references: &str| {
+        let mut metadata = std::collections::HashMap::new();
+        metadata.insert(REFERENCES_KEY.to_string(), references.to_string());
+        artifact_manager.update_artifact(Artifact { id: id.to_string(), content: String::new(), artifact_type: ArtifactType::WorldBuilding, metadata, tags: Vec::new() })
+    };
+    add("world_magic", "world_gods")?;
+    add("world_gods", "world_city, world_tides")?;
+    let Err(StoryChainError::ConfigError(message)) = artifact_manager.validate() else {
+        panic!("invalid references were accepted");
+    };
+    assert!(message.contains("world_gods references world_tides, which does not exist"));
+    assert!(message.contains("world_city -> world_magic -> world_gods -> world_city reference each other in a cycle"));
+    assert!(artifact_manager.topological_order().is_err());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_artifact_search() -> Result<(), StoryChainError> {
+    let dir = tempfile::tempdir()?;
+    let mut artifact_manager = ArtifactManager::new(&dir.path().to_string_lossy());
+    artifact_manager.create_artifact(
+        "world_harbor".to_string(),
+        "The harbor of Vell freezes every winter. Smugglers cross the harbor ice at night.".to_string(),
+        ArtifactType::WorldBuilding,
+    )?;
+    artifact_manager.create_artifact(
+        "world_magic".to_string(),
+        "Magic in Vell is drawn from the tides.".to_string(),
+        ArtifactType::WorldBuilding,
+    )?;
+    artifact_manager.create_artifact("a_captain".to_string(), "Name: Ilse\nArc: Learns to trust".to_string(), ArtifactType::CharacterArc)?;
+
+    // Tags are kept once each, ignoring case, and saved with the artifact
+    let mut captain = artifact_manager.get_artifact("a_captain").unwrap().clone();
+    assert!(captain.add_tag("Harbor"));
+    assert!(!captain.add_tag("harbor"));
+    captain.metadata.insert("home".to_string(), "VELL".to_string());
+    artifact_manager.update_artifact(captain)?;
+    let mut reloaded = ArtifactManager::new(&dir.path().to_string_lossy());
+    reloaded.load_from_dir()?;
+    assert_eq!(reloaded.get_artifact("a_captain").unwrap().tags, vec!["Harbor"]);
+    let tagged: Vec<&str> = reloaded.get_artifacts_by_tag("HARBOR").iter().map(|a| a.id.as_str()).collect();
+    assert_eq!(tagged, vec!["a_captain"]);
+
+    // Matches in content, metadata, and tags count, most occurrences first
+    let results = reloaded.search("harbor");
+    let found: Vec<(&str, &str)> = results.iter().map(|r| (r.artifact.id.as_str(), r.field.as_str())).collect();
+    assert_eq!(found, vec![("world_harbor", "content"), ("a_captain", "tags")]);
+    assert_eq!(results[0].occurrences, 3);
+    assert!(results[0].snippet.starts_with("the harbor of vell freezes"));
+
+    // Every word must match, and tag: filters by tag
+    let ids = |query: &str| reloaded.search(query).iter().map(|r| r.artifact.id.clone()).collect::<Vec<_>>();
+    assert_eq!(ids("vell TIDES"), vec!["world_magic"]);
+    assert_eq!(ids("vell"), vec!["a_captain", "world_harbor", "world_magic"]);
+    assert_eq!(ids("tag:harbor vell"), vec!["a_captain"]);
+    assert_eq!(ids("tag:harbor"), vec!["a_captain"]);
+    assert!(ids("dragons").is_empty());
+    assert!(ids("  ").is_empty());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_delete_and_rename_artifacts() -> Result<(), StoryChainError> {
+    use storychain::artifacts::REFERENCES_KEY;
+
+    let dir = tempfile::tempdir()?;
+    let mut artifact_manager = ArtifactManager::new(&dir.path().to_string_lossy());
+    for id in ["world_city", "world_magic", "a_hero"] {
+        artifact_manager.create_artifact(id.to_string(), format!("<{}>", id), ArtifactType::WorldBuilding)?;
+    }
+    let mut hero = artifact_manager.get_artifact("a_hero").unwrap().clone();
+    hero.metadata.insert(REFERENCES_KEY.to_string(), "world_city, world_magic".to_string());
+    artifact_manager.update_artifact(hero)?;
+
+    // Renaming moves the file and follows the references
+    artifact_manager.rename_artifact("world_city", "world_harbor_city")?;
+    assert!(!dir.path().join("world_city.json").exists());
+    assert!(dir.path().join("world_harbor_city.json").exists());
+    assert!(matches!(artifact_manager.rename_artifact("world_magic", "world_harbor_city"), Err(StoryChainError::ConfigError(_))));
+    assert!(matches!(artifact_manager.rename_artifact("world_gods", "world_tides"), Err(StoryChainError::ConfigError(_))));
+
+    // Deleting removes the file and the references to it
+    let deleted = artifact_manager.delete_artifact("world_magic")?;
+    assert_eq!(deleted.content, "<world_magic>");
+    assert!(!dir.path().join("world_magic.json").exists());
+    assert!(artifact_manager.delete_artifact("world_magic").is_err());
+
+    let mut reloaded = ArtifactManager::new(&dir.path().to_string_lossy());
+    reloaded.load_from_dir()?;
+    let ids: Vec<&str> = reloaded.get_all_artifacts().iter().map(|a| a.id.as_str()).collect();
+    assert_eq!(ids, vec!["a_hero", "world_harbor_city"]);
+    assert_eq!(reloaded.get_artifact("a_hero").unwrap().references(), vec!["world_harbor_city"]);
+    reloaded.validate()?;
+
+    reloaded.delete_artifact("world_harbor_city")?;
+    assert!(!reloaded.get_artifact("a_hero").unwrap().metadata.contains_key(REFERENCES_KEY));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_subcommands_read_compressed_stories() -> Result<(), StoryChainError> {
+    let dir = tempfile::tempdir()?;
+    let gzipped = dir.path().join("story.json.gz").to_string_lossy().to_string();
+    let mut chain = StoryChain::new("Opening".to_string(), "Reasoning".to_string());
+    chain.generate_next_nodes("root", &FixedResponseProvider("The storm breaks."), None, 1, 1).await?;
+    chain.export_to_file(&gzipped)?;
+
+    // A subcommand that writes a story back can read it in the compression it wrote
+    for order in ["node_1,root", "causal"] {
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_storychain")).args(["reorder", &gzipped, order]).output()?;
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+    assert_eq!(Compression::detect(&std::fs::read(&gzipped)?), Compression::Gzip);
+    assert_eq!(StoryChain::load_from_file(&gzipped)?.nodes.len(), 2);
+
+    // With --store the story argument names a stored story, which is saved back to the store
+    let stored = dir.path().join("stored");
+    std::fs::create_dir(&stored)?;
+    chain.export_to_file(&stored.join("dragon.json").to_string_lossy())?;
+    let reorder = ["reorder", "dragon", "node_1,root", "--store", &stored.to_string_lossy()];
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_storychain")).args(reorder).output()?;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(StoryChain::load_from_file(&stored.join("dragon.json").to_string_lossy())?.reading_order, ["node_1", "root"]);
+
+    let stats = ["stats", gzipped.as_str(), "--words-per-minute", "100"];
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_storychain")).args(stats).output()?;
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("at 100 words per minute"));
+    Ok(())
+}